@@ -0,0 +1,448 @@
+use crate::rotation::Rotation;
+use crate::traits::Float;
+use core::str::FromStr;
+use corelib_traits::{Context, Matrix, PassBy, ProcessBlock};
+use nalgebra::{UnitQuaternion, Vector3};
+use pictorus_nalgebra_interop::MatrixExt;
+use utils::{BlockData as OldBlockData, FromPass, ParseEnumError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttitudeAlgorithm {
+    /// Gradient descent on the gravity and magnetic field directions, with a fixed step size
+    Madgwick,
+    /// Proportional-integral feedback of the gravity and magnetic field direction errors
+    Mahony,
+    /// Integrated gyro blended with the attitude measured by the accelerometer and
+    /// magnetometer
+    Complementary,
+}
+
+impl FromStr for AttitudeAlgorithm {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Madgwick" => Ok(Self::Madgwick),
+            "Mahony" => Ok(Self::Mahony),
+            "Complementary" => Ok(Self::Complementary),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+/// Parameters for the Attitude Estimator Block
+pub struct Parameters {
+    pub algorithm: AttitudeAlgorithm,
+    /// Madgwick's β in rad/s, Mahony's proportional gain kp in rad/s, or the crossover
+    /// frequency of the complementary filter in rad/s
+    pub gain: f64,
+    /// Rate of the gyro bias estimation: Madgwick's ζ, or the integral gain ki of the Mahony
+    /// and complementary filters. Zero disables it.
+    pub bias_gain: f64,
+    /// Time the sensor is held still at startup, in seconds. The attitude starts from the
+    /// average accelerometer and magnetometer readings over this time, and the gyro bias from
+    /// the average gyro reading.
+    pub init_time_s: f64,
+    /// Accelerometer and magnetometer corrections are skipped while the magnitude of the
+    /// acceleration differs from its magnitude at startup by more than this fraction. Zero
+    /// disables it.
+    pub accel_rejection: f64,
+    pub use_magnetometer: bool,
+}
+
+impl Parameters {
+    pub fn new(
+        algorithm: &str,
+        gain: f64,
+        bias_gain: f64,
+        init_time_s: f64,
+        accel_rejection: f64,
+        use_magnetometer: bool,
+    ) -> Self {
+        Self {
+            algorithm: algorithm.parse().expect("Invalid attitude algorithm"),
+            gain,
+            bias_gain,
+            init_time_s,
+            accel_rejection,
+            use_magnetometer,
+        }
+    }
+}
+
+/// Quaternion, Euler angles, gyro bias and whether the initialisation is complete
+type Attitude<F> = (Matrix<4, 1, F>, Matrix<3, 1, F>, Matrix<3, 1, F>, bool);
+
+/// Sums of the readings during initialisation
+struct Averages<F: Float> {
+    gyro: Vector3<F>,
+    accel: Vector3<F>,
+    mag: Vector3<F>,
+    count: usize,
+}
+
+impl<F: Float> Averages<F> {
+    fn mean(&self, sum: &Vector3<F>) -> Vector3<F> {
+        sum / F::from(self.count.max(1)).unwrap()
+    }
+}
+
+/// The Attitude Estimator Block fuses a gyro, an accelerometer and optionally a magnetometer
+/// into the orientation of the sensor.
+///
+/// The inputs are the angular rate in rad/s, the acceleration and the magnetic field, each in
+/// the body frame; the units of the acceleration and magnetic field don't matter, only their
+/// directions. The orientation is the rotation from the body frame to a world frame with z up,
+/// and x towards magnetic north when the magnetometer is used, or the initial heading when
+/// it isn't.
+///
+/// The outputs are the quaternion `[w, x, y, z]`, the ZYX Euler angles `[roll, pitch, yaw]` in
+/// radians (see [`crate::rotation`] for the conventions), the estimated gyro bias in rad/s, and
+/// whether the initialisation is complete. While initialising, the block averages the readings
+/// and outputs the attitude of the averages, with a zero bias.
+pub struct AttitudeEstimatorBlock<F: Float>
+where
+    OldBlockData: FromPass<Matrix<4, 1, F>>,
+{
+    pub data: OldBlockData,
+    attitude: UnitQuaternion<F>,
+    bias: Vector3<F>,
+    /// Magnitude of the acceleration at startup
+    gravity: F,
+    elapsed: F,
+    averages: Option<Averages<F>>,
+    output: Attitude<F>,
+}
+
+impl<F: Float> Default for AttitudeEstimatorBlock<F>
+where
+    OldBlockData: FromPass<Matrix<4, 1, F>>,
+{
+    fn default() -> Self {
+        let attitude = UnitQuaternion::identity();
+        let mut output: Attitude<F> = (Matrix::zeroed(), Matrix::zeroed(), Matrix::zeroed(), false);
+        output.0.data[0][0] = F::one();
+        Self {
+            data: <OldBlockData as FromPass<Matrix<4, 1, F>>>::from_pass(&output.0),
+            attitude,
+            bias: Vector3::zeros(),
+            gravity: F::zero(),
+            elapsed: F::zero(),
+            averages: Some(Averages {
+                gyro: Vector3::zeros(),
+                accel: Vector3::zeros(),
+                mag: Vector3::zeros(),
+                count: 0,
+            }),
+            output,
+        }
+    }
+}
+
+/// The attitude measured by the accelerometer and magnetometer. Without a magnetometer the
+/// yaw is `yaw`.
+fn measured_attitude<F: Float>(
+    accel: &Vector3<F>,
+    mag: Option<&Vector3<F>>,
+    yaw: F,
+) -> UnitQuaternion<F> {
+    // At rest the accelerometer measures the reaction to gravity, up in the world frame
+    let roll = num_traits::Float::atan2(accel.y, accel.z);
+    let pitch = num_traits::Float::atan2(-accel.x, num_traits::Float::hypot(accel.y, accel.z));
+    let tilt = UnitQuaternion::from_euler_angles(roll, pitch, F::zero());
+    let yaw = match mag {
+        Some(mag) => {
+            let horizontal = tilt * mag;
+            -num_traits::Float::atan2(horizontal.y, horizontal.x)
+        }
+        None => yaw,
+    };
+    UnitQuaternion::from_euler_angles(F::zero(), F::zero(), yaw) * tilt
+}
+
+/// The direction errors between the measured and predicted gravity and magnetic field, in the
+/// body frame. Rotating the estimate about the error reduces it.
+fn direction_error<F: Float>(
+    attitude: &UnitQuaternion<F>,
+    accel: &Vector3<F>,
+    mag: Option<&Vector3<F>>,
+) -> Vector3<F> {
+    let up = attitude.inverse_transform_vector(&Vector3::z());
+    let mut error = accel.cross(&up);
+    if let Some(mag) = mag {
+        // Only the inclination of the field is known, its heading defines north
+        let field = attitude * mag;
+        let reference = Vector3::new(
+            num_traits::Float::hypot(field.x, field.y),
+            F::zero(),
+            field.z,
+        );
+        error += mag.cross(&attitude.inverse_transform_vector(&reference));
+    }
+    error
+}
+
+impl<F: Float> AttitudeEstimatorBlock<F>
+where
+    OldBlockData: FromPass<Matrix<4, 1, F>>,
+{
+    /// Averages the readings until the initialisation time has passed, then starts the
+    /// estimate from the averages. Returns whether the estimate has started.
+    fn initialise(
+        &mut self,
+        parameters: &Parameters,
+        gyro: Vector3<F>,
+        accel: Vector3<F>,
+        mag: Vector3<F>,
+        dt: F,
+    ) -> bool {
+        let Some(averages) = &mut self.averages else {
+            return true;
+        };
+        averages.gyro += gyro;
+        averages.accel += accel;
+        averages.mag += mag;
+        averages.count += 1;
+        let accel = averages.mean(&averages.accel);
+        let mag = averages.mean(&averages.mag);
+        let mag = parameters.use_magnetometer.then_some(&mag);
+        self.attitude = measured_attitude(&accel, mag, F::zero());
+        self.gravity = accel.norm();
+
+        self.elapsed += dt;
+        if self.elapsed < F::from(parameters.init_time_s).unwrap() {
+            return false;
+        }
+        if averages.count > 1 {
+            self.bias = averages.mean(&averages.gyro);
+        }
+        self.averages = None;
+        true
+    }
+
+    fn update(
+        &mut self,
+        parameters: &Parameters,
+        gyro: Vector3<F>,
+        accel: Vector3<F>,
+        mag: Vector3<F>,
+        dt: F,
+    ) {
+        let gain = F::from(parameters.gain).unwrap();
+        let bias_gain = F::from(parameters.bias_gain).unwrap();
+        let two = F::one() + F::one();
+
+        let rejection = F::from(parameters.accel_rejection).unwrap();
+        let magnitude = accel.norm();
+        let mut corrected = magnitude > F::zero();
+        if rejection > F::zero() && self.gravity > F::zero() {
+            corrected &= num_traits::Float::abs(magnitude / self.gravity - F::one()) <= rejection;
+        }
+        let accel = accel.normalize();
+        let mag = mag
+            .try_normalize(F::zero())
+            .filter(|_| parameters.use_magnetometer);
+
+        let mut rate = gyro - self.bias;
+        match parameters.algorithm {
+            AttitudeAlgorithm::Mahony => {
+                if corrected {
+                    let error = direction_error(&self.attitude, &accel, mag.as_ref());
+                    self.bias -= error * bias_gain * dt;
+                    rate += error * gain;
+                }
+            }
+            AttitudeAlgorithm::Madgwick => {
+                // The normalized gradient of the objective function, expressed as a body rate
+                let step = corrected
+                    .then(|| direction_error(&self.attitude, &accel, mag.as_ref()))
+                    .and_then(|error| error.try_normalize(F::zero()));
+                if let Some(step) = step {
+                    self.bias -= step * two * bias_gain * dt;
+                    rate += step * two * gain;
+                }
+            }
+            AttitudeAlgorithm::Complementary => {}
+        }
+        self.attitude *= UnitQuaternion::from_scaled_axis(rate * dt);
+        self.attitude.renormalize();
+
+        if parameters.algorithm == AttitudeAlgorithm::Complementary && corrected {
+            let (_, _, yaw) = self.attitude.euler_angles();
+            let measured = measured_attitude(&accel, mag.as_ref(), yaw);
+            let weight = num_traits::Float::min(gain * dt, F::one());
+            // The rotation from the estimate to the measurement, in the body frame. In steady
+            // state it's the uncorrected bias divided by the gain.
+            let error = (self.attitude.inverse() * measured).scaled_axis();
+            self.bias -= error * (gain * bias_gain * dt);
+            self.attitude = self
+                .attitude
+                .try_slerp(&measured, weight, F::default_epsilon())
+                .unwrap_or(measured);
+        }
+    }
+}
+
+impl<F: Float> ProcessBlock for AttitudeEstimatorBlock<F>
+where
+    OldBlockData: FromPass<Matrix<4, 1, F>>,
+{
+    type Inputs = (Matrix<3, 1, F>, Matrix<3, 1, F>, Matrix<3, 1, F>);
+    type Output = Attitude<F>;
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let (gyro, accel, mag) = inputs;
+        let gyro: Vector3<F> = gyro.as_view().into_owned();
+        let accel: Vector3<F> = accel.as_view().into_owned();
+        let mag: Vector3<F> = mag.as_view().into_owned();
+        let dt = F::from_duration(context.timestep().unwrap_or(context.fundamental_timestep()));
+
+        let initialised = if self.averages.is_some() {
+            self.initialise(parameters, gyro, accel, mag, dt)
+        } else {
+            self.update(parameters, gyro, accel, mag, dt);
+            true
+        };
+
+        self.output = (
+            Matrix::from_unit_quaternion(&self.attitude),
+            Matrix::from_unit_quaternion(&self.attitude),
+            Matrix::from_view(&self.bias.as_view()),
+            initialised,
+        );
+        self.data = OldBlockData::from_pass(&self.output.0);
+        let (quaternion, euler, bias, initialised) = &self.output;
+        (quaternion, euler, bias, *initialised)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use core::time::Duration;
+    use corelib_traits_testing::StubContext;
+
+    /// Typical gains and bias gains. Madgwick's step has a fixed size, so its gains are
+    /// smaller to keep the estimate from chattering.
+    const ALGORITHMS: [(&str, f64, f64); 3] = [
+        ("Madgwick", 0.05, 0.01),
+        ("Mahony", 1.0, 0.3),
+        ("Complementary", 1.0, 0.1),
+    ];
+
+    fn context() -> StubContext {
+        StubContext {
+            fundamental_timestep: Duration::from_millis(10),
+            ..Default::default()
+        }
+    }
+
+    fn vector(x: f64, y: f64, z: f64) -> Matrix<3, 1, f64> {
+        Matrix { data: [[x, y, z]] }
+    }
+
+    /// Readings of a sensor at rest in the attitude given by Euler angles, in a field pointing
+    /// north and down
+    fn readings(roll: f64, pitch: f64, yaw: f64) -> (Matrix<3, 1, f64>, Matrix<3, 1, f64>) {
+        let attitude = UnitQuaternion::from_euler_angles(roll, pitch, yaw);
+        let accel = attitude.inverse_transform_vector(&Vector3::new(0.0, 0.0, 9.81));
+        let mag = attitude.inverse_transform_vector(&Vector3::new(20.0, 0.0, -45.0));
+        (
+            Matrix::from_view(&accel.as_view()),
+            Matrix::from_view(&mag.as_view()),
+        )
+    }
+
+    #[test]
+    fn test_attitude_estimator_static_bias() {
+        let context = context();
+        let (accel, mag) = readings(0.5, -0.2, 1.0);
+        let bias = vector(0.01, -0.02, 0.005);
+
+        for (algorithm, gain, bias_gain) in ALGORITHMS {
+            // No initialisation phase, so the bias has to be estimated
+            let parameters = Parameters::new(algorithm, gain, bias_gain, 0.0, 0.0, true);
+            let mut block = AttitudeEstimatorBlock::<f64>::default();
+            for _ in 0..10000 {
+                block.process(&parameters, &context, (&bias, &accel, &mag));
+            }
+            let (quaternion, euler, estimated_bias, initialised) = block.output;
+            assert!(initialised);
+            assert_relative_eq!(euler.data[0][..], [0.5, -0.2, 1.0][..], epsilon = 1e-3);
+            assert_relative_eq!(estimated_bias.data[0][..], bias.data[0][..], epsilon = 1e-3);
+            let norm = quaternion.data[0].iter().map(|q| q * q).sum::<f64>();
+            assert_relative_eq!(norm, 1.0, epsilon = 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_attitude_estimator_initialisation_and_rotation() {
+        let context = context();
+        let gyro_bias = vector(0.02, 0.01, -0.01);
+        let (accel, mag) = readings(0.1, 0.2, 0.0);
+
+        for (algorithm, gain, _) in ALGORITHMS {
+            let parameters = Parameters::new(algorithm, gain, 0.0, 0.5, 0.1, false);
+            let mut block = AttitudeEstimatorBlock::<f64>::default();
+            for i in 0..50 {
+                let (_, euler, _, initialised) =
+                    block.process(&parameters, &context, (&gyro_bias, &accel, &mag));
+                assert_eq!(initialised, i == 49);
+                assert_relative_eq!(euler.data[0][..], [0.1, 0.2, 0.0][..], epsilon = 1e-9);
+            }
+            assert_relative_eq!(
+                block.output.2.data[0][..],
+                gyro_bias.data[0][..],
+                epsilon = 1e-9
+            );
+
+            // Yawing at 1 rad/s for a second, which only the gyro sees without the magnetometer
+            let mut gyro = gyro_bias;
+            let rate = UnitQuaternion::from_euler_angles(0.1, 0.2, 0.0)
+                .inverse_transform_vector(&Vector3::z());
+            for (g, r) in gyro.data[0].iter_mut().zip(rate.iter()) {
+                *g += r;
+            }
+            for i in 1..=100 {
+                let (accel, _) = readings(0.1, 0.2, i as f64 * 0.01);
+                block.process(&parameters, &context, (&gyro, &accel, &mag));
+            }
+            assert_relative_eq!(
+                block.output.1.data[0][..],
+                [0.1, 0.2, 1.0][..],
+                epsilon = 1e-3
+            );
+            assert_relative_eq!(block.data.get_data()[0], block.output.0.data[0][0]);
+        }
+    }
+
+    #[test]
+    fn test_attitude_estimator_accel_rejection() {
+        let context = context();
+        let (accel, mag) = readings(0.0, 0.0, 0.0);
+        let parameters = Parameters::new("Mahony", 2.0, 0.0, 0.0, 0.2, false);
+        let mut block = AttitudeEstimatorBlock::<f64>::default();
+        block.process(
+            &parameters,
+            &context,
+            (&vector(0.0, 0.0, 0.0), &accel, &mag),
+        );
+
+        // A hard sideways acceleration doesn't tilt the estimate
+        let bump = vector(9.81, 0.0, 9.81);
+        for _ in 0..50 {
+            block.process(&parameters, &context, (&vector(0.0, 0.0, 0.0), &bump, &mag));
+        }
+        assert_relative_eq!(
+            block.output.1.data[0][..],
+            [0.0, 0.0, 0.0][..],
+            epsilon = 1e-12
+        );
+    }
+}
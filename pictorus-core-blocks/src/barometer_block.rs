@@ -0,0 +1,175 @@
+use core::str::FromStr;
+use corelib_traits::{Context, PassBy, ProcessBlock};
+use utils::{BlockData as OldBlockData, IsValid, ParseEnumError, StaleTracker};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BarometerSensor {
+    Bmp280,
+    Bmp390,
+}
+
+impl FromStr for BarometerSensor {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "BMP280" => Ok(Self::Bmp280),
+            "BMP390" => Ok(Self::Bmp390),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+/// Parameters for the Barometer Block
+pub struct Parameters {
+    pub sensor: BarometerSensor,
+    /// I2C address of the sensor
+    pub address: u8,
+    /// Pressure oversampling, rounded up to a power of two
+    pub oversampling: u8,
+    /// Coefficient of the sensor's IIR filter, 0 to disable it
+    pub iir_coefficient: u8,
+    pub sample_rate_hz: f64,
+    /// Average all samples queued in the sensor's FIFO since the last tick, rather than
+    /// reading the latest sample. Only the BMP390 has a FIFO.
+    pub use_fifo: bool,
+    /// Check the sensor's calibration and that a sample is plausible after configuring it.
+    /// A failed self-test keeps the block stale.
+    pub run_self_test: bool,
+    /// Pressure at sea level in Pa, the reference for the altitude output
+    pub sea_level_pressure: f64,
+    stale_age_ms: f64,
+}
+
+impl Parameters {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        sensor: &str,
+        address: f64,
+        oversampling: f64,
+        iir_coefficient: f64,
+        sample_rate_hz: f64,
+        use_fifo: bool,
+        run_self_test: bool,
+        sea_level_pressure: f64,
+        stale_age_ms: f64,
+    ) -> Self {
+        Self {
+            sensor: sensor.parse().expect("Invalid barometer sensor"),
+            address: address as u8,
+            oversampling: oversampling as u8,
+            iir_coefficient: iir_coefficient as u8,
+            sample_rate_hz,
+            use_fifo,
+            run_self_test,
+            sea_level_pressure,
+            stale_age_ms,
+        }
+    }
+}
+
+/// The Barometer Block converts samples from a barometer driver to an altitude.
+///
+/// The inputs are the pressure in Pa, the temperature in °C and the number of samples the
+/// driver averaged to produce them, which is zero when no new sample was read. The outputs are
+/// the pressure, the temperature and the altitude in meters above the sea level pressure,
+/// using the international barometric formula. They hold their last values, and the block
+/// goes stale, while no new samples arrive.
+pub struct BarometerBlock {
+    pub data: OldBlockData,
+    pub stale_check: StaleTracker,
+    pressure: f64,
+    temperature: f64,
+    altitude: f64,
+    previous_stale_check_time_ms: f64,
+}
+
+impl Default for BarometerBlock {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::from_scalar(0.0),
+            stale_check: StaleTracker::from_ms(0.0),
+            pressure: 0.0,
+            temperature: 0.0,
+            altitude: 0.0,
+            previous_stale_check_time_ms: 0.0,
+        }
+    }
+}
+
+impl IsValid for BarometerBlock {
+    fn is_valid(&self, app_time_s: f64) -> OldBlockData {
+        self.stale_check.is_valid(app_time_s)
+    }
+}
+
+impl ProcessBlock for BarometerBlock {
+    type Parameters = Parameters;
+    type Inputs = (f64, f64, f64);
+    type Output = (f64, f64, f64);
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        if self.previous_stale_check_time_ms != parameters.stale_age_ms {
+            self.stale_check = StaleTracker::from_ms(parameters.stale_age_ms);
+            self.previous_stale_check_time_ms = parameters.stale_age_ms;
+        }
+
+        let (pressure, temperature, samples) = inputs;
+        if samples > 0.0 {
+            self.pressure = pressure;
+            self.temperature = temperature;
+            let ratio = pressure / parameters.sea_level_pressure;
+            self.altitude = 44330.0 * (1.0 - num_traits::Float::powf(ratio, 1.0 / 5.255));
+            self.stale_check.mark_updated(context.time().as_secs_f64());
+            self.data = OldBlockData::from_scalar(self.pressure);
+        }
+
+        (self.pressure, self.temperature, self.altitude)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use core::time::Duration;
+    use corelib_traits_testing::StubRuntime;
+
+    #[test]
+    fn test_barometer_block() {
+        let parameters = Parameters::new(
+            "BMP390",
+            0x77 as f64,
+            8.0,
+            3.0,
+            25.0,
+            true,
+            false,
+            101325.0,
+            100.0,
+        );
+        assert_eq!(parameters.sensor, BarometerSensor::Bmp390);
+        let mut runtime = StubRuntime::default();
+        let mut block = BarometerBlock::default();
+
+        let output = block.process(&parameters, &runtime.context(), (101325.0, 20.0, 1.0));
+        assert_eq!(output, (101325.0, 20.0, 0.0));
+        let valid = block.is_valid(runtime.context().time().as_secs_f64());
+        assert_eq!(valid.scalar(), 1.0);
+
+        // About 111 m above sea level
+        let output = block.process(&parameters, &runtime.context(), (100000.0, 19.0, 3.0));
+        assert_relative_eq!(output.2, 110.9, epsilon = 0.1);
+        assert_eq!(block.data.scalar(), 100000.0);
+
+        runtime.set_time(Duration::from_secs(1));
+        let output = block.process(&parameters, &runtime.context(), (0.0, 0.0, 0.0));
+        assert_eq!(output.0, 100000.0);
+        let valid = block.is_valid(runtime.context().time().as_secs_f64());
+        assert_eq!(valid.scalar(), 0.0);
+    }
+}
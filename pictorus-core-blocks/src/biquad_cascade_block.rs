@@ -0,0 +1,265 @@
+use crate::filter_design::{self, Biquad, FilterSpec, MAX_POLES};
+use corelib_traits::{Context, Matrix, Pass, PassBy, ProcessBlock};
+use utils::{BlockData as OldBlockData, FromPass};
+
+/// Parameters for the Biquad Cascade Block with `S` sections
+pub struct Parameters<const S: usize> {
+    pub spec: FilterSpec,
+}
+
+impl<const S: usize> Parameters<S> {
+    /// Panics if the filter needs more than the block's `S` sections, or more poles than can
+    /// be designed
+    pub fn new(
+        response: &str,
+        design: &str,
+        order: f64,
+        frequency_hz: f64,
+        upper_frequency_hz: f64,
+        ripple_db: f64,
+    ) -> Self {
+        let spec = FilterSpec {
+            response: response.parse().expect("Invalid filter response"),
+            design: design.parse().expect("Invalid filter design"),
+            order: order as usize,
+            frequency_hz,
+            upper_frequency_hz,
+            ripple_db,
+        };
+        assert!(
+            spec.sections() <= S && spec.sections() * 2 <= MAX_POLES,
+            "Invalid filter order {}: needs {} sections, more than the {} available",
+            spec.order,
+            spec.sections(),
+            S
+        );
+        Self { spec }
+    }
+}
+
+/// The Biquad Cascade Block filters its input with a higher-order filter built from
+/// second-order sections.
+///
+/// The sections are designed from the parameters for the model's fundamental timestep on the
+/// first tick; see [`filter_design`] for the responses and designs available. `S` is the
+/// number of sections, which must be at least the number the design needs. The filter starts
+/// settled at its first input, so a constant input passes without a transient.
+///
+/// This block can accept a scalar or a matrix input. For a matrix input, the filter is applied
+/// independently to each element of the matrix.
+pub struct BiquadCascadeBlock<T: Pass, const S: usize> {
+    pub data: OldBlockData,
+    sections: Option<[Biquad; S]>,
+    /// State of each section, two values per section for each element of the input
+    state: Option<[[T; 2]; S]>,
+    output: T,
+}
+
+impl<T: Pass + Default, const S: usize> Default for BiquadCascadeBlock<T, S>
+where
+    OldBlockData: FromPass<T>,
+{
+    fn default() -> Self {
+        Self {
+            data: <OldBlockData as FromPass<T>>::from_pass(T::default().as_by()),
+            sections: None,
+            state: None,
+            output: T::default(),
+        }
+    }
+}
+
+impl<T: Pass, const S: usize> BiquadCascadeBlock<T, S> {
+    fn sections(&mut self, parameters: &Parameters<S>, context: &dyn Context) -> [Biquad; S] {
+        *self.sections.get_or_insert_with(|| {
+            let timestep = context.fundamental_timestep().as_secs_f64();
+            if timestep > 0.0 {
+                filter_design::design(&parameters.spec, 1.0 / timestep)
+            } else {
+                [Biquad::IDENTITY; S]
+            }
+        })
+    }
+}
+
+/// Filters one sample through every section
+fn filter<const S: usize>(sections: &[Biquad; S], state: &mut [[f64; 2]; S], input: f64) -> f64 {
+    sections
+        .iter()
+        .zip(state.iter_mut())
+        .fold(input, |value, (section, state)| section.step(state, value))
+}
+
+/// The state every section settles to with a constant input
+fn settle<const S: usize>(sections: &[Biquad; S], input: f64) -> [[f64; 2]; S] {
+    let mut value = input;
+    core::array::from_fn(|i| {
+        let (state, output) = sections[i].steady_state(value);
+        value = output;
+        state
+    })
+}
+
+impl<const S: usize> ProcessBlock for BiquadCascadeBlock<f64, S> {
+    type Inputs = f64;
+    type Output = f64;
+    type Parameters = Parameters<S>;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let sections = self.sections(parameters, context);
+        let state = self.state.get_or_insert_with(|| settle(&sections, input));
+        self.output = filter(&sections, state, input);
+        self.data = OldBlockData::from_scalar(self.output);
+        self.output
+    }
+}
+
+impl<const NROWS: usize, const NCOLS: usize, const S: usize> ProcessBlock
+    for BiquadCascadeBlock<Matrix<NROWS, NCOLS, f64>, S>
+where
+    OldBlockData: FromPass<Matrix<NROWS, NCOLS, f64>>,
+{
+    type Inputs = Matrix<NROWS, NCOLS, f64>;
+    type Output = Matrix<NROWS, NCOLS, f64>;
+    type Parameters = Parameters<S>;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let sections = self.sections(parameters, context);
+        let state = self.state.get_or_insert_with(|| {
+            let mut state = [[Matrix::zeroed(); 2]; S];
+            for (col, column) in input.data.iter().enumerate() {
+                for (row, value) in column.iter().enumerate() {
+                    for (section, settled) in settle(&sections, *value).iter().enumerate() {
+                        state[section][0].data[col][row] = settled[0];
+                        state[section][1].data[col][row] = settled[1];
+                    }
+                }
+            }
+            state
+        });
+
+        for (col, column) in input.data.iter().enumerate() {
+            for (row, value) in column.iter().enumerate() {
+                let mut element: [[f64; 2]; S] = core::array::from_fn(|section| {
+                    [
+                        state[section][0].data[col][row],
+                        state[section][1].data[col][row],
+                    ]
+                });
+                self.output.data[col][row] = filter(&sections, &mut element, *value);
+                for (section, values) in element.iter().enumerate() {
+                    state[section][0].data[col][row] = values[0];
+                    state[section][1].data[col][row] = values[1];
+                }
+            }
+        }
+
+        self.data = OldBlockData::from_pass(&self.output);
+        &self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use core::f64::consts::TAU;
+    use core::time::Duration;
+    use corelib_traits_testing::StubRuntime;
+
+    /// Runs a sine through a block for a second at 1 kHz and returns the peak output over
+    /// the last half, after the filter has settled
+    fn peak_output<T: Pass + Copy, const S: usize>(
+        block: &mut BiquadCascadeBlock<T, S>,
+        parameters: &Parameters<S>,
+        frequency_hz: f64,
+        make_input: impl Fn(f64) -> T,
+        read_output: impl Fn(PassBy<'_, T>) -> f64,
+    ) -> f64
+    where
+        BiquadCascadeBlock<T, S>: ProcessBlock<Inputs = T, Output = T, Parameters = Parameters<S>>,
+    {
+        let mut runtime = StubRuntime::default();
+        runtime.context.fundamental_timestep = Duration::from_millis(1);
+        let mut peak: f64 = 0.0;
+        for i in 0..1000 {
+            let t = i as f64 / 1000.0;
+            let input = make_input(num_traits::Float::sin(TAU * frequency_hz * t));
+            let output = read_output(block.process(parameters, &runtime.context(), input.as_by()));
+            if i >= 500 {
+                peak = peak.max(output.abs());
+            }
+            runtime.tick();
+        }
+        peak
+    }
+
+    #[test]
+    fn test_biquad_cascade_block_lowpass() {
+        let parameters = Parameters::new("LowPass", "Butterworth", 4.0, 10.0, 0.0, 0.0);
+        let sections = filter_design::design::<2>(&parameters.spec, 1000.0);
+        let runtime = StubRuntime {
+            context: corelib_traits_testing::StubContext {
+                fundamental_timestep: Duration::from_millis(1),
+                ..Default::default()
+            },
+        };
+
+        // Starting settled, a constant input passes straight through
+        let mut block = BiquadCascadeBlock::<f64, 2>::default();
+        for _ in 0..3 {
+            let output = block.process(&parameters, &runtime.context(), 2.0);
+            assert_relative_eq!(output, 2.0, epsilon = 1e-12);
+        }
+
+        for frequency in [2.0, 10.0, 40.0] {
+            let mut block = BiquadCascadeBlock::<f64, 2>::default();
+            let peak = peak_output(&mut block, &parameters, frequency, |x| x, |y| y);
+            let expected = filter_design::magnitude(&sections, frequency, 1000.0);
+            assert_relative_eq!(peak, expected, max_relative = 0.01);
+            assert_eq!(block.data.scalar(), block.output);
+        }
+    }
+
+    #[test]
+    fn test_biquad_cascade_block_notch_matrix() {
+        // Removes 50 Hz vibration from one channel while passing a slow signal on the other
+        let parameters = Parameters::new("Notch", "Butterworth", 0.0, 40.0, 62.5, 0.0);
+        let mut block = BiquadCascadeBlock::<Matrix<1, 2, f64>, 1>::default();
+        let vibration = peak_output(
+            &mut block,
+            &parameters,
+            50.0,
+            |x| Matrix { data: [[x], [0.0]] },
+            |y| y.data[0][0],
+        );
+        assert!(vibration < 0.01, "vibration {vibration}");
+
+        let mut block = BiquadCascadeBlock::<Matrix<1, 2, f64>, 1>::default();
+        let signal = peak_output(
+            &mut block,
+            &parameters,
+            5.0,
+            |x| Matrix { data: [[0.0], [x]] },
+            |y| y.data[1][0],
+        );
+        assert_relative_eq!(signal, 1.0, max_relative = 0.01);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid filter order")]
+    fn test_biquad_cascade_too_few_sections() {
+        // A 4th order band pass needs 4 sections
+        Parameters::<2>::new("BandPass", "Butterworth", 4.0, 10.0, 20.0, 0.0);
+    }
+}
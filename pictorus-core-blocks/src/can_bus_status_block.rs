@@ -0,0 +1,120 @@
+use corelib_traits::{Context, PassBy, ProcessBlock};
+use utils::BlockData as OldBlockData;
+
+/// Bus state codes reported by the platform
+const ERROR_PASSIVE: f64 = 2.0;
+const BUS_OFF: f64 = 3.0;
+
+/// Parameters for the CAN Bus Status Block
+pub struct Parameters {
+    /// Time constant of the bus load average, in seconds. Zero reports each tick's load.
+    pub load_time_constant: f64,
+}
+
+impl Parameters {
+    pub fn new(load_time_constant: f64) -> Self {
+        Self {
+            load_time_constant: load_time_constant.max(0.0),
+        }
+    }
+}
+
+/// The CAN Bus Status Block reports the health of a CAN bus.
+///
+/// The inputs come from the platform's CAN interface: the controller's error state, where 0
+/// is error active, 1 error warning, 2 error passive and 3 bus off, then the error frames
+/// received and the frames dropped from a full transmit queue since the previous tick, and
+/// the fraction of the tick the bus was busy. The outputs are whether the controller is bus
+/// off, whether it is error passive or worse, the averaged bus load, and the error frames and
+/// dropped frames since startup.
+pub struct CanBusStatusBlock {
+    pub data: OldBlockData,
+    bus_load: Option<f64>,
+    error_frames: f64,
+    tx_dropped: f64,
+}
+
+impl Default for CanBusStatusBlock {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::from_scalar(0.0),
+            bus_load: None,
+            error_frames: 0.0,
+            tx_dropped: 0.0,
+        }
+    }
+}
+
+impl ProcessBlock for CanBusStatusBlock {
+    type Parameters = Parameters;
+    type Inputs = (f64, f64, f64, f64);
+    type Output = (bool, bool, f64, f64, f64);
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let (state, error_frames, tx_dropped, bus_load) = inputs;
+        self.error_frames += error_frames;
+        self.tx_dropped += tx_dropped;
+
+        let dt = context
+            .timestep()
+            .unwrap_or(context.fundamental_timestep())
+            .as_secs_f64();
+        let bus_load = match self.bus_load {
+            Some(average) if parameters.load_time_constant > 0.0 => {
+                average + (bus_load - average) * dt / (parameters.load_time_constant + dt)
+            }
+            _ => bus_load,
+        };
+        self.bus_load = Some(bus_load);
+
+        self.data = OldBlockData::from_scalar(bus_load);
+        (
+            state >= BUS_OFF,
+            state >= ERROR_PASSIVE,
+            bus_load,
+            self.error_frames,
+            self.tx_dropped,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use core::time::Duration;
+    use corelib_traits_testing::StubContext;
+
+    #[test]
+    fn test_can_bus_status_block() {
+        let parameters = Parameters::new(0.03);
+        let context = StubContext {
+            fundamental_timestep: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let mut block = CanBusStatusBlock::default();
+
+        let output = block.process(&parameters, &context, (0.0, 0.0, 0.0, 0.4));
+        assert_eq!(output, (false, false, 0.4, 0.0, 0.0));
+
+        // The load average moves a quarter of the way to a new load each tick
+        let output = block.process(&parameters, &context, (1.0, 2.0, 0.0, 0.8));
+        assert_eq!((output.0, output.1), (false, false));
+        assert_relative_eq!(output.2, 0.5);
+        assert_eq!((output.3, output.4), (2.0, 0.0));
+
+        let output = block.process(&parameters, &context, (2.0, 1.0, 5.0, 0.5));
+        assert_eq!((output.0, output.1), (false, true));
+        assert_eq!((output.3, output.4), (3.0, 5.0));
+
+        let output = block.process(&parameters, &context, (3.0, 0.0, 1.0, 0.0));
+        assert_eq!((output.0, output.1), (true, true));
+        assert_eq!(output.4, 6.0);
+        assert_relative_eq!(block.data.scalar(), 0.375);
+    }
+}
@@ -0,0 +1,282 @@
+extern crate alloc;
+use alloc::vec::Vec;
+use core::str::FromStr;
+use corelib_traits::{ByteSliceSignal, Context, PassBy, ProcessBlock};
+use log::debug;
+use utils::byte_data::checksum::{ChecksumAlgorithm, ChecksumKind};
+use utils::byte_data::ByteOrderSpec;
+use utils::{BlockData as OldBlockData, IsValid, ParseEnumError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumMode {
+    /// Append the checksum to the input bytes
+    Append,
+    /// Check and strip the checksum trailing the input bytes
+    Verify,
+}
+
+impl FromStr for ChecksumMode {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Append" => Ok(Self::Append),
+            "Verify" => Ok(Self::Verify),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+/// Parameters for the Checksum Block
+pub struct Parameters {
+    pub algorithm: ChecksumAlgorithm,
+    pub mode: ChecksumMode,
+    /// Byte order the checksum is stored in
+    pub byte_order: ByteOrderSpec,
+    /// Number of leading bytes (e.g. sync or header bytes) not covered by the checksum
+    pub skip_bytes: usize,
+}
+
+impl Parameters {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        algorithm: &str,
+        crc_poly: f64,
+        crc_init: f64,
+        crc_refin: bool,
+        crc_refout: bool,
+        crc_xorout: f64,
+        mode: &str,
+        byte_order: &str,
+        skip_bytes: f64,
+    ) -> Self {
+        let kind = algorithm
+            .parse::<ChecksumKind>()
+            .expect("Invalid checksum algorithm");
+        Self {
+            algorithm: ChecksumAlgorithm::from_kind(
+                kind,
+                crc_poly as u32,
+                crc_init as u32,
+                crc_refin,
+                crc_refout,
+                crc_xorout as u32,
+            )
+            .expect("Invalid CRC parameters"),
+            mode: mode.parse().expect("Invalid checksum mode"),
+            byte_order: byte_order
+                .parse::<ByteOrderSpec>()
+                .expect("Invalid byte order"),
+            skip_bytes: skip_bytes as usize,
+        }
+    }
+}
+
+/// The Checksum Block computes or checks a CRC-8/16/32, Fletcher-16/32, XOR or sum-8
+/// checksum over its input bytes.
+///
+/// In `Append` mode the input is output with its checksum appended, ready to be sent on to a
+/// transmit block. The validity output is always true.
+///
+/// In `Verify` mode the input is expected to end with a checksum, for example a frame from a
+/// receive block. If the checksum matches, the input without the checksum is output and can be
+/// fed to a `BytesUnpackBlock`. Otherwise the last valid payload is held and the validity
+/// output is false for that tick.
+pub struct ChecksumBlock {
+    pub data: OldBlockData,
+    buffer: Vec<u8>,
+    valid: bool,
+}
+
+impl Default for ChecksumBlock {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::from_bytes(&[]),
+            buffer: Vec::new(),
+            valid: false,
+        }
+    }
+}
+
+impl IsValid for ChecksumBlock {
+    fn is_valid(&self, _app_time_s: f64) -> OldBlockData {
+        OldBlockData::scalar_from_bool(self.valid)
+    }
+}
+
+impl ProcessBlock for ChecksumBlock {
+    type Parameters = Parameters;
+    type Inputs = ByteSliceSignal;
+    type Output = (ByteSliceSignal, bool);
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let Parameters {
+            algorithm,
+            mode,
+            byte_order,
+            skip_bytes,
+        } = *parameters;
+
+        match mode {
+            ChecksumMode::Append => {
+                self.buffer.clear();
+                // An empty input is nothing to send, rather than a bare checksum
+                if !input.is_empty() {
+                    algorithm.append(input, skip_bytes, byte_order, &mut self.buffer);
+                }
+                self.valid = true;
+            }
+            ChecksumMode::Verify => match algorithm.verify(input, skip_bytes, byte_order) {
+                Some(payload) => {
+                    self.buffer.clear();
+                    self.buffer.extend_from_slice(payload);
+                    self.valid = true;
+                }
+                None => {
+                    debug!("Checksum mismatch for input: {:?}", input);
+                    self.valid = false;
+                }
+            },
+        }
+
+        self.data.set_bytes(&self.buffer);
+        (&self.buffer, self.valid)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corelib_traits_testing::StubContext;
+
+    #[test]
+    fn test_append_crc32() {
+        let context = StubContext::default();
+        let parameters = Parameters::new(
+            "Crc32",
+            0x04C1_1DB7 as f64,
+            0xFFFF_FFFFu32 as f64,
+            true,
+            true,
+            0xFFFF_FFFFu32 as f64,
+            "Append",
+            "LittleEndian",
+            0.0,
+        );
+        let mut block = ChecksumBlock::default();
+
+        let (output, valid) = block.process(&parameters, &context, b"123456789");
+        assert_eq!(output, b"123456789\x26\x39\xF4\xCB");
+        assert!(valid);
+        assert_eq!(
+            block.data,
+            OldBlockData::from_bytes(b"123456789\x26\x39\xF4\xCB")
+        );
+    }
+
+    #[test]
+    fn test_empty_input_outputs_nothing() {
+        let context = StubContext::default();
+        let parameters = Parameters::new(
+            "Crc16",
+            4129.0,
+            65535.0,
+            false,
+            false,
+            0.0,
+            "Append",
+            "BigEndian",
+            0.0,
+        );
+        let mut block = ChecksumBlock::default();
+
+        block.process(&parameters, &context, b"\x01");
+        let (output, valid) = block.process(&parameters, &context, b"");
+        assert!(output.is_empty());
+        assert!(valid);
+        assert_eq!(block.data, OldBlockData::from_bytes(b""));
+    }
+
+    #[test]
+    fn test_verify_holds_last_valid_payload() {
+        let context = StubContext::default();
+        let parameters = Parameters::new(
+            "Xor8",
+            0.0,
+            0.0,
+            false,
+            false,
+            0.0,
+            "Verify",
+            "BigEndian",
+            1.0,
+        );
+        let mut block = ChecksumBlock::default();
+
+        // Leading sync byte is skipped
+        let (output, valid) = block.process(&parameters, &context, b"\xAA\x01\x02\x03");
+        assert_eq!(output, b"\xAA\x01\x02");
+        assert!(valid);
+        assert!(block.is_valid(0.0).any());
+
+        let (output, valid) = block.process(&parameters, &context, b"\xAA\x01\x02\x04");
+        assert_eq!(output, b"\xAA\x01\x02");
+        assert!(!valid);
+        assert!(!block.is_valid(0.0).any());
+    }
+
+    #[test]
+    fn test_append_then_verify_roundtrip() {
+        let context = StubContext::default();
+        let append = Parameters::new(
+            "Crc16",
+            4129.0,
+            65535.0,
+            false,
+            false,
+            0.0,
+            "Append",
+            "BigEndian",
+            0.0,
+        );
+        let verify = Parameters::new(
+            "Crc16",
+            4129.0,
+            65535.0,
+            false,
+            false,
+            0.0,
+            "Verify",
+            "BigEndian",
+            0.0,
+        );
+        let mut append_block = ChecksumBlock::default();
+        let mut verify_block = ChecksumBlock::default();
+
+        let (framed, _) = append_block.process(&append, &context, b"\x10\x20\x30");
+        assert_eq!(framed.len(), 5);
+        let (payload, valid) = verify_block.process(&verify, &context, framed);
+        assert_eq!(payload, b"\x10\x20\x30");
+        assert!(valid);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid CRC parameters")]
+    fn test_crc_poly_too_wide() {
+        Parameters::new(
+            "Crc8",
+            263.0,
+            0.0,
+            false,
+            false,
+            0.0,
+            "Append",
+            "BigEndian",
+            0.0,
+        );
+    }
+}
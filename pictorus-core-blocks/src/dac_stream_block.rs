@@ -0,0 +1,289 @@
+use core::f64::consts::TAU;
+use core::str::FromStr;
+
+use corelib_traits::{Context, Matrix, PassBy, ProcessBlock};
+use utils::{BlockData as OldBlockData, ParseEnumError};
+
+/// Signal a DAC Stream Block plays back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DacWaveform {
+    /// The block input, ramped linearly from one tick's value to the next
+    Input,
+    /// The waveform table, one row per sample, repeated
+    Table,
+    Sine,
+    Triangle,
+    Sawtooth,
+}
+
+impl FromStr for DacWaveform {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Input" => Ok(Self::Input),
+            "Table" => Ok(Self::Table),
+            "Sine" => Ok(Self::Sine),
+            "Triangle" => Ok(Self::Triangle),
+            "Sawtooth" => Ok(Self::Sawtooth),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+/// Parameters for the DAC Stream Block
+pub struct Parameters<const CHANNELS: usize, const TABLE: usize> {
+    pub waveform: DacWaveform,
+    /// Rate the DAC plays samples at, independent of the model rate
+    pub sample_rate_hz: f64,
+    /// Angular frequency of generated waveforms, in rad/s
+    pub frequency: f64,
+    /// Amplitude of generated waveforms, in volts
+    pub amplitude: f64,
+    /// Phase of generated waveforms, in radians
+    pub phase: f64,
+    /// Offset of generated waveforms, in volts
+    pub bias: f64,
+    /// Waveform table in volts, with a row per sample and a column per channel
+    pub table: [[f64; CHANNELS]; TABLE],
+    /// Output voltage at full scale
+    pub reference_voltage: f64,
+    /// Full-scale count of the DAC
+    pub full_scale: f64,
+}
+
+impl<const CHANNELS: usize, const TABLE: usize> Parameters<CHANNELS, TABLE> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        waveform: &str,
+        sample_rate_hz: f64,
+        frequency: f64,
+        amplitude: f64,
+        phase: f64,
+        bias: f64,
+        table: &OldBlockData,
+        reference_voltage: f64,
+        resolution_bits: f64,
+    ) -> Self {
+        let mut table_arr = [[0.0; CHANNELS]; TABLE];
+        for (sample, row) in table_arr.iter_mut().enumerate() {
+            for (channel, value) in row.iter_mut().enumerate() {
+                *value = table.at_rc(sample, channel);
+            }
+        }
+
+        Self {
+            waveform: waveform.parse().expect("Invalid DAC waveform"),
+            sample_rate_hz,
+            frequency,
+            amplitude,
+            phase,
+            bias,
+            table: table_arr,
+            reference_voltage,
+            full_scale: ((1u32 << resolution_bits as u32) - 1) as f64,
+        }
+    }
+
+    /// Voltage of a generated waveform at `time` seconds
+    fn generate(&self, time: f64) -> f64 {
+        let cycles = (self.frequency * time + self.phase) / TAU;
+        let t = num_traits::Float::fract(cycles);
+        let t = if t < 0.0 { t + 1.0 } else { t };
+        let x = match self.waveform {
+            DacWaveform::Sine => num_traits::Float::sin(TAU * t),
+            DacWaveform::Triangle => 4.0 * if t < 0.5 { t } else { 1.0 - t } - 1.0,
+            _ => 2.0 * t - 1.0,
+        };
+        self.amplitude * x + self.bias
+    }
+
+    /// DAC count for a voltage, clipped to the DAC's range
+    fn to_counts(&self, voltage: f64) -> f64 {
+        num_traits::Float::round(voltage / self.reference_voltage * self.full_scale)
+            .clamp(0.0, self.full_scale)
+    }
+}
+
+/// The DAC Stream Block generates samples for a DAC that plays them back at its own sample
+/// rate, which can be much faster than the model.
+///
+/// Each tick the block produces the samples due to play before the next tick, in DAC counts,
+/// for the platform to queue while the previous tick's samples play. The input is the
+/// voltage of each channel, used by the `Input` waveform. The outputs are a buffer of up to
+/// `SAMPLES` samples per channel and the number of them that are valid. Samples that don't
+/// fit in the buffer, or whose time has passed, are skipped rather than delayed so playback
+/// stays aligned with app time.
+pub struct DacStreamBlock<const CHANNELS: usize, const SAMPLES: usize, const TABLE: usize> {
+    pub data: OldBlockData,
+    buffer: Matrix<SAMPLES, CHANNELS, f64>,
+    /// Index of the next sample to generate, counted from app time zero
+    next_sample: u64,
+    previous_input: Option<[f64; CHANNELS]>,
+}
+
+impl<const CHANNELS: usize, const SAMPLES: usize, const TABLE: usize> Default
+    for DacStreamBlock<CHANNELS, SAMPLES, TABLE>
+{
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::from_scalar(0.0),
+            buffer: Matrix::zeroed(),
+            next_sample: 0,
+            previous_input: None,
+        }
+    }
+}
+
+impl<const CHANNELS: usize, const SAMPLES: usize, const TABLE: usize> ProcessBlock
+    for DacStreamBlock<CHANNELS, SAMPLES, TABLE>
+{
+    type Parameters = Parameters<CHANNELS, TABLE>;
+    type Inputs = Matrix<1, CHANNELS, f64>;
+    type Output = (Matrix<SAMPLES, CHANNELS, f64>, f64);
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        // Index of the first sample that plays at or after a time, allowing for rounding
+        let first_sample_at = |time: f64| {
+            num_traits::Float::ceil(time * parameters.sample_rate_hz - 1e-6).max(0.0) as u64
+        };
+        let now = context.time().as_secs_f64();
+        let next_tick = now + context.fundamental_timestep().as_secs_f64();
+        let first = self.next_sample.max(first_sample_at(now));
+        let end = first_sample_at(next_tick).max(first);
+        let valid = ((end - first) as usize).min(SAMPLES);
+
+        let current = input.data.map(|channel| channel[0]);
+        let previous = self.previous_input.unwrap_or(current);
+        for i in 0..valid {
+            let sample = first + i as u64;
+            let generated = parameters.generate(sample as f64 / parameters.sample_rate_hz);
+            for (channel, samples) in self.buffer.data.iter_mut().enumerate() {
+                let voltage = match parameters.waveform {
+                    DacWaveform::Input => {
+                        let ramp = (i + 1) as f64 / valid as f64;
+                        previous[channel] + (current[channel] - previous[channel]) * ramp
+                    }
+                    DacWaveform::Table if TABLE > 0 => {
+                        parameters.table[(sample % TABLE as u64) as usize][channel]
+                    }
+                    DacWaveform::Table => 0.0,
+                    _ => generated,
+                };
+                samples[i] = parameters.to_counts(voltage);
+            }
+        }
+
+        self.next_sample = end;
+        self.previous_input = Some(current);
+        self.data = OldBlockData::from_scalar(valid as f64);
+        (&self.buffer, valid as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::time::Duration;
+    use corelib_traits_testing::{StubContext, StubRuntime};
+
+    fn parameters<const TABLE: usize>(
+        waveform: &str,
+        table: &OldBlockData,
+    ) -> Parameters<2, TABLE> {
+        Parameters::new(waveform, 10_000.0, 0.0, 1.0, 0.0, 1.65, table, 3.3, 12.0)
+    }
+
+    #[test]
+    fn test_dac_stream_block_paces_samples() {
+        // A 10 kHz stream from a 100 Hz model produces 100 samples a tick
+        let parameters = Parameters::<2, 0> {
+            frequency: TAU * 1000.0,
+            ..parameters("Sine", &OldBlockData::from_scalar(0.0))
+        };
+        let mut runtime = StubRuntime::default();
+        runtime.context.fundamental_timestep = Duration::from_millis(10);
+        let mut block = DacStreamBlock::<2, 128, 0>::default();
+
+        let mut previous_last = None;
+        for _ in 0..3 {
+            let (buffer, valid) = block.process(&parameters, &runtime.context(), &Matrix::zeroed());
+            assert_eq!(valid, 100.0);
+            // The 1 kHz sine crosses mid scale every five samples and peaks between samples
+            assert_eq!(buffer.data[0][0], 2048.0);
+            assert_eq!(buffer.data[0][5], 2048.0);
+            assert_eq!(buffer.data[1][2], buffer.data[1][3]);
+            assert!(buffer.data[1][2] > 3000.0);
+            if let Some(previous_last) = previous_last {
+                assert_eq!(previous_last, buffer.data[0][99]);
+            }
+            previous_last = Some(buffer.data[0][99]);
+            runtime.tick();
+        }
+        assert_eq!(block.data.scalar(), 100.0);
+    }
+
+    #[test]
+    fn test_dac_stream_block_skips_late_samples() {
+        let table = OldBlockData::from_row_slice(4, 2, &[0.0, 3.3, 1.1, 2.2, 2.2, 1.1, 3.3, 0.0]);
+        let parameters = parameters::<4>("Table", &table);
+        let mut context = StubContext {
+            fundamental_timestep: Duration::from_micros(500),
+            ..Default::default()
+        };
+        let mut block = DacStreamBlock::<2, 8, 4>::default();
+
+        let (buffer, valid) = block.process(&parameters, &context, &Matrix::zeroed());
+        assert_eq!(valid, 5.0);
+        assert_eq!(buffer.data[0][..5], [0.0, 1365.0, 2730.0, 4095.0, 0.0]);
+        assert_eq!(buffer.data[1][..5], [4095.0, 2730.0, 1365.0, 0.0, 4095.0]);
+
+        // The model ran late, so the samples that should have played already are skipped, and
+        // more samples are due than fit in the buffer
+        context.time = Duration::from_micros(1000);
+        context.fundamental_timestep = Duration::from_micros(1000);
+        let (buffer, valid) = block.process(&parameters, &context, &Matrix::zeroed());
+        assert_eq!(valid, 8.0);
+        assert_eq!(buffer.data[0][..4], [2730.0, 4095.0, 0.0, 1365.0]);
+        context.time = Duration::from_micros(2000);
+        let (buffer, _) = block.process(&parameters, &context, &Matrix::zeroed());
+        // The two samples that didn't fit were dropped, so the next tick starts on time
+        assert_eq!(buffer.data[0][0], 0.0);
+    }
+
+    #[test]
+    fn test_dac_stream_block_ramps_input() {
+        let parameters = Parameters::<2, 0> {
+            sample_rate_hz: 4000.0,
+            ..parameters("Input", &OldBlockData::from_scalar(0.0))
+        };
+        let mut runtime = StubRuntime::default();
+        runtime.context.fundamental_timestep = Duration::from_millis(1);
+        let mut block = DacStreamBlock::<2, 4, 0>::default();
+
+        let (buffer, _) = block.process(
+            &parameters,
+            &runtime.context(),
+            &Matrix {
+                data: [[1.1], [5.0]],
+            },
+        );
+        assert_eq!(buffer.data, [[1365.0; 4], [4095.0; 4]]);
+
+        runtime.tick();
+        let (buffer, valid) = block.process(
+            &parameters,
+            &runtime.context(),
+            &Matrix {
+                data: [[2.2], [0.0]],
+            },
+        );
+        assert_eq!(valid, 4.0);
+        assert_eq!(buffer.data[0], [1706.0, 2048.0, 2389.0, 2730.0]);
+        assert_eq!(buffer.data[1][3], 0.0);
+    }
+}
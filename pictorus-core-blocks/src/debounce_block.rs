@@ -0,0 +1,122 @@
+use core::marker::PhantomData;
+use core::time::Duration;
+
+use corelib_traits::{Context, PassBy, ProcessBlock};
+use utils::BlockData as OldBlockData;
+
+use crate::gpio_output_block::ToBool;
+
+/// Parameters for the Debounce Block
+pub struct Parameters {
+    /// How long the input must hold a new level before the output follows it
+    pub debounce_time_ms: f64,
+}
+
+impl Parameters {
+    pub fn new(debounce_time_ms: f64) -> Self {
+        Self { debounce_time_ms }
+    }
+}
+
+/// The Debounce Block filters out bounces of a digital input, such as a switch contact.
+///
+/// The output starts at the first input level. It only changes once the input has held a new
+/// level for the debounce time, so changes that revert sooner are ignored. Non-zero inputs
+/// are high.
+pub struct DebounceBlock<T: ToBool> {
+    pub data: OldBlockData,
+    output: Option<bool>,
+    /// Latest input level and the time it started
+    candidate: (bool, Duration),
+    _unused: PhantomData<T>,
+}
+
+impl<T: ToBool> Default for DebounceBlock<T> {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::scalar_from_bool(false),
+            output: None,
+            candidate: (false, Duration::ZERO),
+            _unused: PhantomData,
+        }
+    }
+}
+
+impl<T: ToBool> ProcessBlock for DebounceBlock<T> {
+    type Parameters = Parameters;
+    type Inputs = T;
+    type Output = bool;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let level = T::to_bool(input);
+        let now = context.time();
+        let output = match self.output {
+            None => {
+                self.candidate = (level, now);
+                level
+            }
+            Some(output) => {
+                if level != self.candidate.0 {
+                    self.candidate = (level, now);
+                }
+                let debounce_time =
+                    Duration::from_nanos((parameters.debounce_time_ms * 1e6) as u64);
+                if now.saturating_sub(self.candidate.1) >= debounce_time {
+                    self.candidate.0
+                } else {
+                    output
+                }
+            }
+        };
+        self.output = Some(output);
+        self.data.set_scalar_bool(output);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corelib_traits_testing::StubRuntime;
+
+    #[test]
+    fn test_debounce_block_ignores_chatter() {
+        let parameters = Parameters::new(30.0);
+        let mut runtime = StubRuntime::default();
+        let mut block = DebounceBlock::<f64>::default();
+
+        let samples = [
+            (0, 0.0, false),
+            // The switch closes with bounces
+            (10, 1.0, false),
+            (20, 0.0, false),
+            (30, 1.0, false),
+            (40, 1.0, false),
+            (50, 1.0, false),
+            (60, 1.0, true),
+            // A single glitch doesn't open it again
+            (70, 0.0, true),
+            (80, 1.0, true),
+            (120, 1.0, true),
+        ];
+        for (time_ms, input, expected) in samples {
+            runtime.set_time(Duration::from_millis(time_ms));
+            let output = block.process(&parameters, &runtime.context(), input);
+            assert_eq!(output, expected, "at {time_ms} ms");
+        }
+        assert_eq!(block.data.scalar(), 1.0);
+    }
+
+    #[test]
+    fn test_debounce_block_starts_at_input() {
+        let parameters = Parameters::new(30.0);
+        let runtime = StubRuntime::default();
+        let mut block = DebounceBlock::<bool>::default();
+        assert!(block.process(&parameters, &runtime.context(), true));
+    }
+}
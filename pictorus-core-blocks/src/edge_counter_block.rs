@@ -0,0 +1,153 @@
+use core::str::FromStr;
+use corelib_traits::{Context, PassBy, ProcessBlock};
+use utils::{BlockData as OldBlockData, ParseEnumError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureEdge {
+    Rising,
+    Falling,
+    Both,
+}
+
+impl FromStr for CaptureEdge {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Rising" => Ok(Self::Rising),
+            "Falling" => Ok(Self::Falling),
+            "Both" => Ok(Self::Both),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+/// Parameters for the Edge Counter Block
+pub struct Parameters {
+    /// Which edges of the input are counted
+    pub edge: CaptureEdge,
+}
+
+impl Parameters {
+    pub fn new(edge: &str) -> Self {
+        Self {
+            edge: edge.parse().expect("Invalid capture edge"),
+        }
+    }
+}
+
+/// Estimates a rate from counts and the time of the latest count.
+///
+/// Dividing the counts in a tick by the tick period quantizes the rate to one count per tick,
+/// which is coarse for slow signals. Instead the counts are divided by the time between the
+/// last counted edges of two ticks, which spans exactly those counts. While no counts arrive
+/// the rate can be at most one count over the time since the last one, so it decays toward
+/// zero rather than holding.
+#[derive(Debug, Default)]
+pub(crate) struct EdgeRate {
+    last_edge_time: Option<f64>,
+    rate: f64,
+}
+
+impl EdgeRate {
+    pub fn update(&mut self, counts: f64, last_edge_time: f64, now: f64) -> f64 {
+        if counts != 0.0 {
+            if let Some(previous) = self.last_edge_time {
+                let span = last_edge_time - previous;
+                if span > 0.0 {
+                    self.rate = counts / span;
+                }
+            }
+            self.last_edge_time = Some(last_edge_time);
+        } else if let Some(previous) = self.last_edge_time {
+            let bound = 1.0 / (now - previous);
+            if self.rate.abs() > bound {
+                self.rate = if self.rate > 0.0 { bound } else { -bound };
+            }
+        }
+        self.rate
+    }
+}
+
+/// The Edge Counter Block counts edges on an input between ticks and measures their frequency.
+///
+/// The inputs are the number of edges counted by the hardware since the previous tick and the
+/// app time of the last of them. Platforms that don't timestamp edges pass the current time.
+/// The outputs are the edges this tick, the total edges since startup and the signal frequency
+/// in Hz, which accounts for both edges of each cycle being counted.
+pub struct EdgeCounterBlock {
+    pub data: OldBlockData,
+    total: f64,
+    rate: EdgeRate,
+}
+
+impl Default for EdgeCounterBlock {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::from_scalar(0.0),
+            total: 0.0,
+            rate: EdgeRate::default(),
+        }
+    }
+}
+
+impl ProcessBlock for EdgeCounterBlock {
+    type Parameters = Parameters;
+    type Inputs = (f64, f64);
+    type Output = (f64, f64, f64);
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let (edges, last_edge_time) = inputs;
+        self.total += edges;
+        let edge_rate = self
+            .rate
+            .update(edges, last_edge_time, context.time().as_secs_f64());
+        let frequency = match parameters.edge {
+            CaptureEdge::Both => edge_rate / 2.0,
+            CaptureEdge::Rising | CaptureEdge::Falling => edge_rate,
+        };
+        self.data = OldBlockData::from_scalar(frequency);
+        (edges, self.total, frequency)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use core::time::Duration;
+    use corelib_traits_testing::StubRuntime;
+
+    #[test]
+    fn test_edge_counter_block_frequency() {
+        let parameters = Parameters::new("Both");
+        let mut runtime = StubRuntime::default();
+        let mut block = EdgeCounterBlock::default();
+
+        // A 15 Hz signal sampled every 100 ms has 3 edges in some ticks and 2 in others
+        runtime.set_time(Duration::from_millis(100));
+        let output = block.process(&parameters, &runtime.context(), (3.0, 0.1));
+        assert_eq!(output, (3.0, 3.0, 0.0));
+
+        runtime.set_time(Duration::from_millis(200));
+        let output = block.process(&parameters, &runtime.context(), (3.0, 0.1 + 3.0 / 30.0));
+        assert_relative_eq!(output.2, 15.0, epsilon = 1e-9);
+
+        runtime.set_time(Duration::from_millis(300));
+        let last_edge = 0.2 + 2.0 / 30.0;
+        let output = block.process(&parameters, &runtime.context(), (2.0, last_edge));
+        assert_eq!(output.1, 8.0);
+        assert_relative_eq!(output.2, 15.0, epsilon = 1e-9);
+        assert_relative_eq!(block.data.scalar(), 15.0, epsilon = 1e-9);
+
+        // Once the signal stops the frequency decays
+        runtime.set_time(Duration::from_secs(1));
+        let output = block.process(&parameters, &runtime.context(), (0.0, 0.0));
+        assert_relative_eq!(output.2, 0.5 / (1.0 - last_edge), epsilon = 1e-9);
+        assert_eq!(output.1, 8.0);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::running_statistics_block::{forgetting_weight, Averaging};
+use crate::traits::Float;
+use corelib_traits::{Context, PassBy, ProcessBlock};
+use heapless::Deque;
+use utils::BlockData as OldBlockData;
+
+/// Parameters for the Entropy Block
+pub struct Parameters {
+    /// Lower edge of the histogram; lower samples count in the first bin
+    pub min: f64,
+    /// Upper edge of the histogram; higher samples count in the last bin
+    pub max: f64,
+    pub averaging: Averaging,
+    /// Time constant of the exponential forgetting, in seconds
+    pub time_constant_s: f64,
+}
+
+impl Parameters {
+    pub fn new(min: f64, max: f64, averaging: &str, time_constant_s: f64) -> Self {
+        Self {
+            min,
+            max,
+            averaging: averaging.parse().expect("Invalid averaging"),
+            time_constant_s,
+        }
+    }
+}
+
+/// The Entropy Block computes the Shannon entropy, in bits, of a histogram of its input with
+/// `B` equal bins between `min` and `max`. The entropy is 0 when every sample lands in the
+/// same bin, and at most `log2(B)` when they are spread evenly over all of them.
+///
+/// With `Window` averaging the histogram counts the last `N` samples. With `Exponential`
+/// averaging it counts every sample, with a weight that decays with the given time constant.
+///
+/// The second output is the entropy difference: the entropy minus what it was `N` samples
+/// earlier, or since the first sample until `N` have arrived. NaN samples are ignored.
+pub struct EntropyBlock<F: Float, const N: usize, const B: usize> {
+    pub data: OldBlockData,
+    window: Deque<usize, N>,
+    /// Sample count, or decayed weight, of each bin
+    histogram: [F; B],
+    history: Deque<F, N>,
+    output: (F, F),
+}
+
+impl<F: Float, const N: usize, const B: usize> Default for EntropyBlock<F, N, B> {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::from_scalar(0.0),
+            window: Deque::new(),
+            histogram: [F::zero(); B],
+            history: Deque::new(),
+            output: (F::zero(), F::zero()),
+        }
+    }
+}
+
+impl<F: Float, const N: usize, const B: usize> EntropyBlock<F, N, B> {
+    fn bin(parameters: &Parameters, x: f64) -> usize {
+        let position = (x - parameters.min) / (parameters.max - parameters.min) * B as f64;
+        // Saturates below zero and at infinity
+        (position as usize).min(B - 1)
+    }
+
+    fn entropy(&self) -> F {
+        let total = self.histogram.iter().fold(F::zero(), |total, w| total + *w);
+        if total <= F::zero() {
+            return F::zero();
+        }
+        self.histogram
+            .iter()
+            .filter(|w| **w > F::zero())
+            .fold(F::zero(), |entropy, w| {
+                let p = *w / total;
+                entropy - p * num_traits::Float::log2(p)
+            })
+    }
+}
+
+impl<F: Float, const N: usize, const B: usize> ProcessBlock for EntropyBlock<F, N, B> {
+    type Inputs = F;
+    type Output = (F, F);
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let x = num_traits::ToPrimitive::to_f64(&input).unwrap_or(f64::NAN);
+        if !x.is_nan() {
+            let bin = Self::bin(parameters, x);
+            match parameters.averaging {
+                Averaging::Window => {
+                    if self.window.is_full() {
+                        if let Some(oldest) = self.window.pop_front() {
+                            self.histogram[oldest] -= F::one();
+                        }
+                    }
+                    // Can't fail, there's room after removing the oldest sample
+                    self.window.push_back(bin).ok();
+                    self.histogram[bin] += F::one();
+                }
+                Averaging::Exponential => {
+                    let alpha: F = forgetting_weight(context, parameters.time_constant_s);
+                    for weight in self.histogram.iter_mut() {
+                        *weight *= F::one() - alpha;
+                    }
+                    self.histogram[bin] += alpha;
+                }
+            }
+
+            let entropy = self.entropy();
+            if self.history.is_full() {
+                self.history.pop_front();
+            }
+            // Can't fail, there's room after removing the oldest entropy
+            self.history.push_back(entropy).ok();
+            let earlier = self.history.front().copied().unwrap_or(entropy);
+            self.output = (entropy, entropy - earlier);
+        }
+        self.data = OldBlockData::from_scalar(
+            num_traits::ToPrimitive::to_f64(&self.output.0).unwrap_or_default(),
+        );
+        self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use core::time::Duration;
+    use corelib_traits_testing::StubContext;
+
+    #[test]
+    fn test_entropy_window() {
+        let context = StubContext::default();
+        let parameters = Parameters::new(0.0, 4.0, "Window", 0.0);
+        let mut block = EntropyBlock::<f64, 4, 4>::default();
+
+        for _ in 0..4 {
+            let (entropy, diff) = block.process(&parameters, &context, 0.5);
+            assert_eq!(entropy, 0.0);
+            assert_eq!(diff, 0.0);
+        }
+
+        // Two samples in each of two bins
+        block.process(&parameters, &context, 1.5);
+        let (entropy, diff) = block.process(&parameters, &context, 1.5);
+        assert_relative_eq!(entropy, 1.0);
+        assert_relative_eq!(diff, 1.0);
+        let (entropy, _) = block.process(&parameters, &context, 2.5);
+        assert_relative_eq!(entropy, 1.5);
+
+        // Three samples earlier the window held three samples in the first bin and one in
+        // the second
+        let (entropy, diff) = block.process(&parameters, &context, 100.0);
+        let three_one = -(0.75 * 0.75f64.log2() + 0.25 * 0.25f64.log2());
+        assert_relative_eq!(entropy, 1.5);
+        assert_relative_eq!(diff, 1.5 - three_one);
+
+        // Out of range samples count in the end bins
+        let (entropy, diff) = block.process(&parameters, &context, -5.0);
+        assert_relative_eq!(entropy, 2.0);
+        assert_relative_eq!(diff, 1.0);
+        assert_relative_eq!(block.data.scalar(), 2.0);
+
+        let (entropy, _) = block.process(&parameters, &context, f64::NAN);
+        assert_relative_eq!(entropy, 2.0);
+    }
+
+    #[test]
+    fn test_entropy_exponential() {
+        let context = StubContext {
+            fundamental_timestep: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let parameters = Parameters::new(-1.0, 1.0, "Exponential", 1.0);
+        let mut block = EntropyBlock::<f32, 100, 8>::default();
+
+        // An alternating input splits evenly between two bins
+        for i in 0..1000 {
+            let x = if i % 2 == 0 { -0.9 } else { 0.9 };
+            block.process(&parameters, &context, x);
+        }
+        assert_relative_eq!(block.output.0, 1.0, epsilon = 1e-3);
+
+        // Then it settles on a single value, and the histogram forgets the old ones
+        for _ in 0..2000 {
+            block.process(&parameters, &context, 0.1);
+        }
+        let (entropy, diff) = block.output;
+        assert_relative_eq!(entropy, 0.0, epsilon = 1e-3);
+        assert_relative_eq!(diff, 0.0, epsilon = 1e-3);
+    }
+}
@@ -0,0 +1,260 @@
+use crate::kalman_filter_block::{correct, matrix_from, to_nalgebra, Estimate};
+use crate::traits::Float;
+use corelib_traits::{Matrix, PassBy, ProcessBlock};
+use nalgebra::SMatrix;
+use pictorus_nalgebra_interop::MatrixExt;
+use utils::{BlockData as OldBlockData, FromPass};
+
+/// State transition `x[k+1] = f(x[k], u[k], dt)`, or its Jacobian with respect to the state
+pub type TransitionFn<const NX: usize, const NU: usize, const NR: usize, F> =
+    fn(&Matrix<NX, 1, F>, &Matrix<NU, 1, F>, F) -> Matrix<NX, NR, F>;
+
+/// Measurement `z[k] = h(x[k])`, or its Jacobian with respect to the state
+pub type MeasurementFn<const NX: usize, const NZ: usize, const NC: usize, F> =
+    fn(&Matrix<NX, 1, F>) -> Matrix<NZ, NC, F>;
+
+/// Parameters for the Extended Kalman Filter Block: the nonlinear model, its Jacobians, and
+/// the covariances of the process noise Q and measurement noise R
+pub struct Parameters<const NX: usize, const NU: usize, const NZ: usize, F: Float> {
+    pub transition: TransitionFn<NX, NU, 1, F>,
+    pub transition_jacobian: TransitionFn<NX, NU, NX, F>,
+    pub measurement: MeasurementFn<NX, NZ, 1, F>,
+    pub measurement_jacobian: MeasurementFn<NX, NZ, NX, F>,
+    pub q: Matrix<NX, NX, F>,
+    pub r: Matrix<NZ, NZ, F>,
+    pub initial_state: Matrix<NX, 1, F>,
+    pub initial_covariance: Matrix<NX, NX, F>,
+}
+
+impl<const NX: usize, const NU: usize, const NZ: usize, F: Float> Parameters<NX, NU, NZ, F> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        transition: TransitionFn<NX, NU, 1, F>,
+        transition_jacobian: TransitionFn<NX, NU, NX, F>,
+        measurement: MeasurementFn<NX, NZ, 1, F>,
+        measurement_jacobian: MeasurementFn<NX, NZ, NX, F>,
+        q: &OldBlockData,
+        r: &OldBlockData,
+        initial_state: &OldBlockData,
+        initial_covariance: &OldBlockData,
+    ) -> Self {
+        Self {
+            transition,
+            transition_jacobian,
+            measurement,
+            measurement_jacobian,
+            q: matrix_from(q),
+            r: matrix_from(r),
+            initial_state: matrix_from(initial_state),
+            initial_covariance: matrix_from(initial_covariance),
+        }
+    }
+}
+
+/// The Extended Kalman Filter Block estimates the state of a nonlinear system.
+///
+/// It works like the [`KalmanFilterBlock`](crate::KalmanFilterBlock), with the same inputs and
+/// outputs, but the model is given as functions. The state is predicted through the
+/// transition function, with the timestep of the tick, and the covariance through its
+/// Jacobian at the previous estimate. The measurement Jacobian is evaluated at the prediction.
+pub struct ExtendedKalmanFilterBlock<const NX: usize, const NU: usize, const NZ: usize, F: Float>
+where
+    OldBlockData: FromPass<Matrix<NX, 1, F>>,
+{
+    pub data: OldBlockData,
+    estimate: Option<(Matrix<NX, 1, F>, SMatrix<F, NX, NX>)>,
+    output: Estimate<NX, NZ, F>,
+}
+
+impl<const NX: usize, const NU: usize, const NZ: usize, F: Float> Default
+    for ExtendedKalmanFilterBlock<NX, NU, NZ, F>
+where
+    OldBlockData: FromPass<Matrix<NX, 1, F>>,
+{
+    fn default() -> Self {
+        Self {
+            data: <OldBlockData as FromPass<Matrix<NX, 1, F>>>::from_pass(&Matrix::zeroed()),
+            estimate: None,
+            output: (Matrix::zeroed(), Matrix::zeroed(), Matrix::zeroed()),
+        }
+    }
+}
+
+impl<const NX: usize, const NU: usize, const NZ: usize, F: Float> ProcessBlock
+    for ExtendedKalmanFilterBlock<NX, NU, NZ, F>
+where
+    OldBlockData: FromPass<Matrix<NX, 1, F>>,
+{
+    type Inputs = (Matrix<NU, 1, F>, Matrix<NZ, 1, F>, bool);
+    type Output = Estimate<NX, NZ, F>;
+    type Parameters = Parameters<NX, NU, NZ, F>;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn corelib_traits::Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let (u, z, z_valid) = inputs;
+        let (x, p) = match &mut self.estimate {
+            Some((x, p)) => {
+                let dt =
+                    F::from_duration(context.timestep().unwrap_or(context.fundamental_timestep()));
+                let f = to_nalgebra(&(parameters.transition_jacobian)(x, u, dt));
+                *x = (parameters.transition)(x, u, dt);
+                *p = f * *p * f.transpose() + to_nalgebra(&parameters.q);
+                (x, p)
+            }
+            None => {
+                let (x, p) = self.estimate.insert((
+                    parameters.initial_state,
+                    to_nalgebra(&parameters.initial_covariance),
+                ));
+                (x, p)
+            }
+        };
+
+        let mut innovation = SMatrix::<F, NZ, 1>::zeros();
+        if z_valid {
+            innovation = z.as_view() - to_nalgebra(&(parameters.measurement)(x));
+            let h = to_nalgebra(&(parameters.measurement_jacobian)(x));
+            let mut state = to_nalgebra(x);
+            if correct(&mut state, p, &innovation, &h, &to_nalgebra(&parameters.r)) {
+                *x = Matrix::from_view(&state.as_view());
+            } else {
+                innovation.fill(F::zero());
+            }
+        }
+
+        self.output = (
+            *x,
+            Matrix::from_view(&p.as_view()),
+            Matrix::from_view(&innovation.as_view()),
+        );
+        self.data = OldBlockData::from_pass(&self.output.0);
+        let (x, p, innovation) = &self.output;
+        (x, p, innovation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use core::time::Duration;
+    use corelib_traits_testing::StubContext;
+
+    // A target moving at constant velocity, tracked by range and bearing from the origin.
+    // The state is x and y position and velocity.
+    fn transition(x: &Matrix<4, 1, f64>, _u: &Matrix<1, 1, f64>, dt: f64) -> Matrix<4, 1, f64> {
+        let [px, py, vx, vy] = x.data[0];
+        Matrix {
+            data: [[px + vx * dt, py + vy * dt, vx, vy]],
+        }
+    }
+
+    fn transition_jacobian(
+        _x: &Matrix<4, 1, f64>,
+        _u: &Matrix<1, 1, f64>,
+        dt: f64,
+    ) -> Matrix<4, 4, f64> {
+        Matrix {
+            data: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [dt, 0.0, 1.0, 0.0],
+                [0.0, dt, 0.0, 1.0],
+            ],
+        }
+    }
+
+    fn measurement(x: &Matrix<4, 1, f64>) -> Matrix<2, 1, f64> {
+        let [px, py, ..] = x.data[0];
+        Matrix {
+            data: [[px.hypot(py), py.atan2(px)]],
+        }
+    }
+
+    fn measurement_jacobian(x: &Matrix<4, 1, f64>) -> Matrix<2, 4, f64> {
+        let [px, py, ..] = x.data[0];
+        let range_squared = px * px + py * py;
+        let range = range_squared.sqrt();
+        Matrix {
+            data: [
+                [px / range, -py / range_squared],
+                [py / range, px / range_squared],
+                [0.0, 0.0],
+                [0.0, 0.0],
+            ],
+        }
+    }
+
+    #[test]
+    fn test_extended_kalman_filter_tracking() {
+        let q = OldBlockData::from_row_slice(
+            4,
+            4,
+            &[
+                1e-6, 0.0, 0.0, 0.0, //
+                0.0, 1e-6, 0.0, 0.0, //
+                0.0, 0.0, 1e-4, 0.0, //
+                0.0, 0.0, 0.0, 1e-4,
+            ],
+        );
+        let r = OldBlockData::from_row_slice(2, 2, &[0.01, 0.0, 0.0, 1e-4]);
+        // Starting a few metres off with no idea of the velocity
+        let initial_state = OldBlockData::from_vector(&[18.0, 12.0, 0.0, 0.0]);
+        let initial_covariance = OldBlockData::from_row_slice(
+            4,
+            4,
+            &[
+                25.0, 0.0, 0.0, 0.0, //
+                0.0, 25.0, 0.0, 0.0, //
+                0.0, 0.0, 25.0, 0.0, //
+                0.0, 0.0, 0.0, 25.0,
+            ],
+        );
+        let parameters = Parameters::new(
+            transition,
+            transition_jacobian,
+            measurement,
+            measurement_jacobian,
+            &q,
+            &r,
+            &initial_state,
+            &initial_covariance,
+        );
+        let context = StubContext {
+            fundamental_timestep: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let mut block = ExtendedKalmanFilterBlock::<4, 1, 2, f64>::default();
+
+        let truth = |i: usize| {
+            let t = i as f64 * 0.1;
+            Matrix {
+                data: [[20.0 + 2.0 * t, 10.0 - 1.0 * t, 2.0, -1.0]],
+            }
+        };
+        for i in 0..300 {
+            // No measurements for the last two seconds
+            let valid = i < 280;
+            let z = measurement(&truth(i));
+            let (x, _, innovation) =
+                block.process(&parameters, &context, (&Matrix::zeroed(), &z, valid));
+            if i > 100 {
+                for (estimate, expected) in x.data[0].iter().zip(truth(i).data[0]) {
+                    assert_relative_eq!(*estimate, expected, epsilon = 0.05);
+                }
+            }
+            if !valid {
+                assert_eq!(innovation.data[0], [0.0, 0.0]);
+            }
+        }
+        assert_relative_eq!(
+            block.data.get_data()[0],
+            truth(299).data[0][0],
+            epsilon = 0.05
+        );
+    }
+}
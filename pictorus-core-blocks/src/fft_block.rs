@@ -0,0 +1,484 @@
+use crate::traits::Float;
+use core::str::FromStr;
+use corelib_traits::{Matrix, Pass, PassBy, ProcessBlock, Scalar};
+use utils::{BlockData as OldBlockData, FromPass, ParseEnumError};
+
+/// Window applied to each frame before the FFT, to reduce the leakage of each frequency into
+/// its neighbouring bins
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FftWindow {
+    #[default]
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+    /// Wide main lobe but almost no scalloping, for reading amplitudes accurately between bins
+    FlatTop,
+}
+
+impl FromStr for FftWindow {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Rectangular" => Ok(Self::Rectangular),
+            "Hann" => Ok(Self::Hann),
+            "Hamming" => Ok(Self::Hamming),
+            "Blackman" => Ok(Self::Blackman),
+            "FlatTop" => Ok(Self::FlatTop),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+impl FftWindow {
+    /// Cosine series coefficients of the periodic window
+    fn coefficients(&self) -> [f64; 5] {
+        match self {
+            Self::Rectangular => [1.0, 0.0, 0.0, 0.0, 0.0],
+            Self::Hann => [0.5, 0.5, 0.0, 0.0, 0.0],
+            Self::Hamming => [0.54, 0.46, 0.0, 0.0, 0.0],
+            Self::Blackman => [0.42, 0.5, 0.08, 0.0, 0.0],
+            Self::FlatTop => [
+                0.215_578_95,
+                0.416_631_58,
+                0.277_263_158,
+                0.083_578_947,
+                0.006_947_368,
+            ],
+        }
+    }
+}
+
+/// Parameters for the FFT Block
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Parameters {
+    pub window: FftWindow,
+    /// New samples between frames. Zero, or anything above `N`, gives back-to-back frames
+    /// that don't overlap.
+    pub hop_size: usize,
+}
+
+impl Parameters {
+    pub fn new(window: &str, hop_size: f64) -> Parameters {
+        Parameters {
+            window: window.parse().expect("Invalid FFT window"),
+            hop_size: hop_size as usize,
+        }
+    }
+}
+
+/// Forward FFT of one frame, done in place on its real and imaginary parts
+pub trait FftTransform<T, const N: usize>: Default {
+    fn transform(&mut self, re: &mut [T; N], im: &mut [T; N]);
+}
+
+/// `no_std` transform. Power of two sizes use a radix-2 FFT; other sizes fall back to a direct
+/// DFT, which takes time proportional to `N²`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CoreFft;
+
+impl<T: Float, const N: usize> FftTransform<T, N> for CoreFft {
+    fn transform(&mut self, re: &mut [T; N], im: &mut [T; N]) {
+        transform(re, im);
+    }
+}
+
+/// Spectrum, amplitude, phase, power spectral density and frequency of each bin
+type Spectra<T, const N: usize> = (
+    Matrix<2, N, T>,
+    Matrix<1, N, T>,
+    Matrix<1, N, T>,
+    Matrix<1, N, T>,
+    Matrix<1, N, T>,
+);
+
+/// FFT Block performs FFT on the last `N` samples it received.
+///
+/// On each time step a new sample is added to the buffer. Once the buffer is full, a frame of
+/// the last `N` samples is windowed and transformed every `hop_size` samples, so frames can
+/// overlap for a short-time Fourier transform. The outputs only change when a frame is
+/// transformed:
+/// - the raw spectrum, with the real and imaginary parts of each bin in a column
+/// - the amplitude of each frequency, corrected for the window, so a sinusoid of amplitude A
+///   reads A in its bin
+/// - the phase of each bin, in radians
+/// - the power spectral density, in units² per Hz, corrected for the window
+/// - the frequency of each bin, in Hz, for the sample rate of the model's fundamental timestep
+///
+/// For a real input the bins above `N / 2` are the negative frequencies, which mirror the
+/// positive ones. The amplitude and density of a positive frequency include its mirror, so
+/// they read zero above `N / 2`, and the frequency output gives those bins their negative
+/// frequencies.
+///
+/// The transform is done by `F`, [`CoreFft`] by default.
+pub struct FftBlock<T: Scalar, const N: usize, F = CoreFft> {
+    pub data: OldBlockData,
+    /// Circular buffer of the last `N` samples
+    samples: [T; N],
+    /// Index of the next sample to be added to the buffer, which is also the oldest sample.
+    sample_index: usize,
+    /// Samples received, until the buffer first fills
+    filled: usize,
+    /// Samples until the next frame is transformed
+    countdown: usize,
+    /// The window last applied, with its values
+    window: Option<(FftWindow, [T; N])>,
+    output: Spectra<T, N>,
+    fft: F,
+}
+
+impl<T: Float, const N: usize, F: FftTransform<T, N>> Default for FftBlock<T, N, F>
+where
+    OldBlockData: FromPass<Matrix<2, N, T>>,
+{
+    fn default() -> Self {
+        Self {
+            data: <OldBlockData as FromPass<Matrix<2, N, T>>>::from_pass(Matrix::zeroed().as_by()),
+            samples: [T::zero(); N],
+            sample_index: 0,
+            filled: 0,
+            countdown: 0,
+            window: None,
+            output: (
+                Matrix::zeroed(),
+                Matrix::zeroed(),
+                Matrix::zeroed(),
+                Matrix::zeroed(),
+                Matrix::zeroed(),
+            ),
+            fft: F::default(),
+        }
+    }
+}
+
+impl<T: Float, const N: usize, F: FftTransform<T, N>> ProcessBlock for FftBlock<T, N, F>
+where
+    OldBlockData: FromPass<Matrix<2, N, T>>,
+{
+    type Inputs = T;
+    type Output = Spectra<T, N>;
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn corelib_traits::Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        if self.update(parameters, context, inputs) {
+            let spectrum = &self.output.0;
+            self.data = <OldBlockData as FromPass<Matrix<2, N, T>>>::from_pass(spectrum.as_by());
+        }
+        self.outputs()
+    }
+}
+
+impl<T: Float, const N: usize, F: FftTransform<T, N>> FftBlock<T, N, F> {
+    /// Adds a sample, transforming a frame when one is due. Returns whether it did.
+    pub fn update(
+        &mut self,
+        parameters: &Parameters,
+        context: &dyn corelib_traits::Context,
+        input: T,
+    ) -> bool {
+        self.samples[self.sample_index] = input;
+        self.sample_index = (self.sample_index + 1) % N;
+        if self.filled < N {
+            self.filled += 1;
+            if self.filled < N {
+                return false;
+            }
+        } else if self.countdown > 0 {
+            self.countdown -= 1;
+            return false;
+        }
+        let hop_size = match parameters.hop_size {
+            0 => N,
+            hop_size => hop_size.min(N),
+        };
+        self.countdown = hop_size - 1;
+
+        let window = match self.window {
+            Some((window, values)) if window == parameters.window => values,
+            _ => {
+                let values = periodic_window(parameters.window);
+                self.window = Some((parameters.window, values));
+                values
+            }
+        };
+        let mut re = [T::zero(); N];
+        let mut im = [T::zero(); N];
+        for (i, value) in re.iter_mut().enumerate() {
+            // Oldest sample first
+            *value = self.samples[(self.sample_index + i) % N] * window[i];
+        }
+        self.fft.transform(&mut re, &mut im);
+
+        let sample_rate = match T::from_duration(context.fundamental_timestep()) {
+            timestep if timestep > T::zero() => T::one() / timestep,
+            _ => T::zero(),
+        };
+        let n = T::from(N).unwrap();
+        let two = T::one() + T::one();
+        let window_sum = window.iter().fold(T::zero(), |sum, w| sum + *w);
+        let window_power = window.iter().fold(T::zero(), |sum, w| sum + *w * *w);
+        let (spectrum, magnitude, phase, psd, frequency) = &mut self.output;
+        for k in 0..N {
+            spectrum.data[k] = [re[k], im[k]];
+            phase.data[k][0] = num_traits::Float::atan2(im[k], re[k]);
+
+            let bin = T::from(k).unwrap();
+            let (frequency_k, one_sided) = if 2 * k <= N {
+                (bin, if k == 0 || 2 * k == N { T::one() } else { two })
+            } else {
+                (bin - n, T::zero())
+            };
+            frequency.data[k][0] = frequency_k * sample_rate / n;
+
+            let power = re[k] * re[k] + im[k] * im[k];
+            magnitude.data[k][0] = one_sided * num_traits::Float::sqrt(power) / window_sum;
+            psd.data[k][0] = if sample_rate > T::zero() {
+                one_sided * power / (sample_rate * window_power)
+            } else {
+                T::zero()
+            };
+        }
+        true
+    }
+
+    /// The real and imaginary parts of each bin of the last frame
+    pub fn spectrum(&self) -> &Matrix<2, N, T> {
+        &self.output.0
+    }
+}
+
+impl<T: Float, const N: usize, F: FftTransform<T, N>> FftBlock<T, N, F>
+where
+    OldBlockData: FromPass<Matrix<2, N, T>>,
+{
+    fn outputs(&self) -> PassBy<'_, <Self as ProcessBlock>::Output> {
+        let (spectrum, magnitude, phase, psd, frequency) = &self.output;
+        (spectrum, magnitude, phase, psd, frequency)
+    }
+}
+
+fn periodic_window<T: Float, const N: usize>(window: FftWindow) -> [T; N] {
+    let coefficients = window.coefficients();
+    core::array::from_fn(|n| {
+        let phase = T::TAU * T::from(n).unwrap() / T::from(N).unwrap();
+        coefficients
+            .iter()
+            .enumerate()
+            .fold(T::zero(), |sum, (harmonic, coefficient)| {
+                // The cosine terms alternate in sign
+                let sign = if harmonic % 2 == 0 { 1.0 } else { -1.0 };
+                let cosine = num_traits::Float::cos(phase * T::from(harmonic).unwrap());
+                sum + T::from(sign * coefficient).unwrap() * cosine
+            })
+    })
+}
+
+/// Forward DFT in place
+fn transform<T: Float, const N: usize>(re: &mut [T; N], im: &mut [T; N]) {
+    if N.is_power_of_two() {
+        radix2(re, im);
+        return;
+    }
+
+    let input = *re;
+    for k in 0..N {
+        let (mut sum_re, mut sum_im) = (T::zero(), T::zero());
+        for (n, value) in input.iter().enumerate() {
+            // Reduce the index first to keep the angle accurate
+            let angle = -T::TAU * T::from((k * n) % N).unwrap() / T::from(N).unwrap();
+            sum_re += *value * num_traits::Float::cos(angle);
+            sum_im += *value * num_traits::Float::sin(angle);
+        }
+        re[k] = sum_re;
+        im[k] = sum_im;
+    }
+}
+
+/// Iterative radix-2 decimation-in-time FFT
+fn radix2<T: Float, const N: usize>(re: &mut [T; N], im: &mut [T; N]) {
+    let bits = N.trailing_zeros();
+    if bits == 0 {
+        return;
+    }
+    for i in 0..N {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if j > i {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= N {
+        let angle = -T::TAU / T::from(len).unwrap();
+        for start in (0..N).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = num_traits::Float::sin_cos(angle * T::from(k).unwrap());
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len *= 2;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::f64;
+
+    use super::*;
+    use crate::SinewaveBlock;
+    use approx::assert_relative_eq;
+    use core::time::Duration;
+    use corelib_traits::GeneratorBlock;
+    use corelib_traits_testing::StubRuntime;
+
+    #[test]
+    fn test_fft_block() {
+        let mut runtime = StubRuntime::default();
+        runtime.context.fundamental_timestep = Duration::from_secs_f32(0.1);
+
+        // 2Hz sinewave, amplitude 5, with small bias
+        let mut sinewave_2_hz: SinewaveBlock<f64> = SinewaveBlock::default();
+        let sinewave_2_hz_parameters = <SinewaveBlock<f64> as GeneratorBlock>::Parameters::new(
+            5.0,
+            2.0 * f64::consts::TAU,
+            0.0,
+            1.2345,
+        );
+
+        // 3Hz sinewave, amplitude 10, with small phase shift
+        let mut sinewave_3_hz: SinewaveBlock<f64> = SinewaveBlock::default();
+        let sinewave_3_hz_parameters = <SinewaveBlock<f64> as GeneratorBlock>::Parameters::new(
+            10.0,
+            3.0 * f64::consts::TAU,
+            0.5,
+            0.0,
+        );
+
+        let mut fft_block: FftBlock<f64, 10> = FftBlock::default();
+        let fft_parameters = Parameters::default();
+
+        for _ in 0..100 {
+            let output_2hz = sinewave_2_hz.generate(&sinewave_2_hz_parameters, &runtime.context);
+            let output_3hz = sinewave_3_hz.generate(&sinewave_3_hz_parameters, &runtime.context);
+
+            let combined = output_2hz + output_3hz;
+
+            let _ = fft_block.process(&fft_parameters, &runtime.context, combined);
+            runtime.tick();
+        }
+
+        let (spectrum, magnitude, _, _, frequency) = &fft_block.output;
+        let output_magnitudes = spectrum.data.map(|[re, im]| (re * re + im * im).sqrt());
+        let expected_output = [
+            12.345, // DC value (bias in the signal from 2hz signal)
+            0.0,    // 1Hz
+            25.0,   // 2Hz response twice as strong as DC bias
+            50.0,   // 3Hz response twice as strong as 2Hz response
+            0.0,    // 4Hz
+            0.0,    // 5Hz
+            0.0,    // 4Hz
+            50.0,   // 3Hz
+            25.0,   // 2Hz
+            0.0,    // 1Hz
+        ];
+
+        for i in 0..10 {
+            assert_relative_eq!(output_magnitudes[i], expected_output[i], epsilon = 0.01);
+        }
+
+        let expected_amplitudes = [1.2345, 0.0, 5.0, 10.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0];
+        let expected_frequencies = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, -4.0, -3.0, -2.0, -1.0];
+        for i in 0..10 {
+            assert_relative_eq!(magnitude.data[i][0], expected_amplitudes[i], epsilon = 0.01);
+            assert_relative_eq!(
+                frequency.data[i][0],
+                expected_frequencies[i],
+                epsilon = 1e-6
+            );
+        }
+    }
+
+    #[test]
+    fn test_fft_block_window_hop_and_psd() {
+        let mut runtime = StubRuntime::default();
+        runtime.context.fundamental_timestep = Duration::from_millis(1);
+        let parameters = Parameters::new("FlatTop", 16.0);
+        let mut block = FftBlock::<f32, 64>::default();
+
+        // 2 V at 101.5625 Hz, halfway between the bins at 93.75 Hz and 109.375 Hz
+        let signal =
+            |i: usize| 2.0 * f32::sin(core::f32::consts::TAU * 101.5625 * i as f32 / 1000.0);
+        let mut frames = 0;
+        let mut last = Matrix::<2, 64, f32>::zeroed();
+        for i in 0..160 {
+            let (spectrum, ..) = block.process(&parameters, &runtime.context(), signal(i));
+            if *spectrum != last {
+                frames += 1;
+                last = *spectrum;
+            }
+            runtime.tick();
+        }
+        // Frames at the 64th sample and every 16 after
+        assert_eq!(frames, 7);
+
+        // The flat-top window reads the amplitude even between bins
+        let (_, magnitude, _, psd, frequency) = &block.output;
+        let peak = (0..32)
+            .max_by(|a, b| magnitude.data[*a][0].total_cmp(&magnitude.data[*b][0]))
+            .unwrap();
+        assert_relative_eq!(magnitude.data[peak][0], 2.0, max_relative = 0.01);
+        assert_relative_eq!(frequency.data[6][0], 93.75);
+
+        // The density integrates to the signal's power, half its amplitude squared
+        let power: f32 = (0..64).map(|k| psd.data[k][0] * 1000.0 / 64.0).sum();
+        assert_relative_eq!(power, 2.0, max_relative = 0.01);
+    }
+
+    #[test]
+    fn test_fft_block_window_change() {
+        let runtime = StubRuntime::default();
+        let mut parameters = Parameters::default();
+        let mut block = FftBlock::<f64, 4>::default();
+
+        for _ in 0..4 {
+            block.process(&parameters, &runtime.context(), 1.0);
+        }
+        assert_eq!(block.spectrum().data[0], [4.0, 0.0]);
+
+        // The Hann window sums to half the frame
+        parameters.window = FftWindow::Hann;
+        for _ in 0..4 {
+            block.process(&parameters, &runtime.context(), 1.0);
+        }
+        assert_relative_eq!(block.spectrum().data[0][0], 2.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_radix2_matches_dft() {
+        let input: [f64; 16] = core::array::from_fn(|i| (i as f64 * 0.7).sin() + i as f64 * 0.1);
+        let (mut re, mut im) = (input, [0.0; 16]);
+        radix2(&mut re, &mut im);
+        for k in 0..16 {
+            let (mut sum_re, mut sum_im) = (0.0, 0.0);
+            for (n, value) in input.iter().enumerate() {
+                let angle = -f64::consts::TAU * (k * n) as f64 / 16.0;
+                sum_re += value * angle.cos();
+                sum_im += value * angle.sin();
+            }
+            assert_relative_eq!(re[k], sum_re, epsilon = 1e-12);
+            assert_relative_eq!(im[k], sum_im, epsilon = 1e-12);
+        }
+    }
+}
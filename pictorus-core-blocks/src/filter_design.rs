@@ -0,0 +1,863 @@
+//! Digital filter design as cascaded second-order sections
+//!
+//! Filters are designed the classical way: an analog low-pass prototype with a cutoff of
+//! 1 rad/s is transformed to the requested response and frequencies, then mapped to a digital
+//! filter by the bilinear transform, with the frequencies prewarped so the cutoffs land where
+//! they were asked for. The poles and zeros are then grouped into second-order sections,
+//! which are far less sensitive to coefficient rounding than a single high-order filter.
+//!
+//! FIR filters are designed by the window method: the ideal impulse response of the filter,
+//! a sum of sinc functions, is truncated to the number of taps and shaped by a window that
+//! trades transition width against stopband attenuation. The taps are symmetric, so the
+//! filter has linear phase and delays every frequency by the same half the filter length.
+use core::f64::consts::PI;
+use core::ops::{Add, Div, Mul, Neg, Sub};
+use core::str::FromStr;
+
+use num_traits::Float;
+use utils::ParseEnumError;
+
+/// Most poles a design can have. Low-pass and high-pass filters have as many poles as their
+/// order, band-pass and band-stop filters twice as many.
+pub const MAX_POLES: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterResponse {
+    LowPass,
+    HighPass,
+    BandPass,
+    BandStop,
+    /// A second-order band-stop that removes a single frequency
+    Notch,
+}
+
+impl FromStr for FilterResponse {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "LowPass" => Ok(Self::LowPass),
+            "HighPass" => Ok(Self::HighPass),
+            "BandPass" => Ok(Self::BandPass),
+            "BandStop" => Ok(Self::BandStop),
+            "Notch" => Ok(Self::Notch),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterDesign {
+    /// Maximally flat passband
+    Butterworth,
+    /// Equiripple passband with a sharper transition than Butterworth
+    ChebyshevI,
+    /// Flat passband and equiripple stopband. The cutoff is where the stopband starts.
+    ChebyshevII,
+    /// Maximally flat group delay, which preserves the shape of pulses and steps
+    Bessel,
+}
+
+impl FromStr for FilterDesign {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Butterworth" => Ok(Self::Butterworth),
+            "ChebyshevI" => Ok(Self::ChebyshevI),
+            "ChebyshevII" => Ok(Self::ChebyshevII),
+            "Bessel" => Ok(Self::Bessel),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FilterSpec {
+    pub response: FilterResponse,
+    /// Ignored by `Notch`, which is always a second-order Butterworth band-stop
+    pub design: FilterDesign,
+    /// Order of the low-pass prototype. Band-pass and band-stop filters have twice this order.
+    pub order: usize,
+    /// Cutoff of low-pass and high-pass filters, and the lower edge of the band of the others,
+    /// in Hz. Butterworth, Bessel and notch edges are where the gain is -3 dB.
+    pub frequency_hz: f64,
+    /// Upper edge of the band of band-pass, band-stop and notch filters, in Hz
+    pub upper_frequency_hz: f64,
+    /// Passband ripple of Chebyshev I filters and stopband attenuation of Chebyshev II filters,
+    /// in dB
+    pub ripple_db: f64,
+}
+
+impl FilterSpec {
+    /// Number of second-order sections the design needs
+    pub fn sections(&self) -> usize {
+        match self.response {
+            FilterResponse::LowPass | FilterResponse::HighPass => self.order.div_ceil(2),
+            FilterResponse::BandPass | FilterResponse::BandStop => self.order,
+            FilterResponse::Notch => 1,
+        }
+    }
+}
+
+/// A second-order section, `(b0 + b1·z⁻¹ + b2·z⁻²) / (1 + a1·z⁻¹ + a2·z⁻²)`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Biquad {
+    pub b: [f64; 3],
+    pub a: [f64; 2],
+}
+
+impl Biquad {
+    /// Passes the input through unchanged
+    pub const IDENTITY: Self = Self {
+        b: [1.0, 0.0, 0.0],
+        a: [0.0, 0.0],
+    };
+
+    /// Filters one sample in transposed direct form II, which keeps two state values
+    pub fn step(&self, state: &mut [f64; 2], input: f64) -> f64 {
+        let output = self.b[0] * input + state[0];
+        state[0] = self.b[1] * input - self.a[0] * output + state[1];
+        state[1] = self.b[2] * input - self.a[1] * output;
+        output
+    }
+
+    /// The state the section settles to with a constant input, and its output
+    pub fn steady_state(&self, input: f64) -> ([f64; 2], f64) {
+        let denominator = 1.0 + self.a[0] + self.a[1];
+        let output = if denominator.abs() > f64::EPSILON {
+            input * (self.b[0] + self.b[1] + self.b[2]) / denominator
+        } else {
+            0.0
+        };
+        let s1 = self.b[2] * input - self.a[1] * output;
+        ([self.b[1] * input - self.a[0] * output + s1, s1], output)
+    }
+
+    fn response(&self, z_inv: Complex) -> Complex {
+        let z_inv2 = z_inv * z_inv;
+        let numerator = Complex::real(self.b[0]) + z_inv * self.b[1] + z_inv2 * self.b[2];
+        let denominator = Complex::real(1.0) + z_inv * self.a[0] + z_inv2 * self.a[1];
+        numerator / denominator
+    }
+}
+
+/// Gain of a cascade of sections at a frequency
+pub fn magnitude(sections: &[Biquad], frequency_hz: f64, sample_rate_hz: f64) -> f64 {
+    let z_inv = Complex::exp_j(-2.0 * PI * frequency_hz / sample_rate_hz);
+    sections
+        .iter()
+        .fold(Complex::real(1.0), |response, section| {
+            response * section.response(z_inv)
+        })
+        .abs()
+}
+
+/// Designs a filter for a sample rate. Frequencies are limited to just below the Nyquist
+/// frequency. Sections the design doesn't need are left as the identity.
+///
+/// Panics if the design needs more than `S` sections or more than [`MAX_POLES`] poles.
+pub fn design<const S: usize>(spec: &FilterSpec, sample_rate_hz: f64) -> [Biquad; S] {
+    assert!(
+        spec.sections() <= S && spec.sections() * 2 <= MAX_POLES,
+        "Filter of order {} needs {} sections, more than the {} available",
+        spec.order,
+        spec.sections(),
+        S
+    );
+
+    let mut sections = [Biquad::IDENTITY; S];
+    let nyquist = sample_rate_hz / 2.0;
+    let clamp = |frequency: f64| frequency.clamp(nyquist * 1e-6, nyquist * 0.999);
+    let (low, high) = match spec.response {
+        FilterResponse::LowPass | FilterResponse::HighPass => {
+            (clamp(spec.frequency_hz), clamp(spec.frequency_hz))
+        }
+        _ => (
+            clamp(spec.frequency_hz.min(spec.upper_frequency_hz)),
+            clamp(spec.frequency_hz.max(spec.upper_frequency_hz)),
+        ),
+    };
+
+    // A notch is the band-stop of a first-order low-pass
+    let (design, order, response) = match spec.response {
+        FilterResponse::Notch => (FilterDesign::Butterworth, 1, FilterResponse::BandStop),
+        response => (spec.design, spec.order, response),
+    };
+    if order == 0 {
+        return sections;
+    }
+
+    let fs2 = 2.0 * sample_rate_hz;
+    let warp = |frequency: f64| fs2 * Float::tan(PI * frequency / sample_rate_hz);
+    let (mut zeros, mut poles, mut gain) = prototype(design, order, spec.ripple_db);
+    match response {
+        FilterResponse::LowPass => lowpass(&mut zeros, &mut poles, &mut gain, warp(low)),
+        FilterResponse::HighPass => highpass(&mut zeros, &mut poles, &mut gain, warp(low)),
+        FilterResponse::BandPass => {
+            bandpass(&mut zeros, &mut poles, &mut gain, warp(low), warp(high))
+        }
+        FilterResponse::BandStop => {
+            bandstop(&mut zeros, &mut poles, &mut gain, warp(low), warp(high))
+        }
+        FilterResponse::Notch => unreachable!(),
+    }
+    bilinear(&mut zeros, &mut poles, &mut gain, fs2);
+    to_sections(&zeros, &poles, gain, &mut sections);
+    sections
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FirWindow {
+    /// Narrowest transition, but only about 21 dB of stopband attenuation
+    Rectangular,
+    /// About 44 dB of stopband attenuation
+    Hann,
+    /// About 53 dB of stopband attenuation
+    Hamming,
+    /// About 74 dB of stopband attenuation, with the widest transition
+    Blackman,
+    /// Attenuation set by `beta`: 0 is rectangular, 5 is close to Hamming, 8.6 to Blackman
+    Kaiser { beta: f64 },
+}
+
+impl FromStr for FirWindow {
+    type Err = ParseEnumError;
+    /// Kaiser windows parse with a beta of 0; set it afterwards
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Rectangular" => Ok(Self::Rectangular),
+            "Hann" => Ok(Self::Hann),
+            "Hamming" => Ok(Self::Hamming),
+            "Blackman" => Ok(Self::Blackman),
+            "Kaiser" => Ok(Self::Kaiser { beta: 0.0 }),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+impl FirWindow {
+    /// Weight of tap `n` of `taps`
+    fn weight(&self, n: usize, taps: usize) -> f64 {
+        if taps < 2 {
+            return 1.0;
+        }
+        let phase = 2.0 * PI * n as f64 / (taps - 1) as f64;
+        match *self {
+            Self::Rectangular => 1.0,
+            Self::Hann => 0.5 - 0.5 * Float::cos(phase),
+            Self::Hamming => 0.54 - 0.46 * Float::cos(phase),
+            Self::Blackman => 0.42 - 0.5 * Float::cos(phase) + 0.08 * Float::cos(2.0 * phase),
+            Self::Kaiser { beta } => {
+                let x = 2.0 * n as f64 / (taps - 1) as f64 - 1.0;
+                bessel_i0(beta * Float::sqrt(1.0 - x * x)) / bessel_i0(beta)
+            }
+        }
+    }
+}
+
+/// Modified Bessel function of the first kind and order zero, by its power series
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut k = 1.0;
+    while term > sum * 1e-16 {
+        term *= (x / (2.0 * k)) * (x / (2.0 * k));
+        sum += term;
+        k += 1.0;
+    }
+    sum
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FirSpec {
+    /// `Notch` designs a band-stop
+    pub response: FilterResponse,
+    pub window: FirWindow,
+    /// Cutoff of low-pass and high-pass filters, and the lower edge of the band of the others,
+    /// in Hz. The gain is -6 dB at the edges.
+    pub frequency_hz: f64,
+    /// Upper edge of the band of band-pass and band-stop filters, in Hz
+    pub upper_frequency_hz: f64,
+}
+
+/// Designs the taps of a windowed-sinc FIR filter for a sample rate. The gain is normalized to
+/// 1 in the middle of the passband: at DC for low-pass and band-stop filters, at the Nyquist
+/// frequency for high-pass filters and at the center of the band for band-pass filters.
+///
+/// Panics for high-pass and band-stop filters with an even number of taps, which can't pass
+/// the Nyquist frequency.
+pub fn design_fir<const N: usize>(spec: &FirSpec, sample_rate_hz: f64) -> [f64; N] {
+    let passes_nyquist = matches!(
+        spec.response,
+        FilterResponse::HighPass | FilterResponse::BandStop | FilterResponse::Notch
+    );
+    assert!(
+        N % 2 == 1 || !passes_nyquist,
+        "{:?} FIR filters need an odd number of taps",
+        spec.response
+    );
+
+    let nyquist = sample_rate_hz / 2.0;
+    let normalize = |frequency: f64| frequency.clamp(0.0, nyquist) / sample_rate_hz;
+    let low = normalize(spec.frequency_hz.min(spec.upper_frequency_hz));
+    let high = normalize(spec.frequency_hz.max(spec.upper_frequency_hz));
+    let cutoff = normalize(spec.frequency_hz);
+
+    // Ideal low-pass with a cutoff of `fc` cycles per sample, delayed to the middle tap
+    let middle = (N as f64 - 1.0) / 2.0;
+    let sinc = |n: usize, fc: f64| {
+        let t = n as f64 - middle;
+        if t == 0.0 {
+            2.0 * fc
+        } else {
+            Float::sin(2.0 * PI * fc * t) / (PI * t)
+        }
+    };
+    let delta = |n: usize| if n as f64 == middle { 1.0 } else { 0.0 };
+    let mut taps: [f64; N] = core::array::from_fn(|n| {
+        let ideal = match spec.response {
+            FilterResponse::LowPass => sinc(n, cutoff),
+            FilterResponse::HighPass => delta(n) - sinc(n, cutoff),
+            FilterResponse::BandPass => sinc(n, high) - sinc(n, low),
+            FilterResponse::BandStop | FilterResponse::Notch => {
+                delta(n) - sinc(n, high) + sinc(n, low)
+            }
+        };
+        ideal * spec.window.weight(n, N)
+    });
+
+    let reference = match spec.response {
+        FilterResponse::LowPass | FilterResponse::BandStop | FilterResponse::Notch => 0.0,
+        FilterResponse::HighPass => nyquist,
+        FilterResponse::BandPass => (low + high) / 2.0 * sample_rate_hz,
+    };
+    let gain = fir_magnitude(&taps, reference, sample_rate_hz);
+    if gain > f64::EPSILON {
+        taps.iter_mut().for_each(|tap| *tap /= gain);
+    }
+    taps
+}
+
+/// Gain of an FIR filter at a frequency
+pub fn fir_magnitude(taps: &[f64], frequency_hz: f64, sample_rate_hz: f64) -> f64 {
+    let z_inv = Complex::exp_j(-2.0 * PI * frequency_hz / sample_rate_hz);
+    taps.iter()
+        .rev()
+        .fold(Complex::ZERO, |response, tap| {
+            response * z_inv + Complex::real(*tap)
+        })
+        .abs()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    const ZERO: Self = Self::new(0.0, 0.0);
+
+    const fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    const fn real(re: f64) -> Self {
+        Self::new(re, 0.0)
+    }
+
+    fn exp_j(theta: f64) -> Self {
+        Self::new(Float::cos(theta), Float::sin(theta))
+    }
+
+    fn abs(self) -> f64 {
+        Float::hypot(self.re, self.im)
+    }
+
+    fn sqrt(self) -> Self {
+        let r = self.abs();
+        let re = Float::sqrt((r + self.re).max(0.0) / 2.0);
+        let im = Float::sqrt((r - self.re).max(0.0) / 2.0);
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    fn is_real(self) -> bool {
+        self.im.abs() <= 1e-9 * self.abs().max(1.0)
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Mul<f64> for Complex {
+    type Output = Self;
+    fn mul(self, rhs: f64) -> Self {
+        Self::new(self.re * rhs, self.im * rhs)
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+    fn div(self, rhs: Self) -> Self {
+        let denominator = rhs.re * rhs.re + rhs.im * rhs.im;
+        Self::new(
+            (self.re * rhs.re + self.im * rhs.im) / denominator,
+            (self.im * rhs.re - self.re * rhs.im) / denominator,
+        )
+    }
+}
+
+impl Neg for Complex {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self::new(-self.re, -self.im)
+    }
+}
+
+/// The zeros or poles of a filter
+#[derive(Clone, Copy)]
+struct Roots {
+    roots: [Complex; MAX_POLES],
+    len: usize,
+}
+
+impl Roots {
+    fn new() -> Self {
+        Self {
+            roots: [Complex::ZERO; MAX_POLES],
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, root: Complex) {
+        self.roots[self.len] = root;
+        self.len += 1;
+    }
+
+    fn as_slice(&self) -> &[Complex] {
+        &self.roots[..self.len]
+    }
+
+    fn map(&mut self, f: impl Fn(Complex) -> Complex) {
+        for root in &mut self.roots[..self.len] {
+            *root = f(*root);
+        }
+    }
+
+    /// Product of `offset - root` over the roots
+    fn product(&self, offset: Complex) -> Complex {
+        self.as_slice()
+            .iter()
+            .fold(Complex::real(1.0), |product, root| {
+                product * (offset - *root)
+            })
+    }
+}
+
+/// Zeros, poles and gain of an analog low-pass prototype with a cutoff of 1 rad/s
+fn prototype(design: FilterDesign, order: usize, ripple_db: f64) -> (Roots, Roots, f64) {
+    let mut zeros = Roots::new();
+    let mut poles = Roots::new();
+    let n = order as f64;
+    let angle = |k: usize| PI * (2 * k + 1) as f64 / (2.0 * n);
+
+    match design {
+        FilterDesign::Butterworth => {
+            for k in 0..order {
+                poles.push(Complex::new(-Float::sin(angle(k)), Float::cos(angle(k))));
+            }
+        }
+        FilterDesign::ChebyshevI => {
+            let epsilon = Float::sqrt(Float::powf(10.0, ripple_db / 10.0) - 1.0);
+            let mu = Float::asinh(1.0 / epsilon) / n;
+            for k in 0..order {
+                poles.push(Complex::new(
+                    -Float::sinh(mu) * Float::sin(angle(k)),
+                    Float::cosh(mu) * Float::cos(angle(k)),
+                ));
+            }
+        }
+        FilterDesign::ChebyshevII => {
+            let epsilon = 1.0 / Float::sqrt(Float::powf(10.0, ripple_db / 10.0) - 1.0);
+            let mu = Float::asinh(1.0 / epsilon) / n;
+            for k in 0..order {
+                let cos = Float::cos(angle(k));
+                // The middle pole of odd orders has its zero at infinity
+                if 2 * k + 1 != order {
+                    zeros.push(Complex::new(0.0, 1.0 / cos));
+                }
+                let pole = Complex::new(
+                    -Float::sinh(mu) * Float::sin(angle(k)),
+                    Float::cosh(mu) * cos,
+                );
+                poles.push(Complex::real(1.0) / pole);
+            }
+        }
+        FilterDesign::Bessel => bessel_poles(order, &mut poles),
+    }
+
+    let mut gain = (poles.product(Complex::ZERO) / zeros.product(Complex::ZERO)).re;
+    if design == FilterDesign::ChebyshevI && order.is_multiple_of(2) {
+        // Even orders start the passband at the bottom of the ripple
+        gain /= Float::powf(10.0, ripple_db / 20.0);
+    }
+    (zeros, poles, gain)
+}
+
+/// Poles of a Bessel filter with its -3 dB point at 1 rad/s
+fn bessel_poles(order: usize, poles: &mut Roots) {
+    // Coefficients of the reverse Bessel polynomial, lowest power first. The highest is 1.
+    let factorial = |n: usize| (1..=n).fold(1.0, |product, i| product * i as f64);
+    let mut coefficients = [0.0; MAX_POLES + 1];
+    for (k, coefficient) in coefficients.iter_mut().enumerate().take(order + 1) {
+        *coefficient = factorial(2 * order - k)
+            / (Float::powi(2.0, (order - k) as i32) * factorial(k) * factorial(order - k));
+    }
+    let polynomial = |s: Complex| {
+        coefficients[..=order]
+            .iter()
+            .rev()
+            .fold(Complex::ZERO, |value, c| value * s + Complex::real(*c))
+    };
+
+    // Durand-Kerner iteration finds every root at once
+    for k in 0..order {
+        let mut guess = Complex::real(1.0);
+        for _ in 0..=k {
+            guess = guess * Complex::new(0.4, 0.9);
+        }
+        poles.push(guess);
+    }
+    for _ in 0..500 {
+        for i in 0..order {
+            let root = poles.roots[i];
+            let denominator = (0..order)
+                .filter(|j| *j != i)
+                .fold(Complex::real(1.0), |d, j| d * (root - poles.roots[j]));
+            poles.roots[i] = root - polynomial(root) / denominator;
+        }
+    }
+
+    // The polynomial's poles have unit group delay. Scale them so the gain at 1 rad/s is
+    // -3 dB, finding that frequency by bisection since the gain falls monotonically.
+    let dc = poles.product(Complex::ZERO).abs();
+    let gain_squared = |w: f64| Float::powi(dc / poles.product(Complex::new(0.0, w)).abs(), 2);
+    let (mut low, mut high) = (1e-3, 1e3);
+    for _ in 0..100 {
+        let mid = Float::sqrt(low * high);
+        if gain_squared(mid) > 0.5 {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+    let cutoff = Float::sqrt(low * high);
+    poles.map(|pole| pole * (1.0 / cutoff));
+}
+
+fn lowpass(zeros: &mut Roots, poles: &mut Roots, gain: &mut f64, w0: f64) {
+    let degree = poles.len - zeros.len;
+    zeros.map(|zero| zero * w0);
+    poles.map(|pole| pole * w0);
+    *gain *= Float::powi(w0, degree as i32);
+}
+
+fn highpass(zeros: &mut Roots, poles: &mut Roots, gain: &mut f64, w0: f64) {
+    let degree = poles.len - zeros.len;
+    *gain *= (zeros.product(Complex::ZERO) / poles.product(Complex::ZERO)).re;
+    zeros.map(|zero| Complex::real(w0) / zero);
+    poles.map(|pole| Complex::real(w0) / pole);
+    for _ in 0..degree {
+        zeros.push(Complex::ZERO);
+    }
+}
+
+/// Splits each root `r` of a low-pass into the pair `r ± √(r² - w0²)`
+fn split_roots(roots: &mut Roots, w0: f64) {
+    let original = *roots;
+    roots.len = 0;
+    for root in original.as_slice() {
+        let offset = (*root * *root - Complex::real(w0 * w0)).sqrt();
+        roots.push(*root + offset);
+        roots.push(*root - offset);
+    }
+}
+
+fn bandpass(zeros: &mut Roots, poles: &mut Roots, gain: &mut f64, low: f64, high: f64) {
+    let degree = poles.len - zeros.len;
+    let bandwidth = high - low;
+    zeros.map(|zero| zero * (bandwidth / 2.0));
+    poles.map(|pole| pole * (bandwidth / 2.0));
+    split_roots(zeros, Float::sqrt(low * high));
+    split_roots(poles, Float::sqrt(low * high));
+    for _ in 0..degree {
+        zeros.push(Complex::ZERO);
+    }
+    *gain *= Float::powi(bandwidth, degree as i32);
+}
+
+fn bandstop(zeros: &mut Roots, poles: &mut Roots, gain: &mut f64, low: f64, high: f64) {
+    let degree = poles.len - zeros.len;
+    let half_bandwidth = Complex::real((high - low) / 2.0);
+    let w0 = Float::sqrt(low * high);
+    *gain *= (zeros.product(Complex::ZERO) / poles.product(Complex::ZERO)).re;
+    zeros.map(|zero| half_bandwidth / zero);
+    poles.map(|pole| half_bandwidth / pole);
+    split_roots(zeros, w0);
+    split_roots(poles, w0);
+    for _ in 0..degree {
+        zeros.push(Complex::new(0.0, w0));
+        zeros.push(Complex::new(0.0, -w0));
+    }
+}
+
+/// Maps analog roots to digital ones, where `fs2` is twice the sample rate
+fn bilinear(zeros: &mut Roots, poles: &mut Roots, gain: &mut f64, fs2: f64) {
+    let degree = poles.len - zeros.len;
+    let fs2 = Complex::real(fs2);
+    *gain *= (zeros.product(fs2) / poles.product(fs2)).re;
+    zeros.map(|zero| (fs2 + zero) / (fs2 - zero));
+    poles.map(|pole| (fs2 + pole) / (fs2 - pole));
+    for _ in 0..degree {
+        zeros.push(Complex::real(-1.0));
+    }
+}
+
+/// A real root, or a complex root standing for itself and its conjugate, as the polynomial
+/// `1 + c1·z⁻¹ + c2·z⁻²`
+#[derive(Clone, Copy)]
+struct RootGroup {
+    root: Complex,
+    polynomial: [f64; 2],
+}
+
+/// Groups roots into conjugate pairs and pairs of real roots, up to `MAX_POLES / 2` groups.
+/// Roots with negative imaginary parts are taken to be the conjugates of the others.
+fn group_roots(roots: &Roots) -> ([RootGroup; MAX_POLES / 2], usize) {
+    let empty = RootGroup {
+        root: Complex::ZERO,
+        polynomial: [0.0, 0.0],
+    };
+    let mut groups = [empty; MAX_POLES / 2];
+    let mut len = 0;
+    let mut unpaired_real: Option<f64> = None;
+    for root in roots.as_slice() {
+        if root.is_real() {
+            match unpaired_real.take() {
+                Some(other) => {
+                    groups[len] = RootGroup {
+                        root: *root,
+                        polynomial: [-(root.re + other), root.re * other],
+                    };
+                    len += 1;
+                }
+                None => unpaired_real = Some(root.re),
+            }
+        } else if root.im > 0.0 {
+            groups[len] = RootGroup {
+                root: *root,
+                polynomial: [-2.0 * root.re, root.re * root.re + root.im * root.im],
+            };
+            len += 1;
+        }
+    }
+    if let Some(real) = unpaired_real {
+        groups[len] = RootGroup {
+            root: Complex::real(real),
+            polynomial: [-real, 0.0],
+        };
+        len += 1;
+    }
+    (groups, len)
+}
+
+/// Pairs each group of poles with the nearest group of zeros. The poles closest to the unit
+/// circle, which have the highest gain, go last so earlier sections attenuate first.
+fn to_sections(zeros: &Roots, poles: &Roots, gain: f64, sections: &mut [Biquad]) {
+    let (mut pole_groups, pole_count) = group_roots(poles);
+    let (zero_groups, zero_count) = group_roots(zeros);
+    let pole_groups = &mut pole_groups[..pole_count];
+    pole_groups.sort_unstable_by(|a, b| a.root.abs().total_cmp(&b.root.abs()));
+
+    let mut used = [false; MAX_POLES / 2];
+    for (section, poles) in sections.iter_mut().zip(pole_groups.iter()) {
+        let nearest = (0..zero_count).filter(|i| !used[*i]).min_by(|a, b| {
+            let distance = |i: &usize| (zero_groups[*i].root - poles.root).abs();
+            distance(a).total_cmp(&distance(b))
+        });
+        let b = match nearest {
+            Some(i) => {
+                used[i] = true;
+                [
+                    1.0,
+                    zero_groups[i].polynomial[0],
+                    zero_groups[i].polynomial[1],
+                ]
+            }
+            None => [1.0, 0.0, 0.0],
+        };
+        *section = Biquad {
+            b,
+            a: poles.polynomial,
+        };
+    }
+    if let Some(first) = sections.first_mut() {
+        first.b = first.b.map(|b| b * gain);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    fn spec(response: &str, design: &str, order: usize, low: f64, high: f64) -> FilterSpec {
+        FilterSpec {
+            response: response.parse().unwrap(),
+            design: design.parse().unwrap(),
+            order,
+            frequency_hz: low,
+            upper_frequency_hz: high,
+            ripple_db: 1.0,
+        }
+    }
+
+    fn db(gain: f64) -> f64 {
+        20.0 * gain.log10()
+    }
+
+    #[test]
+    fn test_butterworth_matches_prewarped_response() {
+        let fs = 1000.0;
+        let sections = design::<2>(&spec("LowPass", "Butterworth", 4, 50.0, 0.0), fs);
+        let warped = |f: f64| (PI * f / fs).tan();
+        for f in [0.0, 10.0, 50.0, 120.0, 400.0] {
+            let expected = 1.0 / (1.0 + (warped(f) / warped(50.0)).powi(8)).sqrt();
+            assert_relative_eq!(magnitude(&sections, f, fs), expected, epsilon = 1e-9);
+        }
+
+        let sections = design::<3>(&spec("HighPass", "Butterworth", 5, 50.0, 0.0), fs);
+        for f in [1.0, 50.0, 300.0] {
+            let expected = 1.0 / (1.0 + (warped(50.0) / warped(f)).powi(10)).sqrt();
+            assert_relative_eq!(magnitude(&sections, f, fs), expected, epsilon = 1e-9);
+        }
+        assert_relative_eq!(magnitude(&sections, 0.0, fs), 0.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_chebyshev_ripple_and_attenuation() {
+        let fs = 1000.0;
+        let sections = design::<2>(&spec("LowPass", "ChebyshevI", 4, 100.0, 0.0), fs);
+        assert_relative_eq!(db(magnitude(&sections, 0.0, fs)), -1.0, epsilon = 1e-6);
+        assert_relative_eq!(db(magnitude(&sections, 100.0, fs)), -1.0, epsilon = 1e-6);
+        for f in (0..100).map(f64::from) {
+            assert!(db(magnitude(&sections, f, fs)) < 1e-9);
+            assert!(db(magnitude(&sections, f, fs)) > -1.0 - 1e-9);
+        }
+
+        let spec = FilterSpec {
+            ripple_db: 40.0,
+            ..spec("LowPass", "ChebyshevII", 5, 100.0, 0.0)
+        };
+        let sections = design::<3>(&spec, fs);
+        assert_relative_eq!(magnitude(&sections, 0.0, fs), 1.0, epsilon = 1e-9);
+        assert_relative_eq!(db(magnitude(&sections, 100.0, fs)), -40.0, epsilon = 1e-6);
+        for f in (100..500).map(f64::from) {
+            assert!(db(magnitude(&sections, f, fs)) < -40.0 + 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_bessel_cutoff() {
+        let fs = 1000.0;
+        for order in 1..=8 {
+            let sections = design::<4>(&spec("LowPass", "Bessel", order, 100.0, 0.0), fs);
+            assert_relative_eq!(magnitude(&sections, 0.0, fs), 1.0, epsilon = 1e-9);
+            assert_relative_eq!(
+                magnitude(&sections, 100.0, fs),
+                core::f64::consts::FRAC_1_SQRT_2,
+                epsilon = 1e-6
+            );
+        }
+    }
+
+    #[test]
+    fn test_band_filters_and_notch() {
+        let fs = 1000.0;
+        let bandpass = design::<4>(&spec("BandPass", "Butterworth", 4, 40.0, 60.0), fs);
+        let bandstop = design::<4>(&spec("BandStop", "Butterworth", 4, 40.0, 60.0), fs);
+        let notch = design::<1>(&spec("Notch", "Butterworth", 0, 48.0, 52.0), fs);
+        // The prewarped geometric center of the band
+        let center = fs / PI
+            * ((PI * 40.0 / fs).tan() * (PI * 60.0 / fs).tan())
+                .sqrt()
+                .atan();
+        let edge = core::f64::consts::FRAC_1_SQRT_2;
+        assert_relative_eq!(magnitude(&bandpass, center, fs), 1.0, epsilon = 1e-9);
+        assert_relative_eq!(magnitude(&bandpass, 40.0, fs), edge, epsilon = 1e-9);
+        assert_relative_eq!(magnitude(&bandpass, 60.0, fs), edge, epsilon = 1e-9);
+        assert!(magnitude(&bandpass, 10.0, fs) < 1e-4);
+        assert_relative_eq!(magnitude(&bandstop, center, fs), 0.0, epsilon = 1e-9);
+        assert_relative_eq!(magnitude(&bandstop, 0.0, fs), 1.0, epsilon = 1e-9);
+        assert_relative_eq!(magnitude(&bandstop, 60.0, fs), edge, epsilon = 1e-9);
+
+        assert_relative_eq!(magnitude(&notch, 48.0, fs), edge, epsilon = 1e-9);
+        assert_relative_eq!(magnitude(&notch, 52.0, fs), edge, epsilon = 1e-9);
+        assert!(magnitude(&notch, 20.0, fs) > 0.99);
+    }
+
+    #[test]
+    fn test_windowed_sinc_fir() {
+        let fs = 1000.0;
+        let fir = |response: &str, window: FirWindow, low: f64, high: f64| FirSpec {
+            response: response.parse().unwrap(),
+            window,
+            frequency_hz: low,
+            upper_frequency_hz: high,
+        };
+
+        let lowpass = design_fir::<101>(&fir("LowPass", FirWindow::Blackman, 100.0, 0.0), fs);
+        assert_relative_eq!(lowpass.iter().sum::<f64>(), 1.0, epsilon = 1e-12);
+        assert_relative_eq!(fir_magnitude(&lowpass, 100.0, fs), 0.5, epsilon = 1e-3);
+        assert!(db(fir_magnitude(&lowpass, 200.0, fs)) < -70.0);
+        // Symmetric taps give linear phase
+        for n in 0..50 {
+            assert_relative_eq!(lowpass[n], lowpass[100 - n], epsilon = 1e-15);
+        }
+
+        let window = FirWindow::Kaiser { beta: 8.0 };
+        let highpass = design_fir::<101>(&fir("HighPass", window, 100.0, 0.0), fs);
+        assert_relative_eq!(fir_magnitude(&highpass, 500.0, fs), 1.0, epsilon = 1e-12);
+        assert!(db(fir_magnitude(&highpass, 20.0, fs)) < -70.0);
+
+        let bandpass = design_fir::<101>(&fir("BandPass", FirWindow::Hamming, 150.0, 250.0), fs);
+        assert_relative_eq!(fir_magnitude(&bandpass, 200.0, fs), 1.0, epsilon = 1e-12);
+        assert!(db(fir_magnitude(&bandpass, 50.0, fs)) < -45.0);
+        assert!(db(fir_magnitude(&bandpass, 350.0, fs)) < -45.0);
+
+        let bandstop = design_fir::<101>(&fir("BandStop", FirWindow::Hann, 150.0, 250.0), fs);
+        assert_relative_eq!(fir_magnitude(&bandstop, 0.0, fs), 1.0, epsilon = 1e-12);
+        assert!(db(fir_magnitude(&bandstop, 200.0, fs)) < -40.0);
+        assert_relative_eq!(fir_magnitude(&bandstop, 400.0, fs), 1.0, epsilon = 1e-2);
+    }
+}
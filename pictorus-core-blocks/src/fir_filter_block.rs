@@ -0,0 +1,260 @@
+use crate::filter_design::{self, FirSpec, FirWindow};
+use corelib_traits::{Context, Matrix, Pass, PassBy, ProcessBlock};
+use heapless::Deque;
+use utils::{BlockData as OldBlockData, FromPass};
+
+/// Where the taps of the filter come from
+pub enum FirCoefficients<const N: usize> {
+    Taps([f64; N]),
+    /// Designed for the model's fundamental timestep on the first tick
+    Designed(FirSpec),
+}
+
+/// Parameters for the FIR Filter Block
+pub struct Parameters<const N: usize> {
+    pub coefficients: FirCoefficients<N>,
+    /// The output is updated every `decimation` inputs
+    pub decimation: usize,
+}
+
+impl<const N: usize> Parameters<N> {
+    /// Filters with the given taps, where the first tap weights the newest input. Missing
+    /// taps are zero.
+    pub fn new(taps: &OldBlockData, decimation: f64) -> Self {
+        let mut coefficients = [0.0; N];
+        for (coefficient, tap) in coefficients.iter_mut().zip(taps.iter()) {
+            *coefficient = *tap;
+        }
+        Self {
+            coefficients: FirCoefficients::Taps(coefficients),
+            decimation: (decimation as usize).max(1),
+        }
+    }
+
+    /// Filters with windowed-sinc taps. `kaiser_beta` is only used by the Kaiser window.
+    pub fn designed(
+        response: &str,
+        window: &str,
+        frequency_hz: f64,
+        upper_frequency_hz: f64,
+        kaiser_beta: f64,
+        decimation: f64,
+    ) -> Self {
+        let window = match window.parse().expect("Invalid FIR window") {
+            FirWindow::Kaiser { .. } => FirWindow::Kaiser { beta: kaiser_beta },
+            window => window,
+        };
+        Self {
+            coefficients: FirCoefficients::Designed(FirSpec {
+                response: response.parse().expect("Invalid filter response"),
+                window,
+                frequency_hz,
+                upper_frequency_hz,
+            }),
+            decimation: (decimation as usize).max(1),
+        }
+    }
+}
+
+/// The FIR Filter Block convolves its input with `N` taps.
+///
+/// The block keeps the last `N` inputs, filled with the first input when it starts so a
+/// constant input passes without a transient. Designed filters have symmetric taps and so
+/// linear phase: every frequency is delayed by `(N - 1) / 2` samples, which preserves the
+/// shape of pulses. With a decimation factor above 1 the output is only updated every
+/// `decimation` inputs, starting with the first, and held in between; the filter should then
+/// remove everything above the decimated Nyquist frequency.
+///
+/// This block can accept a scalar or a matrix input. For a matrix input, the filter is applied
+/// independently to each element of the matrix.
+pub struct FirFilterBlock<T: Pass, const N: usize> {
+    pub data: OldBlockData,
+    taps: Option<[f64; N]>,
+    /// Newest input at the back
+    history: Deque<T, N>,
+    /// Inputs until the output is next updated
+    countdown: usize,
+    output: T,
+}
+
+impl<T: Pass + Default, const N: usize> Default for FirFilterBlock<T, N>
+where
+    OldBlockData: FromPass<T>,
+{
+    fn default() -> Self {
+        Self {
+            data: <OldBlockData as FromPass<T>>::from_pass(T::default().as_by()),
+            taps: None,
+            history: Deque::new(),
+            countdown: 0,
+            output: T::default(),
+        }
+    }
+}
+
+impl<T: Pass + Copy, const N: usize> FirFilterBlock<T, N> {
+    fn taps(&mut self, parameters: &Parameters<N>, context: &dyn Context) -> [f64; N] {
+        *self
+            .taps
+            .get_or_insert_with(|| match &parameters.coefficients {
+                FirCoefficients::Taps(taps) => *taps,
+                FirCoefficients::Designed(spec) => {
+                    let timestep = context.fundamental_timestep().as_secs_f64();
+                    if timestep > 0.0 {
+                        filter_design::design_fir(spec, 1.0 / timestep)
+                    } else {
+                        core::array::from_fn(|n| if n == 0 { 1.0 } else { 0.0 })
+                    }
+                }
+            })
+    }
+
+    /// Adds an input to the history and returns whether the output is due
+    fn push(&mut self, input: T, decimation: usize) -> bool {
+        if self.history.is_empty() {
+            while self.history.push_back(input).is_ok() {}
+        } else {
+            self.history.pop_front();
+            // Can't fail, there's room for the input we just removed
+            self.history.push_back(input).ok();
+        }
+
+        let due = self.countdown == 0;
+        self.countdown = if due {
+            decimation - 1
+        } else {
+            self.countdown - 1
+        };
+        due
+    }
+}
+
+/// Convolves the history of each element with the taps
+fn convolve<'a, const N: usize>(
+    taps: &[f64; N],
+    history: impl DoubleEndedIterator<Item = &'a [f64]>,
+    output: &mut [f64],
+) {
+    output.iter_mut().for_each(|value| *value = 0.0);
+    for (tap, sample) in taps.iter().zip(history.rev()) {
+        for (value, element) in output.iter_mut().zip(sample) {
+            *value += tap * element;
+        }
+    }
+}
+
+impl<const N: usize> ProcessBlock for FirFilterBlock<f64, N> {
+    type Inputs = f64;
+    type Output = f64;
+    type Parameters = Parameters<N>;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let taps = self.taps(parameters, context);
+        if self.push(input, parameters.decimation) {
+            convolve(
+                &taps,
+                self.history.iter().map(core::slice::from_ref),
+                core::slice::from_mut(&mut self.output),
+            );
+            self.data = OldBlockData::from_scalar(self.output);
+        }
+        self.output
+    }
+}
+
+impl<const NROWS: usize, const NCOLS: usize, const N: usize> ProcessBlock
+    for FirFilterBlock<Matrix<NROWS, NCOLS, f64>, N>
+where
+    OldBlockData: FromPass<Matrix<NROWS, NCOLS, f64>>,
+{
+    type Inputs = Matrix<NROWS, NCOLS, f64>;
+    type Output = Matrix<NROWS, NCOLS, f64>;
+    type Parameters = Parameters<N>;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let taps = self.taps(parameters, context);
+        if self.push(*input, parameters.decimation) {
+            convolve(
+                &taps,
+                self.history.iter().map(|sample| sample.data.as_flattened()),
+                self.output.data.as_flattened_mut(),
+            );
+            self.data = OldBlockData::from_pass(&self.output);
+        }
+        &self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use core::f64::consts::TAU;
+    use core::time::Duration;
+    use corelib_traits_testing::StubContext;
+
+    fn context() -> StubContext {
+        StubContext {
+            fundamental_timestep: Duration::from_millis(1),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_fir_filter_block_taps() {
+        let parameters = Parameters::<3>::new(&OldBlockData::from_vector(&[0.5, 0.25, 0.25]), 1.0);
+        let context = context();
+        let mut block = FirFilterBlock::<f64, 3>::default();
+
+        // Starts with the history full of the first input
+        assert_relative_eq!(block.process(&parameters, &context, 4.0), 4.0);
+        assert_relative_eq!(block.process(&parameters, &context, 0.0), 2.0);
+        assert_relative_eq!(block.process(&parameters, &context, 8.0), 5.0);
+        assert_relative_eq!(block.process(&parameters, &context, 0.0), 2.0);
+        assert_relative_eq!(block.data.scalar(), 2.0);
+    }
+
+    #[test]
+    fn test_fir_filter_block_designed_matrix() {
+        let parameters = Parameters::<51>::designed("LowPass", "Hamming", 50.0, 0.0, 0.0, 1.0);
+        let context = context();
+        let mut block = FirFilterBlock::<Matrix<1, 2, f64>, 51>::default();
+        let signal = |t: f64| num_traits::Float::sin(TAU * 5.0 * t);
+        let noise = |t: f64| num_traits::Float::sin(TAU * 200.0 * t);
+
+        for i in 0..500 {
+            let t = i as f64 / 1000.0;
+            let input = Matrix {
+                data: [[signal(t) + noise(t)], [noise(t)]],
+            };
+            let output = block.process(&parameters, &context, &input);
+            if i >= 50 {
+                // Delayed by half the filter length, without distortion
+                let delayed = (i - 25) as f64 / 1000.0;
+                assert_relative_eq!(output.data[0][0], signal(delayed), epsilon = 5e-3);
+                assert_relative_eq!(output.data[1][0], 0.0, epsilon = 5e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fir_filter_block_decimation() {
+        let parameters = Parameters::<2>::new(&OldBlockData::from_vector(&[0.5, 0.5]), 3.0);
+        let context = context();
+        let mut block = FirFilterBlock::<f64, 2>::default();
+
+        let outputs: [f64; 7] =
+            core::array::from_fn(|i| block.process(&parameters, &context, i as f64));
+        assert_eq!(outputs, [0.0, 0.0, 0.0, 2.5, 2.5, 2.5, 5.5]);
+    }
+}
@@ -0,0 +1,123 @@
+extern crate alloc;
+use alloc::vec::Vec;
+use corelib_traits::{ByteSliceSignal, Context, PassBy, ProcessBlock};
+use log::debug;
+use utils::byte_data::framing::{encode_frame, FrameConfig};
+use utils::byte_data::ByteOrderSpec;
+use utils::BlockData as OldBlockData;
+
+/// Parameters for the Frame Encode Block
+pub struct Parameters {
+    /// How payloads are framed, see [`utils::byte_data::framing`]
+    pub config: FrameConfig,
+}
+
+impl Parameters {
+    pub fn new(framing: &str, checksum: &str, byte_order: &str, max_payload_bytes: f64) -> Self {
+        Self {
+            config: FrameConfig {
+                framing: framing.parse().expect("Invalid framing"),
+                checksum: checksum.parse().expect("Invalid frame checksum"),
+                byte_order: byte_order
+                    .parse::<ByteOrderSpec>()
+                    .expect("Invalid byte order"),
+                max_payload_bytes: max_payload_bytes as usize,
+            },
+        }
+    }
+}
+
+/// The Frame Encode Block wraps its input bytes in a COBS, SLIP or length-prefixed frame,
+/// optionally protected by a CRC. The output is intended to be connected to a
+/// `SerialTransmitBlock` (with no delimiters) or a `UdpTransmitBlock`. If the input is empty,
+/// or the payload is larger than the configured maximum, nothing is output for that tick.
+pub struct FrameEncodeBlock {
+    pub data: OldBlockData,
+    buffer: Vec<u8>,
+}
+
+impl Default for FrameEncodeBlock {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::from_bytes(&[]),
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl ProcessBlock for FrameEncodeBlock {
+    type Parameters = Parameters;
+    type Inputs = ByteSliceSignal;
+    type Output = ByteSliceSignal;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        self.buffer.clear();
+        // An empty input is nothing to send, rather than a frame with an empty payload
+        if !input.is_empty() {
+            if let Err(err) = encode_frame(input, &parameters.config, &mut self.buffer) {
+                debug!("Failed to encode frame: {:?}", err);
+            }
+        }
+        self.data.set_bytes(&self.buffer);
+        &self.buffer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corelib_traits_testing::StubContext;
+
+    #[test]
+    fn test_cobs_frame() {
+        let context = StubContext::default();
+        let parameters = Parameters::new("Cobs", "None", "LittleEndian", 64.0);
+        let mut block = FrameEncodeBlock::default();
+
+        let output = block.process(&parameters, &context, b"\x11\x22\x00\x33");
+        assert_eq!(output, b"\x03\x11\x22\x02\x33\x00");
+        assert_eq!(
+            block.data,
+            OldBlockData::from_bytes(b"\x03\x11\x22\x02\x33\x00")
+        );
+    }
+
+    #[test]
+    fn test_length_prefixed_frame_with_crc() {
+        let context = StubContext::default();
+        let parameters = Parameters::new("LengthPrefixed", "Crc16", "BigEndian", 64.0);
+        let mut block = FrameEncodeBlock::default();
+
+        let output = block.process(&parameters, &context, b"123456789");
+        assert_eq!(&output[..2], b"\x00\x09");
+        assert_eq!(&output[2..11], b"123456789");
+        assert_eq!(output.len(), 13);
+    }
+
+    #[test]
+    fn test_oversized_payload_outputs_nothing() {
+        let context = StubContext::default();
+        let parameters = Parameters::new("Slip", "Crc32", "LittleEndian", 2.0);
+        let mut block = FrameEncodeBlock::default();
+
+        let output = block.process(&parameters, &context, b"too long");
+        assert!(output.is_empty());
+    }
+
+    #[test]
+    fn test_empty_input_outputs_nothing() {
+        let context = StubContext::default();
+        let parameters = Parameters::new("Cobs", "Crc16", "LittleEndian", 64.0);
+        let mut block = FrameEncodeBlock::default();
+
+        block.process(&parameters, &context, b"\x01");
+        let output = block.process(&parameters, &context, b"");
+        assert!(output.is_empty());
+        assert_eq!(block.data, OldBlockData::from_bytes(b""));
+    }
+}
@@ -0,0 +1,122 @@
+use crate::kalman_filter_block::matrix_from;
+use crate::rotation::{Rotation, Vector};
+use crate::traits::Float;
+use corelib_traits::{Context, Matrix, PassBy, ProcessBlock};
+use nalgebra::{Quaternion, UnitQuaternion, Vector3};
+use utils::{BlockData as OldBlockData, FromPass};
+
+/// Parameters for the Frame Transform Block
+pub struct Parameters {
+    /// Orientation of the source frame in the destination frame
+    pub rotation: UnitQuaternion<f64>,
+}
+
+impl Parameters {
+    /// The orientation of the source frame in the destination frame as ZYX Euler angles in
+    /// radians, for example how a sensor is mounted on the body
+    pub fn new(roll: f64, pitch: f64, yaw: f64) -> Self {
+        Self {
+            rotation: UnitQuaternion::from_euler_angles(roll, pitch, yaw),
+        }
+    }
+
+    /// The orientation as a 3x3 rotation matrix, whose columns are the source frame axes in
+    /// the destination frame. A matrix that isn't quite orthonormal, such as a calibrated
+    /// mounting matrix, is replaced with the nearest rotation.
+    pub fn from_matrix(rotation: &OldBlockData) -> Self {
+        Self {
+            rotation: Matrix::<3, 3, f64>::to_unit_quaternion(&matrix_from(rotation)),
+        }
+    }
+}
+
+/// Expresses a vector measured in one frame in another, using the fixed orientation of the
+/// first frame in the second. This is typically used to bring IMU or magnetometer readings
+/// from the sensor frame into the body frame.
+///
+/// The input is a 3D vector, as a row or a column.
+pub struct FrameTransformBlock<V: Vector> {
+    pub data: OldBlockData,
+    output: V,
+}
+
+impl<V: Vector> Default for FrameTransformBlock<V>
+where
+    OldBlockData: FromPass<V>,
+{
+    fn default() -> Self {
+        let output = V::from_vector3(&Vector3::zeros());
+        Self {
+            data: <OldBlockData as FromPass<V>>::from_pass(output.as_by()),
+            output,
+        }
+    }
+}
+
+fn cast<F: Float>(rotation: &UnitQuaternion<f64>) -> UnitQuaternion<F> {
+    let q = rotation.quaternion();
+    let convert = |value: f64| F::from(value).expect("Failed to convert parameter to Float");
+    UnitQuaternion::new_unchecked(Quaternion::new(
+        convert(q.w),
+        convert(q.i),
+        convert(q.j),
+        convert(q.k),
+    ))
+}
+
+impl<V: Vector> ProcessBlock for FrameTransformBlock<V>
+where
+    OldBlockData: FromPass<V>,
+{
+    type Inputs = V;
+    type Output = V;
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let rotation = cast::<V::Float>(&parameters.rotation);
+        self.output = V::from_vector3(&rotation.transform_vector(&V::to_vector3(input)));
+        self.data = OldBlockData::from_pass(self.output.as_by());
+        self.output.as_by()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use core::f64::consts::PI;
+    use corelib_traits_testing::StubContext;
+
+    #[test]
+    fn test_frame_transform_block() {
+        let context = StubContext::default();
+        // An IMU mounted upside down, with its x axis pointing backwards
+        let parameters = Parameters::new(0.0, PI, 0.0);
+        let mut block = FrameTransformBlock::<Matrix<1, 3, f64>>::default();
+        let accel = Matrix {
+            data: [[0.5], [0.2], [-9.81]],
+        };
+        let output = block.process(&parameters, &context, &accel);
+        assert_relative_eq!(
+            output.data.as_flattened(),
+            [-0.5, 0.2, 9.81].as_slice(),
+            epsilon = 1e-12
+        );
+
+        // The same mounting as a slightly skewed matrix, in f32
+        let matrix =
+            OldBlockData::from_row_slice(3, 3, &[-1.0, 0.0, 0.01, 0.0, 1.0, 0.0, -0.01, 0.0, -1.0]);
+        let parameters = Parameters::from_matrix(&matrix);
+        let mut block = FrameTransformBlock::<Matrix<3, 1, f32>>::default();
+        let accel = Matrix {
+            data: [[0.5, 0.2, -9.81]],
+        };
+        let output = block.process(&parameters, &context, &accel);
+        assert_relative_eq!(output.data[0][..], [-0.6, 0.2, 9.8][..], epsilon = 0.01);
+    }
+}
@@ -0,0 +1,90 @@
+use corelib_traits::{Context, PassBy, ProcessBlock};
+use utils::BlockData as OldBlockData;
+
+use crate::CaptureEdge;
+
+/// Parameters for the GPIO Edge Event Block
+pub struct Parameters {
+    /// Which edges are reported
+    pub edge: CaptureEdge,
+}
+
+impl Parameters {
+    pub fn new(edge: &str) -> Self {
+        Self {
+            edge: edge.parse().expect("Invalid capture edge"),
+        }
+    }
+}
+
+/// The GPIO Edge Event Block reports the edges of a GPIO input streamed by the platform,
+/// rather than sampling its level each tick.
+///
+/// The inputs are the level of the line, the rising and falling edges since the previous tick
+/// and the app times of the latest rising and falling edges, which are only used along with
+/// edges. The outputs are the level, the number of selected edges this tick and the app time
+/// of the latest selected edge, which holds until the next one.
+pub struct GpioEdgeEventBlock {
+    pub data: OldBlockData,
+    last_edge_time: f64,
+}
+
+impl Default for GpioEdgeEventBlock {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::from_scalar(0.0),
+            last_edge_time: 0.0,
+        }
+    }
+}
+
+impl ProcessBlock for GpioEdgeEventBlock {
+    type Parameters = Parameters;
+    type Inputs = (bool, f64, f64, f64, f64);
+    type Output = (bool, f64, f64);
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let (level, rising, falling, last_rising_time, last_falling_time) = inputs;
+        if rising > 0.0 && parameters.edge != CaptureEdge::Falling {
+            self.last_edge_time = last_rising_time;
+        }
+        if falling > 0.0 && parameters.edge != CaptureEdge::Rising {
+            self.last_edge_time = self.last_edge_time.max(last_falling_time);
+        }
+        let edges = match parameters.edge {
+            CaptureEdge::Rising => rising,
+            CaptureEdge::Falling => falling,
+            CaptureEdge::Both => rising + falling,
+        };
+        self.data = OldBlockData::from_scalar(edges);
+        (level, edges, self.last_edge_time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corelib_traits_testing::StubContext;
+
+    #[test]
+    fn test_gpio_edge_event_block() {
+        let context = StubContext::default();
+        let mut block = GpioEdgeEventBlock::default();
+
+        let both = Parameters::new("Both");
+        let output = block.process(&both, &context, (false, 2.0, 3.0, 0.25, 0.5));
+        assert_eq!(output, (false, 5.0, 0.5));
+        assert_eq!(block.data.scalar(), 5.0);
+
+        let rising = Parameters::new("Rising");
+        let output = block.process(&rising, &context, (false, 0.0, 1.0, 0.0, 0.75));
+        assert_eq!(output, (false, 0.0, 0.5));
+        let output = block.process(&rising, &context, (true, 1.0, 1.0, 1.0, 0.9));
+        assert_eq!(output, (true, 1.0, 1.0));
+    }
+}
@@ -0,0 +1,105 @@
+use crate::traits::Float;
+use corelib_traits::{Context, PassBy, ProcessBlock};
+use utils::BlockData as OldBlockData;
+
+/// Parameters for the Hysteresis Block
+pub struct Parameters {
+    /// The output turns off when the input falls below this
+    pub low_threshold: f64,
+    /// The output turns on when the input rises above this
+    pub high_threshold: f64,
+}
+
+impl Parameters {
+    pub fn new(low_threshold: f64, high_threshold: f64) -> Self {
+        Self {
+            low_threshold,
+            high_threshold,
+        }
+    }
+}
+
+/// The Hysteresis Block is a Schmitt trigger: a comparator whose output turns on when the input
+/// rises above the high threshold and only turns off again when it falls below the low
+/// threshold. Noise smaller than the gap between the thresholds can't make it chatter.
+///
+/// The output starts off, and holds while the input is between the thresholds or NaN.
+pub struct HysteresisBlock<F: Float> {
+    pub data: OldBlockData,
+    output: bool,
+    _unused: core::marker::PhantomData<F>,
+}
+
+impl<F: Float> Default for HysteresisBlock<F> {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::scalar_from_bool(false),
+            output: false,
+            _unused: core::marker::PhantomData,
+        }
+    }
+}
+
+/// Schmitt trigger state, shared with the blocks that detect events with hysteresis
+pub(crate) fn hysteresis<F: Float>(on: bool, input: F, low: F, high: F) -> bool {
+    if num_traits::Float::is_nan(input) {
+        on
+    } else if on {
+        input >= low
+    } else {
+        input > high
+    }
+}
+
+impl<F: Float> ProcessBlock for HysteresisBlock<F> {
+    type Inputs = F;
+    type Output = bool;
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        self.output = hysteresis(
+            self.output,
+            input,
+            F::from(parameters.low_threshold).unwrap(),
+            F::from(parameters.high_threshold).unwrap(),
+        );
+        self.data.set_scalar_bool(self.output);
+        self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corelib_traits_testing::StubContext;
+
+    #[test]
+    fn test_hysteresis_block() {
+        let parameters = Parameters::new(-0.5, 0.5);
+        let context = StubContext::default();
+        let mut block = HysteresisBlock::<f64>::default();
+
+        let samples = [
+            (0.0, false),
+            (0.4, false),
+            (0.6, true),
+            // Noise around zero doesn't turn it off
+            (-0.4, true),
+            (0.1, true),
+            (f64::NAN, true),
+            (-0.6, false),
+            (0.4, false),
+            (0.5, false),
+            (0.51, true),
+        ];
+        for (input, expected) in samples {
+            assert_eq!(block.process(&parameters, &context, input), expected);
+        }
+        assert_eq!(block.data.scalar(), 1.0);
+    }
+}
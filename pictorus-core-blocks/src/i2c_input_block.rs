@@ -2,7 +2,7 @@ extern crate alloc;
 use alloc::vec::Vec;
 use corelib_traits::{ByteSliceSignal, Context, PassBy, ProcessBlock};
 use utils::BlockData as OldBlockData;
-use utils::{IsValid, StaleTracker};
+use utils::{I2cScript, IsValid, StaleTracker};
 
 /// Parameters for I2C Input Block
 pub struct Parameters {
@@ -14,6 +14,8 @@ pub struct Parameters {
     pub read_bytes: usize,
     /// Stale age in milliseconds
     stale_age_ms: f64,
+    /// The write of `command` and read of `read_bytes` as a transaction script
+    script: I2cScript,
 }
 
 impl Parameters {
@@ -27,11 +29,20 @@ impl Parameters {
             command: command_u8,
             read_bytes: read_bytes_u8,
             stale_age_ms,
+            script: I2cScript::write_read(&[command_u8], read_bytes_u8),
         }
     }
+
+    /// The write of `command` followed by a repeated-start read of `read_bytes`
+    pub fn script(&self) -> &I2cScript {
+        &self.script
+    }
 }
 
 /// I2C Input Block buffers data read from an I2C peripheral.
+///
+/// See [`crate::I2cTransactionBlock`] for devices that need more than a single write-read,
+/// or for the result of each transaction.
 pub struct I2cInputBlock {
     pub data: OldBlockData,
     pub stale_check: StaleTracker,
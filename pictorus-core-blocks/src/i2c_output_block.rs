@@ -2,23 +2,31 @@ extern crate alloc;
 use alloc::vec::Vec;
 use corelib_traits::{ByteSliceSignal, Context, PassBy, ProcessBlock};
 use utils::BlockData as OldBlockData;
+use utils::{I2cAddress, I2cScript};
 
 /// Parameters for I2C Output Block
 pub struct Parameters {
-    /// 8-bit address to write to
-    pub address: u8,
-    /// 8-bit command to send, typically a register address
-    pub command: u8,
+    /// Address of the I2C device
+    pub address: I2cAddress,
+    /// Transactions run against the device each tick, with `IN` writing the block input
+    pub script: I2cScript,
 }
 
 impl Parameters {
+    /// Writes the 8-bit `command`, typically a register address, followed by the input
     pub fn new(address: f64, command: f64) -> Self {
-        let addr_u8 = address as u8;
-        let command_u8 = command as u8;
+        Self {
+            address: I2cAddress::SevenBit(address as u8),
+            script: I2cScript::write_input(&[command as u8]),
+        }
+    }
 
+    /// Runs a transaction script, see [`utils::i2c_script`] for the syntax.
+    /// Addresses above 0x7F are always 10-bit, and above 0x3FF are invalid.
+    pub fn from_script(address: f64, ten_bit_address: bool, script: &str) -> Self {
         Self {
-            address: addr_u8,
-            command: command_u8,
+            address: I2cAddress::new(address as u16, ten_bit_address).expect("Invalid I2C address"),
+            script: script.parse().expect("Invalid I2C transaction script"),
         }
     }
 }
@@ -74,4 +82,18 @@ mod tests {
         assert_eq!(output_signal, input_data);
         assert_eq!(block.data.to_bytes(), input_data);
     }
+
+    #[test]
+    fn test_i2c_output_block_script() {
+        let params = Parameters::from_script(0x50 as f64, true, "W16 0x0100 IN; D 5");
+        assert_eq!(params.address, I2cAddress::TenBit(0x50));
+        assert_eq!(params.script.transaction_count(), 1);
+        assert_eq!(params.script.read_len(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid I2C address")]
+    fn test_i2c_output_block_address_too_large() {
+        Parameters::from_script(0x400 as f64, true, "IN");
+    }
 }
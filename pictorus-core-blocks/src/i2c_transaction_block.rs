@@ -0,0 +1,162 @@
+extern crate alloc;
+use alloc::vec::Vec;
+use corelib_traits::{ByteSliceSignal, Context, PassBy, ProcessBlock};
+use utils::BlockData as OldBlockData;
+use utils::{I2cAddress, I2cScript, IsValid, StaleTracker};
+
+/// Parameters for I2C Transaction Block
+pub struct Parameters {
+    /// Address of the I2C device
+    pub address: I2cAddress,
+    /// Transactions run against the device each tick
+    pub script: I2cScript,
+    /// Number of bytes read by the script
+    pub read_bytes: usize,
+    /// Stale age in milliseconds
+    stale_age_ms: f64,
+}
+
+impl Parameters {
+    /// Runs a transaction script, see [`utils::i2c_script`] for the syntax.
+    /// Addresses above 0x7F are always 10-bit, and above 0x3FF are invalid.
+    pub fn new(address: f64, ten_bit_address: bool, script: &str, stale_age_ms: f64) -> Self {
+        let script: I2cScript = script.parse().expect("Invalid I2C transaction script");
+        Self {
+            address: I2cAddress::new(address as u16, ten_bit_address).expect("Invalid I2C address"),
+            read_bytes: script.read_len(),
+            script,
+            stale_age_ms,
+        }
+    }
+}
+
+/// I2C Transaction Block buffers data read from an I2C peripheral by a transaction script,
+/// such as configuration writes followed by a burst read.
+///
+/// The inputs are the bytes read by the transaction script and one result code per
+/// transaction (see [`utils::i2c_script`]), which are passed through as the outputs. The read
+/// data only updates when all reads completed and every transaction succeeded.
+pub struct I2cTransactionBlock {
+    pub data: OldBlockData,
+    pub stale_check: StaleTracker,
+    buffer: Vec<u8>,
+    results: Vec<u8>,
+    previous_stale_check_time_ms: f64,
+}
+
+impl Default for I2cTransactionBlock {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::from_bytes(b""),
+            stale_check: StaleTracker::from_ms(0.0),
+            buffer: Vec::new(),
+            results: Vec::new(),
+            previous_stale_check_time_ms: 0.,
+        }
+    }
+}
+
+impl IsValid for I2cTransactionBlock {
+    fn is_valid(&self, app_time_s: f64) -> OldBlockData {
+        self.stale_check.is_valid(app_time_s)
+    }
+}
+
+impl ProcessBlock for I2cTransactionBlock {
+    type Parameters = Parameters;
+    type Inputs = (ByteSliceSignal, ByteSliceSignal);
+    type Output = (ByteSliceSignal, ByteSliceSignal);
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        if self.previous_stale_check_time_ms != parameters.stale_age_ms {
+            self.stale_check = StaleTracker::from_ms(parameters.stale_age_ms);
+            self.previous_stale_check_time_ms = parameters.stale_age_ms;
+        }
+
+        let (read_data, results) = inputs;
+        self.results.clear();
+        self.results.extend_from_slice(results);
+
+        // Make sure the data is the correct size and no transaction failed, if so, update the
+        // stale check, otherwise something has gone wrong.
+        let succeeded = results.len() == parameters.script.transaction_count()
+            && results.iter().all(|r| *r == utils::i2c_script::I2C_OK);
+        if succeeded && read_data.len() == parameters.read_bytes {
+            self.buffer.clear();
+            self.stale_check.mark_updated(context.time().as_secs_f64());
+            self.buffer.extend_from_slice(read_data);
+            self.data.set_bytes(&self.buffer);
+        }
+
+        (&self.buffer, &self.results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+    use corelib_traits_testing::StubRuntime;
+    use utils::i2c_script::{I2C_ERROR_NO_ACKNOWLEDGE_ADDRESS, I2C_OK};
+    use utils::IsValid;
+
+    use super::*;
+
+    #[test]
+    fn test_i2c_transaction_block() {
+        let parameters = Parameters::new(0., false, "W 0 R 10", 100.0);
+        let mut runtime = StubRuntime::default();
+        let mut block = I2cTransactionBlock::default();
+
+        let input_data: &[u8] = &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+
+        let output = block.process(&parameters, &runtime.context(), (input_data, &[I2C_OK]));
+        assert_eq!(output, (input_data, &[I2C_OK][..]));
+        assert_eq!(block.data.to_bytes(), input_data);
+        let valid = block
+            .is_valid(runtime.context().time().as_secs_f64())
+            .clone();
+        assert_eq!(valid.scalar(), 1.0);
+
+        runtime.set_time(Duration::from_secs(1));
+
+        // When the I2cWrapper has an error, the buffer is clear and the parameters.read_bytes is not
+        // equal to the length of the empty buffer, however the previous value is buffered
+        let output = block.process(
+            &parameters,
+            &runtime.context(),
+            (&[], &[I2C_ERROR_NO_ACKNOWLEDGE_ADDRESS]),
+        );
+        assert_eq!(
+            output,
+            (input_data, &[I2C_ERROR_NO_ACKNOWLEDGE_ADDRESS][..])
+        );
+        let valid = block.is_valid(runtime.context().time().as_secs_f64());
+        assert_eq!(valid.scalar(), 0.0);
+    }
+
+    #[test]
+    fn test_i2c_transaction_block_script() {
+        let parameters = Parameters::new(0x2A0 as f64, false, "W 1; D 5; W16 0x10 R 2", 100.0);
+        assert_eq!(parameters.address, I2cAddress::TenBit(0x2A0));
+        assert_eq!(parameters.read_bytes, 2);
+
+        let runtime = StubRuntime::default();
+        let mut block = I2cTransactionBlock::default();
+
+        // A failed configuration write invalidates the data even if the read succeeded
+        let output = block.process(&parameters, &runtime.context(), (&[1, 2], &[3, 0]));
+        assert_eq!(output, (&[][..], &[3, 0][..]));
+        let valid = block.is_valid(runtime.context().time().as_secs_f64());
+        assert_eq!(valid.scalar(), 0.0);
+
+        let output = block.process(&parameters, &runtime.context(), (&[1, 2], &[0, 0]));
+        assert_eq!(output, (&[1, 2][..], &[0, 0][..]));
+        let valid = block.is_valid(runtime.context().time().as_secs_f64());
+        assert_eq!(valid.scalar(), 1.0);
+    }
+}
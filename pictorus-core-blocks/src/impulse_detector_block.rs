@@ -0,0 +1,129 @@
+use crate::traits::Float;
+use corelib_traits::{Context, PassBy, ProcessBlock};
+use utils::BlockData as OldBlockData;
+
+/// Parameters for the Impulse Detector Block
+pub struct Parameters {
+    /// Magnitude above which the input is part of an impulse
+    pub threshold: f64,
+    /// Longest an impulse can last; anything longer is a sustained load rather than an impulse
+    pub max_duration_s: f64,
+}
+
+impl Parameters {
+    pub fn new(threshold: f64, max_duration_s: f64) -> Self {
+        Self {
+            threshold,
+            max_duration_s,
+        }
+    }
+}
+
+/// Whether an impulse ended this tick, and the peak magnitude, duration in seconds and app time
+/// of the peak in seconds of the latest impulse
+type Impulse<F> = (bool, F, F, F);
+
+/// An impulse in progress
+#[derive(Debug, Clone, Copy)]
+struct Event<F> {
+    peak: F,
+    peak_time: F,
+    duration: F,
+}
+
+/// The Impulse Detector Block detects short, sharp events in its input, such as the jolt of an
+/// impact in an acceleration magnitude.
+///
+/// An event starts when the magnitude of the input rises above `threshold` and ends when it
+/// falls back below. Each tick of the event adds its timestep to its duration. An event that
+/// lasts no longer than `max_duration_s` is an impulse: it is reported on the tick it ends,
+/// along with its peak magnitude, its duration and the app time of the peak. These outputs hold
+/// until the next impulse. Longer events aren't reported. NaN inputs are ignored.
+pub struct ImpulseDetectorBlock<F: Float> {
+    pub data: OldBlockData,
+    event: Option<Event<F>>,
+    output: Impulse<F>,
+}
+
+impl<F: Float> Default for ImpulseDetectorBlock<F> {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::scalar_from_bool(false),
+            event: None,
+            output: (false, F::zero(), F::zero(), F::zero()),
+        }
+    }
+}
+
+impl<F: Float> ProcessBlock for ImpulseDetectorBlock<F> {
+    type Inputs = F;
+    type Output = Impulse<F>;
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        self.output.0 = false;
+        if !num_traits::Float::is_nan(input) {
+            let magnitude = num_traits::Float::abs(input);
+            let now = F::from_duration(context.time());
+            let dt = F::from_duration(context.timestep().unwrap_or(context.fundamental_timestep()));
+            if magnitude > F::from(parameters.threshold).unwrap() {
+                let event = self.event.get_or_insert(Event {
+                    peak: magnitude,
+                    peak_time: now,
+                    duration: F::zero(),
+                });
+                if magnitude > event.peak {
+                    event.peak = magnitude;
+                    event.peak_time = now;
+                }
+                event.duration += dt;
+            } else if let Some(event) = self.event.take() {
+                if event.duration <= F::from(parameters.max_duration_s).unwrap() {
+                    self.output = (true, event.peak, event.duration, event.peak_time);
+                }
+            }
+        }
+        self.data.set_scalar_bool(self.output.0);
+        self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use corelib_traits_testing::StubRuntime;
+
+    #[test]
+    fn test_impulse_detector_block() {
+        let parameters = Parameters::new(2.0, 0.35);
+        let mut runtime = StubRuntime::default();
+        let mut block = ImpulseDetectorBlock::<f64>::default();
+
+        // A short negative jolt, then a sustained load
+        let signal = [
+            1.0, -3.0, -8.0, -4.0, 1.0, 0.0, 3.0, 3.0, 3.0, 3.0, 1.0, 0.0,
+        ];
+        let mut impulses = std::vec::Vec::new();
+        for input in signal {
+            runtime.tick();
+            let (detected, peak, duration, time) =
+                block.process(&parameters, &runtime.context(), input);
+            if detected {
+                impulses.push((peak, duration, time));
+            }
+        }
+
+        assert_eq!(impulses.len(), 1);
+        let (peak, duration, time) = impulses[0];
+        assert_relative_eq!(peak, 8.0);
+        assert_relative_eq!(duration, 0.3, epsilon = 1e-12);
+        assert_relative_eq!(time, 0.3, epsilon = 1e-12);
+        assert_eq!(block.data.scalar(), 0.0);
+    }
+}
@@ -0,0 +1,220 @@
+use core::str::FromStr;
+use corelib_traits::{Context, Matrix, PassBy, ProcessBlock};
+use utils::{BlockData as OldBlockData, FromPass, IsValid, ParseEnumError, StaleTracker};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImuSensor {
+    Mpu6050,
+    Icm20948,
+    Bmi088,
+    Lsm6dsx,
+}
+
+impl FromStr for ImuSensor {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "MPU6050" => Ok(Self::Mpu6050),
+            "ICM20948" => Ok(Self::Icm20948),
+            "BMI088" => Ok(Self::Bmi088),
+            "LSM6DSX" => Ok(Self::Lsm6dsx),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+/// Parameters for the IMU Block
+pub struct Parameters {
+    pub sensor: ImuSensor,
+    /// I2C address of the sensor, or of the accelerometer for the BMI088
+    pub address: u8,
+    /// I2C address of the BMI088 gyro, unused by the other sensors
+    pub gyro_address: u8,
+    /// Requested accelerometer full scale in g. The sensor uses the smallest range covering it.
+    pub accel_range_g: f64,
+    /// Requested gyro full scale in degrees per second
+    pub gyro_range_dps: f64,
+    pub sample_rate_hz: f64,
+    /// Average all samples queued in the sensor's FIFO since the last tick, rather than
+    /// reading the latest sample
+    pub use_fifo: bool,
+    /// Run the sensor's self-test after configuring it. A failed self-test keeps the block
+    /// stale.
+    pub run_self_test: bool,
+    /// Accelerometer offset in m/s², subtracted before scaling
+    pub accel_bias: [f64; 3],
+    pub accel_scale: [f64; 3],
+    /// Gyro offset in rad/s
+    pub gyro_bias: [f64; 3],
+    stale_age_ms: f64,
+}
+
+impl Parameters {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        sensor: &str,
+        address: f64,
+        gyro_address: f64,
+        accel_range_g: f64,
+        gyro_range_dps: f64,
+        sample_rate_hz: f64,
+        use_fifo: bool,
+        run_self_test: bool,
+        accel_bias: [f64; 3],
+        accel_scale: [f64; 3],
+        gyro_bias: [f64; 3],
+        stale_age_ms: f64,
+    ) -> Self {
+        Self {
+            sensor: sensor.parse().expect("Invalid IMU sensor"),
+            address: address as u8,
+            gyro_address: gyro_address as u8,
+            accel_range_g,
+            gyro_range_dps,
+            sample_rate_hz,
+            use_fifo,
+            run_self_test,
+            accel_bias,
+            accel_scale,
+            gyro_bias,
+            stale_age_ms,
+        }
+    }
+}
+
+/// The IMU Block calibrates samples from an IMU driver.
+///
+/// The inputs are the acceleration in m/s², the angular rate in rad/s, the die temperature in
+/// °C and the number of samples the driver averaged to produce them, which is zero when no new
+/// sample was read. The outputs are the calibrated acceleration, the angular rate with the
+/// gyro bias removed, and the temperature. They hold their last values, and the block goes
+/// stale, while no new samples arrive.
+pub struct ImuBlock {
+    pub data: OldBlockData,
+    pub stale_check: StaleTracker,
+    accel: Matrix<1, 3, f64>,
+    gyro: Matrix<1, 3, f64>,
+    temperature: f64,
+    previous_stale_check_time_ms: f64,
+}
+
+impl Default for ImuBlock {
+    fn default() -> Self {
+        Self {
+            data: <OldBlockData as FromPass<Matrix<1, 3, f64>>>::from_pass(&Matrix::zeroed()),
+            stale_check: StaleTracker::from_ms(0.0),
+            accel: Matrix::zeroed(),
+            gyro: Matrix::zeroed(),
+            temperature: 0.0,
+            previous_stale_check_time_ms: 0.0,
+        }
+    }
+}
+
+impl IsValid for ImuBlock {
+    fn is_valid(&self, app_time_s: f64) -> OldBlockData {
+        self.stale_check.is_valid(app_time_s)
+    }
+}
+
+impl ProcessBlock for ImuBlock {
+    type Parameters = Parameters;
+    type Inputs = (Matrix<1, 3, f64>, Matrix<1, 3, f64>, f64, f64);
+    type Output = (Matrix<1, 3, f64>, Matrix<1, 3, f64>, f64);
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        if self.previous_stale_check_time_ms != parameters.stale_age_ms {
+            self.stale_check = StaleTracker::from_ms(parameters.stale_age_ms);
+            self.previous_stale_check_time_ms = parameters.stale_age_ms;
+        }
+
+        let (accel, gyro, temperature, samples) = inputs;
+        if samples > 0.0 {
+            for i in 0..3 {
+                self.accel.data[i][0] =
+                    (accel.data[i][0] - parameters.accel_bias[i]) * parameters.accel_scale[i];
+                self.gyro.data[i][0] = gyro.data[i][0] - parameters.gyro_bias[i];
+            }
+            self.temperature = temperature;
+            self.stale_check.mark_updated(context.time().as_secs_f64());
+            self.data = <OldBlockData as FromPass<Matrix<1, 3, f64>>>::from_pass(&self.accel);
+        }
+
+        (&self.accel, &self.gyro, self.temperature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::time::Duration;
+    use corelib_traits_testing::StubRuntime;
+
+    fn vector(values: [f64; 3]) -> Matrix<1, 3, f64> {
+        Matrix {
+            data: values.map(|v| [v]),
+        }
+    }
+
+    #[test]
+    fn test_imu_block_calibration() {
+        let parameters = Parameters::new(
+            "BMI088",
+            0x18 as f64,
+            0x68 as f64,
+            6.0,
+            500.0,
+            200.0,
+            false,
+            false,
+            [0.25, -0.25, 0.5],
+            [1.0, 2.0, 0.5],
+            [0.5, 0.25, 0.0],
+            100.0,
+        );
+        assert_eq!(parameters.sensor, ImuSensor::Bmi088);
+        let mut runtime = StubRuntime::default();
+        let mut block = ImuBlock::default();
+
+        let output = block.process(
+            &parameters,
+            &runtime.context(),
+            (
+                &vector([1.25, 0.75, 9.5]),
+                &vector([0.5, 0.0, -1.0]),
+                31.5,
+                1.0,
+            ),
+        );
+        assert_eq!(output.0, &vector([1.0, 2.0, 4.5]));
+        assert_eq!(output.1, &vector([0.0, -0.25, -1.0]));
+        assert_eq!(output.2, 31.5);
+        assert_eq!(block.data.get_data().as_slice(), [1.0, 2.0, 4.5]);
+        let valid = block.is_valid(runtime.context().time().as_secs_f64());
+        assert_eq!(valid.scalar(), 1.0);
+
+        // Without a new sample the outputs hold and go stale
+        runtime.set_time(Duration::from_secs(1));
+        let output = block.process(
+            &parameters,
+            &runtime.context(),
+            (&Matrix::zeroed(), &Matrix::zeroed(), 0.0, 0.0),
+        );
+        assert_eq!(output.0, &vector([1.0, 2.0, 4.5]));
+        assert_eq!(output.2, 31.5);
+        let valid = block.is_valid(runtime.context().time().as_secs_f64());
+        assert_eq!(valid.scalar(), 0.0);
+    }
+
+    #[test]
+    fn test_imu_sensor_from_str() {
+        assert_eq!("MPU6050".parse::<ImuSensor>().unwrap(), ImuSensor::Mpu6050);
+        assert_eq!("LSM6DSX".parse::<ImuSensor>().unwrap(), ImuSensor::Lsm6dsx);
+        assert!("LSM6".parse::<ImuSensor>().is_err());
+    }
+}
@@ -5,37 +5,54 @@ use alloc::vec;
 use alloc::vec::Vec;
 
 use corelib_traits::{ByteSliceSignal, Context, Matrix, Pass, PassBy, ProcessBlock};
-use miniserde::json::{self, Array, Number, Object, Value};
+use miniserde::json::{self, Array, Number, Value};
 use utils::{BlockData as OldBlockData, BlockDataType, FromPass, IsValid, StaleTracker};
 
-use crate::traits::DefaultStorage;
+use crate::traits::{DefaultStorage, Scalar};
 
 /// JSON Load Block attempts to deserialize bytes encoded as JSON into
 /// the specified output signals. If select_data is provided in the parameters,
-/// we assume that the passed in bytes represent an object where each key of the
-/// select_data is a key in the object. If select_data is not provided, we assume
-/// that the passed in bytes represent a single value (either scalar or matrix).
-pub struct JsonLoadBlock<T: Apply> {
+/// each selector is a path into the passed in document (see [`Selector`]) and
+/// every selected field is loaded independently of the others. If select_data is
+/// not provided, we assume that the passed in bytes represent a single value
+/// (either scalar or matrix).
+///
+/// The trailing validity output reports whether the fields were loaded on the
+/// latest tick. By default it is a boolean that is true only if every selected
+/// field was loaded. With `V` set to `Matrix<N, 1, bool>`, where `N` is the
+/// number of selected fields, it is a vector with the validity of each field
+/// instead, so one missing field doesn't invalidate the others. See [`Validity`].
+pub struct JsonLoadBlock<T: Apply, V: Validity = bool> {
     pub data: Vec<OldBlockData>,
     buffer: T::Storage,
+    validity: V,
+    field_valid: [bool; MAX_FIELDS],
     stale_tracker: Option<StaleTracker>,
+    field_stale_trackers: Vec<StaleTracker>,
 }
 
-impl<T: Apply> Default for JsonLoadBlock<T> {
+/// The largest number of fields a single JSON Load Block can select
+const MAX_FIELDS: usize = 7;
+
+impl<T: Apply, V: Validity> Default for JsonLoadBlock<T, V> {
     fn default() -> Self {
+        V::check_field_count(T::FIELD_COUNT);
         let buffer = T::default_storage();
         let data = T::build_block_data(&buffer);
         JsonLoadBlock {
             data,
             buffer,
+            validity: V::default(),
+            field_valid: [false; MAX_FIELDS],
             stale_tracker: None,
+            field_stale_trackers: Vec::new(),
         }
     }
 }
 
-impl<T: Apply> ProcessBlock for JsonLoadBlock<T> {
+impl<T: Apply, V: Validity> ProcessBlock for JsonLoadBlock<T, V> {
     type Inputs = ByteSliceSignal;
-    type Output = T::Output;
+    type Output = T::Output<V>;
     type Parameters = Parameters;
 
     fn process<'b>(
@@ -44,19 +61,46 @@ impl<T: Apply> ProcessBlock for JsonLoadBlock<T> {
         context: &dyn Context,
         inputs: PassBy<'_, Self::Inputs>,
     ) -> PassBy<'b, Self::Output> {
-        let success = T::apply(&mut self.buffer, inputs, parameters);
+        let field_valid = &mut self.field_valid[..T::FIELD_COUNT];
+        let success = T::apply(&mut self.buffer, inputs, parameters, field_valid);
+        self.validity = V::from_field_valid(field_valid);
+        self.data = T::build_block_data(&self.buffer);
+
+        let app_time_s = context.time().as_secs_f64();
+        if self.field_stale_trackers.is_empty() {
+            self.field_stale_trackers = (0..T::FIELD_COUNT)
+                .map(|_| StaleTracker::from_ms(parameters.stale_age_ms))
+                .collect();
+        }
+        self.field_stale_trackers
+            .iter_mut()
+            .zip(field_valid.iter())
+            .filter(|(_, valid)| **valid)
+            .for_each(|(tracker, _)| tracker.mark_updated(app_time_s));
+
         if success.is_ok() {
-            self.data = T::build_block_data(&self.buffer);
             let tracker = self
                 .stale_tracker
                 .get_or_insert(StaleTracker::from_ms(parameters.stale_age_ms));
-            tracker.mark_updated(context.time().as_secs_f64());
+            tracker.mark_updated(app_time_s);
+        }
+        T::storage_as_by(&self.buffer, &self.validity)
+    }
+}
+
+impl<T: Apply, V: Validity> JsonLoadBlock<T, V> {
+    /// Returns whether the selected field at `index` has been loaded within the
+    /// stale age. Unlike [`IsValid::is_valid`], this is not affected by failures
+    /// to load any of the other selected fields.
+    pub fn field_is_valid(&self, index: usize, app_time_s: f64) -> OldBlockData {
+        match self.field_stale_trackers.get(index) {
+            Some(tracker) => tracker.is_valid(app_time_s),
+            None => OldBlockData::scalar_from_bool(false),
         }
-        T::storage_as_by(&self.buffer)
     }
 }
 
-impl<T: Apply> IsValid for JsonLoadBlock<T> {
+impl<T: Apply, V: Validity> IsValid for JsonLoadBlock<T, V> {
     fn is_valid(&self, app_time_s: f64) -> OldBlockData {
         match self.stale_tracker {
             Some(ref tracker) => tracker.is_valid(app_time_s),
@@ -67,11 +111,8 @@ impl<T: Apply> IsValid for JsonLoadBlock<T> {
 
 /// Parameters for the JSON Load Block
 pub struct Parameters {
-    /// The select data is a list of tuples where the first element is the
-    /// type of the output and the second element is the key in the JSON object
-    /// Note: The data type is not actually used in code, but encoded into the
-    /// generics of the block instance.
-    pub select_data: Vec<(BlockDataType, String)>,
+    /// The fields to select from the JSON document, in output order
+    pub select_data: Vec<Selector>,
     /// The age in milliseconds after which the data is considered stale
     pub stale_age_ms: f64,
 }
@@ -81,38 +122,189 @@ impl Parameters {
     // `<S: AsRef<str>>` to allow for both &str and String. It's tricky to do that here because
     // we allow empty select_data, which would require us to specify a generic type.
     pub fn new(select_data: &[String], stale_age_ms: f64) -> Self {
-        let select_data = Self::parse_select_spec(select_data);
+        let select_data = select_data.iter().map(|d| Selector::parse(d)).collect();
         Self {
             select_data,
             stale_age_ms,
         }
     }
+}
+
+/// A single step in a [`Selector`] path
+#[derive(Clone, Debug, PartialEq)]
+pub enum PathSegment {
+    /// An object key. When applied to an array, the key is parsed as an index.
+    Key(String),
+    /// An array index
+    Index(usize),
+}
+
+/// Describes where a single output field lives in the JSON document.
+///
+/// Selectors are specified as `<DataType>:<path>[=<default>]`, where the path is either:
+/// - a JSON pointer (RFC 6901) starting with `/`, e.g. `/imu/accel/2`
+/// - a dotted path with optional array indexing, e.g. `imu.accel[2]`
+///
+/// The optional default is a JSON literal (e.g. `0.0`, `true`, `"idle"`) that is used
+/// when the path does not exist in the document or resolves to `null`. Paths may contain `=`
+/// themselves; the default starts after the last `=` that is followed by a valid literal.
+#[derive(Clone, Debug)]
+pub struct Selector {
+    /// The data type of the output
+    /// Note: The data type is not actually used in code, but encoded into the
+    /// generics of the block instance.
+    pub data_type: BlockDataType,
+    /// The path as originally specified
+    pub key: String,
+    /// The parsed path
+    pub path: Vec<PathSegment>,
+    /// Value used if the path can not be found in the document
+    pub default: Option<Value>,
+}
+
+impl Selector {
+    fn parse(spec: &str) -> Self {
+        let (dt, field) = spec.split_once(':').expect("Invalid select data format");
+        let data_type = dt.parse::<BlockDataType>().unwrap();
+        let (key, default) = field
+            .rmatch_indices('=')
+            .find_map(|(i, _)| {
+                let default = json::from_str::<Value>(field[i + 1..].trim()).ok()?;
+                Some((&field[..i], Some(default)))
+            })
+            .unwrap_or((field, None));
+
+        Self {
+            data_type,
+            key: key.into(),
+            path: Self::parse_path(key),
+            default,
+        }
+    }
+
+    fn parse_path(key: &str) -> Vec<PathSegment> {
+        if let Some(pointer) = key.strip_prefix('/') {
+            return pointer
+                .split('/')
+                .map(|token| PathSegment::Key(token.replace("~1", "/").replace("~0", "~")))
+                .collect();
+        }
+
+        let mut path = Vec::new();
+        for part in key.split('.') {
+            let (name, mut indices) = match part.find('[') {
+                Some(i) => part.split_at(i),
+                None => (part, ""),
+            };
+            if !name.is_empty() {
+                path.push(PathSegment::Key(name.into()));
+            }
+            while let Some(rest) = indices.strip_prefix('[') {
+                let (index, rest) = rest.split_once(']').expect("Invalid select data path");
+                let index = index.trim().parse().expect("Invalid select data index");
+                path.push(PathSegment::Index(index));
+                indices = rest;
+            }
+            assert!(indices.is_empty(), "Invalid select data path");
+        }
+        path
+    }
+
+    /// Finds the value this selector points to in the document. Keys that contain path
+    /// separators are still matched verbatim at the top level of the document.
+    pub fn resolve<'a>(&self, root: &'a Value) -> Option<&'a Value> {
+        if let Value::Object(obj) = root {
+            if let Some(value) = obj.get(&self.key) {
+                return Some(value);
+            }
+        }
 
-    fn parse_select_spec(data: &[String]) -> Vec<(BlockDataType, String)> {
-        data.iter()
-            .map(|d| d.split_once(':').expect("Invalid select data format"))
-            .map(|(dt, field)| (dt.parse::<BlockDataType>().unwrap(), field.into()))
-            .collect()
+        self.path
+            .iter()
+            .try_fold(root, |value, segment| match (value, segment) {
+                (Value::Object(obj), PathSegment::Key(key)) => obj.get(key),
+                (Value::Array(arr), PathSegment::Index(index)) => arr.get(*index),
+                (Value::Array(arr), PathSegment::Key(key)) => {
+                    key.parse::<usize>().ok().and_then(|index| arr.get(index))
+                }
+                _ => None,
+            })
     }
 }
 
 pub trait Deserialize: DefaultStorage {
     fn from_json_value(data: &Value) -> Result<Self::Storage, ()>;
-    fn from_json_object(data: &Object, key: &str) -> Result<Self::Storage, ()> {
-        let value = data.get(key).ok_or(())?;
-        Self::from_json_value(value)
+    fn from_json_selector(data: &Value, selector: &Selector) -> Result<Self::Storage, ()> {
+        match selector.resolve(data) {
+            Some(Value::Null) | None => {
+                let default = selector.default.as_ref().ok_or(())?;
+                Self::from_json_value(default)
+            }
+            Some(value) => Self::from_json_value(value),
+        }
     }
 }
 
-impl Deserialize for f64 {
-    fn from_json_value(data: &Value) -> Result<Self::Storage, ()> {
+/// Scalar types that can be extracted from a single JSON value
+pub trait JsonScalar: Scalar {
+    fn from_json_scalar(data: &Value) -> Option<Self>;
+}
+
+impl JsonScalar for f64 {
+    fn from_json_scalar(data: &Value) -> Option<Self> {
         match data {
-            Value::Number(n) => Ok(parse_number(n)),
-            _ => Err(()),
+            Value::Number(n) => Some(parse_number(n)),
+            _ => None,
+        }
+    }
+}
+
+impl JsonScalar for f32 {
+    fn from_json_scalar(data: &Value) -> Option<Self> {
+        match data {
+            Value::Number(n) => Some(parse_number(n) as f32),
+            _ => None,
+        }
+    }
+}
+
+impl JsonScalar for bool {
+    fn from_json_scalar(data: &Value) -> Option<Self> {
+        match data {
+            Value::Bool(b) => Some(*b),
+            _ => None,
         }
     }
 }
 
+// Integers are only extracted if the JSON number is integral and fits in the target type
+macro_rules! impl_json_integer {
+    ($($t:ty),*) => {
+        $(
+            impl JsonScalar for $t {
+                fn from_json_scalar(data: &Value) -> Option<Self> {
+                    match data {
+                        Value::Number(Number::I64(v)) => <$t>::try_from(*v).ok(),
+                        Value::Number(Number::U64(v)) => <$t>::try_from(*v).ok(),
+                        Value::Number(Number::F64(v)) if (*v as i64) as f64 == *v => {
+                            <$t>::try_from(*v as i64).ok()
+                        }
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_json_integer!(u8, i8, u16, i16, u32, i32);
+
+impl<S: JsonScalar> Deserialize for S {
+    fn from_json_value(data: &Value) -> Result<Self::Storage, ()> {
+        S::from_json_scalar(data).ok_or(())
+    }
+}
+
 impl Deserialize for ByteSliceSignal {
     fn from_json_value(data: &Value) -> Result<Self::Storage, ()> {
         match data {
@@ -122,9 +314,9 @@ impl Deserialize for ByteSliceSignal {
     }
 }
 
-fn parse_num_array<const NROWS: usize, const NCOLS: usize>(
+fn parse_num_array<const NROWS: usize, const NCOLS: usize, S: JsonScalar>(
     data: &Array,
-    res: &mut Matrix<NROWS, NCOLS, f64>,
+    res: &mut Matrix<NROWS, NCOLS, S>,
 ) -> Result<(), ()> {
     // If ROWS * COLS is equal to the total number of elements in the matrix
     // then we can fill the matrix column-wise
@@ -132,20 +324,18 @@ fn parse_num_array<const NROWS: usize, const NCOLS: usize>(
         return Err(());
     }
 
-    data.iter().enumerate().for_each(|(i, v)| {
-        if let Value::Number(n) = v {
-            let col = i % NCOLS;
-            let row = i / NCOLS;
-            res.data[col][row] = parse_number(n);
-        }
-    });
+    for (i, v) in data.iter().enumerate() {
+        let col = i % NCOLS;
+        let row = i / NCOLS;
+        res.data[col][row] = S::from_json_scalar(v).ok_or(())?;
+    }
 
     Ok(())
 }
 
-fn parse_nested_array<const NROWS: usize, const NCOLS: usize>(
+fn parse_nested_array<const NROWS: usize, const NCOLS: usize, S: JsonScalar>(
     data: &Array,
-    res: &mut Matrix<NROWS, NCOLS, f64>,
+    res: &mut Matrix<NROWS, NCOLS, S>,
 ) -> Result<(), ()> {
     let rows = data.len();
     if rows != NROWS {
@@ -161,19 +351,16 @@ fn parse_nested_array<const NROWS: usize, const NCOLS: usize>(
             return Err(());
         }
         for j in 0..NCOLS {
-            match &row[j] {
-                Value::Number(n) => {
-                    res.data[j][i] = parse_number(n);
-                }
-                _ => return Err(()),
-            }
+            res.data[j][i] = S::from_json_scalar(&row[j]).ok_or(())?;
         }
     }
 
     Ok(())
 }
 
-impl<const NROWS: usize, const NCOLS: usize> Deserialize for Matrix<NROWS, NCOLS, f64> {
+impl<const NROWS: usize, const NCOLS: usize, S: JsonScalar> Deserialize
+    for Matrix<NROWS, NCOLS, S>
+{
     fn from_json_value(data: &Value) -> Result<Self::Storage, ()> {
         let mut res = Self::Storage::default();
         let val = match data {
@@ -187,9 +374,8 @@ impl<const NROWS: usize, const NCOLS: usize> Deserialize for Matrix<NROWS, NCOLS
         }
 
         match &val[0] {
-            Value::Number(_) => parse_num_array(val, &mut res),
             Value::Array(_) => parse_nested_array(val, &mut res),
-            _ => Err(()),
+            _ => parse_num_array(val, &mut res),
         }?;
         Ok(res)
     }
@@ -197,18 +383,63 @@ impl<const NROWS: usize, const NCOLS: usize> Deserialize for Matrix<NROWS, NCOLS
 
 pub trait Apply: Pass {
     type Storage;
-    type Output: Pass;
+    /// The loaded fields followed by the validity output
+    type Output<V: Validity>: Pass;
+
+    /// The number of selected fields
+    const FIELD_COUNT: usize;
+
+    /// Loads each field into `dest`, recording whether each was loaded in `field_valid`.
+    /// Fields that fail to load keep their previous value. Returns `Ok` only if every
+    /// field was loaded.
     fn apply(
         dest: &mut Self::Storage,
         data: PassBy<ByteSliceSignal>,
         parameters: &Parameters,
+        field_valid: &mut [bool],
     ) -> Result<(), ()>;
 
     fn default_storage() -> Self::Storage;
-    fn storage_as_by(storage: &Self::Storage) -> PassBy<'_, Self::Output>;
+    fn storage_as_by<'a, V: Validity>(
+        storage: &'a Self::Storage,
+        validity: &'a V,
+    ) -> PassBy<'a, Self::Output<V>>;
     fn build_block_data(storage: &Self::Storage) -> Vec<OldBlockData>;
 }
 
+/// The trailing validity output of a JSON Load Block
+pub trait Validity: Pass + Default {
+    fn from_field_valid(field_valid: &[bool]) -> Self;
+
+    /// Panics if this output can't report the validity of `field_count` fields
+    fn check_field_count(_field_count: usize) {}
+}
+
+/// True only if every selected field was loaded
+impl Validity for bool {
+    fn from_field_valid(field_valid: &[bool]) -> Self {
+        field_valid.iter().all(|v| *v)
+    }
+}
+
+/// Whether each selected field was loaded, in output order
+impl<const N: usize> Validity for Matrix<N, 1, bool> {
+    fn from_field_valid(field_valid: &[bool]) -> Self {
+        let mut validity = Self::zeroed();
+        for (dest, valid) in validity.data[0].iter_mut().zip(field_valid) {
+            *dest = *valid;
+        }
+        validity
+    }
+
+    fn check_field_count(field_count: usize) {
+        assert_eq!(
+            N, field_count,
+            "Invalid validity output: {N} rows for {field_count} selected fields"
+        );
+    }
+}
+
 fn parse_number(num_val: &Number) -> f64 {
     match num_val {
         Number::F64(v) => *v,
@@ -217,49 +448,76 @@ fn parse_number(num_val: &Number) -> f64 {
     }
 }
 
+fn parse_document(data: PassBy<ByteSliceSignal>) -> Result<Value, ()> {
+    let data = core::str::from_utf8(data).or(Err(()))?;
+    json::from_str(data).or(Err(()))
+}
+
+fn load_field<A: Deserialize>(data: &Value, selector: &Selector, dest: &mut A::Storage) -> bool {
+    match A::from_json_selector(data, selector) {
+        Ok(v) => {
+            *dest = v;
+            true
+        }
+        Err(()) => false,
+    }
+}
+
+fn all_valid(field_valid: &[bool]) -> Result<(), ()> {
+    if field_valid.iter().all(|v| *v) {
+        Ok(())
+    } else {
+        Err(())
+    }
+}
+
 // Impl for single value
 impl<A: Deserialize> Apply for A
 where
     OldBlockData: FromPass<A>,
 {
-    type Storage = (A::Storage, bool);
-    type Output = (A, bool);
+    type Storage = A::Storage;
+    type Output<V: Validity> = (A, V);
+    const FIELD_COUNT: usize = 1;
 
     fn apply(
         dest: &mut Self::Storage,
         data: PassBy<ByteSliceSignal>,
         parameters: &Parameters,
+        field_valid: &mut [bool],
     ) -> Result<(), ()> {
-        let data = core::str::from_utf8(data).or(Err(()))?;
-        // Special case for a single value where no selectors are provided
-        // In this case we will attempt to parse the entire data as a single value
-        let v1 = if parameters.select_data.is_empty() {
-            let data: Value = json::from_str(data).or(Err(()))?;
-            A::from_json_value(&data)
-        } else {
-            let data: Object = json::from_str(data).or(Err(()))?;
-            A::from_json_object(&data, &parameters.select_data[0].1)
-        };
-
-        if let Ok(v1) = v1 {
-            *dest = (v1, true);
-            Ok(())
-        } else {
-            dest.1 = false;
-            Err(())
+        field_valid.fill(false);
+        if let Ok(data) = parse_document(data) {
+            // Special case for a single value where no selectors are provided
+            // In this case we will attempt to parse the entire data as a single value
+            field_valid[0] = match parameters.select_data.first() {
+                Some(selector) => load_field::<A>(&data, selector, dest),
+                None => match A::from_json_value(&data) {
+                    Ok(v) => {
+                        *dest = v;
+                        true
+                    }
+                    Err(()) => false,
+                },
+            };
         }
+
+        all_valid(field_valid)
     }
 
     fn default_storage() -> Self::Storage {
-        (A::default_storage(), false)
+        A::default_storage()
     }
 
     fn build_block_data(storage: &Self::Storage) -> Vec<OldBlockData> {
-        vec![OldBlockData::from_pass(A::from_storage(&storage.0))]
+        vec![OldBlockData::from_pass(A::from_storage(storage))]
     }
 
-    fn storage_as_by(storage: &Self::Storage) -> PassBy<'_, Self::Output> {
-        (A::from_storage(&storage.0), storage.1)
+    fn storage_as_by<'a, V: Validity>(
+        storage: &'a Self::Storage,
+        validity: &'a V,
+    ) -> PassBy<'a, Self::Output<V>> {
+        (A::from_storage(storage), validity.as_by())
     }
 }
 
@@ -269,29 +527,27 @@ where
     OldBlockData: FromPass<A>,
     OldBlockData: FromPass<B>,
 {
-    type Storage = (A::Storage, B::Storage, bool);
-    type Output = (A, B, bool);
+    type Storage = (A::Storage, B::Storage);
+    type Output<V: Validity> = (A, B, V);
+    const FIELD_COUNT: usize = 2;
 
     fn apply(
         dest: &mut Self::Storage,
         data: PassBy<ByteSliceSignal>,
         parameters: &Parameters,
+        field_valid: &mut [bool],
     ) -> Result<(), ()> {
-        let data = core::str::from_utf8(data).or(Err(()))?;
-        let data: Object = json::from_str(data).or(Err(()))?;
-        let v1 = A::from_json_object(&data, &parameters.select_data[0].1);
-        let v2 = B::from_json_object(&data, &parameters.select_data[1].1);
-        if let (Ok(v1), Ok(v2)) = (v1, v2) {
-            *dest = (v1, v2, true);
-            Ok(())
-        } else {
-            dest.2 = false;
-            Err(())
+        field_valid.fill(false);
+        if let Ok(data) = parse_document(data) {
+            field_valid[0] = load_field::<A>(&data, &parameters.select_data[0], &mut dest.0);
+            field_valid[1] = load_field::<B>(&data, &parameters.select_data[1], &mut dest.1);
         }
+
+        all_valid(field_valid)
     }
 
     fn default_storage() -> Self::Storage {
-        (A::default_storage(), B::default_storage(), false)
+        (A::default_storage(), B::default_storage())
     }
 
     fn build_block_data(storage: &Self::Storage) -> Vec<OldBlockData> {
@@ -301,11 +557,14 @@ where
         ]
     }
 
-    fn storage_as_by(storage: &Self::Storage) -> PassBy<'_, Self::Output> {
+    fn storage_as_by<'a, V: Validity>(
+        storage: &'a Self::Storage,
+        validity: &'a V,
+    ) -> PassBy<'a, Self::Output<V>> {
         (
             A::from_storage(&storage.0),
             B::from_storage(&storage.1),
-            storage.2,
+            validity.as_by(),
         )
     }
 }
@@ -317,26 +576,24 @@ where
     OldBlockData: FromPass<B>,
     OldBlockData: FromPass<C>,
 {
-    type Storage = (A::Storage, B::Storage, C::Storage, bool);
-    type Output = (A, B, C, bool);
+    type Storage = (A::Storage, B::Storage, C::Storage);
+    type Output<V: Validity> = (A, B, C, V);
+    const FIELD_COUNT: usize = 3;
 
     fn apply(
         dest: &mut Self::Storage,
         data: PassBy<ByteSliceSignal>,
         parameters: &Parameters,
+        field_valid: &mut [bool],
     ) -> Result<(), ()> {
-        let data = core::str::from_utf8(data).or(Err(()))?;
-        let data: Object = json::from_str(data).or(Err(()))?;
-        let v1 = A::from_json_object(&data, &parameters.select_data[0].1);
-        let v2 = B::from_json_object(&data, &parameters.select_data[1].1);
-        let v3 = C::from_json_object(&data, &parameters.select_data[2].1);
-        if let (Ok(v1), Ok(v2), Ok(v3)) = (v1, v2, v3) {
-            *dest = (v1, v2, v3, true);
-            Ok(())
-        } else {
-            dest.3 = false;
-            Err(())
+        field_valid.fill(false);
+        if let Ok(data) = parse_document(data) {
+            field_valid[0] = load_field::<A>(&data, &parameters.select_data[0], &mut dest.0);
+            field_valid[1] = load_field::<B>(&data, &parameters.select_data[1], &mut dest.1);
+            field_valid[2] = load_field::<C>(&data, &parameters.select_data[2], &mut dest.2);
         }
+
+        all_valid(field_valid)
     }
 
     fn default_storage() -> Self::Storage {
@@ -344,7 +601,6 @@ where
             A::default_storage(),
             B::default_storage(),
             C::default_storage(),
-            false,
         )
     }
 
@@ -356,12 +612,15 @@ where
         ]
     }
 
-    fn storage_as_by(storage: &Self::Storage) -> PassBy<'_, Self::Output> {
+    fn storage_as_by<'a, V: Validity>(
+        storage: &'a Self::Storage,
+        validity: &'a V,
+    ) -> PassBy<'a, Self::Output<V>> {
         (
             A::from_storage(&storage.0),
             B::from_storage(&storage.1),
             C::from_storage(&storage.2),
-            storage.3,
+            validity.as_by(),
         )
     }
 }
@@ -374,27 +633,25 @@ where
     OldBlockData: FromPass<C>,
     OldBlockData: FromPass<D>,
 {
-    type Storage = (A::Storage, B::Storage, C::Storage, D::Storage, bool);
-    type Output = (A, B, C, D, bool);
+    type Storage = (A::Storage, B::Storage, C::Storage, D::Storage);
+    type Output<V: Validity> = (A, B, C, D, V);
+    const FIELD_COUNT: usize = 4;
 
     fn apply(
         dest: &mut Self::Storage,
         data: PassBy<ByteSliceSignal>,
         parameters: &Parameters,
+        field_valid: &mut [bool],
     ) -> Result<(), ()> {
-        let data = core::str::from_utf8(data).or(Err(()))?;
-        let data: Object = json::from_str(data).or(Err(()))?;
-        let v1 = A::from_json_object(&data, &parameters.select_data[0].1);
-        let v2 = B::from_json_object(&data, &parameters.select_data[1].1);
-        let v3 = C::from_json_object(&data, &parameters.select_data[2].1);
-        let v4 = D::from_json_object(&data, &parameters.select_data[3].1);
-        if let (Ok(v1), Ok(v2), Ok(v3), Ok(v4)) = (v1, v2, v3, v4) {
-            *dest = (v1, v2, v3, v4, true);
-            Ok(())
-        } else {
-            dest.4 = false;
-            Err(())
+        field_valid.fill(false);
+        if let Ok(data) = parse_document(data) {
+            field_valid[0] = load_field::<A>(&data, &parameters.select_data[0], &mut dest.0);
+            field_valid[1] = load_field::<B>(&data, &parameters.select_data[1], &mut dest.1);
+            field_valid[2] = load_field::<C>(&data, &parameters.select_data[2], &mut dest.2);
+            field_valid[3] = load_field::<D>(&data, &parameters.select_data[3], &mut dest.3);
         }
+
+        all_valid(field_valid)
     }
 
     fn default_storage() -> Self::Storage {
@@ -403,7 +660,6 @@ where
             B::default_storage(),
             C::default_storage(),
             D::default_storage(),
-            false,
         )
     }
 
@@ -416,13 +672,16 @@ where
         ]
     }
 
-    fn storage_as_by(storage: &Self::Storage) -> PassBy<'_, Self::Output> {
+    fn storage_as_by<'a, V: Validity>(
+        storage: &'a Self::Storage,
+        validity: &'a V,
+    ) -> PassBy<'a, Self::Output<V>> {
         (
             A::from_storage(&storage.0),
             B::from_storage(&storage.1),
             C::from_storage(&storage.2),
             D::from_storage(&storage.3),
-            storage.4,
+            validity.as_by(),
         )
     }
 }
@@ -437,35 +696,26 @@ where
     OldBlockData: FromPass<D>,
     OldBlockData: FromPass<E>,
 {
-    type Storage = (
-        A::Storage,
-        B::Storage,
-        C::Storage,
-        D::Storage,
-        E::Storage,
-        bool,
-    );
-    type Output = (A, B, C, D, E, bool);
+    type Storage = (A::Storage, B::Storage, C::Storage, D::Storage, E::Storage);
+    type Output<V: Validity> = (A, B, C, D, E, V);
+    const FIELD_COUNT: usize = 5;
 
     fn apply(
         dest: &mut Self::Storage,
         data: PassBy<ByteSliceSignal>,
         parameters: &Parameters,
+        field_valid: &mut [bool],
     ) -> Result<(), ()> {
-        let data = core::str::from_utf8(data).or(Err(()))?;
-        let data: Object = json::from_str(data).or(Err(()))?;
-        let v1 = A::from_json_object(&data, &parameters.select_data[0].1);
-        let v2 = B::from_json_object(&data, &parameters.select_data[1].1);
-        let v3 = C::from_json_object(&data, &parameters.select_data[2].1);
-        let v4 = D::from_json_object(&data, &parameters.select_data[3].1);
-        let v5 = E::from_json_object(&data, &parameters.select_data[4].1);
-        if let (Ok(v1), Ok(v2), Ok(v3), Ok(v4), Ok(v5)) = (v1, v2, v3, v4, v5) {
-            *dest = (v1, v2, v3, v4, v5, true);
-            Ok(())
-        } else {
-            dest.5 = false;
-            Err(())
+        field_valid.fill(false);
+        if let Ok(data) = parse_document(data) {
+            field_valid[0] = load_field::<A>(&data, &parameters.select_data[0], &mut dest.0);
+            field_valid[1] = load_field::<B>(&data, &parameters.select_data[1], &mut dest.1);
+            field_valid[2] = load_field::<C>(&data, &parameters.select_data[2], &mut dest.2);
+            field_valid[3] = load_field::<D>(&data, &parameters.select_data[3], &mut dest.3);
+            field_valid[4] = load_field::<E>(&data, &parameters.select_data[4], &mut dest.4);
         }
+
+        all_valid(field_valid)
     }
 
     fn default_storage() -> Self::Storage {
@@ -475,7 +725,6 @@ where
             C::default_storage(),
             D::default_storage(),
             E::default_storage(),
-            false,
         )
     }
 
@@ -488,14 +737,18 @@ where
             <OldBlockData as FromPass<E>>::from_pass(E::from_storage(&storage.4)),
         ]
     }
-    fn storage_as_by(storage: &Self::Storage) -> PassBy<'_, Self::Output> {
+
+    fn storage_as_by<'a, V: Validity>(
+        storage: &'a Self::Storage,
+        validity: &'a V,
+    ) -> PassBy<'a, Self::Output<V>> {
         (
             A::from_storage(&storage.0),
             B::from_storage(&storage.1),
             C::from_storage(&storage.2),
             D::from_storage(&storage.3),
             E::from_storage(&storage.4),
-            storage.5,
+            validity.as_by(),
         )
     }
 }
@@ -524,31 +777,29 @@ where
         D::Storage,
         E::Storage,
         F::Storage,
-        bool,
     );
-    type Output = (A, B, C, D, E, F, bool);
+    type Output<V: Validity> = (A, B, C, D, E, F, V);
+    const FIELD_COUNT: usize = 6;
 
     fn apply(
         dest: &mut Self::Storage,
         data: PassBy<ByteSliceSignal>,
         parameters: &Parameters,
+        field_valid: &mut [bool],
     ) -> Result<(), ()> {
-        let data = core::str::from_utf8(data).or(Err(()))?;
-        let data: Object = json::from_str(data).or(Err(()))?;
-        let v1 = A::from_json_object(&data, &parameters.select_data[0].1);
-        let v2 = B::from_json_object(&data, &parameters.select_data[1].1);
-        let v3 = C::from_json_object(&data, &parameters.select_data[2].1);
-        let v4 = D::from_json_object(&data, &parameters.select_data[3].1);
-        let v5 = E::from_json_object(&data, &parameters.select_data[4].1);
-        let v6 = F::from_json_object(&data, &parameters.select_data[5].1);
-        if let (Ok(v1), Ok(v2), Ok(v3), Ok(v4), Ok(v5), Ok(v6)) = (v1, v2, v3, v4, v5, v6) {
-            *dest = (v1, v2, v3, v4, v5, v6, true);
-            Ok(())
-        } else {
-            dest.6 = false;
-            Err(())
+        field_valid.fill(false);
+        if let Ok(data) = parse_document(data) {
+            field_valid[0] = load_field::<A>(&data, &parameters.select_data[0], &mut dest.0);
+            field_valid[1] = load_field::<B>(&data, &parameters.select_data[1], &mut dest.1);
+            field_valid[2] = load_field::<C>(&data, &parameters.select_data[2], &mut dest.2);
+            field_valid[3] = load_field::<D>(&data, &parameters.select_data[3], &mut dest.3);
+            field_valid[4] = load_field::<E>(&data, &parameters.select_data[4], &mut dest.4);
+            field_valid[5] = load_field::<F>(&data, &parameters.select_data[5], &mut dest.5);
         }
+
+        all_valid(field_valid)
     }
+
     fn default_storage() -> Self::Storage {
         (
             A::default_storage(),
@@ -557,9 +808,9 @@ where
             D::default_storage(),
             E::default_storage(),
             F::default_storage(),
-            false,
         )
     }
+
     fn build_block_data(storage: &Self::Storage) -> Vec<OldBlockData> {
         vec![
             <OldBlockData as FromPass<A>>::from_pass(A::from_storage(&storage.0)),
@@ -570,7 +821,11 @@ where
             <OldBlockData as FromPass<F>>::from_pass(F::from_storage(&storage.5)),
         ]
     }
-    fn storage_as_by(storage: &Self::Storage) -> PassBy<'_, Self::Output> {
+
+    fn storage_as_by<'a, V: Validity>(
+        storage: &'a Self::Storage,
+        validity: &'a V,
+    ) -> PassBy<'a, Self::Output<V>> {
         (
             A::from_storage(&storage.0),
             B::from_storage(&storage.1),
@@ -578,7 +833,7 @@ where
             D::from_storage(&storage.3),
             E::from_storage(&storage.4),
             F::from_storage(&storage.5),
-            storage.6,
+            validity.as_by(),
         )
     }
 }
@@ -610,33 +865,28 @@ where
         E::Storage,
         F::Storage,
         G::Storage,
-        bool,
     );
-    type Output = (A, B, C, D, E, F, G, bool);
+    type Output<V: Validity> = (A, B, C, D, E, F, G, V);
+    const FIELD_COUNT: usize = 7;
 
     fn apply(
         dest: &mut Self::Storage,
         data: PassBy<ByteSliceSignal>,
         parameters: &Parameters,
+        field_valid: &mut [bool],
     ) -> Result<(), ()> {
-        let data = core::str::from_utf8(data).or(Err(()))?;
-        let data: Object = json::from_str(data).or(Err(()))?;
-        let v1 = A::from_json_object(&data, &parameters.select_data[0].1);
-        let v2 = B::from_json_object(&data, &parameters.select_data[1].1);
-        let v3 = C::from_json_object(&data, &parameters.select_data[2].1);
-        let v4 = D::from_json_object(&data, &parameters.select_data[3].1);
-        let v5 = E::from_json_object(&data, &parameters.select_data[4].1);
-        let v6 = F::from_json_object(&data, &parameters.select_data[5].1);
-        let v7 = G::from_json_object(&data, &parameters.select_data[6].1);
-        if let (Ok(v1), Ok(v2), Ok(v3), Ok(v4), Ok(v5), Ok(v6), Ok(v7)) =
-            (v1, v2, v3, v4, v5, v6, v7)
-        {
-            *dest = (v1, v2, v3, v4, v5, v6, v7, true);
-            Ok(())
-        } else {
-            dest.7 = false;
-            Err(())
+        field_valid.fill(false);
+        if let Ok(data) = parse_document(data) {
+            field_valid[0] = load_field::<A>(&data, &parameters.select_data[0], &mut dest.0);
+            field_valid[1] = load_field::<B>(&data, &parameters.select_data[1], &mut dest.1);
+            field_valid[2] = load_field::<C>(&data, &parameters.select_data[2], &mut dest.2);
+            field_valid[3] = load_field::<D>(&data, &parameters.select_data[3], &mut dest.3);
+            field_valid[4] = load_field::<E>(&data, &parameters.select_data[4], &mut dest.4);
+            field_valid[5] = load_field::<F>(&data, &parameters.select_data[5], &mut dest.5);
+            field_valid[6] = load_field::<G>(&data, &parameters.select_data[6], &mut dest.6);
         }
+
+        all_valid(field_valid)
     }
 
     fn default_storage() -> Self::Storage {
@@ -648,7 +898,6 @@ where
             E::default_storage(),
             F::default_storage(),
             G::default_storage(),
-            false,
         )
     }
 
@@ -664,7 +913,10 @@ where
         ]
     }
 
-    fn storage_as_by(storage: &Self::Storage) -> PassBy<'_, Self::Output> {
+    fn storage_as_by<'a, V: Validity>(
+        storage: &'a Self::Storage,
+        validity: &'a V,
+    ) -> PassBy<'a, Self::Output<V>> {
         (
             A::from_storage(&storage.0),
             B::from_storage(&storage.1),
@@ -673,7 +925,7 @@ where
             E::from_storage(&storage.4),
             F::from_storage(&storage.5),
             G::from_storage(&storage.6),
-            storage.7,
+            validity.as_by(),
         )
     }
 }
@@ -1108,4 +1360,226 @@ mod tests {
 
         assert!(block.is_valid(ctxt.time().as_secs_f64()).any());
     }
+
+    #[test]
+    fn test_reads_nested_paths_and_array_indices() {
+        let ctxt = StubContext::default();
+        let input = br#"{"imu": {"accel": [0.1, 0.2, 9.8], "gyro": {"z": 0.5}}, "samples": [[1.0, 2.0], [3.0, 4.0]]}"#;
+        let params = Parameters::new(
+            &[
+                "Scalar:imu.accel[2]".into(),
+                "Scalar:imu.gyro.z".into(),
+                "Scalar:samples[1]".into(),
+                "Scalar:samples[0][1]".into(),
+            ],
+            1000.0,
+        );
+        let mut block = JsonLoadBlock::<(f64, f64, Matrix<1, 2, f64>, f64)>::default();
+        let res = block.process(&params, &ctxt, input);
+        assert_eq!(
+            res,
+            (
+                9.8,
+                0.5,
+                &Matrix {
+                    data: [[3.0], [4.0]]
+                },
+                2.0,
+                true
+            )
+        );
+        assert!(block.is_valid(ctxt.time().as_secs_f64()).any());
+    }
+
+    #[test]
+    fn test_reads_json_pointer_paths() {
+        let ctxt = StubContext::default();
+        let input = br#"{"imu": {"accel": [0.1, 0.2, 9.8]}, "a/b": {"c~d": 3.0}}"#;
+        let params = Parameters::new(
+            &["Scalar:/imu/accel/0".into(), "Scalar:/a~1b/c~0d".into()],
+            1000.0,
+        );
+        let mut block = JsonLoadBlock::<(f64, f64)>::default();
+        let res = block.process(&params, &ctxt, input);
+        assert_eq!(res, (0.1, 3.0, true));
+    }
+
+    #[test]
+    fn test_reads_top_level_keys_containing_separators() {
+        let ctxt = StubContext::default();
+        let input = br#"{"speed.kph": 42.0}"#;
+        let params = Parameters::new(&["Scalar:speed.kph".into()], 1000.0);
+        let mut block = JsonLoadBlock::<f64>::default();
+        let res = block.process(&params, &ctxt, input);
+        assert_eq!(res, (42.0, true));
+    }
+
+    #[test]
+    fn test_uses_default_for_missing_or_null_values() {
+        let ctxt = StubContext::default();
+        let input = br#"{"imu": {"temp": null}}"#;
+        let params = Parameters::new(
+            &[
+                "Scalar:imu.temp=20.5".into(),
+                "Scalar:gps.valid=false".into(),
+                "BytesArray:mode=\"idle\"".into(),
+            ],
+            1000.0,
+        );
+        let mut block = JsonLoadBlock::<(f64, bool, ByteSliceSignal)>::default();
+        let res = block.process(&params, &ctxt, input);
+        assert_eq!(res, (20.5, false, b"idle".as_slice(), true));
+        assert!(block.is_valid(ctxt.time().as_secs_f64()).any());
+    }
+
+    #[test]
+    fn test_reads_paths_and_defaults_containing_equals() {
+        let ctxt = StubContext::default();
+        let input = br#"{"a=b": 1.0, "c": {"d=e": 2.0}}"#;
+        let params = Parameters::new(
+            &[
+                "Scalar:a=b".into(),
+                "Scalar:/c/d=e=0.5".into(),
+                "BytesArray:mode=\"x=y\"".into(),
+            ],
+            1000.0,
+        );
+        assert_eq!(params.select_data[1].key, "/c/d=e");
+        assert_eq!(params.select_data[2].key, "mode");
+
+        let mut block = JsonLoadBlock::<(f64, f64, ByteSliceSignal)>::default();
+        let res = block.process(&params, &ctxt, input);
+        assert_eq!(res, (1.0, 2.0, b"x=y".as_slice(), true));
+    }
+
+    #[test]
+    fn test_reads_booleans_and_integers() {
+        let ctxt = StubContext::default();
+        let input = br#"{"armed": true, "count": 300, "offset": -2, "mask": [[1, 0], [0, 1]]}"#;
+        let params = Parameters::new(
+            &[
+                "Scalar:armed".into(),
+                "Scalar:count".into(),
+                "Scalar:offset".into(),
+                "Scalar:mask".into(),
+            ],
+            1000.0,
+        );
+        let mut block = JsonLoadBlock::<(bool, u16, i8, Matrix<2, 2, u8>)>::default();
+        let res = block.process(&params, &ctxt, input);
+        assert_eq!(
+            res,
+            (
+                true,
+                300,
+                -2,
+                &Matrix {
+                    data: [[1, 0], [0, 1]]
+                },
+                true
+            )
+        );
+        assert_eq!(
+            block.data,
+            vec![
+                OldBlockData::from_scalar(1.0),
+                OldBlockData::from_scalar(300.0),
+                OldBlockData::from_scalar(-2.0),
+                OldBlockData::from_matrix(&[&[1.0, 0.0], &[0.0, 1.0]]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rejects_integers_that_do_not_fit() {
+        let ctxt = StubContext::default();
+        let input = br#"{"small": 256, "frac": 1.5, "whole": 2.0, "flag": 1}"#;
+        let params = Parameters::new(
+            &[
+                "Scalar:small".into(),
+                "Scalar:frac".into(),
+                "Scalar:whole".into(),
+                "Scalar:flag".into(),
+            ],
+            1000.0,
+        );
+        let mut block = JsonLoadBlock::<(u8, i32, i32, bool)>::default();
+        let res = block.process(&params, &ctxt, input);
+        assert_eq!(res, (0, 0, 2, false, false));
+        let app_time_s = ctxt.time().as_secs_f64();
+        assert!(!block.field_is_valid(0, app_time_s).any());
+        assert!(!block.field_is_valid(1, app_time_s).any());
+        assert!(block.field_is_valid(2, app_time_s).any());
+        assert!(!block.field_is_valid(3, app_time_s).any());
+    }
+
+    #[test]
+    fn test_reports_validity_per_field() {
+        let ctxt = StubContext::default();
+        let params = Parameters::new(&["Scalar:speed".into(), "Scalar:lean".into()], 1000.0);
+        let mut block = JsonLoadBlock::<(f64, f64)>::default();
+
+        let res = block.process(&params, &ctxt, br#"{"speed": 12.0, "lean": 0.1}"#);
+        assert_eq!(res, (12.0, 0.1, true));
+
+        // A missing field keeps its last value and only that field is invalidated
+        let res = block.process(&params, &ctxt, br#"{"speed": 13.0}"#);
+        assert_eq!(res, (13.0, 0.1, false));
+        assert_eq!(
+            block.data,
+            vec![
+                OldBlockData::from_scalar(13.0),
+                OldBlockData::from_scalar(0.1),
+            ]
+        );
+
+        let mut block = JsonLoadBlock::<(f64, f64)>::default();
+        block.process(&params, &ctxt, br#"{"speed": 13.0}"#);
+        let app_time_s = ctxt.time().as_secs_f64();
+        assert!(block.field_is_valid(0, app_time_s).any());
+        assert!(!block.field_is_valid(1, app_time_s).any());
+        assert!(!block.is_valid(app_time_s).any());
+    }
+
+    #[test]
+    fn test_outputs_validity_per_field() {
+        let ctxt = StubContext::default();
+        let params = Parameters::new(&["Scalar:speed".into(), "Scalar:lean".into()], 1000.0);
+        let mut block = JsonLoadBlock::<(f64, f64), Matrix<2, 1, bool>>::default();
+
+        let res = block.process(&params, &ctxt, br#"{"speed": 12.0, "lean": 0.1}"#);
+        assert_eq!(
+            res,
+            (
+                12.0,
+                0.1,
+                &Matrix {
+                    data: [[true, true]]
+                }
+            )
+        );
+
+        let res = block.process(&params, &ctxt, br#"{"lean": 0.2}"#);
+        assert_eq!(
+            res,
+            (
+                12.0,
+                0.2,
+                &Matrix {
+                    data: [[false, true]]
+                }
+            )
+        );
+
+        let mut block = JsonLoadBlock::<f64, Matrix<1, 1, bool>>::default();
+        let params = Parameters::new(&[], 1000.0);
+        let (value, valid) = block.process(&params, &ctxt, b"not json");
+        assert_eq!((value, valid.data), (0.0, [[false]]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid validity output")]
+    fn test_validity_output_must_match_field_count() {
+        JsonLoadBlock::<(f64, f64), Matrix<3, 1, bool>>::default();
+    }
 }
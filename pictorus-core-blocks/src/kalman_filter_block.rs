@@ -0,0 +1,260 @@
+use crate::traits::Float;
+use corelib_traits::{Matrix, PassBy, ProcessBlock};
+use nalgebra::{ArrayStorage, Cholesky, SMatrix};
+use pictorus_nalgebra_interop::MatrixExt;
+use utils::{BlockData as OldBlockData, FromPass};
+
+/// Parameters for the Kalman Filter Block, for the discrete-time model
+///
+/// x[k+1] = F·x[k] + B·u[k] + w, with process noise covariance Q
+///
+/// z[k] = H·x[k] + v, with measurement noise covariance R
+pub struct Parameters<const NX: usize, const NU: usize, const NZ: usize, F: Float> {
+    pub f: Matrix<NX, NX, F>,
+    pub b: Matrix<NX, NU, F>,
+    pub h: Matrix<NZ, NX, F>,
+    pub q: Matrix<NX, NX, F>,
+    pub r: Matrix<NZ, NZ, F>,
+    pub initial_state: Matrix<NX, 1, F>,
+    pub initial_covariance: Matrix<NX, NX, F>,
+}
+
+impl<const NX: usize, const NU: usize, const NZ: usize, F: Float> Parameters<NX, NU, NZ, F> {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        f: &OldBlockData,
+        b: &OldBlockData,
+        h: &OldBlockData,
+        q: &OldBlockData,
+        r: &OldBlockData,
+        initial_state: &OldBlockData,
+        initial_covariance: &OldBlockData,
+    ) -> Self {
+        Self {
+            f: matrix_from(f),
+            b: matrix_from(b),
+            h: matrix_from(h),
+            q: matrix_from(q),
+            r: matrix_from(r),
+            initial_state: matrix_from(initial_state),
+            initial_covariance: matrix_from(initial_covariance),
+        }
+    }
+}
+
+/// Copies the elements of a parameter into a matrix, in column-major order
+pub(crate) fn matrix_from<const R: usize, const C: usize, F: Float>(
+    data: &OldBlockData,
+) -> Matrix<R, C, F> {
+    let mut matrix = Matrix::zeroed();
+    for (element, value) in matrix.data.as_flattened_mut().iter_mut().zip(data.iter()) {
+        *element = F::from(*value).expect("Failed to convert parameter to Float");
+    }
+    matrix
+}
+
+pub(crate) fn to_nalgebra<const R: usize, const C: usize, F: Float>(
+    matrix: &Matrix<R, C, F>,
+) -> SMatrix<F, R, C> {
+    SMatrix::from_array_storage(ArrayStorage(matrix.data))
+}
+
+/// State estimate, covariance and innovation
+pub(crate) type Estimate<const NX: usize, const NZ: usize, F> =
+    (Matrix<NX, 1, F>, Matrix<NX, NX, F>, Matrix<NZ, 1, F>);
+
+/// Corrects the prediction with an innovation, the difference between a measurement and its
+/// prediction, and `h`, the sensitivity of the measurement to the state.
+///
+/// The covariance is updated in Joseph form, which keeps it symmetric and positive definite
+/// in the face of rounding. Returns false and leaves the estimate alone if the innovation
+/// covariance isn't positive definite.
+pub(crate) fn correct<const NX: usize, const NZ: usize, F: Float>(
+    x: &mut SMatrix<F, NX, 1>,
+    p: &mut SMatrix<F, NX, NX>,
+    innovation: &SMatrix<F, NZ, 1>,
+    h: &SMatrix<F, NZ, NX>,
+    r: &SMatrix<F, NZ, NZ>,
+) -> bool {
+    let s = h * *p * h.transpose() + r;
+    let Some(s) = Cholesky::new(s) else {
+        return false;
+    };
+    // K = P·Hᵀ·S⁻¹, solved as Kᵀ = S⁻¹·H·P since S and P are symmetric
+    let k = s.solve(&(h * *p)).transpose();
+    *x += k * innovation;
+    let joseph = SMatrix::<F, NX, NX>::identity() - k * h;
+    *p = joseph * *p * joseph.transpose() + k * r * k.transpose();
+    true
+}
+
+/// The Kalman Filter Block estimates the state of a linear system from its inputs and noisy
+/// measurements.
+///
+/// The inputs are the control input `u`, the measurement `z` and whether the measurement is
+/// valid, which can come from the `IsValid` output of the block that received it. Each tick
+/// the estimate is predicted forward through the model, then corrected with the measurement
+/// if it's valid; while it isn't, the filter coasts on the model and the covariance grows.
+/// The first tick corrects the initial state without predicting.
+///
+/// The outputs are the state estimate, its covariance, and the innovation, the difference
+/// between the measurement and the predicted measurement, which is zero while coasting.
+pub struct KalmanFilterBlock<const NX: usize, const NU: usize, const NZ: usize, F: Float>
+where
+    OldBlockData: FromPass<Matrix<NX, 1, F>>,
+{
+    pub data: OldBlockData,
+    estimate: Option<(SMatrix<F, NX, 1>, SMatrix<F, NX, NX>)>,
+    output: Estimate<NX, NZ, F>,
+}
+
+impl<const NX: usize, const NU: usize, const NZ: usize, F: Float> Default
+    for KalmanFilterBlock<NX, NU, NZ, F>
+where
+    OldBlockData: FromPass<Matrix<NX, 1, F>>,
+{
+    fn default() -> Self {
+        Self {
+            data: <OldBlockData as FromPass<Matrix<NX, 1, F>>>::from_pass(&Matrix::zeroed()),
+            estimate: None,
+            output: (Matrix::zeroed(), Matrix::zeroed(), Matrix::zeroed()),
+        }
+    }
+}
+
+impl<const NX: usize, const NU: usize, const NZ: usize, F: Float> ProcessBlock
+    for KalmanFilterBlock<NX, NU, NZ, F>
+where
+    OldBlockData: FromPass<Matrix<NX, 1, F>>,
+{
+    type Inputs = (Matrix<NU, 1, F>, Matrix<NZ, 1, F>, bool);
+    type Output = Estimate<NX, NZ, F>;
+    type Parameters = Parameters<NX, NU, NZ, F>;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn corelib_traits::Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let (u, z, z_valid) = inputs;
+        let h = to_nalgebra(&parameters.h);
+        let (x, p) = match &mut self.estimate {
+            Some((x, p)) => {
+                let f = to_nalgebra(&parameters.f);
+                *x = f * *x + to_nalgebra(&parameters.b) * u.as_view();
+                *p = f * *p * f.transpose() + to_nalgebra(&parameters.q);
+                (x, p)
+            }
+            None => {
+                let (x, p) = self.estimate.insert((
+                    to_nalgebra(&parameters.initial_state),
+                    to_nalgebra(&parameters.initial_covariance),
+                ));
+                (x, p)
+            }
+        };
+
+        let mut innovation = SMatrix::<F, NZ, 1>::zeros();
+        if z_valid {
+            innovation = z.as_view() - h * *x;
+            if !correct(x, p, &innovation, &h, &to_nalgebra(&parameters.r)) {
+                innovation.fill(F::zero());
+            }
+        }
+
+        self.output = (
+            Matrix::from_view(&x.as_view()),
+            Matrix::from_view(&p.as_view()),
+            Matrix::from_view(&innovation.as_view()),
+        );
+        self.data = OldBlockData::from_pass(&self.output.0);
+        let (x, p, innovation) = &self.output;
+        (x, p, innovation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use corelib_traits_testing::StubContext;
+
+    #[test]
+    fn test_kalman_filter_constant() {
+        // Estimating a constant: after n measurements the variance is 1 / (n + 1) and the
+        // estimate is their sum divided by n + 1
+        let one = OldBlockData::from_scalar(1.0);
+        let zero = OldBlockData::from_scalar(0.0);
+        let parameters =
+            Parameters::<1, 1, 1, f64>::new(&one, &zero, &one, &zero, &one, &zero, &one);
+        let context = StubContext::default();
+        let mut block = KalmanFilterBlock::<1, 1, 1, f64>::default();
+
+        let mut sum = 0.0;
+        for (n, z) in [4.0, 6.0, 5.0, 3.0, 7.0].into_iter().enumerate() {
+            let input = Matrix { data: [[z]] };
+            let (x, p, innovation) =
+                block.process(&parameters, &context, (&Matrix::zeroed(), &input, true));
+            let n = (n + 1) as f64;
+            assert_relative_eq!(innovation.data[0][0], z - sum / n, epsilon = 1e-12);
+            sum += z;
+            assert_relative_eq!(x.data[0][0], sum / (n + 1.0), epsilon = 1e-12);
+            assert_relative_eq!(p.data[0][0], 1.0 / (n + 1.0), epsilon = 1e-12);
+        }
+        assert_relative_eq!(block.data.get_data()[(0, 0)], 25.0 / 6.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_kalman_filter_speed_fusion() {
+        // Speed and acceleration from wheel speed, which is noisy, and GPS speed, with no
+        // control input
+        let dt = 0.01;
+        let parameters = Parameters::<2, 1, 2, f64> {
+            f: Matrix {
+                data: [[1.0, 0.0], [dt, 1.0]],
+            },
+            b: Matrix::zeroed(),
+            h: Matrix {
+                data: [[1.0, 1.0], [0.0, 0.0]],
+            },
+            q: Matrix {
+                data: [[1e-6, 0.0], [0.0, 1e-2]],
+            },
+            r: Matrix {
+                data: [[0.25, 0.0], [0.0, 0.04]],
+            },
+            initial_state: Matrix::zeroed(),
+            initial_covariance: Matrix {
+                data: [[100.0, 0.0], [0.0, 10.0]],
+            },
+        };
+        let context = StubContext::default();
+        let mut block = KalmanFilterBlock::<2, 1, 2, f64>::default();
+
+        // Accelerating at 0.5 m/s² from 10 m/s, with deterministic wheel speed noise
+        let speed = |i: usize| 10.0 + 0.5 * i as f64 * dt;
+        let mut variance = 0.0;
+        for i in 0..1000 {
+            let noise = if i % 2 == 0 { 0.3 } else { -0.3 };
+            let z = Matrix {
+                data: [[speed(i) + noise, speed(i)]],
+            };
+            // The sensors go stale for the last second
+            let coasting = i >= 900;
+            let (x, p, innovation) =
+                block.process(&parameters, &context, (&Matrix::zeroed(), &z, !coasting));
+
+            if coasting {
+                assert_eq!(innovation.data[0], [0.0, 0.0]);
+                assert!(p.data[0][0] > variance);
+            } else if i > 200 {
+                assert_relative_eq!(x.data[0][0], speed(i), epsilon = 0.05);
+                assert_relative_eq!(x.data[0][1], 0.5, epsilon = 0.1);
+            }
+            variance = p.data[0][0];
+        }
+        // The acceleration estimate carries the speed through the dropout
+        assert_relative_eq!(block.output.0.data[0][0], speed(999), epsilon = 0.05);
+    }
+}
@@ -21,9 +21,21 @@ pub use app_time_block::AppTimeBlock;
 mod arg_min_max_block;
 pub use arg_min_max_block::ArgMinMaxBlock;
 
+mod attitude_estimator_block;
+pub use attitude_estimator_block::Parameters as AttitudeEstimatorBlockParams;
+pub use attitude_estimator_block::{AttitudeAlgorithm, AttitudeEstimatorBlock};
+
+mod barometer_block;
+pub use barometer_block::Parameters as BarometerBlockParams;
+pub use barometer_block::{BarometerBlock, BarometerSensor};
+
 mod bias_block;
 pub use bias_block::BiasBlock;
 
+mod biquad_cascade_block;
+pub use biquad_cascade_block::BiquadCascadeBlock;
+pub use biquad_cascade_block::Parameters as BiquadCascadeBlockParams;
+
 mod bit_shift_block;
 pub use bit_shift_block::BitShiftBlock;
 
@@ -45,9 +57,17 @@ pub use bytes_split_block::BytesSplitBlock;
 mod bytes_unpack_block;
 pub use bytes_unpack_block::BytesUnpackBlock;
 
+mod can_bus_status_block;
+pub use can_bus_status_block::CanBusStatusBlock;
+pub use can_bus_status_block::Parameters as CanBusStatusBlockParams;
+
 mod change_detection_block;
 pub use change_detection_block::ChangeDetectionBlock;
 
+mod checksum_block;
+pub use checksum_block::ChecksumBlock;
+pub use checksum_block::Parameters as ChecksumBlockParams;
+
 mod clamp_block;
 pub use clamp_block::ClampBlock;
 
@@ -70,9 +90,17 @@ mod dac_block;
 pub use dac_block::DacBlock;
 pub use dac_block::Parameters as DacBlockParams;
 
+mod dac_stream_block;
+pub use dac_stream_block::Parameters as DacStreamBlockParams;
+pub use dac_stream_block::{DacStreamBlock, DacWaveform};
+
 mod deadband_block;
 pub use deadband_block::DeadbandBlock;
 
+mod debounce_block;
+pub use debounce_block::DebounceBlock;
+pub use debounce_block::Parameters as DebounceBlockParams;
+
 mod delay_block;
 pub use delay_block::DelayBlock;
 
@@ -88,15 +116,46 @@ pub use derivative_block::DerivativeBlock;
 mod dot_product_block;
 pub use dot_product_block::DotProductBlock;
 
+mod edge_counter_block;
+pub use edge_counter_block::Parameters as EdgeCounterBlockParams;
+pub use edge_counter_block::{CaptureEdge, EdgeCounterBlock};
+
+mod entropy_block;
+pub use entropy_block::EntropyBlock;
+
 mod exponent_block;
 pub use exponent_block::ExponentBlock;
 
+mod extended_kalman_filter_block;
+pub use extended_kalman_filter_block::Parameters as ExtendedKalmanFilterBlockParams;
+pub use extended_kalman_filter_block::{ExtendedKalmanFilterBlock, MeasurementFn, TransitionFn};
+
+mod fft_block;
+pub use fft_block::Parameters as FftBlockParams;
+pub use fft_block::{CoreFft, FftBlock, FftTransform, FftWindow};
+
+mod fir_filter_block;
+pub use fir_filter_block::Parameters as FirFilterBlockParams;
+pub use fir_filter_block::{FirCoefficients, FirFilterBlock};
+
 // These blocks are special versions of passthrough blocks that are
 // used to handle user-input functions that might return non-finite data
 mod fix_non_finite_block;
 pub use fix_non_finite_block::FixNonFiniteBlock as RustCodeBlock;
 pub use fix_non_finite_block::FixNonFiniteBlock as EquationBlock;
 
+mod frame_decode_block;
+pub use frame_decode_block::FrameDecodeBlock;
+pub use frame_decode_block::Parameters as FrameDecodeBlockParams;
+
+mod frame_encode_block;
+pub use frame_encode_block::FrameEncodeBlock;
+pub use frame_encode_block::Parameters as FrameEncodeBlockParams;
+
+mod frame_transform_block;
+pub use frame_transform_block::FrameTransformBlock;
+pub use frame_transform_block::Parameters as FrameTransformBlockParams;
+
 mod frequency_filter_block;
 pub use frequency_filter_block::FrequencyFilterBlock;
 
@@ -111,13 +170,31 @@ mod i2c_output_block;
 pub use i2c_output_block::I2cOutputBlock;
 pub use i2c_output_block::Parameters as I2cOutputBlockParams;
 
+mod i2c_transaction_block;
+pub use i2c_transaction_block::I2cTransactionBlock;
+pub use i2c_transaction_block::Parameters as I2cTransactionBlockParams;
+
+mod gpio_edge_event_block;
+pub use gpio_edge_event_block::GpioEdgeEventBlock;
+pub use gpio_edge_event_block::Parameters as GpioEdgeEventBlockParams;
+
 mod gpio_output_block;
 pub use gpio_output_block::GpioOutputBlock;
 pub use gpio_output_block::Parameters as GpioOutputBlockParams;
 
+mod hysteresis_block;
+pub use hysteresis_block::HysteresisBlock;
+
 mod iir_filter_block;
 pub use iir_filter_block::IirFilterBlock;
 
+mod impulse_detector_block;
+pub use impulse_detector_block::ImpulseDetectorBlock;
+
+mod imu_block;
+pub use imu_block::Parameters as ImuBlockParams;
+pub use imu_block::{ImuBlock, ImuSensor};
+
 mod integral_block;
 pub use integral_block::IntegralBlock;
 
@@ -127,6 +204,14 @@ pub use json_dump_block::JsonDumpBlock;
 mod json_load_block;
 pub use json_load_block::JsonLoadBlock;
 
+mod kalman_filter_block;
+pub use kalman_filter_block::KalmanFilterBlock;
+pub use kalman_filter_block::Parameters as KalmanFilterBlockParams;
+
+mod linear_solve_block;
+pub use linear_solve_block::LinearSolveBlock;
+pub use linear_solve_block::Parameters as LinearSolveBlockParams;
+
 mod logical_block;
 pub use logical_block::LogicalBlock;
 
@@ -136,12 +221,45 @@ pub use lookup_2d_block::Lookup2DBlock;
 mod lookup_1d_block;
 pub use lookup_1d_block::Lookup1DBlock;
 
+mod lookup_nd_block;
+pub use lookup_nd_block::{LookupInput, LookupNDBlock};
+
+mod magnetometer_block;
+pub use magnetometer_block::Parameters as MagnetometerBlockParams;
+pub use magnetometer_block::{MagnetometerBlock, MagnetometerSensor};
+
+mod mavlink_decode_block;
+pub use mavlink_decode_block::MavlinkDecodeBlock;
+pub use mavlink_decode_block::Parameters as MavlinkDecodeBlockParams;
+
+mod mavlink_encode_block;
+pub use mavlink_encode_block::MavlinkEncodeBlock;
+pub use mavlink_encode_block::Parameters as MavlinkEncodeBlockParams;
+
 mod min_max_block;
 pub use min_max_block::MinMaxBlock;
 
+mod matrix_decomposition_block;
+pub use matrix_decomposition_block::{
+    Cholesky, Decomposition, Lu, MatrixDecompositionBlock, Qr, SymmetricEigen,
+};
+
 mod matrix_inverse_block;
 pub use matrix_inverse_block::{Inverse, MatrixInverseBlock, Svd};
 
+mod modbus_read_block;
+pub use modbus_read_block::Parameters as ModbusReadBlockParams;
+pub use modbus_read_block::{ModbusDataType, ModbusLayout, ModbusReadBlock, ModbusTable};
+
+
+mod modbus_write_block;
+pub use modbus_write_block::ModbusWriteBlock;
+pub use modbus_write_block::Parameters as ModbusWriteBlockParams;
+
+mod multi_channel_adc_block;
+pub use multi_channel_adc_block::MultiChannelAdcBlock;
+pub use multi_channel_adc_block::Parameters as MultiChannelAdcBlockParams;
+
 mod not_block;
 pub use not_block::NotBlock;
 
@@ -158,15 +276,41 @@ pub use passthrough_block::PassthroughBlock as DataWriteBlock;
 pub use passthrough_block::PassthroughBlock as GpioInputBlock;
 pub use passthrough_block::PassthroughBlock as SpiTransmitBlock;
 
+mod peak_detector_block;
+pub use peak_detector_block::PeakDetectorBlock;
+
 mod pid_block;
 pub use pid_block::PidBlock;
 
+mod prelookup_block;
+pub use prelookup_block::PrelookupBlock;
+
 mod product_block;
 pub use product_block::{ComponentWise, MatrixMultiply, ProductBlock};
 
+mod pulse_capture_block;
+pub use pulse_capture_block::Parameters as PulseCaptureBlockParams;
+pub use pulse_capture_block::PulseCaptureBlock;
+
+mod quadrature_encoder_block;
+pub use quadrature_encoder_block::Parameters as QuadratureEncoderBlockParams;
+pub use quadrature_encoder_block::QuadratureEncoderBlock;
+
 mod quantize_block;
 pub use quantize_block::QuantizeBlock;
 
+mod quaternion_conjugate_block;
+pub use quaternion_conjugate_block::QuaternionConjugateBlock;
+
+mod quaternion_multiply_block;
+pub use quaternion_multiply_block::QuaternionMultiplyBlock;
+
+mod quaternion_normalize_block;
+pub use quaternion_normalize_block::QuaternionNormalizeBlock;
+
+mod quaternion_slerp_block;
+pub use quaternion_slerp_block::QuaternionSlerpBlock;
+
 mod ramp_block;
 pub use ramp_block::RampBlock;
 
@@ -176,6 +320,12 @@ pub use random_number_block::RandomNumberBlock;
 mod rate_limit_block;
 pub use rate_limit_block::RateLimitBlock;
 
+mod rotation_conversion_block;
+pub use rotation_conversion_block::RotationConversionBlock;
+
+mod running_statistics_block;
+pub use running_statistics_block::{Averaging, RunningStatisticsBlock, Statistic};
+
 mod sawtoothwave_block;
 pub use sawtoothwave_block::SawtoothwaveBlock;
 
@@ -187,6 +337,10 @@ mod serial_transmit_block;
 pub use serial_transmit_block::Parameters as SerialTransmitBlockParams;
 pub use serial_transmit_block::SerialTransmitBlock;
 
+mod servo_output_block;
+pub use servo_output_block::Parameters as ServoOutputBlockParams;
+pub use servo_output_block::{ServoMode, ServoOutputBlock};
+
 mod sinewave_block;
 pub use sinewave_block::SinewaveBlock;
 
@@ -206,6 +360,9 @@ pub use sum_block::SumBlock;
 mod switch_block;
 pub use switch_block::SwitchBlock;
 
+mod time_over_threshold_block;
+pub use time_over_threshold_block::TimeOverThresholdBlock;
+
 mod timer_block;
 pub use timer_block::TimerBlock;
 
@@ -241,10 +398,23 @@ pub use vector_norm_block::VectorNormBlock;
 mod vector_reshape_block;
 pub use vector_reshape_block::VectorReshapeBlock;
 
+mod vector_rotation_block;
+pub use vector_rotation_block::Parameters as VectorRotationBlockParams;
+pub use vector_rotation_block::{RotationDirection, VectorRotationBlock};
+
 mod vector_slice_block;
 pub use vector_slice_block::VectorSliceBlock;
 
 mod vector_sort_block;
 pub use vector_sort_block::VectorSortBlock;
 
+mod zero_crossing_block;
+pub use zero_crossing_block::ZeroCrossingBlock;
+
+pub mod filter_design;
+
+pub mod lookup;
+
+pub mod rotation;
+
 pub(crate) mod traits;
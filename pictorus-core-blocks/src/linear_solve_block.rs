@@ -0,0 +1,207 @@
+use corelib_traits::{Matrix, PassBy, ProcessBlock};
+use nalgebra::{allocator::Allocator, Const, DefaultAllocator, DimDiff, DimMin, DimSub, SVD, U1};
+use pictorus_nalgebra_interop::MatrixExt;
+use utils::{BlockData as OldBlockData, FromPass, IsValid};
+
+use crate::matrix_decomposition_block::{to_finite_nalgebra, MAX_ITERATIONS};
+use crate::traits::Float;
+
+/// Parameters for the Linear Solve Block
+pub struct Parameters {
+    /// Largest condition number of `A`, the ratio of its largest to smallest singular value,
+    /// for which the solution is valid
+    pub max_condition: f64,
+}
+
+impl Parameters {
+    pub fn new(max_condition: f64) -> Self {
+        Self { max_condition }
+    }
+}
+
+/// Solution, condition number of `A`, norm of the residual `A·x - b`, and whether the solution
+/// is valid
+type Solution<const C: usize, const K: usize, F> = (Matrix<C, K, F>, F, F, bool);
+
+/// Solves `A·x = b` for `x`, exactly if `A` is square or in the least squares sense if it has
+/// more rows than columns. `b` can have several columns, each solved for independently.
+///
+/// The solution uses the singular value decomposition of `A`, so a singular or ill-conditioned
+/// `A` is detected rather than amplifying noise into the solution. Besides the solution, the
+/// outputs are the condition number of `A`, how much it can amplify relative errors in `b`,
+/// the norm of the residual, which measures how well the solution fits an overdetermined
+/// system, and whether the solution is valid.
+///
+/// The solution is invalid when the condition number exceeds `max_condition`, or when the
+/// inputs aren't finite. Directions in which `A` is too weak to be inverted are then left out
+/// of the solution, which is the minimum norm least squares solution of the well-conditioned
+/// part of the system, or zero for non-finite inputs.
+pub struct LinearSolveBlock<const R: usize, const C: usize, const K: usize, F: Float> {
+    pub data: OldBlockData,
+    output: Solution<C, K, F>,
+}
+
+impl<const R: usize, const C: usize, const K: usize, F: Float> Default
+    for LinearSolveBlock<R, C, K, F>
+where
+    OldBlockData: FromPass<Matrix<C, K, F>>,
+{
+    fn default() -> Self {
+        Self {
+            data: <OldBlockData as FromPass<Matrix<C, K, F>>>::from_pass(&Matrix::zeroed()),
+            output: (Matrix::zeroed(), F::zero(), F::zero(), false),
+        }
+    }
+}
+
+impl<const R: usize, const C: usize, const K: usize, F: Float> ProcessBlock
+    for LinearSolveBlock<R, C, K, F>
+where
+    Const<R>: DimMin<Const<C>, Output = Const<C>>,
+    Const<C>: DimSub<U1>,
+    DefaultAllocator: Allocator<DimDiff<Const<C>, U1>>,
+    OldBlockData: FromPass<Matrix<C, K, F>>,
+{
+    type Inputs = (Matrix<R, C, F>, Matrix<R, K, F>);
+    type Output = Solution<C, K, F>;
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn corelib_traits::Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let (a, b) = inputs;
+        let svd = to_finite_nalgebra(a)
+            .zip(to_finite_nalgebra(b))
+            .and_then(|(a, b)| {
+                SVD::<F, Const<R>, Const<C>>::try_new(a, true, true, F::EPSILON, MAX_ITERATIONS)
+                    .map(|svd| (a, b, svd))
+            });
+
+        self.output = match svd {
+            Some((a, b, svd)) => {
+                let largest = svd.singular_values[0];
+                let smallest = svd.singular_values[C - 1];
+                let condition = if smallest > F::zero() {
+                    largest / smallest
+                } else {
+                    F::infinity()
+                };
+                let max_condition = F::from(parameters.max_condition).unwrap();
+                // Singular values below this are treated as zero
+                let threshold = num_traits::Float::max(
+                    largest / max_condition,
+                    largest * F::from(R).unwrap() * F::EPSILON,
+                );
+                match svd.solve(&b, threshold) {
+                    Ok(x) => (
+                        Matrix::from_view(&x.as_view()),
+                        condition,
+                        (a * x - b).norm(),
+                        condition <= max_condition,
+                    ),
+                    Err(_) => (Matrix::zeroed(), condition, b.norm(), false),
+                }
+            }
+            None => (Matrix::zeroed(), F::infinity(), F::infinity(), false),
+        };
+        self.data = OldBlockData::from_pass(&self.output.0);
+        let (x, condition, residual, valid) = &self.output;
+        (x, *condition, *residual, *valid)
+    }
+}
+
+// TODO: Remove when we remove BlockData
+impl<const R: usize, const C: usize, const K: usize, F: Float> IsValid
+    for LinearSolveBlock<R, C, K, F>
+{
+    fn is_valid(&self, _: f64) -> OldBlockData {
+        OldBlockData::scalar_from_bool(self.output.3)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use corelib_traits_testing::StubContext;
+
+    #[test]
+    fn test_linear_solve_square() {
+        let parameters = Parameters::new(1e6);
+        let context = StubContext::default();
+        let mut block = LinearSolveBlock::<2, 2, 1, f64>::default();
+
+        // x + 2y = 5, 3x + 4y = 6
+        let a = Matrix {
+            data: [[1.0, 3.0], [2.0, 4.0]],
+        };
+        let b = Matrix { data: [[5.0, 6.0]] };
+        let (x, condition, residual, valid) = block.process(&parameters, &context, (&a, &b));
+        assert!(valid);
+        assert_relative_eq!(x.data[0][..], [-4.0, 4.5][..], epsilon = 1e-12);
+        assert!(condition > 10.0 && condition < 20.0);
+        assert_relative_eq!(residual, 0.0, epsilon = 1e-12);
+        assert!(block.is_valid(0.0).any());
+
+        // Singular: the second equation is twice the first, so only the component along
+        // [1, 2] is solved for
+        let a = Matrix {
+            data: [[1.0, 2.0], [2.0, 4.0]],
+        };
+        let b = Matrix {
+            data: [[5.0, 10.0]],
+        };
+        let (x, condition, residual, valid) = block.process(&parameters, &context, (&a, &b));
+        assert!(!valid);
+        assert!(condition > 1e6);
+        assert_relative_eq!(x.data[0][..], [1.0, 2.0][..], epsilon = 1e-12);
+        assert_relative_eq!(residual, 0.0, epsilon = 1e-12);
+
+        let b = Matrix {
+            data: [[f64::NAN, 1.0]],
+        };
+        let (x, _, _, valid) = block.process(&parameters, &context, (&a, &b));
+        assert!(!valid);
+        assert_eq!(x.data[0], [0.0, 0.0]);
+        assert!(!block.is_valid(0.0).any());
+    }
+
+    #[test]
+    fn test_linear_solve_mounting_calibration() {
+        // Fit the matrix mapping sensor readings to reference accelerations, from readings in
+        // six orientations: readings · Mᵀ = references, one row per orientation
+        let parameters = Parameters::new(1e6);
+        let context = StubContext::default();
+        let mut block = LinearSolveBlock::<6, 3, 3, f64>::default();
+
+        let g = 9.81;
+        let references = Matrix::<6, 3, f64> {
+            data: [
+                [g, -g, 0.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, g, -g, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0, g, -g],
+            ],
+        };
+        // The sensor is rotated 90° about z and its x axis reads 2% high
+        let mounting_t = Matrix::<3, 3, f64> {
+            data: [[0.0, -1.0, 0.0], [1.02, 0.0, 0.0], [0.0, 0.0, 1.0]],
+        };
+        let readings: Matrix<6, 3, f64> =
+            Matrix::from_view(&(references.as_view() * mounting_t.as_view()).as_view());
+
+        let (fit, _, residual, valid) =
+            block.process(&parameters, &context, (&readings, &references));
+        assert!(valid);
+        assert_relative_eq!(residual, 0.0, epsilon = 1e-9);
+        let identity: Matrix<6, 3, f64> =
+            Matrix::from_view(&(readings.as_view() * fit.as_view()).as_view());
+        assert_relative_eq!(
+            identity.data.as_flattened(),
+            references.data.as_flattened(),
+            epsilon = 1e-9
+        );
+    }
+}
@@ -0,0 +1,488 @@
+//! Breakpoint search and one-dimensional interpolation shared by the lookup table blocks.
+//!
+//! A lookup happens in two steps. The prelookup finds where an input falls among the
+//! breakpoints of an axis, as the segment between two breakpoints and the fraction of the way
+//! along it. The interpolation then combines the table values around that segment. Splitting
+//! them lets several tables that share an axis search it once, and lets a table with several
+//! axes interpolate along one axis at a time: the values it interpolates along the first axis
+//! are themselves interpolated along the remaining axes.
+//!
+//! The spline methods are cubic Hermite splines: each segment is the cubic with the table
+//! values and slopes at both of its ends. They differ in how the slopes at the breakpoints are
+//! estimated from the neighbouring values, which only involves the few breakpoints around a
+//! segment, so each lookup stays cheap.
+use core::str::FromStr;
+
+use utils::{BlockData as OldBlockData, ParseEnumError};
+
+use crate::traits::Float;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterpMethod {
+    /// Value at the nearest breakpoint
+    Nearest,
+    Linear,
+    /// Spline with the slopes of the parabola through each breakpoint and its neighbours.
+    /// Smooth, but it can overshoot around steps in the data.
+    Cubic,
+    /// Akima spline, whose slopes favour the flatter side of each breakpoint. It overshoots
+    /// much less than `Cubic` near steps and outliers.
+    Akima,
+    /// Monotone spline (Fritsch-Carlson). It never overshoots: it rises or falls wherever the
+    /// data does, and is flat at local extremes of the data.
+    Monotone,
+}
+
+impl FromStr for InterpMethod {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Nearest" => Ok(Self::Nearest),
+            "Linear" => Ok(Self::Linear),
+            "Cubic" => Ok(Self::Cubic),
+            "Akima" => Ok(Self::Akima),
+            "Monotone" => Ok(Self::Monotone),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+/// What a lookup does with inputs beyond the first or last breakpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Extrapolation {
+    /// Holds the value at the end of the table
+    Clamp,
+    /// Continues with the slope at the end of the table. `Nearest` interpolation holds the
+    /// value at the end instead.
+    Linear,
+    /// Holds the value at the end of the table and flags the result as invalid
+    Error,
+}
+
+impl FromStr for Extrapolation {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Clamp" => Ok(Self::Clamp),
+            "Linear" => Ok(Self::Linear),
+            "Error" => Ok(Self::Error),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+/// Why a set of breakpoints or a table can't be used
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakpointError {
+    /// An axis needs at least two breakpoints
+    TooFew { axis: usize },
+    /// An axis has more breakpoints than the block has room for
+    TooMany { axis: usize },
+    /// The breakpoint at `index` isn't finite
+    NotFinite { axis: usize, index: usize },
+    /// The breakpoint at `index` isn't larger than the one before it
+    NotIncreasing { axis: usize, index: usize },
+    /// The table doesn't have one value for each combination of breakpoints
+    TableSize { expected: usize, actual: usize },
+}
+
+/// Checks that breakpoints are finite and strictly increasing
+pub(crate) fn validate_break_points<S: Float>(
+    axis: usize,
+    break_points: &[S],
+) -> Result<(), BreakpointError> {
+    for (index, point) in break_points.iter().enumerate() {
+        if !num_traits::Float::is_finite(*point) {
+            return Err(BreakpointError::NotFinite { axis, index });
+        }
+        if index > 0 && *point <= break_points[index - 1] {
+            return Err(BreakpointError::NotIncreasing { axis, index });
+        }
+    }
+    Ok(())
+}
+
+/// Where an input falls along an axis: the segment from breakpoint `segment` to the next one,
+/// and the fraction of the way along it. The fraction is outside `[0, 1]` when extrapolating.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Position<S> {
+    pub segment: usize,
+    pub fraction: S,
+}
+
+/// The breakpoints of one axis of a table, with room for up to `N`
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Axis<const N: usize, S: Float> {
+    break_points: [S; N],
+    len: usize,
+}
+
+/// An axis with no breakpoints, only a placeholder until the real one is read
+impl<const N: usize, S: Float> Default for Axis<N, S> {
+    fn default() -> Self {
+        Self {
+            break_points: [S::zero(); N],
+            len: 0,
+        }
+    }
+}
+
+impl<const N: usize, S: Float> Axis<N, S> {
+    pub fn new(axis: usize, break_points: &OldBlockData) -> Result<Self, BreakpointError> {
+        let len = break_points.n_elements();
+        if len > N {
+            return Err(BreakpointError::TooMany { axis });
+        }
+        let mut points = [S::zero(); N];
+        for (point, value) in points.iter_mut().zip(break_points.iter()) {
+            *point = S::from(*value).expect("Failed to convert break point to float");
+        }
+        if len < 2 {
+            return Err(BreakpointError::TooFew { axis });
+        }
+        validate_break_points(axis, &points[..len])?;
+        Ok(Self {
+            break_points: points,
+            len,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    fn last_segment(&self) -> usize {
+        self.len - 2
+    }
+
+    /// Finds where `x` falls along the axis, and whether it is within the breakpoints. With
+    /// `Clamp` or `Error` extrapolation, inputs beyond the ends are moved to the ends.
+    pub fn prelookup(&self, x: S, extrapolation: Extrapolation) -> (Position<S>, bool) {
+        let points = &self.break_points[..self.len];
+        let in_range = x >= points[0] && x <= points[self.len - 1];
+        // The first breakpoint above `x`, found by bisection
+        let above = points.partition_point(|point| *point <= x);
+        let segment = above.saturating_sub(1).min(self.last_segment());
+        let fraction = (x - points[segment]) / (points[segment + 1] - points[segment]);
+        let position = Position { segment, fraction };
+        (self.extrapolate(position, extrapolation), in_range)
+    }
+
+    /// The position of a fractional breakpoint index, such as 2.25 for a quarter of the way
+    /// from the third breakpoint to the fourth, and whether it is within the breakpoints
+    pub fn index_position(&self, index: S, extrapolation: Extrapolation) -> (Position<S>, bool) {
+        let last = S::from(self.len - 1).unwrap();
+        let in_range = index >= S::zero() && index <= last;
+        let floor = num_traits::Float::floor(num_traits::Float::max(index, S::zero()));
+        let segment = num_traits::ToPrimitive::to_usize(&floor)
+            .unwrap_or(0)
+            .min(self.last_segment());
+        let fraction = index - S::from(segment).unwrap();
+        let position = Position { segment, fraction };
+        (self.extrapolate(position, extrapolation), in_range)
+    }
+
+    fn extrapolate(&self, position: Position<S>, extrapolation: Extrapolation) -> Position<S> {
+        match extrapolation {
+            Extrapolation::Linear => position,
+            Extrapolation::Clamp | Extrapolation::Error => Position {
+                fraction: num_traits::Float::min(
+                    num_traits::Float::max(position.fraction, S::zero()),
+                    S::one(),
+                ),
+                ..position
+            },
+        }
+    }
+
+    fn width(&self, segment: usize) -> S {
+        self.break_points[segment + 1] - self.break_points[segment]
+    }
+
+    /// Interpolates along this axis at `position`, where `value` gives the table value at a
+    /// breakpoint. Only the breakpoints the method needs are asked for.
+    pub fn interpolate(
+        &self,
+        method: InterpMethod,
+        position: Position<S>,
+        value: &mut dyn FnMut(usize) -> S,
+    ) -> S {
+        let Position { segment, fraction } = position;
+        match method {
+            InterpMethod::Nearest => {
+                if fraction < S::from(0.5).unwrap() {
+                    value(segment)
+                } else {
+                    value(segment + 1)
+                }
+            }
+            InterpMethod::Linear => {
+                let low = value(segment);
+                low + fraction * (value(segment + 1) - low)
+            }
+            InterpMethod::Cubic | InterpMethod::Akima | InterpMethod::Monotone => {
+                // Akima slopes reach two breakpoints further out than the segment ends
+                let first = segment.saturating_sub(2);
+                let last = (segment + 3).min(self.len - 1);
+                let mut values = [S::zero(); 6];
+                for (index, slot) in (first..=last).zip(values.iter_mut()) {
+                    *slot = value(index);
+                }
+                let window = Window {
+                    axis: self,
+                    first,
+                    last,
+                    values,
+                };
+                window.hermite(method, segment, fraction)
+            }
+        }
+    }
+}
+
+/// Table values at consecutive breakpoints around a segment
+struct Window<'a, const N: usize, S: Float> {
+    axis: &'a Axis<N, S>,
+    first: usize,
+    last: usize,
+    values: [S; 6],
+}
+
+impl<const N: usize, S: Float> Window<'_, N, S> {
+    fn value(&self, index: usize) -> S {
+        self.values[index - self.first]
+    }
+
+    /// Slope of the segment starting at breakpoint `segment`. Akima slopes need two segments
+    /// beyond each end of the table, which continue the trend of the last two.
+    fn secant(&self, segment: isize) -> S {
+        let segments = (self.axis.len - 1) as isize;
+        let two = S::from(2.0).unwrap();
+        if segments == 1 {
+            self.secant_within(0)
+        } else if segment < 0 {
+            two * self.secant(segment + 1) - self.secant(segment + 2)
+        } else if segment >= segments {
+            two * self.secant(segment - 1) - self.secant(segment - 2)
+        } else {
+            self.secant_within(segment as usize)
+        }
+    }
+
+    fn secant_within(&self, segment: usize) -> S {
+        debug_assert!(segment >= self.first && segment < self.last);
+        (self.value(segment + 1) - self.value(segment)) / self.axis.width(segment)
+    }
+
+    /// Slope at the first or last breakpoint, from the parabola through it and its two
+    /// neighbours
+    fn end_slope(&self, index: usize) -> S {
+        let segments = self.axis.len - 1;
+        if segments == 1 {
+            return self.secant_within(0);
+        }
+        // The end segment and the one next to it
+        let (end, next) = if index == 0 {
+            (0, 1)
+        } else {
+            (segments - 1, segments - 2)
+        };
+        let (h_end, h_next) = (self.axis.width(end), self.axis.width(next));
+        let (d_end, d_next) = (self.secant_within(end), self.secant_within(next));
+        ((h_end + h_end + h_next) * d_end - h_end * d_next) / (h_end + h_next)
+    }
+
+    fn slope(&self, method: InterpMethod, index: usize) -> S {
+        let segments = self.axis.len - 1;
+        let at_end = index == 0 || index == segments;
+        let k = index as isize;
+        match method {
+            InterpMethod::Akima => {
+                let (d0, d1, d2, d3) = (
+                    self.secant(k - 2),
+                    self.secant(k - 1),
+                    self.secant(k),
+                    self.secant(k + 1),
+                );
+                let w_before = num_traits::Float::abs(d3 - d2);
+                let w_after = num_traits::Float::abs(d1 - d0);
+                if w_before + w_after > S::zero() {
+                    (w_before * d1 + w_after * d2) / (w_before + w_after)
+                } else {
+                    (d1 + d2) / S::from(2.0).unwrap()
+                }
+            }
+            InterpMethod::Monotone if at_end => {
+                let slope = self.end_slope(index);
+                let end = if index == 0 { 0 } else { segments - 1 };
+                let d_end = self.secant_within(end);
+                if segments == 1 {
+                    slope
+                } else if slope * d_end <= S::zero() {
+                    S::zero()
+                } else {
+                    let next = if index == 0 { 1 } else { segments - 2 };
+                    let three = S::from(3.0).unwrap();
+                    let overshoots = d_end * self.secant_within(next) <= S::zero()
+                        && num_traits::Float::abs(slope) > three * num_traits::Float::abs(d_end);
+                    if overshoots {
+                        three * d_end
+                    } else {
+                        slope
+                    }
+                }
+            }
+            InterpMethod::Monotone => {
+                let (h_before, h_after) = (self.axis.width(index - 1), self.axis.width(index));
+                let (d_before, d_after) =
+                    (self.secant_within(index - 1), self.secant_within(index));
+                if d_before * d_after <= S::zero() {
+                    S::zero()
+                } else {
+                    // Weighted harmonic mean of the neighbouring slopes
+                    let w_before = h_after + h_after + h_before;
+                    let w_after = h_after + h_before + h_before;
+                    (w_before + w_after) / (w_before / d_before + w_after / d_after)
+                }
+            }
+            _ if at_end => self.end_slope(index),
+            _ => {
+                let (h_before, h_after) = (self.axis.width(index - 1), self.axis.width(index));
+                let (d_before, d_after) =
+                    (self.secant_within(index - 1), self.secant_within(index));
+                (h_after * d_before + h_before * d_after) / (h_before + h_after)
+            }
+        }
+    }
+
+    fn hermite(&self, method: InterpMethod, segment: usize, t: S) -> S {
+        let h = self.axis.width(segment);
+        let (p0, p1) = (self.value(segment), self.value(segment + 1));
+        let (m0, m1) = (self.slope(method, segment), self.slope(method, segment + 1));
+        // Beyond the ends, continue along the tangent
+        if t < S::zero() {
+            return p0 + m0 * t * h;
+        }
+        if t > S::one() {
+            return p1 + m1 * (t - S::one()) * h;
+        }
+        let (one, two, three) = (S::one(), S::from(2.0).unwrap(), S::from(3.0).unwrap());
+        let t2 = t * t;
+        let t3 = t2 * t;
+        let h00 = two * t3 - three * t2 + one;
+        let h10 = t3 - two * t2 + t;
+        let h01 = three * t2 - two * t3;
+        let h11 = t3 - t2;
+        h00 * p0 + h10 * h * m0 + h01 * p1 + h11 * h * m1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    fn axis(break_points: &[f64]) -> Axis<8, f64> {
+        Axis::new(0, &OldBlockData::from_vector(break_points)).unwrap()
+    }
+
+    fn interpolate(axis: &Axis<8, f64>, method: InterpMethod, values: &[f64], x: f64) -> f64 {
+        let (position, _) = axis.prelookup(x, Extrapolation::Linear);
+        axis.interpolate(method, position, &mut |index| values[index])
+    }
+
+    #[test]
+    fn test_validate_break_points() {
+        let break_points = |points: &[f64]| {
+            Axis::<4, f64>::new(1, &OldBlockData::from_vector(points)).map(|axis| axis.len())
+        };
+        assert_eq!(break_points(&[0.0, 1.0, 5.0]), Ok(3));
+        assert_eq!(
+            break_points(&[0.0]),
+            Err(BreakpointError::TooFew { axis: 1 })
+        );
+        assert_eq!(
+            break_points(&[0.0, 1.0, 2.0, 3.0, 4.0]),
+            Err(BreakpointError::TooMany { axis: 1 })
+        );
+        assert_eq!(
+            break_points(&[0.0, 1.0, 1.0]),
+            Err(BreakpointError::NotIncreasing { axis: 1, index: 2 })
+        );
+        assert_eq!(
+            break_points(&[0.0, f64::NAN]),
+            Err(BreakpointError::NotFinite { axis: 1, index: 1 })
+        );
+    }
+
+    #[test]
+    fn test_prelookup() {
+        let axis = axis(&[0.0, 1.0, 3.0]);
+        let (position, in_range) = axis.prelookup(2.5, Extrapolation::Clamp);
+        assert_eq!(position.segment, 1);
+        assert_relative_eq!(position.fraction, 0.75);
+        assert!(in_range);
+
+        let (position, in_range) = axis.prelookup(5.0, Extrapolation::Linear);
+        assert_eq!(position.segment, 1);
+        assert_relative_eq!(position.fraction, 2.0);
+        assert!(!in_range);
+        let (position, _) = axis.prelookup(-1.0, Extrapolation::Error);
+        assert_eq!(position.segment, 0);
+        assert_relative_eq!(position.fraction, 0.0);
+
+        let (position, in_range) = axis.index_position(1.75, Extrapolation::Clamp);
+        assert_eq!(position.segment, 1);
+        assert_relative_eq!(position.fraction, 0.75);
+        assert!(in_range);
+        let (position, in_range) = axis.index_position(-0.5, Extrapolation::Linear);
+        assert_eq!(position.segment, 0);
+        assert_relative_eq!(position.fraction, -0.5);
+        assert!(!in_range);
+    }
+
+    #[test]
+    fn test_splines() {
+        // Every method reproduces a straight line, on uneven breakpoints and beyond the ends
+        let line = axis(&[0.0, 1.0, 3.0, 4.0, 7.0]);
+        let values = [1.0, 3.0, 7.0, 9.0, 15.0];
+        for method in [
+            InterpMethod::Linear,
+            InterpMethod::Cubic,
+            InterpMethod::Akima,
+            InterpMethod::Monotone,
+        ] {
+            for x in [-1.0, 0.5, 2.0, 3.5, 6.0, 8.0] {
+                assert_relative_eq!(
+                    interpolate(&line, method, &values, x),
+                    1.0 + 2.0 * x,
+                    epsilon = 1e-12
+                );
+            }
+        }
+
+        // The cubic spline reproduces a parabola between interior breakpoints
+        let uniform = axis(&[0.0, 1.0, 2.0, 3.0, 4.0]);
+        let parabola = [0.0, 1.0, 4.0, 9.0, 16.0];
+        assert_relative_eq!(
+            interpolate(&uniform, InterpMethod::Cubic, &parabola, 1.5),
+            2.25,
+            epsilon = 1e-12
+        );
+
+        // A step: the cubic spline overshoots it, the monotone spline doesn't, and the Akima
+        // spline stays flat away from it
+        let long = axis(&[0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let step = [0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0];
+        assert!(interpolate(&long, InterpMethod::Cubic, &step, 3.5) > 1.0);
+        for x in [0.5, 1.5, 2.5, 3.5, 4.5, 5.5] {
+            let monotone = interpolate(&long, InterpMethod::Monotone, &step, x);
+            assert!((0.0..=1.0).contains(&monotone));
+        }
+        assert_relative_eq!(interpolate(&long, InterpMethod::Akima, &step, 1.5), 0.0);
+        assert_relative_eq!(interpolate(&long, InterpMethod::Akima, &step, 4.5), 1.0);
+
+        assert_relative_eq!(interpolate(&long, InterpMethod::Nearest, &step, 2.5), 1.0);
+        assert_relative_eq!(interpolate(&long, InterpMethod::Nearest, &step, 2.4), 0.0);
+    }
+}
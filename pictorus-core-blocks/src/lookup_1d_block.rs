@@ -1,8 +1,10 @@
 use core::marker::PhantomData;
 
 use corelib_traits::{Matrix, Pass, PassBy, ProcessBlock};
+use log::warn;
 use utils::{BlockData as OldBlockData, FromPass};
 
+use crate::lookup::validate_break_points;
 use crate::traits::{Float, MatrixOps};
 
 /// Block for performing a 1D lookup against a set of break points and data points
@@ -71,6 +73,8 @@ pub struct Parameters<const N: usize, S: Float> {
 }
 
 impl<const N: usize, S: Float> Parameters<N, S> {
+    /// Warns if the break points aren't finite and strictly increasing, since lookups between
+    /// them won't be meaningful
     pub fn new(
         interp_method: &str,
         break_points_u1: &OldBlockData,
@@ -80,6 +84,9 @@ impl<const N: usize, S: Float> Parameters<N, S> {
         for (i, val) in break_points_u1.iter().enumerate() {
             break_points_u1_arr[i] = S::from(*val).expect("Failed to convert break point to float");
         }
+        if let Err(err) = validate_break_points(0, &break_points_u1_arr) {
+            warn!("Invalid 1D lookup break points: {:?}", err);
+        }
 
         let mut data_points_arr = [S::default(); N];
         for (i, val) in data_points.iter().enumerate() {
@@ -220,6 +227,18 @@ mod tests {
         assert_eq!(block.data.scalar(), -1.0);
     }
 
+    #[test]
+    fn test_repeated_break_points_dont_panic() {
+        let ctxt = StubContext::default();
+        let break_points_u1 = OldBlockData::from_vector(&[0.0, 1.0, 1.0]);
+        let data_points = OldBlockData::from_vector(&[-1.0, 1.0, 10.0]);
+        let params = Parameters::new("Linear", &break_points_u1, &data_points);
+
+        let mut block = Lookup1DBlock::<3, f64, f64>::default();
+        let res = block.process(&params, &ctxt, 0.5);
+        assert_eq!(res, 0.0);
+    }
+
     #[test]
     fn test_scalar_nearest() {
         let ctxt = StubContext::default();
@@ -1,8 +1,10 @@
 use core::marker::PhantomData;
 
 use corelib_traits::{Matrix, Pass, PassBy, ProcessBlock};
+use log::warn;
 use utils::{BlockData as OldBlockData, FromPass};
 
+use crate::lookup::validate_break_points;
 use crate::traits::Float;
 
 /// Block for performing a 2D lookup against two sets of break points and a 2D table of data points.
@@ -75,6 +77,8 @@ pub struct Parameters<const NX: usize, const NY: usize, S: Float> {
 }
 
 impl<const NX: usize, const NY: usize, S: Float> Parameters<NX, NY, S> {
+    /// Warns if the break points of either axis aren't finite and strictly increasing, since
+    /// lookups between them won't be meaningful
     pub fn new(
         interp_method: &str,
         break_points_u1: &OldBlockData,
@@ -92,6 +96,11 @@ impl<const NX: usize, const NY: usize, S: Float> Parameters<NX, NY, S> {
             break_points_u2_arr[i] =
                 S::from(*val).expect("Failed to convert Y break point to float");
         }
+        let validation = validate_break_points(0, &break_points_u1_arr)
+            .and(validate_break_points(1, &break_points_u2_arr));
+        if let Err(err) = validation {
+            warn!("Invalid 2D lookup break points: {:?}", err);
+        }
 
         let mut data_points_arr = [[S::default(); NY]; NX];
 
@@ -0,0 +1,368 @@
+use core::str::FromStr;
+
+use corelib_traits::{Matrix, PassBy, ProcessBlock};
+use utils::{BlockData as OldBlockData, IsValid, ParseEnumError};
+
+use crate::lookup::{Axis, BreakpointError, Extrapolation, InterpMethod, Position};
+use crate::traits::Float;
+
+/// What the inputs of a Lookup ND Block are
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LookupInput {
+    /// Coordinates along each axis, which the block finds among the breakpoints
+    Coordinates,
+    /// Fractional breakpoint indices from Prelookup Blocks, which have already done so
+    Prelookup,
+}
+
+impl FromStr for LookupInput {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Coordinates" => Ok(Self::Coordinates),
+            "Prelookup" => Ok(Self::Prelookup),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+/// Parameters for the LookupNDBlock
+pub struct Parameters<const D: usize, const N: usize, const L: usize, S: Float> {
+    interp_method: InterpMethod,
+    extrapolation: Extrapolation,
+    input: LookupInput,
+    axes: [Axis<N, S>; D],
+    /// Data points for the lookup, in row-major order: the last axis varies fastest
+    data_points: [S; L],
+    /// Distance in `data_points` between consecutive breakpoints of each axis
+    strides: [usize; D],
+}
+
+impl<const D: usize, const N: usize, const L: usize, S: Float> Parameters<D, N, L, S> {
+    /// Panics if the breakpoints or the table are invalid. See [`Self::try_new`].
+    pub fn new(
+        interp_method: &str,
+        extrapolation: &str,
+        input: &str,
+        break_points: [&OldBlockData; D],
+        data_points: &OldBlockData,
+    ) -> Self {
+        Self::try_new(
+            interp_method,
+            extrapolation,
+            input,
+            break_points,
+            data_points,
+        )
+        .expect("Invalid lookup table")
+    }
+
+    /// Each axis needs at least two breakpoints, which must be finite and strictly increasing,
+    /// and the table needs one value for each combination of breakpoints
+    pub fn try_new(
+        interp_method: &str,
+        extrapolation: &str,
+        input: &str,
+        break_points: [&OldBlockData; D],
+        data_points: &OldBlockData,
+    ) -> Result<Self, BreakpointError> {
+        let mut axes = [Axis::default(); D];
+        for (index, (axis, points)) in axes.iter_mut().zip(break_points).enumerate() {
+            *axis = Axis::new(index, points)?;
+        }
+
+        let mut strides = [0; D];
+        let mut size = 1;
+        for (stride, axis) in strides.iter_mut().zip(axes.iter()).rev() {
+            *stride = size;
+            size *= axis.len();
+        }
+        let actual = data_points.n_elements();
+        if size != L || actual != L {
+            return Err(BreakpointError::TableSize {
+                expected: size,
+                actual,
+            });
+        }
+        let mut data_points_arr = [S::zero(); L];
+        for (i, point) in data_points_arr.iter_mut().enumerate() {
+            *point = S::from(data_points.at(i)).expect("Failed to convert data point to float");
+        }
+
+        Ok(Self {
+            interp_method: interp_method
+                .parse()
+                .expect("Invalid interp method. Must be Nearest, Linear, Cubic, Akima or Monotone"),
+            extrapolation: extrapolation
+                .parse()
+                .expect("Invalid extrapolation. Must be Clamp, Linear or Error"),
+            input: input
+                .parse()
+                .expect("Invalid lookup input. Must be Coordinates or Prelookup"),
+            axes,
+            data_points: data_points_arr,
+            strides,
+        })
+    }
+
+    /// Interpolates along `axis` and each axis after it, within the slice of the table that
+    /// starts at `offset`
+    fn interpolate(&self, axis: usize, offset: usize, positions: &[Position<S>; D]) -> S {
+        if axis == D {
+            return self.data_points[offset];
+        }
+        let stride = self.strides[axis];
+        self.axes[axis].interpolate(self.interp_method, positions[axis], &mut |index| {
+            self.interpolate(axis + 1, offset + index * stride, positions)
+        })
+    }
+}
+
+/// Block for looking up a value in a table with `D` axes, such as a 3D or 4D map.
+///
+/// The input holds the coordinate along each axis. Each axis has up to `N` breakpoints, which
+/// needn't be evenly spaced, and the table holds `L` values, one for each combination of
+/// breakpoints. The table is interpolated one axis at a time, with any of the methods of
+/// [`InterpMethod`], and inputs beyond the breakpoints are handled as set by
+/// [`Extrapolation`]. The outputs are the value and whether it is valid: it isn't if an input
+/// is NaN, or beyond the breakpoints with `Error` extrapolation.
+///
+/// With `Prelookup` input the inputs are fractional breakpoint indices from Prelookup Blocks
+/// instead of coordinates, so tables sharing an axis only search its breakpoints once.
+pub struct LookupNDBlock<const D: usize, const N: usize, const L: usize, S: Float> {
+    pub data: OldBlockData,
+    output: (S, bool),
+}
+
+impl<const D: usize, const N: usize, const L: usize, S: Float> Default
+    for LookupNDBlock<D, N, L, S>
+{
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::from_scalar(0.0),
+            output: (S::zero(), false),
+        }
+    }
+}
+
+impl<const D: usize, const N: usize, const L: usize, S: Float> ProcessBlock
+    for LookupNDBlock<D, N, L, S>
+{
+    type Inputs = Matrix<D, 1, S>;
+    type Output = (S, bool);
+    type Parameters = Parameters<D, N, L, S>;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn corelib_traits::Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let mut valid = true;
+        let mut positions = [Position {
+            segment: 0,
+            fraction: S::zero(),
+        }; D];
+        for ((position, axis), input) in positions
+            .iter_mut()
+            .zip(parameters.axes.iter())
+            .zip(inputs.data[0])
+        {
+            let (found, in_range) = match parameters.input {
+                LookupInput::Coordinates => axis.prelookup(input, parameters.extrapolation),
+                LookupInput::Prelookup => axis.index_position(input, parameters.extrapolation),
+            };
+            *position = found;
+            valid &= !num_traits::Float::is_nan(input)
+                && (in_range || parameters.extrapolation != Extrapolation::Error);
+        }
+
+        let value = parameters.interpolate(0, 0, &positions);
+        self.output = (value, valid);
+        self.data =
+            OldBlockData::from_scalar(num_traits::ToPrimitive::to_f64(&value).unwrap_or(f64::NAN));
+        self.output
+    }
+}
+
+// TODO: Remove when we remove BlockData
+impl<const D: usize, const N: usize, const L: usize, S: Float> IsValid
+    for LookupNDBlock<D, N, L, S>
+{
+    fn is_valid(&self, _: f64) -> OldBlockData {
+        OldBlockData::scalar_from_bool(self.output.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use corelib_traits_testing::StubContext;
+
+    /// A table of `f` sampled at every combination of the breakpoints
+    fn table<const D: usize>(
+        break_points: [&[f64]; D],
+        f: impl Fn([f64; D]) -> f64,
+    ) -> OldBlockData {
+        let mut values = std::vec::Vec::new();
+        let mut index = [0; D];
+        'outer: loop {
+            values.push(f(core::array::from_fn(|axis| {
+                break_points[axis][index[axis]]
+            })));
+            for axis in (0..D).rev() {
+                index[axis] += 1;
+                if index[axis] < break_points[axis].len() {
+                    continue 'outer;
+                }
+                index[axis] = 0;
+            }
+            break;
+        }
+        OldBlockData::from_vector(&values)
+    }
+
+    #[test]
+    fn test_lookup_4d() {
+        // Consumption over state of charge, temperature, ride mode and speed, which is linear
+        // in each so every method reproduces it between the breakpoints
+        let soc: &[f64] = &[0.0, 20.0, 50.0, 100.0];
+        let temperature: &[f64] = &[-10.0, 0.0, 25.0];
+        let mode: &[f64] = &[0.0, 1.0, 2.0];
+        let speed: &[f64] = &[0.0, 10.0, 20.0, 30.0, 45.0];
+        let consumption = |[soc, temperature, mode, speed]: [f64; 4]| {
+            10.0 - 0.02 * soc - 0.1 * temperature + 2.0 * mode + 0.5 * speed
+        };
+        let data_points = table([soc, temperature, mode, speed], consumption);
+        let break_points = [soc, temperature, mode, speed].map(OldBlockData::from_vector);
+        let context = StubContext::default();
+
+        for method in ["Linear", "Cubic", "Akima", "Monotone"] {
+            let parameters = Parameters::<4, 5, 180, f64>::new(
+                method,
+                "Error",
+                "Coordinates",
+                break_points.each_ref(),
+                &data_points,
+            );
+            let mut block = LookupNDBlock::default();
+            let point = [35.0, 5.0, 1.5, 22.5];
+            let input = Matrix { data: [point] };
+            let (value, valid) = block.process(&parameters, &context, &input);
+            assert_relative_eq!(value, consumption(point), epsilon = 1e-9);
+            assert!(valid);
+            assert!(block.is_valid(0.0).any());
+
+            // Beyond the top speed the value holds, and is flagged
+            let input = Matrix {
+                data: [[35.0, 5.0, 1.5, 60.0]],
+            };
+            let (value, valid) = block.process(&parameters, &context, &input);
+            assert_relative_eq!(value, consumption([35.0, 5.0, 1.5, 45.0]), epsilon = 1e-9);
+            assert!(!valid);
+        }
+
+        let parameters = Parameters::<4, 5, 180, f64>::new(
+            "Linear",
+            "Linear",
+            "Coordinates",
+            break_points.each_ref(),
+            &data_points,
+        );
+        let mut block = LookupNDBlock::default();
+        let point = [-10.0, 30.0, 1.0, 60.0];
+        let (value, valid) = block.process(&parameters, &context, &Matrix { data: [point] });
+        assert_relative_eq!(value, consumption(point), epsilon = 1e-9);
+        assert!(valid);
+        let (_, valid) = block.process(
+            &parameters,
+            &context,
+            &Matrix {
+                data: [[f64::NAN, 30.0, 1.0, 60.0]],
+            },
+        );
+        assert!(!valid);
+    }
+
+    #[test]
+    fn test_lookup_3d_prelookup() {
+        let x: &[f64] = &[0.0, 1.0, 3.0];
+        let y: &[f64] = &[0.0, 2.0];
+        let z: &[f64] = &[-1.0, 0.0, 1.0, 2.0];
+        let data_points = table([x, y, z], |[x, y, z]| x * y + z);
+        let break_points = [x, y, z].map(OldBlockData::from_vector);
+        let context = StubContext::default();
+
+        let parameters = Parameters::<3, 4, 24, f64>::new(
+            "Nearest",
+            "Clamp",
+            "Coordinates",
+            break_points.each_ref(),
+            &data_points,
+        );
+        let mut block = LookupNDBlock::default();
+        let (value, _) = block.process(
+            &parameters,
+            &context,
+            &Matrix {
+                data: [[2.1, 0.9, 0.4]],
+            },
+        );
+        // The nearest breakpoints are (3, 0, 0)
+        assert_eq!(value, 0.0);
+        assert_eq!(block.data.scalar(), 0.0);
+
+        // The same point as fractional indices: 1.55 is 55% of the way from 1 to 3
+        let parameters = Parameters::<3, 4, 24, f64>::new(
+            "Linear",
+            "Clamp",
+            "Prelookup",
+            break_points.each_ref(),
+            &data_points,
+        );
+        let (value, valid) = block.process(
+            &parameters,
+            &context,
+            &Matrix {
+                data: [[1.55, 0.45, 1.4]],
+            },
+        );
+        assert!(valid);
+        assert_relative_eq!(value, 2.1 * 0.9 + 0.4, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_lookup_validation() {
+        let x = OldBlockData::from_vector(&[0.0, 1.0]);
+        let y = OldBlockData::from_vector(&[0.0, 2.0, 1.0]);
+        let data_points = OldBlockData::from_vector(&[0.0; 6]);
+        let result = Parameters::<2, 3, 6, f64>::try_new(
+            "Linear",
+            "Clamp",
+            "Coordinates",
+            [&x, &y],
+            &data_points,
+        );
+        assert_eq!(
+            result.err(),
+            Some(BreakpointError::NotIncreasing { axis: 1, index: 2 })
+        );
+
+        let y = OldBlockData::from_vector(&[0.0, 1.0]);
+        let result = Parameters::<2, 3, 6, f64>::try_new(
+            "Linear",
+            "Clamp",
+            "Coordinates",
+            [&x, &y],
+            &data_points,
+        );
+        assert_eq!(
+            result.err(),
+            Some(BreakpointError::TableSize {
+                expected: 4,
+                actual: 6
+            })
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use core::str::FromStr;
+use corelib_traits::{Context, Matrix, PassBy, ProcessBlock};
+use utils::{BlockData as OldBlockData, FromPass, IsValid, ParseEnumError, StaleTracker};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MagnetometerSensor {
+    Qmc5883,
+}
+
+impl FromStr for MagnetometerSensor {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "QMC5883" => Ok(Self::Qmc5883),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+/// Parameters for the Magnetometer Block
+pub struct Parameters {
+    pub sensor: MagnetometerSensor,
+    /// I2C address of the sensor
+    pub address: u8,
+    /// Requested full scale in gauss. The sensor uses the smallest range covering it.
+    pub range_gauss: f64,
+    pub sample_rate_hz: f64,
+    pub oversampling: u16,
+    /// Check that a sample is plausible for the earth's field after configuring the sensor.
+    /// A failed self-test keeps the block stale.
+    pub run_self_test: bool,
+    /// Hard iron offset in µT, subtracted from each sample
+    pub hard_iron: [f64; 3],
+    /// Soft iron correction matrix in row-major order, applied after the hard iron offset
+    pub soft_iron: [f64; 9],
+    stale_age_ms: f64,
+}
+
+impl Parameters {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        sensor: &str,
+        address: f64,
+        range_gauss: f64,
+        sample_rate_hz: f64,
+        oversampling: f64,
+        run_self_test: bool,
+        hard_iron: [f64; 3],
+        soft_iron: [f64; 9],
+        stale_age_ms: f64,
+    ) -> Self {
+        Self {
+            sensor: sensor.parse().expect("Invalid magnetometer sensor"),
+            address: address as u8,
+            range_gauss,
+            sample_rate_hz,
+            oversampling: oversampling as u16,
+            run_self_test,
+            hard_iron,
+            soft_iron,
+            stale_age_ms,
+        }
+    }
+}
+
+/// The Magnetometer Block calibrates samples from a magnetometer driver.
+///
+/// The inputs are the magnetic field in µT and the number of samples the driver read, which is
+/// zero when no new sample was ready. The output is the field with the hard and soft iron
+/// corrections applied. It holds its last value, and the block goes stale, while no new
+/// samples arrive.
+pub struct MagnetometerBlock {
+    pub data: OldBlockData,
+    pub stale_check: StaleTracker,
+    field: Matrix<1, 3, f64>,
+    previous_stale_check_time_ms: f64,
+}
+
+impl Default for MagnetometerBlock {
+    fn default() -> Self {
+        Self {
+            data: <OldBlockData as FromPass<Matrix<1, 3, f64>>>::from_pass(&Matrix::zeroed()),
+            stale_check: StaleTracker::from_ms(0.0),
+            field: Matrix::zeroed(),
+            previous_stale_check_time_ms: 0.0,
+        }
+    }
+}
+
+impl IsValid for MagnetometerBlock {
+    fn is_valid(&self, app_time_s: f64) -> OldBlockData {
+        self.stale_check.is_valid(app_time_s)
+    }
+}
+
+impl ProcessBlock for MagnetometerBlock {
+    type Parameters = Parameters;
+    type Inputs = (Matrix<1, 3, f64>, f64);
+    type Output = Matrix<1, 3, f64>;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        if self.previous_stale_check_time_ms != parameters.stale_age_ms {
+            self.stale_check = StaleTracker::from_ms(parameters.stale_age_ms);
+            self.previous_stale_check_time_ms = parameters.stale_age_ms;
+        }
+
+        let (field, samples) = inputs;
+        if samples > 0.0 {
+            let offset: [f64; 3] =
+                core::array::from_fn(|i| field.data[i][0] - parameters.hard_iron[i]);
+            for (row, value) in self.field.data.iter_mut().enumerate() {
+                let soft_iron = &parameters.soft_iron[row * 3..row * 3 + 3];
+                value[0] = soft_iron.iter().zip(offset).map(|(s, o)| s * o).sum();
+            }
+            self.stale_check.mark_updated(context.time().as_secs_f64());
+            self.data = <OldBlockData as FromPass<Matrix<1, 3, f64>>>::from_pass(&self.field);
+        }
+
+        &self.field
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::time::Duration;
+    use corelib_traits_testing::StubRuntime;
+
+    fn vector(values: [f64; 3]) -> Matrix<1, 3, f64> {
+        Matrix {
+            data: values.map(|v| [v]),
+        }
+    }
+
+    #[test]
+    fn test_magnetometer_block_calibration() {
+        let parameters = Parameters::new(
+            "QMC5883",
+            0x0D as f64,
+            8.0,
+            50.0,
+            512.0,
+            false,
+            [10.0, -5.0, 0.0],
+            [1.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.5, 0.0, 1.0],
+            100.0,
+        );
+        let mut runtime = StubRuntime::default();
+        let mut block = MagnetometerBlock::default();
+
+        let output = block.process(
+            &parameters,
+            &runtime.context(),
+            (&vector([30.0, 0.0, -40.0]), 1.0),
+        );
+        assert_eq!(output, &vector([20.0, 10.0, -30.0]));
+        assert_eq!(block.data.get_data().as_slice(), [20.0, 10.0, -30.0]);
+        let valid = block.is_valid(runtime.context().time().as_secs_f64());
+        assert_eq!(valid.scalar(), 1.0);
+
+        runtime.set_time(Duration::from_secs(1));
+        let output = block.process(&parameters, &runtime.context(), (&Matrix::zeroed(), 0.0));
+        assert_eq!(output, &vector([20.0, 10.0, -30.0]));
+        let valid = block.is_valid(runtime.context().time().as_secs_f64());
+        assert_eq!(valid.scalar(), 0.0);
+    }
+}
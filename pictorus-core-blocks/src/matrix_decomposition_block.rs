@@ -0,0 +1,438 @@
+use corelib_traits::{Matrix, Pass, PassBy, ProcessBlock};
+use nalgebra::{
+    allocator::Allocator, ArrayStorage, Const, DefaultAllocator, DimDiff, DimMin, DimSub, SMatrix,
+    U1,
+};
+use pictorus_nalgebra_interop::MatrixExt;
+use utils::{BlockData as OldBlockData, FromPass, IsValid};
+
+use crate::matrix_inverse_block::Svd;
+use crate::traits::Float;
+
+/// Most decompositions converge within a few iterations per row; this only stops a pathological
+/// input from stalling the tick.
+pub(crate) const MAX_ITERATIONS: usize = 1000;
+
+/// Which decomposition to perform. Like [`Method`](crate::matrix_inverse_block::Method), these
+/// are types so each decomposition can have its own outputs and constraints on the dimensions.
+pub trait Decomposition {}
+
+/// LU decomposition with partial pivoting of a square matrix
+pub struct Lu;
+impl Decomposition for Lu {}
+
+/// QR decomposition of a matrix with at least as many rows as columns
+pub struct Qr;
+impl Decomposition for Qr {}
+
+/// Cholesky decomposition of a symmetric positive definite matrix
+pub struct Cholesky;
+impl Decomposition for Cholesky {}
+
+/// Thin singular value decomposition of a matrix with at least as many rows as columns
+impl Decomposition for Svd {}
+
+/// Eigenvalues and eigenvectors of a symmetric matrix
+pub struct SymmetricEigen;
+impl Decomposition for SymmetricEigen {}
+
+#[derive(Debug, Clone, Default)]
+pub struct Parameters {}
+
+impl Parameters {
+    pub fn new() -> Parameters {
+        Parameters {}
+    }
+}
+
+/// Block for decomposing a matrix into factors.
+///
+/// The outputs depend on the decomposition, and always end with whether it succeeded:
+/// - `Lu`: `(L, U, P, valid)` with `P·A = L·U`, `L` unit lower triangular and `P` a permutation
+///   matrix. Invalid if the matrix is singular, though the factors are still output.
+/// - `Qr`: `(Q, R, valid)` with `A = Q·R`, `Q` having orthonormal columns and `R` upper
+///   triangular. Invalid if the columns are linearly dependent, though the factors are still
+///   output.
+/// - `Cholesky`: `(L, valid)` with `A = L·Lᵀ` and `L` lower triangular. Invalid, with `L` zero,
+///   if the matrix isn't positive definite. Only the lower triangle of `A` is used.
+/// - `Svd`: `(U, S, V, valid)` with `A = U·diag(S)·Vᵀ`, the singular values `S` in descending
+///   order and `U` and `V` having orthonormal columns.
+/// - `SymmetricEigen`: `(values, vectors, valid)` with the eigenvalues in ascending order and
+///   the matching unit eigenvectors in the columns of `vectors`. Only the lower triangle of `A`
+///   is used.
+///
+/// A matrix with non-finite elements is always invalid, as is an SVD or eigendecomposition that
+/// fails to converge; the factors are then zero.
+pub struct MatrixDecompositionBlock<T: Decompose<M>, M: Decomposition> {
+    pub data: OldBlockData,
+    output: T::Output,
+}
+
+impl<T, M> Default for MatrixDecompositionBlock<T, M>
+where
+    M: Decomposition,
+    T: Decompose<M>,
+{
+    fn default() -> Self {
+        let output = T::Output::default();
+        Self {
+            data: T::data(&output),
+            output,
+        }
+    }
+}
+
+impl<T, M> ProcessBlock for MatrixDecompositionBlock<T, M>
+where
+    M: Decomposition,
+    T: Decompose<M>,
+{
+    type Inputs = T;
+    type Output = T::Output;
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        _parameters: &Self::Parameters,
+        _context: &dyn corelib_traits::Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        self.output = T::decompose(input);
+        self.data = T::data(&self.output);
+        self.output.as_by()
+    }
+}
+
+// TODO: Remove when we remove BlockData
+impl<T: Decompose<M>, M: Decomposition> IsValid for MatrixDecompositionBlock<T, M> {
+    fn is_valid(&self, _: f64) -> OldBlockData {
+        OldBlockData::scalar_from_bool(T::is_valid(&self.output))
+    }
+}
+
+pub trait Decompose<M: Decomposition>: Pass {
+    type Output: Pass + Default;
+
+    fn decompose(input: PassBy<'_, Self>) -> Self::Output;
+
+    /// The first factor, which the block keeps as its `data`
+    fn data(output: &Self::Output) -> OldBlockData;
+
+    fn is_valid(output: &Self::Output) -> bool;
+}
+
+/// The matrix, unless any element isn't finite
+pub(crate) fn to_finite_nalgebra<const R: usize, const C: usize, F: Float>(
+    matrix: &Matrix<R, C, F>,
+) -> Option<SMatrix<F, R, C>> {
+    let matrix = SMatrix::from_array_storage(ArrayStorage(matrix.data));
+    matrix
+        .iter()
+        .all(|value| num_traits::Float::is_finite(*value))
+        .then_some(matrix)
+}
+
+/// Copies the elements of a factor, in column-major order. The dimensions of factors depend
+/// on the smaller dimension of the input, which nalgebra can't resolve for generic dimensions.
+fn copy_factor<'a, const R: usize, const C: usize, F: Float>(
+    values: impl Iterator<Item = &'a F>,
+) -> Matrix<R, C, F> {
+    let mut matrix = Matrix::zeroed();
+    for (element, value) in matrix.data.as_flattened_mut().iter_mut().zip(values) {
+        *element = *value;
+    }
+    matrix
+}
+
+/// Whether a triangular factor has a pivot too small, relative to the largest, to divide by
+fn rank_deficient<const N: usize, const C: usize, F: Float>(factor: &Matrix<N, C, F>) -> bool {
+    let diagonal = || (0..N.min(C)).map(|i| num_traits::Float::abs(factor.data[i][i]));
+    let largest = diagonal().fold(F::zero(), num_traits::Float::max);
+    let tolerance = largest * F::from(N.max(C)).unwrap() * F::EPSILON;
+    diagonal().any(|value| value <= tolerance)
+}
+
+impl<const N: usize, F: Float> Decompose<Lu> for Matrix<N, N, F>
+where
+    Const<N>: DimMin<Const<N>, Output = Const<N>>,
+    OldBlockData: FromPass<Matrix<N, N, F>>,
+{
+    type Output = (Matrix<N, N, F>, Matrix<N, N, F>, Matrix<N, N, F>, bool);
+
+    fn decompose(input: PassBy<'_, Self>) -> Self::Output {
+        let Some(input) = to_finite_nalgebra(input) else {
+            return Self::Output::default();
+        };
+        let (p, l, u) = nalgebra::LU::new(input).unpack();
+        let mut permutation = SMatrix::<F, N, N>::identity();
+        p.permute_rows(&mut permutation);
+        let u = copy_factor(u.iter());
+        (
+            copy_factor(l.iter()),
+            u,
+            Matrix::from_view(&permutation.as_view()),
+            !rank_deficient(&u),
+        )
+    }
+
+    fn data(output: &Self::Output) -> OldBlockData {
+        OldBlockData::from_pass(&output.0)
+    }
+
+    fn is_valid(output: &Self::Output) -> bool {
+        output.3
+    }
+}
+
+impl<const R: usize, const C: usize, F: Float> Decompose<Qr> for Matrix<R, C, F>
+where
+    Const<R>: DimMin<Const<C>, Output = Const<C>>,
+    OldBlockData: FromPass<Matrix<R, C, F>>,
+{
+    type Output = (Matrix<R, C, F>, Matrix<C, C, F>, bool);
+
+    fn decompose(input: PassBy<'_, Self>) -> Self::Output {
+        let Some(input) = to_finite_nalgebra(input) else {
+            return Self::Output::default();
+        };
+        let qr = nalgebra::QR::new(input);
+        let r = copy_factor(qr.r().iter());
+        (copy_factor(qr.q().iter()), r, !rank_deficient(&r))
+    }
+
+    fn data(output: &Self::Output) -> OldBlockData {
+        OldBlockData::from_pass(&output.0)
+    }
+
+    fn is_valid(output: &Self::Output) -> bool {
+        output.2
+    }
+}
+
+impl<const N: usize, F: Float> Decompose<Cholesky> for Matrix<N, N, F>
+where
+    OldBlockData: FromPass<Matrix<N, N, F>>,
+{
+    type Output = (Matrix<N, N, F>, bool);
+
+    fn decompose(input: PassBy<'_, Self>) -> Self::Output {
+        to_finite_nalgebra(input)
+            .and_then(nalgebra::Cholesky::new)
+            .map(|cholesky| (Matrix::from_view(&cholesky.l().as_view()), true))
+            .unwrap_or_default()
+    }
+
+    fn data(output: &Self::Output) -> OldBlockData {
+        OldBlockData::from_pass(&output.0)
+    }
+
+    fn is_valid(output: &Self::Output) -> bool {
+        output.1
+    }
+}
+
+impl<const R: usize, const C: usize, F: Float> Decompose<Svd> for Matrix<R, C, F>
+where
+    Const<R>: DimMin<Const<C>, Output = Const<C>>,
+    Const<C>: DimSub<U1>,
+    DefaultAllocator: Allocator<DimDiff<Const<C>, U1>>,
+    OldBlockData: FromPass<Matrix<R, C, F>>,
+{
+    type Output = (Matrix<R, C, F>, Matrix<C, 1, F>, Matrix<C, C, F>, bool);
+
+    fn decompose(input: PassBy<'_, Self>) -> Self::Output {
+        let svd = to_finite_nalgebra(input).and_then(|input| {
+            nalgebra::SVD::<F, Const<R>, Const<C>>::try_new(
+                input,
+                true,
+                true,
+                F::EPSILON,
+                MAX_ITERATIONS,
+            )
+        });
+        let Some(svd) = svd else {
+            return Self::Output::default();
+        };
+        let (Some(u), Some(v_t)) = (svd.u, svd.v_t) else {
+            return Self::Output::default();
+        };
+        (
+            copy_factor(u.iter()),
+            copy_factor(svd.singular_values.iter()),
+            copy_factor(v_t.transpose().iter()),
+            true,
+        )
+    }
+
+    fn data(output: &Self::Output) -> OldBlockData {
+        OldBlockData::from_pass(&output.0)
+    }
+
+    fn is_valid(output: &Self::Output) -> bool {
+        output.3
+    }
+}
+
+impl<const N: usize, F: Float> Decompose<SymmetricEigen> for Matrix<N, N, F>
+where
+    Const<N>: DimSub<U1>,
+    DefaultAllocator: Allocator<DimDiff<Const<N>, U1>>,
+    OldBlockData: FromPass<Matrix<N, 1, F>>,
+{
+    type Output = (Matrix<N, 1, F>, Matrix<N, N, F>, bool);
+
+    fn decompose(input: PassBy<'_, Self>) -> Self::Output {
+        let eigen = to_finite_nalgebra(input).and_then(|input| {
+            nalgebra::SymmetricEigen::<F, Const<N>>::try_new(input, F::EPSILON, MAX_ITERATIONS)
+        });
+        let Some(eigen) = eigen else {
+            return Self::Output::default();
+        };
+
+        let mut order: [usize; N] = core::array::from_fn(|i| i);
+        order.sort_unstable_by(|a, b| {
+            eigen.eigenvalues[*a]
+                .partial_cmp(&eigen.eigenvalues[*b])
+                .unwrap_or(core::cmp::Ordering::Equal)
+        });
+        let mut values = Matrix::<N, 1, F>::zeroed();
+        let mut vectors = Matrix::<N, N, F>::zeroed();
+        for (column, index) in order.into_iter().enumerate() {
+            values.data[0][column] = eigen.eigenvalues[index];
+            for (element, value) in vectors.data[column]
+                .iter_mut()
+                .zip(eigen.eigenvectors.column(index).iter())
+            {
+                *element = *value;
+            }
+        }
+        (values, vectors, true)
+    }
+
+    fn data(output: &Self::Output) -> OldBlockData {
+        OldBlockData::from_pass(&output.0)
+    }
+
+    fn is_valid(output: &Self::Output) -> bool {
+        output.2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use corelib_traits_testing::StubContext;
+
+    fn product<const R: usize, const K: usize, const C: usize>(
+        a: &Matrix<R, K, f64>,
+        b: &Matrix<K, C, f64>,
+    ) -> Matrix<R, C, f64> {
+        Matrix::from_view(&(a.as_view() * b.as_view()).as_view())
+    }
+
+    #[test]
+    fn test_lu_and_qr() {
+        let params = Parameters::new();
+        let ctxt = StubContext::default();
+        let a = Matrix {
+            data: [[0.0, 2.0, 4.0], [1.0, 1.0, 3.0], [2.0, 7.0, 1.0]],
+        };
+
+        let mut block = MatrixDecompositionBlock::<Matrix<3, 3, f64>, Lu>::default();
+        let (l, u, p, valid) = block.process(&params, &ctxt, &a);
+        assert!(valid);
+        // Pivoting moves the zero off the diagonal
+        assert_relative_eq!(p.data[0][..], [0.0, 1.0, 0.0][..]);
+        assert_relative_eq!(
+            product(p, &a).data.as_flattened(),
+            product(l, u).data.as_flattened(),
+            epsilon = 1e-12
+        );
+        assert_eq!(l.data[1][0], 0.0);
+        assert_eq!(u.data[0][1], 0.0);
+
+        let singular = Matrix {
+            data: [[1.0, 2.0, 3.0], [2.0, 4.0, 6.0], [0.0, 1.0, 1.0]],
+        };
+        assert!(!block.process(&params, &ctxt, &singular).3);
+        assert!(!block.is_valid(0.0).any());
+
+        let mut block = MatrixDecompositionBlock::<Matrix<4, 2, f64>, Qr>::default();
+        let tall = Matrix {
+            data: [[1.0, 2.0, 3.0, 4.0], [1.0, -1.0, 2.0, 0.5]],
+        };
+        let (q, r, valid) = block.process(&params, &ctxt, &tall);
+        assert!(valid);
+        assert_relative_eq!(
+            product(q, r).data.as_flattened(),
+            tall.data.as_flattened(),
+            epsilon = 1e-12
+        );
+        assert_eq!(r.data[0][1], 0.0);
+    }
+
+    #[test]
+    fn test_cholesky_svd_and_eigen() {
+        let params = Parameters::new();
+        let ctxt = StubContext::default();
+        let a = Matrix {
+            data: [[4.0, 2.0], [2.0, 3.0]],
+        };
+
+        let mut block = MatrixDecompositionBlock::<Matrix<2, 2, f64>, Cholesky>::default();
+        let (l, valid) = block.process(&params, &ctxt, &a);
+        assert!(valid);
+        assert_relative_eq!(
+            l.data.as_flattened(),
+            [2.0, 1.0, 0.0, 2f64.sqrt()].as_slice()
+        );
+        let indefinite = Matrix {
+            data: [[1.0, 2.0], [2.0, 1.0]],
+        };
+        assert!(!block.process(&params, &ctxt, &indefinite).1);
+
+        let mut block = MatrixDecompositionBlock::<Matrix<2, 2, f64>, SymmetricEigen>::default();
+        let (values, vectors, valid) = block.process(&params, &ctxt, &a);
+        assert!(valid);
+        let root = 17f64.sqrt();
+        assert_relative_eq!(
+            values.data[0][..],
+            [(7.0 - root) / 2.0, (7.0 + root) / 2.0][..],
+            epsilon = 1e-12
+        );
+        // A·v = λ·v for each column
+        for (value, vector) in values.data[0].iter().zip(vectors.data) {
+            let v = Matrix { data: [vector] };
+            let av = product(&a, &v);
+            for (av, v) in av.data[0].iter().zip(vector) {
+                assert_relative_eq!(*av, value * v, epsilon = 1e-12);
+            }
+        }
+
+        let mut block = MatrixDecompositionBlock::<Matrix<3, 2, f64>, Svd>::default();
+        let tall = Matrix {
+            data: [[3.0, 0.0, 0.0], [0.0, 0.0, -5.0]],
+        };
+        let (u, s, v, valid) = block.process(&params, &ctxt, &tall);
+        assert!(valid);
+        assert_relative_eq!(s.data[0][..], [5.0, 3.0][..], epsilon = 1e-12);
+        let mut us = *u;
+        for (column, sigma) in us.data.iter_mut().zip(s.data[0]) {
+            column.iter_mut().for_each(|value| *value *= sigma);
+        }
+        let v_t = Matrix::from_view(&v.as_view().transpose().as_view());
+        assert_relative_eq!(
+            product(&us, &v_t).data.as_flattened(),
+            tall.data.as_flattened(),
+            epsilon = 1e-12
+        );
+
+        let nan = Matrix {
+            data: [[f64::NAN, 0.0, 0.0], [0.0, 1.0, 0.0]],
+        };
+        let (_, s, _, valid) = block.process(&params, &ctxt, &nan);
+        assert!(!valid);
+        assert_eq!(s.data[0], [0.0, 0.0]);
+    }
+}
@@ -0,0 +1,335 @@
+extern crate alloc;
+use core::ops::Range;
+
+use alloc::{vec, vec::Vec};
+use corelib_traits::{ByteSliceSignal, Context, Matrix, Pass, PassBy, ProcessBlock};
+use utils::byte_data::mavlink::{MavlinkDecoder, MavlinkStats, MessageDef, SigningKey};
+use utils::{BlockData as OldBlockData, IsValid, StaleTracker};
+
+/// Parameters for the MAVLink Decode Block
+pub struct Parameters {
+    /// The message to decode, see [`MessageDef::from_str`] for how it can be specified
+    pub message: MessageDef,
+    /// The range of message values for each output, in output order
+    pub outputs: Vec<Range<usize>>,
+    /// Only accept messages from this system, or any system if `None`
+    pub system_id: Option<u8>,
+    /// Only accept messages from this component, or any component if `None`
+    pub component_id: Option<u8>,
+    /// If set, only correctly signed messages are accepted
+    pub signing_key: Option<SigningKey>,
+    /// The age in milliseconds before the data is considered stale. Stale values are still
+    /// output until a new message is received.
+    pub stale_age_ms: f64,
+}
+
+impl Parameters {
+    /// `fields` names the message field for each output. A `system_id` or `component_id` of 0
+    /// accepts messages from any system or component, and an empty `signing_key` disables
+    /// signature checks. Otherwise `signing_key` is the 32 byte secret key as hex.
+    pub fn new<S: AsRef<str>>(
+        message: &str,
+        fields: &[S],
+        system_id: f64,
+        component_id: f64,
+        signing_key: &str,
+        stale_age_ms: f64,
+    ) -> Self {
+        let message = message
+            .parse::<MessageDef>()
+            .expect("Invalid MAVLink message");
+        let outputs = fields
+            .iter()
+            .map(|f| {
+                message
+                    .field_values(f.as_ref())
+                    .expect("Unknown MAVLink message field")
+            })
+            .collect();
+        Self {
+            message,
+            outputs,
+            system_id: (system_id > 0.0).then_some(system_id as u8),
+            component_id: (component_id > 0.0).then_some(component_id as u8),
+            signing_key: (!signing_key.is_empty())
+                .then(|| SigningKey::from_hex(signing_key).expect("Invalid MAVLink signing key")),
+            stale_age_ms,
+        }
+    }
+}
+
+/// The MAVLink Decode Block extracts MAVLink v2 messages from a byte stream, such as the output
+/// of a `SerialReceiveBlock` or `UdpReceiveBlock`, and outputs the selected message fields.
+/// Frames split across several reads are reassembled and corrupt frames are skipped.
+///
+/// Scalar fields are output as scalars and array fields as vectors. The most recently received
+/// values are held in between messages, and are valid until no message has been received for
+/// the stale age.
+pub struct MavlinkDecodeBlock<T: Apply> {
+    pub data: Vec<OldBlockData>,
+    buffer: T,
+    decoder: Option<MavlinkDecoder>,
+    values: Vec<f64>,
+    stale_check: Option<StaleTracker>,
+}
+
+impl<T: Apply> Default for MavlinkDecodeBlock<T> {
+    fn default() -> Self {
+        let buffer = T::default();
+        let data = buffer.as_old_block_data();
+        Self {
+            data,
+            buffer,
+            decoder: None,
+            values: Vec::new(),
+            stale_check: None,
+        }
+    }
+}
+
+impl<T: Apply> MavlinkDecodeBlock<T> {
+    /// Frame and error counts since the block started
+    pub fn stats(&self) -> MavlinkStats {
+        self.decoder
+            .as_ref()
+            .map(|d| *d.stats())
+            .unwrap_or_default()
+    }
+}
+
+impl<T: Apply> ProcessBlock for MavlinkDecodeBlock<T> {
+    type Inputs = ByteSliceSignal;
+    type Output = T;
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let message = &parameters.message;
+        let decoder = self
+            .decoder
+            .get_or_insert_with(|| MavlinkDecoder::new(&[message], parameters.signing_key));
+
+        let values = &mut self.values;
+        values.resize(message.value_count(), 0.0);
+        let mut received = false;
+        decoder.decode(inputs, |frame| {
+            let from_system = parameters.system_id.is_none_or(|id| id == frame.system_id);
+            let from_component = parameters
+                .component_id
+                .is_none_or(|id| id == frame.component_id);
+            if from_system && from_component {
+                message.unpack_payload(frame.payload, values);
+                received = true;
+            }
+        });
+
+        if received {
+            T::apply(&mut self.buffer, &self.values, &parameters.outputs);
+            self.data = self.buffer.as_old_block_data();
+            self.stale_check
+                .get_or_insert(StaleTracker::from_ms(parameters.stale_age_ms))
+                .mark_updated(context.time().as_secs_f64());
+        }
+        self.buffer.as_by()
+    }
+}
+
+impl<T: Apply> IsValid for MavlinkDecodeBlock<T> {
+    fn is_valid(&self, app_time_s: f64) -> OldBlockData {
+        match self.stale_check {
+            Some(ref tracker) => tracker.is_valid(app_time_s),
+            None => OldBlockData::scalar_from_bool(false),
+        }
+    }
+}
+
+/// A type a message field can be output as
+pub trait FromMavlink: Pass + Default {
+    fn from_values(values: &[f64]) -> Self;
+
+    /// Just needed to support the OldBlockData
+    fn as_old_block_data(&self) -> OldBlockData;
+}
+
+impl FromMavlink for f64 {
+    fn from_values(values: &[f64]) -> Self {
+        values.first().copied().unwrap_or_default()
+    }
+
+    fn as_old_block_data(&self) -> OldBlockData {
+        OldBlockData::from_scalar(*self)
+    }
+}
+
+impl<const NROWS: usize, const NCOLS: usize> FromMavlink for Matrix<NROWS, NCOLS, f64> {
+    fn from_values(values: &[f64]) -> Self {
+        let mut output = Self::zeroed();
+        for (dest, value) in output.data.iter_mut().flatten().zip(values) {
+            *dest = *value;
+        }
+        output
+    }
+
+    fn as_old_block_data(&self) -> OldBlockData {
+        <OldBlockData as utils::FromPass<Self>>::from_pass(self)
+    }
+}
+
+pub trait Apply: Default + Pass {
+    /// Sets each output from the message values in its range
+    fn apply(dest: &mut Self, values: &[f64], outputs: &[Range<usize>]);
+
+    fn as_old_block_data(&self) -> Vec<OldBlockData>;
+}
+
+impl<T: FromMavlink> Apply for T {
+    fn apply(dest: &mut Self, values: &[f64], outputs: &[Range<usize>]) {
+        *dest = T::from_values(&values[outputs[0].clone()]);
+    }
+
+    fn as_old_block_data(&self) -> Vec<OldBlockData> {
+        vec![FromMavlink::as_old_block_data(self)]
+    }
+}
+
+macro_rules! impl_apply {
+    ($($t:ident => $idx:tt),+) => {
+        impl<$($t: FromMavlink),+> Apply for ($($t,)+) {
+            fn apply(dest: &mut Self, values: &[f64], outputs: &[Range<usize>]) {
+                $(dest.$idx = $t::from_values(&values[outputs[$idx].clone()]);)+
+            }
+
+            fn as_old_block_data(&self) -> Vec<OldBlockData> {
+                vec![$(self.$idx.as_old_block_data()),+]
+            }
+        }
+    };
+}
+
+impl_apply!(T1 => 0, T2 => 1);
+impl_apply!(T1 => 0, T2 => 1, T3 => 2);
+impl_apply!(T1 => 0, T2 => 1, T3 => 2, T4 => 3);
+impl_apply!(T1 => 0, T2 => 1, T3 => 2, T4 => 3, T5 => 4);
+impl_apply!(T1 => 0, T2 => 1, T3 => 2, T4 => 3, T5 => 4, T6 => 5);
+impl_apply!(T1 => 0, T2 => 1, T3 => 2, T4 => 3, T5 => 4, T6 => 5, T7 => 6);
+impl_apply!(T1 => 0, T2 => 1, T3 => 2, T4 => 3, T5 => 4, T6 => 5, T7 => 6, T8 => 7);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::time::Duration;
+    use corelib_traits_testing::StubRuntime;
+    use utils::byte_data::mavlink::MavlinkEncoder;
+
+    #[test]
+    fn test_decode_attitude_split_across_ticks() {
+        let mut runtime = StubRuntime::default();
+        let parameters = Parameters::new("ATTITUDE", &["roll", "yaw"], 0.0, 0.0, "", 100.0);
+        let mut block = MavlinkDecodeBlock::<(f64, f64)>::default();
+
+        let mut stream = Vec::new();
+        MavlinkEncoder::new(1, 1, None).encode(
+            &parameters.message,
+            &[10.0, 0.5, 0.25, -1.5, 0.0, 0.0, 0.0],
+            0,
+            &mut stream,
+        );
+        let (first, second) = stream.split_at(8);
+
+        let output = block.process(&parameters, &runtime.context(), first);
+        assert_eq!(output, (0.0, 0.0));
+        assert!(!block.is_valid(0.0).any());
+
+        runtime.tick();
+        let output = block.process(&parameters, &runtime.context(), second);
+        assert_eq!(output, (0.5, -1.5));
+        assert!(block.is_valid(0.1).any());
+        assert_eq!(
+            block.data,
+            vec![
+                OldBlockData::from_scalar(0.5),
+                OldBlockData::from_scalar(-1.5)
+            ]
+        );
+
+        // Values are held until the data goes stale
+        runtime.set_time(Duration::from_millis(150));
+        let output = block.process(&parameters, &runtime.context(), &[]);
+        assert_eq!(output, (0.5, -1.5));
+        assert!(block.is_valid(0.15).any());
+        runtime.set_time(Duration::from_millis(300));
+        let output = block.process(&parameters, &runtime.context(), &[]);
+        assert_eq!(output, (0.5, -1.5));
+        assert!(!block.is_valid(0.3).any());
+        assert_eq!(block.stats().frames, 1);
+    }
+
+    #[test]
+    fn test_decode_array_field_and_system_filter() {
+        let runtime = StubRuntime::default();
+        let parameters = Parameters::new(
+            "42000:77:flags=uint8_t[3],count=uint16_t",
+            &["flags", "count"],
+            2.0,
+            0.0,
+            "",
+            100.0,
+        );
+        let mut block = MavlinkDecodeBlock::<(Matrix<1, 3, f64>, f64)>::default();
+
+        let mut stream = Vec::new();
+        MavlinkEncoder::new(1, 1, None).encode(
+            &parameters.message,
+            &[1.0, 2.0, 3.0, 4.0],
+            0,
+            &mut stream,
+        );
+        MavlinkEncoder::new(2, 1, None).encode(
+            &parameters.message,
+            &[5.0, 6.0, 7.0, 8.0],
+            0,
+            &mut stream,
+        );
+
+        let (flags, count) = block.process(&parameters, &runtime.context(), &stream);
+        assert_eq!(flags.data, [[5.0], [6.0], [7.0]]);
+        assert_eq!(count, 8.0);
+        assert!(block.is_valid(0.0).any());
+    }
+
+    #[test]
+    fn test_decode_requires_signature() {
+        let runtime = StubRuntime::default();
+        let key = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+        let parameters = Parameters::new("HEARTBEAT", &["custom_mode"], 0.0, 0.0, key, 100.0);
+        let mut block = MavlinkDecodeBlock::<f64>::default();
+
+        let mut stream = Vec::new();
+        MavlinkEncoder::new(1, 1, None).encode(
+            &parameters.message,
+            &[0.0, 0.0, 0.0, 7.0],
+            0,
+            &mut stream,
+        );
+        let output = block.process(&parameters, &runtime.context(), &stream);
+        assert_eq!(output, 0.0);
+        assert!(!block.is_valid(0.0).any());
+        assert_eq!(block.stats().signature_errors, 1);
+
+        stream.clear();
+        let signing = Some((SigningKey::from_hex(key).unwrap(), 0));
+        MavlinkEncoder::new(1, 1, signing).encode(
+            &parameters.message,
+            &[0.0, 0.0, 0.0, 7.0],
+            0,
+            &mut stream,
+        );
+        let output = block.process(&parameters, &runtime.context(), &stream);
+        assert_eq!(output, 7.0);
+        assert!(block.is_valid(0.0).any());
+    }
+}
@@ -0,0 +1,269 @@
+extern crate alloc;
+use core::ops::Range;
+
+use alloc::vec::Vec;
+use corelib_traits::{ByteSliceSignal, Context, Matrix, Pass, PassBy, ProcessBlock};
+use utils::byte_data::mavlink::{MavlinkEncoder, MessageDef, SigningKey};
+use utils::BlockData as OldBlockData;
+
+/// Unix time of 2015-01-01 00:00:00 UTC, where MAVLink signing timestamps start
+const MAVLINK_EPOCH_S: f64 = 1_420_070_400.0;
+
+/// Parameters for the MAVLink Encode Block
+pub struct Parameters {
+    /// The message to encode, see [`MessageDef::from_str`] for how it can be specified
+    pub message: MessageDef,
+    /// The range of message values set by each input, in input order
+    pub inputs: Vec<Range<usize>>,
+    pub system_id: u8,
+    pub component_id: u8,
+    /// If set, messages are signed with the key and link id
+    pub signing: Option<(SigningKey, u8)>,
+}
+
+impl Parameters {
+    /// `fields` names the message field set by each input. An empty `signing_key` disables
+    /// signing, otherwise it is the 32 byte secret key as hex.
+    pub fn new<S: AsRef<str>>(
+        message: &str,
+        fields: &[S],
+        system_id: f64,
+        component_id: f64,
+        signing_key: &str,
+        link_id: f64,
+    ) -> Self {
+        let message = message
+            .parse::<MessageDef>()
+            .expect("Invalid MAVLink message");
+        let inputs = fields
+            .iter()
+            .map(|f| {
+                message
+                    .field_values(f.as_ref())
+                    .expect("Unknown MAVLink message field")
+            })
+            .collect();
+        let signing = (!signing_key.is_empty()).then(|| {
+            let key = SigningKey::from_hex(signing_key).expect("Invalid MAVLink signing key");
+            (key, link_id as u8)
+        });
+        Self {
+            message,
+            inputs,
+            system_id: system_id as u8,
+            component_id: component_id as u8,
+            signing,
+        }
+    }
+}
+
+/// The MAVLink Encode Block builds a MAVLink v2 frame from its inputs each time it runs, for
+/// sending with a `SerialTransmitBlock` or `UdpTransmitBlock`. Each input sets one message field,
+/// scalars for scalar fields and vectors for array fields. Fields without an input are sent as
+/// zero.
+///
+/// The last input is the wall-clock time in seconds since the Unix epoch, such as the Epoch
+/// output of a `SystemTimeBlock`, and is only used when signing. MAVLink signing timestamps
+/// count 10 microsecond units since the start of 2015, and receivers reject any timestamp that
+/// isn't newer than the last one they saw, so they need a clock that keeps counting across
+/// restarts rather than the app time. Each frame's timestamp is also kept above the previous
+/// frame's, so a coarse or stalled clock still gives increasing timestamps.
+///
+/// The sequence number increments with each frame.
+pub struct MavlinkEncodeBlock<T: Apply> {
+    pub data: OldBlockData,
+    encoder: Option<MavlinkEncoder>,
+    values: Vec<f64>,
+    buffer: Vec<u8>,
+    _unused: core::marker::PhantomData<T>,
+}
+
+impl<T: Apply> Default for MavlinkEncodeBlock<T> {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::from_bytes(b""),
+            encoder: None,
+            values: Vec::new(),
+            buffer: Vec::new(),
+            _unused: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<T: Apply> ProcessBlock for MavlinkEncodeBlock<T> {
+    type Inputs = (T, f64);
+    type Output = ByteSliceSignal;
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let (inputs, unix_time_s) = inputs;
+        let encoder = self.encoder.get_or_insert_with(|| {
+            MavlinkEncoder::new(
+                parameters.system_id,
+                parameters.component_id,
+                parameters.signing,
+            )
+        });
+
+        self.values.clear();
+        self.values.resize(parameters.message.value_count(), 0.0);
+        T::write_values(inputs, &mut self.values, &parameters.inputs);
+
+        // The encoder keeps the timestamp above the last one it sent. Times before 2015 and
+        // NaN both saturate to zero.
+        let timestamp = ((unix_time_s - MAVLINK_EPOCH_S) * 100_000.0) as u64;
+        self.buffer.clear();
+        encoder.encode(
+            &parameters.message,
+            &self.values,
+            timestamp,
+            &mut self.buffer,
+        );
+        self.data.set_bytes(&self.buffer);
+        &self.buffer
+    }
+}
+
+/// A type a message field can be set from
+pub trait IntoMavlink: Pass {
+    fn write_values(input: PassBy<Self>, dest: &mut [f64]);
+}
+
+impl IntoMavlink for f64 {
+    fn write_values(input: PassBy<Self>, dest: &mut [f64]) {
+        if let Some(dest) = dest.first_mut() {
+            *dest = input;
+        }
+    }
+}
+
+impl<const NROWS: usize, const NCOLS: usize> IntoMavlink for Matrix<NROWS, NCOLS, f64> {
+    fn write_values(input: PassBy<Self>, dest: &mut [f64]) {
+        for (dest, value) in dest.iter_mut().zip(input.data.iter().flatten()) {
+            *dest = *value;
+        }
+    }
+}
+
+pub trait Apply: Pass {
+    /// Writes each input into the message values in its range
+    fn write_values(input: PassBy<Self>, values: &mut [f64], inputs: &[Range<usize>]);
+}
+
+impl<S: IntoMavlink> Apply for S {
+    fn write_values(input: PassBy<Self>, values: &mut [f64], inputs: &[Range<usize>]) {
+        S::write_values(input, &mut values[inputs[0].clone()]);
+    }
+}
+
+macro_rules! impl_apply {
+    ($($t:ident => $idx:tt),+) => {
+        impl<$($t: IntoMavlink),+> Apply for ($($t,)+) {
+            fn write_values(input: PassBy<Self>, values: &mut [f64], inputs: &[Range<usize>]) {
+                $($t::write_values(input.$idx, &mut values[inputs[$idx].clone()]);)+
+            }
+        }
+    };
+}
+
+impl_apply!(S1 => 0, S2 => 1);
+impl_apply!(S1 => 0, S2 => 1, S3 => 2);
+impl_apply!(S1 => 0, S2 => 1, S3 => 2, S4 => 3);
+impl_apply!(S1 => 0, S2 => 1, S3 => 2, S4 => 3, S5 => 4);
+impl_apply!(S1 => 0, S2 => 1, S3 => 2, S4 => 3, S5 => 4, S6 => 5);
+impl_apply!(S1 => 0, S2 => 1, S3 => 2, S4 => 3, S5 => 4, S6 => 5, S7 => 6);
+impl_apply!(S1 => 0, S2 => 1, S3 => 2, S4 => 3, S5 => 4, S6 => 5, S7 => 6, S8 => 7);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corelib_traits_testing::StubContext;
+    use utils::byte_data::mavlink::MavlinkDecoder;
+
+    #[test]
+    fn test_encode_heartbeat() {
+        let context = StubContext::default();
+        let parameters = Parameters::new(
+            "HEARTBEAT",
+            &["type", "autopilot", "mavlink_version"],
+            1.0,
+            191.0,
+            "",
+            0.0,
+        );
+        let mut block = MavlinkEncodeBlock::<(f64, f64, f64)>::default();
+
+        let output = block.process(&parameters, &context, ((2.0, 12.0, 3.0), 0.0));
+        assert_eq!(&output[..10], &[0xFD, 9, 0, 0, 0, 1, 191, 0, 0, 0]);
+        assert_eq!(&output[10..19], &[0, 0, 0, 0, 2, 12, 0, 0, 3]);
+        assert_eq!(output.len(), 21);
+
+        // Sequence number increments each tick
+        let output = block
+            .process(&parameters, &context, ((2.0, 12.0, 3.0), 0.0))
+            .to_vec();
+        assert_eq!(output[4], 1);
+        assert_eq!(block.data, OldBlockData::from_bytes(&output));
+    }
+
+    #[test]
+    fn test_encode_vector_field_roundtrip() {
+        let context = StubContext::default();
+        let parameters = Parameters::new(
+            "ATTITUDE_QUATERNION",
+            &["repr_offset_q"],
+            1.0,
+            1.0,
+            "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+            3.0,
+        );
+        let mut block = MavlinkEncodeBlock::<Matrix<1, 4, f64>>::default();
+
+        let input = Matrix {
+            data: [[1.0], [0.0], [0.0], [0.5]],
+        };
+        let output = block.process(&parameters, &context, (&input, 1.7e9));
+
+        let mut decoder =
+            MavlinkDecoder::new(&[&parameters.message], parameters.signing.map(|s| s.0));
+        let mut values = [0.0; 12];
+        decoder.decode(output, |frame| {
+            assert!(frame.signed);
+            parameters
+                .message
+                .unpack_payload(frame.payload, &mut values);
+        });
+        assert_eq!(decoder.stats().frames, 1);
+        assert_eq!(&values[8..], &[1.0, 0.0, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn test_signing_timestamp_survives_restart() {
+        let context = StubContext::default();
+        let key = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+        let parameters = Parameters::new("HEARTBEAT", &["custom_mode"], 1.0, 1.0, key, 0.0);
+        let mut decoder =
+            MavlinkDecoder::new(&[&parameters.message], parameters.signing.map(|s| s.0));
+        let mut received = 0;
+
+        // A clock that only ticks once a second still gives increasing timestamps
+        let mut block = MavlinkEncodeBlock::<f64>::default();
+        for _ in 0..3 {
+            let output = block.process(&parameters, &context, (7.0, 1.7e9));
+            decoder.decode(output, |_| received += 1);
+        }
+        assert_eq!(received, 3);
+
+        // After a restart the app time starts over, but the wall clock doesn't
+        let mut block = MavlinkEncodeBlock::<f64>::default();
+        let output = block.process(&parameters, &context, (7.0, 1.7e9 + 1.0));
+        decoder.decode(output, |_| received += 1);
+        assert_eq!(received, 4);
+        assert_eq!(decoder.stats().signature_errors, 0);
+    }
+}
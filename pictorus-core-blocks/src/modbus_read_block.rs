@@ -0,0 +1,447 @@
+extern crate alloc;
+use alloc::vec::Vec;
+use core::str::FromStr;
+use core::time::Duration;
+use corelib_traits::{ByteSliceSignal, Context, Matrix, Pass, PassBy, ProcessBlock};
+use utils::{BlockData as OldBlockData, FromPass, IsValid, ParseEnumError, StaleTracker};
+
+/// The four Modbus data tables
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModbusTable {
+    /// Read/write single bits
+    Coils,
+    /// Read-only single bits
+    DiscreteInputs,
+    /// Read/write 16 bit registers
+    HoldingRegisters,
+    /// Read-only 16 bit registers
+    InputRegisters,
+}
+
+impl FromStr for ModbusTable {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Coils" => Ok(Self::Coils),
+            "DiscreteInputs" => Ok(Self::DiscreteInputs),
+            "HoldingRegisters" => Ok(Self::HoldingRegisters),
+            "InputRegisters" => Ok(Self::InputRegisters),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+impl ModbusTable {
+    /// Function code used to read from the table
+    pub fn read_function(&self) -> u8 {
+        match self {
+            Self::Coils => 0x01,
+            Self::DiscreteInputs => 0x02,
+            Self::HoldingRegisters => 0x03,
+            Self::InputRegisters => 0x04,
+        }
+    }
+
+    /// Function code used to write multiple values to the table, or `None` if it is read-only
+    pub fn write_function(&self) -> Option<u8> {
+        match self {
+            Self::Coils => Some(0x0F),
+            Self::HoldingRegisters => Some(0x10),
+            Self::DiscreteInputs | Self::InputRegisters => None,
+        }
+    }
+
+    pub fn is_bits(&self) -> bool {
+        matches!(self, Self::Coils | Self::DiscreteInputs)
+    }
+}
+
+/// How values are stored in registers. Bit tables ignore the data type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModbusDataType {
+    U16,
+    I16,
+    U32,
+    I32,
+    F32,
+}
+
+impl FromStr for ModbusDataType {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "U16" => Ok(Self::U16),
+            "I16" => Ok(Self::I16),
+            "U32" => Ok(Self::U32),
+            "I32" => Ok(Self::I32),
+            "F32" => Ok(Self::F32),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+impl ModbusDataType {
+    /// Number of registers each value occupies
+    pub fn register_count(&self) -> usize {
+        match self {
+            Self::U16 | Self::I16 => 1,
+            Self::U32 | Self::I32 | Self::F32 => 2,
+        }
+    }
+
+    fn decode_bits(&self, bits: u32) -> f64 {
+        match self {
+            Self::U16 => bits as u16 as f64,
+            Self::I16 => bits as u16 as i16 as f64,
+            Self::U32 => bits as f64,
+            Self::I32 => bits as i32 as f64,
+            Self::F32 => f32::from_bits(bits) as f64,
+        }
+    }
+
+    fn encode_bits(&self, value: f64) -> u32 {
+        // Float to int casts saturate, which is what we want for out of range values
+        match self {
+            Self::U16 => value as u16 as u32,
+            Self::I16 => value as i16 as u16 as u32,
+            Self::U32 => value as u32,
+            Self::I32 => value as i32 as u32,
+            Self::F32 => (value as f32).to_bits(),
+        }
+    }
+}
+
+/// Layout of a run of values in a Modbus table, shared by the read and write blocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModbusLayout {
+    pub table: ModbusTable,
+    /// Number of values
+    pub count: usize,
+    pub data_type: ModbusDataType,
+    /// Whether 32 bit values are stored with the low word in the first register
+    pub low_word_first: bool,
+}
+
+impl ModbusLayout {
+    /// Number of coils, inputs or registers that hold the values
+    pub fn quantity(&self) -> usize {
+        if self.table.is_bits() {
+            self.count
+        } else {
+            self.count * self.data_type.register_count()
+        }
+    }
+
+    /// Number of bytes the values take on the wire
+    pub fn byte_count(&self) -> usize {
+        if self.table.is_bits() {
+            self.count.div_ceil(8)
+        } else {
+            self.quantity() * 2
+        }
+    }
+
+    /// Decodes values from wire data, with registers big-endian and bits packed least
+    /// significant bit first
+    pub fn decode(&self, data: &[u8], dest: &mut [f64]) {
+        if self.table.is_bits() {
+            for (i, value) in dest.iter_mut().enumerate().take(self.count) {
+                *value = if data[i / 8] & (1 << (i % 8)) != 0 {
+                    1.0
+                } else {
+                    0.0
+                };
+            }
+            return;
+        }
+
+        let value_bytes = self.data_type.register_count() * 2;
+        for (value, bytes) in dest.iter_mut().zip(data.chunks_exact(value_bytes)) {
+            let bits = match bytes {
+                [hi, lo] => u16::from_be_bytes([*hi, *lo]) as u32,
+                [a, b, c, d] if self.low_word_first => u32::from_be_bytes([*c, *d, *a, *b]),
+                [a, b, c, d] => u32::from_be_bytes([*a, *b, *c, *d]),
+                _ => unreachable!(),
+            };
+            *value = self.data_type.decode_bits(bits);
+        }
+    }
+
+    /// Encodes values as wire data, the inverse of [`ModbusLayout::decode`]. Missing values are
+    /// encoded as zero.
+    pub fn encode(&self, values: &[f64], dest: &mut Vec<u8>) {
+        dest.clear();
+        dest.resize(self.byte_count(), 0);
+        if self.table.is_bits() {
+            for (i, value) in values.iter().enumerate().take(self.count) {
+                if *value != 0.0 {
+                    dest[i / 8] |= 1 << (i % 8);
+                }
+            }
+            return;
+        }
+
+        let value_bytes = self.data_type.register_count() * 2;
+        for (bytes, value) in dest.chunks_exact_mut(value_bytes).zip(values) {
+            let bits = self.data_type.encode_bits(*value).to_be_bytes();
+            match bytes.len() {
+                2 => bytes.copy_from_slice(&bits[2..]),
+                _ if self.low_word_first => {
+                    bytes[..2].copy_from_slice(&bits[2..]);
+                    bytes[2..].copy_from_slice(&bits[..2]);
+                }
+                _ => bytes.copy_from_slice(&bits),
+            }
+        }
+    }
+}
+
+/// Parameters for the Modbus Read Block
+pub struct Parameters {
+    /// Unit (slave) id of the device to read from
+    pub unit_id: u8,
+    /// Address of the first value in the table
+    pub address: u16,
+    pub layout: ModbusLayout,
+    /// How often the device is polled
+    pub poll_period: Duration,
+    /// How long to wait for the device to respond
+    pub timeout: Duration,
+    /// Stale age in milliseconds
+    stale_age_ms: f64,
+}
+
+impl Parameters {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        unit_id: f64,
+        table: &str,
+        address: f64,
+        count: f64,
+        data_type: &str,
+        low_word_first: bool,
+        poll_period_ms: f64,
+        timeout_ms: f64,
+        stale_age_ms: f64,
+    ) -> Self {
+        Self {
+            unit_id: unit_id as u8,
+            address: address as u16,
+            layout: ModbusLayout {
+                table: table.parse().expect("Invalid Modbus table"),
+                count: count as usize,
+                data_type: data_type.parse().expect("Invalid Modbus data type"),
+                low_word_first,
+            },
+            poll_period: Duration::from_secs_f64(poll_period_ms / 1000.0),
+            timeout: Duration::from_secs_f64(timeout_ms / 1000.0),
+            stale_age_ms,
+        }
+    }
+
+    /// Whether the device should be polled at `now`, given when it was last polled
+    pub fn should_poll(&self, last_poll: Option<Duration>, now: Duration) -> bool {
+        last_poll.is_none_or(|last_poll| now.saturating_sub(last_poll) >= self.poll_period)
+    }
+}
+
+/// The Modbus Read Block decodes values read from a Modbus device. Its input is the raw data
+/// read by the platform's Modbus protocol, which is empty on ticks where the device was not
+/// polled or did not respond. The last values read are held in between polls, and the block
+/// becomes invalid once no response has been received for the stale age, so the stale age
+/// should be longer than the poll period.
+///
+/// Coils and discrete inputs are output as 1.0 or 0.0, and registers are converted from the
+/// configured data type.
+pub struct ModbusReadBlock<T: Apply> {
+    pub data: OldBlockData,
+    pub stale_check: StaleTracker,
+    buffer: T,
+    values: Vec<f64>,
+    previous_stale_check_time_ms: f64,
+}
+
+impl<T: Apply> Default for ModbusReadBlock<T> {
+    fn default() -> Self {
+        let buffer = T::default();
+        Self {
+            data: buffer.as_old_block_data(),
+            stale_check: StaleTracker::from_ms(0.0),
+            buffer,
+            values: Vec::new(),
+            previous_stale_check_time_ms: 0.,
+        }
+    }
+}
+
+impl<T: Apply> IsValid for ModbusReadBlock<T> {
+    fn is_valid(&self, app_time_s: f64) -> OldBlockData {
+        self.stale_check.is_valid(app_time_s)
+    }
+}
+
+impl<T: Apply> ProcessBlock for ModbusReadBlock<T> {
+    type Parameters = Parameters;
+    type Inputs = ByteSliceSignal;
+    type Output = T;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        if self.previous_stale_check_time_ms != parameters.stale_age_ms {
+            self.stale_check = StaleTracker::from_ms(parameters.stale_age_ms);
+            self.previous_stale_check_time_ms = parameters.stale_age_ms;
+        }
+
+        // Anything other than a complete response means the device wasn't read this tick
+        let layout = &parameters.layout;
+        if !inputs.is_empty() && inputs.len() == layout.byte_count() {
+            self.values.resize(layout.count, 0.0);
+            layout.decode(inputs, &mut self.values);
+            self.buffer = T::from_values(&self.values);
+            self.data = self.buffer.as_old_block_data();
+            self.stale_check.mark_updated(context.time().as_secs_f64());
+        }
+
+        self.buffer.as_by()
+    }
+}
+
+pub trait Apply: Pass + Default {
+    fn from_values(values: &[f64]) -> Self;
+
+    /// Just needed to support the OldBlockData
+    fn as_old_block_data(&self) -> OldBlockData;
+}
+
+impl Apply for f64 {
+    fn from_values(values: &[f64]) -> Self {
+        values.first().copied().unwrap_or_default()
+    }
+
+    fn as_old_block_data(&self) -> OldBlockData {
+        OldBlockData::from_scalar(*self)
+    }
+}
+
+impl<const NROWS: usize, const NCOLS: usize> Apply for Matrix<NROWS, NCOLS, f64> {
+    fn from_values(values: &[f64]) -> Self {
+        let mut output = Self::zeroed();
+        for (dest, value) in output.data.iter_mut().flatten().zip(values) {
+            *dest = *value;
+        }
+        output
+    }
+
+    fn as_old_block_data(&self) -> OldBlockData {
+        <OldBlockData as FromPass<Self>>::from_pass(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corelib_traits_testing::StubRuntime;
+
+    #[test]
+    fn test_layout_roundtrip() {
+        let mut layout = ModbusLayout {
+            table: ModbusTable::HoldingRegisters,
+            count: 3,
+            data_type: ModbusDataType::I32,
+            low_word_first: false,
+        };
+        assert_eq!(layout.quantity(), 6);
+        assert_eq!(layout.byte_count(), 12);
+
+        let mut data = Vec::new();
+        layout.encode(&[-2.0, 70000.0, 1.0], &mut data);
+        assert_eq!(
+            &data[..8],
+            &[0xFF, 0xFF, 0xFF, 0xFE, 0x00, 0x01, 0x11, 0x70]
+        );
+        let mut values = [0.0; 3];
+        layout.decode(&data, &mut values);
+        assert_eq!(values, [-2.0, 70000.0, 1.0]);
+
+        layout.low_word_first = true;
+        layout.data_type = ModbusDataType::F32;
+        layout.encode(&[1.5], &mut data);
+        assert_eq!(&data[..4], &[0x00, 0x00, 0x3F, 0xC0]);
+        layout.decode(&data, &mut values);
+        assert_eq!(values, [1.5, 0.0, 0.0]);
+
+        let layout = ModbusLayout {
+            table: ModbusTable::Coils,
+            count: 10,
+            data_type: ModbusDataType::U16,
+            low_word_first: false,
+        };
+        layout.encode(
+            &[1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+            &mut data,
+        );
+        assert_eq!(data, [0b0000_0101, 0b0000_0010]);
+        assert_eq!(layout.quantity(), 10);
+    }
+
+    #[test]
+    fn test_should_poll() {
+        let parameters = Parameters::new(
+            1.0,
+            "InputRegisters",
+            0.0,
+            1.0,
+            "U16",
+            false,
+            500.0,
+            100.0,
+            1000.0,
+        );
+        assert!(parameters.should_poll(None, Duration::ZERO));
+        let last_poll = Some(Duration::from_millis(100));
+        assert!(!parameters.should_poll(last_poll, Duration::from_millis(500)));
+        assert!(parameters.should_poll(last_poll, Duration::from_millis(600)));
+    }
+
+    #[test]
+    fn test_modbus_read_block() {
+        let parameters = Parameters::new(
+            1.0,
+            "HoldingRegisters",
+            100.0,
+            2.0,
+            "I16",
+            false,
+            100.0,
+            50.0,
+            500.0,
+        );
+        let mut runtime = StubRuntime::default();
+        let mut block = ModbusReadBlock::<Matrix<2, 1, f64>>::default();
+
+        let output = block.process(&parameters, &runtime.context(), &[0x00, 0x2A, 0xFF, 0xFE]);
+        assert_eq!(output.data, [[42.0, -2.0]]);
+        assert_eq!(block.is_valid(0.0).scalar(), 1.0);
+
+        // Values are held between polls until they go stale
+        runtime.set_time(Duration::from_millis(400));
+        let output = block.process(&parameters, &runtime.context(), &[]);
+        assert_eq!(output.data, [[42.0, -2.0]]);
+        assert_eq!(block.is_valid(0.4).scalar(), 1.0);
+
+        runtime.set_time(Duration::from_secs(1));
+        let output = block.process(&parameters, &runtime.context(), &[0x00]);
+        assert_eq!(output.data, [[42.0, -2.0]]);
+        assert_eq!(block.is_valid(1.0).scalar(), 0.0);
+        assert_eq!(
+            block.data,
+            <OldBlockData as FromPass<Matrix<2, 1, f64>>>::from_pass(&Matrix {
+                data: [[42.0, -2.0]]
+            })
+        );
+    }
+}
@@ -0,0 +1,187 @@
+extern crate alloc;
+use alloc::vec::Vec;
+use core::time::Duration;
+use corelib_traits::{ByteSliceSignal, Context, Matrix, Pass, PassBy, ProcessBlock};
+use utils::BlockData as OldBlockData;
+
+use crate::modbus_read_block::{ModbusLayout, ModbusTable};
+
+/// Parameters for the Modbus Write Block
+pub struct Parameters {
+    /// Unit (slave) id of the device to write to
+    pub unit_id: u8,
+    /// Address of the first value in the table
+    pub address: u16,
+    pub layout: ModbusLayout,
+    /// How often unchanged values are written again, or `None` to only write on change
+    pub write_period: Option<Duration>,
+    /// How long to wait for the device to respond
+    pub timeout: Duration,
+}
+
+impl Parameters {
+    /// `table` must be `Coils` or `HoldingRegisters`. A `write_period_ms` of 0 only writes
+    /// values when they change.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        unit_id: f64,
+        table: &str,
+        address: f64,
+        count: f64,
+        data_type: &str,
+        low_word_first: bool,
+        write_period_ms: f64,
+        timeout_ms: f64,
+    ) -> Self {
+        let table = table.parse::<ModbusTable>().expect("Invalid Modbus table");
+        assert!(
+            table.write_function().is_some(),
+            "Modbus table is not writable"
+        );
+        Self {
+            unit_id: unit_id as u8,
+            address: address as u16,
+            layout: ModbusLayout {
+                table,
+                count: count as usize,
+                data_type: data_type.parse().expect("Invalid Modbus data type"),
+                low_word_first,
+            },
+            write_period: (write_period_ms > 0.0)
+                .then(|| Duration::from_secs_f64(write_period_ms / 1000.0)),
+            timeout: Duration::from_secs_f64(timeout_ms / 1000.0),
+        }
+    }
+}
+
+/// The Modbus Write Block encodes its input for writing to coils or holding registers of a
+/// Modbus device. It outputs the raw data for the platform's Modbus protocol to write, on the
+/// first tick, whenever the encoded values change and, if a write period is set, whenever the
+/// period has passed since the last write. On all other ticks the output is empty and nothing
+/// is written.
+///
+/// Coils are set for any non-zero input, and register values are converted to the configured
+/// data type, saturating if they are out of range.
+pub struct ModbusWriteBlock<T: Apply> {
+    pub data: OldBlockData,
+    values: Vec<f64>,
+    encoded: Vec<u8>,
+    last_written: Vec<u8>,
+    last_write_time: Option<Duration>,
+    _unused: core::marker::PhantomData<T>,
+}
+
+impl<T: Apply> Default for ModbusWriteBlock<T> {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::from_bytes(b""),
+            values: Vec::new(),
+            encoded: Vec::new(),
+            last_written: Vec::new(),
+            last_write_time: None,
+            _unused: core::marker::PhantomData,
+        }
+    }
+}
+
+impl<T: Apply> ProcessBlock for ModbusWriteBlock<T> {
+    type Parameters = Parameters;
+    type Inputs = T;
+    type Output = ByteSliceSignal;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        self.values.clear();
+        T::write_values(inputs, &mut self.values);
+        parameters.layout.encode(&self.values, &mut self.encoded);
+
+        let now = context.time();
+        let period_elapsed = match (parameters.write_period, self.last_write_time) {
+            (_, None) => true,
+            (Some(period), Some(last_write)) => now.saturating_sub(last_write) >= period,
+            (None, Some(_)) => false,
+        };
+        if period_elapsed || self.encoded != self.last_written {
+            self.last_written.clone_from(&self.encoded);
+            self.last_write_time = Some(now);
+            self.data.set_bytes(&self.encoded);
+            return &self.encoded;
+        }
+
+        self.data.set_bytes(b"");
+        &[]
+    }
+}
+
+pub trait Apply: Pass {
+    /// Appends the input values to `dest`
+    fn write_values(input: PassBy<Self>, dest: &mut Vec<f64>);
+}
+
+impl Apply for f64 {
+    fn write_values(input: PassBy<Self>, dest: &mut Vec<f64>) {
+        dest.push(input);
+    }
+}
+
+impl<const NROWS: usize, const NCOLS: usize> Apply for Matrix<NROWS, NCOLS, f64> {
+    fn write_values(input: PassBy<Self>, dest: &mut Vec<f64>) {
+        dest.extend(input.data.iter().flatten());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corelib_traits_testing::StubRuntime;
+
+    #[test]
+    fn test_write_on_change() {
+        let parameters =
+            Parameters::new(1.0, "HoldingRegisters", 0.0, 1.0, "U32", false, 0.0, 100.0);
+        let mut runtime = StubRuntime::default();
+        let mut block = ModbusWriteBlock::<f64>::default();
+
+        let output = block.process(&parameters, &runtime.context(), 65536.0);
+        assert_eq!(output, &[0x00, 0x01, 0x00, 0x00]);
+        assert_eq!(block.data.to_bytes(), &[0x00, 0x01, 0x00, 0x00]);
+
+        runtime.tick();
+        let output = block.process(&parameters, &runtime.context(), 65536.0);
+        assert!(output.is_empty());
+
+        runtime.tick();
+        let output = block.process(&parameters, &runtime.context(), 1.0);
+        assert_eq!(output, &[0x00, 0x00, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn test_write_period() {
+        let parameters = Parameters::new(1.0, "Coils", 8.0, 3.0, "U16", false, 250.0, 100.0);
+        let mut runtime = StubRuntime::default();
+        let mut block = ModbusWriteBlock::<Matrix<1, 3, f64>>::default();
+        let input = Matrix {
+            data: [[1.0], [0.0], [1.0]],
+        };
+
+        let output = block.process(&parameters, &runtime.context(), &input);
+        assert_eq!(output, &[0b101]);
+        runtime.set_time(Duration::from_millis(200));
+        assert!(block
+            .process(&parameters, &runtime.context(), &input)
+            .is_empty());
+        runtime.set_time(Duration::from_millis(250));
+        let output = block.process(&parameters, &runtime.context(), &input);
+        assert_eq!(output, &[0b101]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Modbus table is not writable")]
+    fn test_read_only_table() {
+        Parameters::new(1.0, "InputRegisters", 0.0, 1.0, "U16", false, 0.0, 100.0);
+    }
+}
@@ -0,0 +1,181 @@
+use corelib_traits::{Context, Matrix, PassBy, ProcessBlock};
+use utils::{BlockData as OldBlockData, FromPass};
+
+/// Parameters for the Multi-Channel ADC Block
+pub struct Parameters<const N: usize> {
+    /// Conversions averaged into each sample
+    pub oversampling: u16,
+    /// Average in the ADC's hardware oversampler, where it has one, rather than converting
+    /// each channel repeatedly. Hardware oversampling rounds down to a power of two.
+    pub hardware_oversampling: bool,
+    /// Nominal reference voltage of the ADC, the voltage at full scale
+    pub reference_voltage: f64,
+    /// Use the reference voltage measured against the ADC's internal reference instead of
+    /// the nominal one, which corrects for supply drift
+    pub vref_compensation: bool,
+    /// Coefficients of each channel's calibration polynomial from volts to engineering units,
+    /// c0 + c1·v + c2·v² + c3·v³. A linear calibration is `[offset, gain, 0, 0]`.
+    pub calibration: [[f64; 4]; N],
+    /// Lowest valid value of each channel in engineering units
+    pub min: [f64; N],
+    /// Highest valid value of each channel in engineering units
+    pub max: [f64; N],
+}
+
+impl<const N: usize> Parameters<N> {
+    pub fn new(
+        oversampling: f64,
+        hardware_oversampling: bool,
+        reference_voltage: f64,
+        vref_compensation: bool,
+        calibration: [[f64; 4]; N],
+        min: [f64; N],
+        max: [f64; N],
+    ) -> Self {
+        Self {
+            oversampling: (oversampling as u16).max(1),
+            hardware_oversampling,
+            reference_voltage,
+            vref_compensation,
+            calibration,
+            min,
+            max,
+        }
+    }
+}
+
+/// The Multi-Channel ADC Block converts readings of several ADC channels to engineering
+/// units.
+///
+/// The inputs are the reading of each channel as a fraction of full scale, and the ADC's
+/// reference voltage measured against its internal reference, which is zero when the
+/// platform can't measure it. Each reading is scaled to volts by the reference voltage and then
+/// calibrated. The outputs are the calibrated values, a flag for each channel that is 1 when
+/// it is out of range, and whether any channel is out of range. A channel is out of range when
+/// its reading is at either end of the ADC's range, where the input may be clipped, or when
+/// its value is outside its valid range.
+pub struct MultiChannelAdcBlock<const N: usize> {
+    pub data: OldBlockData,
+    values: Matrix<1, N, f64>,
+    out_of_range: Matrix<1, N, f64>,
+}
+
+impl<const N: usize> Default for MultiChannelAdcBlock<N>
+where
+    OldBlockData: FromPass<Matrix<1, N, f64>>,
+{
+    fn default() -> Self {
+        Self {
+            data: <OldBlockData as FromPass<Matrix<1, N, f64>>>::from_pass(&Matrix::zeroed()),
+            values: Matrix::zeroed(),
+            out_of_range: Matrix::zeroed(),
+        }
+    }
+}
+
+impl<const N: usize> ProcessBlock for MultiChannelAdcBlock<N>
+where
+    OldBlockData: FromPass<Matrix<1, N, f64>>,
+{
+    type Parameters = Parameters<N>;
+    type Inputs = (Matrix<1, N, f64>, f64);
+    type Output = (Matrix<1, N, f64>, Matrix<1, N, f64>, bool);
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let (readings, measured_reference) = inputs;
+        let reference_voltage = if parameters.vref_compensation && measured_reference > 0.0 {
+            measured_reference
+        } else {
+            parameters.reference_voltage
+        };
+
+        let mut any_out_of_range = false;
+        for channel in 0..N {
+            let reading = readings.data[channel][0];
+            let volts = reading * reference_voltage;
+            let value = parameters.calibration[channel]
+                .iter()
+                .rev()
+                .fold(0.0, |value, coefficient| value * volts + coefficient);
+            let out_of_range = reading <= 0.0
+                || reading >= 1.0
+                || !(parameters.min[channel]..=parameters.max[channel]).contains(&value);
+            self.values.data[channel][0] = value;
+            self.out_of_range.data[channel][0] = if out_of_range { 1.0 } else { 0.0 };
+            any_out_of_range |= out_of_range;
+        }
+        self.data = <OldBlockData as FromPass<Matrix<1, N, f64>>>::from_pass(&self.values);
+
+        (&self.values, &self.out_of_range, any_out_of_range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use corelib_traits_testing::StubContext;
+
+    fn vector<const N: usize>(values: [f64; N]) -> Matrix<1, N, f64> {
+        Matrix {
+            data: values.map(|v| [v]),
+        }
+    }
+
+    #[test]
+    fn test_multi_channel_adc_block_calibration() {
+        // A battery voltage divider, a linear thermistor circuit and a quadratic sensor
+        let parameters = Parameters::new(
+            16.0,
+            true,
+            3.3,
+            false,
+            [
+                [0.0, 11.0, 0.0, 0.0],
+                [-50.0, 100.0, 0.0, 0.0],
+                [1.0, 0.0, 2.0, 0.0],
+            ],
+            [0.0, -40.0, 0.0],
+            [30.0, 125.0, 10.0],
+        );
+        let context = StubContext::default();
+        let mut block = MultiChannelAdcBlock::<3>::default();
+
+        let output = block.process(&parameters, &context, (&vector([0.5, 0.25, 0.4]), 0.0));
+        let expected = [18.15, 32.5, 1.0 + 2.0 * 1.32 * 1.32];
+        for (actual, expected) in output.0.data.iter().zip(expected) {
+            assert_relative_eq!(actual[0], expected, epsilon = 1e-9);
+        }
+        assert_eq!(output.1, &vector([0.0, 0.0, 0.0]));
+        assert!(!output.2);
+        assert_relative_eq!(block.data.get_data().as_slice()[0], 18.15, epsilon = 1e-9);
+
+        // The first channel is clipped and the second is above its range
+        let output = block.process(&parameters, &context, (&vector([1.0, 0.6, 0.4]), 0.0));
+        assert_eq!(output.1, &vector([1.0, 1.0, 0.0]));
+        assert!(output.2);
+    }
+
+    #[test]
+    fn test_multi_channel_adc_block_vref_compensation() {
+        let mut parameters =
+            Parameters::new(1.0, false, 3.3, true, [[0.0, 1.0, 0.0, 0.0]], [0.0], [5.0]);
+        let context = StubContext::default();
+        let mut block = MultiChannelAdcBlock::<1>::default();
+
+        let output = block.process(&parameters, &context, (&vector([0.5]), 3.2));
+        assert_relative_eq!(output.0.data[0][0], 1.6, epsilon = 1e-9);
+        // Without a measurement the nominal reference is used
+        let output = block.process(&parameters, &context, (&vector([0.5]), 0.0));
+        assert_relative_eq!(output.0.data[0][0], 1.65, epsilon = 1e-9);
+
+        parameters.vref_compensation = false;
+        let output = block.process(&parameters, &context, (&vector([0.5]), 3.2));
+        assert_relative_eq!(output.0.data[0][0], 1.65, epsilon = 1e-9);
+    }
+}
@@ -0,0 +1,183 @@
+use crate::traits::Float;
+use corelib_traits::{Context, PassBy, ProcessBlock};
+use utils::BlockData as OldBlockData;
+
+/// Parameters for the Peak Detector Block
+pub struct Parameters {
+    /// How far the input must rise and fall around a peak, or fall and rise around a valley
+    pub prominence: f64,
+    /// Peaks closer than this to the previous reported peak are ignored, and likewise for
+    /// valleys
+    pub min_spacing_s: f64,
+}
+
+impl Parameters {
+    pub fn new(prominence: f64, min_spacing_s: f64) -> Self {
+        Self {
+            prominence,
+            min_spacing_s,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Searching {
+    Either,
+    Peak,
+    Valley,
+}
+
+/// Whether a peak and a valley were detected this tick, and the value and app time in seconds
+/// of the latest one
+type Extremum<F> = (bool, bool, F, F);
+
+/// The Peak Detector Block finds the peaks and valleys of its input as it arrives.
+///
+/// A peak is the largest value between two valleys. It must rise at least `prominence` above
+/// the valley before it and be followed by a fall of at least `prominence`, so smaller
+/// wiggles such as noise are ignored. Valleys are the same upside down. A peak is only known
+/// once the input has fallen far enough, so it is reported on that tick, along with the value
+/// and app time of the peak itself. Peaks less than `min_spacing_s` after the previous
+/// reported peak aren't reported, and likewise for valleys. The start of the input is neither.
+///
+/// NaN inputs are ignored.
+pub struct PeakDetectorBlock<F: Float> {
+    pub data: OldBlockData,
+    searching: Searching,
+    /// Highest and lowest values since the last extremum, and their times
+    high: Option<(F, f64)>,
+    low: Option<(F, f64)>,
+    last_peak_time: Option<f64>,
+    last_valley_time: Option<f64>,
+    output: Extremum<F>,
+}
+
+impl<F: Float> Default for PeakDetectorBlock<F> {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::scalar_from_bool(false),
+            searching: Searching::Either,
+            high: None,
+            low: None,
+            last_peak_time: None,
+            last_valley_time: None,
+            output: (false, false, F::zero(), F::zero()),
+        }
+    }
+}
+
+/// Whether an extremum at `time` is far enough from the last reported one, recording it if so
+fn spaced(last_time: &mut Option<f64>, time: f64, min_spacing_s: f64) -> bool {
+    if last_time.is_some_and(|last| time - last < min_spacing_s) {
+        return false;
+    }
+    *last_time = Some(time);
+    true
+}
+
+impl<F: Float> ProcessBlock for PeakDetectorBlock<F> {
+    type Inputs = F;
+    type Output = Extremum<F>;
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        self.output.0 = false;
+        self.output.1 = false;
+        if !num_traits::Float::is_nan(input) {
+            let now = context.time().as_secs_f64();
+            let (high, high_time) = match self.high {
+                Some(high) if high.0 >= input => high,
+                _ => (input, now),
+            };
+            let (low, low_time) = match self.low {
+                Some(low) if low.0 <= input => low,
+                _ => (input, now),
+            };
+            self.high = Some((high, high_time));
+            self.low = Some((low, low_time));
+
+            let prominence = F::from(parameters.prominence).unwrap();
+            let fallen = input <= high - prominence;
+            let risen = input >= low + prominence;
+            match self.searching {
+                Searching::Peak | Searching::Either if fallen => {
+                    if self.searching == Searching::Peak
+                        && spaced(
+                            &mut self.last_peak_time,
+                            high_time,
+                            parameters.min_spacing_s,
+                        )
+                    {
+                        self.output = (true, false, high, F::from(high_time).unwrap());
+                    }
+                    self.searching = Searching::Valley;
+                    self.low = Some((input, now));
+                }
+                Searching::Valley | Searching::Either if risen => {
+                    if self.searching == Searching::Valley
+                        && spaced(
+                            &mut self.last_valley_time,
+                            low_time,
+                            parameters.min_spacing_s,
+                        )
+                    {
+                        self.output = (false, true, low, F::from(low_time).unwrap());
+                    }
+                    self.searching = Searching::Peak;
+                    self.high = Some((input, now));
+                }
+                _ => {}
+            }
+        }
+        self.data.set_scalar_bool(self.output.0);
+        self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use corelib_traits_testing::StubRuntime;
+
+    #[test]
+    fn test_peak_detector_block() {
+        let parameters = Parameters::new(1.0, 0.55);
+        let mut runtime = StubRuntime::default();
+        let mut block = PeakDetectorBlock::<f64>::default();
+
+        // Two bumps 0.5 s apart, then a third well after, with small wiggles on the way
+        let signal = [
+            0.0, 0.5, 1.8, 2.0, 1.9, 0.5, -0.5, 0.0, 2.5, 1.0, 0.3, 0.2, 0.1, 0.3, 3.0, 1.5,
+        ];
+        let mut peaks = std::vec::Vec::new();
+        let mut valleys = std::vec::Vec::new();
+        for input in signal {
+            let (peak, valley, value, time) = block.process(&parameters, &runtime.context(), input);
+            if peak {
+                peaks.push((value, time));
+            }
+            if valley {
+                valleys.push((value, time));
+            }
+            runtime.tick();
+        }
+
+        // The peak at 0.8 s is too close to the one at 0.3 s, and the wiggle at 0.4 s isn't one
+        assert_eq!(peaks.len(), 2);
+        assert_relative_eq!(peaks[0].0, 2.0);
+        assert_relative_eq!(peaks[0].1, 0.3, epsilon = 1e-12);
+        assert_relative_eq!(peaks[1].0, 3.0);
+        assert_relative_eq!(peaks[1].1, 1.4, epsilon = 1e-12);
+        assert_eq!(valleys.len(), 2);
+        assert_relative_eq!(valleys[0].0, -0.5);
+        assert_relative_eq!(valleys[1].0, 0.1);
+        assert_relative_eq!(valleys[1].1, 1.2, epsilon = 1e-12);
+        assert_eq!(block.data.scalar(), 1.0);
+    }
+}
@@ -0,0 +1,163 @@
+use corelib_traits::{PassBy, ProcessBlock};
+use utils::{BlockData as OldBlockData, IsValid};
+
+use crate::lookup::{Axis, BreakpointError, Extrapolation};
+use crate::traits::Float;
+
+/// Parameters for the PrelookupBlock
+pub struct Parameters<const N: usize, S: Float> {
+    extrapolation: Extrapolation,
+    axis: Axis<N, S>,
+}
+
+impl<const N: usize, S: Float> Parameters<N, S> {
+    /// Panics if the breakpoints are invalid. See [`Self::try_new`].
+    pub fn new(extrapolation: &str, break_points: &OldBlockData) -> Self {
+        Self::try_new(extrapolation, break_points).expect("Invalid break points")
+    }
+
+    /// There must be at least two breakpoints, which must be finite and strictly increasing
+    pub fn try_new(
+        extrapolation: &str,
+        break_points: &OldBlockData,
+    ) -> Result<Self, BreakpointError> {
+        Ok(Self {
+            extrapolation: extrapolation
+                .parse()
+                .expect("Invalid extrapolation. Must be Clamp, Linear or Error"),
+            axis: Axis::new(0, break_points)?,
+        })
+    }
+}
+
+/// Block for finding where an input falls among a set of up to `N` breakpoints, once for
+/// several lookup tables that share them.
+///
+/// The output is the fractional breakpoint index of the input: its segment between two
+/// breakpoints plus the fraction of the way along it, so 2.25 is a quarter of the way from the
+/// third breakpoint to the fourth. Lookup ND Blocks with `Prelookup` input take it in place of
+/// the coordinate. Inputs beyond the breakpoints are handled as set by [`Extrapolation`]; the
+/// second output is false if the input is NaN, or beyond the breakpoints with `Error`
+/// extrapolation.
+pub struct PrelookupBlock<const N: usize, S: Float> {
+    pub data: OldBlockData,
+    output: (S, bool),
+}
+
+impl<const N: usize, S: Float> Default for PrelookupBlock<N, S> {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::from_scalar(0.0),
+            output: (S::zero(), false),
+        }
+    }
+}
+
+impl<const N: usize, S: Float> ProcessBlock for PrelookupBlock<N, S> {
+    type Inputs = S;
+    type Output = (S, bool);
+    type Parameters = Parameters<N, S>;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn corelib_traits::Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let (position, in_range) = parameters.axis.prelookup(input, parameters.extrapolation);
+        let index = S::from(position.segment).unwrap() + position.fraction;
+        let valid = !num_traits::Float::is_nan(input)
+            && (in_range || parameters.extrapolation != Extrapolation::Error);
+        self.output = (index, valid);
+        self.data =
+            OldBlockData::from_scalar(num_traits::ToPrimitive::to_f64(&index).unwrap_or(f64::NAN));
+        self.output
+    }
+}
+
+// TODO: Remove when we remove BlockData
+impl<const N: usize, S: Float> IsValid for PrelookupBlock<N, S> {
+    fn is_valid(&self, _: f64) -> OldBlockData {
+        OldBlockData::scalar_from_bool(self.output.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lookup_nd_block::{LookupNDBlock, Parameters as LookupNDParameters};
+    use approx::assert_relative_eq;
+    use corelib_traits::Matrix;
+    use corelib_traits_testing::StubContext;
+
+    #[test]
+    fn test_prelookup_block() {
+        let context = StubContext::default();
+        let break_points = OldBlockData::from_vector(&[0.0, 10.0, 30.0]);
+        let mut block = PrelookupBlock::<4, f64>::default();
+
+        let parameters = Parameters::new("Error", &break_points);
+        assert_eq!(block.process(&parameters, &context, 20.0), (1.5, true));
+        assert_eq!(block.data.scalar(), 1.5);
+        assert_eq!(block.process(&parameters, &context, 40.0), (2.0, false));
+        assert!(!block.is_valid(0.0).any());
+
+        let parameters = Parameters::new("Linear", &break_points);
+        assert_eq!(block.process(&parameters, &context, 40.0), (2.5, true));
+        assert_eq!(block.process(&parameters, &context, -5.0), (-0.5, true));
+
+        assert_eq!(
+            Parameters::<4, f64>::try_new("Clamp", &OldBlockData::from_vector(&[1.0])).err(),
+            Some(BreakpointError::TooFew { axis: 0 })
+        );
+    }
+
+    #[test]
+    fn test_prelookup_shared_axis() {
+        // Two tables over the same speeds, one searched for both
+        let context = StubContext::default();
+        let speed = OldBlockData::from_vector(&[0.0, 10.0, 20.0, 40.0]);
+        let prelookup_parameters = Parameters::<4, f64>::new("Clamp", &speed);
+        let mut prelookup = PrelookupBlock::default();
+
+        let drag = OldBlockData::from_vector(&[0.0, 1.0, 4.0, 16.0]);
+        let drag_parameters = LookupNDParameters::<1, 4, 4, f64>::new(
+            "Monotone",
+            "Clamp",
+            "Prelookup",
+            [&speed],
+            &drag,
+        );
+        let rolling = OldBlockData::from_vector(&[2.0, 2.0, 2.5, 3.0]);
+        let rolling_parameters = LookupNDParameters::<1, 4, 4, f64>::new(
+            "Linear",
+            "Clamp",
+            "Prelookup",
+            [&speed],
+            &rolling,
+        );
+        let mut drag_block = LookupNDBlock::default();
+        let mut rolling_block = LookupNDBlock::default();
+        let direct_parameters = LookupNDParameters::<1, 4, 4, f64>::new(
+            "Monotone",
+            "Clamp",
+            "Coordinates",
+            [&speed],
+            &drag,
+        );
+        let mut direct_block = LookupNDBlock::default();
+
+        for speed in [0.0, 5.0, 15.0, 25.0, 50.0] {
+            let (index, _) = prelookup.process(&prelookup_parameters, &context, speed);
+            let index = Matrix { data: [[index]] };
+            let (drag, _) = drag_block.process(&drag_parameters, &context, &index);
+            let (rolling, _) = rolling_block.process(&rolling_parameters, &context, &index);
+            let (direct, _) =
+                direct_block.process(&direct_parameters, &context, &Matrix { data: [[speed]] });
+            assert_relative_eq!(drag, direct, epsilon = 1e-12);
+            if speed == 25.0 {
+                assert_relative_eq!(rolling, 2.625, epsilon = 1e-12);
+            }
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use corelib_traits::{Context, PassBy, ProcessBlock};
+use utils::{BlockData as OldBlockData, IsValid, StaleTracker};
+
+/// Parameters for the Pulse Capture Block
+pub struct Parameters {
+    stale_age_ms: f64,
+}
+
+impl Parameters {
+    pub fn new(stale_age_ms: f64) -> Self {
+        Self { stale_age_ms }
+    }
+}
+
+/// The Pulse Capture Block converts captured pulse timings to a frequency and duty cycle.
+///
+/// The inputs are the period and high time in seconds of the most recent complete pulse, with
+/// a period of zero when no pulse completed since the previous tick. The outputs are the
+/// frequency in Hz, the period and the duty cycle from 0 to 1. A pulse longer than the time
+/// since the last one completed can't have arrived yet, so while no pulses arrive the
+/// frequency decays toward zero. The period and duty cycle hold their last values, and the
+/// block goes stale.
+pub struct PulseCaptureBlock {
+    pub data: OldBlockData,
+    pub stale_check: StaleTracker,
+    frequency: f64,
+    period: f64,
+    duty_cycle: f64,
+    last_pulse_time: Option<f64>,
+    previous_stale_check_time_ms: f64,
+}
+
+impl Default for PulseCaptureBlock {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::from_scalar(0.0),
+            stale_check: StaleTracker::from_ms(0.0),
+            frequency: 0.0,
+            period: 0.0,
+            duty_cycle: 0.0,
+            last_pulse_time: None,
+            previous_stale_check_time_ms: 0.0,
+        }
+    }
+}
+
+impl IsValid for PulseCaptureBlock {
+    fn is_valid(&self, app_time_s: f64) -> OldBlockData {
+        self.stale_check.is_valid(app_time_s)
+    }
+}
+
+impl ProcessBlock for PulseCaptureBlock {
+    type Parameters = Parameters;
+    type Inputs = (f64, f64);
+    type Output = (f64, f64, f64);
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        if self.previous_stale_check_time_ms != parameters.stale_age_ms {
+            self.stale_check = StaleTracker::from_ms(parameters.stale_age_ms);
+            self.previous_stale_check_time_ms = parameters.stale_age_ms;
+        }
+
+        let now = context.time().as_secs_f64();
+        let (period, high_time) = inputs;
+        if period > 0.0 {
+            self.period = period;
+            self.frequency = 1.0 / period;
+            self.duty_cycle = (high_time / period).clamp(0.0, 1.0);
+            self.last_pulse_time = Some(now);
+            self.stale_check.mark_updated(now);
+        } else if let Some(last_pulse_time) = self.last_pulse_time {
+            self.frequency = self.frequency.min(1.0 / (now - last_pulse_time));
+        }
+        self.data = OldBlockData::from_scalar(self.frequency);
+
+        (self.frequency, self.period, self.duty_cycle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::time::Duration;
+    use corelib_traits_testing::StubRuntime;
+
+    #[test]
+    fn test_pulse_capture_block() {
+        let parameters = Parameters::new(100.0);
+        let mut runtime = StubRuntime::default();
+        let mut block = PulseCaptureBlock::default();
+
+        let output = block.process(&parameters, &runtime.context(), (0.0, 0.0));
+        assert_eq!(output, (0.0, 0.0, 0.0));
+
+        let output = block.process(&parameters, &runtime.context(), (0.02, 0.005));
+        assert_eq!(output, (50.0, 0.02, 0.25));
+        let valid = block.is_valid(runtime.context().time().as_secs_f64());
+        assert_eq!(valid.scalar(), 1.0);
+
+        runtime.set_time(Duration::from_millis(10));
+        let output = block.process(&parameters, &runtime.context(), (0.0, 0.0));
+        assert_eq!(output, (50.0, 0.02, 0.25));
+
+        runtime.set_time(Duration::from_millis(500));
+        let output = block.process(&parameters, &runtime.context(), (0.0, 0.0));
+        assert_eq!(output, (2.0, 0.02, 0.25));
+        assert_eq!(block.data.scalar(), 2.0);
+        let valid = block.is_valid(runtime.context().time().as_secs_f64());
+        assert_eq!(valid.scalar(), 0.0);
+    }
+}
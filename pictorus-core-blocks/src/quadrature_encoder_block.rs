@@ -0,0 +1,118 @@
+use corelib_traits::{Context, PassBy, ProcessBlock};
+use utils::BlockData as OldBlockData;
+
+use crate::edge_counter_block::EdgeRate;
+
+/// Parameters for the Quadrature Encoder Block
+pub struct Parameters {
+    /// Counts per revolution, four per encoder line
+    pub counts_per_revolution: f64,
+    /// Make the position relative to the first index pulse rather than the position at startup
+    pub reset_on_index: bool,
+}
+
+impl Parameters {
+    pub fn new(counts_per_revolution: f64, reset_on_index: bool) -> Self {
+        Self {
+            counts_per_revolution,
+            reset_on_index,
+        }
+    }
+}
+
+/// The Quadrature Encoder Block converts the count of a quadrature decoder to a position and
+/// velocity.
+///
+/// The inputs are the position in counts, the app time of the last count, whether an index
+/// pulse arrived since the previous tick and the count it arrived at. Platforms that don't
+/// timestamp counts pass the current time. The outputs are the position in revolutions, the
+/// velocity in revolutions per second and whether an index pulse has been seen.
+pub struct QuadratureEncoderBlock {
+    pub data: OldBlockData,
+    offset: Option<f64>,
+    previous_position: Option<f64>,
+    index_found: bool,
+    rate: EdgeRate,
+}
+
+impl Default for QuadratureEncoderBlock {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::from_scalar(0.0),
+            offset: None,
+            previous_position: None,
+            index_found: false,
+            rate: EdgeRate::default(),
+        }
+    }
+}
+
+impl ProcessBlock for QuadratureEncoderBlock {
+    type Parameters = Parameters;
+    type Inputs = (f64, f64, bool, f64);
+    type Output = (f64, f64, bool);
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let (position, last_count_time, index, index_position) = inputs;
+        if index && !self.index_found {
+            self.index_found = true;
+            if parameters.reset_on_index {
+                self.offset = Some(index_position);
+            }
+        }
+
+        let counts = position - self.previous_position.unwrap_or(position);
+        self.previous_position = Some(position);
+        let count_rate = self
+            .rate
+            .update(counts, last_count_time, context.time().as_secs_f64());
+
+        let position = (position - self.offset.unwrap_or(0.0)) / parameters.counts_per_revolution;
+        let velocity = count_rate / parameters.counts_per_revolution;
+        self.data = OldBlockData::from_scalar(position);
+        (position, velocity, self.index_found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use core::time::Duration;
+    use corelib_traits_testing::StubRuntime;
+
+    #[test]
+    fn test_quadrature_encoder_block() {
+        let parameters = Parameters::new(400.0, true);
+        let mut runtime = StubRuntime::default();
+        let mut block = QuadratureEncoderBlock::default();
+
+        let output = block.process(&parameters, &runtime.context(), (0.0, 0.0, false, 0.0));
+        assert_eq!(output, (0.0, 0.0, false));
+
+        // The first counts only start the velocity estimate
+        runtime.set_time(Duration::from_millis(10));
+        let output = block.process(&parameters, &runtime.context(), (-40.0, 0.01, false, 0.0));
+        assert_eq!(output, (-0.1, 0.0, false));
+
+        // The index arrived at count -20, which becomes the zero position
+        runtime.set_time(Duration::from_millis(20));
+        let output = block.process(&parameters, &runtime.context(), (-60.0, 0.02, true, -20.0));
+        assert_eq!(output.0, -0.1);
+        assert_relative_eq!(output.1, -5.0, epsilon = 1e-9);
+        assert!(output.2);
+
+        // Later index pulses don't move the zero position, and the velocity decays while the
+        // encoder is still
+        runtime.set_time(Duration::from_millis(30));
+        let output = block.process(&parameters, &runtime.context(), (-60.0, 0.0, true, -420.0));
+        assert_eq!(output.0, -0.1);
+        assert_relative_eq!(output.1, -0.25, epsilon = 1e-9);
+        assert_eq!(block.data.scalar(), -0.1);
+    }
+}
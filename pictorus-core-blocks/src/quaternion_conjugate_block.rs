@@ -0,0 +1,77 @@
+use crate::rotation::{from_quaternion, to_quaternion};
+use crate::traits::Float;
+use corelib_traits::{Context, Matrix, PassBy, ProcessBlock};
+use utils::{BlockData as OldBlockData, FromPass};
+
+pub struct Parameters {
+    // No parameters needed for this block
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parameters {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+/// Outputs the conjugate `[w, -x, -y, -z]` of a quaternion. For a unit quaternion this is the
+/// inverse rotation, the orientation of the world in the body frame.
+pub struct QuaternionConjugateBlock<F: Float> {
+    pub data: OldBlockData,
+    output: Matrix<4, 1, F>,
+}
+
+impl<F: Float> Default for QuaternionConjugateBlock<F>
+where
+    OldBlockData: FromPass<Matrix<4, 1, F>>,
+{
+    fn default() -> Self {
+        Self {
+            data: <OldBlockData as FromPass<Matrix<4, 1, F>>>::from_pass(&Matrix::zeroed()),
+            output: Matrix::zeroed(),
+        }
+    }
+}
+
+impl<F: Float> ProcessBlock for QuaternionConjugateBlock<F>
+where
+    OldBlockData: FromPass<Matrix<4, 1, F>>,
+{
+    type Inputs = Matrix<4, 1, F>;
+    type Output = Matrix<4, 1, F>;
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        _parameters: &Self::Parameters,
+        _context: &dyn Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        self.output = from_quaternion(&to_quaternion(input).conjugate());
+        self.data = OldBlockData::from_pass(&self.output);
+        &self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corelib_traits_testing::StubContext;
+
+    #[test]
+    fn test_quaternion_conjugate_block() {
+        let context = StubContext::default();
+        let mut block = QuaternionConjugateBlock::<f64>::default();
+        let input = Matrix {
+            data: [[0.5, 0.5, -0.5, 0.5]],
+        };
+        let output = block.process(&Parameters::new(), &context, &input);
+        assert_eq!(output.data[0], [0.5, -0.5, 0.5, -0.5]);
+        assert_eq!(block.data.get_data()[1], -0.5);
+    }
+}
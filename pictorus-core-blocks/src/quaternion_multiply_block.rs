@@ -0,0 +1,98 @@
+use crate::rotation::{from_quaternion, to_quaternion};
+use crate::traits::Float;
+use corelib_traits::{Context, Matrix, PassBy, ProcessBlock};
+use utils::{BlockData as OldBlockData, FromPass};
+
+pub struct Parameters {
+    // No parameters needed for this block
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parameters {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+/// Multiplies two `[w, x, y, z]` quaternions, `q1·q2`. For unit quaternions this is the
+/// rotation `q2` followed by `q1`; if `q2` is the orientation of a body in a frame and `q1` the
+/// orientation of that frame in the world, the product is the orientation of the body in the
+/// world.
+pub struct QuaternionMultiplyBlock<F: Float> {
+    pub data: OldBlockData,
+    output: Matrix<4, 1, F>,
+}
+
+impl<F: Float> Default for QuaternionMultiplyBlock<F>
+where
+    OldBlockData: FromPass<Matrix<4, 1, F>>,
+{
+    fn default() -> Self {
+        Self {
+            data: <OldBlockData as FromPass<Matrix<4, 1, F>>>::from_pass(&Matrix::zeroed()),
+            output: Matrix::zeroed(),
+        }
+    }
+}
+
+impl<F: Float> ProcessBlock for QuaternionMultiplyBlock<F>
+where
+    OldBlockData: FromPass<Matrix<4, 1, F>>,
+{
+    type Inputs = (Matrix<4, 1, F>, Matrix<4, 1, F>);
+    type Output = Matrix<4, 1, F>;
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        _parameters: &Self::Parameters,
+        _context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let (q1, q2) = inputs;
+        self.output = from_quaternion(&(to_quaternion(q1) * to_quaternion(q2)));
+        self.data = OldBlockData::from_pass(&self.output);
+        &self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use core::f64::consts::FRAC_1_SQRT_2;
+    use corelib_traits_testing::StubContext;
+
+    #[test]
+    fn test_quaternion_multiply_block() {
+        let context = StubContext::default();
+        let mut block = QuaternionMultiplyBlock::<f64>::default();
+
+        // i·j = k
+        let i = Matrix {
+            data: [[0.0, 1.0, 0.0, 0.0]],
+        };
+        let j = Matrix {
+            data: [[0.0, 0.0, 1.0, 0.0]],
+        };
+        let output = block.process(&Parameters::new(), &context, (&i, &j));
+        assert_eq!(output.data[0], [0.0, 0.0, 0.0, 1.0]);
+
+        // Two quarter turns about z make a half turn
+        let quarter = Matrix {
+            data: [[FRAC_1_SQRT_2, 0.0, 0.0, FRAC_1_SQRT_2]],
+        };
+        let output = block.process(&Parameters::new(), &context, (&quarter, &quarter));
+        assert_relative_eq!(
+            output.data[0][..],
+            [0.0, 0.0, 0.0, 1.0][..],
+            epsilon = 1e-12
+        );
+        assert_relative_eq!(block.data.get_data()[3], 1.0, epsilon = 1e-12);
+    }
+}
@@ -0,0 +1,89 @@
+use crate::rotation::{from_quaternion, to_quaternion};
+use crate::traits::Float;
+use corelib_traits::{Context, Matrix, PassBy, ProcessBlock};
+use nalgebra::UnitQuaternion;
+use utils::{BlockData as OldBlockData, FromPass};
+
+pub struct Parameters {
+    // No parameters needed for this block
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parameters {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+/// Scales a quaternion to unit length, removing the drift that builds up when quaternions are
+/// integrated or multiplied repeatedly. The output keeps the sign of the input. A zero or
+/// non-finite quaternion outputs the identity.
+pub struct QuaternionNormalizeBlock<F: Float> {
+    pub data: OldBlockData,
+    output: Matrix<4, 1, F>,
+}
+
+impl<F: Float> Default for QuaternionNormalizeBlock<F>
+where
+    OldBlockData: FromPass<Matrix<4, 1, F>>,
+{
+    fn default() -> Self {
+        let output = from_quaternion(UnitQuaternion::identity().quaternion());
+        Self {
+            data: <OldBlockData as FromPass<Matrix<4, 1, F>>>::from_pass(&output),
+            output,
+        }
+    }
+}
+
+impl<F: Float> ProcessBlock for QuaternionNormalizeBlock<F>
+where
+    OldBlockData: FromPass<Matrix<4, 1, F>>,
+{
+    type Inputs = Matrix<4, 1, F>;
+    type Output = Matrix<4, 1, F>;
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        _parameters: &Self::Parameters,
+        _context: &dyn Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let quaternion = to_quaternion(input);
+        let norm = quaternion.norm();
+        self.output = if num_traits::Float::is_normal(norm) {
+            from_quaternion(&(quaternion / norm))
+        } else {
+            from_quaternion(UnitQuaternion::identity().quaternion())
+        };
+        self.data = OldBlockData::from_pass(&self.output);
+        &self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corelib_traits_testing::StubContext;
+
+    #[test]
+    fn test_quaternion_normalize_block() {
+        let context = StubContext::default();
+        let mut block = QuaternionNormalizeBlock::<f64>::default();
+        let input = Matrix {
+            data: [[-2.0, 2.0, 0.0, 1.0]],
+        };
+        let output = block.process(&Parameters::new(), &context, &input);
+        assert_eq!(output.data[0], [-2.0 / 3.0, 2.0 / 3.0, 0.0, 1.0 / 3.0]);
+
+        let output = block.process(&Parameters::new(), &context, &Matrix::zeroed());
+        assert_eq!(output.data[0], [1.0, 0.0, 0.0, 0.0]);
+        assert_eq!(block.data.get_data()[0], 1.0);
+    }
+}
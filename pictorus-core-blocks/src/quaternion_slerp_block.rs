@@ -0,0 +1,111 @@
+use crate::rotation::Rotation;
+use crate::traits::Float;
+use corelib_traits::{Context, Matrix, PassBy, ProcessBlock};
+use nalgebra::UnitQuaternion;
+use utils::{BlockData as OldBlockData, FromPass};
+
+pub struct Parameters {
+    // No parameters needed for this block
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parameters {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+/// Interpolates between two rotations given as `[w, x, y, z]` quaternions, at a fraction of
+/// the way from the first to the second, clamped to `[0, 1]`.
+///
+/// The interpolation is spherical: the output turns at a constant rate about a single axis as
+/// the fraction goes from 0 to 1, along the shorter way between the two rotations. The inputs
+/// are normalized, and the output is a unit quaternion.
+pub struct QuaternionSlerpBlock<F: Float> {
+    pub data: OldBlockData,
+    output: Matrix<4, 1, F>,
+}
+
+impl<F: Float> Default for QuaternionSlerpBlock<F>
+where
+    OldBlockData: FromPass<Matrix<4, 1, F>>,
+{
+    fn default() -> Self {
+        let output = Matrix::<4, 1, F>::from_unit_quaternion(&UnitQuaternion::identity());
+        Self {
+            data: <OldBlockData as FromPass<Matrix<4, 1, F>>>::from_pass(&output),
+            output,
+        }
+    }
+}
+
+impl<F: Float> ProcessBlock for QuaternionSlerpBlock<F>
+where
+    OldBlockData: FromPass<Matrix<4, 1, F>>,
+{
+    type Inputs = (Matrix<4, 1, F>, Matrix<4, 1, F>, F);
+    type Output = Matrix<4, 1, F>;
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        _parameters: &Self::Parameters,
+        _context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let (from, to, fraction) = inputs;
+        let from = Matrix::<4, 1, F>::to_unit_quaternion(from);
+        let to = Matrix::<4, 1, F>::to_unit_quaternion(to);
+        let fraction = num_traits::Float::clamp(fraction, F::zero(), F::one());
+        // Only fails when the rotations are the same
+        let output = from.try_slerp(&to, fraction, F::EPSILON).unwrap_or(from);
+        self.output = Matrix::<4, 1, F>::from_unit_quaternion(&output);
+        self.data = OldBlockData::from_pass(&self.output);
+        &self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use core::f64::consts::FRAC_PI_2;
+    use corelib_traits_testing::StubContext;
+
+    #[test]
+    fn test_quaternion_slerp_block() {
+        let context = StubContext::default();
+        let mut block = QuaternionSlerpBlock::<f64>::default();
+        let yaw = |angle: f64| {
+            Matrix::<4, 1, f64>::from_unit_quaternion(&UnitQuaternion::from_euler_angles(
+                0.0, 0.0, angle,
+            ))
+        };
+
+        let output = block.process(
+            &Parameters::new(),
+            &context,
+            (&yaw(0.0), &yaw(FRAC_PI_2), 0.25),
+        );
+        assert_relative_eq!(
+            output.data[0][..],
+            yaw(FRAC_PI_2 / 4.0).data[0][..],
+            epsilon = 1e-12
+        );
+
+        // -q is the same rotation as q, and the interpolation doesn't go the long way round
+        let mut negated = yaw(FRAC_PI_2);
+        negated.data[0].iter_mut().for_each(|q| *q = -*q);
+        let output = block.process(&Parameters::new(), &context, (&yaw(0.0), &negated, 0.5));
+        let (_, _, angle) = Matrix::<4, 1, f64>::to_unit_quaternion(output).euler_angles();
+        assert_relative_eq!(angle, FRAC_PI_2 / 2.0, epsilon = 1e-12);
+
+        let output = block.process(&Parameters::new(), &context, (&yaw(0.0), &yaw(1.0), 2.0));
+        assert_relative_eq!(output.data[0][..], yaw(1.0).data[0][..], epsilon = 1e-12);
+    }
+}
@@ -0,0 +1,162 @@
+//! Rotations carried as matrix signals, and their conversions.
+//!
+//! A rotation can be carried in three forms:
+//! - a quaternion, as a `Matrix<4, 1, F>` holding `[w, x, y, z]`
+//! - a rotation matrix, as a `Matrix<3, 3, F>`
+//! - Euler angles, as a `Matrix<3, 1, F>` holding `[roll, pitch, yaw]` in radians
+//!
+//! Each form describes the orientation of a body frame in a world frame: it rotates vectors
+//! from the body frame into the world frame, so the columns of the rotation matrix are the
+//! body axes expressed in world coordinates. The Euler angles use the ZYX (yaw, pitch, roll)
+//! sequence: the body is yawed about the world z axis, then pitched about its new y axis, then
+//! rolled about its new x axis, so the rotation matrix is `Rz(yaw)·Ry(pitch)·Rx(roll)`. The
+//! angles are right-handed, so in a world frame with z up a positive pitch lowers the nose.
+use crate::traits::Float;
+use corelib_traits::{Matrix, Pass, PassBy};
+use nalgebra::{Quaternion, UnitQuaternion, Vector3};
+use pictorus_nalgebra_interop::MatrixExt;
+
+/// A signal that carries a rotation
+pub trait Rotation: Pass + Sized {
+    type Float: Float;
+
+    fn to_unit_quaternion(rotation: PassBy<'_, Self>) -> UnitQuaternion<Self::Float>;
+
+    fn from_unit_quaternion(rotation: &UnitQuaternion<Self::Float>) -> Self;
+}
+
+/// The quaternion in a `[w, x, y, z]` signal, which may not be a unit quaternion
+pub(crate) fn to_quaternion<F: Float>(quaternion: &Matrix<4, 1, F>) -> Quaternion<F> {
+    let [w, x, y, z] = quaternion.data[0];
+    Quaternion::new(w, x, y, z)
+}
+
+pub(crate) fn from_quaternion<F: Float>(quaternion: &Quaternion<F>) -> Matrix<4, 1, F> {
+    Matrix {
+        data: [[quaternion.w, quaternion.i, quaternion.j, quaternion.k]],
+    }
+}
+
+/// Quaternions are normalized; a zero quaternion is the identity
+impl<F: Float> Rotation for Matrix<4, 1, F> {
+    type Float = F;
+
+    fn to_unit_quaternion(rotation: PassBy<'_, Self>) -> UnitQuaternion<F> {
+        UnitQuaternion::try_new(to_quaternion(rotation), F::zero())
+            .unwrap_or_else(UnitQuaternion::identity)
+    }
+
+    fn from_unit_quaternion(rotation: &UnitQuaternion<F>) -> Self {
+        from_quaternion(rotation.quaternion())
+    }
+}
+
+/// Rotation matrices that aren't quite orthonormal, from accumulated rounding or a hand-entered
+/// calibration, are replaced with the nearest rotation
+impl<F: Float> Rotation for Matrix<3, 3, F> {
+    type Float = F;
+
+    fn to_unit_quaternion(rotation: PassBy<'_, Self>) -> UnitQuaternion<F> {
+        UnitQuaternion::from_matrix(&rotation.as_view().into_owned())
+    }
+
+    fn from_unit_quaternion(rotation: &UnitQuaternion<F>) -> Self {
+        Matrix::from_view(&rotation.to_rotation_matrix().matrix().as_view())
+    }
+}
+
+/// At a pitch of ±90° the roll and yaw rotate about the same axis. The conversion from other
+/// forms then puts all of it in the roll and leaves the yaw at zero.
+impl<F: Float> Rotation for Matrix<3, 1, F> {
+    type Float = F;
+
+    fn to_unit_quaternion(rotation: PassBy<'_, Self>) -> UnitQuaternion<F> {
+        let [roll, pitch, yaw] = rotation.data[0];
+        UnitQuaternion::from_euler_angles(roll, pitch, yaw)
+    }
+
+    fn from_unit_quaternion(rotation: &UnitQuaternion<F>) -> Self {
+        let (roll, pitch, yaw) = rotation.euler_angles();
+        Matrix {
+            data: [[roll, pitch, yaw]],
+        }
+    }
+}
+
+/// A signal that carries a 3D vector, as a row or a column
+pub trait Vector: Pass + Sized {
+    type Float: Float;
+
+    fn to_vector3(vector: PassBy<'_, Self>) -> Vector3<Self::Float>;
+
+    fn from_vector3(vector: &Vector3<Self::Float>) -> Self;
+}
+
+impl<F: Float> Vector for Matrix<3, 1, F> {
+    type Float = F;
+
+    fn to_vector3(vector: PassBy<'_, Self>) -> Vector3<F> {
+        Vector3::from(vector.data[0])
+    }
+
+    fn from_vector3(vector: &Vector3<F>) -> Self {
+        Matrix {
+            data: [[vector.x, vector.y, vector.z]],
+        }
+    }
+}
+
+impl<F: Float> Vector for Matrix<1, 3, F> {
+    type Float = F;
+
+    fn to_vector3(vector: PassBy<'_, Self>) -> Vector3<F> {
+        let [[x], [y], [z]] = vector.data;
+        Vector3::new(x, y, z)
+    }
+
+    fn from_vector3(vector: &Vector3<F>) -> Self {
+        Matrix {
+            data: [[vector.x], [vector.y], [vector.z]],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use core::f64::consts::FRAC_PI_2;
+
+    #[test]
+    fn test_rotation_conventions() {
+        // Yawed 90°, then pitched 90°: the body x axis points down, the body y axis along the
+        // world -x axis and the body z axis along the world y axis
+        let euler = Matrix {
+            data: [[0.0, FRAC_PI_2, FRAC_PI_2]],
+        };
+        let quaternion = Matrix::<3, 1, f64>::to_unit_quaternion(&euler);
+        let matrix = Matrix::<3, 3, f64>::from_unit_quaternion(&quaternion);
+        assert_relative_eq!(matrix.data[0][..], [0.0, 0.0, -1.0][..], epsilon = 1e-12);
+        assert_relative_eq!(matrix.data[1][..], [-1.0, 0.0, 0.0][..], epsilon = 1e-12);
+        assert_relative_eq!(matrix.data[2][..], [0.0, 1.0, 0.0][..], epsilon = 1e-12);
+
+        // Back through every form
+        let quaternion = Matrix::<4, 1, f64>::from_unit_quaternion(
+            &Matrix::<3, 3, f64>::to_unit_quaternion(&Matrix::<3, 3, f64>::from_unit_quaternion(
+                &Matrix::<3, 1, f64>::to_unit_quaternion(&Matrix {
+                    data: [[0.3, -0.4, 2.5]],
+                }),
+            )),
+        );
+        let euler = Matrix::<3, 1, f64>::from_unit_quaternion(
+            &Matrix::<4, 1, f64>::to_unit_quaternion(&quaternion),
+        );
+        assert_relative_eq!(euler.data[0][..], [0.3, -0.4, 2.5][..], epsilon = 1e-12);
+
+        let zero = Matrix::<4, 1, f64>::zeroed();
+        assert_eq!(
+            Matrix::<4, 1, f64>::to_unit_quaternion(&zero),
+            UnitQuaternion::identity()
+        );
+    }
+}
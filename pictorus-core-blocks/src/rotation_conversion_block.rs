@@ -0,0 +1,99 @@
+use crate::rotation::Rotation;
+use core::marker::PhantomData;
+use corelib_traits::{Context, PassBy, ProcessBlock};
+use utils::{BlockData as OldBlockData, FromPass};
+
+pub struct Parameters {
+    // No parameters needed for this block
+}
+
+impl Default for Parameters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parameters {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+/// Converts a rotation from one form to another: a quaternion (`Matrix<4, 1>`), a rotation
+/// matrix (`Matrix<3, 3>`) or ZYX Euler angles (`Matrix<3, 1>`). See [`crate::rotation`] for
+/// the conventions.
+pub struct RotationConversionBlock<I: Rotation, O: Rotation<Float = I::Float>> {
+    pub data: OldBlockData,
+    output: O,
+    _input: PhantomData<I>,
+}
+
+impl<I, O> Default for RotationConversionBlock<I, O>
+where
+    I: Rotation,
+    O: Rotation<Float = I::Float>,
+    OldBlockData: FromPass<O>,
+{
+    fn default() -> Self {
+        let output = O::from_unit_quaternion(&nalgebra::UnitQuaternion::identity());
+        Self {
+            data: <OldBlockData as FromPass<O>>::from_pass(output.as_by()),
+            output,
+            _input: PhantomData,
+        }
+    }
+}
+
+impl<I, O> ProcessBlock for RotationConversionBlock<I, O>
+where
+    I: Rotation,
+    O: Rotation<Float = I::Float>,
+    OldBlockData: FromPass<O>,
+{
+    type Inputs = I;
+    type Output = O;
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        _parameters: &Self::Parameters,
+        _context: &dyn Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        self.output = O::from_unit_quaternion(&I::to_unit_quaternion(input));
+        self.data = OldBlockData::from_pass(self.output.as_by());
+        self.output.as_by()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use core::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_2};
+    use corelib_traits::Matrix;
+    use corelib_traits_testing::StubContext;
+
+    #[test]
+    fn test_rotation_conversion_block() {
+        let context = StubContext::default();
+        let mut block = RotationConversionBlock::<Matrix<3, 1, f64>, Matrix<4, 1, f64>>::default();
+        assert_eq!(block.data.get_data()[0], 1.0);
+
+        // A quarter turn about z
+        let euler = Matrix {
+            data: [[0.0, 0.0, FRAC_PI_2]],
+        };
+        let quaternion = *block.process(&Parameters::new(), &context, &euler);
+        assert_relative_eq!(
+            quaternion.data[0][..],
+            [FRAC_1_SQRT_2, 0.0, 0.0, FRAC_1_SQRT_2][..],
+            epsilon = 1e-12
+        );
+
+        let mut block = RotationConversionBlock::<Matrix<4, 1, f64>, Matrix<3, 3, f64>>::default();
+        let matrix = block.process(&Parameters::new(), &context, &quaternion);
+        assert_relative_eq!(matrix.data[0][..], [0.0, 1.0, 0.0][..], epsilon = 1e-12);
+        assert_relative_eq!(matrix.data[1][..], [-1.0, 0.0, 0.0][..], epsilon = 1e-12);
+    }
+}
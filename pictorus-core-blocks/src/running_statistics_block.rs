@@ -0,0 +1,501 @@
+use crate::traits::Float;
+use core::str::FromStr;
+use corelib_traits::{Context, PassBy, ProcessBlock};
+use heapless::Deque;
+use utils::{BlockData as OldBlockData, ParseEnumError};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Statistic {
+    Mean,
+    /// Population variance, normalized by the number of samples
+    Variance,
+    StdDev,
+    Rms,
+    Skewness,
+    /// Kurtosis, which is 3 for a normal distribution and larger for heavier tails
+    Kurtosis,
+    /// Largest magnitude divided by the RMS
+    CrestFactor,
+    Percentile,
+}
+
+impl FromStr for Statistic {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Mean" => Ok(Self::Mean),
+            "Variance" => Ok(Self::Variance),
+            "StdDev" => Ok(Self::StdDev),
+            "Rms" => Ok(Self::Rms),
+            "Skewness" => Ok(Self::Skewness),
+            "Kurtosis" => Ok(Self::Kurtosis),
+            "CrestFactor" => Ok(Self::CrestFactor),
+            "Percentile" => Ok(Self::Percentile),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Averaging {
+    /// Equal weight to each of the last `N` samples
+    Window,
+    /// Weights decaying exponentially with the age of the sample
+    Exponential,
+}
+
+impl FromStr for Averaging {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Window" => Ok(Self::Window),
+            "Exponential" => Ok(Self::Exponential),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+/// Parameters for the Running Statistics Block
+pub struct Parameters {
+    pub statistic: Statistic,
+    pub averaging: Averaging,
+    /// Time constant of the exponential forgetting, in seconds
+    pub time_constant_s: f64,
+    /// Percentile to output, between 0 and 100
+    pub percentile: f64,
+}
+
+impl Parameters {
+    pub fn new(statistic: &str, averaging: &str, time_constant_s: f64, percentile: f64) -> Self {
+        Self {
+            statistic: statistic.parse().expect("Invalid statistic"),
+            averaging: averaging.parse().expect("Invalid averaging"),
+            time_constant_s,
+            percentile,
+        }
+    }
+}
+
+/// Weight of a new sample under exponential forgetting with the given time constant
+pub(crate) fn forgetting_weight<F: Float>(context: &dyn Context, time_constant_s: f64) -> F {
+    let dt = context
+        .timestep()
+        .unwrap_or(context.fundamental_timestep())
+        .as_secs_f64();
+    let weight = if time_constant_s > 0.0 {
+        1.0 - num_traits::Float::exp(-dt / time_constant_s)
+    } else {
+        1.0
+    };
+    F::from(weight).unwrap()
+}
+
+/// Mean and sums of the powers of the deviations from it, updated one sample at a time with
+/// Welford's method, extended by Pébay to the third and fourth powers. Samples can be removed
+/// by running the update backwards.
+#[derive(Debug, Clone, Copy)]
+struct Moments<F: Float> {
+    count: F,
+    mean: F,
+    m2: F,
+    m3: F,
+    m4: F,
+}
+
+impl<F: Float> Moments<F> {
+    fn new() -> Self {
+        Self {
+            count: F::zero(),
+            mean: F::zero(),
+            m2: F::zero(),
+            m3: F::zero(),
+            m4: F::zero(),
+        }
+    }
+
+    /// The terms shared by adding and removing `x`, where `count` includes `x` and `mean`
+    /// excludes it
+    fn terms(count: F, mean: F, x: F) -> (F, F, F) {
+        let delta = x - mean;
+        let delta_n = delta / count;
+        let term = delta * delta_n * (count - F::one());
+        let three = F::from(3.0).unwrap();
+        let m4_term = term * delta_n * delta_n * (count * count - three * count + three);
+        (delta_n, term, m4_term)
+    }
+
+    fn push(&mut self, x: F) {
+        let old = *self;
+        self.count = old.count + F::one();
+        let (delta_n, term, m4_term) = Self::terms(self.count, old.mean, x);
+        let three = F::from(3.0).unwrap();
+        let four = F::from(4.0).unwrap();
+        let six = F::from(6.0).unwrap();
+        self.mean = old.mean + delta_n;
+        self.m4 = old.m4 + m4_term + six * delta_n * delta_n * old.m2 - four * delta_n * old.m3;
+        self.m3 =
+            old.m3 + term * delta_n * (self.count - F::one() - F::one()) - three * delta_n * old.m2;
+        self.m2 = old.m2 + term;
+    }
+
+    fn pop(&mut self, x: F) {
+        if self.count <= F::one() {
+            *self = Self::new();
+            return;
+        }
+        let new = *self;
+        self.mean = (new.count * new.mean - x) / (new.count - F::one());
+        let (delta_n, term, m4_term) = Self::terms(new.count, self.mean, x);
+        let three = F::from(3.0).unwrap();
+        let four = F::from(4.0).unwrap();
+        let six = F::from(6.0).unwrap();
+        self.m2 = new.m2 - term;
+        self.m3 =
+            new.m3 - term * delta_n * (new.count - F::one() - F::one()) + three * delta_n * self.m2;
+        self.m4 = new.m4 - m4_term - six * delta_n * delta_n * self.m2 + four * delta_n * self.m3;
+        self.count = new.count - F::one();
+    }
+
+    /// Mean and central moments, normalized by the number of samples
+    fn summary(&self) -> Summary<F> {
+        if self.count <= F::zero() {
+            return Summary::default();
+        }
+        Summary {
+            mean: self.mean,
+            variance: num_traits::Float::max(self.m2 / self.count, F::zero()),
+            third: self.m3 / self.count,
+            fourth: num_traits::Float::max(self.m4 / self.count, F::zero()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Summary<F: Float> {
+    mean: F,
+    variance: F,
+    third: F,
+    fourth: F,
+}
+
+impl<F: Float> Default for Summary<F> {
+    fn default() -> Self {
+        Self {
+            mean: F::zero(),
+            variance: F::zero(),
+            third: F::zero(),
+            fourth: F::zero(),
+        }
+    }
+}
+
+impl<F: Float> Summary<F> {
+    /// Mixes in a new sample with weight `alpha`, shifting the central moments of the old
+    /// samples to the new mean
+    fn update(&mut self, x: F, alpha: F) {
+        let keep = F::one() - alpha;
+        let delta = x - self.mean;
+        let shift = alpha * delta;
+        let deviation = keep * delta;
+        let three = F::from(3.0).unwrap();
+        let four = F::from(4.0).unwrap();
+        let six = F::from(6.0).unwrap();
+        self.mean += shift;
+        self.fourth = keep
+            * (self.fourth - four * shift * self.third
+                + six * shift * shift * self.variance
+                + shift * shift * shift * shift)
+            + alpha * deviation * deviation * deviation * deviation;
+        self.third = keep * (self.third - three * shift * self.variance - shift * shift * shift)
+            + alpha * deviation * deviation * deviation;
+        self.variance = keep * (self.variance + alpha * delta * delta);
+    }
+
+    fn rms(&self) -> F {
+        num_traits::Float::sqrt(self.mean * self.mean + self.variance)
+    }
+}
+
+/// The Running Statistics Block computes a statistic of its input over time.
+///
+/// With `Window` averaging the statistic covers the last `N` samples, or all of them until `N`
+/// have arrived. The moments are updated as each sample enters and leaves the window, and
+/// recomputed from the window each time it has been replaced, so rounding errors can't build
+/// up. With `Exponential` averaging every sample counts, with a weight that decays with the
+/// given time constant; the first sample starts the statistics. The percentile is then
+/// tracked by nudging it up or down with each sample, in steps proportional to the standard
+/// deviation, and the peak magnitude for the crest factor decays with the same time constant.
+///
+/// Variance-based statistics of a constant input are zero, including the skewness and
+/// kurtosis, which are otherwise undefined.
+pub struct RunningStatisticsBlock<F: Float, const N: usize> {
+    pub data: OldBlockData,
+    window: Deque<F, N>,
+    moments: Moments<F>,
+    /// Samples since the moments were recomputed from the window
+    since_reseed: usize,
+    exponential: Option<Summary<F>>,
+    peak: F,
+    quantile: F,
+    output: F,
+}
+
+impl<F: Float, const N: usize> Default for RunningStatisticsBlock<F, N> {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::from_scalar(0.0),
+            window: Deque::new(),
+            moments: Moments::new(),
+            since_reseed: 0,
+            exponential: None,
+            peak: F::zero(),
+            quantile: F::zero(),
+            output: F::zero(),
+        }
+    }
+}
+
+impl<F: Float, const N: usize> RunningStatisticsBlock<F, N> {
+    fn push_window(&mut self, x: F) -> Summary<F> {
+        if self.window.is_full() {
+            if let Some(oldest) = self.window.pop_front() {
+                self.moments.pop(oldest);
+            }
+        }
+        // Can't fail, there's room after removing the oldest sample
+        self.window.push_back(x).ok();
+        self.moments.push(x);
+
+        self.since_reseed += 1;
+        if self.since_reseed >= N {
+            self.since_reseed = 0;
+            self.moments = Moments::new();
+            for sample in self.window.iter() {
+                self.moments.push(*sample);
+            }
+        }
+        self.peak = self.window.iter().fold(F::zero(), |peak, x| {
+            num_traits::Float::max(peak, num_traits::Float::abs(*x))
+        });
+        self.moments.summary()
+    }
+
+    fn window_percentile(&self, percentile: f64) -> F {
+        let mut sorted = [F::zero(); N];
+        let count = self.window.len();
+        for (slot, sample) in sorted.iter_mut().zip(self.window.iter()) {
+            *slot = *sample;
+        }
+        let sorted = &mut sorted[..count];
+        sorted.sort_unstable_by(|a, b| a.partial_cmp(b).unwrap_or(core::cmp::Ordering::Equal));
+        let Some(last) = count.checked_sub(1) else {
+            return F::zero();
+        };
+        let position = (percentile / 100.0).clamp(0.0, 1.0) * last as f64;
+        let below = position as usize;
+        let above = (below + 1).min(last);
+        let fraction = F::from(position - below as f64).unwrap();
+        sorted[below] + (sorted[above] - sorted[below]) * fraction
+    }
+
+    fn push_exponential(&mut self, x: F, alpha: F, percentile: f64) -> Summary<F> {
+        let magnitude = num_traits::Float::abs(x);
+        match &mut self.exponential {
+            Some(summary) => {
+                // The step is zero on average when the fraction of samples below the
+                // quantile is the percentile
+                let below = if x < self.quantile {
+                    F::one()
+                } else {
+                    F::zero()
+                };
+                let fraction = F::from((percentile / 100.0).clamp(0.0, 1.0)).unwrap();
+                let spread = num_traits::Float::sqrt(summary.variance);
+                self.quantile += alpha * spread * (fraction - below);
+                summary.update(x, alpha);
+                self.peak = num_traits::Float::max(magnitude, self.peak * (F::one() - alpha));
+                *summary
+            }
+            None => {
+                self.quantile = x;
+                self.peak = magnitude;
+                *self.exponential.insert(Summary {
+                    mean: x,
+                    ..Default::default()
+                })
+            }
+        }
+    }
+}
+
+impl<F: Float, const N: usize> ProcessBlock for RunningStatisticsBlock<F, N> {
+    type Inputs = F;
+    type Output = F;
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let summary = match parameters.averaging {
+            Averaging::Window => {
+                let summary = self.push_window(input);
+                if parameters.statistic == Statistic::Percentile {
+                    self.quantile = self.window_percentile(parameters.percentile);
+                }
+                summary
+            }
+            Averaging::Exponential => {
+                let alpha = forgetting_weight(context, parameters.time_constant_s);
+                self.push_exponential(input, alpha, parameters.percentile)
+            }
+        };
+
+        let standardized = |moment: F, power: i32| {
+            if summary.variance > F::zero() {
+                moment / num_traits::Float::powi(num_traits::Float::sqrt(summary.variance), power)
+            } else {
+                F::zero()
+            }
+        };
+        self.output = match parameters.statistic {
+            Statistic::Mean => summary.mean,
+            Statistic::Variance => summary.variance,
+            Statistic::StdDev => num_traits::Float::sqrt(summary.variance),
+            Statistic::Rms => summary.rms(),
+            Statistic::Skewness => standardized(summary.third, 3),
+            Statistic::Kurtosis => standardized(summary.fourth, 4),
+            Statistic::CrestFactor => {
+                let rms = summary.rms();
+                if rms > F::zero() {
+                    self.peak / rms
+                } else {
+                    F::zero()
+                }
+            }
+            Statistic::Percentile => self.quantile,
+        };
+        self.data = OldBlockData::from_scalar(self.output.to_f64().unwrap_or_default());
+        self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use core::time::Duration;
+    use corelib_traits_testing::StubContext;
+
+    fn context() -> StubContext {
+        StubContext {
+            fundamental_timestep: Duration::from_millis(10),
+            ..Default::default()
+        }
+    }
+
+    /// Statistics of the last `N` values, computed directly
+    fn reference(values: &[f64]) -> [f64; 4] {
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let moment = |power: i32| values.iter().map(|x| (x - mean).powi(power)).sum::<f64>() / n;
+        let variance = moment(2);
+        [
+            mean,
+            variance,
+            moment(3) / variance.powf(1.5),
+            moment(4) / (variance * variance),
+        ]
+    }
+
+    #[test]
+    fn test_running_statistics_window() {
+        let context = context();
+        // A large offset, which naive sums of powers would lose to rounding
+        let signal = |i: usize| 1e6 + ((i * 7919) % 101) as f64 / 10.0 + (i % 3) as f64;
+        let statistics = ["Mean", "Variance", "Skewness", "Kurtosis"];
+        let mut blocks: [RunningStatisticsBlock<f64, 50>; 4] = Default::default();
+
+        for i in 0..1000 {
+            let outputs: [f64; 4] = core::array::from_fn(|s| {
+                let parameters = Parameters::new(statistics[s], "Window", 0.0, 50.0);
+                blocks[s].process(&parameters, &context, signal(i))
+            });
+            if i >= 2 {
+                let values: std::vec::Vec<f64> = (i.saturating_sub(49)..=i).map(signal).collect();
+                let expected = reference(&values);
+                for (output, expected) in outputs.iter().zip(expected) {
+                    assert_relative_eq!(*output, expected, epsilon = 1e-9, max_relative = 1e-6);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_running_statistics_window_rms_crest_percentile() {
+        let context = context();
+        let mut rms = RunningStatisticsBlock::<f64, 4>::default();
+        let mut crest = RunningStatisticsBlock::<f64, 4>::default();
+        let mut median = RunningStatisticsBlock::<f64, 4>::default();
+        for x in [10.0, 3.0, -1.0, 1.0, -3.0] {
+            rms.process(&Parameters::new("Rms", "Window", 0.0, 0.0), &context, x);
+            crest.process(
+                &Parameters::new("CrestFactor", "Window", 0.0, 0.0),
+                &context,
+                x,
+            );
+            median.process(
+                &Parameters::new("Percentile", "Window", 0.0, 50.0),
+                &context,
+                x,
+            );
+        }
+        // The window holds 3, -1, 1, -3
+        assert_relative_eq!(rms.output, 5f64.sqrt());
+        assert_relative_eq!(crest.output, 3.0 / 5f64.sqrt());
+        assert_relative_eq!(median.output, 0.0);
+        assert_relative_eq!(median.data.scalar(), 0.0);
+    }
+
+    #[test]
+    fn test_running_statistics_exponential() {
+        let context = context();
+        let mut mean = RunningStatisticsBlock::<f32, 1>::default();
+        let mut std_dev = RunningStatisticsBlock::<f32, 1>::default();
+        let mut skewness = RunningStatisticsBlock::<f32, 1>::default();
+        let mut median = RunningStatisticsBlock::<f32, 1>::default();
+
+        // A square wave between 1 and 5 spends a quarter of the time high, so it has a mean of
+        // 2, a standard deviation of √3 and is skewed upwards
+        for i in 0..20000 {
+            let x = if i % 4 == 0 { 5.0 } else { 1.0 };
+            mean.process(
+                &Parameters::new("Mean", "Exponential", 2.0, 0.0),
+                &context,
+                x,
+            );
+            std_dev.process(
+                &Parameters::new("StdDev", "Exponential", 2.0, 0.0),
+                &context,
+                x,
+            );
+            skewness.process(
+                &Parameters::new("Skewness", "Exponential", 2.0, 0.0),
+                &context,
+                x,
+            );
+            median.process(
+                &Parameters::new("Percentile", "Exponential", 2.0, 50.0),
+                &context,
+                x,
+            );
+        }
+        assert_relative_eq!(mean.output, 2.0, epsilon = 0.05);
+        assert_relative_eq!(std_dev.output, 3f32.sqrt(), epsilon = 0.05);
+        assert_relative_eq!(skewness.output, 2.0 / 3f32.sqrt(), epsilon = 0.05);
+        // Three quarters of the samples are at the low level, so that's the median
+        assert_relative_eq!(median.output, 1.0, epsilon = 0.1);
+    }
+}
@@ -0,0 +1,207 @@
+use core::str::FromStr;
+use core::time::Duration;
+
+use corelib_traits::{Context, PassBy, ProcessBlock};
+use utils::{BlockData as OldBlockData, ParseEnumError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServoMode {
+    /// Commands from -1 to 1 map from the minimum pulse through the center pulse to the
+    /// maximum pulse
+    Servo,
+    /// Commands from 0 to 1 map from the minimum pulse to the maximum pulse, like a throttle
+    Esc,
+}
+
+impl FromStr for ServoMode {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Servo" => Ok(Self::Servo),
+            "Esc" => Ok(Self::Esc),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+/// Parameters for the Servo Output Block
+pub struct Parameters {
+    pub mode: ServoMode,
+    /// PWM frequency the pulses repeat at
+    pub frequency_hz: f64,
+    /// Calibrated pulse width of the lowest command
+    pub min_pulse_us: f64,
+    /// Calibrated pulse width of a zero command in `Servo` mode
+    pub center_pulse_us: f64,
+    /// Calibrated pulse width of the highest command
+    pub max_pulse_us: f64,
+    /// Pulse width while disarmed, and when the command isn't finite
+    pub failsafe_pulse_us: f64,
+    /// Pulse width held while arming, such as the zero throttle an ESC waits for
+    pub arming_pulse_us: f64,
+    /// How long the arming pulse is held before commands are followed
+    pub arming_time: Duration,
+}
+
+impl Parameters {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        mode: &str,
+        frequency_hz: f64,
+        min_pulse_us: f64,
+        center_pulse_us: f64,
+        max_pulse_us: f64,
+        failsafe_pulse_us: f64,
+        arming_pulse_us: f64,
+        arming_time_ms: f64,
+    ) -> Self {
+        Self {
+            mode: mode.parse().expect("Invalid servo mode"),
+            frequency_hz,
+            min_pulse_us,
+            center_pulse_us,
+            max_pulse_us,
+            failsafe_pulse_us,
+            arming_pulse_us,
+            arming_time: Duration::from_secs_f64(arming_time_ms.max(0.0) / 1000.0),
+        }
+    }
+
+    fn command_pulse_us(&self, command: f64) -> f64 {
+        match self.mode {
+            ServoMode::Servo => {
+                let command = command.clamp(-1.0, 1.0);
+                if command >= 0.0 {
+                    self.center_pulse_us + command * (self.max_pulse_us - self.center_pulse_us)
+                } else {
+                    self.center_pulse_us + command * (self.center_pulse_us - self.min_pulse_us)
+                }
+            }
+            ServoMode::Esc => {
+                let command = command.clamp(0.0, 1.0);
+                self.min_pulse_us + command * (self.max_pulse_us - self.min_pulse_us)
+            }
+        }
+    }
+}
+
+/// The Servo Output Block maps a normalised command to the pulse width of a hobby servo or
+/// ESC, for a PWM output running at the block's frequency.
+///
+/// The inputs are the command and whether the output is armed. While disarmed the block
+/// outputs the failsafe pulse. When armed it holds the arming pulse for the arming time, then
+/// follows the command, falling back to the failsafe pulse if the command isn't finite. The
+/// outputs are the PWM duty cycle as a fraction of the period, the pulse width in
+/// microseconds and whether arming has finished.
+pub struct ServoOutputBlock {
+    pub data: OldBlockData,
+    armed_at: Option<Duration>,
+}
+
+impl Default for ServoOutputBlock {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::from_scalar(0.0),
+            armed_at: None,
+        }
+    }
+}
+
+impl ProcessBlock for ServoOutputBlock {
+    type Parameters = Parameters;
+    type Inputs = (f64, bool);
+    type Output = (f64, f64, bool);
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let (command, arm) = inputs;
+        let now = context.time();
+        let (pulse_us, armed) = if arm {
+            let armed_at = *self.armed_at.get_or_insert(now);
+            if now.saturating_sub(armed_at) < parameters.arming_time {
+                (parameters.arming_pulse_us, false)
+            } else if command.is_finite() {
+                (parameters.command_pulse_us(command), true)
+            } else {
+                (parameters.failsafe_pulse_us, true)
+            }
+        } else {
+            self.armed_at = None;
+            (parameters.failsafe_pulse_us, false)
+        };
+
+        let duty_cycle = (pulse_us * 1e-6 * parameters.frequency_hz).clamp(0.0, 1.0);
+        self.data = OldBlockData::from_scalar(pulse_us);
+        (duty_cycle, pulse_us, armed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use corelib_traits_testing::{StubContext, StubRuntime};
+
+    #[test]
+    fn test_servo_output_block_maps_command() {
+        let parameters = Parameters::new("Servo", 50.0, 1000.0, 1400.0, 2000.0, 1500.0, 0.0, 0.0);
+        let context = StubContext::default();
+        let mut block = ServoOutputBlock::default();
+
+        let cases = [
+            (0.0, 1400.0),
+            (1.0, 2000.0),
+            (-1.0, 1000.0),
+            (0.5, 1700.0),
+            (-0.5, 1200.0),
+            (3.0, 2000.0),
+            (f64::NAN, 1500.0),
+        ];
+        for (command, expected) in cases {
+            let (duty_cycle, pulse_us, armed) =
+                block.process(&parameters, &context, (command, true));
+            assert_eq!(pulse_us, expected, "command {command}");
+            assert_relative_eq!(duty_cycle, expected * 50e-6);
+            assert!(armed);
+        }
+
+        let (_, pulse_us, armed) = block.process(&parameters, &context, (1.0, false));
+        assert_eq!(pulse_us, 1500.0);
+        assert!(!armed);
+        assert_eq!(block.data.scalar(), 1500.0);
+    }
+
+    #[test]
+    fn test_servo_output_block_arms_esc() {
+        let parameters = Parameters::new("Esc", 400.0, 1100.0, 0.0, 1900.0, 900.0, 1000.0, 250.0);
+        let mut runtime = StubRuntime::default();
+        let mut block = ServoOutputBlock::default();
+
+        let output = block.process(&parameters, &runtime.context(), (0.5, false));
+        assert_eq!(output.1, 900.0);
+
+        // Arming holds the arming pulse for 250 ms before following the throttle
+        runtime.tick();
+        for _ in 0..2 {
+            let output = block.process(&parameters, &runtime.context(), (0.5, true));
+            assert_eq!((output.1, output.2), (1000.0, false));
+            runtime.tick();
+        }
+        runtime.tick();
+        let (duty_cycle, pulse_us, armed) =
+            block.process(&parameters, &runtime.context(), (0.5, true));
+        assert_eq!((pulse_us, armed), (1500.0, true));
+        assert_relative_eq!(duty_cycle, 0.6);
+
+        // Disarming resets the sequence
+        runtime.tick();
+        block.process(&parameters, &runtime.context(), (0.5, false));
+        runtime.tick();
+        let output = block.process(&parameters, &runtime.context(), (0.5, true));
+        assert_eq!((output.1, output.2), (1000.0, false));
+    }
+}
@@ -0,0 +1,113 @@
+use crate::hysteresis_block::hysteresis;
+use crate::traits::Float;
+use corelib_traits::{Context, PassBy, ProcessBlock};
+use utils::BlockData as OldBlockData;
+
+/// Parameters for the Time Over Threshold Block
+pub struct Parameters {
+    /// The input exceeds the threshold when it rises above this
+    pub threshold: f64,
+    /// How far below the threshold the input must fall to end an exceedance
+    pub hysteresis: f64,
+}
+
+impl Parameters {
+    pub fn new(threshold: f64, hysteresis: f64) -> Self {
+        Self {
+            threshold,
+            hysteresis,
+        }
+    }
+}
+
+/// Whether the input is over the threshold, how long it has been over it, the total time over
+/// it, the duration of the last completed exceedance and the number of exceedances, with the
+/// times in seconds
+type Exceedance<F> = (bool, F, F, F, F);
+
+/// The Time Over Threshold Block measures how long its input exceeds a threshold.
+///
+/// An exceedance starts when the input rises above `threshold` and ends when it falls below
+/// `threshold - hysteresis`. Each tick of an exceedance adds its timestep to the durations. The
+/// outputs are whether the input is over the threshold, the duration of the current
+/// exceedance, the total time over the threshold since startup, the duration of the last
+/// completed exceedance and the number of exceedances so far. NaN inputs leave the state as it
+/// was.
+pub struct TimeOverThresholdBlock<F: Float> {
+    pub data: OldBlockData,
+    output: Exceedance<F>,
+}
+
+impl<F: Float> Default for TimeOverThresholdBlock<F> {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::scalar_from_bool(false),
+            output: (false, F::zero(), F::zero(), F::zero(), F::zero()),
+        }
+    }
+}
+
+impl<F: Float> ProcessBlock for TimeOverThresholdBlock<F> {
+    type Inputs = F;
+    type Output = Exceedance<F>;
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let (was_over, duration, total, last_duration, count) = self.output;
+        let threshold = F::from(parameters.threshold).unwrap();
+        let low = threshold - F::from(parameters.hysteresis.abs()).unwrap();
+        let over = hysteresis(was_over, input, low, threshold);
+        let dt = F::from_duration(context.timestep().unwrap_or(context.fundamental_timestep()));
+
+        self.output = match (was_over, over) {
+            (false, true) => (true, dt, total + dt, last_duration, count + F::one()),
+            (true, true) => (true, duration + dt, total + dt, last_duration, count),
+            (true, false) => (false, F::zero(), total, duration, count),
+            (false, false) => (false, F::zero(), total, last_duration, count),
+        };
+        self.data.set_scalar_bool(over);
+        self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use corelib_traits_testing::StubRuntime;
+
+    #[test]
+    fn test_time_over_threshold_block() {
+        let parameters = Parameters::new(1.0, 0.2);
+        let mut runtime = StubRuntime::default();
+        let mut block = TimeOverThresholdBlock::<f64>::default();
+
+        let signal = [0.5, 1.2, 1.5, 0.9, 1.1, 0.7, 0.0, 2.0, 0.0];
+        let mut outputs = std::vec::Vec::new();
+        for input in signal {
+            runtime.tick();
+            outputs.push(block.process(&parameters, &runtime.context(), input));
+        }
+
+        // The dip to 0.9 is within the hysteresis, so the first exceedance lasts 4 ticks
+        let (over, duration, total, last_duration, count) = outputs[4];
+        assert!(over);
+        assert_relative_eq!(duration, 0.4, epsilon = 1e-12);
+        assert_relative_eq!(total, 0.4, epsilon = 1e-12);
+        assert_relative_eq!(last_duration, 0.0);
+        assert_relative_eq!(count, 1.0);
+
+        let (over, duration, total, last_duration, count) = outputs[8];
+        assert!(!over);
+        assert_relative_eq!(duration, 0.0);
+        assert_relative_eq!(total, 0.5, epsilon = 1e-12);
+        assert_relative_eq!(last_duration, 0.1, epsilon = 1e-12);
+        assert_relative_eq!(count, 2.0);
+        assert_eq!(block.data.scalar(), 0.0);
+    }
+}
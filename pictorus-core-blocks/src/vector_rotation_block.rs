@@ -0,0 +1,134 @@
+use crate::rotation::{Rotation, Vector};
+use core::marker::PhantomData;
+use core::str::FromStr;
+use corelib_traits::{Context, PassBy, ProcessBlock};
+use nalgebra::Vector3;
+use utils::{BlockData as OldBlockData, FromPass, ParseEnumError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RotationDirection {
+    /// Expresses a body frame vector in the world frame
+    BodyToWorld,
+    /// Expresses a world frame vector in the body frame
+    WorldToBody,
+}
+
+impl FromStr for RotationDirection {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "BodyToWorld" => Ok(Self::BodyToWorld),
+            "WorldToBody" => Ok(Self::WorldToBody),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+/// Parameters for the Vector Rotation Block
+pub struct Parameters {
+    pub direction: RotationDirection,
+}
+
+impl Parameters {
+    pub fn new(direction: &str) -> Self {
+        Self {
+            direction: direction.parse().expect("Invalid rotation direction"),
+        }
+    }
+}
+
+/// Rotates a 3D vector by a rotation signal, the orientation of a body in the world as a
+/// quaternion, rotation matrix or Euler angles (see [`crate::rotation`]).
+///
+/// The inputs are the rotation and the vector, as a row or a column. `BodyToWorld` rotates a
+/// vector measured in the body frame, such as an acceleration, into the world frame;
+/// `WorldToBody` does the opposite, for example to find the direction of gravity in the body
+/// frame.
+pub struct VectorRotationBlock<R: Rotation, V: Vector<Float = R::Float>> {
+    pub data: OldBlockData,
+    output: V,
+    _rotation: PhantomData<R>,
+}
+
+impl<R, V> Default for VectorRotationBlock<R, V>
+where
+    R: Rotation,
+    V: Vector<Float = R::Float>,
+    OldBlockData: FromPass<V>,
+{
+    fn default() -> Self {
+        let output = V::from_vector3(&Vector3::zeros());
+        Self {
+            data: <OldBlockData as FromPass<V>>::from_pass(output.as_by()),
+            output,
+            _rotation: PhantomData,
+        }
+    }
+}
+
+impl<R, V> ProcessBlock for VectorRotationBlock<R, V>
+where
+    R: Rotation,
+    V: Vector<Float = R::Float>,
+    OldBlockData: FromPass<V>,
+{
+    type Inputs = (R, V);
+    type Output = V;
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let (rotation, vector) = inputs;
+        let rotation = R::to_unit_quaternion(rotation);
+        let vector = V::to_vector3(vector);
+        let rotated = match parameters.direction {
+            RotationDirection::BodyToWorld => rotation.transform_vector(&vector),
+            RotationDirection::WorldToBody => rotation.inverse_transform_vector(&vector),
+        };
+        self.output = V::from_vector3(&rotated);
+        self.data = OldBlockData::from_pass(self.output.as_by());
+        self.output.as_by()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use core::f64::consts::FRAC_PI_2;
+    use corelib_traits::Matrix;
+    use corelib_traits_testing::StubContext;
+
+    #[test]
+    fn test_vector_rotation_block() {
+        let context = StubContext::default();
+        // Pitched 90°, so the body x axis points down
+        let euler = Matrix {
+            data: [[0.0, FRAC_PI_2, 0.0]],
+        };
+        let x = Matrix {
+            data: [[1.0, 0.0, 0.0]],
+        };
+
+        let mut block = VectorRotationBlock::<Matrix<3, 1, f64>, Matrix<3, 1, f64>>::default();
+        let output = block.process(&Parameters::new("BodyToWorld"), &context, (&euler, &x));
+        assert_relative_eq!(output.data[0][..], [0.0, 0.0, -1.0][..], epsilon = 1e-12);
+
+        // Gravity, straight down in the world, is along the body x axis
+        let mut block = VectorRotationBlock::<Matrix<3, 1, f64>, Matrix<1, 3, f64>>::default();
+        let down = Matrix {
+            data: [[0.0], [0.0], [-9.81]],
+        };
+        let output = block.process(&Parameters::new("WorldToBody"), &context, (&euler, &down));
+        assert_relative_eq!(
+            output.data.as_flattened(),
+            [9.81, 0.0, 0.0].as_slice(),
+            epsilon = 1e-12
+        );
+        assert_relative_eq!(block.data.get_data()[0], 9.81, epsilon = 1e-12);
+    }
+}
@@ -0,0 +1,142 @@
+use crate::edge_counter_block::{CaptureEdge, EdgeRate};
+use crate::hysteresis_block::hysteresis;
+use crate::traits::Float;
+use corelib_traits::{Context, PassBy, ProcessBlock};
+use utils::BlockData as OldBlockData;
+
+/// Parameters for the Zero Crossing Block
+pub struct Parameters {
+    /// Which crossings are detected: upward, downward or both
+    pub edge: CaptureEdge,
+    /// How far the input must go past zero for a crossing to count
+    pub hysteresis: f64,
+}
+
+impl Parameters {
+    pub fn new(edge: &str, hysteresis: f64) -> Self {
+        Self {
+            edge: edge.parse().expect("Invalid capture edge"),
+            hysteresis,
+        }
+    }
+}
+
+/// The Zero Crossing Block detects its input crossing zero and measures how often it does.
+///
+/// A crossing counts once the input reaches `hysteresis` past zero, so noise around zero
+/// doesn't add crossings. The outputs are whether a crossing was detected this tick and the
+/// frequency of the input in Hz. Each crossing is timed by interpolating where the input last
+/// passed through zero between two samples, so the frequency is finer than the tick rate.
+/// While no crossings arrive the frequency decays toward zero. NaN inputs are ignored.
+pub struct ZeroCrossingBlock<F: Float> {
+    pub data: OldBlockData,
+    positive: Option<bool>,
+    /// Previous input and its app time in seconds
+    previous: Option<(F, f64)>,
+    /// When the input last passed through zero
+    zero_time: f64,
+    rate: EdgeRate,
+    output: (bool, F),
+}
+
+impl<F: Float> Default for ZeroCrossingBlock<F> {
+    fn default() -> Self {
+        Self {
+            data: OldBlockData::scalar_from_bool(false),
+            positive: None,
+            previous: None,
+            zero_time: 0.0,
+            rate: EdgeRate::default(),
+            output: (false, F::zero()),
+        }
+    }
+}
+
+impl<F: Float> ProcessBlock for ZeroCrossingBlock<F> {
+    type Inputs = F;
+    type Output = (bool, F);
+    type Parameters = Parameters;
+
+    fn process<'b>(
+        &'b mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        input: PassBy<'_, Self::Inputs>,
+    ) -> PassBy<'b, Self::Output> {
+        let now = context.time().as_secs_f64();
+        let x = num_traits::ToPrimitive::to_f64(&input).unwrap_or(f64::NAN);
+        let mut crossed = false;
+        if !x.is_nan() {
+            if let Some((previous, previous_time)) = self.previous {
+                let previous = num_traits::ToPrimitive::to_f64(&previous).unwrap_or_default();
+                if (previous <= 0.0) != (x <= 0.0) {
+                    self.zero_time =
+                        previous_time + (now - previous_time) * previous / (previous - x);
+                }
+            }
+            self.previous = Some((input, now));
+
+            let hysteresis_band = F::from(parameters.hysteresis.abs()).unwrap();
+            match self.positive {
+                Some(positive) => {
+                    let now_positive =
+                        hysteresis(positive, input, -hysteresis_band, hysteresis_band);
+                    if now_positive != positive {
+                        crossed = match parameters.edge {
+                            CaptureEdge::Rising => now_positive,
+                            CaptureEdge::Falling => !now_positive,
+                            CaptureEdge::Both => true,
+                        };
+                    }
+                    self.positive = Some(now_positive);
+                }
+                None => self.positive = Some(x > 0.0),
+            }
+        }
+
+        let counts = if crossed { 1.0 } else { 0.0 };
+        let crossing_rate = self.rate.update(counts, self.zero_time, now);
+        let frequency = match parameters.edge {
+            CaptureEdge::Both => crossing_rate / 2.0,
+            CaptureEdge::Rising | CaptureEdge::Falling => crossing_rate,
+        };
+        self.output = (crossed, F::from(frequency).unwrap());
+        self.data.set_scalar_bool(crossed);
+        self.output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use core::f64::consts::TAU;
+    use corelib_traits_testing::StubRuntime;
+
+    #[test]
+    fn test_zero_crossing_block() {
+        let parameters = Parameters::new("Both", 0.2);
+        let mut runtime = StubRuntime::default();
+        let mut block = ZeroCrossingBlock::<f64>::default();
+
+        // 1.3 Hz, which doesn't divide evenly into 0.1 s ticks, with noise that crosses zero on
+        // its own around each real crossing
+        let mut crossings = 0;
+        for i in 0..100 {
+            let t = runtime.context().time.as_secs_f64();
+            let noise = if i % 2 == 0 { 0.1 } else { -0.1 };
+            let (crossed, frequency) = block.process(
+                &parameters,
+                &runtime.context(),
+                (TAU * 1.3 * t).sin() + noise,
+            );
+            crossings += crossed as usize;
+            if i > 20 {
+                assert_relative_eq!(frequency, 1.3, max_relative = 0.2);
+            }
+            runtime.tick();
+        }
+        // Two crossings per cycle, the first at 0.38 s and the 25th at 9.62 s
+        assert_eq!(crossings, 25);
+    }
+}
@@ -1,84 +1,114 @@
-use crate::traits::Float;
-use corelib_traits::{Matrix, Pass, ProcessBlock};
-use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use corelib_traits::{Matrix, Pass, PassBy, ProcessBlock, Scalar};
+use pictorus_core_blocks::{FftBlock, FftBlockParams, FftTransform, FftWindow};
+use rustfft::{num_complex::Complex, Fft, FftNum, FftPlanner};
 use std::sync::Arc;
 use utils::{BlockData as OldBlockData, FromPass};
 
-/// FFT Block performs FFT on samples it accumulates.
-///
-/// On each time step a new sample is added to the buffer. When the buffer is full, FFT is performed on the samples.
-/// The size of this buffer is set by the generic parameter `N`.
-pub struct FftBlock<T: Float, const N: usize>
-where
-    OldBlockData: FromPass<Matrix<2, N, T>>,
-{
-    pub data: OldBlockData,
-    /// Samples buffer that stores samples as we accumulate them.
-    samples: [T; N],
-    /// Index of the next sample to be added to the buffer.
-    sample_index: usize,
-    /// Output of the FFT block, only updated when the buffer is full.
-    output: Matrix<2, N, T>,
-    /// FFT planner used to create the FFT object.
-    fft: Arc<dyn Fft<T>>,
+/// Transform using rustfft, which is fast for any size
+pub struct RustFft<T: FftNum> {
+    fft: Option<Arc<dyn Fft<T>>>,
 }
 
-impl<T: Float, const N: usize> Default for FftBlock<T, N> {
+impl<T: FftNum> Default for RustFft<T> {
     fn default() -> Self {
-        let mut planner = FftPlanner::<T>::new();
-        let fft = planner.plan_fft_forward(N);
-        Self {
-            samples: [T::default(); N],
-            sample_index: 0,
-            output: Matrix::zeroed(),
-            data: <OldBlockData as FromPass<Matrix<2, N, T>>>::from_pass(Matrix::zeroed().as_by()),
-            fft,
-        }
+        Self { fft: None }
     }
 }
 
-impl<T: Float, const N: usize> ProcessBlock for FftBlock<T, N> {
-    type Inputs = T;
-    type Output = Matrix<2, N, T>;
-    type Parameters = Parameters;
-
-    fn process<'b>(
-        &'b mut self,
-        _parameters: &Self::Parameters,
-        _context: &dyn corelib_traits::Context,
-        inputs: corelib_traits::PassBy<'_, Self::Inputs>,
-    ) -> corelib_traits::PassBy<'b, Self::Output> {
-        self.samples[self.sample_index] = inputs;
-
-        if self.sample_index >= N - 1 {
-            self.sample_index = 0;
-            let mut data_buf: [Complex<T>; N] = [Complex::new(T::zero(), T::zero()); N];
-            self.samples.iter().enumerate().for_each(|(i, &sample)| {
-                data_buf[i].re = sample;
-            });
-            self.fft.process(&mut data_buf);
-            for (i, buf_val) in data_buf.iter().enumerate().take(N) {
-                self.output.data[i][0] = buf_val.re;
-                self.output.data[i][1] = buf_val.im;
-            }
-            self.data = <OldBlockData as FromPass<Matrix<2, N, T>>>::from_pass(self.output.as_by());
-        } else {
-            self.sample_index += 1;
+impl<T: FftNum, const N: usize> FftTransform<T, N> for RustFft<T> {
+    fn transform(&mut self, re: &mut [T; N], im: &mut [T; N]) {
+        let fft = self
+            .fft
+            .get_or_insert_with(|| FftPlanner::new().plan_fft_forward(N));
+        let mut data_buf: [Complex<T>; N] = core::array::from_fn(|i| Complex::new(re[i], im[i]));
+        fft.process(&mut data_buf);
+        for (i, buf_val) in data_buf.iter().enumerate() {
+            re[i] = buf_val.re;
+            im[i] = buf_val.im;
         }
-
-        self.output.as_by()
     }
 }
 
+/// FFT Block performs FFT on samples it accumulates.
+///
+/// On each time step a new sample is added to the buffer. When the buffer is full, FFT is performed on the samples.
+/// The size of this buffer is set by the generic parameter `N`. The output is the real and imaginary part of each bin.
+///
+/// This is the core FFT block's spectrum output, transformed with rustfft. Use
+/// [`pictorus_core_blocks::FftBlock`] for the amplitude, phase, density and frequency outputs.
+pub struct FFTBlock<T: FftNum + Scalar, const N: usize> {
+    pub data: OldBlockData,
+    inner: FftBlock<T, N, RustFft<T>>,
+}
+
+/// Parameters for the FFT Block. By default frames have a rectangular window and don't overlap.
 #[derive(Debug, Clone, Copy, Default)]
-pub struct Parameters;
+pub struct Parameters {
+    pub window: FftWindow,
+    /// New samples between frames. Zero, or anything above `N`, gives back-to-back frames
+    /// that don't overlap.
+    pub hop_size: usize,
+}
 
 impl Parameters {
     pub fn new() -> Parameters {
-        Parameters
+        Parameters::default()
     }
+
+    pub fn with_window(window: &str, hop_size: f64) -> Parameters {
+        let FftBlockParams { window, hop_size } = FftBlockParams::new(window, hop_size);
+        Parameters { window, hop_size }
+    }
+}
+
+macro_rules! impl_fft_block {
+    ($type:ty) => {
+        impl<const N: usize> Default for FFTBlock<$type, N>
+        where
+            OldBlockData: FromPass<Matrix<2, N, $type>>,
+        {
+            fn default() -> Self {
+                Self {
+                    data: <OldBlockData as FromPass<Matrix<2, N, $type>>>::from_pass(
+                        Matrix::zeroed().as_by(),
+                    ),
+                    inner: FftBlock::default(),
+                }
+            }
+        }
+
+        impl<const N: usize> ProcessBlock for FFTBlock<$type, N>
+        where
+            OldBlockData: FromPass<Matrix<2, N, $type>>,
+        {
+            type Inputs = $type;
+            type Output = Matrix<2, N, $type>;
+            type Parameters = Parameters;
+
+            fn process<'b>(
+                &'b mut self,
+                parameters: &Self::Parameters,
+                context: &dyn corelib_traits::Context,
+                inputs: PassBy<'_, Self::Inputs>,
+            ) -> PassBy<'b, Self::Output> {
+                let parameters = FftBlockParams {
+                    window: parameters.window,
+                    hop_size: parameters.hop_size,
+                };
+                if self.inner.update(&parameters, context, inputs) {
+                    self.data = <OldBlockData as FromPass<Matrix<2, N, $type>>>::from_pass(
+                        self.inner.spectrum().as_by(),
+                    );
+                }
+                self.inner.spectrum()
+            }
+        }
+    };
 }
 
+impl_fft_block!(f32);
+impl_fft_block!(f64);
+
 #[cfg(test)]
 mod tests {
     use core::f64;
@@ -113,23 +143,21 @@ mod tests {
             0.0,
         );
 
-        let mut fft_block: FftBlock<f64, 10> = FftBlock::default();
+        let mut fft_block: FFTBlock<f64, 10> = FFTBlock::default();
         let fft_parameters = Parameters::new();
 
+        let mut output = Matrix::<2, 10, f64>::zeroed();
         for _ in 0..100 {
             let output_2hz = sinewave_2_hz.generate(&sinewave_2_hz_parameters, &runtime.context);
             let output_3hz = sinewave_3_hz.generate(&sinewave_3_hz_parameters, &runtime.context);
 
             let combined = output_2hz + output_3hz;
 
-            let _ = fft_block.process(&fft_parameters, &runtime.context, combined);
+            output = *fft_block.process(&fft_parameters, &runtime.context, combined);
             runtime.tick();
         }
 
-        let output_magnitudes = fft_block
-            .output
-            .data
-            .map(|[re, im]| (re * re + im * im).sqrt());
+        let output_magnitudes = output.data.map(|[re, im]| (re * re + im * im).sqrt());
         let expected_output = [
             12.345, // DC value (bias in the signal from 2hz signal)
             0.0,    // 1Hz
@@ -146,5 +174,30 @@ mod tests {
         for i in 0..10 {
             assert_relative_eq!(output_magnitudes[i], expected_output[i], epsilon = 0.01);
         }
+        assert_eq!(
+            fft_block.data,
+            <OldBlockData as FromPass<Matrix<2, 10, f64>>>::from_pass(output.as_by())
+        );
+    }
+
+    #[test]
+    fn test_fft_block_window_and_hop() {
+        let runtime = StubRuntime::default();
+        let parameters = Parameters::with_window("Hann", 3.0);
+        let mut fft_block: FFTBlock<f32, 6> = FFTBlock::default();
+
+        let mut frames = 0;
+        let mut last = Matrix::<2, 6, f32>::zeroed();
+        for i in 0..12 {
+            let output = fft_block.process(&parameters, &runtime.context(), i as f32);
+            if *output != last {
+                frames += 1;
+                last = *output;
+            }
+        }
+        // Frames at the 6th sample and every 3 after
+        assert_eq!(frames, 3);
+        // Samples 6 to 11 weighted by the periodic Hann window [0, 0.25, 0.75, 1, 0.75, 0.25]
+        assert_relative_eq!(last.data[0][0], 27.0, epsilon = 1e-4);
     }
 }
@@ -1,44 +1,250 @@
+use std::collections::VecDeque;
+use std::io;
+use std::time::Instant;
+
+use corelib_traits::{Context, InputBlock, PassBy};
 use embedded_can::{nb::Can, Frame as EmbeddedFrame};
-use protocols::CanProtocol;
-use socketcan::{CanFrame, CanSocket, Socket};
+use pictorus_core_blocks::CanBusStatusBlockParams;
+use protocols::can::{raw_id, BusLoadMeter, CanBusState, CanBusStatus, CanConfig, CanFrame};
+use protocols::{CanProtocol, CanStatusProtocol};
+use socketcan::{
+    CanAnyFrame, CanErrorFrame, CanFdFrame, CanFdSocket, CanSocket, Socket, SocketOptions,
+};
 use utils::PictorusError;
 
 const ERR_TYPE: &str = "CanProtocol";
 
+/// Frames held while the kernel's transmit queue is full. Once this many are waiting the oldest
+/// is dropped, so a disconnected bus can't delay new frames indefinitely.
+const TX_QUEUE_LEN: usize = 64;
+
+// From linux/can.h and linux/can/error.h
+const CAN_EFF_FLAG: u32 = 0x8000_0000;
+const CAN_ERR_CRTL: u32 = 0x0000_0004;
+const CAN_ERR_BUSOFF: u32 = 0x0000_0040;
+const CAN_ERR_RESTARTED: u32 = 0x0000_0100;
+const CAN_ERR_CRTL_RX_WARNING: u8 = 0x04;
+const CAN_ERR_CRTL_TX_WARNING: u8 = 0x08;
+const CAN_ERR_CRTL_RX_PASSIVE: u8 = 0x10;
+const CAN_ERR_CRTL_TX_PASSIVE: u8 = 0x20;
+const CAN_ERR_CRTL_ACTIVE: u8 = 0x40;
+
+enum CanInterface {
+    Classic(CanSocket),
+    Fd(CanFdSocket),
+}
+
+enum Received {
+    Frame(CanFrame),
+    Error(CanErrorFrame),
+}
+
+impl CanInterface {
+    fn open(iface: &str, config: &CanConfig<'_>) -> io::Result<Self> {
+        let interface = if config.fd {
+            Self::Fd(CanFdSocket::open(iface)?)
+        } else {
+            Self::Classic(CanSocket::open(iface)?)
+        };
+
+        // The kernel matches the EFF flag too, so standard and extended filters only accept
+        // their own kind of ID
+        let filters: Vec<_> = config
+            .filters
+            .iter()
+            .map(|filter| match filter.id {
+                embedded_can::Id::Standard(id) => {
+                    socketcan::CanFilter::new(id.as_raw() as u32, filter.mask | CAN_EFF_FLAG)
+                }
+                embedded_can::Id::Extended(id) => socketcan::CanFilter::new(
+                    id.as_raw() | CAN_EFF_FLAG,
+                    filter.mask | CAN_EFF_FLAG,
+                ),
+            })
+            .collect();
+        let error_mask = CAN_ERR_CRTL | CAN_ERR_BUSOFF | CAN_ERR_RESTARTED;
+
+        match &interface {
+            Self::Classic(socket) => configure(socket, &filters, error_mask)?,
+            Self::Fd(socket) => configure(socket, &filters, error_mask)?,
+        }
+        Ok(interface)
+    }
+
+    fn read(&self) -> io::Result<Received> {
+        let received = match self {
+            Self::Classic(socket) => match socket.read_frame()? {
+                socketcan::CanFrame::Data(frame) => Received::Frame(classic_frame(&frame)),
+                socketcan::CanFrame::Remote(frame) => Received::Frame(classic_frame(&frame)),
+                socketcan::CanFrame::Error(frame) => Received::Error(frame),
+            },
+            Self::Fd(socket) => match socket.read_frame()? {
+                CanAnyFrame::Normal(frame) => Received::Frame(classic_frame(&frame)),
+                CanAnyFrame::Remote(frame) => Received::Frame(classic_frame(&frame)),
+                CanAnyFrame::Error(frame) => Received::Error(frame),
+                CanAnyFrame::Fd(frame) => Received::Frame(
+                    CanFrame::new_fd(frame.id(), frame.data(), frame.is_brs())
+                        .expect("FD frames carry at most 64 bytes"),
+                ),
+            },
+        };
+        Ok(received)
+    }
+
+    fn write(&self, frame: &CanFrame) -> io::Result<()> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "Invalid CAN frame");
+        if frame.is_fd() {
+            let Self::Fd(socket) = self else {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "CAN FD frame sent on a classic CAN interface",
+                ));
+            };
+            let mut fd_frame = CanFdFrame::new(frame.id(), frame.data()).ok_or_else(invalid)?;
+            fd_frame.set_brs(frame.is_brs());
+            return socket.write_frame(&fd_frame);
+        }
+
+        let classic = if frame.is_remote_frame() {
+            socketcan::CanFrame::new_remote(frame.id(), frame.dlc())
+        } else {
+            socketcan::CanFrame::new(frame.id(), frame.data())
+        }
+        .ok_or_else(invalid)?;
+        match self {
+            Self::Classic(socket) => socket.write_frame(&classic),
+            Self::Fd(socket) => socket.write_frame(&classic),
+        }
+    }
+}
+
+fn configure(
+    socket: &(impl Socket + SocketOptions),
+    filters: &[socketcan::CanFilter],
+    error_mask: u32,
+) -> io::Result<()> {
+    if !filters.is_empty() {
+        socket.set_filters(filters)?;
+    }
+    socket.set_error_filter(error_mask)?;
+    socket.set_nonblocking(true)
+}
+
+fn classic_frame(frame: &impl EmbeddedFrame) -> CanFrame {
+    if frame.is_remote_frame() {
+        CanFrame::new_remote(frame.id(), frame.dlc())
+    } else {
+        CanFrame::new(frame.id(), frame.data())
+    }
+    .expect("Classic frames carry at most 8 bytes")
+}
+
+/// Bus state after an error frame, from its error class and controller status byte
+fn bus_state_after(state: CanBusState, error_class: u32, data: &[u8]) -> CanBusState {
+    if error_class & CAN_ERR_BUSOFF != 0 {
+        return CanBusState::BusOff;
+    }
+    if error_class & CAN_ERR_RESTARTED != 0 {
+        return CanBusState::ErrorActive;
+    }
+    if error_class & CAN_ERR_CRTL == 0 {
+        return state;
+    }
+
+    let status = data.get(1).copied().unwrap_or(0);
+    if status & (CAN_ERR_CRTL_RX_PASSIVE | CAN_ERR_CRTL_TX_PASSIVE) != 0 {
+        CanBusState::ErrorPassive
+    } else if status & (CAN_ERR_CRTL_RX_WARNING | CAN_ERR_CRTL_TX_WARNING) != 0 {
+        CanBusState::ErrorWarning
+    } else if status & CAN_ERR_CRTL_ACTIVE != 0 {
+        CanBusState::ErrorActive
+    } else {
+        state
+    }
+}
+
+fn queue_full(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::WouldBlock || err.raw_os_error() == Some(libc::ENOBUFS)
+}
+
+/// A SocketCAN interface, classic or FD.
+///
+/// Received frames are filtered by the kernel. Frames sent while the kernel's transmit queue
+/// is full wait in a queue of our own and are retried every read and transmit. The interface
+/// also receives error frames, which drive the reported bus state.
 pub struct CanConnection {
-    socket: CanSocket,
+    interface: CanInterface,
     frames: Vec<CanFrame>,
     stale: bool,
+    tx_queue: VecDeque<CanFrame>,
+    state: CanBusState,
+    error_frames: u32,
+    tx_dropped: u32,
+    bus_load: BusLoadMeter,
+    last_status: Instant,
 }
 
 impl CanConnection {
     pub fn new(iface: &str) -> Result<Self, PictorusError> {
-        let socket = CanSocket::open(iface).map_err(|err| {
-            PictorusError::new(
-                ERR_TYPE.into(),
-                format!(
-                    "Failed to open CAN socket on interface: {} ({})",
-                    iface, err
-                ),
-            )
-        })?;
+        Self::with_config(iface, &CanConfig::default())
+    }
 
-        socket.set_nonblocking(true).map_err(|err| {
+    pub fn with_config(iface: &str, config: &CanConfig<'_>) -> Result<Self, PictorusError> {
+        let interface = CanInterface::open(iface, config).map_err(|err| {
             PictorusError::new(
                 ERR_TYPE.into(),
                 format!(
-                    "Failed to set CAN socket to non-blocking mode: {} ({})",
+                    "Failed to open CAN socket on interface: {} ({})",
                     iface, err
                 ),
             )
         })?;
 
         Ok(Self {
-            socket,
+            interface,
             frames: vec![],
             stale: true,
+            tx_queue: VecDeque::new(),
+            state: CanBusState::default(),
+            error_frames: 0,
+            tx_dropped: 0,
+            bus_load: BusLoadMeter::new(config.bitrate, config.data_bitrate),
+            last_status: Instant::now(),
         })
     }
+
+    /// Sends queued frames until the kernel's queue fills
+    fn send_queued(&mut self) {
+        while let Some(frame) = self.tx_queue.front() {
+            match self.interface.write(frame) {
+                Ok(()) => self.bus_load.record(frame),
+                Err(err) if queue_full(&err) => return,
+                Err(err) => log::warn!("Dropping CAN frame {:#x}: {}", raw_id(frame.id()), err),
+            }
+            self.tx_queue.pop_front();
+        }
+    }
+
+    fn refresh(&mut self) {
+        if !self.stale {
+            return;
+        }
+
+        self.send_queued();
+        while let Ok(received) = self.interface.read() {
+            match received {
+                Received::Frame(frame) => {
+                    self.bus_load.record(&frame);
+                    self.frames.push(frame);
+                }
+                Received::Error(frame) => {
+                    self.error_frames += 1;
+                    self.state = bus_state_after(self.state, frame.error_bits(), frame.data());
+                }
+            }
+        }
+        self.stale = false;
+    }
 }
 
 impl Can for CanConnection {
@@ -46,25 +252,46 @@ impl Can for CanConnection {
     type Error = socketcan::Error;
 
     fn transmit(&mut self, frame: &Self::Frame) -> nb::Result<Option<Self::Frame>, Self::Error> {
-        self.socket.transmit(frame)
+        self.send_queued();
+        if self.tx_queue.is_empty() {
+            match self.interface.write(frame) {
+                Ok(()) => {
+                    self.bus_load.record(frame);
+                    return Ok(None);
+                }
+                Err(err) if queue_full(&err) => {}
+                Err(err) => return Err(nb::Error::Other(err.into())),
+            }
+        }
+
+        if self.tx_queue.len() == TX_QUEUE_LEN {
+            self.tx_queue.pop_front();
+            self.tx_dropped += 1;
+        }
+        self.tx_queue.push_back(*frame);
+        Ok(None)
     }
 
     fn receive(&mut self) -> nb::Result<Self::Frame, Self::Error> {
-        self.socket.receive()
+        loop {
+            match self.interface.read() {
+                Ok(Received::Frame(frame)) => return Ok(frame),
+                Ok(Received::Error(frame)) => {
+                    self.error_frames += 1;
+                    self.state = bus_state_after(self.state, frame.error_bits(), frame.data());
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                    return Err(nb::Error::WouldBlock)
+                }
+                Err(err) => return Err(nb::Error::Other(err.into())),
+            }
+        }
     }
 }
 
 impl CanProtocol for CanConnection {
     fn read_frames(&mut self) -> &[impl EmbeddedFrame] {
-        if !self.stale {
-            return &self.frames;
-        }
-
-        while let Ok(frame) = self.receive() {
-            self.frames.push(frame);
-        }
-
-        self.stale = false;
+        self.refresh();
         &self.frames
     }
 
@@ -73,3 +300,37 @@ impl CanProtocol for CanConnection {
         self.frames.clear();
     }
 }
+
+impl CanStatusProtocol for CanConnection {
+    fn bus_status(&mut self) -> CanBusStatus {
+        self.refresh();
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_status);
+        self.last_status = now;
+        CanBusStatus {
+            state: self.state,
+            error_frames: core::mem::take(&mut self.error_frames),
+            tx_dropped: core::mem::take(&mut self.tx_dropped),
+            bus_load: self.bus_load.take_load(elapsed),
+        }
+    }
+}
+
+impl InputBlock for CanConnection {
+    type Output = (f64, f64, f64, f64);
+    type Parameters = CanBusStatusBlockParams;
+
+    fn input(
+        &mut self,
+        _parameters: &Self::Parameters,
+        _context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        let status = self.bus_status();
+        (
+            status.state as u8 as f64,
+            status.error_frames as f64,
+            status.tx_dropped as f64,
+            status.bus_load as f64,
+        )
+    }
+}
@@ -0,0 +1,165 @@
+use alloc::sync::Arc;
+use core::time::Duration;
+use std::sync::Mutex;
+
+use corelib_traits::{Context, InputBlock, PassBy};
+use pictorus_core_blocks::{
+    CaptureEdge, EdgeCounterBlockParams, PulseCaptureBlockParams, QuadratureEncoderBlockParams,
+};
+use protocols::capture::{
+    EdgeAccumulator, EdgeCount, PulseMeasurement, PulseTimer, QuadratureCount, QuadratureDecoder,
+};
+use protocols::{EdgeCounterProtocol, PulseCaptureProtocol, QuadratureProtocol};
+use utils::PictorusError;
+
+use crate::gpio_protocol::{event_timestamp, monotonic_now, watch_lines, GpioLineConfig};
+
+/// App time of the last edge, from its age on the event clock. Uses the current time if no
+/// edge has been seen, which the blocks ignore because they only use it along with counts.
+fn last_edge_time(context: &dyn Context, since_last_edge: Option<Duration>) -> f64 {
+    context
+        .time()
+        .saturating_sub(since_last_edge.unwrap_or_default())
+        .as_secs_f64()
+}
+
+/// Counts edges on a GPIO line from kernel edge events
+pub struct CdevEdgeCounter {
+    edges: Arc<Mutex<EdgeAccumulator>>,
+}
+
+impl CdevEdgeCounter {
+    pub fn new(config: &GpioLineConfig, edge: CaptureEdge) -> Result<Self, PictorusError> {
+        let handle =
+            config.request_events(edge != CaptureEdge::Falling, edge != CaptureEdge::Rising)?;
+        let edges = Arc::new(Mutex::new(EdgeAccumulator::default()));
+        let shared = edges.clone();
+        watch_lines(alloc::vec![handle], move |_, event| {
+            shared.lock().unwrap().edge(event_timestamp(&event));
+        });
+        Ok(Self { edges })
+    }
+}
+
+impl EdgeCounterProtocol for CdevEdgeCounter {
+    fn read_edges(&mut self) -> EdgeCount {
+        self.edges.lock().unwrap().read(monotonic_now())
+    }
+}
+
+impl InputBlock for CdevEdgeCounter {
+    type Output = (f64, f64);
+    type Parameters = EdgeCounterBlockParams;
+
+    fn input(
+        &mut self,
+        _parameters: &Self::Parameters,
+        context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        let count = self.read_edges();
+        (
+            count.edges as f64,
+            last_edge_time(context, count.since_last_edge),
+        )
+    }
+}
+
+/// Decodes a quadrature encoder on GPIO lines from kernel edge events
+pub struct CdevQuadrature {
+    decoder: Arc<Mutex<QuadratureDecoder>>,
+}
+
+impl CdevQuadrature {
+    pub fn new(
+        a: &GpioLineConfig,
+        b: &GpioLineConfig,
+        index: Option<&GpioLineConfig>,
+    ) -> Result<Self, PictorusError> {
+        let mut handles = alloc::vec![a.request_events(true, true)?, b.request_events(true, true)?];
+        if let Some(index) = index {
+            handles.push(index.request_events(true, false)?);
+        }
+
+        let mut levels = [a.level(&handles[0]), b.level(&handles[1])];
+        let configs = [a.clone(), b.clone()];
+        let decoder = Arc::new(Mutex::new(QuadratureDecoder::new(levels[0], levels[1])));
+        let shared = decoder.clone();
+        watch_lines(handles, move |line, event| {
+            let mut decoder = shared.lock().unwrap();
+            if line < 2 {
+                levels[line] = configs[line].is_rising(&event);
+                decoder.update(levels[0], levels[1], event_timestamp(&event));
+            } else {
+                decoder.index();
+            }
+        });
+        Ok(Self { decoder })
+    }
+}
+
+impl QuadratureProtocol for CdevQuadrature {
+    fn read_position(&mut self) -> QuadratureCount {
+        self.decoder.lock().unwrap().read(monotonic_now())
+    }
+}
+
+impl InputBlock for CdevQuadrature {
+    type Output = (f64, f64, bool, f64);
+    type Parameters = QuadratureEncoderBlockParams;
+
+    fn input(
+        &mut self,
+        _parameters: &Self::Parameters,
+        context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        let count = self.read_position();
+        (
+            count.position as f64,
+            last_edge_time(context, count.since_last_count),
+            count.index_position.is_some(),
+            count.index_position.unwrap_or_default() as f64,
+        )
+    }
+}
+
+/// Measures pulses on a GPIO line from kernel edge events
+pub struct CdevPulseCapture {
+    timer: Arc<Mutex<PulseTimer>>,
+}
+
+impl CdevPulseCapture {
+    pub fn new(config: &GpioLineConfig) -> Result<Self, PictorusError> {
+        let handle = config.request_events(true, true)?;
+        let config = config.clone();
+        let timer = Arc::new(Mutex::new(PulseTimer::default()));
+        let shared = timer.clone();
+        watch_lines(alloc::vec![handle], move |_, event| {
+            shared
+                .lock()
+                .unwrap()
+                .edge(config.is_rising(&event), event_timestamp(&event));
+        });
+        Ok(Self { timer })
+    }
+}
+
+impl PulseCaptureProtocol for CdevPulseCapture {
+    fn read_pulse(&mut self) -> Option<PulseMeasurement> {
+        self.timer.lock().unwrap().read()
+    }
+}
+
+impl InputBlock for CdevPulseCapture {
+    type Output = (f64, f64);
+    type Parameters = PulseCaptureBlockParams;
+
+    fn input(
+        &mut self,
+        _parameters: &Self::Parameters,
+        _context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        self.read_pulse()
+            .map(|pulse| (pulse.period.as_secs_f64(), pulse.high_time.as_secs_f64()))
+            .unwrap_or_default()
+    }
+}
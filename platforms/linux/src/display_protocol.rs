@@ -1,6 +1,5 @@
-use crate::protocols::delay_protocol::{create_delay_protocol, DelayProtocolType};
-use crate::protocols::i2c_protocol::{create_i2c_protocol, I2cProtocolType};
-use crate::utils::PictorusError;
+use crate::{create_delay_protocol, create_i2c_protocol, I2cdev, StdDelayProtocol};
+use utils::PictorusError;
 
 use embedded_graphics::{
     mono_font::{ascii::FONT_10X20, MonoTextStyle, MonoTextStyleBuilder},
@@ -8,7 +7,6 @@ use embedded_graphics::{
     prelude::*,
     text::{Baseline, Text},
 };
-use embedded_hal_compat::Reverse;
 use hd44780_driver::bus::I2CBus;
 use hd44780_driver::{Cursor, CursorBlink, Display as LcdDisplay, DisplayMode, HD44780};
 use log::debug;
@@ -20,7 +18,32 @@ pub enum DisplayType {
     LCD,
 }
 
-const ERR_TYPE: &str = "UdpProtocol";
+const ERR_TYPE: &str = "DisplayProtocol";
+
+/// The SSD1306 and HD44780 drivers still use the embedded-hal 0.2 I2C traits, while `I2cdev`
+/// only implements embedded-hal 1.0. This adapts the bus back to the 0.2 traits.
+struct Reverse<I>(I);
+
+impl<I: embedded_hal::i2c::I2c> embedded_hal_02::blocking::i2c::Write for Reverse<I> {
+    type Error = I::Error;
+
+    fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.write(address, bytes)
+    }
+}
+
+impl<I: embedded_hal::i2c::I2c> embedded_hal_02::blocking::i2c::WriteRead for Reverse<I> {
+    type Error = I::Error;
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        bytes: &[u8],
+        buffer: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.0.write_read(address, bytes, buffer)
+    }
+}
 
 fn create_error(message: String) -> PictorusError {
     PictorusError::new(ERR_TYPE.into(), message)
@@ -86,18 +109,17 @@ impl DisplayProtocol for DisplayConnection {
 
 struct Ssd1306Display {
     display: Ssd1306<
-        I2CInterface<I2cProtocolType>,
+        I2CInterface<Reverse<I2cdev>>,
         DisplaySize128x32,
         BufferedGraphicsMode<DisplaySize128x32>,
     >,
     text_style: MonoTextStyle<'static, BinaryColor>,
 }
 
-#[cfg(feature = "std")]
 impl Ssd1306Display {
     pub fn new(address: u16) -> Result<Self, PictorusError> {
         debug!("Creating Ssd1306Display for address {}", address);
-        let i2c = create_i2c_protocol()?;
+        let i2c = Reverse(create_i2c_protocol()?);
         let interface = I2CDisplayInterface::new_custom_address(i2c, address as u8);
 
         let mut display = Ssd1306::new(interface, DisplaySize128x32, DisplayRotation::Rotate0)
@@ -122,7 +144,7 @@ impl Ssd1306Display {
 
     fn render(&mut self, value: &str, x_offset: i64) {
         debug!("Rendering value: {}", value);
-        self.display.clear();
+        self.display.clear_buffer();
         let start_point = Point::new(x_offset as i32, 16);
         Text::with_baseline(value, start_point, self.text_style, Baseline::Middle)
             .draw(&mut self.display)
@@ -133,14 +155,14 @@ impl Ssd1306Display {
 }
 
 struct Hd44780Display {
-    display: HD44780<I2CBus<I2cProtocolType>>,
-    delay: DelayProtocolType,
+    display: HD44780<I2CBus<Reverse<I2cdev>>>,
+    delay: StdDelayProtocol,
 }
 
 impl Hd44780Display {
     pub fn new(address: u16) -> Result<Self, PictorusError> {
         debug!("Creating Hd44780Display for address {}", address);
-        let i2c = create_i2c_protocol()?;
+        let i2c = Reverse(create_i2c_protocol()?);
         let mut delay = create_delay_protocol();
 
         let mut display = HD44780::new_i2c(i2c, address as u8, &mut delay)
@@ -169,6 +191,7 @@ impl Hd44780Display {
 
         Ok(Hd44780Display { display, delay })
     }
+
     fn render(&mut self, value: &str, x_offset: i64) {
         debug!("Rendering value: {}", value);
         self.display.clear(&mut self.delay).unwrap();
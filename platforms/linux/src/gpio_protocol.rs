@@ -1,19 +1,192 @@
-use alloc::{format, string::String};
+use alloc::{
+    collections::VecDeque,
+    format,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::str::FromStr;
+use core::time::Duration;
+use std::os::fd::AsRawFd;
+use std::sync::Mutex;
+use std::thread;
 
 use corelib_traits::{Context, InputBlock, OutputBlock, PassBy};
 pub use embedded_hal::digital::{ErrorType, InputPin, OutputPin};
-use linux_embedded_hal::gpio_cdev::{Chip, LineRequestFlags};
-use pictorus_core_blocks::{GpioInputBlockParams, GpioOutputBlockParams};
-use utils::PictorusError;
+use linux_embedded_hal::gpio_cdev::{
+    Chip, EventRequestFlags, EventType, Line, LineEvent, LineEventHandle, LineRequestFlags,
+};
+use log::warn;
+use pictorus_core_blocks::{GpioEdgeEventBlockParams, GpioInputBlockParams, GpioOutputBlockParams};
+use protocols::{GpioEdgeEvent, GpioEventProtocol};
+use utils::{ParseEnumError, PictorusError};
 
-// TODO: This should be configurable by block param
-const GPIO_CHIP: &str = "/dev/gpiochip0";
+const DEFAULT_GPIO_CHIP: &str = "/dev/gpiochip0";
 const ERR_TYPE: &str = "GpioProtocol";
+/// Edge events kept for a line between reads. The oldest are dropped beyond this.
+const MAX_QUEUED_EVENTS: usize = 1024;
 
-pub struct CdevPin(linux_embedded_hal::CdevPin);
+// Bias flags of line requests from linux/gpio.h, which gpio-cdev doesn't define. They need
+// Linux 5.5 or newer.
+const GPIOHANDLE_REQUEST_BIAS_PULL_UP: u32 = 1 << 5;
+const GPIOHANDLE_REQUEST_BIAS_PULL_DOWN: u32 = 1 << 6;
+const GPIOHANDLE_REQUEST_BIAS_DISABLE: u32 = 1 << 7;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GpioBias {
+    /// Leave the line's bias as it is configured, usually by the device tree
+    #[default]
+    AsIs,
+    Disabled,
+    PullUp,
+    PullDown,
+}
+
+impl FromStr for GpioBias {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "AsIs" => Ok(Self::AsIs),
+            "Disabled" => Ok(Self::Disabled),
+            "PullUp" => Ok(Self::PullUp),
+            "PullDown" => Ok(Self::PullDown),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+/// Selects a GPIO line and how it is requested
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpioLineConfig {
+    /// Path of the GPIO chip, or its name such as `gpiochip1`
+    pub chip: String,
+    /// Offset of the line on the chip, or the name of the line
+    pub line: String,
+    pub bias: GpioBias,
+    /// Invert the line, so it is high while the signal is low. The inversion happens here
+    /// rather than in the kernel, so edge events are inverted the same way as levels.
+    pub active_low: bool,
+}
+
+impl GpioLineConfig {
+    pub fn new(
+        chip: &str,
+        line: &str,
+        bias: &str,
+        active_low: bool,
+    ) -> Result<Self, PictorusError> {
+        let bias = bias
+            .parse()
+            .map_err(|_| create_error(format!("Invalid GPIO bias: {}", bias)))?;
+        Ok(Self {
+            chip: chip.into(),
+            line: line.into(),
+            bias,
+            active_low,
+        })
+    }
+
+    /// Line `pin_number` of the default chip, as it is configured
+    pub fn pin(pin_number: f64) -> Self {
+        Self {
+            chip: DEFAULT_GPIO_CHIP.into(),
+            line: (pin_number as u32).to_string(),
+            bias: GpioBias::AsIs,
+            active_low: false,
+        }
+    }
+
+    fn chip_path(&self) -> String {
+        if self.chip.contains('/') {
+            self.chip.clone()
+        } else {
+            format!("/dev/{}", self.chip)
+        }
+    }
+
+    fn line_error(&self) -> PictorusError {
+        create_error(format!(
+            "Failed to bind to GPIO line {} on {}",
+            self.line, self.chip
+        ))
+    }
+
+    fn get_line(&self) -> Result<Line, PictorusError> {
+        let path = self.chip_path();
+        let mut chip = Chip::new(&path).map_err(|_| {
+            create_error(format!(
+                "Failed to bind to GPIO bus {} for line: {}",
+                path, self.line
+            ))
+        })?;
+        let offset = match self.line.parse() {
+            Ok(offset) => offset,
+            Err(_) => find_line(&mut chip, &self.line).ok_or_else(|| {
+                create_error(format!("No GPIO line named {} on {}", self.line, path))
+            })?,
+        };
+        chip.get_line(offset).map_err(|_| self.line_error())
+    }
+
+    fn request_flags(&self, direction: LineRequestFlags) -> LineRequestFlags {
+        let bias = match self.bias {
+            GpioBias::AsIs => 0,
+            GpioBias::Disabled => GPIOHANDLE_REQUEST_BIAS_DISABLE,
+            GpioBias::PullUp => GPIOHANDLE_REQUEST_BIAS_PULL_UP,
+            GpioBias::PullDown => GPIOHANDLE_REQUEST_BIAS_PULL_DOWN,
+        };
+        direction | LineRequestFlags::from_bits_retain(bias)
+    }
+
+    /// Requests edge events for the line. The edges are after the active-low inversion.
+    pub(crate) fn request_events(
+        &self,
+        rising: bool,
+        falling: bool,
+    ) -> Result<LineEventHandle, PictorusError> {
+        let (rising, falling) = if self.active_low {
+            (falling, rising)
+        } else {
+            (rising, falling)
+        };
+        let mut event_flags = EventRequestFlags::empty();
+        if rising {
+            event_flags |= EventRequestFlags::RISING_EDGE;
+        }
+        if falling {
+            event_flags |= EventRequestFlags::FALLING_EDGE;
+        }
+        self.get_line()?
+            .events(
+                self.request_flags(LineRequestFlags::INPUT),
+                event_flags,
+                "pictorus",
+            )
+            .map_err(|_| self.line_error())
+    }
+
+    pub(crate) fn is_rising(&self, event: &LineEvent) -> bool {
+        (event.event_type() == EventType::RisingEdge) != self.active_low
+    }
+
+    pub(crate) fn level(&self, handle: &LineEventHandle) -> bool {
+        (handle.get_value().unwrap_or(0) != 0) != self.active_low
+    }
+}
+
+fn find_line(chip: &mut Chip, name: &str) -> Option<u32> {
+    let lines = chip.num_lines();
+    (0..lines).find(|&offset| {
+        chip.get_line(offset)
+            .and_then(|line| line.info())
+            .is_ok_and(|info| info.name() == Some(name))
+    })
+}
+
+pub struct CdevPin(linux_embedded_hal::CdevPin, bool);
 impl CdevPin {
     pub fn new(inner: linux_embedded_hal::CdevPin) -> Result<Self, PictorusError> {
-        Ok(CdevPin(inner))
+        Ok(CdevPin(inner, false))
     }
 }
 
@@ -23,21 +196,29 @@ impl ErrorType for CdevPin {
 
 impl InputPin for CdevPin {
     fn is_high(&mut self) -> Result<bool, Self::Error> {
-        self.0.is_high()
+        self.0.is_high().map(|high| high != self.1)
     }
 
     fn is_low(&mut self) -> Result<bool, Self::Error> {
-        self.0.is_low()
+        self.is_high().map(|high| !high)
     }
 }
 
 impl OutputPin for CdevPin {
     fn set_high(&mut self) -> Result<(), Self::Error> {
-        self.0.set_high()
+        if self.1 {
+            self.0.set_low()
+        } else {
+            self.0.set_high()
+        }
     }
 
     fn set_low(&mut self) -> Result<(), Self::Error> {
-        self.0.set_low()
+        if self.1 {
+            self.0.set_high()
+        } else {
+            self.0.set_low()
+        }
     }
 }
 
@@ -45,39 +226,39 @@ fn create_error(message: String) -> PictorusError {
     PictorusError::new(ERR_TYPE.into(), message)
 }
 
-fn create_pin_error(pin: u32) -> PictorusError {
-    create_error(format!("Failed to bind to GPIO pin: {}", pin))
-}
-
 fn create_cdev_pin(
-    chip: &str,
-    pin_line: f64,
-    flag: LineRequestFlags,
+    config: &GpioLineConfig,
+    direction: LineRequestFlags,
 ) -> Result<CdevPin, PictorusError> {
-    let pin_line = pin_line as u32;
-    let mut chip = Chip::new(chip).map_err(|_| {
-        create_error(format!(
-            "Failed to bind to GPIO bus {} for pin: {}",
-            chip, pin_line
-        ))
-    })?;
-    let handle = chip
-        .get_line(pin_line)
+    let handle = config
+        .get_line()?
         // TODO: Might be cleaner to impl From<linux_embedded_hal::gpio_cdev::Error> for PictorusError
-        .map_err(|_| create_pin_error(pin_line))?
-        .request(flag, 0, "pictorus")
-        .map_err(|_| create_pin_error(pin_line))?;
+        // Outputs start low, after the active-low inversion
+        .request(
+            config.request_flags(direction),
+            config.active_low as u8,
+            "pictorus",
+        )
+        .map_err(|_| config.line_error())?;
 
-    let inner = linux_embedded_hal::CdevPin::new(handle).map_err(|_| create_pin_error(pin_line))?;
-    Ok(CdevPin(inner))
+    let inner = linux_embedded_hal::CdevPin::new(handle).map_err(|_| config.line_error())?;
+    Ok(CdevPin(inner, config.active_low))
 }
 
 pub fn create_gpio_input_pin(pin_number: f64) -> Result<CdevPin, PictorusError> {
-    create_cdev_pin(GPIO_CHIP, pin_number, LineRequestFlags::INPUT)
+    create_gpio_input_line(&GpioLineConfig::pin(pin_number))
 }
 
 pub fn create_gpio_output_pin(pin_number: f64) -> Result<CdevPin, PictorusError> {
-    create_cdev_pin(GPIO_CHIP, pin_number, LineRequestFlags::OUTPUT)
+    create_gpio_output_line(&GpioLineConfig::pin(pin_number))
+}
+
+pub fn create_gpio_input_line(config: &GpioLineConfig) -> Result<CdevPin, PictorusError> {
+    create_cdev_pin(config, LineRequestFlags::INPUT)
+}
+
+pub fn create_gpio_output_line(config: &GpioLineConfig) -> Result<CdevPin, PictorusError> {
+    create_cdev_pin(config, LineRequestFlags::OUTPUT)
 }
 
 impl InputBlock for CdevPin {
@@ -110,3 +291,143 @@ impl OutputBlock for CdevPin {
         }
     }
 }
+
+/// Current time on the clock the kernel timestamps GPIO events with. Kernels before 5.7 used
+/// the realtime clock instead, which breaks converting event times to app time.
+pub(crate) fn monotonic_now() -> Duration {
+    let mut now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `now` is a valid timespec for the call to write to
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    Duration::new(now.tv_sec as u64, now.tv_nsec as u32)
+}
+
+pub(crate) fn event_timestamp(event: &LineEvent) -> Duration {
+    Duration::from_nanos(event.timestamp())
+}
+
+/// Waits for edge events on the lines and passes them to `on_event` with the index of their
+/// line, in the order they happened. Runs until the lines fail, so it gets its own thread:
+/// the kernel only buffers a few events per line, which fast signals overflow between ticks.
+pub(crate) fn watch_lines<F>(mut handles: Vec<LineEventHandle>, mut on_event: F)
+where
+    F: FnMut(usize, LineEvent) + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut fds: Vec<libc::pollfd> = handles
+            .iter()
+            .map(|handle| libc::pollfd {
+                fd: handle.as_raw_fd(),
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+        let mut events = Vec::new();
+        loop {
+            // SAFETY: `fds` is a valid array of pollfds for the duration of the call
+            let ready = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) };
+            if ready < 0 {
+                warn!("Polling GPIO edge events failed, stopping");
+                return;
+            }
+            for (line, (fd, handle)) in fds.iter().zip(handles.iter_mut()).enumerate() {
+                if fd.revents & libc::POLLIN != 0 {
+                    match handle.get_event() {
+                        Ok(event) => events.push((line, event)),
+                        Err(err) => {
+                            warn!("Reading GPIO edge event failed, stopping: {}", err);
+                            return;
+                        }
+                    }
+                }
+            }
+            events.sort_by_key(|(_, event)| event.timestamp());
+            for (line, event) in events.drain(..) {
+                on_event(line, event);
+            }
+        }
+    });
+}
+
+#[derive(Default)]
+struct EventQueue {
+    events: VecDeque<GpioEdgeEvent>,
+    level: bool,
+}
+
+/// Streams the edge events of a GPIO input line from the kernel. Events are queued on a
+/// background thread, so edges between ticks aren't missed.
+pub struct CdevEventLine {
+    queue: Arc<Mutex<EventQueue>>,
+}
+
+impl CdevEventLine {
+    pub fn new(config: &GpioLineConfig) -> Result<Self, PictorusError> {
+        let handle = config.request_events(true, true)?;
+        let queue = Arc::new(Mutex::new(EventQueue {
+            events: VecDeque::new(),
+            level: config.level(&handle),
+        }));
+        let shared = queue.clone();
+        let config = config.clone();
+        watch_lines(alloc::vec![handle], move |_, event| {
+            let rising = config.is_rising(&event);
+            let mut queue = shared.lock().unwrap();
+            if queue.events.len() == MAX_QUEUED_EVENTS {
+                warn!("Dropping GPIO edge events on line {}", config.line);
+                queue.events.pop_front();
+            }
+            queue.events.push_back(GpioEdgeEvent {
+                rising,
+                timestamp: event_timestamp(&event),
+            });
+            queue.level = rising;
+        });
+        Ok(Self { queue })
+    }
+}
+
+impl GpioEventProtocol for CdevEventLine {
+    fn next_event(&mut self) -> Option<GpioEdgeEvent> {
+        self.queue.lock().unwrap().events.pop_front()
+    }
+
+    fn level(&mut self) -> bool {
+        self.queue.lock().unwrap().level
+    }
+
+    fn now(&mut self) -> Duration {
+        monotonic_now()
+    }
+}
+
+impl InputBlock for CdevEventLine {
+    type Output = (bool, f64, f64, f64, f64);
+    type Parameters = GpioEdgeEventBlockParams;
+
+    fn input(
+        &mut self,
+        _parameters: &Self::Parameters,
+        context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        // Events on the kernel clock are converted to app time by their age
+        let now = self.now();
+        let app_time = context.time();
+        let mut edges = (self.level(), 0.0, 0.0, 0.0, 0.0);
+        while let Some(event) = self.next_event() {
+            let time = app_time
+                .saturating_sub(now.saturating_sub(event.timestamp))
+                .as_secs_f64();
+            if event.rising {
+                edges.1 += 1.0;
+                edges.3 = time;
+            } else {
+                edges.2 += 1.0;
+                edges.4 = time;
+            }
+        }
+        edges
+    }
+}
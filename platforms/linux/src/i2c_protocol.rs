@@ -1,64 +1,149 @@
 pub use embedded_hal_02::blocking::i2c::{Write, WriteRead};
 
+use core::marker::PhantomData;
+
 use corelib_traits::{ByteSliceSignal, InputBlock, OutputBlock};
-use pictorus_core_blocks::{I2cInputBlockParams, I2cOutputBlockParams};
-use protocols::I2c;
-use utils::PictorusError;
+use embedded_hal::i2c::{ErrorType, Operation, TenBitAddress};
+use log::warn;
+use pictorus_core_blocks::{I2cInputBlockParams, I2cOutputBlockParams, I2cTransactionBlockParams};
+use utils::i2c_script::I2C_OK;
+use utils::{I2cAddress, I2cScript, PictorusError};
 
-use linux_embedded_hal::i2cdev::linux::LinuxI2CError;
+use crate::{create_delay_protocol, StdDelayProtocol};
+use linux_embedded_hal::i2cdev::core::{I2CMessage, I2CTransfer};
+use linux_embedded_hal::i2cdev::linux::{
+    I2CMessageFlags, LinuxI2CBus, LinuxI2CError, LinuxI2CMessage,
+};
+use linux_embedded_hal::I2CError;
 pub use linux_embedded_hal::I2cdev;
 
 const ERR_TYPE: &str = "I2cProtocol";
 // TODO: This should be configurable by block param
 const I2C_PATH: &str = "/dev/i2c-1";
 
-pub fn create_i2c_protocol() -> Result<I2cdev, PictorusError> {
-    let i2c = I2cdev::new(I2C_PATH).map_err(|err| {
-        let msg = match err {
-            LinuxI2CError::Errno(e) => format!(
+fn bind_error(err: LinuxI2CError) -> PictorusError {
+    let msg = match err {
+        LinuxI2CError::Errno(e) => format!(
+            "Unknown error! Failed to bind to I2C device: {} ({})",
+            I2C_PATH, e
+        ),
+        LinuxI2CError::Io(e) => match e.kind() {
+            std::io::ErrorKind::NotFound => format!(
+                "Failed to bind to I2C device: {} - not found. Is the I2C bus enabled?",
+                I2C_PATH
+            ),
+            _ => format!(
                 "Unknown error! Failed to bind to I2C device: {} ({})",
                 I2C_PATH, e
             ),
-            LinuxI2CError::Io(e) => match e.kind() {
-                std::io::ErrorKind::NotFound => format!(
-                    "Failed to bind to I2C device: {} - not found. Is the I2C bus enabled?",
-                    I2C_PATH
-                ),
-                _ => format!(
-                    "Unknown error! Failed to bind to I2C device: {} ({})",
-                    I2C_PATH, e
-                ),
-            },
-        };
-        PictorusError::new(ERR_TYPE.into(), msg)
-    })?;
-
-    Ok(i2c)
+        },
+    };
+    PictorusError::new(ERR_TYPE.into(), msg)
+}
+
+pub fn create_i2c_protocol() -> Result<I2cdev, PictorusError> {
+    I2cdev::new(I2C_PATH).map_err(bind_error)
+}
+
+/// I2C bus that addresses devices with 10-bit addresses, which `I2cdev` doesn't support
+pub struct TenBitI2cBus {
+    bus: LinuxI2CBus,
+}
+
+impl TenBitI2cBus {
+    pub fn new() -> Result<Self, PictorusError> {
+        let bus = LinuxI2CBus::new(I2C_PATH).map_err(bind_error)?;
+        Ok(Self { bus })
+    }
+}
+
+impl ErrorType for TenBitI2cBus {
+    type Error = I2CError;
+}
+
+impl embedded_hal::i2c::I2c<TenBitAddress> for TenBitI2cBus {
+    fn transaction(
+        &mut self,
+        address: TenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        let mut messages: Vec<_> = operations
+            .iter_mut()
+            .map(|op| match op {
+                Operation::Write(bytes) => {
+                    LinuxI2CMessage::write(bytes).with_flags(I2CMessageFlags::TEN_BIT_ADDRESS)
+                }
+                Operation::Read(bytes) => LinuxI2CMessage::read(bytes)
+                    .with_flags(I2CMessageFlags::READ | I2CMessageFlags::TEN_BIT_ADDRESS),
+            })
+            .map(|message| message.with_address(address))
+            .collect();
+        self.bus.transfer(&mut messages).map_err(I2CError::from)?;
+        Ok(())
+    }
 }
 
-pub struct I2cWrapper {
+/// Runs the I2C block scripts on the bus. `P` is the parameters of the input block it reads
+/// for: `I2cInputBlockParams` for an I2C Input Block, or `I2cTransactionBlockParams` for an
+/// I2C Transaction Block, which also takes the result codes.
+pub struct I2cWrapper<P = I2cInputBlockParams> {
     pub i2c: I2cdev,
+    ten_bit_bus: Option<TenBitI2cBus>,
+    delay: StdDelayProtocol,
     buffer: Vec<u8>,
+    results: Vec<u8>,
+    _input: PhantomData<fn() -> P>,
 }
 
-impl I2cWrapper {
+impl<P> I2cWrapper<P> {
     pub fn new() -> Self {
         let i2c = create_i2c_protocol().expect("I2C device not found");
 
         Self {
             i2c,
+            ten_bit_bus: None,
+            delay: create_delay_protocol(),
             buffer: Vec::new(),
+            results: Vec::new(),
+            _input: PhantomData,
+        }
+    }
+
+    fn run(&mut self, address: I2cAddress, script: &I2cScript, input: &[u8]) {
+        match address {
+            I2cAddress::SevenBit(address) => script.run(
+                &mut self.i2c,
+                address,
+                &mut self.delay,
+                input,
+                &mut self.buffer,
+                &mut self.results,
+            ),
+            I2cAddress::TenBit(address) => {
+                if self.ten_bit_bus.is_none() {
+                    self.ten_bit_bus = Some(TenBitI2cBus::new().expect("I2C device not found"));
+                }
+                let bus = self.ten_bit_bus.as_mut().unwrap();
+                script.run(
+                    bus,
+                    address,
+                    &mut self.delay,
+                    input,
+                    &mut self.buffer,
+                    &mut self.results,
+                )
+            }
         }
     }
 }
 
-impl Default for I2cWrapper {
+impl<P> Default for I2cWrapper<P> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl InputBlock for I2cWrapper {
+impl InputBlock for I2cWrapper<I2cInputBlockParams> {
     type Output = ByteSliceSignal;
     type Parameters = I2cInputBlockParams;
 
@@ -67,24 +152,35 @@ impl InputBlock for I2cWrapper {
         parameters: &Self::Parameters,
         _context: &dyn corelib_traits::Context,
     ) -> corelib_traits::PassBy<'_, Self::Output> {
-        let size = parameters.read_bytes;
-        self.buffer.resize(parameters.read_bytes, 0);
-        let result = self.i2c.write_read(
-            parameters.address,
-            &[parameters.command],
-            &mut self.buffer[..size],
-        );
-
-        if result.is_err() {
-            // TODO: Error handling
-            // Keep results, good or bad, in memory
+        let address = I2cAddress::SevenBit(parameters.address);
+        self.run(address, parameters.script(), &[]);
+        if self.results.iter().any(|r| *r != I2C_OK) {
+            self.buffer.clear();
         }
 
         &self.buffer
     }
 }
 
-impl OutputBlock for I2cWrapper {
+impl InputBlock for I2cWrapper<I2cTransactionBlockParams> {
+    type Output = (ByteSliceSignal, ByteSliceSignal);
+    type Parameters = I2cTransactionBlockParams;
+
+    fn input(
+        &mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn corelib_traits::Context,
+    ) -> corelib_traits::PassBy<'_, Self::Output> {
+        self.run(parameters.address, &parameters.script, &[]);
+        if self.results.iter().any(|r| *r != I2C_OK) {
+            self.buffer.clear();
+        }
+
+        (&self.buffer, &self.results)
+    }
+}
+
+impl<P> OutputBlock for I2cWrapper<P> {
     type Inputs = ByteSliceSignal;
     type Parameters = I2cOutputBlockParams;
 
@@ -94,9 +190,9 @@ impl OutputBlock for I2cWrapper {
         _context: &dyn corelib_traits::Context,
         inputs: corelib_traits::PassBy<'_, Self::Inputs>,
     ) {
-        let mut tx_buffer = Vec::new();
-        tx_buffer.push(parameters.command);
-        tx_buffer.extend_from_slice(inputs);
-        self.i2c.write(parameters.address, &tx_buffer).ok();
+        self.run(parameters.address, &parameters.script, inputs);
+        if self.results.iter().any(|r| *r != I2C_OK) {
+            warn!("I2C write failed with results {:?}", self.results);
+        }
     }
 }
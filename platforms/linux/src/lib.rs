@@ -1,20 +1,35 @@
 extern crate alloc;
 
+mod camera_protocol;
+pub use camera_protocol::*;
+
+mod capture_protocol;
+pub use capture_protocol::*;
+
 mod clock_protocol;
 pub use clock_protocol::*;
 
 mod delay_protocol;
 pub use delay_protocol::*;
 
+mod display_protocol;
+pub use display_protocol::*;
+
 mod gpio_protocol;
 pub use gpio_protocol::*;
 
 mod i2c_protocol;
 pub use i2c_protocol::*;
 
+mod modbus_protocol;
+pub use modbus_protocol::*;
+
 mod pwm_protocol;
 pub use pwm_protocol::*;
 
+mod sensor_protocol;
+pub use sensor_protocol::*;
+
 mod serial_protocol;
 pub use serial_protocol::*;
 
@@ -0,0 +1,216 @@
+use core::time::Duration;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+
+use log::{debug, info};
+use protocols::modbus::{
+    decode_mbap_header, decode_rtu_frame, encode_mbap_header, encode_rtu_frame,
+    rtu_response_remaining, MAX_PDU_BYTES, MAX_RTU_FRAME_BYTES, MBAP_HEADER_BYTES,
+};
+use protocols::{ModbusError, ModbusProtocol};
+use serialport::{ClearBuffer, SerialPort};
+use utils::PictorusError;
+
+use crate::create_serial_port;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
+
+fn map_io_error(err: io::Error) -> ModbusError<io::Error> {
+    match err.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ModbusError::Timeout,
+        _ => ModbusError::Io(err),
+    }
+}
+
+/// Modbus TCP connection to a server. The connection is opened on the first request and
+/// re-opened after any failed request, so a server that restarts is picked up again.
+pub struct ModbusTcpConnection {
+    address: String,
+    stream: Option<TcpStream>,
+    timeout: Duration,
+    transaction_id: u16,
+}
+
+impl ModbusTcpConnection {
+    /// `address` is the server `host:port`, usually on port 502
+    pub fn new(address: &str) -> Self {
+        Self {
+            address: address.to_string(),
+            stream: None,
+            timeout: DEFAULT_TIMEOUT,
+            transaction_id: 0,
+        }
+    }
+
+    fn connect(&mut self) -> io::Result<&mut TcpStream> {
+        if self.stream.is_none() {
+            let address = self.address.to_socket_addrs()?.next().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "Modbus server address not found")
+            })?;
+            info!("Connecting to Modbus server {}", self.address);
+            let stream = TcpStream::connect_timeout(&address, self.timeout)?;
+            stream.set_nodelay(true)?;
+            self.stream = Some(stream);
+        }
+
+        let stream = self.stream.as_mut().unwrap();
+        stream.set_read_timeout(Some(self.timeout))?;
+        stream.set_write_timeout(Some(self.timeout))?;
+        Ok(stream)
+    }
+
+    fn exchange(
+        &mut self,
+        unit_id: u8,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, ModbusError<io::Error>> {
+        self.transaction_id = self.transaction_id.wrapping_add(1);
+        let transaction_id = self.transaction_id;
+        let mut frame = [0u8; MBAP_HEADER_BYTES + MAX_PDU_BYTES];
+        let frame_len = MBAP_HEADER_BYTES + request.len();
+        encode_mbap_header(transaction_id, unit_id, request.len(), &mut frame);
+        frame[MBAP_HEADER_BYTES..frame_len].copy_from_slice(request);
+
+        let stream = self.connect().map_err(map_io_error)?;
+        stream
+            .write_all(&frame[..frame_len])
+            .map_err(map_io_error)?;
+
+        // Responses to earlier requests that timed out may still arrive, so skip anything that
+        // doesn't match this transaction
+        loop {
+            let mut header = [0u8; MBAP_HEADER_BYTES];
+            stream.read_exact(&mut header).map_err(map_io_error)?;
+            let (response_id, pdu_len) = decode_mbap_header(&header)?;
+            let pdu = &mut frame[..pdu_len];
+            stream.read_exact(pdu).map_err(map_io_error)?;
+            if response_id != transaction_id {
+                debug!("Skipping Modbus response to transaction {}", response_id);
+                continue;
+            }
+            if header[6] != unit_id || pdu_len > response.len() {
+                return Err(ModbusError::InvalidResponse);
+            }
+            response[..pdu_len].copy_from_slice(pdu);
+            return Ok(pdu_len);
+        }
+    }
+}
+
+impl ModbusProtocol for ModbusTcpConnection {
+    type Error = io::Error;
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    fn transaction(
+        &mut self,
+        unit_id: u8,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, ModbusError<Self::Error>> {
+        let result = self.exchange(unit_id, request, response);
+        // After a timeout or a bad response, part of a frame may still be unread and the stream
+        // out of sync, so reconnect next time. Only an exception response leaves it clean.
+        if result
+            .as_ref()
+            .is_err_and(|err| !matches!(err, ModbusError::Exception(_)))
+        {
+            self.stream = None;
+        }
+        result
+    }
+}
+
+/// Modbus RTU connection to devices on a serial line
+pub struct ModbusRtuConnection {
+    port: Box<dyn SerialPort>,
+    /// Silent interval required between frames
+    frame_gap: Duration,
+    timeout: Duration,
+    frame: [u8; MAX_RTU_FRAME_BYTES],
+}
+
+impl ModbusRtuConnection {
+    pub fn new(port: &str, baud_rate: f64) -> Result<Self, PictorusError> {
+        info!("Opening Modbus RTU port {} with baud {}", port, baud_rate);
+        let port = create_serial_port(port, baud_rate, true)?
+            .expect("Serial port is always opened when transmit is enabled");
+        // 3.5 character times of 11 bits, fixed at 1.75 ms above 19200 baud by the spec
+        let frame_gap = if baud_rate > 19200.0 {
+            Duration::from_micros(1750)
+        } else {
+            Duration::from_secs_f64(3.5 * 11.0 / baud_rate)
+        };
+        Ok(Self {
+            port,
+            frame_gap,
+            timeout: DEFAULT_TIMEOUT,
+            frame: [0; MAX_RTU_FRAME_BYTES],
+        })
+    }
+}
+
+impl ModbusProtocol for ModbusRtuConnection {
+    type Error = io::Error;
+
+    fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    fn transaction(
+        &mut self,
+        unit_id: u8,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, ModbusError<Self::Error>> {
+        std::thread::sleep(self.frame_gap);
+        // Drop any late or unsolicited data so it isn't mistaken for this response
+        self.port
+            .clear(ClearBuffer::Input)
+            .map_err(|e| ModbusError::Io(e.into()))?;
+        self.port
+            .set_timeout(self.timeout)
+            .map_err(|e| ModbusError::Io(e.into()))?;
+
+        let frame_len = encode_rtu_frame(unit_id, request, &mut self.frame);
+        self.port
+            .write_all(&self.frame[..frame_len])
+            .map_err(map_io_error)?;
+
+        let mut head = [0u8; 3];
+        self.port.read_exact(&mut head).map_err(map_io_error)?;
+        let response_len = head.len() + rtu_response_remaining(&head);
+        if response_len > self.frame.len() {
+            return Err(ModbusError::InvalidResponse);
+        }
+        self.frame[..head.len()].copy_from_slice(&head);
+        self.port
+            .read_exact(&mut self.frame[head.len()..response_len])
+            .map_err(map_io_error)?;
+
+        let pdu = decode_rtu_frame(unit_id, &self.frame[..response_len])?;
+        if pdu.len() > response.len() {
+            return Err(ModbusError::InvalidResponse);
+        }
+        response[..pdu.len()].copy_from_slice(pdu);
+        Ok(pdu.len())
+    }
+}
+
+pub use protocols::ModbusWrapper;
+
+pub fn create_modbus_tcp_protocol(address: &str) -> ModbusWrapper<ModbusTcpConnection> {
+    ModbusWrapper::new(ModbusTcpConnection::new(address))
+}
+
+pub fn create_modbus_rtu_protocol(
+    port: &str,
+    baud_rate: f64,
+) -> Result<ModbusWrapper<ModbusRtuConnection>, PictorusError> {
+    Ok(ModbusWrapper::new(ModbusRtuConnection::new(
+        port, baud_rate,
+    )?))
+}
@@ -1,7 +1,8 @@
 use crate::gpio_protocol::create_gpio_output_pin;
+use core::str::FromStr;
 use embedded_hal_02::Pwm;
 use std::time::Duration;
-use utils::{positive_duration, PictorusError};
+use utils::{positive_duration, ParseEnumError, PictorusError};
 
 mod soft_pwm;
 use soft_pwm::SoftPwm;
@@ -9,6 +10,63 @@ use soft_pwm::SoftPwm;
 mod hard_pwm;
 use hard_pwm::HardPwm;
 
+const ERR_TYPE: &str = "PwmProtocol";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PwmPolarity {
+    /// The output is high for the duty cycle
+    #[default]
+    Normal,
+    /// The output is low for the duty cycle
+    Inversed,
+}
+
+impl FromStr for PwmPolarity {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Normal" => Ok(Self::Normal),
+            "Inversed" => Ok(Self::Inversed),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+/// Selects a hardware PWM channel, as numbered under `/sys/class/pwm`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HardPwmConfig {
+    /// Number of the PWM chip, `N` of `pwmchipN`
+    pub chip: u32,
+    /// Channel on the chip
+    pub channel: u32,
+    pub polarity: PwmPolarity,
+}
+
+impl HardPwmConfig {
+    pub fn new(chip: f64, channel: f64, polarity: &str) -> Result<Self, PictorusError> {
+        let polarity = polarity.parse().map_err(|_| {
+            PictorusError::new(
+                ERR_TYPE.into(),
+                format!("Invalid PWM polarity: {}", polarity),
+            )
+        })?;
+        Ok(Self {
+            chip: chip as u32,
+            channel: channel as u32,
+            polarity,
+        })
+    }
+
+    /// Channel `pin_number` of the first PWM chip, with normal polarity
+    pub fn pin(pin_number: f64) -> Self {
+        Self {
+            chip: 0,
+            channel: pin_number as u32,
+            polarity: PwmPolarity::Normal,
+        }
+    }
+}
+
 fn freq_to_period(frequency: f64) -> f64 {
     1.0 / frequency
 }
@@ -26,7 +84,7 @@ pub struct PwmConnection {
 
 impl PwmConnection {
     pub fn new(pin_number: f64) -> Result<Self, PictorusError> {
-        let hard_pwm = HardPwm::new(pin_number);
+        let hard_pwm = HardPwm::new(&HardPwmConfig::pin(pin_number));
         let hard_pwm = match hard_pwm {
             Ok(pwm) => {
                 log::debug!("Using hard PWM");
@@ -59,6 +117,43 @@ impl PwmConnection {
         })
     }
 
+    /// Uses a hardware PWM channel, failing rather than falling back to soft PWM if it isn't
+    /// available
+    pub fn new_hard(config: &HardPwmConfig) -> Result<Self, PictorusError> {
+        let hard_pwm = HardPwm::new(config).map_err(|err| {
+            PictorusError::new(
+                ERR_TYPE.into(),
+                format!(
+                    "Failed to open PWM channel {} of pwmchip{}: {}",
+                    config.channel, config.chip, err
+                ),
+            )
+        })?;
+        Ok(Self {
+            hard_pwm: Some(hard_pwm),
+            soft_pwm: None,
+            duty_cycle: 0.0,
+            frequency: 1.0,
+        })
+    }
+
+    /// Sets the polarity of a hardware PWM channel. Soft PWM is always normal polarity.
+    pub fn set_polarity(&mut self, polarity: PwmPolarity) -> Result<(), PictorusError> {
+        match &mut self.hard_pwm {
+            Some(hard_pwm) => hard_pwm.set_polarity(polarity).map_err(|err| {
+                PictorusError::new(
+                    ERR_TYPE.into(),
+                    format!("Failed to set PWM polarity: {}", err),
+                )
+            }),
+            None if polarity == PwmPolarity::Normal => Ok(()),
+            None => Err(PictorusError::new(
+                ERR_TYPE.into(),
+                "Soft PWM doesn't support inversed polarity".into(),
+            )),
+        }
+    }
+
     fn period(&self) -> Duration {
         positive_duration(freq_to_period(self.frequency))
     }
@@ -143,3 +238,7 @@ pub fn create_pwm_protocol(pin_number: f64) -> Result<PwmConnection, PictorusErr
     let conn = PwmConnection::new(pin_number)?;
     Ok(conn)
 }
+
+pub fn create_hard_pwm_protocol(config: &HardPwmConfig) -> Result<PwmConnection, PictorusError> {
+    PwmConnection::new_hard(config)
+}
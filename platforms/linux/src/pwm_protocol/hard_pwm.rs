@@ -1,12 +1,20 @@
 use embedded_hal_02::Pwm;
 
+use super::{HardPwmConfig, PwmPolarity};
+
 #[cfg(any(not(target_os = "linux"), test))]
 mod arch {
+    use super::{HardPwmConfig, PwmPolarity};
+
     pub struct HardPwm {}
     impl HardPwm {
-        pub fn new(_: f64) -> Result<Self, Box<dyn std::error::Error>> {
+        pub fn new(_: &HardPwmConfig) -> Result<Self, Box<dyn std::error::Error>> {
             Ok(Self {})
         }
+
+        pub fn set_polarity(&mut self, _: PwmPolarity) -> std::io::Result<()> {
+            Ok(())
+        }
     }
 
     impl super::Pwm for HardPwm {
@@ -42,29 +50,85 @@ mod arch {
 
 #[cfg(all(target_os = "linux", not(test)))]
 mod arch {
+    use std::path::PathBuf;
+
     use sysfs_pwm::Pwm as SysfsPwm;
 
+    use super::{HardPwmConfig, PwmPolarity};
+
     const NANOSECONDS_PER_SECOND: f64 = 1_000_000_000.0;
 
     pub struct HardPwm {
         pwm: SysfsPwm,
+        config: HardPwmConfig,
+        /// Duty cycle as a fraction of the period, kept so it survives period changes
+        duty: f64,
+        enabled: bool,
     }
 
     impl HardPwm {
-        pub fn new(pin: f64) -> Result<Self, Box<dyn std::error::Error>> {
-            // TODO: Allow selecting chip number
-            let pwm = SysfsPwm::new(0, pin as u32)?;
+        pub fn new(config: &HardPwmConfig) -> Result<Self, Box<dyn std::error::Error>> {
+            let pwm = SysfsPwm::new(config.chip, config.channel)?;
 
-            // Need to export/enable the pin before we can tell if it actually exists
-            // Alternatively we could try to check npwm to see if the pin number is in range
+            // Need to export the channel before we can tell if it actually exists
+            // Alternatively we could try to check npwm to see if the channel number is in range
             pwm.export()?;
-            pwm.enable(true)?;
-            Ok(Self { pwm })
+            let mut hard_pwm = Self {
+                pwm,
+                config: config.clone(),
+                duty: 0.0,
+                enabled: false,
+            };
+            // Polarity can only be changed while the channel is disabled
+            hard_pwm.pwm.enable(false)?;
+            hard_pwm.write_polarity(config.polarity)?;
+            hard_pwm.pwm.enable(true)?;
+            hard_pwm.enabled = true;
+            Ok(hard_pwm)
+        }
+
+        fn attribute_path(&self, attribute: &str) -> PathBuf {
+            PathBuf::from(format!(
+                "/sys/class/pwm/pwmchip{}/pwm{}/{}",
+                self.config.chip, self.config.channel, attribute
+            ))
+        }
+
+        // sysfs-pwm doesn't expose polarity, so it is written to the attribute directly
+        fn write_polarity(&mut self, polarity: PwmPolarity) -> std::io::Result<()> {
+            let value = match polarity {
+                PwmPolarity::Normal => "normal",
+                PwmPolarity::Inversed => "inversed",
+            };
+            std::fs::write(self.attribute_path("polarity"), value)?;
+            self.config.polarity = polarity;
+            Ok(())
+        }
+
+        pub fn set_polarity(&mut self, polarity: PwmPolarity) -> std::io::Result<()> {
+            if polarity == self.config.polarity {
+                return Ok(());
+            }
+
+            let map_err = |err: sysfs_pwm::Error| std::io::Error::other(err.to_string());
+            self.pwm.enable(false).map_err(map_err)?;
+            let result = self.write_polarity(polarity);
+            if self.enabled {
+                self.pwm.enable(true).map_err(map_err)?;
+            }
+            result
+        }
+
+        fn duty_ns(&self, period_ns: u32) -> u32 {
+            (period_ns as f64 * self.duty).round() as u32
         }
     }
 
     impl Drop for HardPwm {
         fn drop(&mut self) {
+            // Some drivers keep an unexported channel running, so stop it first rather than
+            // leaving it at its last duty cycle after the app exits
+            self.pwm.enable(false).ok();
             self.pwm.unexport().ok();
         }
     }
@@ -76,10 +140,12 @@ mod arch {
 
         fn disable(&mut self, _: Self::Channel) {
             self.pwm.enable(false).unwrap();
+            self.enabled = false;
         }
 
         fn enable(&mut self, _: Self::Channel) {
             self.pwm.enable(true).unwrap();
+            self.enabled = true;
         }
 
         fn get_duty(&self, _: Self::Channel) -> Self::Duty {
@@ -91,7 +157,8 @@ mod arch {
         }
 
         fn set_duty(&mut self, _: Self::Channel, duty: Self::Duty) {
-            let duty_ns = (self.pwm.get_period_ns().unwrap() as f64 * duty).round() as u32;
+            self.duty = duty.clamp(0.0, 1.0);
+            let duty_ns = self.duty_ns(self.pwm.get_period_ns().unwrap());
             self.pwm.set_duty_cycle_ns(duty_ns).unwrap();
         }
 
@@ -100,11 +167,25 @@ mod arch {
             P: Into<Self::Time>,
         {
             let period_ns = period.into() * NANOSECONDS_PER_SECOND;
-            if period_ns == 0.0 {
+            if period_ns < 1.0 {
                 log::warn!("Period cannot be zero");
                 return;
             }
-            self.pwm.set_period_ns(period_ns as u32).unwrap();
+            let period_ns = period_ns as u32;
+            if period_ns == self.pwm.get_period_ns().unwrap() {
+                return;
+            }
+
+            // The kernel rejects a duty cycle longer than the period, so when the period
+            // shrinks the duty cycle has to shrink first
+            let duty_ns = self.duty_ns(period_ns);
+            if duty_ns < self.pwm.get_duty_cycle_ns().unwrap() {
+                self.pwm.set_duty_cycle_ns(duty_ns).unwrap();
+                self.pwm.set_period_ns(period_ns).unwrap();
+            } else {
+                self.pwm.set_period_ns(period_ns).unwrap();
+                self.pwm.set_duty_cycle_ns(duty_ns).unwrap();
+            }
         }
 
         fn get_period(&self) -> Self::Time {
@@ -0,0 +1,28 @@
+use utils::PictorusError;
+
+use crate::{create_delay_protocol, create_i2c_protocol, I2cdev, StdDelayProtocol};
+
+pub use sensor_drivers::wrappers::{BarometerWrapper, ImuWrapper, MagnetometerWrapper};
+
+pub fn create_imu_protocol() -> Result<ImuWrapper<I2cdev, StdDelayProtocol>, PictorusError> {
+    Ok(ImuWrapper::new(
+        create_i2c_protocol()?,
+        create_delay_protocol(),
+    ))
+}
+
+pub fn create_barometer_protocol(
+) -> Result<BarometerWrapper<I2cdev, StdDelayProtocol>, PictorusError> {
+    Ok(BarometerWrapper::new(
+        create_i2c_protocol()?,
+        create_delay_protocol(),
+    ))
+}
+
+pub fn create_magnetometer_protocol(
+) -> Result<MagnetometerWrapper<I2cdev, StdDelayProtocol>, PictorusError> {
+    Ok(MagnetometerWrapper::new(
+        create_i2c_protocol()?,
+        create_delay_protocol(),
+    ))
+}
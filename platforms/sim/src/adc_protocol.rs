@@ -1,4 +1,6 @@
-use protocols::AdcProtocol;
+use corelib_traits::{Context, InputBlock, Matrix, PassBy};
+use pictorus_core_blocks::MultiChannelAdcBlockParams;
+use protocols::{AdcProtocol, MultiChannelAdcProtocol};
 
 pub struct SimAdc {}
 
@@ -23,3 +25,110 @@ impl AdcProtocol for SimAdc {
         // Do nothing
     }
 }
+
+/// Full-scale count of the simulated ADC, which has 12 bits like most MCU ADCs
+const SIM_ADC_FULL_SCALE: f32 = 4095.0;
+
+/// A simulated multi-channel ADC. The voltage on each channel and the reference voltage can
+/// be set, and readings are quantized and clipped like a 12-bit converter.
+pub struct SimMultiChannelAdc<const N: usize> {
+    voltages: [f32; N],
+    reference_voltage: f32,
+    readings: Matrix<1, N, f64>,
+}
+
+impl<const N: usize> SimMultiChannelAdc<N> {
+    pub fn new(reference_voltage: f32) -> Self {
+        Self {
+            voltages: [0.0; N],
+            reference_voltage,
+            readings: Matrix::zeroed(),
+        }
+    }
+
+    /// Sets the voltage on a channel
+    pub fn set_voltage(&mut self, channel: usize, voltage: f32) {
+        self.voltages[channel] = voltage;
+    }
+
+    /// Sets the reference voltage, which the internal reference measures exactly
+    pub fn set_reference_voltage(&mut self, reference_voltage: f32) {
+        self.reference_voltage = reference_voltage;
+    }
+}
+
+impl<const N: usize> Default for SimMultiChannelAdc<N> {
+    fn default() -> Self {
+        Self::new(3.3)
+    }
+}
+
+impl<const N: usize> MultiChannelAdcProtocol<N> for SimMultiChannelAdc<N> {
+    fn read_channels(&mut self, _oversampling: u16, _hardware_oversampling: bool) -> [f32; N] {
+        // The inputs are noiseless, so averaging repeated conversions changes nothing
+        self.voltages.map(|voltage| {
+            let counts = (voltage / self.reference_voltage * SIM_ADC_FULL_SCALE)
+                .round()
+                .clamp(0.0, SIM_ADC_FULL_SCALE);
+            counts / SIM_ADC_FULL_SCALE
+        })
+    }
+
+    fn reference_voltage(&mut self) -> Option<f32> {
+        Some(self.reference_voltage)
+    }
+}
+
+impl<const N: usize> InputBlock for SimMultiChannelAdc<N> {
+    type Output = (Matrix<1, N, f64>, f64);
+    type Parameters = MultiChannelAdcBlockParams<N>;
+
+    fn input(
+        &mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        let readings =
+            self.read_channels(parameters.oversampling, parameters.hardware_oversampling);
+        for (output, reading) in self.readings.data.iter_mut().zip(readings) {
+            output[0] = reading as f64;
+        }
+        let reference_voltage = self.reference_voltage().unwrap_or_default() as f64;
+        (&self.readings, reference_voltage)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corelib_traits::ProcessBlock;
+    use corelib_traits_testing::StubContext;
+    use pictorus_core_blocks::MultiChannelAdcBlock;
+
+    #[test]
+    fn test_sim_multi_channel_adc_feeds_block() {
+        // A 1:11 battery divider and a sensor that is pulled to the rail
+        let parameters = MultiChannelAdcBlockParams::new(
+            4.0,
+            false,
+            3.3,
+            true,
+            [[0.0, 11.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0]],
+            [9.0, 0.0],
+            [13.0, 3.3],
+        );
+        let context = StubContext::default();
+        let mut adc = SimMultiChannelAdc::<2>::default();
+        let mut block = MultiChannelAdcBlock::<2>::default();
+
+        adc.set_reference_voltage(3.2);
+        adc.set_voltage(0, 12.1 / 11.0);
+        adc.set_voltage(1, 5.0);
+        let inputs = adc.input(&parameters, &context);
+        let (values, out_of_range, any_out_of_range) = block.process(&parameters, &context, inputs);
+
+        assert!((values.data[0][0] - 12.1).abs() < 0.01);
+        assert_eq!(out_of_range.data, [[0.0], [1.0]]);
+        assert!(any_out_of_range);
+    }
+}
@@ -1,14 +1,61 @@
+use std::sync::{Arc, Mutex};
+
 use log::debug;
 use protocols::CameraProtocol;
 
-pub struct CameraConnection;
+/// A photo requested from the simulated camera
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedPhoto {
+    pub photo_dir: String,
+    pub jpeg_quality: u8,
+}
+
+/// Simulated camera that records each capture instead of taking a photo. Clones share the
+/// same record, so a clone can be kept to inspect captures made from another thread.
+#[derive(Clone, Default)]
+pub struct CameraConnection {
+    captures: Arc<Mutex<Vec<CapturedPhoto>>>,
+}
+
+impl CameraConnection {
+    /// All captures so far, oldest first
+    pub fn captures(&self) -> Vec<CapturedPhoto> {
+        self.captures.lock().unwrap().clone()
+    }
+}
 
 impl CameraProtocol for CameraConnection {
-    fn capture(&self, _: &str, _: u8) {
-        debug!("Capturing photo");
+    fn capture(&self, photo_dir: &str, jpeg_quality: u8) {
+        debug!("Capturing photo to {}", photo_dir);
+        self.captures.lock().unwrap().push(CapturedPhoto {
+            photo_dir: photo_dir.to_string(),
+            jpeg_quality,
+        });
     }
 }
 
 pub fn create_camera_connection() -> CameraConnection {
-    CameraConnection {}
+    CameraConnection::default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_camera_records_captures() {
+        let camera = create_camera_connection();
+        let handle = camera.clone();
+        std::thread::spawn(move || camera.capture("/tmp/photos", 80))
+            .join()
+            .unwrap();
+
+        assert_eq!(
+            handle.captures(),
+            [CapturedPhoto {
+                photo_dir: "/tmp/photos".to_string(),
+                jpeg_quality: 80,
+            }]
+        );
+    }
 }
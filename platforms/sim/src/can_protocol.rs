@@ -1,67 +1,534 @@
+use std::collections::{BTreeMap, VecDeque};
 use std::convert::Infallible;
+use std::sync::{Arc, Mutex, Weak};
 
-use embedded_can::nb::Can;
-use protocols::CanProtocol;
+use core::time::Duration;
+use corelib_traits::{Context, InputBlock, PassBy};
+use embedded_can::{nb::Can, ErrorKind, Frame};
+use pictorus_core_blocks::CanBusStatusBlockParams;
+use protocols::can::{BusLoadMeter, CanBusState, CanBusStatus, CanConfig, CanFilter, CanFrame};
+use protocols::{CanProtocol, CanStatusProtocol};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
 
-pub struct SimFrame {}
-impl embedded_can::Frame for SimFrame {
-    fn new(_id: impl Into<embedded_can::Id>, _data: &[u8]) -> Option<Self> {
-        Some(Self {})
+/// Frames a node holds before the oldest are lost, like a socket's receive buffer
+const RX_QUEUE_LEN: usize = 256;
+
+/// Frame type of [`SimCan`]
+pub type SimFrame = CanFrame;
+
+/// Order frames waiting for a virtual bus are sent in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CanArbitration {
+    /// Lowest ID first, as CAN arbitration orders frames sent at the same time
+    Priority,
+    /// The order they were transmitted in, as from a single FIFO transmit queue
+    Fifo,
+}
+
+/// A frame that went on a virtual bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusFrame {
+    /// Bus time the frame was sent at
+    pub time: Duration,
+    pub frame: CanFrame,
+    /// Whether the frame was lost rather than delivered
+    pub dropped: bool,
+}
+
+struct Node {
+    id: usize,
+    fd: bool,
+    filters: Vec<CanFilter>,
+    /// Received frames and the bus time they arrive at
+    rx: VecDeque<(Duration, CanFrame)>,
+    bus_load: BusLoadMeter,
+}
+
+struct Pending {
+    /// Node that sent the frame, or `None` if it was injected
+    from: Option<usize>,
+    frame: CanFrame,
+}
+
+/// An in-process CAN bus connecting every `SimCan` opened on the same interface name.
+///
+/// Transmitted frames wait until the bus next runs, which is whenever a node reads from it,
+/// and are then sent in arbitration order. The bus keeps its own clock, which advances to the
+/// latest time any node or [`SimCanBus`] handle gives it.
+struct VirtualBus {
+    next_node: usize,
+    nodes: Vec<Node>,
+    pending: Vec<Pending>,
+    now: Duration,
+    latency: Duration,
+    drop_rate: f64,
+    arbitration: CanArbitration,
+    rng: SmallRng,
+    log: Vec<BusFrame>,
+}
+
+impl Default for VirtualBus {
+    fn default() -> Self {
+        Self {
+            next_node: 0,
+            nodes: vec![],
+            pending: vec![],
+            now: Duration::ZERO,
+            latency: Duration::ZERO,
+            drop_rate: 0.0,
+            arbitration: CanArbitration::Priority,
+            rng: SmallRng::seed_from_u64(0),
+            log: vec![],
+        }
+    }
+}
+
+impl VirtualBus {
+    /// Queues a frame to send, returning false if no other node is attached to acknowledge it
+    fn queue(&mut self, from: Option<usize>, frame: &CanFrame) -> bool {
+        self.pending.push(Pending {
+            from,
+            frame: *frame,
+        });
+        self.nodes.iter().any(|node| Some(node.id) != from)
+    }
+
+    /// Sends the waiting frames
+    fn run(&mut self) {
+        let mut pending = core::mem::take(&mut self.pending);
+        if self.arbitration == CanArbitration::Priority {
+            // Stable, so frames with the same ID keep their order
+            pending.sort_by_key(|pending| pending.frame.id());
+        }
+
+        let arrival = self.now + self.latency;
+        for Pending { from, frame } in pending {
+            let dropped = self.drop_rate > 0.0 && self.rng.gen::<f64>() < self.drop_rate;
+            self.log.push(BusFrame {
+                time: self.now,
+                frame,
+                dropped,
+            });
+
+            for node in self.nodes.iter_mut() {
+                node.bus_load.record(&frame);
+                // Classic controllers can't receive FD frames
+                if dropped
+                    || Some(node.id) == from
+                    || (frame.is_fd() && !node.fd)
+                    || !CanFilter::accepts(&node.filters, frame.id())
+                {
+                    continue;
+                }
+                if node.rx.len() == RX_QUEUE_LEN {
+                    node.rx.pop_front();
+                }
+                node.rx.push_back((arrival, frame));
+            }
+        }
+    }
+
+    fn set_time(&mut self, now: Duration) {
+        self.now = self.now.max(now);
     }
 
-    fn new_remote(_id: impl Into<embedded_can::Id>, _dlc: usize) -> Option<Self> {
-        Some(Self {})
+    fn node(&mut self, id: usize) -> &mut Node {
+        self.nodes
+            .iter_mut()
+            .find(|node| node.id == id)
+            .expect("Node is attached while its SimCan exists")
+    }
+}
+
+/// Buses by interface name. A bus is freed once its last `SimCan` and `SimCanBus` are
+/// dropped, so the next simulation or test to open the name starts with a fresh bus.
+static BUSES: Mutex<BTreeMap<String, Weak<Mutex<VirtualBus>>>> = Mutex::new(BTreeMap::new());
+
+fn attach(name: &str) -> Arc<Mutex<VirtualBus>> {
+    let mut buses = BUSES.lock().unwrap();
+    buses.retain(|_, bus| bus.strong_count() > 0);
+    if let Some(bus) = buses.get(name).and_then(Weak::upgrade) {
+        return bus;
+    }
+    let bus = Arc::new(Mutex::new(VirtualBus::default()));
+    buses.insert(name.to_string(), Arc::downgrade(&bus));
+    bus
+}
+
+/// Handle to a virtual bus, for configuring it and for tests to inject and inspect frames
+#[derive(Clone)]
+pub struct SimCanBus {
+    bus: Arc<Mutex<VirtualBus>>,
+}
+
+impl SimCanBus {
+    /// The bus `SimCan`s opened on interface `name` attach to
+    pub fn named(name: &str) -> Self {
+        Self { bus: attach(name) }
+    }
+
+    /// Sets the delay from a frame being sent to it arriving at the other nodes
+    pub fn set_latency(&self, latency: Duration) {
+        self.bus.lock().unwrap().latency = latency;
     }
 
-    fn is_extended(&self) -> bool {
-        false
+    /// Sets the fraction of frames lost on the bus, from 0 to 1
+    pub fn set_drop_rate(&self, drop_rate: f64) {
+        self.bus.lock().unwrap().drop_rate = drop_rate.clamp(0.0, 1.0);
     }
 
-    fn is_remote_frame(&self) -> bool {
-        false
+    /// Seeds the random choice of frames to drop
+    pub fn set_seed(&self, seed: u64) {
+        self.bus.lock().unwrap().rng = SmallRng::seed_from_u64(seed);
     }
 
-    fn id(&self) -> embedded_can::Id {
-        embedded_can::Id::Standard(embedded_can::StandardId::ZERO)
+    pub fn set_arbitration(&self, arbitration: CanArbitration) {
+        self.bus.lock().unwrap().arbitration = arbitration;
     }
 
-    fn dlc(&self) -> usize {
-        0
+    /// Advances the bus clock
+    pub fn set_time(&self, now: Duration) {
+        self.bus.lock().unwrap().set_time(now);
     }
 
-    fn data(&self) -> &[u8] {
-        &[]
+    /// Sends a frame from outside any model, like another device on the bus
+    pub fn inject(&self, frame: &CanFrame) {
+        self.bus.lock().unwrap().queue(None, frame);
+    }
+
+    /// Sends the waiting frames and returns every frame that has gone on the bus
+    pub fn frames(&self) -> Vec<BusFrame> {
+        let mut bus = self.bus.lock().unwrap();
+        bus.run();
+        bus.log.clone()
+    }
+
+    pub fn clear_frames(&self) {
+        self.bus.lock().unwrap().log.clear();
     }
 }
 
+/// Simulated CAN interface on an in-process virtual bus. Every `SimCan` opened with the same
+/// interface name shares a bus, so models, or a model and a test, can exchange frames.
+///
+/// Frames reach the other nodes once the bus runs, after the bus's latency, unless the bus
+/// drops them; see [`SimCanBus`]. A frame sent while no other node is attached goes
+/// unacknowledged, which leaves the node error passive until another node acknowledges one of
+/// its frames, as on a real bus.
 pub struct SimCan {
-    frames: Vec<SimFrame>,
+    bus: Arc<Mutex<VirtualBus>>,
+    node: usize,
+    fd: bool,
+    frames: Vec<CanFrame>,
+    stale: bool,
+    state: CanBusState,
+    error_frames: u32,
+    now: Duration,
+    last_status: Duration,
 }
 
 impl SimCan {
-    pub fn new(_iface: &str) -> Result<Self, Infallible> {
-        Ok(Self { frames: vec![] })
+    pub fn new(iface: &str) -> Result<Self, Infallible> {
+        Self::with_config(iface, &CanConfig::default())
+    }
+
+    pub fn with_config(iface: &str, config: &CanConfig<'_>) -> Result<Self, Infallible> {
+        let bus = attach(iface);
+
+        let node = {
+            let mut bus = bus.lock().unwrap();
+            let node = bus.next_node;
+            bus.next_node += 1;
+            bus.nodes.push(Node {
+                id: node,
+                fd: config.fd,
+                filters: config.filters.to_vec(),
+                rx: VecDeque::new(),
+                bus_load: BusLoadMeter::new(config.bitrate, config.data_bitrate),
+            });
+            node
+        };
+
+        Ok(Self {
+            bus,
+            node,
+            fd: config.fd,
+            frames: vec![],
+            stale: true,
+            state: CanBusState::default(),
+            error_frames: 0,
+            now: Duration::ZERO,
+            last_status: Duration::ZERO,
+        })
+    }
+
+    /// Sets the app time of the node, advancing the bus clock. Bus load is measured against
+    /// this time.
+    pub fn set_time(&mut self, now: Duration) {
+        self.now = now;
+        self.bus.lock().unwrap().set_time(now);
+    }
+
+    pub fn bus(&self) -> SimCanBus {
+        SimCanBus {
+            bus: self.bus.clone(),
+        }
+    }
+}
+
+impl Drop for SimCan {
+    fn drop(&mut self) {
+        if let Ok(mut bus) = self.bus.lock() {
+            bus.nodes.retain(|node| node.id != self.node);
+        }
     }
 }
 
 impl Can for SimCan {
-    type Frame = SimFrame;
-    type Error = embedded_can::ErrorKind;
+    type Frame = CanFrame;
+    type Error = ErrorKind;
+
+    fn transmit(&mut self, frame: &Self::Frame) -> nb::Result<Option<Self::Frame>, Self::Error> {
+        if frame.is_fd() && !self.fd {
+            return Err(nb::Error::Other(ErrorKind::Other));
+        }
 
-    fn transmit(&mut self, _frame: &Self::Frame) -> nb::Result<Option<Self::Frame>, Self::Error> {
+        if self.bus.lock().unwrap().queue(Some(self.node), frame) {
+            self.state = CanBusState::ErrorActive;
+        } else {
+            self.state = CanBusState::ErrorPassive;
+            self.error_frames += 1;
+        }
         Ok(None)
     }
+
     fn receive(&mut self) -> nb::Result<Self::Frame, Self::Error> {
-        Ok(SimFrame {})
+        let mut bus = self.bus.lock().unwrap();
+        bus.run();
+        let now = bus.now;
+        let rx = &mut bus.node(self.node).rx;
+        match rx.front() {
+            Some((arrival, _)) if *arrival <= now => Ok(rx.pop_front().unwrap().1),
+            _ => Err(nb::Error::WouldBlock),
+        }
     }
 }
 
 impl CanProtocol for SimCan {
     fn read_frames(&mut self) -> &[impl protocols::Frame] {
+        if self.stale {
+            while let Ok(frame) = self.receive() {
+                self.frames.push(frame);
+            }
+            self.stale = false;
+        }
         &self.frames
     }
 
     fn flush(&mut self) {
+        self.stale = true;
         self.frames.clear();
     }
 }
+
+impl CanStatusProtocol for SimCan {
+    fn bus_status(&mut self) -> CanBusStatus {
+        let elapsed = self.now.saturating_sub(self.last_status);
+        self.last_status = self.now;
+        let mut bus = self.bus.lock().unwrap();
+        bus.run();
+        let bus_load = bus.node(self.node).bus_load.take_load(elapsed);
+        CanBusStatus {
+            state: self.state,
+            error_frames: core::mem::take(&mut self.error_frames),
+            tx_dropped: 0,
+            bus_load,
+        }
+    }
+}
+
+impl InputBlock for SimCan {
+    type Output = (f64, f64, f64, f64);
+    type Parameters = CanBusStatusBlockParams;
+
+    fn input(
+        &mut self,
+        _parameters: &Self::Parameters,
+        context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        self.set_time(context.time());
+        let status = self.bus_status();
+        (
+            status.state as u8 as f64,
+            status.error_frames as f64,
+            status.tx_dropped as f64,
+            status.bus_load as f64,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use embedded_can::{ExtendedId, Id, StandardId};
+
+    fn standard(id: u16) -> StandardId {
+        StandardId::new(id).unwrap()
+    }
+
+    #[test]
+    fn test_sim_can_models_exchange_frames() {
+        let filters = [
+            CanFilter::exact(standard(0x100)),
+            CanFilter::new(standard(0x200), 0x700),
+        ];
+        let config = CanConfig {
+            fd: true,
+            filters: &filters,
+            ..Default::default()
+        };
+        let mut receiver = SimCan::with_config("test_exchange", &config).unwrap();
+        let mut classic = SimCan::new("test_exchange").unwrap();
+        let mut other_bus = SimCan::new("test_exchange_other").unwrap();
+
+        let frames = [
+            CanFrame::new(standard(0x100), &[1, 2]).unwrap(),
+            CanFrame::new(standard(0x101), &[3]).unwrap(),
+            CanFrame::new(standard(0x2AB), &[4]).unwrap(),
+            CanFrame::new(ExtendedId::new(0x100).unwrap(), &[5]).unwrap(),
+        ];
+        for frame in &frames {
+            classic.transmit(frame).unwrap();
+        }
+        other_bus.transmit(&frames[0]).unwrap();
+
+        let received = receiver.read_frames();
+        assert_eq!(received.len(), 2);
+        assert_eq!(received[0].data(), &[1, 2]);
+        assert_eq!(received[1].id(), frames[2].id());
+        receiver.flush();
+
+        // FD frames reach FD nodes only, and classic nodes can't send them
+        let fd_frame = CanFrame::new_fd(standard(0x100), &[7; 10], true).unwrap();
+        assert!(classic.transmit(&fd_frame).is_err());
+        receiver.transmit(&fd_frame).unwrap();
+        assert!(classic.read_frames().is_empty());
+        assert!(receiver.read_frames().is_empty());
+        assert_eq!(fd_frame.data().len(), 12);
+
+        assert_eq!(classic.bus_status().state, CanBusState::ErrorActive);
+        let status = other_bus.bus_status();
+        assert_eq!(status.state, CanBusState::ErrorPassive);
+        assert_eq!(status.error_frames, 1);
+    }
+
+    #[test]
+    fn test_sim_can_bus_status() {
+        let config = CanConfig {
+            bitrate: 125_000,
+            ..Default::default()
+        };
+        let mut node = SimCan::with_config("test_status", &config).unwrap();
+        let _peer = SimCan::new("test_status").unwrap();
+
+        // Ten 8 byte standard frames of 111 bits take 8.88 ms at 125 kbit/s
+        let frame = CanFrame::new(standard(0x10), &[0; 8]).unwrap();
+        for _ in 0..10 {
+            node.transmit(&frame).unwrap();
+        }
+        let mut context = corelib_traits_testing::StubContext {
+            time: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let (state, error_frames, tx_dropped, bus_load) =
+            node.input(&CanBusStatusBlockParams::new(0.0), &context);
+        assert_eq!((state, error_frames, tx_dropped), (0.0, 0.0, 0.0));
+        assert!((bus_load - 0.0888).abs() < 1e-6);
+
+        context.time = Duration::from_millis(200);
+        let output = node.input(&CanBusStatusBlockParams::new(0.0), &context);
+        assert_eq!(output.3, 0.0);
+    }
+
+    #[test]
+    fn test_sim_can_bus_latency_and_arbitration() {
+        let mut sender = SimCan::new("test_arbitration").unwrap();
+        let mut receiver = SimCan::new("test_arbitration").unwrap();
+        let bus = receiver.bus();
+        bus.set_latency(Duration::from_millis(5));
+
+        for id in [0x300, 0x100, 0x200] {
+            sender
+                .transmit(&CanFrame::new(standard(id), &[id as u8]).unwrap())
+                .unwrap();
+        }
+        assert!(receiver.read_frames().is_empty());
+        receiver.flush();
+
+        receiver.set_time(Duration::from_millis(5));
+        let ids: Vec<_> = receiver.read_frames().iter().map(|f| f.id()).collect();
+        assert_eq!(
+            ids,
+            [standard(0x100), standard(0x200), standard(0x300)].map(Id::from)
+        );
+        receiver.flush();
+
+        bus.set_arbitration(CanArbitration::Fifo);
+        bus.set_latency(Duration::ZERO);
+        for id in [0x300, 0x100] {
+            sender
+                .transmit(&CanFrame::new(standard(id), &[]).unwrap())
+                .unwrap();
+        }
+        let ids: Vec<_> = receiver.read_frames().iter().map(|f| f.id()).collect();
+        assert_eq!(ids, [standard(0x300), standard(0x100)].map(Id::from));
+
+        let frames = bus.frames();
+        assert_eq!(frames.len(), 5);
+        assert_eq!(frames[0].time, Duration::ZERO);
+        assert_eq!(frames[4].time, Duration::from_millis(5));
+        assert!(frames.iter().all(|frame| !frame.dropped));
+    }
+
+    #[test]
+    fn test_sim_can_bus_injection_and_drops() {
+        let mut node = SimCan::new("test_injection").unwrap();
+        let bus = SimCanBus::named("test_injection");
+        bus.set_seed(7);
+        bus.set_drop_rate(0.25);
+
+        for i in 0..200u16 {
+            bus.inject(&CanFrame::new(standard(i & 0x7FF), &i.to_le_bytes()).unwrap());
+        }
+        let received = node.read_frames().len();
+        let frames = bus.frames();
+        let dropped = frames.iter().filter(|frame| frame.dropped).count();
+        assert_eq!(frames.len(), 200);
+        assert_eq!(received + dropped, 200);
+        assert!((30..70).contains(&dropped), "dropped {dropped}");
+
+        bus.clear_frames();
+        assert!(bus.frames().is_empty());
+    }
+
+    #[test]
+    fn test_sim_can_bus_freed_with_last_node() {
+        let node = SimCan::new("test_freed").unwrap();
+        let bus = node.bus();
+        bus.set_latency(Duration::from_millis(5));
+        bus.inject(&CanFrame::new(standard(0x10), &[]).unwrap());
+        assert_eq!(bus.frames().len(), 1);
+        drop(bus);
+        drop(node);
+        let buses = BUSES.lock().unwrap();
+        assert_eq!(buses.get("test_freed").map_or(0, Weak::strong_count), 0);
+        drop(buses);
+
+        // The next node on the name starts with a fresh bus, without the latency or frames
+        let mut node = SimCan::new("test_freed").unwrap();
+        let bus = node.bus();
+        assert!(bus.frames().is_empty());
+        bus.inject(&CanFrame::new(standard(0x10), &[]).unwrap());
+        assert_eq!(node.read_frames().len(), 1);
+    }
+}
@@ -0,0 +1,237 @@
+use core::time::Duration;
+
+use corelib_traits::{Context, InputBlock, PassBy};
+use pictorus_core_blocks::{
+    EdgeCounterBlockParams, PulseCaptureBlockParams, QuadratureEncoderBlockParams,
+};
+use protocols::capture::{
+    EdgeAccumulator, EdgeCount, PulseMeasurement, PulseTimer, QuadratureCount, QuadratureDecoder,
+};
+use protocols::{EdgeCounterProtocol, PulseCaptureProtocol, QuadratureProtocol};
+
+/// Simulated edge counter input. Tests and simulations drive it by adding edges at app times,
+/// and reads report the edges added since the previous read.
+#[derive(Default)]
+pub struct SimEdgeCounter {
+    edges: EdgeAccumulator,
+    now: Duration,
+}
+
+impl SimEdgeCounter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn edge(&mut self, at: Duration) {
+        self.edges.edge(at);
+    }
+}
+
+impl EdgeCounterProtocol for SimEdgeCounter {
+    fn read_edges(&mut self) -> EdgeCount {
+        self.edges.read(self.now)
+    }
+}
+
+impl InputBlock for SimEdgeCounter {
+    type Output = (f64, f64);
+    type Parameters = EdgeCounterBlockParams;
+
+    fn input(
+        &mut self,
+        _parameters: &Self::Parameters,
+        context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        self.now = context.time();
+        let count = self.read_edges();
+        let since_last_edge = count.since_last_edge.unwrap_or_default();
+        (
+            count.edges as f64,
+            self.now.saturating_sub(since_last_edge).as_secs_f64(),
+        )
+    }
+}
+
+/// Simulated quadrature encoder. Tests and simulations drive it by stepping the A and B
+/// signals, and reads report the decoded position.
+#[derive(Default)]
+pub struct SimQuadrature {
+    decoder: QuadratureDecoder,
+    /// Position in counts, which determines the A and B levels
+    counts: i64,
+    now: Duration,
+}
+
+impl SimQuadrature {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves the encoder by `counts`, one A or B edge per count, with the last edge at `at`
+    pub fn step(&mut self, counts: i64, at: Duration) {
+        for _ in 0..counts.unsigned_abs() {
+            self.counts += counts.signum();
+            // The A and B levels lead and lag each other by a quarter cycle
+            let phase = self.counts.rem_euclid(4);
+            self.decoder
+                .update(phase == 1 || phase == 2, phase >= 2, at);
+        }
+    }
+
+    /// Sends an index pulse at the current position
+    pub fn index(&mut self) {
+        self.decoder.index();
+    }
+}
+
+impl QuadratureProtocol for SimQuadrature {
+    fn read_position(&mut self) -> QuadratureCount {
+        self.decoder.read(self.now)
+    }
+}
+
+impl InputBlock for SimQuadrature {
+    type Output = (f64, f64, bool, f64);
+    type Parameters = QuadratureEncoderBlockParams;
+
+    fn input(
+        &mut self,
+        _parameters: &Self::Parameters,
+        context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        self.now = context.time();
+        let count = self.read_position();
+        let since_last_count = count.since_last_count.unwrap_or_default();
+        (
+            count.position as f64,
+            self.now.saturating_sub(since_last_count).as_secs_f64(),
+            count.index_position.is_some(),
+            count.index_position.unwrap_or_default() as f64,
+        )
+    }
+}
+
+/// Simulated pulse capture input. Tests and simulations drive it with the edges of the
+/// captured signal.
+#[derive(Default)]
+pub struct SimPulseCapture {
+    timer: PulseTimer,
+    period_end: Option<Duration>,
+}
+
+impl SimPulseCapture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds one period of the signal, high for `high_time` from `at` and then low until the
+    /// next rising edge
+    pub fn pulse(&mut self, at: Duration, period: Duration, high_time: Duration) {
+        // Back to back periods share their rising edge
+        if self.period_end != Some(at) {
+            self.timer.edge(true, at);
+        }
+        self.timer.edge(false, at + high_time);
+        self.timer.edge(true, at + period);
+        self.period_end = Some(at + period);
+    }
+}
+
+impl PulseCaptureProtocol for SimPulseCapture {
+    fn read_pulse(&mut self) -> Option<PulseMeasurement> {
+        self.timer.read()
+    }
+}
+
+impl InputBlock for SimPulseCapture {
+    type Output = (f64, f64);
+    type Parameters = PulseCaptureBlockParams;
+
+    fn input(
+        &mut self,
+        _parameters: &Self::Parameters,
+        _context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        self.read_pulse()
+            .map(|pulse| (pulse.period.as_secs_f64(), pulse.high_time.as_secs_f64()))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corelib_traits::ProcessBlock;
+    use corelib_traits_testing::StubRuntime;
+    use pictorus_core_blocks::{EdgeCounterBlock, QuadratureEncoderBlock};
+
+    #[test]
+    fn test_sim_edge_counter_measures_frequency_between_ticks() {
+        let parameters = EdgeCounterBlockParams::new("Rising");
+        let mut runtime = StubRuntime::default();
+        let mut counter = SimEdgeCounter::new();
+        let mut block = EdgeCounterBlock::default();
+
+        // 130 Hz sampled at 100 Hz would alias to 30 Hz
+        let mut edges = (1..)
+            .map(|i| Duration::from_secs_f64(i as f64 / 130.0))
+            .peekable();
+        let mut frequency = 0.0;
+        for tick in 1..=10 {
+            let now = Duration::from_millis(tick * 10);
+            while let Some(at) = edges.next_if(|at| *at <= now) {
+                counter.edge(at);
+            }
+            runtime.set_time(now);
+            let inputs = counter.input(&parameters, &runtime.context());
+            frequency = block.process(&parameters, &runtime.context(), inputs).2;
+        }
+        assert!((frequency - 130.0).abs() < 1.0, "{frequency}");
+    }
+
+    #[test]
+    fn test_sim_quadrature_position_and_index() {
+        let parameters = QuadratureEncoderBlockParams::new(100.0, false);
+        let mut runtime = StubRuntime::default();
+        let mut encoder = SimQuadrature::new();
+        let mut block = QuadratureEncoderBlock::default();
+
+        encoder.step(30, Duration::from_millis(5));
+        encoder.index();
+        encoder.step(-5, Duration::from_millis(8));
+        runtime.set_time(Duration::from_millis(10));
+        let inputs = encoder.input(&parameters, &runtime.context());
+        assert_eq!(inputs, (25.0, 0.008, true, 30.0));
+        let output = block.process(&parameters, &runtime.context(), inputs);
+        assert_eq!(output, (0.25, 0.0, true));
+
+        runtime.set_time(Duration::from_millis(20));
+        let inputs = encoder.input(&parameters, &runtime.context());
+        assert_eq!(inputs, (25.0, 0.008, false, 0.0));
+    }
+
+    #[test]
+    fn test_sim_pulse_capture() {
+        let runtime = StubRuntime::default();
+        let mut capture = SimPulseCapture::new();
+        let parameters = PulseCaptureBlockParams::new(100.0);
+
+        assert_eq!(capture.input(&parameters, &runtime.context()), (0.0, 0.0));
+        capture.pulse(
+            Duration::ZERO,
+            Duration::from_millis(20),
+            Duration::from_millis(15),
+        );
+        capture.pulse(
+            Duration::from_millis(20),
+            Duration::from_millis(25),
+            Duration::from_millis(5),
+        );
+        // Only the most recent pulse is reported
+        assert_eq!(
+            capture.input(&parameters, &runtime.context()),
+            (0.025, 0.005)
+        );
+        assert_eq!(capture.input(&parameters, &runtime.context()), (0.0, 0.0));
+    }
+}
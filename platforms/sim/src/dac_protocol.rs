@@ -0,0 +1,200 @@
+use alloc::vec::Vec;
+use core::time::Duration;
+
+use corelib_traits::{Context, InputBlock, Matrix, OutputBlock, PassBy};
+use pictorus_core_blocks::DacStreamBlockParams;
+use protocols::DacStreamProtocol;
+
+/// Simulated streaming DAC that records every sample with the app time it plays at, so tests
+/// can check the signal a model drives. Playback is modelled on the app time of each write.
+pub struct SimDacStream<const CHANNELS: usize, const SAMPLES: usize, const TABLE: usize> {
+    sample_rate_hz: f32,
+    now: Duration,
+    /// When the most recently written buffer starts and finishes playing
+    queued: Option<(Duration, Duration)>,
+    underruns: u32,
+    recording: Vec<(Duration, [u16; CHANNELS])>,
+}
+
+impl<const CHANNELS: usize, const SAMPLES: usize, const TABLE: usize>
+    SimDacStream<CHANNELS, SAMPLES, TABLE>
+{
+    pub fn new() -> Self {
+        Self {
+            sample_rate_hz: 1.0,
+            now: Duration::ZERO,
+            queued: None,
+            underruns: 0,
+            recording: Vec::new(),
+        }
+    }
+
+    /// Sets the app time of the next write
+    pub fn set_time(&mut self, now: Duration) {
+        self.now = now;
+    }
+
+    /// Every sample played so far, with the app time it plays at
+    pub fn recording(&self) -> &[(Duration, [u16; CHANNELS])] {
+        &self.recording
+    }
+
+    /// Clears the recording, keeping the playback state
+    pub fn clear_recording(&mut self) {
+        self.recording.clear();
+    }
+
+    fn sample_period(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.sample_rate_hz as f64)
+    }
+}
+
+impl<const CHANNELS: usize, const SAMPLES: usize, const TABLE: usize> Default
+    for SimDacStream<CHANNELS, SAMPLES, TABLE>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const CHANNELS: usize, const SAMPLES: usize, const TABLE: usize>
+    DacStreamProtocol<CHANNELS, SAMPLES> for SimDacStream<CHANNELS, SAMPLES, TABLE>
+{
+    fn set_sample_rate(&mut self, sample_rate_hz: f32) {
+        self.sample_rate_hz = sample_rate_hz;
+    }
+
+    fn write_buffer(&mut self, samples: &[[u16; SAMPLES]; CHANNELS], len: usize) -> bool {
+        let period = self.sample_period();
+        let start = match self.queued {
+            // One buffer is playing and the other hasn't started yet
+            Some((start, _)) if start > self.now => return false,
+            // Allow for the rounding of sample times, which can't be exactly represented
+            Some((_, end)) if end + period / 2 >= self.now => end.max(self.now),
+            Some(_) => {
+                self.underruns += 1;
+                self.now
+            }
+            None => self.now,
+        };
+
+        let len = len.min(SAMPLES);
+        self.recording.extend((0..len).map(|i| {
+            let sample = core::array::from_fn(|channel| samples[channel][i]);
+            (start + period * i as u32, sample)
+        }));
+        self.queued = Some((start, start + period * len as u32));
+        true
+    }
+
+    fn underruns(&mut self) -> u32 {
+        self.underruns
+    }
+}
+
+impl<const CHANNELS: usize, const SAMPLES: usize, const TABLE: usize> OutputBlock
+    for SimDacStream<CHANNELS, SAMPLES, TABLE>
+{
+    type Inputs = (Matrix<SAMPLES, CHANNELS, f64>, f64);
+    type Parameters = DacStreamBlockParams<CHANNELS, TABLE>;
+
+    fn output(
+        &mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) {
+        let (buffer, valid) = inputs;
+        self.set_time(context.time());
+        self.set_sample_rate(parameters.sample_rate_hz as f32);
+        let samples = buffer
+            .data
+            .map(|channel| channel.map(|sample| sample as u16));
+        if !self.write_buffer(&samples, valid as usize) {
+            log::warn!("DAC stream buffers full, dropping {valid} samples");
+        }
+    }
+}
+
+impl<const CHANNELS: usize, const SAMPLES: usize, const TABLE: usize> InputBlock
+    for SimDacStream<CHANNELS, SAMPLES, TABLE>
+{
+    type Output = f64;
+    type Parameters = DacStreamBlockParams<CHANNELS, TABLE>;
+
+    fn input(
+        &mut self,
+        _parameters: &Self::Parameters,
+        _context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        self.underruns() as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f64::consts::TAU;
+    use corelib_traits::ProcessBlock;
+    use corelib_traits_testing::StubRuntime;
+    use pictorus_core_blocks::DacStreamBlock;
+    use utils::BlockData as OldBlockData;
+
+    #[test]
+    fn test_sim_dac_stream_records_10khz_signal() {
+        // A 1 kHz sine at 10 kHz from a 100 Hz model
+        let parameters = DacStreamBlockParams::<1, 0>::new(
+            "Sine",
+            10_000.0,
+            TAU * 1000.0,
+            1.0,
+            0.0,
+            1.65,
+            &OldBlockData::from_scalar(0.0),
+            3.3,
+            12.0,
+        );
+        let mut runtime = StubRuntime::default();
+        runtime.context.fundamental_timestep = Duration::from_millis(10);
+        let mut block = DacStreamBlock::<1, 128, 0>::default();
+        let mut dac = SimDacStream::<1, 128, 0>::new();
+
+        for _ in 0..5 {
+            let output = block.process(&parameters, &runtime.context(), &Matrix::zeroed());
+            dac.output(&parameters, &runtime.context(), output);
+            runtime.tick();
+        }
+
+        let recording = dac.recording();
+        assert_eq!(recording.len(), 500);
+        for (i, (time, _)) in recording.iter().enumerate() {
+            assert_eq!(*time, Duration::from_micros(100 * i as u64));
+        }
+        assert_eq!(recording[0].1, [2048]);
+        assert_eq!(recording[10].1, [2048]);
+        assert_eq!(dac.input(&parameters, &runtime.context()), 0.0);
+
+        // Missing a tick starves the DAC
+        runtime.tick();
+        let output = block.process(&parameters, &runtime.context(), &Matrix::zeroed());
+        dac.output(&parameters, &runtime.context(), output);
+        assert_eq!(dac.input(&parameters, &runtime.context()), 1.0);
+        assert_eq!(dac.recording()[500].0, Duration::from_millis(60));
+    }
+
+    #[test]
+    fn test_sim_dac_stream_rejects_third_buffer() {
+        let mut dac = SimDacStream::<1, 4, 0>::new();
+        dac.set_sample_rate(1000.0);
+        let samples = [[1, 2, 3, 4]];
+        assert!(dac.write_buffer(&samples, 4));
+        assert!(dac.write_buffer(&samples, 4));
+        assert!(!dac.write_buffer(&samples, 4));
+
+        dac.set_time(Duration::from_millis(4));
+        assert!(dac.write_buffer(&samples, 2));
+        assert_eq!(dac.recording().len(), 10);
+        assert_eq!(dac.recording()[9].0, Duration::from_millis(9));
+        assert_eq!(dac.underruns(), 0);
+    }
+}
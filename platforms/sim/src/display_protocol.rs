@@ -1,194 +1,231 @@
-use crate::protocols::delay_protocol::{create_delay_protocol, DelayProtocolType};
-use crate::protocols::i2c_protocol::{create_i2c_protocol, I2cProtocolType};
-use crate::utils::PictorusError;
-
 use embedded_graphics::{
     mono_font::{ascii::FONT_10X20, MonoTextStyle, MonoTextStyleBuilder},
     pixelcolor::BinaryColor,
     prelude::*,
     text::{Baseline, Text},
 };
-use hd44780_driver::bus::I2CBus;
-use hd44780_driver::{Cursor, CursorBlink, Display as LcdDisplay, DisplayMode, HD44780};
 use log::debug;
-use ssd1306::{mode::BufferedGraphicsMode, prelude::*, I2CDisplayInterface, Ssd1306};
-
-#[cfg(test)]
-use mockall::automock;
+use protocols::DisplayProtocol;
+use utils::PictorusError;
 
-#[cfg_attr(test, automock)]
-pub trait DisplayProtocol {
-    fn render(&mut self, value: &str, x_offset: f64);
-}
+/// Size of the simulated OLED, matching the SSD1306 used on hardware
+pub const OLED_WIDTH: usize = 128;
+pub const OLED_HEIGHT: usize = 32;
+/// Size of the simulated character LCD, matching a 16x2 HD44780
+pub const LCD_COLUMNS: usize = 16;
+pub const LCD_ROWS: usize = 2;
 
 pub enum DisplayType {
     OLED,
     LCD,
 }
 
-const ERR_TYPE: &str = "UdpProtocol";
+const ERR_TYPE: &str = "DisplayProtocol";
+
+/// A monochrome framebuffer that `embedded-graphics` can draw into
+pub struct Framebuffer {
+    pixels: [[bool; OLED_WIDTH]; OLED_HEIGHT],
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Self {
+            pixels: [[false; OLED_WIDTH]; OLED_HEIGHT],
+        }
+    }
+}
+
+impl Framebuffer {
+    /// Whether the pixel is lit. Pixels outside the display are never lit.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.pixels
+            .get(y)
+            .and_then(|row| row.get(x))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    /// Number of lit pixels, handy for checking something was drawn
+    pub fn lit_pixels(&self) -> usize {
+        self.pixels.iter().flatten().filter(|p| **p).count()
+    }
+
+    fn fill(&mut self, on: bool) {
+        self.pixels = [[on; OLED_WIDTH]; OLED_HEIGHT];
+    }
+}
+
+impl OriginDimensions for Framebuffer {
+    fn size(&self) -> Size {
+        Size::new(OLED_WIDTH as u32, OLED_HEIGHT as u32)
+    }
+}
 
-fn create_error(message: String) -> PictorusError {
-    PictorusError::new(ERR_TYPE.into(), message)
+impl DrawTarget for Framebuffer {
+    type Color = BinaryColor;
+    type Error = core::convert::Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            let (Ok(x), Ok(y)) = (usize::try_from(point.x), usize::try_from(point.y)) else {
+                continue;
+            };
+            if let Some(pixel) = self.pixels.get_mut(y).and_then(|row| row.get_mut(x)) {
+                *pixel = color.is_on();
+            }
+        }
+        Ok(())
+    }
 }
 
+/// Simulated display. OLED text is rendered into a [`Framebuffer`] the same way it is drawn on
+/// the SSD1306, and LCD text is written into a grid of characters like the HD44780.
 pub struct DisplayConnection {
-    ssd1306_display: Option<Ssd1306Display>,
-    hd44780_display: Option<Hd44780Display>,
+    display_type: DisplayType,
+    framebuffer: Framebuffer,
+    characters: [[char; LCD_COLUMNS]; LCD_ROWS],
+    text_style: MonoTextStyle<'static, BinaryColor>,
     last_display_value: String,
     last_rendered_offset: i64,
+    render_count: usize,
 }
 
 impl DisplayConnection {
     pub fn new(address: f64, display_type: &str) -> Result<Self, PictorusError> {
-        let address = address as u16;
         let display_type = match display_type {
             "OLED" => DisplayType::OLED,
             "LCD" => DisplayType::LCD,
             _ => {
-                return Err(create_error(format!(
-                    "Unknown DisplayBlock type '{}'!",
-                    display_type
-                )))
+                return Err(PictorusError::new(
+                    ERR_TYPE.into(),
+                    format!("Unknown DisplayBlock type '{}'!", display_type),
+                ))
             }
         };
-
-        let mut ssd1306_display = None;
-        let mut hd44780_display = None;
-        match display_type {
-            DisplayType::OLED => ssd1306_display = Some(Ssd1306Display::new(address)?),
-            DisplayType::LCD => hd44780_display = Some(Hd44780Display::new(address)?),
-        }
+        debug!("Creating simulated display for address {}", address as u16);
 
         Ok(DisplayConnection {
-            ssd1306_display,
-            hd44780_display,
+            display_type,
+            framebuffer: Framebuffer::default(),
+            characters: [[' '; LCD_COLUMNS]; LCD_ROWS],
+            text_style: MonoTextStyleBuilder::new()
+                .font(&FONT_10X20)
+                .text_color(BinaryColor::On)
+                .build(),
             last_display_value: String::new(),
             last_rendered_offset: 0,
+            render_count: 0,
         })
     }
+
+    /// The OLED framebuffer, which stays blank for an LCD
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    /// The LCD rows, which stay blank for an OLED
+    pub fn lcd_rows(&self) -> [String; LCD_ROWS] {
+        self.characters.map(|row| row.iter().collect())
+    }
+
+    /// The text currently shown
+    pub fn text(&self) -> &str {
+        &self.last_display_value
+    }
+
+    /// Number of times the display was redrawn
+    pub fn render_count(&self) -> usize {
+        self.render_count
+    }
+
+    fn render_oled(&mut self, value: &str, x_offset: i64) {
+        self.framebuffer.fill(false);
+        let start_point = Point::new(x_offset as i32, OLED_HEIGHT as i32 / 2);
+        Text::with_baseline(value, start_point, self.text_style, Baseline::Middle)
+            .draw(&mut self.framebuffer)
+            .ok();
+    }
+
+    fn render_lcd(&mut self, value: &str, x_offset: i64) {
+        self.characters = [[' '; LCD_COLUMNS]; LCD_ROWS];
+        // Like the HD44780, text past the end of the first row is not shown
+        let row = &mut self.characters[0];
+        let start = x_offset.clamp(0, LCD_COLUMNS as i64) as usize;
+        for (dest, c) in row[start..].iter_mut().zip(value.chars()) {
+            *dest = c;
+        }
+    }
 }
 
 impl DisplayProtocol for DisplayConnection {
     fn render(&mut self, value: &str, x_offset: f64) {
         let x_offset = x_offset as i64;
-        // Re-rendering is expensive, don't do it if string is identical
+        // Match the hardware implementation, which skips re-rendering identical text
         if value == self.last_display_value && x_offset == self.last_rendered_offset {
             return;
         }
 
-        if let Some(display) = &mut self.ssd1306_display {
-            display.render(value, x_offset);
-        }
-
-        if let Some(display) = &mut self.hd44780_display {
-            display.render(value, x_offset);
+        match self.display_type {
+            DisplayType::OLED => self.render_oled(value, x_offset),
+            DisplayType::LCD => self.render_lcd(value, x_offset),
         }
-
+        self.render_count += 1;
         self.last_rendered_offset = x_offset;
         self.last_display_value = value.to_string();
     }
 }
 
-struct Ssd1306Display {
-    display: Ssd1306<
-        I2CInterface<I2cProtocolType>,
-        DisplaySize128x32,
-        BufferedGraphicsMode<DisplaySize128x32>,
-    >,
-    text_style: MonoTextStyle<'static, BinaryColor>,
+pub fn create_display_protocol(
+    address: f64,
+    display_type: &str,
+) -> Result<DisplayConnection, PictorusError> {
+    DisplayConnection::new(address, display_type)
 }
 
-#[cfg(feature = "std")]
-impl Ssd1306Display {
-    pub fn new(address: u16) -> Result<Self, PictorusError> {
-        debug!("Creating Ssd1306Display for address {}", address);
-        let i2c = create_i2c_protocol()?;
-        let interface = I2CDisplayInterface::new_custom_address(i2c, address as u8);
-
-        let mut display = Ssd1306::new(interface, DisplaySize128x32, DisplayRotation::Rotate0)
-            .into_buffered_graphics_mode();
-        display.init().map_err(|_| {
-            create_error(format!(
-                "Failed to initialize display at address: {}",
-                address
-            ))
-        })?;
-        display.set_brightness(Brightness::BRIGHTEST).ok();
-        let text_style = MonoTextStyleBuilder::new()
-            .font(&FONT_10X20)
-            .text_color(BinaryColor::On)
-            .build();
-
-        Ok(Ssd1306Display {
-            display,
-            text_style,
-        })
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oled_renders_to_framebuffer() {
+        let mut display = create_display_protocol(60.0, "OLED").unwrap();
+        assert_eq!(display.framebuffer().lit_pixels(), 0);
+
+        display.render("88", 0.0);
+        let lit = display.framebuffer().lit_pixels();
+        assert!(lit > 0);
+        assert_eq!(display.text(), "88");
+        // Only the first two 10 pixel wide characters are drawn
+        assert!(
+            (20..OLED_WIDTH).all(|x| (0..OLED_HEIGHT).all(|y| !display.framebuffer().pixel(x, y)))
+        );
+
+        // Shifting the text moves the pixels without changing how many are lit
+        display.render("88", 50.0);
+        assert_eq!(display.framebuffer().lit_pixels(), lit);
+        assert!(!(0..50).any(|x| (0..OLED_HEIGHT).any(|y| display.framebuffer().pixel(x, y))));
+
+        // Identical text isn't redrawn
+        display.render("88", 50.0);
+        assert_eq!(display.render_count(), 2);
     }
 
-    fn render(&mut self, value: &str, x_offset: i64) {
-        debug!("Rendering value: {}", value);
-        self.display.clear();
-        let start_point = Point::new(x_offset as i32, 16);
-        Text::with_baseline(value, start_point, self.text_style, Baseline::Middle)
-            .draw(&mut self.display)
-            .unwrap();
+    #[test]
+    fn test_lcd_renders_characters() {
+        let mut display = create_display_protocol(39.0, "LCD").unwrap();
+        display.render("12.50", 2.0);
+        assert_eq!(display.lcd_rows(), ["  12.50         ", "                "]);
+        assert_eq!(display.framebuffer().lit_pixels(), 0);
 
-        self.display.flush().unwrap();
+        display.render("this text is too long", 0.0);
+        assert_eq!(display.lcd_rows()[0], "this text is too");
     }
-}
-
-struct Hd44780Display {
-    display: HD44780<I2CBus<I2cProtocolType>>,
-    delay: DelayProtocolType,
-}
-
-impl Hd44780Display {
-    pub fn new(address: u16) -> Result<Self, PictorusError> {
-        debug!("Creating Hd44780Display for address {}", address);
-        let i2c = create_i2c_protocol()?;
-        let mut delay = create_delay_protocol();
-
-        let mut display = HD44780::new_i2c(i2c, address as u8, &mut delay)
-            .map_err(|_| create_error(format!("Failed to bind display to address: {}", address)))?;
-        display.reset(&mut delay).map_err(|_| {
-            create_error(format!("Failed to reset display at address: {}", address))
-        })?;
-        display.clear(&mut delay).map_err(|_| {
-            create_error(format!("Failed to clear display at address: {}", address))
-        })?;
-        display
-            .set_display_mode(
-                DisplayMode {
-                    display: LcdDisplay::On,
-                    cursor_visibility: Cursor::Invisible,
-                    cursor_blink: CursorBlink::Off,
-                },
-                &mut delay,
-            )
-            .map_err(|_| {
-                create_error(format!(
-                    "Failed to set display mode at address: {}",
-                    address
-                ))
-            })?;
 
-        Ok(Hd44780Display { display, delay })
-    }
-    fn render(&mut self, value: &str, x_offset: i64) {
-        debug!("Rendering value: {}", value);
-        self.display.clear(&mut self.delay).unwrap();
-        self.display
-            .set_cursor_pos(x_offset as u8, &mut self.delay)
-            .unwrap();
-        self.display.write_str(value, &mut self.delay).unwrap();
+    #[test]
+    fn test_unknown_display_type() {
+        assert!(create_display_protocol(60.0, "CRT").is_err());
     }
 }
-
-pub fn create_display_protocol(
-    address: f64,
-    display_type: &str,
-) -> Result<DisplayConnection, PictorusError> {
-    let connection = DisplayConnection::new(address, display_type)?;
-    Ok(connection)
-}
@@ -1,8 +1,11 @@
+use std::collections::VecDeque;
 use std::convert::Infallible;
+use std::time::Duration;
 
 use corelib_traits::{Context, InputBlock, OutputBlock, PassBy};
 use embedded_hal::digital::{ErrorType, InputPin, OutputPin};
-use pictorus_core_blocks::{GpioInputBlockParams, GpioOutputBlockParams};
+use pictorus_core_blocks::{GpioEdgeEventBlockParams, GpioInputBlockParams, GpioOutputBlockParams};
+use protocols::{GpioEdgeEvent, GpioEventProtocol};
 
 pub struct SimGpioPin {}
 
@@ -66,3 +69,100 @@ impl OutputBlock for SimGpioPin {
         }
     }
 }
+
+/// Simulated GPIO line that streams edge events. Tests and simulations drive it by setting
+/// its level at app times, and each change queues an edge event.
+#[derive(Default)]
+pub struct SimGpioEventLine {
+    events: VecDeque<GpioEdgeEvent>,
+    level: bool,
+    now: Duration,
+}
+
+impl SimGpioEventLine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_level(&mut self, level: bool, at: Duration) {
+        if level != self.level {
+            self.level = level;
+            self.events.push_back(GpioEdgeEvent {
+                rising: level,
+                timestamp: at,
+            });
+        }
+    }
+}
+
+impl GpioEventProtocol for SimGpioEventLine {
+    fn next_event(&mut self) -> Option<GpioEdgeEvent> {
+        self.events.pop_front()
+    }
+
+    fn level(&mut self) -> bool {
+        self.level
+    }
+
+    fn now(&mut self) -> Duration {
+        self.now
+    }
+}
+
+impl InputBlock for SimGpioEventLine {
+    type Output = (bool, f64, f64, f64, f64);
+    type Parameters = GpioEdgeEventBlockParams;
+
+    fn input(
+        &mut self,
+        _parameters: &Self::Parameters,
+        context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        // Event timestamps are already app times
+        self.now = context.time();
+        let mut edges = (self.level(), 0.0, 0.0, 0.0, 0.0);
+        while let Some(event) = self.next_event() {
+            let time = event.timestamp.as_secs_f64();
+            if event.rising {
+                edges.1 += 1.0;
+                edges.3 = time;
+            } else {
+                edges.2 += 1.0;
+                edges.4 = time;
+            }
+        }
+        edges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corelib_traits::ProcessBlock;
+    use corelib_traits_testing::StubRuntime;
+    use pictorus_core_blocks::GpioEdgeEventBlock;
+
+    #[test]
+    fn test_sim_gpio_event_line_reports_edges_between_ticks() {
+        let parameters = GpioEdgeEventBlockParams::new("Falling");
+        let mut runtime = StubRuntime::default();
+        let mut line = SimGpioEventLine::new();
+        let mut block = GpioEdgeEventBlock::default();
+
+        // A short pulse within one tick is invisible to a level read
+        line.set_level(true, Duration::from_millis(2));
+        line.set_level(false, Duration::from_millis(4));
+        line.set_level(false, Duration::from_millis(6));
+        runtime.set_time(Duration::from_millis(10));
+        let inputs = line.input(&parameters, &runtime.context());
+        assert_eq!(inputs, (false, 1.0, 1.0, 0.002, 0.004));
+        let output = block.process(&parameters, &runtime.context(), inputs);
+        assert_eq!(output, (false, 1.0, 0.004));
+
+        runtime.set_time(Duration::from_millis(20));
+        let inputs = line.input(&parameters, &runtime.context());
+        assert_eq!(inputs, (false, 0.0, 0.0, 0.0, 0.0));
+        let output = block.process(&parameters, &runtime.context(), inputs);
+        assert_eq!(output, (false, 0.0, 0.004));
+    }
+}
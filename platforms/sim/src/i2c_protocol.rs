@@ -1,30 +1,201 @@
 use core::convert::Infallible;
+use core::marker::PhantomData;
+use std::collections::HashMap;
 
 use corelib_traits::{ByteSliceSignal, InputBlock, OutputBlock};
-use pictorus_core_blocks::{I2cInputBlockParams, I2cOutputBlockParams};
+use embedded_hal::i2c::{
+    ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation, SevenBitAddress, TenBitAddress,
+};
+use log::debug;
+use pictorus_core_blocks::{I2cInputBlockParams, I2cOutputBlockParams, I2cTransactionBlockParams};
+use utils::i2c_script::I2C_OK;
+use utils::{I2cAddress, I2cScript};
 
-pub struct SimI2cProtocol {
+use crate::{create_delay_protocol, SimDelayProtocol};
+
+/// A simulated I2C device made up of byte registers. Each transaction starts with a write of
+/// the register address, after which reads and writes auto-increment the register address.
+struct SimI2cDevice {
+    register_address_bytes: usize,
+    connected: bool,
+    register_address: u16,
+    registers: HashMap<u16, u8>,
+}
+
+impl SimI2cDevice {
+    fn new(register_address_bytes: usize) -> Self {
+        Self {
+            register_address_bytes,
+            connected: true,
+            register_address: 0,
+            registers: HashMap::new(),
+        }
+    }
+
+    fn transaction(&mut self, operations: &mut [Operation<'_>]) -> Result<(), ErrorKind> {
+        if !self.connected {
+            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        }
+
+        let mut address_bytes = 0;
+        for op in operations {
+            match op {
+                Operation::Write(bytes) => {
+                    for byte in bytes.iter() {
+                        if address_bytes < self.register_address_bytes {
+                            if address_bytes == 0 {
+                                self.register_address = 0;
+                            }
+                            self.register_address = (self.register_address << 8) | *byte as u16;
+                            address_bytes += 1;
+                        } else {
+                            self.registers.insert(self.register_address, *byte);
+                            self.register_address = self.register_address.wrapping_add(1);
+                        }
+                    }
+                }
+                Operation::Read(bytes) => {
+                    for byte in bytes.iter_mut() {
+                        *byte = self
+                            .registers
+                            .get(&self.register_address)
+                            .copied()
+                            .unwrap_or(0);
+                        self.register_address = self.register_address.wrapping_add(1);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// In-memory stand-in for the devices on an I2C bus. Devices with 8-bit register addresses
+/// are created the first time an unknown address is used, so every register reads as zero
+/// until it is written. Tests can add devices with 16-bit register addresses, set and inspect
+/// registers, and disconnect devices to simulate a missing acknowledge.
+#[derive(Default)]
+pub struct SimI2cBus {
+    devices: HashMap<I2cAddress, SimI2cDevice>,
+}
+
+impl SimI2cBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a device whose register addresses are 1 or 2 bytes, replacing any existing device
+    pub fn add_device(&mut self, address: I2cAddress, register_address_bytes: usize) {
+        self.devices
+            .insert(address, SimI2cDevice::new(register_address_bytes));
+    }
+
+    /// Stops the device acknowledging its address
+    pub fn disconnect(&mut self, address: I2cAddress) {
+        self.device(address).connected = false;
+    }
+
+    pub fn register(&self, address: I2cAddress, register: u16) -> u8 {
+        self.devices
+            .get(&address)
+            .and_then(|device| device.registers.get(&register))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn set_register(&mut self, address: I2cAddress, register: u16, value: u8) {
+        self.device(address).registers.insert(register, value);
+    }
+
+    fn device(&mut self, address: I2cAddress) -> &mut SimI2cDevice {
+        self.devices
+            .entry(address)
+            .or_insert_with(|| SimI2cDevice::new(1))
+    }
+}
+
+impl ErrorType for SimI2cBus {
+    type Error = ErrorKind;
+}
+
+impl I2c<SevenBitAddress> for SimI2cBus {
+    fn transaction(
+        &mut self,
+        address: SevenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.device(I2cAddress::SevenBit(address))
+            .transaction(operations)
+    }
+}
+
+impl I2c<TenBitAddress> for SimI2cBus {
+    fn transaction(
+        &mut self,
+        address: TenBitAddress,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.device(I2cAddress::TenBit(address))
+            .transaction(operations)
+    }
+}
+
+/// Runs the I2C block scripts against the simulated bus. `P` is the parameters of the input
+/// block it reads for: `I2cInputBlockParams` for an I2C Input Block, or
+/// `I2cTransactionBlockParams` for an I2C Transaction Block, which also takes the result codes.
+pub struct SimI2cProtocol<P = I2cInputBlockParams> {
+    pub bus: SimI2cBus,
+    delay: SimDelayProtocol,
     buffer: Vec<u8>,
+    results: Vec<u8>,
+    _input: PhantomData<fn() -> P>,
 }
 pub type I2cProtocolType = SimI2cProtocol;
 
-impl SimI2cProtocol {
+impl<P> SimI2cProtocol<P> {
     pub fn new() -> Self {
-        SimI2cProtocol { buffer: Vec::new() }
+        SimI2cProtocol {
+            bus: SimI2cBus::new(),
+            delay: create_delay_protocol(),
+            buffer: Vec::new(),
+            results: Vec::new(),
+            _input: PhantomData,
+        }
+    }
+
+    fn run(&mut self, address: I2cAddress, script: &I2cScript, input: &[u8]) {
+        match address {
+            I2cAddress::SevenBit(address) => script.run(
+                &mut self.bus,
+                address,
+                &mut self.delay,
+                input,
+                &mut self.buffer,
+                &mut self.results,
+            ),
+            I2cAddress::TenBit(address) => script.run(
+                &mut self.bus,
+                address,
+                &mut self.delay,
+                input,
+                &mut self.buffer,
+                &mut self.results,
+            ),
+        }
     }
 }
 
-impl Default for SimI2cProtocol {
+impl<P> Default for SimI2cProtocol<P> {
     fn default() -> Self {
         Self::new()
     }
 }
 
 pub fn create_i2c_protocol() -> Result<SimI2cProtocol, Infallible> {
-    Ok(SimI2cProtocol { buffer: Vec::new() })
+    Ok(SimI2cProtocol::new())
 }
 
-impl InputBlock for SimI2cProtocol {
+impl InputBlock for SimI2cProtocol<I2cInputBlockParams> {
     type Output = ByteSliceSignal;
     type Parameters = I2cInputBlockParams;
 
@@ -33,22 +204,99 @@ impl InputBlock for SimI2cProtocol {
         parameters: &Self::Parameters,
         _context: &dyn corelib_traits::Context,
     ) -> corelib_traits::PassBy<'_, Self::Output> {
-        self.buffer.resize(parameters.read_bytes, 0);
+        let address = I2cAddress::SevenBit(parameters.address);
+        self.run(address, parameters.script(), &[]);
+        if self.results.iter().any(|r| *r != I2C_OK) {
+            self.buffer.clear();
+        }
+
         &self.buffer
     }
 }
 
-impl OutputBlock for SimI2cProtocol {
+impl InputBlock for SimI2cProtocol<I2cTransactionBlockParams> {
+    type Output = (ByteSliceSignal, ByteSliceSignal);
+    type Parameters = I2cTransactionBlockParams;
+
+    fn input(
+        &mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn corelib_traits::Context,
+    ) -> corelib_traits::PassBy<'_, Self::Output> {
+        self.run(parameters.address, &parameters.script, &[]);
+        if self.results.iter().any(|r| *r != I2C_OK) {
+            self.buffer.clear();
+        }
+
+        (&self.buffer, &self.results)
+    }
+}
+
+impl<P> OutputBlock for SimI2cProtocol<P> {
     type Inputs = ByteSliceSignal;
     type Parameters = I2cOutputBlockParams;
 
     fn output(
         &mut self,
-        _parameters: &Self::Parameters,
+        parameters: &Self::Parameters,
         _context: &dyn corelib_traits::Context,
         inputs: corelib_traits::PassBy<'_, Self::Inputs>,
     ) {
-        self.buffer.clear();
-        self.buffer.extend_from_slice(inputs);
+        self.run(parameters.address, &parameters.script, inputs);
+        if self.results.iter().any(|r| *r != I2C_OK) {
+            debug!("I2C write failed with results {:?}", self.results);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corelib_traits_testing::StubContext;
+    use utils::i2c_script::I2C_ERROR_NO_ACKNOWLEDGE_ADDRESS;
+
+    #[test]
+    fn test_sim_i2c_script() {
+        let context = StubContext::default();
+        let mut protocol = SimI2cProtocol::<I2cTransactionBlockParams>::new();
+        let sensor = I2cAddress::TenBit(0x2A0);
+        protocol.bus.add_device(sensor, 2);
+
+        // Configure, then burst read back across a 16-bit register boundary
+        let output = I2cOutputBlockParams::from_script(0x2A0 as f64, false, "W16 0x01FF IN");
+        protocol.output(&output, &context, &[1, 2, 3]);
+        assert_eq!(protocol.bus.register(sensor, 0x0200), 2);
+
+        let input =
+            I2cTransactionBlockParams::new(0x2A0 as f64, false, "W16 0x01FE R 4; D 1", 100.0);
+        assert_eq!(
+            protocol.input(&input, &context),
+            (&[0, 1, 2, 3][..], &[I2C_OK][..])
+        );
+
+        protocol.bus.disconnect(sensor);
+        assert_eq!(
+            protocol.input(&input, &context),
+            (&[][..], &[I2C_ERROR_NO_ACKNOWLEDGE_ADDRESS][..])
+        );
+    }
+
+    #[test]
+    fn test_sim_i2c_legacy_parameters() {
+        let context = StubContext::default();
+        let mut protocol = create_i2c_protocol().unwrap();
+        protocol
+            .bus
+            .set_register(I2cAddress::SevenBit(0x68), 0x3B, 0x12);
+
+        let input = I2cInputBlockParams::new(104.0, 59.0, 2.0, 100.0);
+        assert_eq!(protocol.input(&input, &context), &[0x12, 0]);
+
+        let output = I2cOutputBlockParams::new(104.0, 107.0);
+        protocol.output(&output, &context, &[0x80]);
+        assert_eq!(protocol.bus.register(I2cAddress::SevenBit(0x68), 107), 0x80);
+
+        protocol.bus.disconnect(I2cAddress::SevenBit(0x68));
+        assert!(protocol.input(&input, &context).is_empty());
     }
 }
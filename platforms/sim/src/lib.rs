@@ -3,18 +3,33 @@ extern crate alloc;
 mod adc_protocol;
 pub use adc_protocol::*;
 
+mod camera_protocol;
+pub use camera_protocol::*;
+
+mod capture_protocol;
+pub use capture_protocol::*;
+
 mod clock_protocol;
 pub use clock_protocol::*;
 
+mod dac_protocol;
+pub use dac_protocol::*;
+
 mod delay_protocol;
 pub use delay_protocol::*;
 
+mod display_protocol;
+pub use display_protocol::*;
+
 mod gpio_protocol;
 pub use gpio_protocol::*;
 
 mod i2c_protocol;
 pub use i2c_protocol::*;
 
+mod modbus_protocol;
+pub use modbus_protocol::*;
+
 mod pwm_protocol;
 pub use pwm_protocol::*;
 
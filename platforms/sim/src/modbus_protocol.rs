@@ -0,0 +1,255 @@
+use core::convert::Infallible;
+use core::time::Duration;
+use std::collections::HashMap;
+
+pub use protocols::ModbusWrapper;
+use protocols::modbus::{
+    MAX_READ_BITS, MAX_READ_REGISTERS, READ_COILS, READ_DISCRETE_INPUTS, READ_HOLDING_REGISTERS,
+    READ_INPUT_REGISTERS, WRITE_MULTIPLE_COILS, WRITE_MULTIPLE_REGISTERS, WRITE_SINGLE_COIL,
+    WRITE_SINGLE_REGISTER,
+};
+use protocols::{ModbusError, ModbusProtocol};
+
+const ILLEGAL_FUNCTION: u8 = 0x01;
+const ILLEGAL_DATA_VALUE: u8 = 0x03;
+
+/// In-memory stand-in for the Modbus devices on a bus. Every unit has all four tables and
+/// values that have not been set read as zero. Tests can set inputs and inspect what was
+/// written through the accessors.
+#[derive(Default)]
+pub struct SimModbus {
+    coils: HashMap<(u8, u16), bool>,
+    discrete_inputs: HashMap<(u8, u16), bool>,
+    holding_registers: HashMap<(u8, u16), u16>,
+    input_registers: HashMap<(u8, u16), u16>,
+}
+
+impl SimModbus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn coil(&self, unit_id: u8, address: u16) -> bool {
+        self.coils
+            .get(&(unit_id, address))
+            .copied()
+            .unwrap_or(false)
+    }
+
+    pub fn set_coil(&mut self, unit_id: u8, address: u16, value: bool) {
+        self.coils.insert((unit_id, address), value);
+    }
+
+    pub fn set_discrete_input(&mut self, unit_id: u8, address: u16, value: bool) {
+        self.discrete_inputs.insert((unit_id, address), value);
+    }
+
+    pub fn holding_register(&self, unit_id: u8, address: u16) -> u16 {
+        self.holding_registers
+            .get(&(unit_id, address))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    pub fn set_holding_register(&mut self, unit_id: u8, address: u16, value: u16) {
+        self.holding_registers.insert((unit_id, address), value);
+    }
+
+    pub fn set_input_register(&mut self, unit_id: u8, address: u16, value: u16) {
+        self.input_registers.insert((unit_id, address), value);
+    }
+
+    /// Handles a request PDU, returning the response PDU length or an exception code
+    fn handle(&mut self, unit_id: u8, request: &[u8], response: &mut [u8]) -> Result<usize, u8> {
+        let (function, address, value) = match request {
+            [function, a0, a1, v0, v1, ..] => (
+                *function,
+                u16::from_be_bytes([*a0, *a1]),
+                u16::from_be_bytes([*v0, *v1]),
+            ),
+            _ => return Err(ILLEGAL_DATA_VALUE),
+        };
+        let addresses = (0..value as usize).map(|i| address.wrapping_add(i as u16));
+        response[0] = function;
+        match function {
+            READ_COILS | READ_DISCRETE_INPUTS => {
+                if value == 0 || value as usize > MAX_READ_BITS {
+                    return Err(ILLEGAL_DATA_VALUE);
+                }
+                let table = if function == READ_COILS {
+                    &self.coils
+                } else {
+                    &self.discrete_inputs
+                };
+                let byte_count = (value as usize).div_ceil(8);
+                response[1] = byte_count as u8;
+                response[2..2 + byte_count].fill(0);
+                for (i, address) in addresses.enumerate() {
+                    if table.get(&(unit_id, address)).copied().unwrap_or(false) {
+                        response[2 + i / 8] |= 1 << (i % 8);
+                    }
+                }
+                Ok(2 + byte_count)
+            }
+            READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS => {
+                if value == 0 || value as usize > MAX_READ_REGISTERS {
+                    return Err(ILLEGAL_DATA_VALUE);
+                }
+                let table = if function == READ_HOLDING_REGISTERS {
+                    &self.holding_registers
+                } else {
+                    &self.input_registers
+                };
+                response[1] = (value * 2) as u8;
+                for (i, address) in addresses.enumerate() {
+                    let register = table.get(&(unit_id, address)).copied().unwrap_or(0);
+                    response[2 + i * 2..4 + i * 2].copy_from_slice(&register.to_be_bytes());
+                }
+                Ok(2 + value as usize * 2)
+            }
+            WRITE_SINGLE_COIL => {
+                self.set_coil(unit_id, address, value == 0xFF00);
+                response[1..5].copy_from_slice(&request[1..5]);
+                Ok(5)
+            }
+            WRITE_SINGLE_REGISTER => {
+                self.set_holding_register(unit_id, address, value);
+                response[1..5].copy_from_slice(&request[1..5]);
+                Ok(5)
+            }
+            WRITE_MULTIPLE_COILS | WRITE_MULTIPLE_REGISTERS => {
+                let data = request.get(6..).ok_or(ILLEGAL_DATA_VALUE)?;
+                for (i, address) in addresses.enumerate() {
+                    if function == WRITE_MULTIPLE_COILS {
+                        let byte = data.get(i / 8).ok_or(ILLEGAL_DATA_VALUE)?;
+                        self.set_coil(unit_id, address, byte & (1 << (i % 8)) != 0);
+                    } else {
+                        let bytes = data.get(i * 2..i * 2 + 2).ok_or(ILLEGAL_DATA_VALUE)?;
+                        let register = u16::from_be_bytes([bytes[0], bytes[1]]);
+                        self.set_holding_register(unit_id, address, register);
+                    }
+                }
+                response[1..5].copy_from_slice(&request[1..5]);
+                Ok(5)
+            }
+            _ => Err(ILLEGAL_FUNCTION),
+        }
+    }
+}
+
+impl ModbusProtocol for SimModbus {
+    type Error = Infallible;
+
+    fn set_timeout(&mut self, _timeout: Duration) {}
+
+    fn transaction(
+        &mut self,
+        unit_id: u8,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, ModbusError<Self::Error>> {
+        match self.handle(unit_id, request, response) {
+            Ok(len) => Ok(len),
+            Err(exception) => {
+                response[0] = request.first().copied().unwrap_or(0) | 0x80;
+                response[1] = exception;
+                Ok(2)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use corelib_traits::InputBlock;
+    use corelib_traits_testing::StubRuntime;
+    use pictorus_core_blocks::ModbusReadBlockParams;
+
+    #[test]
+    fn test_sim_modbus_registers() {
+        let mut modbus = SimModbus::new();
+        modbus.set_input_register(3, 10, 0x1234);
+        modbus.set_discrete_input(3, 1, true);
+
+        let mut registers = [0u16; 2];
+        modbus.read_input_registers(3, 10, &mut registers).unwrap();
+        assert_eq!(registers, [0x1234, 0]);
+        let mut inputs = [false; 3];
+        modbus.read_discrete_inputs(3, 0, &mut inputs).unwrap();
+        assert_eq!(inputs, [false, true, false]);
+
+        modbus.write_registers(3, 20, &[1, 2]).unwrap();
+        assert_eq!(modbus.holding_register(3, 21), 2);
+        modbus.write_coils(3, 5, &[true]).unwrap();
+        assert!(modbus.coil(3, 5));
+        assert!(!modbus.coil(4, 5));
+
+        let mut registers = [0u16; 126];
+        assert_eq!(
+            modbus.read_holding_registers(3, 0, &mut registers),
+            Err(ModbusError::InvalidRequest)
+        );
+    }
+
+    #[test]
+    fn test_sim_modbus_wrapper_polls() {
+        let mut runtime = StubRuntime::default();
+        let mut wrapper = ModbusWrapper::<SimModbus>::default();
+        wrapper.protocol.set_holding_register(1, 0, 7);
+        let parameters = ModbusReadBlockParams::new(
+            1.0,
+            "HoldingRegisters",
+            0.0,
+            1.0,
+            "U16",
+            false,
+            250.0,
+            100.0,
+            1000.0,
+        );
+
+        assert_eq!(wrapper.input(&parameters, &runtime.context()), &[0, 7]);
+        runtime.tick();
+        assert!(wrapper.input(&parameters, &runtime.context()).is_empty());
+        runtime.set_time(Duration::from_millis(250));
+        assert_eq!(wrapper.input(&parameters, &runtime.context()), &[0, 7]);
+    }
+
+    #[test]
+    fn test_sim_modbus_wrapper_shares_reads() {
+        let mut runtime = StubRuntime::default();
+        let mut wrapper = ModbusWrapper::<SimModbus>::default();
+        wrapper.protocol.set_holding_register(1, 0, 7);
+        wrapper.protocol.set_holding_register(1, 1, 8);
+        let read = |count, poll_period_ms| {
+            ModbusReadBlockParams::new(
+                1.0,
+                "HoldingRegisters",
+                0.0,
+                count,
+                "U16",
+                false,
+                poll_period_ms,
+                100.0,
+                1000.0,
+            )
+        };
+        let first = read(1.0, 250.0);
+        let same = read(1.0, 250.0);
+        let longer = read(2.0, 250.0);
+        let slower = read(1.0, 1000.0);
+
+        // Blocks making the same read in one tick share the response
+        assert_eq!(wrapper.input(&first, &runtime.context()), &[0, 7]);
+        assert_eq!(wrapper.input(&same, &runtime.context()), &[0, 7]);
+        // Reads of a different quantity or schedule are polled separately
+        assert_eq!(wrapper.input(&longer, &runtime.context()), &[0, 7, 0, 8]);
+        assert_eq!(wrapper.input(&slower, &runtime.context()), &[0, 7]);
+
+        runtime.set_time(Duration::from_millis(250));
+        assert_eq!(wrapper.input(&slower, &runtime.context()), &[] as &[u8]);
+        assert_eq!(wrapper.input(&first, &runtime.context()), &[0, 7]);
+        assert_eq!(wrapper.input(&same, &runtime.context()), &[0, 7]);
+    }
+}
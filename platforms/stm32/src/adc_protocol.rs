@@ -1,7 +1,8 @@
-use corelib_traits::{Context, InputBlock, PassBy};
+use corelib_traits::{Context, InputBlock, Matrix, PassBy};
 use embassy_stm32::adc::{Adc, AnyAdcChannel};
-use pictorus_core_blocks::AdcBlockParams;
-use protocols::AdcProtocol;
+use embassy_stm32::pac::adc::vals::{Rovsm, Trovs};
+use pictorus_core_blocks::{AdcBlockParams, MultiChannelAdcBlockParams};
+use protocols::{AdcProtocol, MultiChannelAdcProtocol};
 
 pub struct AdcWrapper<'a, T: embassy_stm32::adc::Instance> {
     adc: Adc<'a, T>,
@@ -58,3 +59,130 @@ where
         self.buffer = None;
     }
 }
+
+/// Full-scale count at the ADC's default 12-bit resolution
+const FULL_SCALE: f32 = 4095.0;
+
+/// Address of the factory reading of the internal reference, VREFINT_CAL, on STM32G4
+const VREFINT_CAL_ADDRESS: usize = 0x1FFF_75AA;
+
+/// Reference voltage VREFINT_CAL was measured at
+const VREFINT_CAL_VOLTAGE: f32 = 3.0;
+
+/// Converts a set of channels on one ADC each tick, with optional oversampling and an
+/// internal reference channel for measuring the reference voltage
+pub struct MultiChannelAdcWrapper<'a, T: embassy_stm32::adc::Instance, const N: usize> {
+    adc: Adc<'a, T>,
+    channels: [AnyAdcChannel<T>; N],
+    vrefint: Option<AnyAdcChannel<T>>,
+    /// Ratio the hardware oversampler is configured with, 1 when it is off
+    hardware_ratio: u16,
+    readings: Matrix<1, N, f64>,
+}
+
+impl<'a, T, const N: usize> MultiChannelAdcWrapper<'a, T, N>
+where
+    T: embassy_stm32::adc::Instance,
+{
+    /// Creates the wrapper. `vrefint` is the internal reference channel, from
+    /// `adc.enable_vrefint().degrade_adc()`, if the reference voltage should be measured.
+    pub fn new(
+        adc: Adc<'a, T>,
+        channels: [AnyAdcChannel<T>; N],
+        vrefint: Option<AnyAdcChannel<T>>,
+    ) -> Self {
+        Self {
+            adc,
+            channels,
+            vrefint,
+            hardware_ratio: 1,
+            readings: Matrix::zeroed(),
+        }
+    }
+
+    /// Configures the hardware oversampler for the largest power of two ratio up to
+    /// `oversampling`, with a matching shift so readings keep their 12-bit scale
+    fn configure_hardware_oversampling(&mut self, oversampling: u16) {
+        let ratio_log2 = oversampling.clamp(1, 256).ilog2() as u8;
+        let ratio = 1 << ratio_log2;
+        if ratio == self.hardware_ratio {
+            return;
+        }
+
+        if ratio_log2 > 0 {
+            // OVSR counts from a ratio of 2
+            self.adc.set_oversampling_ratio(ratio_log2 - 1);
+            self.adc.set_oversampling_shift(ratio_log2);
+        }
+        self.adc.enable_regular_oversampling_mode(
+            Rovsm::CONTINUED,
+            Trovs::AUTOMATIC,
+            ratio_log2 > 0,
+        );
+        self.hardware_ratio = ratio;
+    }
+
+    /// Averages `conversions` readings of a channel
+    fn average(adc: &mut Adc<'a, T>, channel: &mut AnyAdcChannel<T>, conversions: u16) -> f32 {
+        let sum: u32 = (0..conversions).map(|_| adc.read(channel) as u32).sum();
+        sum as f32 / conversions as f32
+    }
+}
+
+impl<T, const N: usize> MultiChannelAdcProtocol<N> for MultiChannelAdcWrapper<'_, T, N>
+where
+    T: embassy_stm32::adc::Instance,
+{
+    fn read_channels(&mut self, oversampling: u16, hardware_oversampling: bool) -> [f32; N] {
+        let software_conversions = if hardware_oversampling {
+            self.configure_hardware_oversampling(oversampling);
+            1
+        } else {
+            self.configure_hardware_oversampling(1);
+            oversampling.max(1)
+        };
+
+        let mut readings = [0.0; N];
+        for (reading, channel) in readings.iter_mut().zip(self.channels.iter_mut()) {
+            *reading = Self::average(&mut self.adc, channel, software_conversions) / FULL_SCALE;
+        }
+        readings
+    }
+
+    fn reference_voltage(&mut self) -> Option<f32> {
+        let vrefint = self.vrefint.as_mut()?;
+        let reading = Self::average(&mut self.adc, vrefint, 1);
+        if reading <= 0.0 {
+            return None;
+        }
+        // SAFETY: VREFINT_CAL is a read-only factory value in system memory
+        let calibration = unsafe { core::ptr::read_volatile(VREFINT_CAL_ADDRESS as *const u16) };
+        Some(VREFINT_CAL_VOLTAGE * calibration as f32 / reading)
+    }
+}
+
+impl<T, const N: usize> InputBlock for MultiChannelAdcWrapper<'_, T, N>
+where
+    T: embassy_stm32::adc::Instance,
+{
+    type Output = (Matrix<1, N, f64>, f64);
+    type Parameters = MultiChannelAdcBlockParams<N>;
+
+    fn input(
+        &mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        let readings =
+            self.read_channels(parameters.oversampling, parameters.hardware_oversampling);
+        for (output, reading) in self.readings.data.iter_mut().zip(readings) {
+            output[0] = reading as f64;
+        }
+        let reference_voltage = if parameters.vref_compensation {
+            self.reference_voltage().unwrap_or_default() as f64
+        } else {
+            0.0
+        };
+        (&self.readings, reference_voltage)
+    }
+}
@@ -0,0 +1,249 @@
+use core::time::Duration;
+
+use corelib_traits::{Context, InputBlock, PassBy};
+use embassy_stm32::gpio::Pull;
+use embassy_stm32::time::Hertz;
+use embassy_stm32::timer::input_capture::CapturePin;
+use embassy_stm32::timer::low_level::{
+    InputCaptureMode, InputTISelection, SlaveMode, Timer, TriggerSource,
+};
+use embassy_stm32::timer::{self, Ch1, Ch2, Ch3, Channel, Channel1Pin, Channel2Pin, Channel3Pin};
+use embassy_stm32::Peripheral;
+use pictorus_core_blocks::{
+    CaptureEdge, EdgeCounterBlockParams, PulseCaptureBlockParams, QuadratureEncoderBlockParams,
+};
+use protocols::capture::{EdgeCount, PulseMeasurement, QuadratureCount};
+use protocols::{EdgeCounterProtocol, PulseCaptureProtocol, QuadratureProtocol};
+
+// The timers count edges and capture pulses in hardware, but don't timestamp them, so the
+// blocks get the read time as the time of the last edge. Like the PWM, each timer only handles
+// one input, on CH1 (and CH2 and CH3 for quadrature).
+
+fn read_counter<T: timer::GeneralInstance4Channel>(timer: &Timer<'_, T>) -> u16 {
+    timer.regs_core().cnt().read().cnt()
+}
+
+/// Counts edges on the CH1 input of a timer, using the input as the timer's clock
+pub struct Stm32EdgeCounter<'d, T: timer::GeneralInstance4Channel> {
+    timer: Timer<'d, T>,
+    _pin: CapturePin<'d, T, Ch1>,
+    last_count: u16,
+}
+
+impl<'d, T: timer::GeneralInstance4Channel> Stm32EdgeCounter<'d, T> {
+    pub fn new(
+        tim: impl Peripheral<P = T> + 'd,
+        pin: impl Peripheral<P = impl Channel1Pin<T>> + 'd,
+        edge: CaptureEdge,
+    ) -> Self {
+        let pin = CapturePin::new_ch1(pin, Pull::None);
+        let timer = Timer::new(tim);
+        timer.set_input_ti_selection(Channel::Ch1, InputTISelection::Normal);
+        let trigger = match edge {
+            CaptureEdge::Rising => {
+                timer.set_input_capture_mode(Channel::Ch1, InputCaptureMode::Rising);
+                TriggerSource::TI1FP1
+            }
+            CaptureEdge::Falling => {
+                timer.set_input_capture_mode(Channel::Ch1, InputCaptureMode::Falling);
+                TriggerSource::TI1FP1
+            }
+            // The edge detector ignores the polarity
+            CaptureEdge::Both => TriggerSource::TI1F_ED,
+        };
+        timer.set_trigger_source(trigger);
+        timer.set_slave_mode(SlaveMode::EXT_CLOCK_MODE);
+        timer.start();
+        Self {
+            last_count: read_counter(&timer),
+            timer,
+            _pin: pin,
+        }
+    }
+}
+
+impl<T: timer::GeneralInstance4Channel> EdgeCounterProtocol for Stm32EdgeCounter<'_, T> {
+    /// Counts wrap at 16 bits, so at most 65535 edges can be counted between reads
+    fn read_edges(&mut self) -> EdgeCount {
+        let count = read_counter(&self.timer);
+        let edges = count.wrapping_sub(self.last_count);
+        self.last_count = count;
+        EdgeCount {
+            edges: edges as u32,
+            since_last_edge: None,
+        }
+    }
+}
+
+impl<T: timer::GeneralInstance4Channel> InputBlock for Stm32EdgeCounter<'_, T> {
+    type Output = (f64, f64);
+    type Parameters = EdgeCounterBlockParams;
+
+    fn input(
+        &mut self,
+        _parameters: &Self::Parameters,
+        context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        let count = self.read_edges();
+        (count.edges as f64, context.time().as_secs_f64())
+    }
+}
+
+/// Decodes a quadrature encoder on the CH1 and CH2 inputs of a timer in encoder mode. The
+/// optional index on CH3 captures the count at its rising edge.
+pub struct Stm32Quadrature<'d, T: timer::GeneralInstance4Channel> {
+    timer: Timer<'d, T>,
+    _pins: (
+        CapturePin<'d, T, Ch1>,
+        CapturePin<'d, T, Ch2>,
+        Option<CapturePin<'d, T, Ch3>>,
+    ),
+    last_count: u16,
+    position: i64,
+}
+
+impl<'d, T: timer::GeneralInstance4Channel> Stm32Quadrature<'d, T> {
+    pub fn new(
+        tim: impl Peripheral<P = T> + 'd,
+        pin_a: impl Peripheral<P = impl Channel1Pin<T>> + 'd,
+        pin_b: impl Peripheral<P = impl Channel2Pin<T>> + 'd,
+        pin_index: Option<impl Peripheral<P = impl Channel3Pin<T>> + 'd>,
+    ) -> Self {
+        let pins = (
+            CapturePin::new_ch1(pin_a, Pull::None),
+            CapturePin::new_ch2(pin_b, Pull::None),
+            pin_index.map(|pin| CapturePin::new_ch3(pin, Pull::None)),
+        );
+        let timer = Timer::new(tim);
+        for channel in [Channel::Ch1, Channel::Ch2] {
+            timer.set_input_ti_selection(channel, InputTISelection::Normal);
+            timer.set_input_capture_mode(channel, InputCaptureMode::Rising);
+        }
+        // Count on both edges of both inputs, four counts per encoder line
+        timer.set_slave_mode(SlaveMode::ENCODER_MODE_3);
+        if pins.2.is_some() {
+            timer.set_input_ti_selection(Channel::Ch3, InputTISelection::Normal);
+            timer.set_input_capture_mode(Channel::Ch3, InputCaptureMode::Rising);
+            timer.enable_channel(Channel::Ch3, true);
+        }
+        timer.start();
+        Self {
+            last_count: read_counter(&timer),
+            timer,
+            _pins: pins,
+            position: 0,
+        }
+    }
+}
+
+impl<T: timer::GeneralInstance4Channel> QuadratureProtocol for Stm32Quadrature<'_, T> {
+    /// Counts wrap at 16 bits, so the encoder can move at most 32767 counts between reads
+    fn read_position(&mut self) -> QuadratureCount {
+        let count = read_counter(&self.timer);
+        self.position += i64::from(count.wrapping_sub(self.last_count) as i16);
+        self.last_count = count;
+
+        let index_position = self.timer.get_input_interrupt(Channel::Ch3).then(|| {
+            self.timer.clear_input_interrupt(Channel::Ch3);
+            let captured = self.timer.get_capture_value(Channel::Ch3) as u16;
+            self.position - i64::from(count.wrapping_sub(captured) as i16)
+        });
+        QuadratureCount {
+            position: self.position,
+            since_last_count: None,
+            index_position,
+        }
+    }
+}
+
+impl<T: timer::GeneralInstance4Channel> InputBlock for Stm32Quadrature<'_, T> {
+    type Output = (f64, f64, bool, f64);
+    type Parameters = QuadratureEncoderBlockParams;
+
+    fn input(
+        &mut self,
+        _parameters: &Self::Parameters,
+        context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        let count = self.read_position();
+        (
+            count.position as f64,
+            context.time().as_secs_f64(),
+            count.index_position.is_some(),
+            count.index_position.unwrap_or_default() as f64,
+        )
+    }
+}
+
+/// Measures pulses on the CH1 input of a timer in PWM input mode. Each rising edge resets the
+/// counter and captures the period on CH1, and each falling edge captures the high time on CH2.
+pub struct Stm32PulseCapture<'d, T: timer::GeneralInstance4Channel> {
+    timer: Timer<'d, T>,
+    _pin: CapturePin<'d, T, Ch1>,
+    tick_hz: u32,
+}
+
+impl<'d, T: timer::GeneralInstance4Channel> Stm32PulseCapture<'d, T> {
+    /// The timer counts at `tick_hz`, which sets the resolution. Periods longer than 65535
+    /// ticks overflow the counter and read short, so it should be low enough for the slowest
+    /// expected signal.
+    pub fn new(
+        tim: impl Peripheral<P = T> + 'd,
+        pin: impl Peripheral<P = impl Channel1Pin<T>> + 'd,
+        tick_hz: f64,
+    ) -> Self {
+        let pin = CapturePin::new_ch1(pin, Pull::None);
+        let timer = Timer::new(tim);
+        let tick_hz = tick_hz as u32;
+        timer.set_tick_freq(Hertz(tick_hz));
+        timer.set_input_ti_selection(Channel::Ch1, InputTISelection::Normal);
+        timer.set_input_capture_mode(Channel::Ch1, InputCaptureMode::Rising);
+        timer.set_input_ti_selection(Channel::Ch2, InputTISelection::Alternate);
+        timer.set_input_capture_mode(Channel::Ch2, InputCaptureMode::Falling);
+        timer.set_trigger_source(TriggerSource::TI1FP1);
+        timer.set_slave_mode(SlaveMode::RESET_MODE);
+        timer.enable_channel(Channel::Ch1, true);
+        timer.enable_channel(Channel::Ch2, true);
+        timer.start();
+        Self {
+            timer,
+            _pin: pin,
+            tick_hz,
+        }
+    }
+
+    fn ticks_to_duration(&self, ticks: u32) -> Duration {
+        Duration::from_nanos(u64::from(ticks) * 1_000_000_000 / u64::from(self.tick_hz))
+    }
+}
+
+impl<T: timer::GeneralInstance4Channel> PulseCaptureProtocol for Stm32PulseCapture<'_, T> {
+    fn read_pulse(&mut self) -> Option<PulseMeasurement> {
+        if !self.timer.get_input_interrupt(Channel::Ch1) {
+            return None;
+        }
+        self.timer.clear_input_interrupt(Channel::Ch1);
+        let period = self.timer.get_capture_value(Channel::Ch1);
+        let high_time = self.timer.get_capture_value(Channel::Ch2);
+        // The first rising edge after startup has no period yet
+        (period > 0).then(|| PulseMeasurement {
+            period: self.ticks_to_duration(period),
+            high_time: self.ticks_to_duration(high_time.min(period)),
+        })
+    }
+}
+
+impl<T: timer::GeneralInstance4Channel> InputBlock for Stm32PulseCapture<'_, T> {
+    type Output = (f64, f64);
+    type Parameters = PulseCaptureBlockParams;
+
+    fn input(
+        &mut self,
+        _parameters: &Self::Parameters,
+        _context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        self.read_pulse()
+            .map(|pulse| (pulse.period.as_secs_f64(), pulse.high_time.as_secs_f64()))
+            .unwrap_or_default()
+    }
+}
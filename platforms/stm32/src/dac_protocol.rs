@@ -1,7 +1,14 @@
-use corelib_traits::{Matrix, OutputBlock};
-use embassy_stm32::dac::Dac;
-use pictorus_core_blocks::DacBlockParams;
-use protocols::DacProtocol;
+use core::marker::PhantomData;
+
+use corelib_traits::{Context, InputBlock, Matrix, OutputBlock, PassBy};
+use embassy_stm32::dac::{Dac, TriggerSel};
+use embassy_stm32::pac;
+use embassy_stm32::time::Hertz;
+use embassy_stm32::timer::{self, low_level::Timer};
+use embassy_stm32::Peripheral;
+use log::warn;
+use pictorus_core_blocks::{DacBlockParams, DacStreamBlockParams};
+use protocols::{DacProtocol, DacStreamProtocol};
 
 pub struct DacWrapper<
     'a,
@@ -78,3 +85,271 @@ where
         self.write(&input);
     }
 }
+
+/// Samples left between the DMA and the first sample written after an underrun, since the
+/// DAC may already have loaded the sample at the DMA's position
+const UNDERRUN_LEAD: u64 = 2;
+
+/// DMA channel dedicated to a DAC stream, with the DMAMUX line that routes the DAC's channel 1
+/// DMA request to it
+pub struct DacStreamDma {
+    pub dma: pac::bdma::Dma,
+    /// Channel index within `dma`
+    pub channel: usize,
+    /// DMAMUX channel wired to `channel`
+    pub mux_channel: usize,
+    /// DMAMUX request id of DAC channel 1
+    pub request: u8,
+}
+
+/// Streams samples to both DAC channels at a timer's rate from a circular DMA buffer.
+///
+/// The buffer holds two halves of `SAMPLES` samples. The timer triggers the DAC at the sample
+/// rate, and each trigger has the DAC request its next sample over DMA, so playback carries on
+/// between ticks without the CPU. The half-transfer and transfer-complete events mark each
+/// half as played, freeing it to be refilled by the next write. Samples go to the dual data
+/// register, with channel 2 in the upper half-word.
+///
+/// Each write also fills the rest of the buffer with its last sample, so if the model falls
+/// behind the output holds its last value instead of replaying old samples.
+pub struct DacStreamWrapper<
+    'a,
+    T: embassy_stm32::dac::Instance,
+    TIM: timer::BasicInstance,
+    const CHANNELS: usize,
+    const SAMPLES: usize,
+    const TABLE: usize,
+> {
+    _dac: Dac<'a, T>,
+    timer: Timer<'a, TIM>,
+    dma: DacStreamDma,
+    /// Both halves of the DMA buffer. The DMA reads it while it plays, so it is only accessed
+    /// through this pointer.
+    buffer: *mut u32,
+    /// Half-transfer and transfer-complete events seen since playback started
+    halves_played: u64,
+    /// Samples written since playback started, including the gaps skipped by underruns
+    written: u64,
+    last_sample: u32,
+    started: bool,
+    underruns: u32,
+    _buffer: PhantomData<&'a mut [[u32; SAMPLES]; 2]>,
+}
+
+impl<'a, T, TIM, const CHANNELS: usize, const SAMPLES: usize, const TABLE: usize>
+    DacStreamWrapper<'a, T, TIM, CHANNELS, SAMPLES, TABLE>
+where
+    T: embassy_stm32::dac::Instance,
+    TIM: timer::BasicInstance,
+{
+    /// `dac_regs` are the registers of `dac`, and `trigger` must select the update event of
+    /// `tim`
+    pub fn new(
+        mut dac: Dac<'a, T>,
+        dac_regs: pac::dac::Dac,
+        tim: impl Peripheral<P = TIM> + 'a,
+        trigger: TriggerSel,
+        dma: DacStreamDma,
+        buffer: &'a mut [[u32; SAMPLES]; 2],
+    ) -> Self {
+        // Note: A lot of the configuration options disable the DAC
+        dac.ch1().set_trigger(trigger);
+        dac.ch2().set_trigger(trigger);
+        dac.ch1().set_triggering(true);
+        dac.ch2().set_triggering(true);
+        dac_regs.cr().modify(|w| w.set_dmaen(0, true));
+        dac.ch1().enable();
+        dac.ch2().enable();
+
+        let timer = Timer::new(tim);
+        timer
+            .regs_basic()
+            .cr2()
+            .modify(|w| w.set_mms(pac::timer::vals::Mms::UPDATE));
+
+        let buffer = buffer.as_flattened_mut().as_mut_ptr();
+        pac::DMAMUX1
+            .ccr(dma.mux_channel)
+            .write(|w| w.set_dmareq_id(dma.request));
+        let channel = dma.dma.ch(dma.channel);
+        channel.cr().write(|w| w.set_en(false));
+        channel
+            .par()
+            .write_value(dac_regs.dhr12rd().as_ptr() as u32);
+        channel.mar().write_value(buffer as u32);
+        channel.ndtr().write(|w| w.set_ndt((2 * SAMPLES) as u16));
+
+        Self {
+            _dac: dac,
+            timer,
+            dma,
+            buffer,
+            halves_played: 0,
+            written: 0,
+            last_sample: 0,
+            started: false,
+            underruns: 0,
+            _buffer: PhantomData,
+        }
+    }
+
+    fn start(&mut self) {
+        let (dma, index) = (self.dma.dma, self.dma.channel);
+        dma.ifcr().write(|w| {
+            w.set_htif(index, true);
+            w.set_tcif(index, true);
+        });
+        // The events are polled each tick, so their interrupts stay off
+        dma.ch(index).cr().write(|w| {
+            w.set_dir(pac::bdma::vals::Dir::FROM_MEMORY);
+            w.set_minc(true);
+            w.set_circ(true);
+            w.set_msize(pac::bdma::vals::Size::BITS32);
+            w.set_psize(pac::bdma::vals::Size::BITS32);
+            w.set_pl(pac::bdma::vals::Pl::HIGH);
+            w.set_en(true);
+        });
+        self.timer.start();
+        self.started = true;
+    }
+
+    /// Samples played since playback started. Counts the halves finished since the last call
+    /// from the half-transfer and transfer-complete events, and adds the DMA's position in the
+    /// current half.
+    fn played(&mut self) -> u64 {
+        if !self.started {
+            return 0;
+        }
+        let (dma, index) = (self.dma.dma, self.dma.channel);
+        let position = || 2 * SAMPLES - dma.ch(index).ndtr().read().ndt() as usize;
+        // Retry if a half finished while the events were read, so they match the position
+        let (isr, position) = loop {
+            let before = position();
+            let isr = dma.isr().read();
+            let after = position();
+            if before / SAMPLES == after / SAMPLES {
+                break (isr, after);
+            }
+        };
+        let (half, complete) = (isr.htif(index), isr.tcif(index));
+        dma.ifcr().write(|w| {
+            w.set_htif(index, half);
+            w.set_tcif(index, complete);
+        });
+
+        let mut finished = half as u64 + complete as u64;
+        // Each event is only seen once, so a whole lap between calls shows up as the DMA being
+        // in the other half from the one the events lead to
+        if (self.halves_played + finished) % 2 != (position / SAMPLES) as u64 {
+            finished += 1;
+        }
+        self.halves_played += finished;
+        self.halves_played * SAMPLES as u64 + (position % SAMPLES) as u64
+    }
+
+    /// Checks for an underrun, holding the last sample across the whole buffer if the DMA has
+    /// played everything written. Returns the samples played.
+    fn service(&mut self) -> u64 {
+        let played = self.played();
+        if self.started && played >= self.written {
+            self.underruns += 1;
+            for i in 0..2 * SAMPLES as u64 {
+                self.write_sample(i, self.last_sample);
+            }
+            self.written = played + UNDERRUN_LEAD;
+        }
+        played
+    }
+
+    fn write_sample(&mut self, index: u64, sample: u32) {
+        let index = (index % (2 * SAMPLES) as u64) as usize;
+        unsafe { core::ptr::write_volatile(self.buffer.add(index), sample) };
+    }
+}
+
+impl<T, TIM, const CHANNELS: usize, const SAMPLES: usize, const TABLE: usize>
+    DacStreamProtocol<CHANNELS, SAMPLES> for DacStreamWrapper<'_, T, TIM, CHANNELS, SAMPLES, TABLE>
+where
+    T: embassy_stm32::dac::Instance,
+    TIM: timer::BasicInstance,
+{
+    fn set_sample_rate(&mut self, sample_rate_hz: f32) {
+        self.timer.set_frequency(Hertz(sample_rate_hz as u32));
+    }
+
+    fn write_buffer(&mut self, samples: &[[u16; SAMPLES]; CHANNELS], len: usize) -> bool {
+        let played = self.service();
+        let len = len.min(SAMPLES);
+        // Room for one buffer besides the half that is playing
+        let free = (played + 2 * SAMPLES as u64).saturating_sub(self.written);
+        if len as u64 > free.min(SAMPLES as u64) {
+            return false;
+        }
+
+        for i in 0..len {
+            let channel = |channel: usize| samples.get(channel).map_or(0, |s| s[i] as u32);
+            self.last_sample = channel(0) | channel(1) << 16;
+            self.write_sample(self.written + i as u64, self.last_sample);
+        }
+        self.written += len as u64;
+        for i in self.written..played + 2 * SAMPLES as u64 {
+            self.write_sample(i, self.last_sample);
+        }
+
+        if !self.started && len > 0 {
+            self.start();
+        }
+        true
+    }
+
+    fn underruns(&mut self) -> u32 {
+        self.service();
+        self.underruns
+    }
+}
+
+impl<T, TIM, const CHANNELS: usize, const SAMPLES: usize, const TABLE: usize> OutputBlock
+    for DacStreamWrapper<'_, T, TIM, CHANNELS, SAMPLES, TABLE>
+where
+    T: embassy_stm32::dac::Instance,
+    TIM: timer::BasicInstance,
+{
+    type Inputs = (Matrix<SAMPLES, CHANNELS, f64>, f64);
+    type Parameters = DacStreamBlockParams<CHANNELS, TABLE>;
+
+    fn output(
+        &mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) {
+        let (buffer, valid) = inputs;
+        if !self.started {
+            self.set_sample_rate(parameters.sample_rate_hz as f32);
+        }
+        let samples = buffer
+            .data
+            .map(|channel| channel.map(|sample| sample as u16));
+        if !self.write_buffer(&samples, valid as usize) {
+            warn!("DAC stream buffers full, dropping {valid} samples");
+        }
+    }
+}
+
+impl<T, TIM, const CHANNELS: usize, const SAMPLES: usize, const TABLE: usize> InputBlock
+    for DacStreamWrapper<'_, T, TIM, CHANNELS, SAMPLES, TABLE>
+where
+    T: embassy_stm32::dac::Instance,
+    TIM: timer::BasicInstance,
+{
+    type Output = f64;
+    type Parameters = DacStreamBlockParams<CHANNELS, TABLE>;
+
+    fn input(
+        &mut self,
+        _parameters: &Self::Parameters,
+        _context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        self.underruns() as f64
+    }
+}
@@ -1,25 +1,55 @@
+use alloc::vec;
 use alloc::vec::Vec;
+use core::marker::PhantomData;
 use corelib_traits::{ByteSliceSignal, InputBlock, OutputBlock};
 use embassy_stm32::i2c::I2c;
 use embassy_stm32::mode::Blocking;
-use embedded_hal::i2c::I2c as I2cTrait;
-use pictorus_core_blocks::{I2cInputBlockParams, I2cOutputBlockParams};
+use embassy_time::Delay;
+use log::warn;
+use pictorus_core_blocks::{I2cInputBlockParams, I2cOutputBlockParams, I2cTransactionBlockParams};
+use utils::i2c_script::{I2C_ERROR_UNSUPPORTED, I2C_OK};
+use utils::{I2cAddress, I2cScript};
 
-pub struct I2cWrapper<'a> {
+/// Runs the I2C block scripts on the bus. `P` is the parameters of the input block it reads
+/// for: `I2cInputBlockParams` for an I2C Input Block, or `I2cTransactionBlockParams` for an
+/// I2C Transaction Block, which also takes the result codes.
+pub struct I2cWrapper<'a, P = I2cInputBlockParams> {
     i2c: I2c<'a, Blocking>,
     buffer: Vec<u8>,
+    results: Vec<u8>,
+    _input: PhantomData<fn() -> P>,
 }
 
-impl<'a> I2cWrapper<'a> {
+impl<'a, P> I2cWrapper<'a, P> {
     pub fn new(i2c: I2c<'a, Blocking>) -> Self {
         Self {
             i2c,
             buffer: Vec::new(),
+            results: Vec::new(),
+            _input: PhantomData,
+        }
+    }
+
+    fn run(&mut self, address: I2cAddress, script: &I2cScript, input: &[u8]) {
+        match address {
+            I2cAddress::SevenBit(address) => script.run(
+                &mut self.i2c,
+                address,
+                &mut Delay,
+                input,
+                &mut self.buffer,
+                &mut self.results,
+            ),
+            // The blocking embassy driver only supports 7-bit addresses
+            I2cAddress::TenBit(_) => {
+                self.buffer.clear();
+                self.results = vec![I2C_ERROR_UNSUPPORTED; script.transaction_count()];
+            }
         }
     }
 }
 
-impl InputBlock for I2cWrapper<'_> {
+impl InputBlock for I2cWrapper<'_, I2cInputBlockParams> {
     type Output = ByteSliceSignal;
     type Parameters = I2cInputBlockParams;
 
@@ -28,24 +58,35 @@ impl InputBlock for I2cWrapper<'_> {
         parameters: &Self::Parameters,
         _context: &dyn corelib_traits::Context,
     ) -> corelib_traits::PassBy<'_, Self::Output> {
-        let size = parameters.read_bytes;
-        self.buffer.resize(size, 0);
-        let result = self.i2c.write_read(
-            parameters.address,
-            &[parameters.command],
-            &mut self.buffer[..size],
-        );
-
-        if result.is_err() {
-            // TODO: Error handling
-            // Keep the results, good or bad, in memory
+        let address = I2cAddress::SevenBit(parameters.address);
+        self.run(address, parameters.script(), &[]);
+        if self.results.iter().any(|r| *r != I2C_OK) {
+            self.buffer.clear();
         }
 
         &self.buffer
     }
 }
 
-impl OutputBlock for I2cWrapper<'_> {
+impl InputBlock for I2cWrapper<'_, I2cTransactionBlockParams> {
+    type Output = (ByteSliceSignal, ByteSliceSignal);
+    type Parameters = I2cTransactionBlockParams;
+
+    fn input(
+        &mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn corelib_traits::Context,
+    ) -> corelib_traits::PassBy<'_, Self::Output> {
+        self.run(parameters.address, &parameters.script, &[]);
+        if self.results.iter().any(|r| *r != I2C_OK) {
+            self.buffer.clear();
+        }
+
+        (&self.buffer, &self.results)
+    }
+}
+
+impl<P> OutputBlock for I2cWrapper<'_, P> {
     type Inputs = ByteSliceSignal;
     type Parameters = I2cOutputBlockParams;
 
@@ -55,9 +96,9 @@ impl OutputBlock for I2cWrapper<'_> {
         _context: &dyn corelib_traits::Context,
         inputs: corelib_traits::PassBy<'_, Self::Inputs>,
     ) {
-        let mut tx_buffer = Vec::new();
-        tx_buffer.push(parameters.command);
-        tx_buffer.extend_from_slice(inputs);
-        self.i2c.write(parameters.address, &tx_buffer).ok();
+        self.run(parameters.address, &parameters.script, inputs);
+        if self.results.iter().any(|r| *r != I2C_OK) {
+            warn!("I2C write failed with results {:?}", self.results);
+        }
     }
 }
@@ -2,6 +2,11 @@
 
 extern crate alloc;
 
+#[cfg(feature = "capture")]
+mod capture_protocol;
+#[cfg(feature = "capture")]
+pub use capture_protocol::*;
+
 mod clock_protocol;
 pub use clock_protocol::*;
 
@@ -13,6 +18,11 @@ mod can_protocol;
 #[cfg(any(feature = "can", feature = "fdcan"))]
 pub use can_protocol::*;
 
+#[cfg(feature = "modbus")]
+mod modbus_protocol;
+#[cfg(feature = "modbus")]
+pub use modbus_protocol::*;
+
 mod pwm_protocol;
 pub use pwm_protocol::*;
 
@@ -22,6 +32,9 @@ pub use logger::*;
 mod i2c_protocol;
 pub use i2c_protocol::*;
 
+mod sensor_protocol;
+pub use sensor_protocol::*;
+
 #[cfg(feature = "dac")]
 mod dac_protocol;
 #[cfg(feature = "dac")]
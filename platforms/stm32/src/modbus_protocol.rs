@@ -0,0 +1,77 @@
+use embassy_stm32::usart::Error;
+use embassy_time::Duration;
+use embedded_io::Write;
+pub use protocols::ModbusWrapper;
+use protocols::modbus::{
+    decode_rtu_frame, encode_rtu_frame, rtu_response_remaining, MAX_RTU_FRAME_BYTES,
+};
+use protocols::{ModbusError, ModbusProtocol};
+
+use crate::SerialWrapper;
+
+/// Modbus RTU master over a UART. The UART should be dedicated to Modbus, since any data
+/// received outside of a transaction is discarded.
+pub struct ModbusRtu<'a> {
+    serial: SerialWrapper<'a>,
+    timeout: Duration,
+    frame: [u8; MAX_RTU_FRAME_BYTES],
+}
+
+impl<'a> ModbusRtu<'a> {
+    pub fn new(serial: SerialWrapper<'a>) -> Self {
+        Self {
+            serial,
+            timeout: Duration::from_millis(100),
+            frame: [0; MAX_RTU_FRAME_BYTES],
+        }
+    }
+}
+
+impl ModbusProtocol for ModbusRtu<'_> {
+    type Error = Error;
+
+    fn set_timeout(&mut self, timeout: core::time::Duration) {
+        self.timeout = Duration::from_micros(timeout.as_micros() as u64);
+    }
+
+    fn transaction(
+        &mut self,
+        unit_id: u8,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, ModbusError<Self::Error>> {
+        self.serial.clear_input();
+        let frame_len = encode_rtu_frame(unit_id, request, &mut self.frame);
+        self.serial
+            .write_all(&self.frame[..frame_len])
+            .map_err(ModbusError::Io)?;
+
+        let mut head = [0u8; 3];
+        if !self
+            .serial
+            .read_exact_timeout(&mut head, self.timeout)
+            .map_err(ModbusError::Io)?
+        {
+            return Err(ModbusError::Timeout);
+        }
+        let response_len = head.len() + rtu_response_remaining(&head);
+        if response_len > self.frame.len() {
+            return Err(ModbusError::InvalidResponse);
+        }
+        self.frame[..head.len()].copy_from_slice(&head);
+        if !self
+            .serial
+            .read_exact_timeout(&mut self.frame[head.len()..response_len], self.timeout)
+            .map_err(ModbusError::Io)?
+        {
+            return Err(ModbusError::Timeout);
+        }
+
+        let pdu = decode_rtu_frame(unit_id, &self.frame[..response_len])?;
+        if pdu.len() > response.len() {
+            return Err(ModbusError::InvalidResponse);
+        }
+        response[..pdu.len()].copy_from_slice(pdu);
+        Ok(pdu.len())
+    }
+}
@@ -0,0 +1,14 @@
+use embassy_stm32::i2c::I2c;
+use embassy_stm32::mode::Blocking;
+use embassy_time::Delay;
+
+pub use sensor_drivers::wrappers::{BarometerWrapper, ImuWrapper, MagnetometerWrapper};
+
+/// IMU Block input on a blocking I2C bus, created with `ImuWrapper::new(i2c, Delay)`
+pub type Stm32ImuWrapper<'a> = ImuWrapper<I2c<'a, Blocking>, Delay>;
+
+/// Barometer Block input on a blocking I2C bus
+pub type Stm32BarometerWrapper<'a> = BarometerWrapper<I2c<'a, Blocking>, Delay>;
+
+/// Magnetometer Block input on a blocking I2C bus
+pub type Stm32MagnetometerWrapper<'a> = MagnetometerWrapper<I2c<'a, Blocking>, Delay>;
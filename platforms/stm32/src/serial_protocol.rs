@@ -57,6 +57,39 @@ impl<'a> SerialWrapper<'a> {
             cache: Vec::with_capacity(BUFF_SIZE_BYTES),
         }
     }
+
+    /// Discards any received data that has not been read yet, including the cache
+    pub(crate) fn clear_input(&mut self) {
+        self.cache_stale = true;
+        self.cache.clear();
+        let mut scratch = [0u8; 32];
+        loop {
+            let read_fut = a_io::Read::read(&mut self.rx, &mut scratch);
+            let time_fut = Timer::after(Duration::from_micros(10));
+            match block_on(select(read_fut, time_fut)) {
+                Either::First(Ok(size)) if size > 0 => continue,
+                _ => break,
+            }
+        }
+    }
+
+    /// Fills `buf` with received data, bypassing the cache. Returns `Ok(false)` if `timeout`
+    /// expires before enough data is received.
+    pub(crate) fn read_exact_timeout(
+        &mut self,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<bool, Error> {
+        let read_fut = a_io::Read::read_exact(&mut self.rx, buf);
+        let time_fut = Timer::after(timeout);
+        match block_on(select(read_fut, time_fut)) {
+            Either::First(Ok(())) => Ok(true),
+            Either::First(Err(a_io::ReadExactError::Other(e))) => Err(e),
+            Either::First(Err(a_io::ReadExactError::UnexpectedEof)) | Either::Second(_) => {
+                Ok(false)
+            }
+        }
+    }
 }
 
 impl ErrorType for SerialWrapper<'_> {
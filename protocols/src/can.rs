@@ -0,0 +1,264 @@
+//! CAN frames, acceptance filters and bus status
+//!
+//! Platforms whose drivers don't have a frame type covering both classic and FD frames use
+//! [`CanFrame`], and platforms that don't filter in hardware check frames against
+//! [`CanFilter`]s themselves. [`BusLoadMeter`] estimates bus load from the frames a platform
+//! sees, for hardware that doesn't measure it.
+use core::time::Duration;
+
+use embedded_can::{Frame, Id};
+
+/// Largest payload of a CAN FD frame
+pub const MAX_FD_DATA: usize = 64;
+
+/// Payload lengths a CAN FD frame can have. Longer payloads are padded to the next one.
+const FD_LENGTHS: [usize; 16] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 20, 24, 32, 48, 64];
+
+/// A classic or FD CAN frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CanFrame {
+    id: Id,
+    data: [u8; MAX_FD_DATA],
+    len: u8,
+    remote: bool,
+    fd: bool,
+    brs: bool,
+}
+
+impl CanFrame {
+    /// Creates an FD frame, padding the data with zeros to the next FD payload length. With
+    /// `brs` the data is sent at the data bitrate. Returns `None` if the data is longer than
+    /// 64 bytes.
+    pub fn new_fd(id: impl Into<Id>, data: &[u8], brs: bool) -> Option<Self> {
+        let len = *FD_LENGTHS.iter().find(|len| **len >= data.len())?;
+        let mut frame = Self::empty(id.into());
+        frame.data[..data.len()].copy_from_slice(data);
+        frame.len = len as u8;
+        frame.fd = true;
+        frame.brs = brs;
+        Some(frame)
+    }
+
+    fn empty(id: Id) -> Self {
+        Self {
+            id,
+            data: [0; MAX_FD_DATA],
+            len: 0,
+            remote: false,
+            fd: false,
+            brs: false,
+        }
+    }
+
+    pub fn is_fd(&self) -> bool {
+        self.fd
+    }
+
+    /// Whether the data of an FD frame is sent at the data bitrate
+    pub fn is_brs(&self) -> bool {
+        self.brs
+    }
+
+    /// Time the frame occupies the bus, excluding stuff bits
+    pub fn bus_time(&self, bitrate: u32, data_bitrate: u32) -> Duration {
+        let extended = self.is_extended();
+        let data_bits = 8 * self.len as u32;
+        let (nominal_bits, fast_bits) = if self.fd {
+            // Arbitration up to the bitrate switch, then the control field, data and CRC,
+            // then the CRC delimiter, acknowledgement, end of frame and interframe space
+            let arbitration = if extended { 36 } else { 17 };
+            let crc = if self.len > 16 { 21 } else { 17 };
+            (arbitration + 13, 9 + data_bits + crc)
+        } else {
+            let overhead = if extended { 67 } else { 47 };
+            (overhead + if self.remote { 0 } else { data_bits }, 0)
+        };
+
+        let (nominal_bits, fast_bits) = if self.brs {
+            (nominal_bits, fast_bits)
+        } else {
+            (nominal_bits + fast_bits, 0)
+        };
+        let mut seconds = nominal_bits as f64 / bitrate.max(1) as f64;
+        if fast_bits > 0 {
+            seconds += fast_bits as f64 / data_bitrate.max(1) as f64;
+        }
+        Duration::from_secs_f64(seconds)
+    }
+}
+
+impl Frame for CanFrame {
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        if data.len() > 8 {
+            return None;
+        }
+        let mut frame = Self::empty(id.into());
+        frame.data[..data.len()].copy_from_slice(data);
+        frame.len = data.len() as u8;
+        Some(frame)
+    }
+
+    fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
+        if dlc > 8 {
+            return None;
+        }
+        let mut frame = Self::empty(id.into());
+        frame.len = dlc as u8;
+        frame.remote = true;
+        Some(frame)
+    }
+
+    fn is_extended(&self) -> bool {
+        matches!(self.id, Id::Extended(_))
+    }
+
+    fn is_remote_frame(&self) -> bool {
+        self.remote
+    }
+
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn dlc(&self) -> usize {
+        self.len as usize
+    }
+
+    fn data(&self) -> &[u8] {
+        if self.remote {
+            &[]
+        } else {
+            &self.data[..self.len as usize]
+        }
+    }
+}
+
+/// Raw value of an ID, without any flags
+pub fn raw_id(id: Id) -> u32 {
+    match id {
+        Id::Standard(id) => id.as_raw() as u32,
+        Id::Extended(id) => id.as_raw(),
+    }
+}
+
+/// Accepts frames whose ID matches the filter's ID in every bit set in the mask, like the
+/// Linux kernel's CAN filters. Standard and extended IDs never match each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CanFilter {
+    pub id: Id,
+    pub mask: u32,
+}
+
+impl CanFilter {
+    pub fn new(id: impl Into<Id>, mask: u32) -> Self {
+        Self {
+            id: id.into(),
+            mask,
+        }
+    }
+
+    /// Accepts a single ID, such as the ID of a receive block
+    pub fn exact(id: impl Into<Id>) -> Self {
+        Self::new(id, u32::MAX)
+    }
+
+    pub fn matches(&self, id: Id) -> bool {
+        matches!(
+            (self.id, id),
+            (Id::Standard(_), Id::Standard(_)) | (Id::Extended(_), Id::Extended(_))
+        ) && raw_id(id) & self.mask == raw_id(self.id) & self.mask
+    }
+
+    /// Whether any of `filters` accepts an ID. No filters accepts every ID.
+    pub fn accepts(filters: &[Self], id: Id) -> bool {
+        filters.is_empty() || filters.iter().any(|filter| filter.matches(id))
+    }
+}
+
+/// Settings of a CAN interface
+#[derive(Debug, Clone, Copy)]
+pub struct CanConfig<'a> {
+    /// Use CAN FD, so FD frames can be sent and received
+    pub fd: bool,
+    /// Nominal bitrate of the bus
+    pub bitrate: u32,
+    /// Data phase bitrate of FD frames that switch bitrate
+    pub data_bitrate: u32,
+    /// Frames to receive, usually one filter per receive block in the model. With no filters
+    /// every frame is received.
+    pub filters: &'a [CanFilter],
+}
+
+impl Default for CanConfig<'_> {
+    fn default() -> Self {
+        Self {
+            fd: false,
+            bitrate: 500_000,
+            data_bitrate: 2_000_000,
+            filters: &[],
+        }
+    }
+}
+
+/// Error state of a CAN controller, from the error counters defined by the CAN standard
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum CanBusState {
+    /// Normal operation
+    #[default]
+    ErrorActive,
+    /// An error counter has passed the warning level of 96
+    ErrorWarning,
+    /// An error counter has passed 127, so the controller no longer signals errors it detects
+    ErrorPassive,
+    /// The transmit error counter has passed 255, so the controller has left the bus
+    BusOff,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CanBusStatus {
+    pub state: CanBusState,
+    /// Error frames received since the previous read
+    pub error_frames: u32,
+    /// Frames dropped since the previous read because the transmit queue was full
+    pub tx_dropped: u32,
+    /// Fraction of the time since the previous read that the bus carried frames
+    pub bus_load: f32,
+}
+
+/// A CAN interface that reports the health of its bus
+pub trait CanStatusProtocol {
+    /// Returns the bus status, restarting the counts and bus load measurement
+    fn bus_status(&mut self) -> CanBusStatus;
+}
+
+/// Estimates bus load from the frames sent and received
+#[derive(Debug)]
+pub struct BusLoadMeter {
+    bitrate: u32,
+    data_bitrate: u32,
+    busy: Duration,
+}
+
+impl BusLoadMeter {
+    pub fn new(bitrate: u32, data_bitrate: u32) -> Self {
+        Self {
+            bitrate,
+            data_bitrate,
+            busy: Duration::ZERO,
+        }
+    }
+
+    pub fn record(&mut self, frame: &CanFrame) {
+        self.busy += frame.bus_time(self.bitrate, self.data_bitrate);
+    }
+
+    /// Returns the fraction of `elapsed` that the recorded frames used and restarts the
+    /// measurement
+    pub fn take_load(&mut self, elapsed: Duration) -> f32 {
+        let busy = core::mem::take(&mut self.busy);
+        if elapsed.is_zero() {
+            return 0.0;
+        }
+        (busy.as_secs_f64() / elapsed.as_secs_f64()).min(1.0) as f32
+    }
+}
@@ -0,0 +1,162 @@
+//! Edge counting, quadrature decoding and pulse capture
+//!
+//! Timer peripherals can count, decode and capture in hardware. Platforms that only get
+//! timestamped edge events, such as Linux GPIO lines, can feed them to [`EdgeAccumulator`],
+//! [`QuadratureDecoder`] and [`PulseTimer`] so every platform reports the same values.
+use core::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct EdgeCount {
+    /// Edges counted since the previous read
+    pub edges: u32,
+    /// Time from the most recent edge to the read. `None` if no edge has been seen, or if the
+    /// hardware does not timestamp edges.
+    pub since_last_edge: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QuadratureCount {
+    /// Position in counts, four per encoder line, relative to the position at startup
+    pub position: i64,
+    /// Time from the most recent count to the read. `None` if the position has not changed,
+    /// or if the hardware does not timestamp counts.
+    pub since_last_count: Option<Duration>,
+    /// Position when the most recent index pulse arrived, if one arrived since the previous
+    /// read
+    pub index_position: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PulseMeasurement {
+    /// Time between two rising edges
+    pub period: Duration,
+    /// Time the signal was high during the period
+    pub high_time: Duration,
+}
+
+/// Counts edges on an input between reads
+pub trait EdgeCounterProtocol {
+    fn read_edges(&mut self) -> EdgeCount;
+}
+
+/// Decodes the A, B and index signals of a quadrature encoder
+pub trait QuadratureProtocol {
+    fn read_position(&mut self) -> QuadratureCount;
+}
+
+/// Measures the period and duty cycle of a pulse train
+pub trait PulseCaptureProtocol {
+    /// Returns the most recent complete period, if one completed since the previous read
+    fn read_pulse(&mut self) -> Option<PulseMeasurement>;
+}
+
+/// Counts timestamped edge events
+#[derive(Debug, Default)]
+pub struct EdgeAccumulator {
+    edges: u32,
+    last_edge: Option<Duration>,
+}
+
+impl EdgeAccumulator {
+    pub fn edge(&mut self, timestamp: Duration) {
+        self.edges = self.edges.saturating_add(1);
+        self.last_edge = Some(timestamp);
+    }
+
+    /// Returns the edges since the previous read, with `now` on the same clock as the edge
+    /// timestamps
+    pub fn read(&mut self, now: Duration) -> EdgeCount {
+        EdgeCount {
+            edges: core::mem::take(&mut self.edges),
+            since_last_edge: self.last_edge.map(|t| now.saturating_sub(t)),
+        }
+    }
+}
+
+/// Position change for each transition, indexed by the previous and new `A << 1 | B` states.
+/// Stepping through 00, 10, 11, 01 counts up. Transitions where both signals changed mean an
+/// edge was missed, so the direction is unknown and they don't count.
+const TRANSITIONS: [[i8; 4]; 4] = [[0, -1, 1, 0], [1, 0, 0, -1], [-1, 0, 0, 1], [0, 1, -1, 0]];
+
+/// Decodes the levels of the A and B signals of a quadrature encoder into a position
+#[derive(Debug, Default)]
+pub struct QuadratureDecoder {
+    state: u8,
+    position: i64,
+    last_count: Option<Duration>,
+    index_position: Option<i64>,
+}
+
+impl QuadratureDecoder {
+    /// Creates a decoder from the signal levels at startup
+    pub fn new(a: bool, b: bool) -> Self {
+        Self {
+            state: state(a, b),
+            ..Default::default()
+        }
+    }
+
+    /// Updates the decoder with the levels after an edge on either signal
+    pub fn update(&mut self, a: bool, b: bool, timestamp: Duration) {
+        let state = state(a, b);
+        let step = TRANSITIONS[self.state as usize][state as usize];
+        self.state = state;
+        if step != 0 {
+            self.position += i64::from(step);
+            self.last_count = Some(timestamp);
+        }
+    }
+
+    /// Latches the position on a rising edge of the index signal
+    pub fn index(&mut self) {
+        self.index_position = Some(self.position);
+    }
+
+    /// Returns the position, with `now` on the same clock as the edge timestamps
+    pub fn read(&mut self, now: Duration) -> QuadratureCount {
+        QuadratureCount {
+            position: self.position,
+            since_last_count: self.last_count.map(|t| now.saturating_sub(t)),
+            index_position: self.index_position.take(),
+        }
+    }
+}
+
+fn state(a: bool, b: bool) -> u8 {
+    (a as u8) << 1 | b as u8
+}
+
+/// Measures pulses from the timestamps of their rising and falling edges
+#[derive(Debug, Default)]
+pub struct PulseTimer {
+    last_rising: Option<Duration>,
+    high_time: Option<Duration>,
+    measurement: Option<PulseMeasurement>,
+}
+
+impl PulseTimer {
+    pub fn edge(&mut self, rising: bool, timestamp: Duration) {
+        let Some(last_rising) = self.last_rising else {
+            if rising {
+                self.last_rising = Some(timestamp);
+            }
+            return;
+        };
+        if rising {
+            // The high time is unknown if the falling edge was missed
+            if let Some(high_time) = self.high_time.take() {
+                self.measurement = Some(PulseMeasurement {
+                    period: timestamp.saturating_sub(last_rising),
+                    high_time,
+                });
+            }
+            self.last_rising = Some(timestamp);
+        } else {
+            self.high_time = Some(timestamp.saturating_sub(last_rising));
+        }
+    }
+
+    pub fn read(&mut self) -> Option<PulseMeasurement> {
+        self.measurement.take()
+    }
+}
@@ -38,16 +38,63 @@ pub trait UdpProtocol {
     fn flush(&mut self);
 }
 
+/// A level change on a GPIO input
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GpioEdgeEvent {
+    pub rising: bool,
+    /// Time of the edge, on the clock of [`GpioEventProtocol::now`]
+    pub timestamp: core::time::Duration,
+}
+
+/// A GPIO input that streams timestamped edge events, so edges between reads aren't missed
+pub trait GpioEventProtocol {
+    /// Returns the oldest edge not yet read
+    fn next_event(&mut self) -> Option<GpioEdgeEvent>;
+
+    /// Returns the level of the input
+    fn level(&mut self) -> bool;
+
+    /// Returns the current time on the clock of the event timestamps
+    fn now(&mut self) -> core::time::Duration;
+}
+
 pub trait Flush {
     fn flush(&mut self);
 }
 
+/// A small display showing a line of text, such as a character LCD or OLED
+pub trait DisplayProtocol {
+    /// Shows `value`, shifted right by `x_offset`. The units of the offset depend on the
+    /// display: pixels for graphical displays and characters for character displays.
+    fn render(&mut self, value: &str, x_offset: f64);
+}
+
+/// A camera that captures still photos. Capturing can be slow, so it usually runs on its own
+/// thread.
+pub trait CameraProtocol: Send {
+    /// Captures a JPEG photo into `photo_dir`, with a quality from 1 to 100
+    fn capture(&self, photo_dir: &str, jpeg_quality: u8);
+}
+
 #[cfg(feature = "adc")]
 pub trait AdcProtocol {
     fn read(&mut self) -> u16;
     fn flush(&mut self);
 }
 
+#[cfg(feature = "adc")]
+/// An ADC that converts a set of channels each tick
+pub trait MultiChannelAdcProtocol<const CHANNELS: usize> {
+    /// Converts every channel, averaging `oversampling` conversions of each, and returns the
+    /// readings as fractions of full scale. With `hardware_oversampling` the ADC averages in
+    /// its oversampler where it has one, otherwise each channel is converted repeatedly.
+    fn read_channels(&mut self, oversampling: u16, hardware_oversampling: bool) -> [f32; CHANNELS];
+
+    /// Measures the ADC's reference voltage against its internal reference, or `None` if it
+    /// has none
+    fn reference_voltage(&mut self) -> Option<f32>;
+}
+
 #[cfg(feature = "dac")]
 pub trait DacProtocol<const CHANNELS: usize, const SAMPLES: usize> {
     /// Trait function to write a buffer of samples to the DAC. The samples are the ROWS and the
@@ -56,6 +103,41 @@ pub trait DacProtocol<const CHANNELS: usize, const SAMPLES: usize> {
     fn write(&mut self, value: &[[u16; SAMPLES]; CHANNELS]);
 }
 
+#[cfg(feature = "dac")]
+/// A DAC that plays samples back at its own sample rate from a pair of buffers, so one buffer
+/// can be written while the other plays
+pub trait DacStreamProtocol<const CHANNELS: usize, const SAMPLES: usize> {
+    /// Sets the rate samples are played at
+    fn set_sample_rate(&mut self, sample_rate_hz: f32);
+
+    /// Queues the first `len` samples of each channel to play once the current buffer
+    /// finishes. Returns false, dropping the samples, if both buffers are still in use.
+    fn write_buffer(&mut self, samples: &[[u16; SAMPLES]; CHANNELS], len: usize) -> bool;
+
+    /// Number of underruns since playback started. An underrun is a buffer finishing with no
+    /// other queued, so the output holds its last sample until the next write.
+    fn underruns(&mut self) -> u32;
+}
+
+#[cfg(feature = "modbus")]
+pub mod modbus;
+#[cfg(feature = "modbus")]
+pub use modbus::{ModbusError, ModbusProtocol};
+#[cfg(feature = "modbus")]
+mod modbus_wrapper;
+#[cfg(feature = "modbus")]
+pub use modbus_wrapper::ModbusWrapper;
+
+#[cfg(any(feature = "can", feature = "fdcan"))]
+pub mod can;
+#[cfg(any(feature = "can", feature = "fdcan"))]
+pub use can::CanStatusProtocol;
+
+#[cfg(feature = "capture")]
+pub mod capture;
+#[cfg(feature = "capture")]
+pub use capture::{EdgeCounterProtocol, PulseCaptureProtocol, QuadratureProtocol};
+
 #[cfg(all(feature = "test-utils", feature = "std"))]
 pub use test_utils::*;
 
@@ -219,6 +301,30 @@ mod test_utils {
         }
     }
 
+    mock! {
+        pub DisplayProtocol {}
+        impl DisplayProtocol for DisplayProtocol {
+            fn render(&mut self, value: &str, x_offset: f64);
+        }
+    }
+
+    mock! {
+        pub CameraProtocol {}
+        impl CameraProtocol for CameraProtocol {
+            fn capture(&self, photo_dir: &str, jpeg_quality: u8);
+        }
+    }
+
+    mock! {
+        pub GpioEventProtocol {}
+
+        impl GpioEventProtocol for GpioEventProtocol {
+            fn next_event(&mut self) -> Option<GpioEdgeEvent>;
+            fn level(&mut self) -> bool;
+            fn now(&mut self) -> core::time::Duration;
+        }
+    }
+
     mock! {
         pub FlushableProtocol {}
 
@@ -236,6 +342,15 @@ mod test_utils {
         }
     }
 
+    #[cfg(feature = "adc")]
+    mock! {
+        pub MultiChannelAdcProtocol {}
+        impl MultiChannelAdcProtocol<2> for MultiChannelAdcProtocol {
+            fn read_channels(&mut self, oversampling: u16, hardware_oversampling: bool) -> [f32; 2];
+            fn reference_voltage(&mut self) -> Option<f32>;
+        }
+    }
+
     #[cfg(feature = "dac")]
     mock! {
         // DacProtocol requires the number of channels (2) and the number of samples (1)
@@ -244,4 +359,63 @@ mod test_utils {
             fn write(&mut self, value: &[[u16; 1]; 2]);
         }
     }
+
+    #[cfg(feature = "dac")]
+    mock! {
+        // DacStreamProtocol requires the number of channels (2) and the buffer size (4)
+        pub DacStreamProtocol {}
+        impl DacStreamProtocol<2, 4> for DacStreamProtocol {
+            fn set_sample_rate(&mut self, sample_rate_hz: f32);
+            fn write_buffer(&mut self, samples: &[[u16; 4]; 2], len: usize) -> bool;
+            fn underruns(&mut self) -> u32;
+        }
+    }
+
+    #[cfg(feature = "modbus")]
+    mock! {
+        pub ModbusProtocol {}
+        impl ModbusProtocol for ModbusProtocol {
+            type Error = ();
+
+            fn set_timeout(&mut self, timeout: core::time::Duration);
+            fn transaction(
+                &mut self,
+                unit_id: u8,
+                request: &[u8],
+                response: &mut [u8],
+            ) -> Result<usize, ModbusError<()>>;
+        }
+    }
+
+    #[cfg(any(feature = "can", feature = "fdcan"))]
+    mock! {
+        pub CanStatusProtocol {}
+        impl CanStatusProtocol for CanStatusProtocol {
+            fn bus_status(&mut self) -> can::CanBusStatus;
+        }
+    }
+
+    #[cfg(feature = "capture")]
+    mock! {
+        pub EdgeCounterProtocol {}
+        impl EdgeCounterProtocol for EdgeCounterProtocol {
+            fn read_edges(&mut self) -> capture::EdgeCount;
+        }
+    }
+
+    #[cfg(feature = "capture")]
+    mock! {
+        pub QuadratureProtocol {}
+        impl QuadratureProtocol for QuadratureProtocol {
+            fn read_position(&mut self) -> capture::QuadratureCount;
+        }
+    }
+
+    #[cfg(feature = "capture")]
+    mock! {
+        pub PulseCaptureProtocol {}
+        impl PulseCaptureProtocol for PulseCaptureProtocol {
+            fn read_pulse(&mut self) -> Option<capture::PulseMeasurement>;
+        }
+    }
 }
@@ -0,0 +1,506 @@
+//! Modbus master (client) protocol
+//!
+//! Implementations only need to provide [`ModbusProtocol::transaction`], which exchanges a
+//! request PDU for a response PDU over their transport. The read and write functions are built
+//! on top of it. Helpers for the RTU and TCP framing of PDUs are also provided here so they can
+//! be shared between platforms.
+use core::time::Duration;
+
+/// Largest possible Modbus PDU (function code and data)
+pub const MAX_PDU_BYTES: usize = 253;
+/// Largest possible Modbus RTU frame (unit id, PDU and CRC)
+pub const MAX_RTU_FRAME_BYTES: usize = MAX_PDU_BYTES + 3;
+/// Size of the Modbus TCP MBAP header, including the unit id
+pub const MBAP_HEADER_BYTES: usize = 7;
+
+/// Maximum number of coils or discrete inputs in one read request
+pub const MAX_READ_BITS: usize = 2000;
+/// Maximum number of registers in one read request
+pub const MAX_READ_REGISTERS: usize = 125;
+/// Maximum number of coils in one write request
+pub const MAX_WRITE_BITS: usize = 1968;
+/// Maximum number of registers in one write request
+pub const MAX_WRITE_REGISTERS: usize = 123;
+
+pub const READ_COILS: u8 = 0x01;
+pub const READ_DISCRETE_INPUTS: u8 = 0x02;
+pub const READ_HOLDING_REGISTERS: u8 = 0x03;
+pub const READ_INPUT_REGISTERS: u8 = 0x04;
+pub const WRITE_SINGLE_COIL: u8 = 0x05;
+pub const WRITE_SINGLE_REGISTER: u8 = 0x06;
+pub const WRITE_MULTIPLE_COILS: u8 = 0x0F;
+pub const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+
+const EXCEPTION_FLAG: u8 = 0x80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModbusError<E> {
+    /// The transport failed
+    Io(E),
+    /// No complete response was received in time
+    Timeout,
+    /// The device responded with this exception code
+    Exception(u8),
+    /// The response was malformed or did not match the request
+    InvalidResponse,
+    /// The RTU frame checksum did not match
+    Checksum,
+    /// The request does not fit in a single Modbus PDU
+    InvalidRequest,
+}
+
+pub trait ModbusProtocol {
+    type Error: core::fmt::Debug;
+
+    /// Sets how long to wait for a response to each request
+    fn set_timeout(&mut self, timeout: Duration);
+
+    /// Sends the request PDU to the unit and copies the response PDU into `response`, returning
+    /// its length. Exception responses are returned like any other response.
+    fn transaction(
+        &mut self,
+        unit_id: u8,
+        request: &[u8],
+        response: &mut [u8],
+    ) -> Result<usize, ModbusError<Self::Error>>;
+
+    /// Reads `quantity` bits or registers with one of the read function codes, copying the
+    /// response data into `dest` and returning its length. The data is left as it is on the
+    /// wire: registers are big-endian and bits are packed least significant bit first.
+    fn read_raw(
+        &mut self,
+        unit_id: u8,
+        function: u8,
+        address: u16,
+        quantity: usize,
+        dest: &mut [u8],
+    ) -> Result<usize, ModbusError<Self::Error>> {
+        let (max_quantity, byte_count) = match function {
+            READ_COILS | READ_DISCRETE_INPUTS => (MAX_READ_BITS, quantity.div_ceil(8)),
+            READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS => (MAX_READ_REGISTERS, quantity * 2),
+            _ => return Err(ModbusError::InvalidRequest),
+        };
+        if quantity == 0 || quantity > max_quantity || dest.len() < byte_count {
+            return Err(ModbusError::InvalidRequest);
+        }
+
+        let mut request = [0u8; 5];
+        encode_header(&mut request, function, address, quantity as u16);
+        let mut response = [0u8; MAX_PDU_BYTES];
+        let len = self.transaction(unit_id, &request, &mut response)?;
+        match check_response(function, &response[..len])? {
+            [count, data @ ..] if *count as usize == byte_count && data.len() >= byte_count => {
+                dest[..byte_count].copy_from_slice(&data[..byte_count]);
+                Ok(byte_count)
+            }
+            _ => Err(ModbusError::InvalidResponse),
+        }
+    }
+
+    /// Writes `quantity` coils or registers from data laid out as on the wire, see
+    /// [`ModbusProtocol::read_raw`]. `function` is either [`WRITE_MULTIPLE_COILS`] or
+    /// [`WRITE_MULTIPLE_REGISTERS`], and the single write function is used instead when
+    /// `quantity` is 1.
+    fn write_raw(
+        &mut self,
+        unit_id: u8,
+        function: u8,
+        address: u16,
+        quantity: usize,
+        data: &[u8],
+    ) -> Result<(), ModbusError<Self::Error>> {
+        let (max_quantity, byte_count) = match function {
+            WRITE_MULTIPLE_COILS => (MAX_WRITE_BITS, quantity.div_ceil(8)),
+            WRITE_MULTIPLE_REGISTERS => (MAX_WRITE_REGISTERS, quantity * 2),
+            _ => return Err(ModbusError::InvalidRequest),
+        };
+        if quantity == 0 || quantity > max_quantity || data.len() < byte_count {
+            return Err(ModbusError::InvalidRequest);
+        }
+
+        let mut request = [0u8; MAX_PDU_BYTES];
+        let len = match (function, quantity) {
+            (WRITE_MULTIPLE_COILS, 1) => {
+                let value: u16 = if data[0] & 1 != 0 { 0xFF00 } else { 0x0000 };
+                encode_header(&mut request, WRITE_SINGLE_COIL, address, value)
+            }
+            (WRITE_MULTIPLE_REGISTERS, 1) => {
+                let value = u16::from_be_bytes([data[0], data[1]]);
+                encode_header(&mut request, WRITE_SINGLE_REGISTER, address, value)
+            }
+            _ => {
+                let len = encode_header(&mut request, function, address, quantity as u16);
+                request[len] = byte_count as u8;
+                request[len + 1..len + 1 + byte_count].copy_from_slice(&data[..byte_count]);
+                len + 1 + byte_count
+            }
+        };
+
+        // Write responses echo the address and quantity or value
+        let mut response = [0u8; MAX_PDU_BYTES];
+        let response_len = self.transaction(unit_id, &request[..len], &mut response)?;
+        let echo = check_response(request[0], &response[..response_len])?;
+        if echo != &request[1..5] {
+            return Err(ModbusError::InvalidResponse);
+        }
+        Ok(())
+    }
+
+    fn read_coils(
+        &mut self,
+        unit_id: u8,
+        address: u16,
+        dest: &mut [bool],
+    ) -> Result<(), ModbusError<Self::Error>> {
+        let mut data = [0u8; MAX_READ_BITS.div_ceil(8)];
+        self.read_raw(unit_id, READ_COILS, address, dest.len(), &mut data)?;
+        unpack_bits(&data, dest);
+        Ok(())
+    }
+
+    fn read_discrete_inputs(
+        &mut self,
+        unit_id: u8,
+        address: u16,
+        dest: &mut [bool],
+    ) -> Result<(), ModbusError<Self::Error>> {
+        let mut data = [0u8; MAX_READ_BITS.div_ceil(8)];
+        self.read_raw(
+            unit_id,
+            READ_DISCRETE_INPUTS,
+            address,
+            dest.len(),
+            &mut data,
+        )?;
+        unpack_bits(&data, dest);
+        Ok(())
+    }
+
+    fn read_holding_registers(
+        &mut self,
+        unit_id: u8,
+        address: u16,
+        dest: &mut [u16],
+    ) -> Result<(), ModbusError<Self::Error>> {
+        let mut data = [0u8; MAX_READ_REGISTERS * 2];
+        self.read_raw(
+            unit_id,
+            READ_HOLDING_REGISTERS,
+            address,
+            dest.len(),
+            &mut data,
+        )?;
+        unpack_registers(&data, dest);
+        Ok(())
+    }
+
+    fn read_input_registers(
+        &mut self,
+        unit_id: u8,
+        address: u16,
+        dest: &mut [u16],
+    ) -> Result<(), ModbusError<Self::Error>> {
+        let mut data = [0u8; MAX_READ_REGISTERS * 2];
+        self.read_raw(
+            unit_id,
+            READ_INPUT_REGISTERS,
+            address,
+            dest.len(),
+            &mut data,
+        )?;
+        unpack_registers(&data, dest);
+        Ok(())
+    }
+
+    fn write_coils(
+        &mut self,
+        unit_id: u8,
+        address: u16,
+        values: &[bool],
+    ) -> Result<(), ModbusError<Self::Error>> {
+        let mut data = [0u8; MAX_WRITE_BITS.div_ceil(8)];
+        for (i, _) in values.iter().enumerate().filter(|(_, v)| **v) {
+            if let Some(byte) = data.get_mut(i / 8) {
+                *byte |= 1 << (i % 8);
+            }
+        }
+        self.write_raw(unit_id, WRITE_MULTIPLE_COILS, address, values.len(), &data)
+    }
+
+    fn write_registers(
+        &mut self,
+        unit_id: u8,
+        address: u16,
+        values: &[u16],
+    ) -> Result<(), ModbusError<Self::Error>> {
+        let mut data = [0u8; MAX_WRITE_REGISTERS * 2];
+        for (bytes, value) in data.chunks_exact_mut(2).zip(values) {
+            bytes.copy_from_slice(&value.to_be_bytes());
+        }
+        self.write_raw(
+            unit_id,
+            WRITE_MULTIPLE_REGISTERS,
+            address,
+            values.len(),
+            &data,
+        )
+    }
+}
+
+/// Writes a function code followed by two big-endian words, returning the length
+fn encode_header(dest: &mut [u8], function: u8, first: u16, second: u16) -> usize {
+    dest[0] = function;
+    dest[1..3].copy_from_slice(&first.to_be_bytes());
+    dest[3..5].copy_from_slice(&second.to_be_bytes());
+    5
+}
+
+/// Checks the function code of a response, returning the response data
+fn check_response<E>(function: u8, response: &[u8]) -> Result<&[u8], ModbusError<E>> {
+    match response {
+        [code, exception, ..] if *code == function | EXCEPTION_FLAG => {
+            Err(ModbusError::Exception(*exception))
+        }
+        [code, data @ ..] if *code == function => Ok(data),
+        _ => Err(ModbusError::InvalidResponse),
+    }
+}
+
+fn unpack_bits(data: &[u8], dest: &mut [bool]) {
+    for (i, bit) in dest.iter_mut().enumerate() {
+        *bit = data[i / 8] & (1 << (i % 8)) != 0;
+    }
+}
+
+fn unpack_registers(data: &[u8], dest: &mut [u16]) {
+    for (value, bytes) in dest.iter_mut().zip(data.chunks_exact(2)) {
+        *value = u16::from_be_bytes([bytes[0], bytes[1]]);
+    }
+}
+
+/// CRC-16/MODBUS of `data`
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for byte in data {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+/// Writes the RTU frame for a request PDU into `dest`, returning the frame length
+pub fn encode_rtu_frame(unit_id: u8, pdu: &[u8], dest: &mut [u8]) -> usize {
+    let len = pdu.len() + 1;
+    dest[0] = unit_id;
+    dest[1..len].copy_from_slice(pdu);
+    let crc = crc16(&dest[..len]);
+    dest[len..len + 2].copy_from_slice(&crc.to_le_bytes());
+    len + 2
+}
+
+/// Given the first three bytes of an RTU response (unit id, function code and the first data
+/// byte), returns how many more bytes make up the frame
+pub fn rtu_response_remaining(head: &[u8; 3]) -> usize {
+    match head[1] {
+        code if code & EXCEPTION_FLAG != 0 => 2,
+        READ_COILS | READ_DISCRETE_INPUTS | READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS => {
+            head[2] as usize + 2
+        }
+        // Write responses echo a 4 byte address and quantity or value
+        _ => 5,
+    }
+}
+
+/// Checks an RTU response frame, returning its PDU
+pub fn decode_rtu_frame<E>(unit_id: u8, frame: &[u8]) -> Result<&[u8], ModbusError<E>> {
+    if frame.len() < 4 {
+        return Err(ModbusError::InvalidResponse);
+    }
+    let (body, crc) = frame.split_at(frame.len() - 2);
+    if crc16(body).to_le_bytes() != crc {
+        return Err(ModbusError::Checksum);
+    }
+    if body[0] != unit_id {
+        return Err(ModbusError::InvalidResponse);
+    }
+    Ok(&body[1..])
+}
+
+/// Writes the MBAP header for a request PDU into `dest`. The PDU should follow the header.
+pub fn encode_mbap_header(transaction_id: u16, unit_id: u8, pdu_len: usize, dest: &mut [u8]) {
+    dest[0..2].copy_from_slice(&transaction_id.to_be_bytes());
+    // Protocol id is always 0 for Modbus
+    dest[2..4].copy_from_slice(&[0, 0]);
+    dest[4..6].copy_from_slice(&((pdu_len + 1) as u16).to_be_bytes());
+    dest[6] = unit_id;
+}
+
+/// Parses a response MBAP header, returning the transaction id and the PDU length that follows
+pub fn decode_mbap_header<E>(
+    header: &[u8; MBAP_HEADER_BYTES],
+) -> Result<(u16, usize), ModbusError<E>> {
+    let transaction_id = u16::from_be_bytes([header[0], header[1]]);
+    let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+    if header[2..4] != [0, 0] || !(2..=MAX_PDU_BYTES + 1).contains(&length) {
+        return Err(ModbusError::InvalidResponse);
+    }
+    Ok((transaction_id, length - 1))
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    extern crate std;
+    use std::vec::Vec;
+
+    /// Replies to each request with a canned response, recording the requests
+    struct Canned {
+        response: Vec<u8>,
+        requests: Vec<Vec<u8>>,
+    }
+
+    impl Canned {
+        fn new(response: &[u8]) -> Self {
+            Self {
+                response: response.to_vec(),
+                requests: Vec::new(),
+            }
+        }
+    }
+
+    impl ModbusProtocol for Canned {
+        type Error = ();
+
+        fn set_timeout(&mut self, _timeout: Duration) {}
+
+        fn transaction(
+            &mut self,
+            _unit_id: u8,
+            request: &[u8],
+            response: &mut [u8],
+        ) -> Result<usize, ModbusError<()>> {
+            self.requests.push(request.to_vec());
+            response[..self.response.len()].copy_from_slice(&self.response);
+            Ok(self.response.len())
+        }
+    }
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(crc16(b"123456789"), 0x4B37);
+        // Read 2 holding registers from unit 1 at address 0
+        let mut frame = [0u8; 8];
+        encode_rtu_frame(1, &[0x03, 0x00, 0x00, 0x00, 0x02], &mut frame);
+        assert_eq!(frame, [0x01, 0x03, 0x00, 0x00, 0x00, 0x02, 0xC4, 0x0B]);
+    }
+
+    #[test]
+    fn test_read_registers() {
+        let mut protocol = Canned::new(&[0x03, 0x04, 0x12, 0x34, 0xAB, 0xCD]);
+        let mut dest = [0u16; 2];
+        protocol
+            .read_holding_registers(1, 0x0010, &mut dest)
+            .unwrap();
+        assert_eq!(dest, [0x1234, 0xABCD]);
+        assert_eq!(protocol.requests[0], [0x03, 0x00, 0x10, 0x00, 0x02]);
+
+        // Wrong byte count
+        let mut dest = [0u16; 3];
+        assert_eq!(
+            protocol.read_holding_registers(1, 0x0010, &mut dest),
+            Err(ModbusError::InvalidResponse)
+        );
+
+        let mut protocol = Canned::new(&[0x84, 0x02]);
+        assert_eq!(
+            protocol.read_input_registers(1, 0x0010, &mut dest),
+            Err(ModbusError::Exception(0x02))
+        );
+    }
+
+    #[test]
+    fn test_read_bits() {
+        let mut protocol = Canned::new(&[0x01, 0x02, 0b1000_0101, 0b0000_0001]);
+        let mut dest = [false; 9];
+        protocol.read_coils(1, 0, &mut dest).unwrap();
+        assert_eq!(
+            dest,
+            [true, false, true, false, false, false, false, true, true]
+        );
+    }
+
+    #[test]
+    fn test_write_requests() {
+        let mut protocol = Canned::new(&[0x05, 0x00, 0x07, 0xFF, 0x00]);
+        protocol.write_coils(1, 7, &[true]).unwrap();
+        assert_eq!(protocol.requests[0], [0x05, 0x00, 0x07, 0xFF, 0x00]);
+
+        let mut protocol = Canned::new(&[0x0F, 0x00, 0x00, 0x00, 0x0A]);
+        let coils = [
+            true, false, true, true, false, false, true, true, true, false,
+        ];
+        protocol.write_coils(1, 0, &coils).unwrap();
+        assert_eq!(
+            protocol.requests[0],
+            [0x0F, 0x00, 0x00, 0x00, 0x0A, 0x02, 0xCD, 0x01]
+        );
+
+        let mut protocol = Canned::new(&[0x10, 0x00, 0x01, 0x00, 0x02]);
+        protocol.write_registers(1, 1, &[0x000A, 0x0102]).unwrap();
+        assert_eq!(
+            protocol.requests[0],
+            [0x10, 0x00, 0x01, 0x00, 0x02, 0x04, 0x00, 0x0A, 0x01, 0x02]
+        );
+        // The echoed address doesn't match
+        assert_eq!(
+            protocol.write_registers(1, 2, &[0x000A, 0x0102]),
+            Err(ModbusError::InvalidResponse)
+        );
+        assert_eq!(
+            protocol.write_registers(1, 2, &[]),
+            Err(ModbusError::InvalidRequest)
+        );
+    }
+
+    #[test]
+    fn test_rtu_framing() {
+        let frame = [0x01, 0x03, 0x02, 0x00, 0x2A];
+        let crc = crc16(&frame).to_le_bytes();
+        let mut full = frame.to_vec();
+        full.extend_from_slice(&crc);
+
+        assert_eq!(rtu_response_remaining(&[0x01, 0x03, 0x02]), 4);
+        assert_eq!(rtu_response_remaining(&[0x01, 0x83, 0x02]), 2);
+        assert_eq!(rtu_response_remaining(&[0x01, 0x10, 0x00]), 5);
+        assert_eq!(
+            decode_rtu_frame::<()>(1, &full),
+            Ok([0x03, 0x02, 0x00, 0x2A].as_slice())
+        );
+        assert_eq!(
+            decode_rtu_frame::<()>(2, &full),
+            Err(ModbusError::InvalidResponse)
+        );
+        full[3] = 0xFF;
+        assert_eq!(decode_rtu_frame::<()>(1, &full), Err(ModbusError::Checksum));
+    }
+
+    #[test]
+    fn test_mbap_header() {
+        let mut header = [0u8; MBAP_HEADER_BYTES];
+        encode_mbap_header(0x1234, 7, 5, &mut header);
+        assert_eq!(header, [0x12, 0x34, 0x00, 0x00, 0x00, 0x06, 0x07]);
+        assert_eq!(decode_mbap_header::<()>(&header), Ok((0x1234, 5)));
+
+        header[3] = 1;
+        assert_eq!(
+            decode_mbap_header::<()>(&header),
+            Err(ModbusError::InvalidResponse)
+        );
+    }
+}
@@ -0,0 +1,137 @@
+extern crate alloc;
+use alloc::vec::Vec;
+use core::time::Duration;
+use corelib_traits::{ByteSliceSignal, Context, InputBlock, OutputBlock, PassBy};
+use log::warn;
+use pictorus_core_blocks::{ModbusReadBlockParams, ModbusWriteBlockParams};
+
+use crate::ModbusProtocol;
+
+/// (unit, function, address, quantity, poll period) of a read
+type ReadKey = (u8, u8, u16, usize, Duration);
+
+struct Poll {
+    key: ReadKey,
+    time: Duration,
+    data: Vec<u8>,
+}
+
+/// Runs the Modbus Read and Write blocks against a platform's Modbus connection. One wrapper
+/// can be shared by several blocks; each read is polled on its own schedule, and blocks
+/// making the same read on the same schedule share each response.
+pub struct ModbusWrapper<P: ModbusProtocol> {
+    pub protocol: P,
+    polls: Vec<Poll>,
+}
+
+impl<P: ModbusProtocol> ModbusWrapper<P> {
+    pub fn new(protocol: P) -> Self {
+        Self {
+            protocol,
+            polls: Vec::new(),
+        }
+    }
+}
+
+impl<P: ModbusProtocol + Default> Default for ModbusWrapper<P> {
+    fn default() -> Self {
+        Self::new(P::default())
+    }
+}
+
+impl<P: ModbusProtocol> InputBlock for ModbusWrapper<P> {
+    type Output = ByteSliceSignal;
+    type Parameters = ModbusReadBlockParams;
+
+    fn input(
+        &mut self,
+        parameters: &Self::Parameters,
+        context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        let layout = &parameters.layout;
+        let function = layout.table.read_function();
+        let key = (
+            parameters.unit_id,
+            function,
+            parameters.address,
+            layout.quantity(),
+            parameters.poll_period,
+        );
+        let now = context.time();
+        let poll = match self.polls.iter().position(|poll| poll.key == key) {
+            Some(i) => {
+                let poll = &mut self.polls[i];
+                // Another block already made this read this tick
+                if poll.time == now {
+                    return &poll.data;
+                }
+                if !parameters.should_poll(Some(poll.time), now) {
+                    return &[];
+                }
+                poll.time = now;
+                poll
+            }
+            None => {
+                self.polls.push(Poll {
+                    key,
+                    time: now,
+                    data: Vec::new(),
+                });
+                self.polls.last_mut().unwrap()
+            }
+        };
+
+        self.protocol.set_timeout(parameters.timeout);
+        poll.data.resize(layout.byte_count(), 0);
+        let result = self.protocol.read_raw(
+            parameters.unit_id,
+            function,
+            parameters.address,
+            layout.quantity(),
+            &mut poll.data,
+        );
+        if let Err(err) = result {
+            warn!(
+                "Modbus read from unit {} failed: {:?}",
+                parameters.unit_id, err
+            );
+            poll.data.clear();
+        }
+        &poll.data
+    }
+}
+
+impl<P: ModbusProtocol> OutputBlock for ModbusWrapper<P> {
+    type Inputs = ByteSliceSignal;
+    type Parameters = ModbusWriteBlockParams;
+
+    fn output(
+        &mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn Context,
+        inputs: PassBy<'_, Self::Inputs>,
+    ) {
+        let Some(function) = parameters.layout.table.write_function() else {
+            return;
+        };
+        // The write block only outputs data when a write is due
+        if inputs.is_empty() {
+            return;
+        }
+
+        self.protocol.set_timeout(parameters.timeout);
+        let result = self.protocol.write_raw(
+            parameters.unit_id,
+            function,
+            parameters.address,
+            parameters.layout.quantity(),
+            inputs,
+        );
+        if let Err(err) = result {
+            warn!(
+                "Modbus write to unit {} failed: {:?}",
+                parameters.unit_id, err
+            );
+        }
+    }
+}
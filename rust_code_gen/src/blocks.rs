@@ -29,6 +29,12 @@ pub use can_transmit_block::*;
 // Declare all block modules and re-export them to make them importable from crate::blocks
 cfg_if::cfg_if! {
   if #[cfg(feature = "std")] {
+    mod character_display_block;
+    pub use character_display_block::*;
+
+    mod lib_cam_block;
+    pub use lib_cam_block::*;
+
     #[cfg(target_arch = "x86_64")]
     mod fmu_block;
     #[cfg(target_arch = "x86_64")]
@@ -6,6 +6,8 @@ extern crate alloc;
 pub mod loggers;
 pub use utils::block_data;
 pub mod blocks;
+#[cfg(feature = "std")]
+pub mod thread_manager;
 pub use utils;
 
 #[cfg(all(test, feature = "std"))]
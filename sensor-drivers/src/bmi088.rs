@@ -0,0 +1,439 @@
+//! Bosch BMI088 6-axis IMU. The accelerometer and gyro are separate dies with their own
+//! register maps and addresses, so the driver takes a bus for each. Over SPI, the
+//! accelerometer sends a dummy byte before the register contents, so its bus should be
+//! created with [`crate::SpiRegisters::with_dummy_bytes`].
+use alloc::vec;
+use core::f32::consts::PI;
+use embedded_hal::delay::DelayNs;
+
+use crate::{
+    select, vector_le, Imu, ImuConfig, ImuSample, RegisterBus, SensorError, STANDARD_GRAVITY,
+};
+
+/// Accelerometer I2C address with SDO1 low. It is 0x19 with SDO1 high.
+pub const ACCEL_DEFAULT_ADDRESS: u8 = 0x18;
+/// Gyro I2C address with SDO2 low. It is 0x69 with SDO2 high.
+pub const GYRO_DEFAULT_ADDRESS: u8 = 0x68;
+
+// Accelerometer registers
+const ACC_CHIP_ID: u8 = 0x00;
+const ACC_X_LSB: u8 = 0x12;
+const TEMP_MSB: u8 = 0x22;
+const ACC_FIFO_LENGTH_0: u8 = 0x24;
+const ACC_FIFO_DATA: u8 = 0x26;
+const ACC_CONF: u8 = 0x40;
+const ACC_RANGE: u8 = 0x41;
+const ACC_FIFO_CONFIG_0: u8 = 0x48;
+const ACC_FIFO_CONFIG_1: u8 = 0x49;
+const ACC_SELF_TEST: u8 = 0x6D;
+const ACC_PWR_CONF: u8 = 0x7C;
+const ACC_PWR_CTRL: u8 = 0x7D;
+const ACC_SOFTRESET: u8 = 0x7E;
+// Gyro registers
+const GYRO_CHIP_ID: u8 = 0x00;
+const RATE_X_LSB: u8 = 0x02;
+const GYRO_FIFO_STATUS: u8 = 0x0E;
+const GYRO_RANGE: u8 = 0x0F;
+const GYRO_BANDWIDTH: u8 = 0x10;
+const GYRO_LPM1: u8 = 0x11;
+const GYRO_SOFTRESET: u8 = 0x14;
+const GYRO_SELF_TEST: u8 = 0x3C;
+const GYRO_FIFO_CONFIG_1: u8 = 0x3E;
+const GYRO_FIFO_DATA: u8 = 0x3F;
+
+const ACC_CHIP_ID_VALUE: u8 = 0x1E;
+const GYRO_CHIP_ID_VALUE: u8 = 0x0F;
+const SOFT_RESET: u8 = 0xB6;
+const ACC_ACTIVE: u8 = 0x00;
+const ACC_ENABLE: u8 = 0x04;
+const ACC_BWP_NORMAL: u8 = 0xA0;
+const ACC_FIFO_STREAM: u8 = 0x02;
+const ACC_FIFO_ACCEL: u8 = 0x50;
+const GYRO_NORMAL: u8 = 0x00;
+const GYRO_FIFO_STREAM: u8 = 0x80;
+const GYRO_FIFO_FRAME_BYTES: usize = 6;
+/// Accelerometer FIFO frames are a header byte and the 6 data bytes
+const ACC_FIFO_FRAME_BYTES: usize = 7;
+const ACC_FIFO_HEADER_ACCEL: u8 = 0x84;
+const ACC_FIFO_HEADER_SKIP: u8 = 0x40;
+const ACC_FIFO_HEADER_SENSOR_TIME: u8 = 0x44;
+const ACC_FIFO_HEADER_CONFIG: u8 = 0x48;
+const ACC_FIFO_HEADER_DROP: u8 = 0x50;
+const ACC_SELF_TEST_POSITIVE: u8 = 0x0D;
+const ACC_SELF_TEST_NEGATIVE: u8 = 0x09;
+const GYRO_BIST_TRIGGER: u8 = 0x01;
+const GYRO_BIST_READY: u8 = 0x02;
+const GYRO_BIST_FAIL: u8 = 0x04;
+
+/// Full scale in g and the ACC_RANGE value
+const ACCEL_RANGES: [(f32, u8); 4] = [(3.0, 0), (6.0, 1), (12.0, 2), (24.0, 3)];
+/// Full scale in dps and the GYRO_RANGE value
+const GYRO_RANGES: [(f32, u8); 5] = [(125.0, 4), (250.0, 3), (500.0, 2), (1000.0, 1), (2000.0, 0)];
+/// Output data rate in Hz and the ACC_CONF ODR value
+const ACCEL_RATES: [(f32, u8); 8] = [
+    (12.5, 0x05),
+    (25.0, 0x06),
+    (50.0, 0x07),
+    (100.0, 0x08),
+    (200.0, 0x09),
+    (400.0, 0x0A),
+    (800.0, 0x0B),
+    (1600.0, 0x0C),
+];
+/// Output data rate in Hz and the GYRO_BANDWIDTH value with the widest filter for that rate
+const GYRO_RATES: [(f32, u8); 5] = [
+    (100.0, 0x07),
+    (200.0, 0x06),
+    (400.0, 0x03),
+    (1000.0, 0x02),
+    (2000.0, 0x01),
+];
+
+/// The accelerometer self-test runs at ±24 g and 1600 Hz, and the difference between the
+/// positive and negative excitation must be at least these values
+const SELF_TEST_RANGE: u8 = 3;
+const SELF_TEST_ODR: u8 = 0x0C;
+const SELF_TEST_MIN_G: [f32; 3] = [1.0, 1.0, 0.5];
+
+pub struct Bmi088<A, G> {
+    accel: A,
+    gyro: G,
+    config: ImuConfig,
+    accel_scale: f32,
+    gyro_scale: f32,
+}
+
+impl<A, G> Bmi088<A, G>
+where
+    A: RegisterBus,
+    G: RegisterBus<Error = A::Error>,
+{
+    pub fn new(accel: A, gyro: G) -> Self {
+        Self {
+            accel,
+            gyro,
+            config: ImuConfig::default(),
+            accel_scale: 0.0,
+            gyro_scale: 0.0,
+        }
+    }
+
+    pub fn release(self) -> (A, G) {
+        (self.accel, self.gyro)
+    }
+
+    fn init_accel<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), SensorError<A::Error>> {
+        // Over SPI the first read after power up or reset switches the accelerometer to SPI
+        // mode and returns garbage
+        self.accel.read_register(ACC_CHIP_ID)?;
+        let id = self.accel.read_register(ACC_CHIP_ID)?;
+        if id != ACC_CHIP_ID_VALUE {
+            return Err(SensorError::UnexpectedId(id));
+        }
+
+        self.accel.write_register(ACC_SOFTRESET, SOFT_RESET)?;
+        delay.delay_ms(1);
+        self.accel.read_register(ACC_CHIP_ID)?;
+        self.accel.write_register(ACC_PWR_CONF, ACC_ACTIVE)?;
+        delay.delay_ms(1);
+        self.accel.write_register(ACC_PWR_CTRL, ACC_ENABLE)?;
+        delay.delay_ms(5);
+        self.configure_accel()
+    }
+
+    fn configure_accel(&mut self) -> Result<(), SensorError<A::Error>> {
+        let config = self.config;
+        let (range, range_value) = select(&ACCEL_RANGES, config.accel_range_g);
+        let (_, odr) = select(&ACCEL_RATES, self.gyro_rate());
+        self.accel.write_register(ACC_RANGE, range_value)?;
+        self.accel.write_register(ACC_CONF, ACC_BWP_NORMAL | odr)?;
+        self.accel_scale = range * STANDARD_GRAVITY / 32768.0;
+
+        if config.fifo {
+            self.accel
+                .write_register(ACC_FIFO_CONFIG_0, ACC_FIFO_STREAM)?;
+            self.accel
+                .write_register(ACC_FIFO_CONFIG_1, ACC_FIFO_ACCEL)?;
+        }
+        Ok(())
+    }
+
+    fn init_gyro<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), SensorError<A::Error>> {
+        let id = self.gyro.read_register(GYRO_CHIP_ID)?;
+        if id != GYRO_CHIP_ID_VALUE {
+            return Err(SensorError::UnexpectedId(id));
+        }
+
+        self.gyro.write_register(GYRO_SOFTRESET, SOFT_RESET)?;
+        delay.delay_ms(30);
+        self.gyro.write_register(GYRO_LPM1, GYRO_NORMAL)?;
+
+        let (range, range_value) = select(&GYRO_RANGES, self.config.gyro_range_dps);
+        let (_, bandwidth) = select(&GYRO_RATES, self.config.sample_rate_hz);
+        self.gyro.write_register(GYRO_RANGE, range_value)?;
+        self.gyro.write_register(GYRO_BANDWIDTH, bandwidth)?;
+        self.gyro_scale = range * PI / 180.0 / 32768.0;
+
+        if self.config.fifo {
+            self.gyro
+                .write_register(GYRO_FIFO_CONFIG_1, GYRO_FIFO_STREAM)?;
+        }
+        Ok(())
+    }
+
+    /// The accelerometer runs at the gyro rate, so their FIFO frames pair up
+    fn gyro_rate(&self) -> f32 {
+        select(&GYRO_RATES, self.config.sample_rate_hz).0
+    }
+
+    fn read_temperature(&mut self) -> Result<f32, SensorError<A::Error>> {
+        let mut data = [0; 2];
+        self.accel.read_registers(TEMP_MSB, &mut data)?;
+        // 11-bit two's complement value in the MSB and the top 3 bits of the LSB
+        let raw = ((data[0] as i16) << 3 | (data[1] >> 5) as i16) << 5 >> 5;
+        Ok(raw as f32 * 0.125 + 23.0)
+    }
+
+    fn read_accel_g(&mut self) -> Result<[f32; 3], SensorError<A::Error>> {
+        let mut data = [0; 6];
+        self.accel.read_registers(ACC_X_LSB, &mut data)?;
+        Ok(vector_le(&data, 24.0 / 32768.0))
+    }
+
+    fn accel_self_test<D: DelayNs>(
+        &mut self,
+        delay: &mut D,
+    ) -> Result<bool, SensorError<A::Error>> {
+        self.accel.write_register(ACC_RANGE, SELF_TEST_RANGE)?;
+        self.accel
+            .write_register(ACC_CONF, ACC_BWP_NORMAL | SELF_TEST_ODR)?;
+        delay.delay_ms(2);
+        self.accel
+            .write_register(ACC_SELF_TEST, ACC_SELF_TEST_POSITIVE)?;
+        delay.delay_ms(50);
+        let positive = self.read_accel_g()?;
+        self.accel
+            .write_register(ACC_SELF_TEST, ACC_SELF_TEST_NEGATIVE)?;
+        delay.delay_ms(50);
+        let negative = self.read_accel_g()?;
+        self.accel.write_register(ACC_SELF_TEST, 0)?;
+        delay.delay_ms(50);
+
+        Ok((0..3).all(|i| positive[i] - negative[i] >= SELF_TEST_MIN_G[i]))
+    }
+
+    fn gyro_self_test<D: DelayNs>(&mut self, delay: &mut D) -> Result<bool, SensorError<A::Error>> {
+        self.gyro
+            .write_register(GYRO_SELF_TEST, GYRO_BIST_TRIGGER)?;
+        for _ in 0..10 {
+            delay.delay_ms(10);
+            let status = self.gyro.read_register(GYRO_SELF_TEST)?;
+            if status & GYRO_BIST_READY != 0 {
+                return Ok(status & GYRO_BIST_FAIL == 0);
+            }
+        }
+        Err(SensorError::Timeout)
+    }
+}
+
+impl<A, G> Imu for Bmi088<A, G>
+where
+    A: RegisterBus,
+    G: RegisterBus<Error = A::Error>,
+{
+    type Error = A::Error;
+
+    fn init<D: DelayNs>(
+        &mut self,
+        config: &ImuConfig,
+        delay: &mut D,
+    ) -> Result<(), SensorError<Self::Error>> {
+        self.config = *config;
+        self.init_accel(delay)?;
+        self.init_gyro(delay)
+    }
+
+    fn read(&mut self) -> Result<ImuSample, SensorError<Self::Error>> {
+        let mut accel = [0; 6];
+        self.accel.read_registers(ACC_X_LSB, &mut accel)?;
+        let mut gyro = [0; 6];
+        self.gyro.read_registers(RATE_X_LSB, &mut gyro)?;
+        Ok(ImuSample {
+            accel: vector_le(&accel, self.accel_scale),
+            gyro: vector_le(&gyro, self.gyro_scale),
+            temperature: self.read_temperature()?,
+        })
+    }
+
+    /// Reads the same number of accelerometer and gyro frames and pairs them up, which
+    /// assumes a sample rate of 100, 200 or 400 Hz so both run at the same rate. Frames cut
+    /// off at the end of a read are sent again by the sensor on the next read.
+    fn read_fifo(&mut self, samples: &mut [ImuSample]) -> Result<usize, SensorError<Self::Error>> {
+        if !self.config.fifo {
+            return Err(SensorError::NotSupported);
+        }
+
+        let mut length = [0; 2];
+        self.accel.read_registers(ACC_FIFO_LENGTH_0, &mut length)?;
+        let accel_frames = (u16::from_le_bytes(length) & 0x3FFF) as usize / ACC_FIFO_FRAME_BYTES;
+        let gyro_frames = (self.gyro.read_register(GYRO_FIFO_STATUS)? & 0x7F) as usize;
+        let count = accel_frames.min(gyro_frames).min(samples.len());
+        if count == 0 {
+            return Ok(0);
+        }
+
+        let temperature = self.read_temperature()?;
+        let mut accel = vec![0; count * ACC_FIFO_FRAME_BYTES];
+        self.accel.read_registers(ACC_FIFO_DATA, &mut accel)?;
+        let mut gyro = vec![0; count * GYRO_FIFO_FRAME_BYTES];
+        self.gyro.read_registers(GYRO_FIFO_DATA, &mut gyro)?;
+
+        let mut gyro_frames = gyro.chunks_exact(GYRO_FIFO_FRAME_BYTES);
+        let mut read = 0;
+        let mut index = 0;
+        while index < accel.len() {
+            let header = accel[index];
+            let payload_len = match header & 0xFC {
+                ACC_FIFO_HEADER_ACCEL => 6,
+                ACC_FIFO_HEADER_SKIP | ACC_FIFO_HEADER_CONFIG | ACC_FIFO_HEADER_DROP => 1,
+                ACC_FIFO_HEADER_SENSOR_TIME => 3,
+                // Anything else, such as the 0x80 returned by an empty FIFO, ends the data
+                _ => break,
+            };
+            let Some(payload) = accel.get(index + 1..index + 1 + payload_len) else {
+                break;
+            };
+            index += 1 + payload_len;
+            if header & 0xFC != ACC_FIFO_HEADER_ACCEL {
+                continue;
+            }
+            let Some(gyro) = gyro_frames.next() else {
+                break;
+            };
+            samples[read] = ImuSample {
+                accel: vector_le(payload, self.accel_scale),
+                gyro: vector_le(gyro, self.gyro_scale),
+                temperature,
+            };
+            read += 1;
+        }
+        Ok(read)
+    }
+
+    /// Runs the accelerometer self-test and the gyro built-in self-test, then resets the
+    /// accelerometer as the datasheet recommends
+    fn self_test<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), SensorError<Self::Error>> {
+        let accel_passed = self.accel_self_test(delay)?;
+        let gyro_passed = self.gyro_self_test(delay)?;
+        self.init_accel(delay)?;
+        if accel_passed && gyro_passed {
+            Ok(())
+        } else {
+            Err(SensorError::SelfTestFailed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{FakeRegisters, NoDelay};
+    use crate::{I2cRegisters, SharedI2c};
+    use approx::assert_relative_eq;
+
+    const ACCEL: u8 = ACCEL_DEFAULT_ADDRESS;
+    const GYRO: u8 = GYRO_DEFAULT_ADDRESS;
+
+    fn create(
+        registers: &FakeRegisters,
+    ) -> Bmi088<
+        impl RegisterBus<Error = embedded_hal::i2c::ErrorKind>,
+        impl RegisterBus<Error = embedded_hal::i2c::ErrorKind>,
+    > {
+        registers.set(ACCEL, ACC_CHIP_ID, &[ACC_CHIP_ID_VALUE]);
+        registers.set(GYRO, GYRO_CHIP_ID, &[GYRO_CHIP_ID_VALUE]);
+        let i2c = SharedI2c::new(registers.mock());
+        Bmi088::new(
+            I2cRegisters::new(i2c.clone(), ACCEL),
+            I2cRegisters::new(i2c, GYRO),
+        )
+    }
+
+    #[test]
+    fn test_bmi088_init_and_read() {
+        let registers = FakeRegisters::default();
+        // 1 g, -0.5 g, 0 at ±6 g, and 125 dps, 0, 0 at ±250 dps
+        registers.set(ACCEL, ACC_X_LSB, &[0x55, 0x15, 0x55, 0xF5, 0, 0]);
+        registers.set(GYRO, RATE_X_LSB, &[0x00, 0x40, 0, 0, 0, 0]);
+        // -1 °C
+        registers.set(ACCEL, TEMP_MSB, &[0xE8, 0x00]);
+        let mut imu = create(&registers);
+        let config = ImuConfig {
+            accel_range_g: 4.0,
+            gyro_range_dps: 200.0,
+            sample_rate_hz: 150.0,
+            fifo: false,
+        };
+        imu.init(&config, &mut NoDelay).unwrap();
+
+        assert_eq!(registers.writes(ACCEL, ACC_SOFTRESET), [SOFT_RESET]);
+        assert_eq!(registers.get(ACCEL, ACC_PWR_CTRL), ACC_ENABLE);
+        assert_eq!(registers.get(ACCEL, ACC_RANGE), 1);
+        assert_eq!(registers.get(ACCEL, ACC_CONF), ACC_BWP_NORMAL | 0x09);
+        assert_eq!(registers.get(GYRO, GYRO_RANGE), 3);
+        assert_eq!(registers.get(GYRO, GYRO_BANDWIDTH), 0x06);
+
+        let sample = imu.read().unwrap();
+        let g = STANDARD_GRAVITY;
+        for (actual, expected) in sample.accel.iter().zip([g, -0.5 * g, 0.0]) {
+            assert_relative_eq!(*actual, expected, epsilon = 1e-3);
+        }
+        assert_relative_eq!(sample.gyro[0], 125.0 * PI / 180.0, epsilon = 1e-4);
+        assert_relative_eq!(sample.temperature, -1.0);
+    }
+
+    #[test]
+    fn test_bmi088_fifo() {
+        let registers = FakeRegisters::default();
+        let mut imu = create(&registers);
+        let config = ImuConfig {
+            fifo: true,
+            sample_rate_hz: 100.0,
+            ..Default::default()
+        };
+        imu.init(&config, &mut NoDelay).unwrap();
+        assert_eq!(registers.get(ACCEL, ACC_FIFO_CONFIG_1), ACC_FIFO_ACCEL);
+        assert_eq!(registers.get(GYRO, GYRO_FIFO_CONFIG_1), GYRO_FIFO_STREAM);
+
+        // A skip frame followed by two accelerometer frames
+        registers.set(ACCEL, ACC_FIFO_LENGTH_0, &[16, 0]);
+        registers.queue_fifo(ACCEL, ACC_FIFO_DATA, &[ACC_FIFO_HEADER_SKIP, 1]);
+        registers.queue_fifo(ACCEL, ACC_FIFO_DATA, &[0x84, 1, 0, 0, 0, 0, 0]);
+        registers.queue_fifo(ACCEL, ACC_FIFO_DATA, &[0x84, 2, 0, 0, 0, 0, 0]);
+        registers.set(GYRO, GYRO_FIFO_STATUS, &[2]);
+        registers.queue_fifo(GYRO, GYRO_FIFO_DATA, &[0, 0, 1, 0, 0, 0, 0, 0, 2, 0, 0, 0]);
+
+        let mut samples = [ImuSample::default(); 4];
+        // The skip frame takes up room in the read, so the second frame is read next time
+        assert_eq!(imu.read_fifo(&mut samples).unwrap(), 1);
+        assert_relative_eq!(samples[0].accel[0], 12.0 * STANDARD_GRAVITY / 32768.0);
+        assert_relative_eq!(samples[0].gyro[1], 1000.0 * PI / 180.0 / 32768.0);
+    }
+
+    #[test]
+    fn test_bmi088_self_test_fails_without_response() {
+        let registers = FakeRegisters::default();
+        let mut imu = create(&registers);
+        imu.init(&ImuConfig::default(), &mut NoDelay).unwrap();
+        registers.set_read_only(GYRO, GYRO_SELF_TEST, GYRO_BIST_READY);
+        assert_eq!(
+            imu.self_test(&mut NoDelay),
+            Err(SensorError::SelfTestFailed)
+        );
+        assert_eq!(
+            registers.writes(ACCEL, ACC_SELF_TEST),
+            [ACC_SELF_TEST_POSITIVE, ACC_SELF_TEST_NEGATIVE, 0]
+        );
+        assert_eq!(registers.writes(GYRO, GYRO_SELF_TEST), [GYRO_BIST_TRIGGER]);
+    }
+}
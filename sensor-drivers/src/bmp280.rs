@@ -0,0 +1,255 @@
+//! Bosch BMP280 barometer
+use embedded_hal::delay::DelayNs;
+
+use crate::{select, BaroConfig, BaroSample, Barometer, RegisterBus, SensorError};
+
+/// I2C address with SDO low. It is 0x77 with SDO high.
+pub const DEFAULT_ADDRESS: u8 = 0x76;
+
+const CALIB00: u8 = 0x88;
+const ID: u8 = 0xD0;
+const RESET: u8 = 0xE0;
+const CTRL_MEAS: u8 = 0xF4;
+const CONFIG: u8 = 0xF5;
+const PRESS_MSB: u8 = 0xF7;
+
+const CHIP_ID: u8 = 0x58;
+const SOFT_RESET: u8 = 0xB6;
+const NORMAL_MODE: u8 = 0x03;
+const CALIBRATION_BYTES: usize = 24;
+
+/// Oversampling and the osrs_p or osrs_t value
+const OVERSAMPLING: [(f32, u8); 5] = [(1.0, 1), (2.0, 2), (4.0, 3), (8.0, 4), (16.0, 5)];
+/// IIR filter coefficient and the filter value. The BMP280 filters with
+/// `(previous * (k - 1) + sample) / k`, so `k` is one more than the configured coefficient.
+const FILTERS: [(f32, u8); 5] = [(0.0, 0), (1.0, 1), (3.0, 2), (7.0, 3), (15.0, 4)];
+/// Rate in Hz and the t_sb value for the standby time between measurements. The fastest
+/// setting is limited by the measurement time rather than the standby time.
+const STANDBY: [(f32, u8); 8] = [
+    (0.25, 7),
+    (0.5, 6),
+    (1.0, 5),
+    (2.0, 4),
+    (4.0, 3),
+    (8.0, 2),
+    (16.0, 1),
+    (2000.0, 0),
+];
+
+/// Plausible temperature in °C and pressure in Pa, the sensor's operating range
+const SELF_TEST_TEMPERATURE: (f32, f32) = (-40.0, 85.0);
+const SELF_TEST_PRESSURE: (f32, f32) = (30_000.0, 110_000.0);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Calibration {
+    t1: f64,
+    t2: f64,
+    t3: f64,
+    p: [f64; 9],
+}
+
+impl Calibration {
+    fn parse(data: &[u8; CALIBRATION_BYTES]) -> Self {
+        let signed = |i: usize| i16::from_le_bytes([data[i], data[i + 1]]) as f64;
+        let unsigned = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]) as f64;
+        let mut p = [0.0; 9];
+        p[0] = unsigned(6);
+        for (i, p) in p.iter_mut().enumerate().skip(1) {
+            *p = signed(6 + 2 * i);
+        }
+        Self {
+            t1: unsigned(0),
+            t2: signed(2),
+            t3: signed(4),
+            p,
+        }
+    }
+
+    /// Returns the temperature in °C and pressure in Pa, using the floating point
+    /// compensation from the datasheet
+    fn compensate(&self, adc_t: u32, adc_p: u32) -> (f64, f64) {
+        let adc_t = adc_t as f64;
+        let var1 = (adc_t / 16384.0 - self.t1 / 1024.0) * self.t2;
+        let delta = adc_t / 131072.0 - self.t1 / 8192.0;
+        let var2 = delta * delta * self.t3;
+        let t_fine = var1 + var2;
+        let temperature = t_fine / 5120.0;
+
+        let p = &self.p;
+        let mut var1 = t_fine / 2.0 - 64000.0;
+        let mut var2 = var1 * var1 * p[5] / 32768.0;
+        var2 += var1 * p[4] * 2.0;
+        var2 = var2 / 4.0 + p[3] * 65536.0;
+        var1 = (p[2] * var1 * var1 / 524288.0 + p[1] * var1) / 524288.0;
+        var1 = (1.0 + var1 / 32768.0) * p[0];
+        if var1 == 0.0 {
+            // Avoids dividing by zero with a blank calibration
+            return (temperature, 0.0);
+        }
+        let mut pressure = 1048576.0 - adc_p as f64;
+        pressure = (pressure - var2 / 4096.0) * 6250.0 / var1;
+        let var1 = p[8] * pressure * pressure / 2147483648.0;
+        let var2 = pressure * p[7] / 32768.0;
+        (temperature, pressure + (var1 + var2 + p[6]) / 16.0)
+    }
+}
+
+pub struct Bmp280<B> {
+    bus: B,
+    calibration: Calibration,
+}
+
+impl<B: RegisterBus> Bmp280<B> {
+    pub fn new(bus: B) -> Self {
+        Self {
+            bus,
+            calibration: Calibration::default(),
+        }
+    }
+
+    pub fn release(self) -> B {
+        self.bus
+    }
+}
+
+/// Unpacks a 20-bit ADC value from its MSB, LSB and XLSB registers
+fn adc_value(data: &[u8]) -> u32 {
+    (data[0] as u32) << 12 | (data[1] as u32) << 4 | (data[2] as u32) >> 4
+}
+
+impl<B: RegisterBus> Barometer for Bmp280<B> {
+    type Error = B::Error;
+
+    fn init<D: DelayNs>(
+        &mut self,
+        config: &BaroConfig,
+        delay: &mut D,
+    ) -> Result<(), SensorError<Self::Error>> {
+        let id = self.bus.read_register(ID)?;
+        if id != CHIP_ID {
+            return Err(SensorError::UnexpectedId(id));
+        }
+        if config.fifo {
+            return Err(SensorError::NotSupported);
+        }
+
+        self.bus.write_register(RESET, SOFT_RESET)?;
+        delay.delay_ms(10);
+        let mut data = [0; CALIBRATION_BYTES];
+        self.bus.read_registers(CALIB00, &mut data)?;
+        self.calibration = Calibration::parse(&data);
+
+        let (pressure_oversampling, osrs_p) = select(&OVERSAMPLING, config.oversampling as f32);
+        // The datasheet recommends 2x temperature oversampling only for the highest
+        // pressure oversampling
+        let osrs_t = if pressure_oversampling >= 16.0 { 2 } else { 1 };
+        let (_, filter) = select(&FILTERS, config.iir_coefficient as f32);
+        let (_, t_sb) = select(&STANDBY, config.sample_rate_hz);
+        // The config register can only be written in sleep mode, which follows the reset
+        self.bus.write_register(CONFIG, t_sb << 5 | filter << 2)?;
+        self.bus
+            .write_register(CTRL_MEAS, osrs_t << 5 | osrs_p << 2 | NORMAL_MODE)
+    }
+
+    fn read(&mut self) -> Result<BaroSample, SensorError<Self::Error>> {
+        let mut data = [0; 6];
+        self.bus.read_registers(PRESS_MSB, &mut data)?;
+        let (temperature, pressure) = self
+            .calibration
+            .compensate(adc_value(&data[3..6]), adc_value(&data[0..3]));
+        Ok(BaroSample {
+            pressure: pressure as f32,
+            temperature: temperature as f32,
+        })
+    }
+
+    fn read_fifo(
+        &mut self,
+        _samples: &mut [BaroSample],
+    ) -> Result<usize, SensorError<Self::Error>> {
+        Err(SensorError::NotSupported)
+    }
+
+    fn self_test<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), SensorError<Self::Error>> {
+        if self.calibration.t1 == 0.0 || self.calibration.p[0] == 0.0 {
+            return Err(SensorError::SelfTestFailed);
+        }
+        // Waits for a measurement at the slowest oversampling
+        delay.delay_ms(50);
+        let sample = self.read()?;
+        let (t_min, t_max) = SELF_TEST_TEMPERATURE;
+        let (p_min, p_max) = SELF_TEST_PRESSURE;
+        if (t_min..=t_max).contains(&sample.temperature)
+            && (p_min..=p_max).contains(&sample.pressure)
+        {
+            Ok(())
+        } else {
+            Err(SensorError::SelfTestFailed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{FakeRegisters, NoDelay};
+    use crate::I2cRegisters;
+    use approx::assert_relative_eq;
+    use std::vec::Vec;
+
+    /// The compensation example from the datasheet
+    fn datasheet_registers() -> FakeRegisters {
+        let registers = FakeRegisters::default();
+        registers.set(DEFAULT_ADDRESS, ID, &[CHIP_ID]);
+        let mut calibration = Vec::new();
+        calibration.extend(27504u16.to_le_bytes());
+        calibration.extend(26435i16.to_le_bytes());
+        calibration.extend((-1000i16).to_le_bytes());
+        calibration.extend(36477u16.to_le_bytes());
+        for p in [-10685i16, 3024, 2855, 140, -7, 15500, -14600, 6000] {
+            calibration.extend(p.to_le_bytes());
+        }
+        registers.set(DEFAULT_ADDRESS, CALIB00, &calibration);
+        // adc_P = 415148, adc_T = 519888
+        registers.set(
+            DEFAULT_ADDRESS,
+            PRESS_MSB,
+            &[0x65, 0x5A, 0xC0, 0x7E, 0xED, 0x00],
+        );
+        registers
+    }
+
+    #[test]
+    fn test_bmp280_compensation() {
+        let registers = datasheet_registers();
+        let mut baro = Bmp280::new(I2cRegisters::new(registers.mock(), DEFAULT_ADDRESS));
+        baro.init(&BaroConfig::default(), &mut NoDelay).unwrap();
+
+        assert_eq!(registers.writes(DEFAULT_ADDRESS, RESET), [SOFT_RESET]);
+        // 8x pressure and 1x temperature oversampling, normal mode
+        assert_eq!(registers.get(DEFAULT_ADDRESS, CTRL_MEAS), 0x33);
+        // 0.5 ms standby and a filter coefficient of 4
+        assert_eq!(registers.get(DEFAULT_ADDRESS, CONFIG), 0x08);
+
+        let sample = baro.read().unwrap();
+        assert_relative_eq!(sample.temperature, 25.08, epsilon = 0.01);
+        assert_relative_eq!(sample.pressure, 100653.27, epsilon = 0.5);
+        baro.self_test(&mut NoDelay).unwrap();
+        assert_eq!(
+            baro.read_fifo(&mut [BaroSample::default()]),
+            Err(SensorError::NotSupported)
+        );
+    }
+
+    #[test]
+    fn test_bmp280_blank_calibration_fails_self_test() {
+        let registers = FakeRegisters::default();
+        registers.set(DEFAULT_ADDRESS, ID, &[CHIP_ID]);
+        let mut baro = Bmp280::new(I2cRegisters::new(registers.mock(), DEFAULT_ADDRESS));
+        baro.init(&BaroConfig::default(), &mut NoDelay).unwrap();
+        assert_eq!(
+            baro.self_test(&mut NoDelay),
+            Err(SensorError::SelfTestFailed)
+        );
+    }
+}
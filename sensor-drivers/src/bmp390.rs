@@ -0,0 +1,401 @@
+//! Bosch BMP390 barometer, which also supports the BMP388
+use alloc::vec;
+use embedded_hal::delay::DelayNs;
+
+use crate::{select, BaroConfig, BaroSample, Barometer, RegisterBus, SensorError};
+
+/// I2C address with SDO high, as on most breakout boards. It is 0x76 with SDO low.
+pub const DEFAULT_ADDRESS: u8 = 0x77;
+
+const CHIP_ID: u8 = 0x00;
+const ERR_REG: u8 = 0x02;
+const DATA_0: u8 = 0x04;
+const FIFO_LENGTH_0: u8 = 0x12;
+const FIFO_DATA: u8 = 0x14;
+const FIFO_CONFIG_1: u8 = 0x17;
+const PWR_CTRL: u8 = 0x1B;
+const OSR: u8 = 0x1C;
+const ODR: u8 = 0x1D;
+const CONFIG: u8 = 0x1F;
+const NVM_PAR_T1: u8 = 0x31;
+const CMD: u8 = 0x7E;
+
+const BMP390_ID: u8 = 0x60;
+const BMP388_ID: u8 = 0x50;
+const SOFT_RESET: u8 = 0xB6;
+const FIFO_FLUSH: u8 = 0xB0;
+/// Enables the pressure and temperature sensors in normal mode
+const NORMAL_MODE: u8 = 0x33;
+/// Enables the FIFO with pressure and temperature
+const FIFO_PRESSURE_TEMPERATURE: u8 = 0x19;
+const CALIBRATION_BYTES: usize = 21;
+const FIFO_BYTES: usize = 512;
+
+const FRAME_PRESSURE_TEMPERATURE: u8 = 0x94;
+const FRAME_TEMPERATURE: u8 = 0x90;
+const FRAME_PRESSURE: u8 = 0x84;
+const FRAME_SENSOR_TIME: u8 = 0xA0;
+const FRAME_CONFIG_CHANGE: u8 = 0x48;
+const FRAME_CONFIG_ERROR: u8 = 0x44;
+const FRAME_EMPTY: u8 = 0x80;
+/// The largest frame is a header and 6 data bytes
+const MAX_FRAME_BYTES: usize = 7;
+
+/// Oversampling and the osr_p or osr_t value
+const OVERSAMPLING: [(f32, u8); 6] = [(1.0, 0), (2.0, 1), (4.0, 2), (8.0, 3), (16.0, 4), (32.0, 5)];
+/// IIR filter coefficient and the iir_filter value
+const FILTERS: [(f32, u8); 8] = [
+    (0.0, 0),
+    (1.0, 1),
+    (3.0, 2),
+    (7.0, 3),
+    (15.0, 4),
+    (31.0, 5),
+    (63.0, 6),
+    (127.0, 7),
+];
+/// The output data rate is 200 Hz divided by 2^odr_sel
+const MAX_RATE_HZ: f32 = 200.0;
+const MAX_ODR_SEL: u8 = 17;
+
+/// Plausible temperature in °C and pressure in Pa, the sensor's operating range
+const SELF_TEST_TEMPERATURE: (f32, f32) = (-40.0, 85.0);
+const SELF_TEST_PRESSURE: (f32, f32) = (30_000.0, 125_000.0);
+
+/// Calibration coefficients, scaled as in the datasheet's floating point compensation
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct Calibration {
+    t: [f64; 3],
+    p: [f64; 11],
+}
+
+impl Calibration {
+    fn parse(data: &[u8; CALIBRATION_BYTES]) -> Self {
+        let u16_at = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]) as f64;
+        let i16_at = |i: usize| i16::from_le_bytes([data[i], data[i + 1]]) as f64;
+        let i8_at = |i: usize| data[i] as i8 as f64;
+        let exp2 = |n: u32| (1u128 << n) as f64;
+        Self {
+            t: [
+                u16_at(0) * exp2(8),
+                u16_at(2) / exp2(30),
+                i8_at(4) / exp2(48),
+            ],
+            p: [
+                (i16_at(5) - exp2(14)) / exp2(20),
+                (i16_at(7) - exp2(14)) / exp2(29),
+                i8_at(9) / exp2(32),
+                i8_at(10) / exp2(37),
+                u16_at(11) * exp2(3),
+                u16_at(13) / exp2(6),
+                i8_at(15) / exp2(8),
+                i8_at(16) / exp2(15),
+                i16_at(17) / exp2(48),
+                i8_at(19) / exp2(48),
+                i8_at(20) / exp2(65),
+            ],
+        }
+    }
+
+    /// Returns the compensated temperature in °C
+    fn temperature(&self, raw: u32) -> f64 {
+        let delta = raw as f64 - self.t[0];
+        delta * self.t[1] + delta * delta * self.t[2]
+    }
+
+    /// Returns the compensated pressure in Pa at `temperature` in °C
+    fn pressure(&self, raw: u32, temperature: f64) -> f64 {
+        let p = &self.p;
+        let t = temperature;
+        let raw = raw as f64;
+        let offset = p[4] + p[5] * t + p[6] * t * t + p[7] * t * t * t;
+        let sensitivity = raw * (p[0] + p[1] * t + p[2] * t * t + p[3] * t * t * t);
+        let nonlinear = raw * raw * (p[8] + p[9] * t) + raw * raw * raw * p[10];
+        offset + sensitivity + nonlinear
+    }
+}
+
+pub struct Bmp390<B> {
+    bus: B,
+    calibration: Calibration,
+    fifo: bool,
+    /// The latest temperature from the FIFO, for frames with only pressure
+    fifo_temperature: f64,
+}
+
+impl<B: RegisterBus> Bmp390<B> {
+    pub fn new(bus: B) -> Self {
+        Self {
+            bus,
+            calibration: Calibration::default(),
+            fifo: false,
+            fifo_temperature: 0.0,
+        }
+    }
+
+    pub fn release(self) -> B {
+        self.bus
+    }
+
+    fn sample(&self, raw_pressure: u32, raw_temperature: u32) -> BaroSample {
+        let temperature = self.calibration.temperature(raw_temperature);
+        BaroSample {
+            pressure: self.calibration.pressure(raw_pressure, temperature) as f32,
+            temperature: temperature as f32,
+        }
+    }
+}
+
+fn u24_le(data: &[u8]) -> u32 {
+    u32::from_le_bytes([data[0], data[1], data[2], 0])
+}
+
+/// The fastest ODR setting whose period covers the measurement time from the datasheet
+fn min_odr_sel(osr_p: u8, osr_t: u8) -> u8 {
+    let measurement_us = 234 + 392 + 2020 * (1 << osr_p) + 163 + 2020 * (1 << osr_t);
+    let mut odr_sel = 0;
+    while odr_sel < MAX_ODR_SEL && 5000 << odr_sel < measurement_us {
+        odr_sel += 1;
+    }
+    odr_sel
+}
+
+impl<B: RegisterBus> Barometer for Bmp390<B> {
+    type Error = B::Error;
+
+    fn init<D: DelayNs>(
+        &mut self,
+        config: &BaroConfig,
+        delay: &mut D,
+    ) -> Result<(), SensorError<Self::Error>> {
+        let id = self.bus.read_register(CHIP_ID)?;
+        if id != BMP390_ID && id != BMP388_ID {
+            return Err(SensorError::UnexpectedId(id));
+        }
+
+        self.bus.write_register(CMD, SOFT_RESET)?;
+        delay.delay_ms(10);
+        let mut data = [0; CALIBRATION_BYTES];
+        self.bus.read_registers(NVM_PAR_T1, &mut data)?;
+        self.calibration = Calibration::parse(&data);
+
+        let (pressure_oversampling, osr_p) = select(&OVERSAMPLING, config.oversampling as f32);
+        // The datasheet recommends 2x temperature oversampling only for the highest
+        // pressure oversampling
+        let osr_t = if pressure_oversampling >= 32.0 { 1 } else { 0 };
+        let (_, iir_filter) = select(&FILTERS, config.iir_coefficient as f32);
+        // Picks the slowest rate that is at least the requested rate, but no faster than the
+        // oversampling allows, since that is a configuration error
+        let mut odr_sel = min_odr_sel(osr_p, osr_t);
+        while odr_sel < MAX_ODR_SEL && MAX_RATE_HZ / (2 << odr_sel) as f32 >= config.sample_rate_hz
+        {
+            odr_sel += 1;
+        }
+        self.bus.write_register(OSR, osr_t << 3 | osr_p)?;
+        self.bus.write_register(ODR, odr_sel)?;
+        self.bus.write_register(CONFIG, iir_filter << 1)?;
+
+        self.fifo = config.fifo;
+        if config.fifo {
+            self.bus.write_register(CMD, FIFO_FLUSH)?;
+            self.bus
+                .write_register(FIFO_CONFIG_1, FIFO_PRESSURE_TEMPERATURE)?;
+        } else {
+            self.bus.write_register(FIFO_CONFIG_1, 0)?;
+        }
+        self.bus.write_register(PWR_CTRL, NORMAL_MODE)
+    }
+
+    fn read(&mut self) -> Result<BaroSample, SensorError<Self::Error>> {
+        let mut data = [0; 6];
+        self.bus.read_registers(DATA_0, &mut data)?;
+        Ok(self.sample(u24_le(&data[0..3]), u24_le(&data[3..6])))
+    }
+
+    /// The sensor sends a partially read frame again on the next read, so frames that don't
+    /// fit in `samples` stay queued
+    fn read_fifo(&mut self, samples: &mut [BaroSample]) -> Result<usize, SensorError<Self::Error>> {
+        if !self.fifo {
+            return Err(SensorError::NotSupported);
+        }
+
+        let mut length = [0; 2];
+        self.bus.read_registers(FIFO_LENGTH_0, &mut length)?;
+        let length = (u16::from_le_bytes(length) as usize & 0x1FF)
+            .min(FIFO_BYTES)
+            .min(samples.len() * MAX_FRAME_BYTES);
+        let mut data = vec![0; length];
+        self.bus.read_registers(FIFO_DATA, &mut data)?;
+
+        let mut read = 0;
+        let mut frames = data.as_slice();
+        while let Some((&header, rest)) = frames.split_first() {
+            let payload = match header {
+                FRAME_PRESSURE_TEMPERATURE => 6,
+                FRAME_TEMPERATURE | FRAME_PRESSURE | FRAME_SENSOR_TIME => 3,
+                FRAME_CONFIG_CHANGE | FRAME_CONFIG_ERROR => 1,
+                // Empty frames mean the FIFO has been read out
+                FRAME_EMPTY => break,
+                _ => {
+                    log::warn!("Unexpected BMP390 FIFO frame header: {header:#x}");
+                    break;
+                }
+            };
+            if rest.len() < payload || read == samples.len() {
+                break;
+            }
+            let (payload, rest) = rest.split_at(payload);
+            frames = rest;
+            match header {
+                FRAME_PRESSURE_TEMPERATURE => {
+                    // Temperature comes first in FIFO frames
+                    let sample = self.sample(u24_le(&payload[3..6]), u24_le(&payload[0..3]));
+                    self.fifo_temperature = sample.temperature as f64;
+                    samples[read] = sample;
+                    read += 1;
+                }
+                FRAME_TEMPERATURE => {
+                    self.fifo_temperature = self.calibration.temperature(u24_le(payload));
+                }
+                FRAME_PRESSURE => {
+                    let temperature = self.fifo_temperature;
+                    samples[read] = BaroSample {
+                        pressure: self.calibration.pressure(u24_le(payload), temperature) as f32,
+                        temperature: temperature as f32,
+                    };
+                    read += 1;
+                }
+                _ => {}
+            }
+        }
+        Ok(read)
+    }
+
+    fn self_test<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), SensorError<Self::Error>> {
+        if self.calibration.t[0] == 0.0 || self.calibration.p[4] == 0.0 {
+            return Err(SensorError::SelfTestFailed);
+        }
+        if self.bus.read_register(ERR_REG)? != 0 {
+            return Err(SensorError::SelfTestFailed);
+        }
+        // Waits for a measurement at the slowest oversampling
+        delay.delay_ms(80);
+        let sample = self.read()?;
+        let (t_min, t_max) = SELF_TEST_TEMPERATURE;
+        let (p_min, p_max) = SELF_TEST_PRESSURE;
+        if (t_min..=t_max).contains(&sample.temperature)
+            && (p_min..=p_max).contains(&sample.pressure)
+        {
+            Ok(())
+        } else {
+            Err(SensorError::SelfTestFailed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{FakeRegisters, NoDelay};
+    use crate::I2cRegisters;
+    use approx::assert_relative_eq;
+    use std::vec::Vec;
+
+    /// A calibration where the temperature is `raw / 2^16 - 1` °C and the pressure is
+    /// `90000 + raw / 1024` Pa
+    fn linear_registers() -> FakeRegisters {
+        let registers = FakeRegisters::default();
+        registers.set(DEFAULT_ADDRESS, CHIP_ID, &[BMP390_ID]);
+        let mut calibration = Vec::new();
+        calibration.extend(256u16.to_le_bytes());
+        calibration.extend(16384u16.to_le_bytes());
+        calibration.push(0);
+        calibration.extend(17408i16.to_le_bytes());
+        calibration.extend(16384i16.to_le_bytes());
+        calibration.extend([0, 0]);
+        calibration.extend(11250u16.to_le_bytes());
+        calibration.extend([0; 8]);
+        registers.set(DEFAULT_ADDRESS, NVM_PAR_T1, &calibration);
+        registers
+    }
+
+    #[test]
+    fn test_bmp390_init_and_read() {
+        let registers = linear_registers();
+        // 100000 Pa and 24 °C
+        registers.set(
+            DEFAULT_ADDRESS,
+            DATA_0,
+            &[0x00, 0x40, 0x9C, 0x00, 0x00, 0x19],
+        );
+        let mut baro = Bmp390::new(I2cRegisters::new(registers.mock(), DEFAULT_ADDRESS));
+        baro.init(&BaroConfig::default(), &mut NoDelay).unwrap();
+
+        assert_eq!(registers.get(DEFAULT_ADDRESS, OSR), 0x03);
+        // 25 Hz, which leaves time for the 20 ms measurement with 8x oversampling
+        assert_eq!(registers.get(DEFAULT_ADDRESS, ODR), 3);
+        assert_eq!(registers.get(DEFAULT_ADDRESS, CONFIG), 2 << 1);
+        assert_eq!(registers.get(DEFAULT_ADDRESS, PWR_CTRL), NORMAL_MODE);
+
+        let sample = baro.read().unwrap();
+        assert_relative_eq!(sample.temperature, 24.0, epsilon = 1e-4);
+        assert_relative_eq!(sample.pressure, 100000.0, epsilon = 0.01);
+        baro.self_test(&mut NoDelay).unwrap();
+    }
+
+    #[test]
+    fn test_bmp390_fifo() {
+        let registers = linear_registers();
+        let mut baro = Bmp390::new(I2cRegisters::new(registers.mock(), DEFAULT_ADDRESS));
+        let config = BaroConfig {
+            oversampling: 1,
+            sample_rate_hz: 10.0,
+            fifo: true,
+            ..Default::default()
+        };
+        baro.init(&config, &mut NoDelay).unwrap();
+        assert_eq!(
+            registers.writes(DEFAULT_ADDRESS, CMD),
+            [SOFT_RESET, FIFO_FLUSH]
+        );
+        assert_eq!(
+            registers.get(DEFAULT_ADDRESS, FIFO_CONFIG_1),
+            FIFO_PRESSURE_TEMPERATURE
+        );
+        // 12.5 Hz
+        assert_eq!(registers.get(DEFAULT_ADDRESS, ODR), 4);
+
+        let frames = [
+            &[
+                FRAME_PRESSURE_TEMPERATURE,
+                0x00,
+                0x00,
+                0x19,
+                0x00,
+                0x40,
+                0x9C,
+            ][..],
+            &[FRAME_SENSOR_TIME, 1, 2, 3],
+            &[
+                FRAME_PRESSURE_TEMPERATURE,
+                0x00,
+                0x00,
+                0x1A,
+                0x00,
+                0x00,
+                0x00,
+            ],
+        ];
+        for frame in frames {
+            registers.queue_fifo(DEFAULT_ADDRESS, FIFO_DATA, frame);
+        }
+        registers.set(DEFAULT_ADDRESS, FIFO_LENGTH_0, &[18, 0]);
+
+        let mut samples = [BaroSample::default(); 4];
+        assert_eq!(baro.read_fifo(&mut samples).unwrap(), 2);
+        assert_relative_eq!(samples[0].temperature, 24.0, epsilon = 1e-4);
+        assert_relative_eq!(samples[0].pressure, 100000.0, epsilon = 0.01);
+        assert_relative_eq!(samples[1].temperature, 25.0, epsilon = 1e-4);
+        assert_relative_eq!(samples[1].pressure, 90000.0, epsilon = 0.01);
+    }
+}
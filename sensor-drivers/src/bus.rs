@@ -0,0 +1,175 @@
+use alloc::rc::Rc;
+use core::cell::RefCell;
+use core::fmt::Debug;
+use embedded_hal::i2c::{self, I2c, Operation as I2cOperation};
+use embedded_hal::spi::{Operation as SpiOperation, SpiDevice};
+
+use crate::SensorError;
+
+/// Byte registers of a sensor
+pub trait RegisterBus {
+    type Error: Debug;
+
+    /// Reads consecutive registers starting at `register`
+    fn read_registers(
+        &mut self,
+        register: u8,
+        buffer: &mut [u8],
+    ) -> Result<(), SensorError<Self::Error>>;
+
+    fn write_register(&mut self, register: u8, value: u8) -> Result<(), SensorError<Self::Error>>;
+
+    fn read_register(&mut self, register: u8) -> Result<u8, SensorError<Self::Error>> {
+        let mut value = [0];
+        self.read_registers(register, &mut value)?;
+        Ok(value[0])
+    }
+
+    /// Replaces the bits of `register` in `mask` with those of `value`
+    fn update_register(
+        &mut self,
+        register: u8,
+        mask: u8,
+        value: u8,
+    ) -> Result<(), SensorError<Self::Error>> {
+        let current = self.read_register(register)?;
+        self.write_register(register, (current & !mask) | (value & mask))
+    }
+}
+
+/// Registers of an I2C device with 8-bit register addresses that auto-increment on reads
+pub struct I2cRegisters<I> {
+    i2c: I,
+    address: u8,
+}
+
+impl<I: I2c> I2cRegisters<I> {
+    pub fn new(i2c: I, address: u8) -> Self {
+        Self { i2c, address }
+    }
+
+    pub fn release(self) -> I {
+        self.i2c
+    }
+}
+
+impl<I: I2c> RegisterBus for I2cRegisters<I> {
+    type Error = I::Error;
+
+    fn read_registers(
+        &mut self,
+        register: u8,
+        buffer: &mut [u8],
+    ) -> Result<(), SensorError<Self::Error>> {
+        self.i2c
+            .write_read(self.address, &[register], buffer)
+            .map_err(SensorError::Bus)
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) -> Result<(), SensorError<Self::Error>> {
+        self.i2c
+            .write(self.address, &[register, value])
+            .map_err(SensorError::Bus)
+    }
+}
+
+const MAX_DUMMY_BYTES: usize = 2;
+
+/// Registers of an SPI device, where bit 7 of the register address selects a read. Some
+/// sensors, such as the BMI088 accelerometer, send dummy bytes before the register contents.
+pub struct SpiRegisters<S> {
+    spi: S,
+    dummy_bytes: usize,
+}
+
+impl<S: SpiDevice> SpiRegisters<S> {
+    pub fn new(spi: S) -> Self {
+        Self {
+            spi,
+            dummy_bytes: 0,
+        }
+    }
+
+    /// Skips `dummy_bytes`, at most 2, before the register contents
+    pub fn with_dummy_bytes(spi: S, dummy_bytes: usize) -> Self {
+        assert!(dummy_bytes <= MAX_DUMMY_BYTES, "Too many SPI dummy bytes");
+        Self { spi, dummy_bytes }
+    }
+
+    pub fn release(self) -> S {
+        self.spi
+    }
+}
+
+impl<S: SpiDevice> RegisterBus for SpiRegisters<S> {
+    type Error = S::Error;
+
+    fn read_registers(
+        &mut self,
+        register: u8,
+        buffer: &mut [u8],
+    ) -> Result<(), SensorError<Self::Error>> {
+        let mut dummy = [0; MAX_DUMMY_BYTES];
+        self.spi
+            .transaction(&mut [
+                SpiOperation::Write(&[register | 0x80]),
+                SpiOperation::Read(&mut dummy[..self.dummy_bytes]),
+                SpiOperation::Read(buffer),
+            ])
+            .map_err(SensorError::Bus)
+    }
+
+    fn write_register(&mut self, register: u8, value: u8) -> Result<(), SensorError<Self::Error>> {
+        self.spi
+            .write(&[register & 0x7F, value])
+            .map_err(SensorError::Bus)
+    }
+}
+
+/// An I2C bus shared by several drivers, such as the two dies of the BMI088
+pub struct SharedI2c<I> {
+    i2c: Rc<RefCell<I>>,
+}
+
+impl<I> SharedI2c<I> {
+    pub fn new(i2c: I) -> Self {
+        Self {
+            i2c: Rc::new(RefCell::new(i2c)),
+        }
+    }
+}
+
+impl<I> Clone for SharedI2c<I> {
+    fn clone(&self) -> Self {
+        Self {
+            i2c: self.i2c.clone(),
+        }
+    }
+}
+
+impl<I: I2c> i2c::ErrorType for SharedI2c<I> {
+    type Error = I::Error;
+}
+
+impl<I: I2c> I2c for SharedI2c<I> {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [I2cOperation<'_>],
+    ) -> Result<(), Self::Error> {
+        self.i2c.borrow_mut().transaction(address, operations)
+    }
+
+    fn write_read(
+        &mut self,
+        address: u8,
+        write: &[u8],
+        read: &mut [u8],
+    ) -> Result<(), Self::Error> {
+        self.i2c.borrow_mut().write_read(address, write, read)
+    }
+
+    fn write(&mut self, address: u8, write: &[u8]) -> Result<(), Self::Error> {
+        self.i2c.borrow_mut().write(address, write)
+    }
+}
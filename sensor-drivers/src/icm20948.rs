@@ -0,0 +1,304 @@
+//! TDK InvenSense ICM-20948 9-axis IMU. The AK09916 magnetometer behind its auxiliary I2C bus
+//! isn't used.
+use core::f32::consts::PI;
+use embedded_hal::delay::DelayNs;
+
+use crate::{
+    average, i16_be, select, vector_be, within, Imu, ImuConfig, ImuSample, RegisterBus,
+    SensorError, STANDARD_GRAVITY,
+};
+
+/// I2C address with AD0 high, as on most breakout boards. It is 0x68 with AD0 low.
+pub const DEFAULT_ADDRESS: u8 = 0x69;
+
+// Registers in bank 0
+const WHO_AM_I: u8 = 0x00;
+const USER_CTRL: u8 = 0x03;
+const PWR_MGMT_1: u8 = 0x06;
+const PWR_MGMT_2: u8 = 0x07;
+const ACCEL_XOUT_H: u8 = 0x2D;
+const TEMP_OUT_H: u8 = 0x39;
+const FIFO_EN_2: u8 = 0x67;
+const FIFO_RST: u8 = 0x68;
+const FIFO_MODE: u8 = 0x69;
+const FIFO_COUNTH: u8 = 0x70;
+const FIFO_R_W: u8 = 0x72;
+// Registers in bank 2
+const GYRO_SMPLRT_DIV: u8 = 0x00;
+const GYRO_CONFIG_1: u8 = 0x01;
+const GYRO_CONFIG_2: u8 = 0x02;
+const ACCEL_SMPLRT_DIV_1: u8 = 0x10;
+const ACCEL_SMPLRT_DIV_2: u8 = 0x11;
+const ACCEL_CONFIG: u8 = 0x14;
+const ACCEL_CONFIG_2: u8 = 0x15;
+// Available in every bank
+const REG_BANK_SEL: u8 = 0x7F;
+
+const CHIP_ID: u8 = 0xEA;
+const DEVICE_RESET: u8 = 0x80;
+const CLKSEL_AUTO: u8 = 0x01;
+const FIFO_ENABLE: u8 = 0x40;
+const FIFO_RESET_ALL: u8 = 0x1F;
+/// Queues the accelerometer and gyro, which makes each FIFO sample 12 bytes
+const FIFO_ACCEL_GYRO: u8 = 0x1E;
+const FIFO_SAMPLE_BYTES: usize = 12;
+const DLPF_ENABLE: u8 = 0x01;
+const GYRO_SELF_TEST_ENABLE: u8 = 0x38;
+const ACCEL_SELF_TEST_ENABLE: u8 = 0x1C;
+
+/// Full scale in g and the ACCEL_FS_SEL value
+const ACCEL_RANGES: [(f32, u8); 4] = [(2.0, 0), (4.0, 1), (8.0, 2), (16.0, 3)];
+/// Full scale in dps and the GYRO_FS_SEL value
+const GYRO_RANGES: [(f32, u8); 4] = [(250.0, 0), (500.0, 1), (1000.0, 2), (2000.0, 3)];
+/// Low pass filter bandwidths in Hz and the DLPFCFG values
+const GYRO_LOW_PASS_FILTERS: [(f32, u8); 7] = [
+    (5.7, 6),
+    (11.6, 5),
+    (23.9, 4),
+    (51.2, 3),
+    (119.5, 2),
+    (151.8, 1),
+    (196.6, 0),
+];
+const ACCEL_LOW_PASS_FILTERS: [(f32, u8); 6] = [
+    (5.7, 6),
+    (11.5, 5),
+    (23.9, 4),
+    (50.4, 3),
+    (111.4, 2),
+    (246.0, 1),
+];
+/// Output rate with the low pass filter enabled, before the sample rate dividers
+const OUTPUT_RATE_HZ: f32 = 1125.0;
+
+/// Self-test response limits at ±2 g and ±250 dps
+const SELF_TEST_ACCEL_G: (f32, f32) = (0.225, 0.675);
+const SELF_TEST_GYRO_DPS: (f32, f32) = (60.0, 500.0);
+
+pub struct Icm20948<B> {
+    bus: B,
+    config: ImuConfig,
+    accel_scale: f32,
+    gyro_scale: f32,
+}
+
+impl<B: RegisterBus> Icm20948<B> {
+    pub fn new(bus: B) -> Self {
+        Self {
+            bus,
+            config: ImuConfig::default(),
+            accel_scale: 0.0,
+            gyro_scale: 0.0,
+        }
+    }
+
+    pub fn release(self) -> B {
+        self.bus
+    }
+
+    fn select_bank(&mut self, bank: u8) -> Result<(), SensorError<B::Error>> {
+        self.bus.write_register(REG_BANK_SEL, bank << 4)
+    }
+
+    /// Writes the range and filter settings, with self-test enabled or not
+    fn write_ranges(
+        &mut self,
+        accel_fs_sel: u8,
+        gyro_fs_sel: u8,
+        self_test: bool,
+    ) -> Result<(), SensorError<B::Error>> {
+        let rate = self.config.sample_rate_hz;
+        let (_, gyro_dlpf) = select(&GYRO_LOW_PASS_FILTERS, rate / 2.0);
+        let (_, accel_dlpf) = select(&ACCEL_LOW_PASS_FILTERS, rate / 2.0);
+        let (gyro_st, accel_st) = if self_test {
+            (GYRO_SELF_TEST_ENABLE, ACCEL_SELF_TEST_ENABLE)
+        } else {
+            (0, 0)
+        };
+
+        self.select_bank(2)?;
+        self.bus.write_register(
+            GYRO_CONFIG_1,
+            gyro_dlpf << 3 | gyro_fs_sel << 1 | DLPF_ENABLE,
+        )?;
+        self.bus.write_register(GYRO_CONFIG_2, gyro_st)?;
+        self.bus.write_register(
+            ACCEL_CONFIG,
+            accel_dlpf << 3 | accel_fs_sel << 1 | DLPF_ENABLE,
+        )?;
+        self.bus.write_register(ACCEL_CONFIG_2, accel_st)?;
+        self.select_bank(0)
+    }
+
+    fn configure(&mut self) -> Result<(), SensorError<B::Error>> {
+        let config = self.config;
+        let (accel_range, accel_fs_sel) = select(&ACCEL_RANGES, config.accel_range_g);
+        let (gyro_range, gyro_fs_sel) = select(&GYRO_RANGES, config.gyro_range_dps);
+        let divider = (OUTPUT_RATE_HZ / config.sample_rate_hz).clamp(1.0, 256.0) as u16 - 1;
+
+        self.select_bank(2)?;
+        self.bus.write_register(GYRO_SMPLRT_DIV, divider as u8)?;
+        self.bus.write_register(ACCEL_SMPLRT_DIV_1, 0)?;
+        self.bus.write_register(ACCEL_SMPLRT_DIV_2, divider as u8)?;
+        self.write_ranges(accel_fs_sel, gyro_fs_sel, false)?;
+        self.accel_scale = accel_range * STANDARD_GRAVITY / 32768.0;
+        self.gyro_scale = gyro_range * PI / 180.0 / 32768.0;
+
+        if config.fifo {
+            self.bus.write_register(FIFO_MODE, 0)?;
+            self.bus.write_register(FIFO_EN_2, FIFO_ACCEL_GYRO)?;
+            self.bus.write_register(FIFO_RST, FIFO_RESET_ALL)?;
+            self.bus.write_register(FIFO_RST, 0)?;
+            self.bus.write_register(USER_CTRL, FIFO_ENABLE)?;
+        } else {
+            self.bus.write_register(FIFO_EN_2, 0)?;
+            self.bus.write_register(USER_CTRL, 0)?;
+        }
+        Ok(())
+    }
+
+    fn read_raw(&mut self) -> Result<[f32; 6], SensorError<B::Error>> {
+        let mut data = [0; 12];
+        self.bus.read_registers(ACCEL_XOUT_H, &mut data)?;
+        let accel = vector_be(&data[0..6], 1.0);
+        let gyro = vector_be(&data[6..12], 1.0);
+        Ok([accel[0], accel[1], accel[2], gyro[0], gyro[1], gyro[2]])
+    }
+
+    fn read_temperature(&mut self) -> Result<f32, SensorError<B::Error>> {
+        let mut data = [0; 2];
+        self.bus.read_registers(TEMP_OUT_H, &mut data)?;
+        Ok(temperature(&data))
+    }
+}
+
+fn temperature(data: &[u8]) -> f32 {
+    i16_be(data) as f32 / 333.87 + 21.0
+}
+
+impl<B: RegisterBus> Imu for Icm20948<B> {
+    type Error = B::Error;
+
+    fn init<D: DelayNs>(
+        &mut self,
+        config: &ImuConfig,
+        delay: &mut D,
+    ) -> Result<(), SensorError<Self::Error>> {
+        self.select_bank(0)?;
+        let id = self.bus.read_register(WHO_AM_I)?;
+        if id != CHIP_ID {
+            return Err(SensorError::UnexpectedId(id));
+        }
+
+        self.bus.write_register(PWR_MGMT_1, DEVICE_RESET)?;
+        delay.delay_ms(100);
+        // The reset also resets the bank selection to 0
+        self.bus.write_register(PWR_MGMT_1, CLKSEL_AUTO)?;
+        self.bus.write_register(PWR_MGMT_2, 0)?;
+        delay.delay_ms(10);
+
+        self.config = *config;
+        self.configure()
+    }
+
+    fn read(&mut self) -> Result<ImuSample, SensorError<Self::Error>> {
+        let mut data = [0; 14];
+        self.bus.read_registers(ACCEL_XOUT_H, &mut data)?;
+        Ok(ImuSample {
+            accel: vector_be(&data[0..6], self.accel_scale),
+            gyro: vector_be(&data[6..12], self.gyro_scale),
+            temperature: temperature(&data[12..14]),
+        })
+    }
+
+    fn read_fifo(&mut self, samples: &mut [ImuSample]) -> Result<usize, SensorError<Self::Error>> {
+        if !self.config.fifo {
+            return Err(SensorError::NotSupported);
+        }
+
+        let mut count = [0; 2];
+        self.bus.read_registers(FIFO_COUNTH, &mut count)?;
+        let queued = (u16::from_be_bytes(count) & 0x1FFF) as usize / FIFO_SAMPLE_BYTES;
+        // Temperature isn't queued, so every sample gets the current temperature
+        let temperature = self.read_temperature()?;
+        let read = queued.min(samples.len());
+        for sample in &mut samples[..read] {
+            let mut data = [0; FIFO_SAMPLE_BYTES];
+            self.bus.read_registers(FIFO_R_W, &mut data)?;
+            *sample = ImuSample {
+                accel: vector_be(&data[0..6], self.accel_scale),
+                gyro: vector_be(&data[6..12], self.gyro_scale),
+                temperature,
+            };
+        }
+        Ok(read)
+    }
+
+    fn self_test<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), SensorError<Self::Error>> {
+        self.write_ranges(0, 0, false)?;
+        delay.delay_ms(50);
+        let normal = average(10, 2000, delay, || self.read_raw())?;
+
+        self.write_ranges(0, 0, true)?;
+        delay.delay_ms(50);
+        let test = average(10, 2000, delay, || self.read_raw())?;
+        self.configure()?;
+
+        let response: [f32; 6] = core::array::from_fn(|i| test[i] - normal[i]);
+        let accel = [0, 1, 2].map(|i| response[i] * 2.0 / 32768.0);
+        let gyro = [3, 4, 5].map(|i| response[i] * 250.0 / 32768.0);
+        if within(accel, SELF_TEST_ACCEL_G) && within(gyro, SELF_TEST_GYRO_DPS) {
+            Ok(())
+        } else {
+            Err(SensorError::SelfTestFailed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{FakeRegisters, NoDelay};
+    use crate::I2cRegisters;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_icm20948_init_and_read() {
+        let registers = FakeRegisters::default();
+        registers.set(DEFAULT_ADDRESS, WHO_AM_I, &[CHIP_ID]);
+        // -2 g, 0, 1 g at ±16 g, then 0, 2000 dps, 0 at ±2000 dps, 21 °C
+        registers.set(
+            DEFAULT_ADDRESS,
+            ACCEL_XOUT_H,
+            &[0xF0, 0x00, 0, 0, 0x08, 0x00, 0, 0, 0x7F, 0xFF, 0, 0, 0, 0],
+        );
+        let mut imu = Icm20948::new(I2cRegisters::new(registers.mock(), DEFAULT_ADDRESS));
+        let config = ImuConfig {
+            accel_range_g: 16.0,
+            gyro_range_dps: 2000.0,
+            sample_rate_hz: 112.5,
+            fifo: false,
+        };
+        imu.init(&config, &mut NoDelay).unwrap();
+
+        // Bank 2 registers are configured between the bank switches
+        assert_eq!(
+            registers.writes(DEFAULT_ADDRESS, REG_BANK_SEL)[..3],
+            [0, 0x20, 0x20]
+        );
+        assert_eq!(registers.get(DEFAULT_ADDRESS, REG_BANK_SEL), 0);
+        assert_eq!(registers.writes(DEFAULT_ADDRESS, GYRO_SMPLRT_DIV), [9]);
+        assert_eq!(
+            registers.writes(DEFAULT_ADDRESS, ACCEL_CONFIG),
+            [2 << 3 | 3 << 1 | DLPF_ENABLE]
+        );
+
+        let sample = imu.read().unwrap();
+        let g = STANDARD_GRAVITY;
+        for (actual, expected) in sample.accel.iter().zip([-2.0 * g, 0.0, g]) {
+            assert_relative_eq!(*actual, expected, epsilon = 1e-4);
+        }
+        assert_relative_eq!(sample.gyro[1], 2000.0 * PI / 180.0, epsilon = 2e-3);
+        assert_relative_eq!(sample.temperature, 21.0);
+    }
+}
@@ -0,0 +1,271 @@
+//! Drivers for common IMUs, barometers and magnetometers
+//!
+//! Each driver owns a [`RegisterBus`], which is either an I2C device ([`I2cRegisters`]) or an
+//! SPI device ([`SpiRegisters`]), and converts register contents into physical units:
+//! m/s² for acceleration, rad/s for angular rate, Pa for pressure, µT for magnetic field and
+//! °C for temperature.
+//!
+//! The wrappers in [`wrappers`] run the drivers as input blocks for the IMU, Barometer and
+//! Magnetometer blocks in `pictorus-core-blocks`.
+#![no_std]
+
+extern crate alloc;
+
+#[cfg(test)]
+extern crate std;
+
+use core::fmt::Debug;
+use embedded_hal::delay::DelayNs;
+
+mod bus;
+pub use bus::{I2cRegisters, RegisterBus, SharedI2c, SpiRegisters};
+
+pub mod bmi088;
+pub mod bmp280;
+pub mod bmp390;
+pub mod icm20948;
+pub mod lsm6dsx;
+pub mod mpu6050;
+pub mod qmc5883;
+
+pub mod wrappers;
+
+#[cfg(test)]
+mod test_utils;
+
+pub use bmi088::Bmi088;
+pub use bmp280::Bmp280;
+pub use bmp390::Bmp390;
+pub use icm20948::Icm20948;
+pub use lsm6dsx::Lsm6dsx;
+pub use mpu6050::Mpu6050;
+pub use qmc5883::Qmc5883;
+
+/// Standard gravity, used to convert accelerations from g
+pub const STANDARD_GRAVITY: f32 = 9.80665;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SensorError<E> {
+    /// The bus transfer failed
+    Bus(E),
+    /// The chip ID register didn't match the driver, so the sensor is missing or a different part
+    UnexpectedId(u8),
+    /// The sensor doesn't support the operation, such as reading a FIFO it doesn't have
+    NotSupported,
+    /// The sensor's self-test response was outside the datasheet limits
+    SelfTestFailed,
+    /// The sensor didn't become ready in time
+    Timeout,
+}
+
+/// One IMU sample
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ImuSample {
+    /// Acceleration in m/s²
+    pub accel: [f32; 3],
+    /// Angular rate in rad/s
+    pub gyro: [f32; 3],
+    /// Die temperature in °C
+    pub temperature: f32,
+}
+
+/// One barometer sample
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BaroSample {
+    /// Pressure in Pa
+    pub pressure: f32,
+    /// Temperature in °C
+    pub temperature: f32,
+}
+
+/// One magnetometer sample
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct MagSample {
+    /// Magnetic field in µT
+    pub field: [f32; 3],
+}
+
+/// IMU configuration. Each driver picks the smallest range and the slowest output data rate
+/// that cover the requested values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImuConfig {
+    pub accel_range_g: f32,
+    pub gyro_range_dps: f32,
+    pub sample_rate_hz: f32,
+    /// Queue samples in the sensor's FIFO, to be read with [`Imu::read_fifo`]
+    pub fifo: bool,
+}
+
+impl Default for ImuConfig {
+    fn default() -> Self {
+        Self {
+            accel_range_g: 8.0,
+            gyro_range_dps: 1000.0,
+            sample_rate_hz: 100.0,
+            fifo: false,
+        }
+    }
+}
+
+/// Barometer configuration
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BaroConfig {
+    /// Pressure oversampling, rounded up to a power of two
+    pub oversampling: u8,
+    /// IIR filter coefficient, rounded up to one less than a power of two
+    pub iir_coefficient: u8,
+    pub sample_rate_hz: f32,
+    /// Queue samples in the sensor's FIFO, to be read with [`Barometer::read_fifo`]
+    pub fifo: bool,
+}
+
+impl Default for BaroConfig {
+    fn default() -> Self {
+        Self {
+            oversampling: 8,
+            iir_coefficient: 3,
+            sample_rate_hz: 25.0,
+            fifo: false,
+        }
+    }
+}
+
+/// Magnetometer configuration
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MagConfig {
+    pub range_gauss: f32,
+    pub sample_rate_hz: f32,
+    pub oversampling: u16,
+}
+
+impl Default for MagConfig {
+    fn default() -> Self {
+        Self {
+            range_gauss: 8.0,
+            sample_rate_hz: 50.0,
+            oversampling: 512,
+        }
+    }
+}
+
+pub trait Imu {
+    type Error: Debug;
+
+    /// Resets and configures the sensor, checking its chip ID first
+    fn init<D: DelayNs>(
+        &mut self,
+        config: &ImuConfig,
+        delay: &mut D,
+    ) -> Result<(), SensorError<Self::Error>>;
+
+    /// Reads the latest sample
+    fn read(&mut self) -> Result<ImuSample, SensorError<Self::Error>>;
+
+    /// Reads the samples queued since the last call, oldest first, returning how many were
+    /// read. Samples that don't fit in `samples` stay queued.
+    fn read_fifo(&mut self, samples: &mut [ImuSample]) -> Result<usize, SensorError<Self::Error>>;
+
+    /// Runs the sensor's built-in self-test, then restores the configuration from `init`
+    fn self_test<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), SensorError<Self::Error>>;
+}
+
+pub trait Barometer {
+    type Error: Debug;
+
+    /// Resets and configures the sensor, checking its chip ID and reading its calibration
+    fn init<D: DelayNs>(
+        &mut self,
+        config: &BaroConfig,
+        delay: &mut D,
+    ) -> Result<(), SensorError<Self::Error>>;
+
+    /// Reads the latest sample
+    fn read(&mut self) -> Result<BaroSample, SensorError<Self::Error>>;
+
+    /// Reads the samples queued since the last call, oldest first, returning how many were
+    /// read. Samples that don't fit in `samples` stay queued.
+    fn read_fifo(&mut self, samples: &mut [BaroSample]) -> Result<usize, SensorError<Self::Error>>;
+
+    /// Checks the calibration and that a sample is within the sensor's operating range
+    fn self_test<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), SensorError<Self::Error>>;
+}
+
+pub trait Magnetometer {
+    type Error: Debug;
+
+    /// Resets and configures the sensor, checking its chip ID first
+    fn init<D: DelayNs>(
+        &mut self,
+        config: &MagConfig,
+        delay: &mut D,
+    ) -> Result<(), SensorError<Self::Error>>;
+
+    /// Reads the latest sample, or `None` if no new sample is ready
+    fn read(&mut self) -> Result<Option<MagSample>, SensorError<Self::Error>>;
+
+    /// Checks that a sample is plausible for the earth's field
+    fn self_test<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), SensorError<Self::Error>>;
+}
+
+/// Picks the first option whose value is at least `requested`, or the last option
+fn select<T: Copy>(options: &[(f32, T)], requested: f32) -> (f32, T) {
+    options
+        .iter()
+        .copied()
+        .find(|(value, _)| *value >= requested)
+        .unwrap_or(options[options.len() - 1])
+}
+
+/// Averages `count` samples read by `read`, waiting `period_us` between them
+fn average<const N: usize, E, D: DelayNs>(
+    count: usize,
+    period_us: u32,
+    delay: &mut D,
+    mut read: impl FnMut() -> Result<[f32; N], E>,
+) -> Result<[f32; N], E> {
+    let mut sum = [0.0; N];
+    for _ in 0..count {
+        delay.delay_us(period_us);
+        for (sum, value) in sum.iter_mut().zip(read()?) {
+            *sum += value;
+        }
+    }
+    Ok(sum.map(|s| s / count as f32))
+}
+
+/// Whether the magnitude of every axis is within `limits`
+fn within(values: [f32; 3], limits: (f32, f32)) -> bool {
+    values
+        .iter()
+        .all(|value| (limits.0..=limits.1).contains(&value.abs()))
+}
+
+fn i16_be(bytes: &[u8]) -> i16 {
+    i16::from_be_bytes([bytes[0], bytes[1]])
+}
+
+fn i16_le(bytes: &[u8]) -> i16 {
+    i16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+fn vector_be(bytes: &[u8], scale: f32) -> [f32; 3] {
+    [0, 2, 4].map(|i| i16_be(&bytes[i..]) as f32 * scale)
+}
+
+fn vector_le(bytes: &[u8], scale: f32) -> [f32; 3] {
+    [0, 2, 4].map(|i| i16_le(&bytes[i..]) as f32 * scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select() {
+        let options = [(2.0, 0), (4.0, 1), (8.0, 2)];
+        assert_eq!(select(&options, 1.0), (2.0, 0));
+        assert_eq!(select(&options, 4.0), (4.0, 1));
+        assert_eq!(select(&options, 5.0), (8.0, 2));
+        assert_eq!(select(&options, 100.0), (8.0, 2));
+    }
+}
@@ -0,0 +1,322 @@
+//! ST LSM6DS3, LSM6DSL, LSM6DSO and related 6-axis IMUs, which share a register layout for
+//! configuration and output data. The FIFO is only supported on the parts with a tagged FIFO,
+//! such as the LSM6DSO and LSM6DSOX.
+use core::f32::consts::PI;
+use embedded_hal::delay::DelayNs;
+
+use crate::{
+    average, i16_le, select, vector_le, within, Imu, ImuConfig, ImuSample, RegisterBus,
+    SensorError, STANDARD_GRAVITY,
+};
+
+/// I2C address with SA0 high, as on most breakout boards. It is 0x6A with SA0 low.
+pub const DEFAULT_ADDRESS: u8 = 0x6B;
+
+const FIFO_CTRL3: u8 = 0x09;
+const FIFO_CTRL4: u8 = 0x0A;
+const WHO_AM_I: u8 = 0x0F;
+const CTRL1_XL: u8 = 0x10;
+const CTRL2_G: u8 = 0x11;
+const CTRL3_C: u8 = 0x12;
+const CTRL5_C: u8 = 0x14;
+const OUT_TEMP_L: u8 = 0x20;
+const FIFO_STATUS1: u8 = 0x3A;
+const FIFO_DATA_OUT_TAG: u8 = 0x78;
+
+const LSM6DS3_ID: u8 = 0x69;
+const LSM6DSL_ID: u8 = 0x6A;
+const LSM6DS33_ID: u8 = 0x6B;
+/// The LSM6DSO, LSM6DSOX and LSM6DSR, which have a tagged FIFO
+const LSM6DSO_ID: u8 = 0x6C;
+const SW_RESET: u8 = 0x01;
+const BDU_IF_INC: u8 = 0x44;
+const FIFO_CONTINUOUS: u8 = 0x06;
+const FIFO_TAG_GYRO: u8 = 0x01;
+const FIFO_TAG_ACCEL: u8 = 0x02;
+/// FIFO words are a tag byte and 6 data bytes
+const FIFO_WORD_BYTES: usize = 7;
+const SELF_TEST_ACCEL_POSITIVE: u8 = 0x01;
+const SELF_TEST_GYRO_POSITIVE: u8 = 0x04;
+
+/// Output data rate in Hz and the ODR value, shared by the accelerometer, gyro and FIFO
+const RATES: [(f32, u8); 10] = [
+    (12.5, 1),
+    (26.0, 2),
+    (52.0, 3),
+    (104.0, 4),
+    (208.0, 5),
+    (416.0, 6),
+    (833.0, 7),
+    (1666.0, 8),
+    (3332.0, 9),
+    (6664.0, 10),
+];
+/// Full scale in g and the FS_XL value
+const ACCEL_RANGES: [(f32, u8); 4] = [(2.0, 0b00), (4.0, 0b10), (8.0, 0b11), (16.0, 0b01)];
+/// Full scale in dps and the FS_G and FS_125 bits of CTRL2_G
+const GYRO_RANGES: [(f32, u8); 5] = [
+    (125.0, 0x02),
+    (250.0, 0x00),
+    (500.0, 0x04),
+    (1000.0, 0x08),
+    (2000.0, 0x0C),
+];
+/// Gyro sensitivity at 250 dps. Unlike the accelerometer, the gyro full scale is about 15%
+/// less than the sensitivity suggests.
+const GYRO_SENSITIVITY_MDPS: f32 = 8.75;
+
+/// The self-test runs the accelerometer at ±4 g and 52 Hz and the gyro at ±2000 dps and 208 Hz
+const SELF_TEST_ACCEL: u8 = 3 << 4 | 0b10 << 2;
+const SELF_TEST_GYRO: u8 = 5 << 4 | 0x0C;
+const SELF_TEST_ACCEL_G: (f32, f32) = (0.05, 1.7);
+const SELF_TEST_GYRO_DPS: (f32, f32) = (150.0, 700.0);
+
+pub struct Lsm6dsx<B> {
+    bus: B,
+    config: ImuConfig,
+    id: u8,
+    accel_scale: f32,
+    gyro_scale: f32,
+    pending_accel: Option<[f32; 3]>,
+    pending_gyro: Option<[f32; 3]>,
+}
+
+impl<B: RegisterBus> Lsm6dsx<B> {
+    pub fn new(bus: B) -> Self {
+        Self {
+            bus,
+            config: ImuConfig::default(),
+            id: 0,
+            accel_scale: 0.0,
+            gyro_scale: 0.0,
+            pending_accel: None,
+            pending_gyro: None,
+        }
+    }
+
+    pub fn release(self) -> B {
+        self.bus
+    }
+
+    fn configure(&mut self) -> Result<(), SensorError<B::Error>> {
+        let config = self.config;
+        let (accel_range, fs_xl) = select(&ACCEL_RANGES, config.accel_range_g);
+        let (gyro_range, fs_g) = select(&GYRO_RANGES, config.gyro_range_dps);
+        let (_, odr) = select(&RATES, config.sample_rate_hz);
+        self.bus.write_register(CTRL1_XL, odr << 4 | fs_xl << 2)?;
+        self.bus.write_register(CTRL2_G, odr << 4 | fs_g)?;
+        self.accel_scale = accel_range * STANDARD_GRAVITY / 32768.0;
+        self.gyro_scale = gyro_scale(gyro_range);
+
+        if config.fifo {
+            self.bus.write_register(FIFO_CTRL3, odr << 4 | odr)?;
+            self.bus.write_register(FIFO_CTRL4, FIFO_CONTINUOUS)?;
+        } else {
+            self.bus.write_register(FIFO_CTRL4, 0)?;
+        }
+        self.pending_accel = None;
+        self.pending_gyro = None;
+        Ok(())
+    }
+
+    fn temperature(&self, data: &[u8]) -> f32 {
+        let sensitivity = if self.id == LSM6DS3_ID { 16.0 } else { 256.0 };
+        i16_le(data) as f32 / sensitivity + 25.0
+    }
+
+    fn read_raw(&mut self) -> Result<[f32; 6], SensorError<B::Error>> {
+        let mut data = [0; 12];
+        self.bus.read_registers(OUT_TEMP_L + 2, &mut data)?;
+        let gyro = vector_le(&data[0..6], 1.0);
+        let accel = vector_le(&data[6..12], 1.0);
+        Ok([accel[0], accel[1], accel[2], gyro[0], gyro[1], gyro[2]])
+    }
+}
+
+/// rad/s per LSB at a gyro full scale
+fn gyro_scale(range_dps: f32) -> f32 {
+    GYRO_SENSITIVITY_MDPS * range_dps / 250.0 / 1000.0 * PI / 180.0
+}
+
+impl<B: RegisterBus> Imu for Lsm6dsx<B> {
+    type Error = B::Error;
+
+    fn init<D: DelayNs>(
+        &mut self,
+        config: &ImuConfig,
+        delay: &mut D,
+    ) -> Result<(), SensorError<Self::Error>> {
+        let id = self.bus.read_register(WHO_AM_I)?;
+        if ![LSM6DS3_ID, LSM6DSL_ID, LSM6DS33_ID, LSM6DSO_ID].contains(&id) {
+            return Err(SensorError::UnexpectedId(id));
+        }
+        if config.fifo && id != LSM6DSO_ID {
+            return Err(SensorError::NotSupported);
+        }
+        self.id = id;
+
+        self.bus.write_register(CTRL3_C, SW_RESET)?;
+        delay.delay_ms(10);
+        self.bus.write_register(CTRL3_C, BDU_IF_INC)?;
+
+        self.config = *config;
+        self.configure()
+    }
+
+    fn read(&mut self) -> Result<ImuSample, SensorError<Self::Error>> {
+        let mut data = [0; 14];
+        self.bus.read_registers(OUT_TEMP_L, &mut data)?;
+        Ok(ImuSample {
+            temperature: self.temperature(&data[0..2]),
+            gyro: vector_le(&data[2..8], self.gyro_scale),
+            accel: vector_le(&data[8..14], self.accel_scale),
+        })
+    }
+
+    /// FIFO words hold either an accelerometer or a gyro sample, and a sample is complete
+    /// once one of each has been read
+    fn read_fifo(&mut self, samples: &mut [ImuSample]) -> Result<usize, SensorError<Self::Error>> {
+        if !self.config.fifo {
+            return Err(SensorError::NotSupported);
+        }
+
+        let mut status = [0; 2];
+        self.bus.read_registers(FIFO_STATUS1, &mut status)?;
+        let words = (u16::from_le_bytes(status) & 0x3FF) as usize;
+        let mut temperature = [0; 2];
+        self.bus.read_registers(OUT_TEMP_L, &mut temperature)?;
+        let temperature = self.temperature(&temperature);
+
+        let mut read = 0;
+        for _ in 0..words {
+            if read == samples.len() {
+                break;
+            }
+            let mut word = [0; FIFO_WORD_BYTES];
+            self.bus.read_registers(FIFO_DATA_OUT_TAG, &mut word)?;
+            match word[0] >> 3 {
+                FIFO_TAG_ACCEL => {
+                    self.pending_accel = Some(vector_le(&word[1..], self.accel_scale))
+                }
+                FIFO_TAG_GYRO => self.pending_gyro = Some(vector_le(&word[1..], self.gyro_scale)),
+                // Timestamps, temperature and external sensors aren't enabled
+                _ => continue,
+            }
+            if let (Some(accel), Some(gyro)) = (self.pending_accel, self.pending_gyro) {
+                samples[read] = ImuSample {
+                    accel,
+                    gyro,
+                    temperature,
+                };
+                read += 1;
+                self.pending_accel = None;
+                self.pending_gyro = None;
+            }
+        }
+        Ok(read)
+    }
+
+    fn self_test<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), SensorError<Self::Error>> {
+        self.bus.write_register(CTRL1_XL, SELF_TEST_ACCEL)?;
+        self.bus.write_register(CTRL2_G, SELF_TEST_GYRO)?;
+        delay.delay_ms(100);
+        let normal = average(5, 20_000, delay, || self.read_raw())?;
+
+        self.bus
+            .write_register(CTRL5_C, SELF_TEST_ACCEL_POSITIVE | SELF_TEST_GYRO_POSITIVE)?;
+        delay.delay_ms(100);
+        let test = average(5, 20_000, delay, || self.read_raw())?;
+        self.bus.write_register(CTRL5_C, 0)?;
+        self.configure()?;
+
+        let response: [f32; 6] = core::array::from_fn(|i| test[i] - normal[i]);
+        let accel = [0, 1, 2].map(|i| response[i] * 4.0 / 32768.0);
+        let dps_per_lsb = gyro_scale(2000.0) * 180.0 / PI;
+        let gyro = [3, 4, 5].map(|i| response[i] * dps_per_lsb);
+        if within(accel, SELF_TEST_ACCEL_G) && within(gyro, SELF_TEST_GYRO_DPS) {
+            Ok(())
+        } else {
+            Err(SensorError::SelfTestFailed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{FakeRegisters, NoDelay};
+    use crate::I2cRegisters;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_lsm6dsx_init_and_read() {
+        let registers = FakeRegisters::default();
+        registers.set(DEFAULT_ADDRESS, WHO_AM_I, &[LSM6DSL_ID]);
+        // 26 °C, then 8.75 dps, 0, 0, then 0, 0, 1 g at ±4 g
+        registers.set(
+            DEFAULT_ADDRESS,
+            OUT_TEMP_L,
+            &[0, 1, 0xE8, 0x03, 0, 0, 0, 0, 0, 0, 0, 0, 0x00, 0x20],
+        );
+        let mut imu = Lsm6dsx::new(I2cRegisters::new(registers.mock(), DEFAULT_ADDRESS));
+        let config = ImuConfig {
+            accel_range_g: 4.0,
+            gyro_range_dps: 250.0,
+            sample_rate_hz: 100.0,
+            fifo: false,
+        };
+        imu.init(&config, &mut NoDelay).unwrap();
+
+        assert_eq!(
+            registers.writes(DEFAULT_ADDRESS, CTRL3_C),
+            [SW_RESET, BDU_IF_INC]
+        );
+        assert_eq!(registers.get(DEFAULT_ADDRESS, CTRL1_XL), 0x48);
+        assert_eq!(registers.get(DEFAULT_ADDRESS, CTRL2_G), 0x40);
+
+        let sample = imu.read().unwrap();
+        assert_relative_eq!(sample.temperature, 26.0);
+        assert_relative_eq!(sample.gyro[0], 8.75 * PI / 180.0, epsilon = 1e-5);
+        assert_relative_eq!(sample.accel[2], STANDARD_GRAVITY, epsilon = 1e-4);
+
+        // Only parts with the tagged FIFO support it
+        let config = ImuConfig {
+            fifo: true,
+            ..config
+        };
+        assert_eq!(
+            imu.init(&config, &mut NoDelay),
+            Err(SensorError::NotSupported)
+        );
+    }
+
+    #[test]
+    fn test_lsm6dsx_tagged_fifo() {
+        let registers = FakeRegisters::default();
+        registers.set(DEFAULT_ADDRESS, WHO_AM_I, &[LSM6DSO_ID]);
+        let mut imu = Lsm6dsx::new(I2cRegisters::new(registers.mock(), DEFAULT_ADDRESS));
+        let config = ImuConfig {
+            accel_range_g: 2.0,
+            fifo: true,
+            ..Default::default()
+        };
+        imu.init(&config, &mut NoDelay).unwrap();
+        assert_eq!(registers.get(DEFAULT_ADDRESS, FIFO_CTRL4), FIFO_CONTINUOUS);
+
+        registers.set(DEFAULT_ADDRESS, FIFO_STATUS1, &[3, 0]);
+        let accel_word = [FIFO_TAG_ACCEL << 3, 0, 0, 0, 0, 0x00, 0x40];
+        let gyro_word = [FIFO_TAG_GYRO << 3, 0, 0, 0, 0, 0, 0];
+        registers.queue_fifo(DEFAULT_ADDRESS, FIFO_DATA_OUT_TAG, &gyro_word);
+        registers.queue_fifo(DEFAULT_ADDRESS, FIFO_DATA_OUT_TAG, &accel_word);
+        registers.queue_fifo(DEFAULT_ADDRESS, FIFO_DATA_OUT_TAG, &gyro_word);
+
+        let mut samples = [ImuSample::default(); 4];
+        assert_eq!(imu.read_fifo(&mut samples).unwrap(), 1);
+        assert_relative_eq!(samples[0].accel[2], STANDARD_GRAVITY, epsilon = 1e-4);
+
+        // The last gyro word waits for the next accelerometer word
+        registers.set(DEFAULT_ADDRESS, FIFO_STATUS1, &[1, 0]);
+        registers.queue_fifo(DEFAULT_ADDRESS, FIFO_DATA_OUT_TAG, &accel_word);
+        assert_eq!(imu.read_fifo(&mut samples).unwrap(), 1);
+    }
+}
@@ -0,0 +1,298 @@
+//! InvenSense MPU-6050 6-axis IMU
+use core::f32::consts::PI;
+use embedded_hal::delay::DelayNs;
+
+use crate::{
+    average, i16_be, select, vector_be, within, Imu, ImuConfig, ImuSample, RegisterBus,
+    SensorError, STANDARD_GRAVITY,
+};
+
+/// I2C address with AD0 low. It is 0x69 with AD0 high.
+pub const DEFAULT_ADDRESS: u8 = 0x68;
+
+const SMPLRT_DIV: u8 = 0x19;
+const CONFIG: u8 = 0x1A;
+const GYRO_CONFIG: u8 = 0x1B;
+const ACCEL_CONFIG: u8 = 0x1C;
+const FIFO_EN: u8 = 0x23;
+const ACCEL_XOUT_H: u8 = 0x3B;
+const TEMP_OUT_H: u8 = 0x41;
+const USER_CTRL: u8 = 0x6A;
+const PWR_MGMT_1: u8 = 0x6B;
+const FIFO_COUNTH: u8 = 0x72;
+const FIFO_R_W: u8 = 0x74;
+const WHO_AM_I: u8 = 0x75;
+
+const CHIP_ID: u8 = 0x68;
+const DEVICE_RESET: u8 = 0x80;
+const CLKSEL_PLL_GYRO_X: u8 = 0x01;
+const FIFO_ENABLE: u8 = 0x40;
+const FIFO_RESET: u8 = 0x04;
+/// Queues the gyro and accelerometer, which makes each FIFO sample 12 bytes
+const FIFO_GYRO_ACCEL: u8 = 0x78;
+const FIFO_SAMPLE_BYTES: usize = 12;
+const SELF_TEST_ENABLE: u8 = 0xE0;
+
+/// Full scale in g and the AFS_SEL value
+const ACCEL_RANGES: [(f32, u8); 4] = [(2.0, 0), (4.0, 1), (8.0, 2), (16.0, 3)];
+/// Full scale in dps and the FS_SEL value
+const GYRO_RANGES: [(f32, u8); 4] = [(250.0, 0), (500.0, 1), (1000.0, 2), (2000.0, 3)];
+/// Low pass filter bandwidth in Hz and the DLPF_CFG value. The gyro output rate is 1 kHz
+/// with any of these.
+const LOW_PASS_FILTERS: [(f32, u8); 6] = [
+    (5.0, 6),
+    (10.0, 5),
+    (20.0, 4),
+    (42.0, 3),
+    (98.0, 2),
+    (188.0, 1),
+];
+const OUTPUT_RATE_HZ: f32 = 1000.0;
+
+/// The self-test runs at ±8 g and ±250 dps. The limits are the extremes of the factory trim
+/// values, widened by the allowed 14% deviation.
+const SELF_TEST_ACCEL_RANGE: u8 = 2;
+const SELF_TEST_ACCEL_G: (f32, f32) = (0.29, 1.05);
+const SELF_TEST_GYRO_DPS: (f32, f32) = (21.0, 110.0);
+
+pub struct Mpu6050<B> {
+    bus: B,
+    config: ImuConfig,
+    accel_scale: f32,
+    gyro_scale: f32,
+}
+
+impl<B: RegisterBus> Mpu6050<B> {
+    pub fn new(bus: B) -> Self {
+        Self {
+            bus,
+            config: ImuConfig::default(),
+            accel_scale: 0.0,
+            gyro_scale: 0.0,
+        }
+    }
+
+    pub fn release(self) -> B {
+        self.bus
+    }
+
+    fn configure(&mut self) -> Result<(), SensorError<B::Error>> {
+        let config = self.config;
+        let (accel_range, afs_sel) = select(&ACCEL_RANGES, config.accel_range_g);
+        let (gyro_range, fs_sel) = select(&GYRO_RANGES, config.gyro_range_dps);
+        let (_, dlpf_cfg) = select(&LOW_PASS_FILTERS, config.sample_rate_hz / 2.0);
+        let divider = ((OUTPUT_RATE_HZ / config.sample_rate_hz).clamp(1.0, 256.0) as u16 - 1) as u8;
+
+        self.bus.write_register(SMPLRT_DIV, divider)?;
+        self.bus.write_register(CONFIG, dlpf_cfg)?;
+        self.bus.write_register(GYRO_CONFIG, fs_sel << 3)?;
+        self.bus.write_register(ACCEL_CONFIG, afs_sel << 3)?;
+        self.accel_scale = accel_range * STANDARD_GRAVITY / 32768.0;
+        self.gyro_scale = gyro_range * PI / 180.0 / 32768.0;
+
+        if config.fifo {
+            self.bus.write_register(USER_CTRL, FIFO_RESET)?;
+            self.bus.write_register(USER_CTRL, FIFO_ENABLE)?;
+            self.bus.write_register(FIFO_EN, FIFO_GYRO_ACCEL)?;
+        } else {
+            self.bus.write_register(FIFO_EN, 0)?;
+            self.bus.write_register(USER_CTRL, 0)?;
+        }
+        Ok(())
+    }
+
+    fn read_raw(&mut self) -> Result<[f32; 6], SensorError<B::Error>> {
+        let mut data = [0; 14];
+        self.bus.read_registers(ACCEL_XOUT_H, &mut data)?;
+        let accel = vector_be(&data[0..6], 1.0);
+        let gyro = vector_be(&data[8..14], 1.0);
+        Ok([accel[0], accel[1], accel[2], gyro[0], gyro[1], gyro[2]])
+    }
+
+    fn read_temperature(&mut self) -> Result<f32, SensorError<B::Error>> {
+        let mut data = [0; 2];
+        self.bus.read_registers(TEMP_OUT_H, &mut data)?;
+        Ok(temperature(&data))
+    }
+}
+
+fn temperature(data: &[u8]) -> f32 {
+    i16_be(data) as f32 / 340.0 + 36.53
+}
+
+impl<B: RegisterBus> Imu for Mpu6050<B> {
+    type Error = B::Error;
+
+    fn init<D: DelayNs>(
+        &mut self,
+        config: &ImuConfig,
+        delay: &mut D,
+    ) -> Result<(), SensorError<Self::Error>> {
+        let id = self.bus.read_register(WHO_AM_I)?;
+        if id != CHIP_ID {
+            return Err(SensorError::UnexpectedId(id));
+        }
+
+        self.bus.write_register(PWR_MGMT_1, DEVICE_RESET)?;
+        delay.delay_ms(100);
+        self.bus.write_register(PWR_MGMT_1, CLKSEL_PLL_GYRO_X)?;
+        delay.delay_ms(10);
+
+        self.config = *config;
+        self.configure()
+    }
+
+    fn read(&mut self) -> Result<ImuSample, SensorError<Self::Error>> {
+        let mut data = [0; 14];
+        self.bus.read_registers(ACCEL_XOUT_H, &mut data)?;
+        Ok(ImuSample {
+            accel: vector_be(&data[0..6], self.accel_scale),
+            temperature: temperature(&data[6..8]),
+            gyro: vector_be(&data[8..14], self.gyro_scale),
+        })
+    }
+
+    fn read_fifo(&mut self, samples: &mut [ImuSample]) -> Result<usize, SensorError<Self::Error>> {
+        if !self.config.fifo {
+            return Err(SensorError::NotSupported);
+        }
+
+        let mut count = [0; 2];
+        self.bus.read_registers(FIFO_COUNTH, &mut count)?;
+        let queued = u16::from_be_bytes(count) as usize / FIFO_SAMPLE_BYTES;
+        // Temperature isn't queued, so every sample gets the current temperature
+        let temperature = self.read_temperature()?;
+        let read = queued.min(samples.len());
+        for sample in &mut samples[..read] {
+            let mut data = [0; FIFO_SAMPLE_BYTES];
+            self.bus.read_registers(FIFO_R_W, &mut data)?;
+            *sample = ImuSample {
+                accel: vector_be(&data[0..6], self.accel_scale),
+                gyro: vector_be(&data[6..12], self.gyro_scale),
+                temperature,
+            };
+        }
+        Ok(read)
+    }
+
+    fn self_test<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), SensorError<Self::Error>> {
+        self.bus.write_register(GYRO_CONFIG, 0)?;
+        self.bus
+            .write_register(ACCEL_CONFIG, SELF_TEST_ACCEL_RANGE << 3)?;
+        delay.delay_ms(50);
+        let normal = average(10, 2000, delay, || self.read_raw())?;
+
+        self.bus.write_register(GYRO_CONFIG, SELF_TEST_ENABLE)?;
+        self.bus
+            .write_register(ACCEL_CONFIG, SELF_TEST_ENABLE | SELF_TEST_ACCEL_RANGE << 3)?;
+        delay.delay_ms(50);
+        let test = average(10, 2000, delay, || self.read_raw())?;
+        self.configure()?;
+
+        let response: [f32; 6] = core::array::from_fn(|i| test[i] - normal[i]);
+        let accel = [0, 1, 2].map(|i| response[i] * 8.0 / 32768.0);
+        let gyro = [3, 4, 5].map(|i| response[i] * 250.0 / 32768.0);
+        if within(accel, SELF_TEST_ACCEL_G) && within(gyro, SELF_TEST_GYRO_DPS) {
+            Ok(())
+        } else {
+            Err(SensorError::SelfTestFailed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{FakeRegisters, NoDelay};
+    use crate::I2cRegisters;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_mpu6050_init_and_read() {
+        let registers = FakeRegisters::default();
+        registers.set(DEFAULT_ADDRESS, WHO_AM_I, &[CHIP_ID]);
+        // 0.5 g, -1 g, 1 g at ±4 g, 36.53 °C, then 100 dps, 0, -250 dps at ±500 dps
+        registers.set(
+            DEFAULT_ADDRESS,
+            ACCEL_XOUT_H,
+            &[
+                0x10, 0x00, 0xE0, 0x00, 0x20, 0x00, 0, 0, 0x19, 0x99, 0, 0, 0xC0, 0x00,
+            ],
+        );
+        let mut imu = Mpu6050::new(I2cRegisters::new(registers.mock(), DEFAULT_ADDRESS));
+        let config = ImuConfig {
+            accel_range_g: 3.0,
+            gyro_range_dps: 500.0,
+            sample_rate_hz: 200.0,
+            fifo: false,
+        };
+        imu.init(&config, &mut NoDelay).unwrap();
+
+        assert_eq!(
+            registers.writes(DEFAULT_ADDRESS, PWR_MGMT_1),
+            [DEVICE_RESET, CLKSEL_PLL_GYRO_X]
+        );
+        assert_eq!(registers.get(DEFAULT_ADDRESS, SMPLRT_DIV), 4);
+        assert_eq!(registers.get(DEFAULT_ADDRESS, CONFIG), 1);
+        assert_eq!(registers.get(DEFAULT_ADDRESS, ACCEL_CONFIG), 1 << 3);
+        assert_eq!(registers.get(DEFAULT_ADDRESS, GYRO_CONFIG), 1 << 3);
+
+        let sample = imu.read().unwrap();
+        let g = STANDARD_GRAVITY;
+        for (actual, expected) in sample.accel.iter().zip([0.5 * g, -g, g]) {
+            assert_relative_eq!(*actual, expected, epsilon = 1e-4);
+        }
+        let dps = PI / 180.0;
+        for (actual, expected) in sample.gyro.iter().zip([100.0 * dps, 0.0, -250.0 * dps]) {
+            assert_relative_eq!(*actual, expected, epsilon = 1e-3);
+        }
+        assert_relative_eq!(sample.temperature, 36.53);
+        assert_eq!(
+            imu.read_fifo(&mut [ImuSample::default()]),
+            Err(SensorError::NotSupported)
+        );
+    }
+
+    #[test]
+    fn test_mpu6050_fifo() {
+        let registers = FakeRegisters::default();
+        registers.set(DEFAULT_ADDRESS, WHO_AM_I, &[CHIP_ID]);
+        registers.set(DEFAULT_ADDRESS, FIFO_COUNTH, &[0, 36]);
+        for i in 0..3 {
+            registers.queue_fifo(
+                DEFAULT_ADDRESS,
+                FIFO_R_W,
+                &[0, i, 0, 0, 0x40, 0, 0, 0, 0, 0, 0, 0],
+            );
+        }
+        let mut imu = Mpu6050::new(I2cRegisters::new(registers.mock(), DEFAULT_ADDRESS));
+        let config = ImuConfig {
+            accel_range_g: 2.0,
+            fifo: true,
+            ..Default::default()
+        };
+        imu.init(&config, &mut NoDelay).unwrap();
+        assert_eq!(
+            registers.writes(DEFAULT_ADDRESS, USER_CTRL),
+            [FIFO_RESET, FIFO_ENABLE]
+        );
+        assert_eq!(registers.get(DEFAULT_ADDRESS, FIFO_EN), FIFO_GYRO_ACCEL);
+
+        // Only two samples fit, so one stays queued
+        let mut samples = [ImuSample::default(); 2];
+        assert_eq!(imu.read_fifo(&mut samples).unwrap(), 2);
+        assert_relative_eq!(samples[1].accel[0], STANDARD_GRAVITY / 16384.0);
+        assert_relative_eq!(samples[1].accel[2], STANDARD_GRAVITY);
+    }
+
+    #[test]
+    fn test_mpu6050_wrong_chip() {
+        let registers = FakeRegisters::default();
+        registers.set(DEFAULT_ADDRESS, WHO_AM_I, &[0x70]);
+        let mut imu = Mpu6050::new(I2cRegisters::new(registers.mock(), DEFAULT_ADDRESS));
+        assert_eq!(
+            imu.init(&ImuConfig::default(), &mut NoDelay),
+            Err(SensorError::UnexpectedId(0x70))
+        );
+    }
+}
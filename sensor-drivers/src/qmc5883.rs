@@ -0,0 +1,167 @@
+//! QST QMC5883L 3-axis magnetometer
+use embedded_hal::delay::DelayNs;
+
+use crate::{select, vector_le, MagConfig, MagSample, Magnetometer, RegisterBus, SensorError};
+
+pub const DEFAULT_ADDRESS: u8 = 0x0D;
+
+const DATA_X_LSB: u8 = 0x00;
+const STATUS: u8 = 0x06;
+const CONTROL_1: u8 = 0x09;
+const CONTROL_2: u8 = 0x0A;
+const SET_RESET_PERIOD: u8 = 0x0B;
+const CHIP_ID: u8 = 0x0D;
+
+const ID: u8 = 0xFF;
+const SOFT_RESET: u8 = 0x80;
+/// The datasheet recommends a set/reset period of 1
+const SET_RESET_PERIOD_VALUE: u8 = 0x01;
+const CONTINUOUS_MODE: u8 = 0x01;
+const DATA_READY: u8 = 0x01;
+const OVERFLOW: u8 = 0x02;
+
+/// Oversampling ratio and the OSR value
+const OVERSAMPLING: [(f32, u8); 4] = [(64.0, 3), (128.0, 2), (256.0, 1), (512.0, 0)];
+/// Full scale in gauss and the RNG value
+const RANGES: [(f32, u8); 2] = [(2.0, 0), (8.0, 1)];
+/// Output data rate in Hz and the ODR value
+const RATES: [(f32, u8); 4] = [(10.0, 0), (50.0, 1), (100.0, 2), (200.0, 3)];
+/// Counts per gauss at ±2 gauss. 1 gauss is 100 µT.
+const SENSITIVITY_2_GAUSS: f32 = 12000.0;
+
+/// The earth's field is 25 to 65 µT, so this leaves room for nearby hard iron
+const SELF_TEST_FIELD_UT: (f32, f32) = (10.0, 150.0);
+const SELF_TEST_ATTEMPTS: usize = 10;
+
+pub struct Qmc5883<B> {
+    bus: B,
+    scale: f32,
+    period_ms: u32,
+}
+
+impl<B: RegisterBus> Qmc5883<B> {
+    pub fn new(bus: B) -> Self {
+        Self {
+            bus,
+            scale: 0.0,
+            period_ms: 0,
+        }
+    }
+
+    pub fn release(self) -> B {
+        self.bus
+    }
+}
+
+impl<B: RegisterBus> Magnetometer for Qmc5883<B> {
+    type Error = B::Error;
+
+    fn init<D: DelayNs>(
+        &mut self,
+        config: &MagConfig,
+        delay: &mut D,
+    ) -> Result<(), SensorError<Self::Error>> {
+        let id = self.bus.read_register(CHIP_ID)?;
+        if id != ID {
+            return Err(SensorError::UnexpectedId(id));
+        }
+
+        self.bus.write_register(CONTROL_2, SOFT_RESET)?;
+        delay.delay_ms(10);
+        self.bus
+            .write_register(SET_RESET_PERIOD, SET_RESET_PERIOD_VALUE)?;
+
+        let (_, osr) = select(&OVERSAMPLING, config.oversampling as f32);
+        let (range, rng) = select(&RANGES, config.range_gauss);
+        let (rate, odr) = select(&RATES, config.sample_rate_hz);
+        self.bus
+            .write_register(CONTROL_1, osr << 6 | rng << 4 | odr << 2 | CONTINUOUS_MODE)?;
+        self.scale = 100.0 / (SENSITIVITY_2_GAUSS * 2.0 / range);
+        self.period_ms = (1000.0 / rate) as u32;
+        Ok(())
+    }
+
+    /// Returns `None` until a new sample is ready, and for samples where an axis overflowed
+    fn read(&mut self) -> Result<Option<MagSample>, SensorError<Self::Error>> {
+        let status = self.bus.read_register(STATUS)?;
+        if status & DATA_READY == 0 {
+            return Ok(None);
+        }
+        // Reading the data clears the ready flag, even for an overflowed sample
+        let mut data = [0; 6];
+        self.bus.read_registers(DATA_X_LSB, &mut data)?;
+        if status & OVERFLOW != 0 {
+            return Ok(None);
+        }
+        Ok(Some(MagSample {
+            field: vector_le(&data, self.scale),
+        }))
+    }
+
+    fn self_test<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), SensorError<Self::Error>> {
+        for _ in 0..SELF_TEST_ATTEMPTS {
+            delay.delay_ms(self.period_ms);
+            if let Some(sample) = self.read()? {
+                let squared = sample.field.iter().map(|f| f * f).sum::<f32>();
+                let (min, max) = SELF_TEST_FIELD_UT;
+                return if (min * min..=max * max).contains(&squared) {
+                    Ok(())
+                } else {
+                    Err(SensorError::SelfTestFailed)
+                };
+            }
+        }
+        Err(SensorError::Timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{FakeRegisters, NoDelay};
+    use crate::I2cRegisters;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_qmc5883_init_and_read() {
+        let registers = FakeRegisters::default();
+        registers.set(DEFAULT_ADDRESS, CHIP_ID, &[ID]);
+        let mut mag = Qmc5883::new(I2cRegisters::new(registers.mock(), DEFAULT_ADDRESS));
+        let config = MagConfig {
+            range_gauss: 2.0,
+            sample_rate_hz: 75.0,
+            oversampling: 256,
+        };
+        mag.init(&config, &mut NoDelay).unwrap();
+
+        assert_eq!(registers.writes(DEFAULT_ADDRESS, CONTROL_2), [SOFT_RESET]);
+        assert_eq!(registers.get(DEFAULT_ADDRESS, SET_RESET_PERIOD), 1);
+        assert_eq!(registers.get(DEFAULT_ADDRESS, CONTROL_1), 0x49);
+
+        assert_eq!(mag.read().unwrap(), None);
+
+        // 0.25, -0.1 and 0.4 gauss
+        registers.set(
+            DEFAULT_ADDRESS,
+            DATA_X_LSB,
+            &[0xB8, 0x0B, 0x50, 0xFB, 0xC0, 0x12, DATA_READY],
+        );
+        let sample = mag.read().unwrap().unwrap();
+        for (actual, expected) in sample.field.iter().zip([25.0, -10.0, 40.0]) {
+            assert_relative_eq!(*actual, expected, epsilon = 1e-4);
+        }
+        mag.self_test(&mut NoDelay).unwrap();
+
+        registers.set(DEFAULT_ADDRESS, STATUS, &[DATA_READY | OVERFLOW]);
+        assert_eq!(mag.read().unwrap(), None);
+    }
+
+    #[test]
+    fn test_qmc5883_self_test_timeout() {
+        let registers = FakeRegisters::default();
+        registers.set(DEFAULT_ADDRESS, CHIP_ID, &[ID]);
+        let mut mag = Qmc5883::new(I2cRegisters::new(registers.mock(), DEFAULT_ADDRESS));
+        mag.init(&MagConfig::default(), &mut NoDelay).unwrap();
+        assert_eq!(mag.self_test(&mut NoDelay), Err(SensorError::Timeout));
+    }
+}
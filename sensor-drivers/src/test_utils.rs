@@ -0,0 +1,123 @@
+//! Simulated sensor registers behind [`MockI2cProtocol`]
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::vec::Vec;
+
+use protocols::MockI2cProtocol;
+
+#[derive(Default)]
+struct State {
+    registers: HashMap<(u8, u8), u8>,
+    fifos: HashMap<(u8, u8), VecDeque<u8>>,
+    read_only: HashSet<(u8, u8)>,
+    writes: Vec<(u8, u8, u8)>,
+}
+
+impl State {
+    fn read(&mut self, address: u8, register: u8, buffer: &mut [u8]) {
+        let mut register = register;
+        for byte in buffer {
+            if let Some(fifo) = self.fifos.get_mut(&(address, register)) {
+                // FIFO data registers don't auto-increment
+                *byte = fifo.pop_front().unwrap_or(0);
+                continue;
+            }
+            *byte = self
+                .registers
+                .get(&(address, register))
+                .copied()
+                .unwrap_or(0);
+            register = register.wrapping_add(1);
+        }
+    }
+}
+
+/// Registers of the devices on an I2C bus. Registers read as zero until set, FIFO registers
+/// pop queued bytes, and read-only registers keep their value when written.
+#[derive(Clone, Default)]
+pub struct FakeRegisters {
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeRegisters {
+    pub fn set(&self, address: u8, register: u8, values: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+        for (i, value) in values.iter().enumerate() {
+            state
+                .registers
+                .insert((address, register + i as u8), *value);
+        }
+    }
+
+    pub fn set_read_only(&self, address: u8, register: u8, value: u8) {
+        self.set(address, register, &[value]);
+        self.state
+            .lock()
+            .unwrap()
+            .read_only
+            .insert((address, register));
+    }
+
+    pub fn queue_fifo(&self, address: u8, register: u8, bytes: &[u8]) {
+        self.state
+            .lock()
+            .unwrap()
+            .fifos
+            .entry((address, register))
+            .or_default()
+            .extend(bytes);
+    }
+
+    pub fn get(&self, address: u8, register: u8) -> u8 {
+        let mut value = [0];
+        self.state
+            .lock()
+            .unwrap()
+            .read(address, register, &mut value);
+        value[0]
+    }
+
+    /// Values written to `register`, oldest first
+    pub fn writes(&self, address: u8, register: u8) -> Vec<u8> {
+        self.state
+            .lock()
+            .unwrap()
+            .writes
+            .iter()
+            .filter(|(a, r, _)| *a == address && *r == register)
+            .map(|(_, _, value)| *value)
+            .collect()
+    }
+
+    /// A mock I2C bus backed by these registers
+    pub fn mock(&self) -> MockI2cProtocol {
+        let mut mock = MockI2cProtocol::new();
+        let state = self.state.clone();
+        mock.expect_write_read()
+            .returning(move |address, write, read| {
+                state.lock().unwrap().read(address, write[0], read);
+                Ok(())
+            });
+        let state = self.state.clone();
+        mock.expect_write().returning(move |address, write| {
+            let mut state = state.lock().unwrap();
+            let register = write[0];
+            for (i, value) in write[1..].iter().enumerate() {
+                let register = register + i as u8;
+                state.writes.push((address, register, *value));
+                if !state.read_only.contains(&(address, register)) {
+                    state.registers.insert((address, register), *value);
+                }
+            }
+            Ok(())
+        });
+        mock
+    }
+}
+
+/// Delay that returns immediately
+pub struct NoDelay;
+
+impl embedded_hal::delay::DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
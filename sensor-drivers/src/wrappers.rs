@@ -0,0 +1,543 @@
+//! Input blocks that run the drivers on an I2C bus for the IMU, Barometer and Magnetometer
+//! blocks.
+//!
+//! Each wrapper configures its sensor from the block parameters on the first tick, running
+//! the self-test if the parameters ask for it. A sensor that fails to configure is retried on
+//! later ticks, while one that fails its self-test is not used. Alongside the sample, each
+//! tick outputs how many samples it averaged, which is zero when there was no new sample or
+//! the read failed.
+use core::fmt::Debug;
+use corelib_traits::{Context, InputBlock, Matrix, PassBy};
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+use log::warn;
+use pictorus_core_blocks::{
+    BarometerBlockParams, BarometerSensor, ImuBlockParams, ImuSensor, MagnetometerBlockParams,
+    MagnetometerSensor,
+};
+
+use crate::{
+    BaroConfig, BaroSample, Barometer, Bmi088, Bmp280, Bmp390, I2cRegisters, Icm20948, Imu,
+    ImuConfig, ImuSample, Lsm6dsx, MagConfig, MagSample, Magnetometer, Mpu6050, Qmc5883,
+    SensorError, SharedI2c,
+};
+
+/// Samples read from a FIFO each tick. The rest stay queued for the next tick.
+const FIFO_SAMPLES: usize = 32;
+
+type Registers<I> = I2cRegisters<SharedI2c<I>>;
+
+macro_rules! dispatch {
+    ($driver:expr, $enum:ident { $($variant:ident),* }, $inner:ident => $body:expr) => {
+        match $driver {
+            $($enum::$variant($inner) => $body,)*
+        }
+    };
+}
+
+enum Connection<T> {
+    Disconnected,
+    Connected(T),
+    SelfTestFailed,
+}
+
+/// Configures `driver` and optionally self-tests it, logging failures
+fn connect<T, E: Debug, D>(
+    name: &str,
+    mut driver: T,
+    delay: &mut D,
+    run_self_test: bool,
+    init: impl FnOnce(&mut T, &mut D) -> Result<(), SensorError<E>>,
+    self_test: impl FnOnce(&mut T, &mut D) -> Result<(), SensorError<E>>,
+) -> Connection<T> {
+    if let Err(err) = init(&mut driver, delay) {
+        warn!("Failed to configure {name}: {err:?}");
+        return Connection::Disconnected;
+    }
+    if run_self_test {
+        if let Err(err) = self_test(&mut driver, delay) {
+            warn!("{name} self-test failed: {err:?}");
+            return Connection::SelfTestFailed;
+        }
+    }
+    Connection::Connected(driver)
+}
+
+/// Logs a failed read and returns the number of samples read, or zero on failure
+fn sample_count<E: Debug>(name: &str, result: Result<usize, SensorError<E>>) -> usize {
+    result.unwrap_or_else(|err| {
+        warn!("Failed to read {name}: {err:?}");
+        0
+    })
+}
+
+/// Averages the values of each field
+fn mean<const N: usize>(values: impl ExactSizeIterator<Item = [f32; N]>) -> [f32; N] {
+    let count = values.len() as f32;
+    let mut sum = [0.0; N];
+    for value in values {
+        for (sum, value) in sum.iter_mut().zip(value) {
+            *sum += value;
+        }
+    }
+    sum.map(|s| s / count)
+}
+
+fn vector(values: [f32; 3]) -> Matrix<1, 3, f64> {
+    Matrix {
+        data: values.map(|v| [v as f64]),
+    }
+}
+
+enum ImuDriver<I: I2c> {
+    Mpu6050(Mpu6050<Registers<I>>),
+    Icm20948(Icm20948<Registers<I>>),
+    Bmi088(Bmi088<Registers<I>, Registers<I>>),
+    Lsm6dsx(Lsm6dsx<Registers<I>>),
+}
+
+macro_rules! dispatch_imu {
+    ($driver:expr, $imu:ident => $body:expr) => {
+        dispatch!($driver, ImuDriver { Mpu6050, Icm20948, Bmi088, Lsm6dsx }, $imu => $body)
+    };
+}
+
+impl<I: I2c> Imu for ImuDriver<I> {
+    type Error = I::Error;
+
+    fn init<D: DelayNs>(
+        &mut self,
+        config: &ImuConfig,
+        delay: &mut D,
+    ) -> Result<(), SensorError<Self::Error>> {
+        dispatch_imu!(self, imu => imu.init(config, delay))
+    }
+
+    fn read(&mut self) -> Result<ImuSample, SensorError<Self::Error>> {
+        dispatch_imu!(self, imu => imu.read())
+    }
+
+    fn read_fifo(&mut self, samples: &mut [ImuSample]) -> Result<usize, SensorError<Self::Error>> {
+        dispatch_imu!(self, imu => imu.read_fifo(samples))
+    }
+
+    fn self_test<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), SensorError<Self::Error>> {
+        dispatch_imu!(self, imu => imu.self_test(delay))
+    }
+}
+
+/// Reads an IMU for the IMU Block. The outputs are the acceleration in m/s², the angular rate
+/// in rad/s, the temperature in °C and the number of samples averaged.
+pub struct ImuWrapper<I: I2c, D> {
+    i2c: SharedI2c<I>,
+    delay: D,
+    connection: Connection<ImuDriver<I>>,
+    fifo: [ImuSample; FIFO_SAMPLES],
+    accel: Matrix<1, 3, f64>,
+    gyro: Matrix<1, 3, f64>,
+    temperature: f64,
+}
+
+impl<I: I2c, D: DelayNs> ImuWrapper<I, D> {
+    pub fn new(i2c: I, delay: D) -> Self {
+        Self {
+            i2c: SharedI2c::new(i2c),
+            delay,
+            connection: Connection::Disconnected,
+            fifo: [ImuSample::default(); FIFO_SAMPLES],
+            accel: Matrix::zeroed(),
+            gyro: Matrix::zeroed(),
+            temperature: 0.0,
+        }
+    }
+
+    fn connect(&mut self, parameters: &ImuBlockParams) {
+        let registers = |address| I2cRegisters::new(self.i2c.clone(), address);
+        let driver = match parameters.sensor {
+            ImuSensor::Mpu6050 => ImuDriver::Mpu6050(Mpu6050::new(registers(parameters.address))),
+            ImuSensor::Icm20948 => {
+                ImuDriver::Icm20948(Icm20948::new(registers(parameters.address)))
+            }
+            ImuSensor::Bmi088 => ImuDriver::Bmi088(Bmi088::new(
+                registers(parameters.address),
+                registers(parameters.gyro_address),
+            )),
+            ImuSensor::Lsm6dsx => ImuDriver::Lsm6dsx(Lsm6dsx::new(registers(parameters.address))),
+        };
+        let config = ImuConfig {
+            accel_range_g: parameters.accel_range_g as f32,
+            gyro_range_dps: parameters.gyro_range_dps as f32,
+            sample_rate_hz: parameters.sample_rate_hz as f32,
+            fifo: parameters.use_fifo,
+        };
+        self.connection = connect(
+            "IMU",
+            driver,
+            &mut self.delay,
+            parameters.run_self_test,
+            |imu, delay| imu.init(&config, delay),
+            |imu, delay| imu.self_test(delay),
+        );
+    }
+}
+
+impl<I: I2c, D: DelayNs> InputBlock for ImuWrapper<I, D> {
+    type Output = (Matrix<1, 3, f64>, Matrix<1, 3, f64>, f64, f64);
+    type Parameters = ImuBlockParams;
+
+    fn input(
+        &mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        if let Connection::Disconnected = self.connection {
+            self.connect(parameters);
+        }
+        let Connection::Connected(imu) = &mut self.connection else {
+            return (&self.accel, &self.gyro, self.temperature, 0.0);
+        };
+
+        let count = if parameters.use_fifo {
+            let count = sample_count("IMU", imu.read_fifo(&mut self.fifo));
+            if count > 0 {
+                let samples = &self.fifo[..count];
+                self.accel = vector(mean(samples.iter().map(|s| s.accel)));
+                self.gyro = vector(mean(samples.iter().map(|s| s.gyro)));
+                let [temperature] = mean(samples.iter().map(|s| [s.temperature]));
+                self.temperature = temperature as f64;
+            }
+            count
+        } else {
+            let sample = imu.read().map(|sample| {
+                self.accel = vector(sample.accel);
+                self.gyro = vector(sample.gyro);
+                self.temperature = sample.temperature as f64;
+                1
+            });
+            sample_count("IMU", sample)
+        };
+
+        (&self.accel, &self.gyro, self.temperature, count as f64)
+    }
+}
+
+enum BaroDriver<I: I2c> {
+    Bmp280(Bmp280<Registers<I>>),
+    Bmp390(Bmp390<Registers<I>>),
+}
+
+macro_rules! dispatch_baro {
+    ($driver:expr, $baro:ident => $body:expr) => {
+        dispatch!($driver, BaroDriver { Bmp280, Bmp390 }, $baro => $body)
+    };
+}
+
+impl<I: I2c> Barometer for BaroDriver<I> {
+    type Error = I::Error;
+
+    fn init<D: DelayNs>(
+        &mut self,
+        config: &BaroConfig,
+        delay: &mut D,
+    ) -> Result<(), SensorError<Self::Error>> {
+        dispatch_baro!(self, baro => baro.init(config, delay))
+    }
+
+    fn read(&mut self) -> Result<BaroSample, SensorError<Self::Error>> {
+        dispatch_baro!(self, baro => baro.read())
+    }
+
+    fn read_fifo(&mut self, samples: &mut [BaroSample]) -> Result<usize, SensorError<Self::Error>> {
+        dispatch_baro!(self, baro => baro.read_fifo(samples))
+    }
+
+    fn self_test<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), SensorError<Self::Error>> {
+        dispatch_baro!(self, baro => baro.self_test(delay))
+    }
+}
+
+/// Reads a barometer for the Barometer Block. The outputs are the pressure in Pa, the
+/// temperature in °C and the number of samples averaged.
+pub struct BarometerWrapper<I: I2c, D> {
+    i2c: SharedI2c<I>,
+    delay: D,
+    connection: Connection<BaroDriver<I>>,
+    fifo: [BaroSample; FIFO_SAMPLES],
+    sample: BaroSample,
+}
+
+impl<I: I2c, D: DelayNs> BarometerWrapper<I, D> {
+    pub fn new(i2c: I, delay: D) -> Self {
+        Self {
+            i2c: SharedI2c::new(i2c),
+            delay,
+            connection: Connection::Disconnected,
+            fifo: [BaroSample::default(); FIFO_SAMPLES],
+            sample: BaroSample::default(),
+        }
+    }
+
+    fn connect(&mut self, parameters: &BarometerBlockParams) {
+        let registers = I2cRegisters::new(self.i2c.clone(), parameters.address);
+        let driver = match parameters.sensor {
+            BarometerSensor::Bmp280 => BaroDriver::Bmp280(Bmp280::new(registers)),
+            BarometerSensor::Bmp390 => BaroDriver::Bmp390(Bmp390::new(registers)),
+        };
+        let config = BaroConfig {
+            oversampling: parameters.oversampling,
+            iir_coefficient: parameters.iir_coefficient,
+            sample_rate_hz: parameters.sample_rate_hz as f32,
+            fifo: parameters.use_fifo,
+        };
+        self.connection = connect(
+            "barometer",
+            driver,
+            &mut self.delay,
+            parameters.run_self_test,
+            |baro, delay| baro.init(&config, delay),
+            |baro, delay| baro.self_test(delay),
+        );
+    }
+}
+
+impl<I: I2c, D: DelayNs> InputBlock for BarometerWrapper<I, D> {
+    type Output = (f64, f64, f64);
+    type Parameters = BarometerBlockParams;
+
+    fn input(
+        &mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        if let Connection::Disconnected = self.connection {
+            self.connect(parameters);
+        }
+
+        let mut count = 0;
+        if let Connection::Connected(baro) = &mut self.connection {
+            count = if parameters.use_fifo {
+                let count = sample_count("barometer", baro.read_fifo(&mut self.fifo));
+                if count > 0 {
+                    let samples = &self.fifo[..count];
+                    let [pressure, temperature] =
+                        mean(samples.iter().map(|s| [s.pressure, s.temperature]));
+                    self.sample = BaroSample {
+                        pressure,
+                        temperature,
+                    };
+                }
+                count
+            } else {
+                let sample = baro.read().map(|sample| {
+                    self.sample = sample;
+                    1
+                });
+                sample_count("barometer", sample)
+            };
+        }
+
+        (
+            self.sample.pressure as f64,
+            self.sample.temperature as f64,
+            count as f64,
+        )
+    }
+}
+
+enum MagDriver<I: I2c> {
+    Qmc5883(Qmc5883<Registers<I>>),
+}
+
+impl<I: I2c> Magnetometer for MagDriver<I> {
+    type Error = I::Error;
+
+    fn init<D: DelayNs>(
+        &mut self,
+        config: &MagConfig,
+        delay: &mut D,
+    ) -> Result<(), SensorError<Self::Error>> {
+        dispatch!(self, MagDriver { Qmc5883 }, mag => mag.init(config, delay))
+    }
+
+    fn read(&mut self) -> Result<Option<MagSample>, SensorError<Self::Error>> {
+        dispatch!(self, MagDriver { Qmc5883 }, mag => mag.read())
+    }
+
+    fn self_test<D: DelayNs>(&mut self, delay: &mut D) -> Result<(), SensorError<Self::Error>> {
+        dispatch!(self, MagDriver { Qmc5883 }, mag => mag.self_test(delay))
+    }
+}
+
+/// Reads a magnetometer for the Magnetometer Block. The outputs are the field in µT and the
+/// number of samples read.
+pub struct MagnetometerWrapper<I: I2c, D> {
+    i2c: SharedI2c<I>,
+    delay: D,
+    connection: Connection<MagDriver<I>>,
+    field: Matrix<1, 3, f64>,
+}
+
+impl<I: I2c, D: DelayNs> MagnetometerWrapper<I, D> {
+    pub fn new(i2c: I, delay: D) -> Self {
+        Self {
+            i2c: SharedI2c::new(i2c),
+            delay,
+            connection: Connection::Disconnected,
+            field: Matrix::zeroed(),
+        }
+    }
+
+    fn connect(&mut self, parameters: &MagnetometerBlockParams) {
+        let registers = I2cRegisters::new(self.i2c.clone(), parameters.address);
+        let driver = match parameters.sensor {
+            MagnetometerSensor::Qmc5883 => MagDriver::Qmc5883(Qmc5883::new(registers)),
+        };
+        let config = MagConfig {
+            range_gauss: parameters.range_gauss as f32,
+            sample_rate_hz: parameters.sample_rate_hz as f32,
+            oversampling: parameters.oversampling,
+        };
+        self.connection = connect(
+            "magnetometer",
+            driver,
+            &mut self.delay,
+            parameters.run_self_test,
+            |mag, delay| mag.init(&config, delay),
+            |mag, delay| mag.self_test(delay),
+        );
+    }
+}
+
+impl<I: I2c, D: DelayNs> InputBlock for MagnetometerWrapper<I, D> {
+    type Output = (Matrix<1, 3, f64>, f64);
+    type Parameters = MagnetometerBlockParams;
+
+    fn input(
+        &mut self,
+        parameters: &Self::Parameters,
+        _context: &dyn Context,
+    ) -> PassBy<'_, Self::Output> {
+        if let Connection::Disconnected = self.connection {
+            self.connect(parameters);
+        }
+
+        let mut count = 0;
+        if let Connection::Connected(mag) = &mut self.connection {
+            let sample = mag.read().map(|sample| match sample {
+                Some(sample) => {
+                    self.field = vector(sample.field);
+                    1
+                }
+                None => 0,
+            });
+            count = sample_count("magnetometer", sample);
+        }
+
+        (&self.field, count as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{FakeRegisters, NoDelay};
+    use crate::STANDARD_GRAVITY;
+    use approx::assert_relative_eq;
+    use corelib_traits_testing::StubContext;
+
+    const MPU6050_ADDRESS: f64 = 0x68 as f64;
+
+    fn imu_parameters(use_fifo: bool, run_self_test: bool) -> ImuBlockParams {
+        ImuBlockParams::new(
+            "MPU6050",
+            MPU6050_ADDRESS,
+            0.0,
+            2.0,
+            250.0,
+            100.0,
+            use_fifo,
+            run_self_test,
+            [0.0; 3],
+            [1.0; 3],
+            [0.0; 3],
+            100.0,
+        )
+    }
+
+    #[test]
+    fn test_imu_wrapper_averages_fifo() {
+        let registers = FakeRegisters::default();
+        let address = MPU6050_ADDRESS as u8;
+        registers.set(address, 0x75, &[0x68]);
+        // Two samples of 0.5 g and 1 g on z, 24 bytes queued
+        registers.set(address, 0x72, &[0, 24]);
+        registers.queue_fifo(address, 0x74, &[0, 0, 0, 0, 0x20, 0, 0, 0, 0, 0, 0, 0]);
+        registers.queue_fifo(address, 0x74, &[0, 0, 0, 0, 0x40, 0, 0, 0, 0, 0, 0, 0]);
+
+        let context = StubContext::default();
+        let mut wrapper = ImuWrapper::new(registers.mock(), NoDelay);
+        let parameters = imu_parameters(true, false);
+        let (accel, _, _, count) = wrapper.input(&parameters, &context);
+        assert_eq!(count, 2.0);
+        assert_relative_eq!(
+            accel.data[2][0],
+            0.75 * STANDARD_GRAVITY as f64,
+            epsilon = 1e-4
+        );
+
+        // The FIFO is empty, so the last sample holds with a count of zero
+        registers.set(address, 0x72, &[0, 0]);
+        let (accel, _, _, count) = wrapper.input(&parameters, &context);
+        assert_eq!(count, 0.0);
+        assert_relative_eq!(
+            accel.data[2][0],
+            0.75 * STANDARD_GRAVITY as f64,
+            epsilon = 1e-4
+        );
+    }
+
+    #[test]
+    fn test_imu_wrapper_retries_and_self_test() {
+        let registers = FakeRegisters::default();
+        let address = MPU6050_ADDRESS as u8;
+        let context = StubContext::default();
+        let mut wrapper = ImuWrapper::new(registers.mock(), NoDelay);
+        let parameters = imu_parameters(false, true);
+
+        // Nothing answers yet
+        registers.set(address, 0x75, &[0]);
+        assert_eq!(wrapper.input(&parameters, &context).3, 0.0);
+        assert!(matches!(wrapper.connection, Connection::Disconnected));
+
+        // The sensor now answers, but the self-test sees no response
+        registers.set(address, 0x75, &[0x68]);
+        assert_eq!(wrapper.input(&parameters, &context).3, 0.0);
+        assert!(matches!(wrapper.connection, Connection::SelfTestFailed));
+    }
+
+    #[test]
+    fn test_magnetometer_wrapper() {
+        let registers = FakeRegisters::default();
+        registers.set(0x0D, 0x0D, &[0xFF]);
+        let context = StubContext::default();
+        let mut wrapper = MagnetometerWrapper::new(registers.mock(), NoDelay);
+        let parameters = MagnetometerBlockParams::new(
+            "QMC5883",
+            0x0D as f64,
+            2.0,
+            50.0,
+            512.0,
+            false,
+            [0.0; 3],
+            [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+            100.0,
+        );
+        assert_eq!(wrapper.input(&parameters, &context).1, 0.0);
+
+        // 0.5 gauss on x
+        registers.set(0x0D, 0x00, &[0x70, 0x17, 0, 0, 0, 0, 0x01]);
+        let (field, count) = wrapper.input(&parameters, &context);
+        assert_eq!(count, 1.0);
+        assert_relative_eq!(field.data[0][0], 50.0, epsilon = 1e-4);
+    }
+}
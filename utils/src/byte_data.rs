@@ -1,6 +1,11 @@
 use crate::ParseEnumError;
 use alloc::{str::FromStr, vec::Vec};
 
+pub mod checksum;
+pub mod crc;
+pub mod framing;
+pub mod mavlink;
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum ByteDataError {
     ParseEnumError,
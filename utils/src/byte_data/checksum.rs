@@ -0,0 +1,241 @@
+//! Checksums over byte data
+//!
+//! [`ChecksumAlgorithm`] covers parametrised CRCs (see [`super::crc`]) as well as the simple
+//! additive checksums commonly found in sensor and ECU protocols. Checksums are stored on the
+//! wire as an unsigned integer of [`ChecksumAlgorithm::byte_size`] bytes in the configured
+//! byte order.
+use alloc::{str::FromStr, vec::Vec};
+
+use super::crc::CrcAlgorithm;
+use super::ByteOrderSpec;
+use crate::ParseEnumError;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ChecksumAlgorithm {
+    Crc(CrcAlgorithm),
+    /// Fletcher-16 over bytes
+    Fletcher16,
+    /// Fletcher-32 over little-endian 16-bit words, odd length data is zero padded
+    Fletcher32,
+    /// XOR of all bytes
+    Xor8,
+    /// Sum of all bytes modulo 256
+    Sum8,
+}
+
+/// The kinds of checksum that can be selected by name. CRCs additionally need their
+/// polynomial, initial value, reflection and output XOR specified.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ChecksumKind {
+    Crc8,
+    Crc16,
+    Crc32,
+    Fletcher16,
+    Fletcher32,
+    Xor8,
+    Sum8,
+}
+
+impl FromStr for ChecksumKind {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Crc8" => Ok(Self::Crc8),
+            "Crc16" => Ok(Self::Crc16),
+            "Crc32" => Ok(Self::Crc32),
+            "Fletcher16" => Ok(Self::Fletcher16),
+            "Fletcher32" => Ok(Self::Fletcher32),
+            "Xor8" => Ok(Self::Xor8),
+            "Sum8" => Ok(Self::Sum8),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+impl ChecksumAlgorithm {
+    /// Builds an algorithm from its kind. The CRC parameters are ignored for non-CRC kinds.
+    /// Returns `None` if the CRC parameters do not fit in the CRC width.
+    pub fn from_kind(
+        kind: ChecksumKind,
+        poly: u32,
+        init: u32,
+        refin: bool,
+        refout: bool,
+        xorout: u32,
+    ) -> Option<Self> {
+        let crc = |width| CrcAlgorithm::new(width, poly, init, refin, refout, xorout);
+        let algorithm = match kind {
+            ChecksumKind::Crc8 => Self::Crc(crc(8)?),
+            ChecksumKind::Crc16 => Self::Crc(crc(16)?),
+            ChecksumKind::Crc32 => Self::Crc(crc(32)?),
+            ChecksumKind::Fletcher16 => Self::Fletcher16,
+            ChecksumKind::Fletcher32 => Self::Fletcher32,
+            ChecksumKind::Xor8 => Self::Xor8,
+            ChecksumKind::Sum8 => Self::Sum8,
+        };
+        Some(algorithm)
+    }
+
+    /// Number of bytes needed to store the checksum
+    pub fn byte_size(&self) -> usize {
+        match self {
+            ChecksumAlgorithm::Crc(crc) => crc.byte_size(),
+            ChecksumAlgorithm::Fletcher16 => 2,
+            ChecksumAlgorithm::Fletcher32 => 4,
+            ChecksumAlgorithm::Xor8 | ChecksumAlgorithm::Sum8 => 1,
+        }
+    }
+
+    /// Computes the checksum of `data`
+    pub fn checksum(&self, data: &[u8]) -> u32 {
+        match self {
+            ChecksumAlgorithm::Crc(crc) => crc.checksum(data),
+            ChecksumAlgorithm::Fletcher16 => fletcher16(data),
+            ChecksumAlgorithm::Fletcher32 => fletcher32(data),
+            ChecksumAlgorithm::Xor8 => data.iter().fold(0u8, |acc, b| acc ^ b) as u32,
+            ChecksumAlgorithm::Sum8 => data.iter().fold(0u8, |acc, b| acc.wrapping_add(*b)) as u32,
+        }
+    }
+
+    /// Appends `data` followed by its checksum to `dest`. The first `skip_bytes` of `data`
+    /// (e.g. sync bytes) are copied but not included in the checksum.
+    pub fn append(
+        &self,
+        data: &[u8],
+        skip_bytes: usize,
+        byte_order: ByteOrderSpec,
+        dest: &mut Vec<u8>,
+    ) {
+        let checksum = self.checksum(data.get(skip_bytes..).unwrap_or_default());
+        dest.extend_from_slice(data);
+        write_uint(checksum, self.byte_size(), byte_order, dest);
+    }
+
+    /// Verifies the checksum trailing `data`, returning the data without the checksum if it
+    /// matches. The first `skip_bytes` of `data` are not included in the checksum.
+    pub fn verify<'a>(
+        &self,
+        data: &'a [u8],
+        skip_bytes: usize,
+        byte_order: ByteOrderSpec,
+    ) -> Option<&'a [u8]> {
+        let body_len = data.len().checked_sub(self.byte_size())?;
+        let (body, checksum) = data.split_at(body_len);
+        let covered = body.get(skip_bytes..)?;
+        (self.checksum(covered) == read_uint(checksum, byte_order)).then_some(body)
+    }
+}
+
+fn fletcher16(data: &[u8]) -> u32 {
+    let (sum1, sum2) = data.iter().fold((0u32, 0u32), |(sum1, sum2), b| {
+        let sum1 = (sum1 + *b as u32) % 255;
+        (sum1, (sum2 + sum1) % 255)
+    });
+    (sum2 << 8) | sum1
+}
+
+fn fletcher32(data: &[u8]) -> u32 {
+    let (sum1, sum2) = data.chunks(2).fold((0u32, 0u32), |(sum1, sum2), word| {
+        let word = u16::from_le_bytes([word[0], word.get(1).copied().unwrap_or(0)]);
+        let sum1 = (sum1 + word as u32) % 65535;
+        (sum1, (sum2 + sum1) % 65535)
+    });
+    (sum2 << 16) | sum1
+}
+
+/// Appends the lowest `size` bytes of `value` to `dest`
+pub(crate) fn write_uint(value: u32, size: usize, byte_order: ByteOrderSpec, dest: &mut Vec<u8>) {
+    match byte_order {
+        ByteOrderSpec::BigEndian => dest.extend_from_slice(&value.to_be_bytes()[4 - size..]),
+        ByteOrderSpec::LittleEndian => dest.extend_from_slice(&value.to_le_bytes()[..size]),
+    }
+}
+
+/// Reads an unsigned integer of up to 4 bytes
+pub(crate) fn read_uint(data: &[u8], byte_order: ByteOrderSpec) -> u32 {
+    let fold = |acc: u32, b: &u8| (acc << 8) | *b as u32;
+    match byte_order {
+        ByteOrderSpec::BigEndian => data.iter().fold(0, fold),
+        ByteOrderSpec::LittleEndian => data.iter().rev().fold(0, fold),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::byte_data::crc::{CRC_16_IBM_3740, CRC_8_SMBUS};
+    use alloc::vec;
+
+    #[test]
+    fn test_simple_checksums() {
+        assert_eq!(ChecksumAlgorithm::Fletcher16.checksum(b"abcde"), 0xC8F0);
+        assert_eq!(ChecksumAlgorithm::Fletcher16.checksum(b"abcdef"), 0x2057);
+        assert_eq!(
+            ChecksumAlgorithm::Fletcher32.checksum(b"abcde"),
+            0xF04F_C729
+        );
+        assert_eq!(
+            ChecksumAlgorithm::Fletcher32.checksum(b"abcdef"),
+            0x5650_2D2A
+        );
+        assert_eq!(ChecksumAlgorithm::Xor8.checksum(&[0x01, 0x02, 0x04]), 0x07);
+        assert_eq!(ChecksumAlgorithm::Sum8.checksum(&[0xFF, 0x02]), 0x01);
+    }
+
+    #[test]
+    fn test_from_kind() {
+        let crc16 =
+            ChecksumAlgorithm::from_kind(ChecksumKind::Crc16, 0x1021, 0xFFFF, false, false, 0);
+        assert_eq!(crc16, Some(ChecksumAlgorithm::Crc(CRC_16_IBM_3740)));
+
+        // The polynomial does not fit in 8 bits
+        let crc8 = ChecksumAlgorithm::from_kind(ChecksumKind::Crc8, 0x107, 0, false, false, 0);
+        assert_eq!(crc8, None);
+
+        let xor = ChecksumAlgorithm::from_kind(ChecksumKind::Xor8, 0x107, 0, false, false, 0);
+        assert_eq!(xor, Some(ChecksumAlgorithm::Xor8));
+    }
+
+    #[test]
+    fn test_append_and_verify() {
+        let algorithm = ChecksumAlgorithm::Crc(CRC_16_IBM_3740);
+        let mut framed = Vec::new();
+        algorithm.append(
+            b"\xB5\x62123456789",
+            2,
+            ByteOrderSpec::BigEndian,
+            &mut framed,
+        );
+        assert_eq!(&framed[11..], &[0x29, 0xB1]);
+
+        let payload = algorithm.verify(&framed, 2, ByteOrderSpec::BigEndian);
+        assert_eq!(payload, Some(b"\xB5\x62123456789".as_slice()));
+
+        framed[5] ^= 0x10;
+        assert_eq!(algorithm.verify(&framed, 2, ByteOrderSpec::BigEndian), None);
+    }
+
+    #[test]
+    fn test_verify_short_input() {
+        let algorithm = ChecksumAlgorithm::Crc(CRC_8_SMBUS);
+        assert_eq!(algorithm.verify(&[], 0, ByteOrderSpec::LittleEndian), None);
+        assert_eq!(
+            algorithm.verify(&[0x00], 2, ByteOrderSpec::LittleEndian),
+            None
+        );
+        assert_eq!(
+            algorithm.verify(&[0x00], 0, ByteOrderSpec::LittleEndian),
+            Some([].as_slice())
+        );
+    }
+
+    #[test]
+    fn test_uint_byte_order() {
+        let mut data = vec![];
+        write_uint(0x0102_0304, 3, ByteOrderSpec::BigEndian, &mut data);
+        write_uint(0x0102_0304, 3, ByteOrderSpec::LittleEndian, &mut data);
+        assert_eq!(data, vec![0x02, 0x03, 0x04, 0x04, 0x03, 0x02]);
+        assert_eq!(read_uint(&data[..3], ByteOrderSpec::BigEndian), 0x020304);
+        assert_eq!(read_uint(&data[3..], ByteOrderSpec::LittleEndian), 0x020304);
+    }
+}
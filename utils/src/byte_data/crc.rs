@@ -0,0 +1,171 @@
+//! Cyclic redundancy checks over byte data
+//!
+//! Algorithms are described with the parameter model from the "Catalogue of parametrised
+//! CRC algorithms" (width, poly, init, refin, refout, xorout), so any CRC listed there
+//! with a width between 8 and 32 bits can be expressed as a [`CrcAlgorithm`].
+
+/// A parametrised CRC algorithm
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CrcAlgorithm {
+    /// Width of the CRC register in bits, between 8 and 32
+    pub width: u8,
+    /// Generator polynomial, without the implicit top bit
+    pub poly: u32,
+    /// Initial register value
+    pub init: u32,
+    /// Whether each input byte is bit-reflected before processing
+    pub refin: bool,
+    /// Whether the final register value is bit-reflected
+    pub refout: bool,
+    /// Value XORed with the final register value
+    pub xorout: u32,
+}
+
+/// CRC-8/SMBUS
+pub const CRC_8_SMBUS: CrcAlgorithm = CrcAlgorithm {
+    width: 8,
+    poly: 0x07,
+    init: 0x00,
+    refin: false,
+    refout: false,
+    xorout: 0x00,
+};
+
+/// CRC-16/IBM-3740, also known as CRC-16/CCITT-FALSE
+pub const CRC_16_IBM_3740: CrcAlgorithm = CrcAlgorithm {
+    width: 16,
+    poly: 0x1021,
+    init: 0xFFFF,
+    refin: false,
+    refout: false,
+    xorout: 0x0000,
+};
+
+/// CRC-16/MCRF4XX, the X.25 variant without output XOR used by MAVLink
+pub const CRC_16_MCRF4XX: CrcAlgorithm = CrcAlgorithm {
+    width: 16,
+    poly: 0x1021,
+    init: 0xFFFF,
+    refin: true,
+    refout: true,
+    xorout: 0x0000,
+};
+
+/// CRC-32/ISO-HDLC, the CRC used by Ethernet, zlib and PNG
+pub const CRC_32_ISO_HDLC: CrcAlgorithm = CrcAlgorithm {
+    width: 32,
+    poly: 0x04C1_1DB7,
+    init: 0xFFFF_FFFF,
+    refin: true,
+    refout: true,
+    xorout: 0xFFFF_FFFF,
+};
+
+impl CrcAlgorithm {
+    /// Creates an algorithm from its catalogue parameters. Returns `None` if the width is not
+    /// between 8 and 32 bits or any of `poly`, `init` or `xorout` do not fit in the width.
+    pub fn new(
+        width: u8,
+        poly: u32,
+        init: u32,
+        refin: bool,
+        refout: bool,
+        xorout: u32,
+    ) -> Option<Self> {
+        let algorithm = CrcAlgorithm {
+            width,
+            poly,
+            init,
+            refin,
+            refout,
+            xorout,
+        };
+        let mask = (8..=32).contains(&width).then(|| algorithm.mask())?;
+        (poly & !mask == 0 && init & !mask == 0 && xorout & !mask == 0).then_some(algorithm)
+    }
+
+    /// Number of bytes needed to store the checksum
+    pub fn byte_size(&self) -> usize {
+        (self.width as usize).div_ceil(8)
+    }
+
+    fn mask(&self) -> u32 {
+        u32::MAX >> (32 - self.width as u32)
+    }
+
+    /// Computes the checksum of `data`
+    pub fn checksum(&self, data: &[u8]) -> u32 {
+        self.checksum_chunks([data])
+    }
+
+    /// Computes the checksum of the concatenation of `chunks`, for data that is not stored
+    /// contiguously
+    pub fn checksum_chunks<'a>(&self, chunks: impl IntoIterator<Item = &'a [u8]>) -> u32 {
+        debug_assert!(
+            (8..=32).contains(&self.width),
+            "CRC width must be between 8 and 32 bits"
+        );
+        let top_bit = 1u32 << (self.width - 1);
+        let mask = self.mask();
+        let mut crc = self.init & mask;
+        for byte in chunks.into_iter().flatten() {
+            let byte = if self.refin {
+                byte.reverse_bits()
+            } else {
+                *byte
+            };
+            crc ^= (byte as u32) << (self.width - 8);
+            for _ in 0..8 {
+                crc = if crc & top_bit != 0 {
+                    (crc << 1) ^ self.poly
+                } else {
+                    crc << 1
+                };
+            }
+            crc &= mask;
+        }
+
+        if self.refout {
+            crc = crc.reverse_bits() >> (32 - self.width as u32);
+        }
+        (crc ^ self.xorout) & mask
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The standard check input from the CRC catalogue
+    const CHECK_INPUT: &[u8] = b"123456789";
+
+    #[test]
+    fn test_crc_check_values() {
+        assert_eq!(CRC_8_SMBUS.checksum(CHECK_INPUT), 0xF4);
+        assert_eq!(CRC_16_IBM_3740.checksum(CHECK_INPUT), 0x29B1);
+        assert_eq!(CRC_16_MCRF4XX.checksum(CHECK_INPUT), 0x6F91);
+        assert_eq!(CRC_32_ISO_HDLC.checksum(CHECK_INPUT), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_crc_chunks() {
+        let (head, tail) = CHECK_INPUT.split_at(4);
+        assert_eq!(
+            CRC_32_ISO_HDLC.checksum_chunks([head, &[], tail]),
+            0xCBF4_3926
+        );
+    }
+
+    #[test]
+    fn test_crc_byte_size() {
+        assert_eq!(CRC_8_SMBUS.byte_size(), 1);
+        assert_eq!(CRC_16_IBM_3740.byte_size(), 2);
+        assert_eq!(CRC_32_ISO_HDLC.byte_size(), 4);
+    }
+
+    #[test]
+    fn test_crc_empty_input() {
+        assert_eq!(CRC_16_IBM_3740.checksum(&[]), 0xFFFF);
+        assert_eq!(CRC_32_ISO_HDLC.checksum(&[]), 0);
+    }
+}
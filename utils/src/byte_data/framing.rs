@@ -0,0 +1,685 @@
+//! Framing codecs for byte streams
+//!
+//! Frames carry arbitrary binary payloads, so unlike the start / end delimiters used by the
+//! serial receive block, payloads may contain any byte value. Three framings are supported:
+//! - [`Framing::Cobs`]: Consistent Overhead Byte Stuffing, frames are terminated with `0x00`
+//! - [`Framing::Slip`]: RFC 1055 SLIP, frames are delimited with `0xC0`
+//! - [`Framing::LengthPrefixed`]: a `u16` payload length followed by the payload
+//!
+//! Any framing can additionally carry a [`FrameChecksum`], which is computed over the
+//! unencoded frame (length prefix and payload) and appended before encoding.
+use alloc::{str::FromStr, vec::Vec};
+
+use super::checksum::{read_uint, write_uint};
+use super::crc::{CrcAlgorithm, CRC_16_IBM_3740, CRC_32_ISO_HDLC};
+use super::ByteOrderSpec;
+use crate::ParseEnumError;
+
+const COBS_DELIMITER: u8 = 0x00;
+
+const SLIP_END: u8 = 0xC0;
+const SLIP_ESC: u8 = 0xDB;
+const SLIP_ESC_END: u8 = 0xDC;
+const SLIP_ESC_ESC: u8 = 0xDD;
+
+const LENGTH_PREFIX_BYTES: usize = 2;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FramingError {
+    /// The encoded frame is malformed
+    DecodeError,
+    /// The frame checksum does not match the frame contents
+    ChecksumMismatch,
+    /// The frame is larger than the configured maximum frame size
+    FrameTooLarge,
+    /// The frame is too short to contain the length prefix or checksum
+    FrameTooShort,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Framing {
+    Cobs,
+    Slip,
+    LengthPrefixed,
+}
+
+impl FromStr for Framing {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Cobs" => Ok(Self::Cobs),
+            "Slip" => Ok(Self::Slip),
+            "LengthPrefixed" => Ok(Self::LengthPrefixed),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum FrameChecksum {
+    None,
+    /// CRC-16/IBM-3740 (CCITT-FALSE)
+    Crc16,
+    /// CRC-32/ISO-HDLC
+    Crc32,
+}
+
+impl FrameChecksum {
+    fn algorithm(&self) -> Option<&'static CrcAlgorithm> {
+        match self {
+            FrameChecksum::None => None,
+            FrameChecksum::Crc16 => Some(&CRC_16_IBM_3740),
+            FrameChecksum::Crc32 => Some(&CRC_32_ISO_HDLC),
+        }
+    }
+
+    /// Number of bytes the checksum adds to a frame
+    pub fn byte_size(&self) -> usize {
+        self.algorithm().map_or(0, CrcAlgorithm::byte_size)
+    }
+}
+
+impl FromStr for FrameChecksum {
+    type Err = ParseEnumError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "None" | "" => Ok(Self::None),
+            "Crc16" => Ok(Self::Crc16),
+            "Crc32" => Ok(Self::Crc32),
+            _ => Err(ParseEnumError),
+        }
+    }
+}
+
+/// Describes how payloads are framed on the wire
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FrameConfig {
+    pub framing: Framing,
+    pub checksum: FrameChecksum,
+    /// Byte order of the length prefix and checksum
+    pub byte_order: ByteOrderSpec,
+    /// The largest payload that will be accepted, in bytes
+    pub max_payload_bytes: usize,
+}
+
+impl FrameConfig {
+    /// Size of the unencoded frame for the largest payload
+    fn max_raw_frame_bytes(&self) -> usize {
+        self.header_bytes() + self.max_payload_bytes + self.checksum.byte_size()
+    }
+
+    /// Size of the encoded frame for the largest payload, including delimiters
+    fn max_encoded_frame_bytes(&self) -> usize {
+        let raw = self.max_raw_frame_bytes();
+        match self.framing {
+            // One overhead byte per 254 bytes, plus the leading code byte and delimiter
+            Framing::Cobs => raw + raw / 254 + 2,
+            // Every byte may need escaping, plus leading and trailing END
+            Framing::Slip => 2 * raw + 2,
+            Framing::LengthPrefixed => raw,
+        }
+    }
+
+    fn header_bytes(&self) -> usize {
+        match self.framing {
+            Framing::LengthPrefixed => LENGTH_PREFIX_BYTES,
+            Framing::Cobs | Framing::Slip => 0,
+        }
+    }
+}
+
+/// Appends the COBS encoding of `data` to `dest`, including the trailing `0x00` delimiter
+pub fn cobs_encode(data: &[u8], dest: &mut Vec<u8>) {
+    let mut code_idx = dest.len();
+    let mut code = 1u8;
+    dest.push(0);
+    for (i, byte) in data.iter().enumerate() {
+        if *byte == COBS_DELIMITER {
+            dest[code_idx] = code;
+            code_idx = dest.len();
+            code = 1;
+            dest.push(0);
+        } else {
+            dest.push(*byte);
+            code += 1;
+            // Full blocks only start a new block if more data follows
+            if code == 0xFF && i + 1 < data.len() {
+                dest[code_idx] = code;
+                code_idx = dest.len();
+                code = 1;
+                dest.push(0);
+            }
+        }
+    }
+    dest[code_idx] = code;
+    dest.push(COBS_DELIMITER);
+}
+
+/// Decodes a single COBS encoded frame (without the trailing delimiter) into `dest`
+pub fn cobs_decode(data: &[u8], dest: &mut Vec<u8>) -> Result<(), FramingError> {
+    let mut idx = 0;
+    while idx < data.len() {
+        let code = data[idx] as usize;
+        if code == 0 || idx + code > data.len() {
+            return Err(FramingError::DecodeError);
+        }
+        let block = &data[idx + 1..idx + code];
+        if block.contains(&COBS_DELIMITER) {
+            return Err(FramingError::DecodeError);
+        }
+        dest.extend_from_slice(block);
+        idx += code;
+        // A code of 0xFF means the block ended without a zero
+        if code < 0xFF && idx < data.len() {
+            dest.push(COBS_DELIMITER);
+        }
+    }
+    Ok(())
+}
+
+/// Appends the SLIP encoding of `data` to `dest`. A leading END is written to flush any
+/// line noise received by the peer before the frame.
+pub fn slip_encode(data: &[u8], dest: &mut Vec<u8>) {
+    dest.push(SLIP_END);
+    for byte in data {
+        match *byte {
+            SLIP_END => dest.extend_from_slice(&[SLIP_ESC, SLIP_ESC_END]),
+            SLIP_ESC => dest.extend_from_slice(&[SLIP_ESC, SLIP_ESC_ESC]),
+            b => dest.push(b),
+        }
+    }
+    dest.push(SLIP_END);
+}
+
+/// Decodes a single SLIP encoded frame (without the END delimiters) into `dest`
+pub fn slip_decode(data: &[u8], dest: &mut Vec<u8>) -> Result<(), FramingError> {
+    let mut bytes = data.iter();
+    while let Some(byte) = bytes.next() {
+        match *byte {
+            SLIP_ESC => match bytes.next() {
+                Some(&SLIP_ESC_END) => dest.push(SLIP_END),
+                Some(&SLIP_ESC_ESC) => dest.push(SLIP_ESC),
+                _ => return Err(FramingError::DecodeError),
+            },
+            SLIP_END => return Err(FramingError::DecodeError),
+            b => dest.push(b),
+        }
+    }
+    Ok(())
+}
+
+/// Appends the framed `payload` to `dest`
+pub fn encode_frame(
+    payload: &[u8],
+    config: &FrameConfig,
+    dest: &mut Vec<u8>,
+) -> Result<(), FramingError> {
+    if payload.len() > config.max_payload_bytes || payload.len() > u16::MAX as usize {
+        return Err(FramingError::FrameTooLarge);
+    }
+
+    let mut raw = Vec::with_capacity(config.max_raw_frame_bytes());
+    if config.framing == Framing::LengthPrefixed {
+        write_uint(
+            payload.len() as u32,
+            LENGTH_PREFIX_BYTES,
+            config.byte_order,
+            &mut raw,
+        );
+    }
+    raw.extend_from_slice(payload);
+    if let Some(crc) = config.checksum.algorithm() {
+        let checksum = crc.checksum(&raw);
+        write_uint(checksum, crc.byte_size(), config.byte_order, &mut raw);
+    }
+
+    match config.framing {
+        Framing::Cobs => cobs_encode(&raw, dest),
+        Framing::Slip => slip_encode(&raw, dest),
+        Framing::LengthPrefixed => dest.extend_from_slice(&raw),
+    }
+    Ok(())
+}
+
+/// Verifies and strips the checksum from an unencoded frame, returning the remaining bytes
+fn check_frame<'a>(raw: &'a [u8], config: &FrameConfig) -> Result<&'a [u8], FramingError> {
+    let Some(crc) = config.checksum.algorithm() else {
+        return Ok(raw);
+    };
+    if raw.len() < crc.byte_size() {
+        return Err(FramingError::FrameTooShort);
+    }
+    let (body, checksum) = raw.split_at(raw.len() - crc.byte_size());
+    if crc.checksum(body) != read_uint(checksum, config.byte_order) {
+        return Err(FramingError::ChecksumMismatch);
+    }
+    Ok(body)
+}
+
+/// Running counts of the frames seen by a [`FrameDecoder`]. Counts saturate at `u32::MAX`
+/// rather than wrapping.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct FramingStats {
+    /// Frames successfully decoded
+    pub frames: u32,
+    /// Frames that could not be decoded because they were malformed
+    pub decode_errors: u32,
+    /// Frames whose checksum did not match
+    pub checksum_errors: u32,
+    /// Frames that were larger than the configured maximum size
+    pub overflow_errors: u32,
+    /// Bytes that were discarded while resynchronising to the stream
+    pub discarded_bytes: u32,
+}
+
+impl FramingStats {
+    /// Total number of framing errors of any kind
+    pub fn errors(&self) -> u32 {
+        self.decode_errors
+            .saturating_add(self.checksum_errors)
+            .saturating_add(self.overflow_errors)
+    }
+
+    fn record_error(&mut self, error: FramingError) {
+        let count = match error {
+            FramingError::DecodeError | FramingError::FrameTooShort => &mut self.decode_errors,
+            FramingError::ChecksumMismatch => &mut self.checksum_errors,
+            FramingError::FrameTooLarge => &mut self.overflow_errors,
+        };
+        *count = count.saturating_add(1);
+    }
+
+    fn record_frame(&mut self) {
+        self.frames = self.frames.saturating_add(1);
+    }
+
+    fn record_discarded(&mut self, bytes: usize) {
+        let bytes = u32::try_from(bytes).unwrap_or(u32::MAX);
+        self.discarded_bytes = self.discarded_bytes.saturating_add(bytes);
+    }
+}
+
+/// Streaming frame decoder
+///
+/// Bytes can be pushed in arbitrarily sized chunks, frames are extracted as soon as they are
+/// complete. Invalid frames are dropped and counted in [`FramingStats`]:
+/// - COBS and SLIP resynchronise on the next frame delimiter
+/// - Length prefixed framing drops a single byte and searches for the next valid frame,
+///   which relies on the checksum to reject misaligned data
+pub struct FrameDecoder {
+    config: FrameConfig,
+    buffer: Vec<u8>,
+    /// Set while discarding an oversized COBS or SLIP frame up to its delimiter
+    discarding: bool,
+    stats: FramingStats,
+}
+
+impl FrameDecoder {
+    pub fn new(config: FrameConfig) -> Self {
+        Self {
+            config,
+            buffer: Vec::with_capacity(config.max_encoded_frame_bytes()),
+            discarding: false,
+            stats: FramingStats::default(),
+        }
+    }
+
+    pub fn config(&self) -> &FrameConfig {
+        &self.config
+    }
+
+    pub fn stats(&self) -> &FramingStats {
+        &self.stats
+    }
+
+    /// Discards any partially received frame
+    pub fn reset(&mut self) {
+        self.stats.record_discarded(self.buffer.len());
+        self.buffer.clear();
+        self.discarding = false;
+    }
+
+    /// Feeds `data` into the decoder, calling `on_frame` with the payload of each complete frame
+    pub fn decode(&mut self, data: &[u8], mut on_frame: impl FnMut(&[u8])) {
+        match self.config.framing {
+            Framing::Cobs => {
+                self.decode_delimited(data, COBS_DELIMITER, cobs_decode, &mut on_frame)
+            }
+            Framing::Slip => self.decode_delimited(data, SLIP_END, slip_decode, &mut on_frame),
+            Framing::LengthPrefixed => self.decode_length_prefixed(data, &mut on_frame),
+        }
+    }
+
+    fn decode_delimited(
+        &mut self,
+        data: &[u8],
+        delimiter: u8,
+        decode: fn(&[u8], &mut Vec<u8>) -> Result<(), FramingError>,
+        on_frame: &mut impl FnMut(&[u8]),
+    ) {
+        let max_encoded = self.config.max_encoded_frame_bytes();
+        let mut raw = Vec::with_capacity(self.config.max_raw_frame_bytes());
+        for byte in data {
+            if *byte != delimiter {
+                if self.discarding {
+                    self.stats.record_discarded(1);
+                } else if self.buffer.len() >= max_encoded {
+                    self.stats.record_error(FramingError::FrameTooLarge);
+                    self.stats.record_discarded(self.buffer.len() + 1);
+                    self.buffer.clear();
+                    self.discarding = true;
+                } else {
+                    self.buffer.push(*byte);
+                }
+                continue;
+            }
+
+            self.discarding = false;
+            // Back to back delimiters are used to flush the line and are not frames
+            if self.buffer.is_empty() {
+                continue;
+            }
+
+            raw.clear();
+            let res = decode(&self.buffer, &mut raw).and_then(|_| self.check_payload(&raw));
+            match res {
+                Ok(payload) => {
+                    self.stats.record_frame();
+                    on_frame(payload);
+                }
+                Err(err) => {
+                    self.stats.record_error(err);
+                    self.stats.record_discarded(self.buffer.len());
+                }
+            }
+            self.buffer.clear();
+        }
+    }
+
+    fn check_payload<'a>(&self, raw: &'a [u8]) -> Result<&'a [u8], FramingError> {
+        let payload = check_frame(raw, &self.config)?;
+        if payload.len() > self.config.max_payload_bytes {
+            return Err(FramingError::FrameTooLarge);
+        }
+        Ok(payload)
+    }
+
+    fn decode_length_prefixed(&mut self, data: &[u8], on_frame: &mut impl FnMut(&[u8])) {
+        self.buffer.extend_from_slice(data);
+        let checksum_bytes = self.config.checksum.byte_size();
+        let mut start = 0;
+        while self.buffer.len() - start >= LENGTH_PREFIX_BYTES {
+            let prefix = &self.buffer[start..start + LENGTH_PREFIX_BYTES];
+            let payload_len = read_uint(prefix, self.config.byte_order) as usize;
+            if payload_len > self.config.max_payload_bytes {
+                self.stats.record_error(FramingError::FrameTooLarge);
+                self.stats.record_discarded(1);
+                start += 1;
+                continue;
+            }
+
+            let frame_len = LENGTH_PREFIX_BYTES + payload_len + checksum_bytes;
+            if self.buffer.len() - start < frame_len {
+                break;
+            }
+
+            let frame = &self.buffer[start..start + frame_len];
+            match check_frame(frame, &self.config) {
+                Ok(body) => {
+                    self.stats.record_frame();
+                    on_frame(&body[LENGTH_PREFIX_BYTES..]);
+                    start += frame_len;
+                }
+                Err(err) => {
+                    self.stats.record_error(err);
+                    self.stats.record_discarded(1);
+                    start += 1;
+                }
+            }
+        }
+        self.buffer.drain(..start);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn config(framing: Framing, checksum: FrameChecksum) -> FrameConfig {
+        FrameConfig {
+            framing,
+            checksum,
+            byte_order: ByteOrderSpec::LittleEndian,
+            max_payload_bytes: 512,
+        }
+    }
+
+    fn decode_all(decoder: &mut FrameDecoder, data: &[u8]) -> Vec<Vec<u8>> {
+        let mut frames = Vec::new();
+        decoder.decode(data, |f| frames.push(f.to_vec()));
+        frames
+    }
+
+    #[test]
+    fn test_cobs_encode_known_vectors() {
+        let cases: [(&[u8], &[u8]); 5] = [
+            (&[0x00], &[0x01, 0x01, 0x00]),
+            (&[0x00, 0x00], &[0x01, 0x01, 0x01, 0x00]),
+            (
+                &[0x11, 0x22, 0x00, 0x33],
+                &[0x03, 0x11, 0x22, 0x02, 0x33, 0x00],
+            ),
+            (
+                &[0x11, 0x22, 0x33, 0x44],
+                &[0x05, 0x11, 0x22, 0x33, 0x44, 0x00],
+            ),
+            (&[], &[0x01, 0x00]),
+        ];
+        for (input, expected) in cases {
+            let mut encoded = Vec::new();
+            cobs_encode(input, &mut encoded);
+            assert_eq!(encoded, expected);
+
+            let mut decoded = Vec::new();
+            cobs_decode(&encoded[..encoded.len() - 1], &mut decoded).unwrap();
+            assert_eq!(decoded, input);
+        }
+    }
+
+    #[test]
+    fn test_cobs_long_runs() {
+        let input: Vec<u8> = (1..=255).chain(1..=100).collect();
+        let mut encoded = Vec::new();
+        cobs_encode(&input, &mut encoded);
+        assert_eq!(encoded[0], 0xFF);
+        assert!(!encoded[..encoded.len() - 1].contains(&0));
+
+        let mut decoded = Vec::new();
+        cobs_decode(&encoded[..encoded.len() - 1], &mut decoded).unwrap();
+        assert_eq!(decoded, input);
+
+        // A single full block has no trailing code byte
+        let input: Vec<u8> = (1..=254).collect();
+        let mut encoded = Vec::new();
+        cobs_encode(&input, &mut encoded);
+        assert_eq!(encoded.len(), 256);
+        assert_eq!(encoded[0], 0xFF);
+        assert_eq!(encoded[255], 0x00);
+    }
+
+    #[test]
+    fn test_cobs_decode_rejects_malformed() {
+        let mut decoded = Vec::new();
+        assert_eq!(
+            cobs_decode(&[0x05, 0x11, 0x22], &mut decoded),
+            Err(FramingError::DecodeError)
+        );
+        assert_eq!(
+            cobs_decode(&[0x03, 0x11, 0x00], &mut decoded),
+            Err(FramingError::DecodeError)
+        );
+    }
+
+    #[test]
+    fn test_slip_roundtrip() {
+        let input = [0x01, SLIP_END, 0x02, SLIP_ESC, 0x03];
+        let mut encoded = Vec::new();
+        slip_encode(&input, &mut encoded);
+        assert_eq!(
+            encoded,
+            vec![
+                SLIP_END,
+                0x01,
+                SLIP_ESC,
+                SLIP_ESC_END,
+                0x02,
+                SLIP_ESC,
+                SLIP_ESC_ESC,
+                0x03,
+                SLIP_END
+            ]
+        );
+
+        let mut decoded = Vec::new();
+        slip_decode(&encoded[1..encoded.len() - 1], &mut decoded).unwrap();
+        assert_eq!(decoded, input);
+        assert_eq!(
+            slip_decode(&[SLIP_ESC, 0x01], &mut decoded),
+            Err(FramingError::DecodeError)
+        );
+    }
+
+    #[test]
+    fn test_length_prefixed_encoding() {
+        let mut encoded = Vec::new();
+        let cfg = FrameConfig {
+            byte_order: ByteOrderSpec::BigEndian,
+            ..config(Framing::LengthPrefixed, FrameChecksum::Crc16)
+        };
+        encode_frame(b"123456789", &cfg, &mut encoded).unwrap();
+        let crc = CRC_16_IBM_3740.checksum(&encoded[..11]) as u16;
+        let mut expected = vec![0x00, 0x09];
+        expected.extend_from_slice(b"123456789");
+        expected.extend_from_slice(&crc.to_be_bytes());
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn test_encode_rejects_large_payloads() {
+        let cfg = FrameConfig {
+            max_payload_bytes: 4,
+            ..config(Framing::Cobs, FrameChecksum::None)
+        };
+        let mut encoded = Vec::new();
+        assert_eq!(
+            encode_frame(b"12345", &cfg, &mut encoded),
+            Err(FramingError::FrameTooLarge)
+        );
+        assert!(encoded.is_empty());
+    }
+
+    #[test]
+    fn test_stream_roundtrip_all_framings() {
+        let payloads: [&[u8]; 3] = [b"\x00\x01\x02\x00", b"\xC0\xDB\xC0", b"hello"];
+        for framing in [Framing::Cobs, Framing::Slip, Framing::LengthPrefixed] {
+            for checksum in [
+                FrameChecksum::None,
+                FrameChecksum::Crc16,
+                FrameChecksum::Crc32,
+            ] {
+                let cfg = config(framing, checksum);
+                let mut stream = Vec::new();
+                for p in payloads {
+                    encode_frame(p, &cfg, &mut stream).unwrap();
+                }
+
+                // Feed the stream one byte at a time to exercise partial frames
+                let mut decoder = FrameDecoder::new(cfg);
+                let mut frames = Vec::new();
+                for byte in &stream {
+                    frames.extend(decode_all(&mut decoder, &[*byte]));
+                }
+                assert_eq!(frames, payloads.map(|p| p.to_vec()));
+                assert_eq!(decoder.stats().frames, 3);
+                assert_eq!(decoder.stats().errors(), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_cobs_stream_resyncs_after_corruption() {
+        let cfg = config(Framing::Cobs, FrameChecksum::Crc16);
+        let mut stream = Vec::new();
+        encode_frame(b"first", &cfg, &mut stream).unwrap();
+        stream[3] ^= 0xFF;
+        encode_frame(b"second", &cfg, &mut stream).unwrap();
+
+        let mut decoder = FrameDecoder::new(cfg);
+        let frames = decode_all(&mut decoder, &stream);
+        assert_eq!(frames, vec![b"second".to_vec()]);
+        assert_eq!(decoder.stats().checksum_errors, 1);
+    }
+
+    #[test]
+    fn test_cobs_stream_starts_mid_frame() {
+        let cfg = config(Framing::Cobs, FrameChecksum::Crc16);
+        let mut stream = Vec::new();
+        encode_frame(b"first", &cfg, &mut stream).unwrap();
+        encode_frame(b"second", &cfg, &mut stream).unwrap();
+
+        let mut decoder = FrameDecoder::new(cfg);
+        let frames = decode_all(&mut decoder, &stream[4..]);
+        assert_eq!(frames, vec![b"second".to_vec()]);
+        assert_eq!(decoder.stats().frames, 1);
+        assert_eq!(decoder.stats().errors(), 1);
+    }
+
+    #[test]
+    fn test_slip_stream_discards_oversized_frames() {
+        let cfg = FrameConfig {
+            max_payload_bytes: 4,
+            ..config(Framing::Slip, FrameChecksum::None)
+        };
+        let mut stream = vec![SLIP_END];
+        stream.extend_from_slice(&[0x55; 32]);
+        stream.push(SLIP_END);
+        encode_frame(b"ok", &cfg, &mut stream).unwrap();
+
+        let mut decoder = FrameDecoder::new(cfg);
+        let frames = decode_all(&mut decoder, &stream);
+        assert_eq!(frames, vec![b"ok".to_vec()]);
+        assert_eq!(decoder.stats().overflow_errors, 1);
+        assert_eq!(decoder.stats().discarded_bytes, 32);
+    }
+
+    #[test]
+    fn test_length_prefixed_stream_resyncs_after_garbage() {
+        let cfg = config(Framing::LengthPrefixed, FrameChecksum::Crc32);
+        let mut stream = vec![0x03, 0x00, 0xAA, 0xBB];
+        encode_frame(b"payload", &cfg, &mut stream).unwrap();
+        encode_frame(b"\x00\x00", &cfg, &mut stream).unwrap();
+
+        let mut decoder = FrameDecoder::new(cfg);
+        let frames = decode_all(&mut decoder, &stream);
+        assert_eq!(frames, vec![b"payload".to_vec(), b"\x00\x00".to_vec()]);
+        assert_eq!(decoder.stats().frames, 2);
+        assert!(decoder.stats().checksum_errors > 0);
+    }
+
+    #[test]
+    fn test_parse_framing_spec() {
+        assert_eq!("Cobs".parse::<Framing>().unwrap(), Framing::Cobs);
+        assert_eq!("Slip".parse::<Framing>().unwrap(), Framing::Slip);
+        assert_eq!(
+            "LengthPrefixed".parse::<Framing>().unwrap(),
+            Framing::LengthPrefixed
+        );
+        assert!("Hdlc".parse::<Framing>().is_err());
+        assert_eq!("".parse::<FrameChecksum>().unwrap(), FrameChecksum::None);
+        assert_eq!(
+            "Crc32".parse::<FrameChecksum>().unwrap(),
+            FrameChecksum::Crc32
+        );
+    }
+}
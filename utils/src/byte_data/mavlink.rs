@@ -0,0 +1,945 @@
+//! MAVLink v2 message encoding and decoding
+//!
+//! Messages are described at runtime by a [`MessageDef`], either looked up by name from the
+//! subset of the `common` dialect in [`COMMON_MESSAGES`] or parsed from a custom definition.
+//! Field values are exchanged as `f64`s in the order the fields are declared in the dialect
+//! XML, with array fields flattened. Reordering fields into wire order, payload truncation,
+//! `CRC_EXTRA` and message signing are all handled here.
+//!
+//! [`MavlinkEncoder`] produces complete frames and [`MavlinkDecoder`] extracts frames from a
+//! byte stream, buffering partial frames across calls and resynchronising on corrupt data.
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{ops::Range, str::FromStr};
+
+use super::crc::CRC_16_MCRF4XX;
+use super::{try_pack_data, try_unpack_data, DataType};
+use crate::ParseEnumError;
+
+/// Start-of-frame marker for MAVLink v2
+pub const MAVLINK_V2_STX: u8 = 0xFD;
+const HEADER_BYTES: usize = 10;
+const CHECKSUM_BYTES: usize = 2;
+const SIGNATURE_BYTES: usize = 13;
+const MAX_PAYLOAD_BYTES: usize = 255;
+/// Size of the largest possible signed MAVLink v2 frame
+pub const MAX_FRAME_BYTES: usize =
+    HEADER_BYTES + MAX_PAYLOAD_BYTES + CHECKSUM_BYTES + SIGNATURE_BYTES;
+const INCOMPAT_FLAG_SIGNED: u8 = 0x01;
+
+/// Message definitions from the MAVLink `common` dialect, in the custom definition format
+/// accepted by [`MessageDef::from_str`]. Extension fields follow the `;`.
+pub const COMMON_MESSAGES: &[(&str, &str)] = &[
+    (
+        "HEARTBEAT",
+        "0:50:type=uint8_t,autopilot=uint8_t,base_mode=uint8_t,custom_mode=uint32_t,\
+         system_status=uint8_t,mavlink_version=uint8_t",
+    ),
+    (
+        "SYS_STATUS",
+        "1:124:onboard_control_sensors_present=uint32_t,onboard_control_sensors_enabled=uint32_t,\
+         onboard_control_sensors_health=uint32_t,load=uint16_t,voltage_battery=uint16_t,\
+         current_battery=int16_t,battery_remaining=int8_t,drop_rate_comm=uint16_t,\
+         errors_comm=uint16_t,errors_count1=uint16_t,errors_count2=uint16_t,\
+         errors_count3=uint16_t,errors_count4=uint16_t;\
+         onboard_control_sensors_present_extended=uint32_t,\
+         onboard_control_sensors_enabled_extended=uint32_t,\
+         onboard_control_sensors_health_extended=uint32_t",
+    ),
+    (
+        "SCALED_IMU",
+        "26:170:time_boot_ms=uint32_t,xacc=int16_t,yacc=int16_t,zacc=int16_t,xgyro=int16_t,\
+         ygyro=int16_t,zgyro=int16_t,xmag=int16_t,ymag=int16_t,zmag=int16_t;\
+         temperature=int16_t",
+    ),
+    (
+        "RAW_IMU",
+        "27:144:time_usec=uint64_t,xacc=int16_t,yacc=int16_t,zacc=int16_t,xgyro=int16_t,\
+         ygyro=int16_t,zgyro=int16_t,xmag=int16_t,ymag=int16_t,zmag=int16_t;\
+         id=uint8_t,temperature=int16_t",
+    ),
+    (
+        "SCALED_PRESSURE",
+        "29:115:time_boot_ms=uint32_t,press_abs=float,press_diff=float,temperature=int16_t;\
+         temperature_press_diff=int16_t",
+    ),
+    (
+        "ATTITUDE",
+        "30:39:time_boot_ms=uint32_t,roll=float,pitch=float,yaw=float,rollspeed=float,\
+         pitchspeed=float,yawspeed=float",
+    ),
+    (
+        "ATTITUDE_QUATERNION",
+        "31:246:time_boot_ms=uint32_t,q1=float,q2=float,q3=float,q4=float,rollspeed=float,\
+         pitchspeed=float,yawspeed=float;repr_offset_q=float[4]",
+    ),
+    (
+        "GLOBAL_POSITION_INT",
+        "33:104:time_boot_ms=uint32_t,lat=int32_t,lon=int32_t,alt=int32_t,relative_alt=int32_t,\
+         vx=int16_t,vy=int16_t,vz=int16_t,hdg=uint16_t",
+    ),
+    (
+        "HIGHRES_IMU",
+        "105:93:time_usec=uint64_t,xacc=float,yacc=float,zacc=float,xgyro=float,ygyro=float,\
+         zgyro=float,xmag=float,ymag=float,zmag=float,abs_pressure=float,diff_pressure=float,\
+         pressure_alt=float,temperature=float,fields_updated=uint16_t;id=uint8_t",
+    ),
+];
+
+/// A single field of a MAVLink message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MavField {
+    pub name: String,
+    /// The MAVLink type name, e.g. `uint8_t` or `float`, used to compute `CRC_EXTRA`
+    pub type_name: &'static str,
+    pub data_type: DataType,
+    /// Number of elements for array fields, or 0 for scalar fields
+    pub array_length: usize,
+    /// Extension fields are not reordered and are not part of `CRC_EXTRA`
+    pub extension: bool,
+}
+
+impl MavField {
+    /// Number of `f64` values this field is flattened into
+    pub fn value_count(&self) -> usize {
+        self.array_length.max(1)
+    }
+
+    fn byte_size(&self) -> usize {
+        self.data_type.byte_size() * self.value_count()
+    }
+}
+
+/// Parses a MAVLink field type such as `uint16_t`, `float[4]` or `char[16]`. The short names
+/// used by [`DataType`] (e.g. `u16`, `f32`) are also accepted.
+fn parse_field_type(spec: &str) -> Option<(&'static str, DataType, usize)> {
+    let (base, array_length) = match spec.split_once('[') {
+        Some((base, len)) => {
+            let len = len.strip_suffix(']')?.parse::<usize>().ok()?;
+            (base, (len > 0).then_some(len)?)
+        }
+        None => (spec, 0),
+    };
+    let (type_name, data_type) = match base {
+        "uint8_t" | "uint8_t_mavlink_version" | "u8" => ("uint8_t", DataType::U8),
+        "int8_t" | "i8" => ("int8_t", DataType::I8),
+        "char" => ("char", DataType::U8),
+        "uint16_t" | "u16" => ("uint16_t", DataType::U16),
+        "int16_t" | "i16" => ("int16_t", DataType::I16),
+        "uint32_t" | "u32" => ("uint32_t", DataType::U32),
+        "int32_t" | "i32" => ("int32_t", DataType::I32),
+        "uint64_t" | "u64" => ("uint64_t", DataType::U64),
+        "int64_t" | "i64" => ("int64_t", DataType::I64),
+        "float" | "f32" => ("float", DataType::F32),
+        "double" | "f64" => ("double", DataType::F64),
+        _ => return None,
+    };
+    Some((type_name, data_type, array_length))
+}
+
+/// Description of a MAVLink message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageDef {
+    pub name: String,
+    pub id: u32,
+    pub crc_extra: u8,
+    /// Fields in declaration order
+    fields: Vec<MavField>,
+    /// Indices into `fields` in the order they are serialized
+    wire_order: Vec<usize>,
+    /// Index of the first flattened value of each field
+    value_offsets: Vec<usize>,
+}
+
+impl MessageDef {
+    /// Creates a message definition from its fields in declaration order. Base fields are
+    /// serialized sorted by element size, largest first, followed by extension fields.
+    pub fn new(name: &str, id: u32, crc_extra: u8, fields: Vec<MavField>) -> Self {
+        let mut wire_order: Vec<usize> = (0..fields.len()).collect();
+        // Stable sort, so fields of the same size keep their declaration order
+        wire_order.sort_by_key(|&i| match &fields[i] {
+            field if field.extension => (true, 0),
+            field => (false, usize::MAX - field.data_type.byte_size()),
+        });
+
+        let value_offsets = fields
+            .iter()
+            .scan(0, |offset, field| {
+                let start = *offset;
+                *offset += field.value_count();
+                Some(start)
+            })
+            .collect();
+
+        Self {
+            name: name.to_string(),
+            id,
+            crc_extra,
+            fields,
+            wire_order,
+            value_offsets,
+        }
+    }
+
+    /// Looks up a message from [`COMMON_MESSAGES`] by name
+    pub fn common(name: &str) -> Option<Self> {
+        let (name, spec) = COMMON_MESSAGES.iter().find(|(n, _)| *n == name)?;
+        let mut def = spec.parse::<Self>().ok()?;
+        def.name = name.to_string();
+        Some(def)
+    }
+
+    pub fn fields(&self) -> &[MavField] {
+        &self.fields
+    }
+
+    /// Range of flattened values belonging to the named field
+    pub fn field_values(&self, name: &str) -> Option<Range<usize>> {
+        let index = self.fields.iter().position(|f| f.name == name)?;
+        let start = self.value_offsets[index];
+        Some(start..start + self.fields[index].value_count())
+    }
+
+    /// Total number of flattened values in the message
+    pub fn value_count(&self) -> usize {
+        self.fields.iter().map(MavField::value_count).sum()
+    }
+
+    /// Length of the payload including extension fields, before truncation
+    pub fn payload_len(&self) -> usize {
+        self.fields.iter().map(MavField::byte_size).sum()
+    }
+
+    /// Computes `CRC_EXTRA` from the message name and base fields, as the MAVLink code
+    /// generators do. Useful for checking custom dialect definitions.
+    pub fn compute_crc_extra(&self) -> u8 {
+        const SPACE: &[u8] = b" ";
+        let lengths: Vec<[u8; 1]> = self.fields.iter().map(|f| [f.array_length as u8]).collect();
+        let mut parts = Vec::from([self.name.as_bytes(), SPACE]);
+        for &index in self
+            .wire_order
+            .iter()
+            .filter(|&&i| !self.fields[i].extension)
+        {
+            let field = &self.fields[index];
+            parts.extend([
+                field.type_name.as_bytes(),
+                SPACE,
+                field.name.as_bytes(),
+                SPACE,
+            ]);
+            if field.array_length > 0 {
+                parts.push(&lengths[index]);
+            }
+        }
+        let crc = CRC_16_MCRF4XX.checksum_chunks(parts);
+        ((crc & 0xFF) ^ (crc >> 8)) as u8
+    }
+
+    /// Serializes `values`, in declaration order, into `dest`. Missing values are zero. The
+    /// payload is not truncated.
+    pub fn pack_payload(&self, values: &[f64], dest: &mut Vec<u8>) {
+        let mut scratch = [0u8; 8];
+        for &index in &self.wire_order {
+            let field = &self.fields[index];
+            let offset = self.value_offsets[index];
+            for i in 0..field.value_count() {
+                let value = values.get(offset + i).copied().unwrap_or_default();
+                let n =
+                    try_pack_data::<byteorder::LittleEndian>(&mut scratch, value, field.data_type)
+                        .expect("Scratch fits all MAVLink field types");
+                dest.extend_from_slice(&scratch[..n]);
+            }
+        }
+    }
+
+    /// Deserializes `payload` into `values`, in declaration order. Truncated payloads are zero
+    /// extended as required by MAVLink v2 and any bytes past the known fields are ignored.
+    pub fn unpack_payload(&self, payload: &[u8], values: &mut [f64]) {
+        let mut full = [0u8; MAX_PAYLOAD_BYTES];
+        let len = payload.len().min(MAX_PAYLOAD_BYTES);
+        full[..len].copy_from_slice(&payload[..len]);
+
+        let mut data = &full[..];
+        for &index in &self.wire_order {
+            let field = &self.fields[index];
+            let offset = self.value_offsets[index];
+            for i in 0..field.value_count() {
+                let value = try_unpack_data::<byteorder::LittleEndian>(data, field.data_type)
+                    .expect("Payload length is validated on construction");
+                if let Some(dest) = values.get_mut(offset + i) {
+                    *dest = value;
+                }
+                data = &data[field.data_type.byte_size()..];
+            }
+        }
+    }
+}
+
+impl FromStr for MessageDef {
+    type Err = ParseEnumError;
+
+    /// Parses either the name of a message in [`COMMON_MESSAGES`], or a custom definition of
+    /// the form `id:crc_extra:name=type,name=type[;extension=type,...]`, for example
+    /// `42000:77:x=float,y=float,flags=uint8_t[4];quality=uint8_t`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((id, rest)) = s.split_once(':') else {
+            return Self::common(s).ok_or(ParseEnumError);
+        };
+        let (crc_extra, fields) = rest.split_once(':').ok_or(ParseEnumError)?;
+        let id = id.trim().parse::<u32>().map_err(|_| ParseEnumError)?;
+        let crc_extra = crc_extra.trim().parse::<u8>().map_err(|_| ParseEnumError)?;
+        if id > 0x00FF_FFFF {
+            return Err(ParseEnumError);
+        }
+
+        let (base, extensions) = fields.split_once(';').unwrap_or((fields, ""));
+        let fields = base
+            .split(',')
+            .map(|f| (f, false))
+            .chain(extensions.split(',').map(|f| (f, true)))
+            .filter(|(f, _)| !f.trim().is_empty())
+            .map(|(field, extension)| {
+                let (name, type_spec) = field.split_once('=').ok_or(ParseEnumError)?;
+                let (type_name, data_type, array_length) =
+                    parse_field_type(type_spec.trim()).ok_or(ParseEnumError)?;
+                Ok(MavField {
+                    name: name.trim().to_string(),
+                    type_name,
+                    data_type,
+                    array_length,
+                    extension,
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let def = Self::new(&id.to_string(), id, crc_extra, fields);
+        if def.fields.is_empty() || def.payload_len() > MAX_PAYLOAD_BYTES {
+            return Err(ParseEnumError);
+        }
+        Ok(def)
+    }
+}
+
+/// A 32 byte secret key used to sign messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SigningKey(pub [u8; 32]);
+
+impl SigningKey {
+    /// Parses a key from 64 hex characters
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim();
+        if hex.len() != 64 || !hex.is_ascii() {
+            return None;
+        }
+        let mut key = [0u8; 32];
+        for (byte, digits) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            let digits = core::str::from_utf8(digits).ok()?;
+            *byte = u8::from_str_radix(digits, 16).ok()?;
+        }
+        Some(Self(key))
+    }
+
+    /// The 6 byte signature of a frame, given everything up to and including the checksum
+    fn sign(&self, frame: &[u8], link_id: u8, timestamp: &[u8]) -> [u8; 6] {
+        let hash = sha256(&[&self.0, frame, &[link_id], timestamp]);
+        let mut signature = [0u8; 6];
+        signature.copy_from_slice(&hash[..6]);
+        signature
+    }
+}
+
+/// Builds MAVLink v2 frames, tracking the sequence number and signing timestamp
+#[derive(Debug, Clone)]
+pub struct MavlinkEncoder {
+    system_id: u8,
+    component_id: u8,
+    signing: Option<(SigningKey, u8)>,
+    sequence: u8,
+    last_timestamp: u64,
+}
+
+impl MavlinkEncoder {
+    /// Creates an encoder for the given source system and component. If `signing` is set,
+    /// frames are signed with the key and link id.
+    pub fn new(system_id: u8, component_id: u8, signing: Option<(SigningKey, u8)>) -> Self {
+        Self {
+            system_id,
+            component_id,
+            signing,
+            sequence: 0,
+            last_timestamp: 0,
+        }
+    }
+
+    /// Sequence number that will be used for the next frame
+    pub fn sequence(&self) -> u8 {
+        self.sequence
+    }
+
+    /// Appends a frame containing `values` to `dest`. `timestamp` is the signing timestamp in
+    /// units of 10 microseconds; it is bumped if needed so it always increases.
+    pub fn encode(&mut self, def: &MessageDef, values: &[f64], timestamp: u64, dest: &mut Vec<u8>) {
+        let start = dest.len();
+        let incompat_flags = match self.signing {
+            Some(_) => INCOMPAT_FLAG_SIGNED,
+            None => 0,
+        };
+        let id = def.id.to_le_bytes();
+        dest.extend_from_slice(&[
+            MAVLINK_V2_STX,
+            0,
+            incompat_flags,
+            0,
+            self.sequence,
+            self.system_id,
+            self.component_id,
+            id[0],
+            id[1],
+            id[2],
+        ]);
+        def.pack_payload(values, dest);
+
+        // Trailing zeros are truncated, but at least one payload byte is always sent
+        let payload_start = start + HEADER_BYTES;
+        while dest.len() > payload_start + 1 && dest.last() == Some(&0) {
+            dest.pop();
+        }
+        dest[start + 1] = (dest.len() - payload_start) as u8;
+
+        let crc = CRC_16_MCRF4XX.checksum_chunks([&dest[start + 1..], &[def.crc_extra]]) as u16;
+        dest.extend_from_slice(&crc.to_le_bytes());
+
+        if let Some((key, link_id)) = self.signing {
+            let timestamp = timestamp.max(self.last_timestamp + 1) & 0xFFFF_FFFF_FFFF;
+            self.last_timestamp = timestamp;
+            let timestamp = &timestamp.to_le_bytes()[..6];
+            let signature = key.sign(&dest[start..], link_id, timestamp);
+            dest.push(link_id);
+            dest.extend_from_slice(timestamp);
+            dest.extend_from_slice(&signature);
+        }
+
+        self.sequence = self.sequence.wrapping_add(1);
+    }
+}
+
+/// A frame extracted by [`MavlinkDecoder`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MavlinkFrame<'a> {
+    pub sequence: u8,
+    pub system_id: u8,
+    pub component_id: u8,
+    pub message_id: u32,
+    /// The payload as received, which may be truncated
+    pub payload: &'a [u8],
+    pub signed: bool,
+}
+
+/// Counts of frames and errors seen by a [`MavlinkDecoder`]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MavlinkStats {
+    /// Frames that passed all checks
+    pub frames: usize,
+    /// Frames with an incorrect checksum
+    pub checksum_errors: usize,
+    /// Frames that were unsigned, incorrectly signed or replayed when signing is required
+    pub signature_errors: usize,
+    /// Frames for messages the decoder does not know and were skipped
+    pub unknown_messages: usize,
+    /// Frames missing according to gaps in the sequence numbers of each system and component
+    pub lost_frames: usize,
+    /// Bytes discarded while searching for the start of a frame
+    pub discarded_bytes: usize,
+}
+
+impl MavlinkStats {
+    /// Number of frames rejected as corrupt or unauthenticated
+    pub fn errors(&self) -> usize {
+        self.checksum_errors + self.signature_errors
+    }
+}
+
+/// Extracts MAVLink v2 frames from a byte stream
+#[derive(Debug, Clone)]
+pub struct MavlinkDecoder {
+    /// Message id and `CRC_EXTRA` of each message the decoder accepts
+    messages: Vec<(u32, u8)>,
+    signing: Option<SigningKey>,
+    buffer: Vec<u8>,
+    stats: MavlinkStats,
+    /// Last sequence number seen from each (system, component)
+    sequences: Vec<(u8, u8, u8)>,
+    /// Last signing timestamp seen on each (link, system, component)
+    timestamps: Vec<(u8, u8, u8, u64)>,
+}
+
+impl MavlinkDecoder {
+    /// Creates a decoder accepting the given messages. If `signing` is set, only frames
+    /// correctly signed with the key and with increasing timestamps are accepted.
+    pub fn new(messages: &[&MessageDef], signing: Option<SigningKey>) -> Self {
+        Self {
+            messages: messages.iter().map(|m| (m.id, m.crc_extra)).collect(),
+            signing,
+            buffer: Vec::new(),
+            stats: MavlinkStats::default(),
+            sequences: Vec::new(),
+            timestamps: Vec::new(),
+        }
+    }
+
+    pub fn stats(&self) -> &MavlinkStats {
+        &self.stats
+    }
+
+    /// Discards any partially received frame
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+    }
+
+    /// Appends `data` to the stream and calls `on_frame` for each complete, valid frame of a
+    /// known message
+    pub fn decode(&mut self, data: &[u8], mut on_frame: impl FnMut(MavlinkFrame)) {
+        self.buffer.extend_from_slice(data);
+        let mut start = 0;
+        loop {
+            match self.buffer[start..]
+                .iter()
+                .position(|b| *b == MAVLINK_V2_STX)
+            {
+                Some(offset) => {
+                    self.stats.discarded_bytes += offset;
+                    start += offset;
+                }
+                None => {
+                    self.stats.discarded_bytes += self.buffer.len() - start;
+                    start = self.buffer.len();
+                    break;
+                }
+            }
+
+            let rest = &self.buffer[start..];
+            if rest.len() < HEADER_BYTES {
+                break;
+            }
+            let payload_len = rest[1] as usize;
+            let incompat_flags = rest[2];
+            if incompat_flags & !INCOMPAT_FLAG_SIGNED != 0 {
+                // Unsupported features, so this can't be a frame we understand
+                self.stats.discarded_bytes += 1;
+                start += 1;
+                continue;
+            }
+            let signed = incompat_flags & INCOMPAT_FLAG_SIGNED != 0;
+            let checksum_end = HEADER_BYTES + payload_len + CHECKSUM_BYTES;
+            let frame_len = checksum_end + if signed { SIGNATURE_BYTES } else { 0 };
+            if rest.len() < frame_len {
+                break;
+            }
+
+            let frame = &rest[..frame_len];
+            let system_id = frame[5];
+            let component_id = frame[6];
+            let message_id = u32::from_le_bytes([frame[7], frame[8], frame[9], 0]);
+            let sequence = frame[4];
+            let Some(&(_, crc_extra)) = self.messages.iter().find(|(id, _)| *id == message_id)
+            else {
+                // Without CRC_EXTRA the frame can't be checked, and may be a stray STX in the
+                // stream, so resync from the next byte like a checksum error would
+                self.stats.unknown_messages += 1;
+                start += 1;
+                continue;
+            };
+
+            let body = &frame[1..HEADER_BYTES + payload_len];
+            let crc = CRC_16_MCRF4XX.checksum_chunks([body, &[crc_extra]]) as u16;
+            if crc.to_le_bytes() != frame[checksum_end - CHECKSUM_BYTES..checksum_end] {
+                self.stats.checksum_errors += 1;
+                start += 1;
+                continue;
+            }
+
+            if let Some(key) = &self.signing {
+                let authentic =
+                    signed && check_signature(key, frame, checksum_end, &mut self.timestamps);
+                if !authentic {
+                    self.stats.signature_errors += 1;
+                    start += frame_len;
+                    continue;
+                }
+            }
+
+            self.stats.lost_frames +=
+                track_sequence(&mut self.sequences, system_id, component_id, sequence);
+            self.stats.frames += 1;
+            on_frame(MavlinkFrame {
+                sequence,
+                system_id,
+                component_id,
+                message_id,
+                payload: &frame[HEADER_BYTES..HEADER_BYTES + payload_len],
+                signed,
+            });
+            start += frame_len;
+        }
+        self.buffer.drain(..start);
+    }
+}
+
+/// Records `sequence` for the system and component, returning the number of frames skipped
+/// since the last one
+fn track_sequence(
+    sequences: &mut Vec<(u8, u8, u8)>,
+    system_id: u8,
+    component_id: u8,
+    sequence: u8,
+) -> usize {
+    match sequences
+        .iter_mut()
+        .find(|(s, c, _)| *s == system_id && *c == component_id)
+    {
+        Some((_, _, last)) => {
+            let lost = sequence.wrapping_sub(*last).wrapping_sub(1);
+            *last = sequence;
+            lost as usize
+        }
+        None => {
+            sequences.push((system_id, component_id, sequence));
+            0
+        }
+    }
+}
+
+/// Checks the signature of a frame, and that its timestamp is newer than the last frame on
+/// the same link from the same system and component
+fn check_signature(
+    key: &SigningKey,
+    frame: &[u8],
+    checksum_end: usize,
+    timestamps: &mut Vec<(u8, u8, u8, u64)>,
+) -> bool {
+    let link_id = frame[checksum_end];
+    let timestamp_bytes = &frame[checksum_end + 1..checksum_end + 7];
+    if key.sign(&frame[..checksum_end], link_id, timestamp_bytes) != frame[checksum_end + 7..] {
+        return false;
+    }
+
+    let mut timestamp = [0u8; 8];
+    timestamp[..6].copy_from_slice(timestamp_bytes);
+    let timestamp = u64::from_le_bytes(timestamp);
+    let (system_id, component_id) = (frame[5], frame[6]);
+    match timestamps
+        .iter_mut()
+        .find(|(l, s, c, _)| *l == link_id && *s == system_id && *c == component_id)
+    {
+        Some((_, _, _, last)) if timestamp <= *last => false,
+        Some((_, _, _, last)) => {
+            *last = timestamp;
+            true
+        }
+        None => {
+            timestamps.push((link_id, system_id, component_id, timestamp));
+            true
+        }
+    }
+}
+
+/// SHA-256 of the concatenation of `chunks`, as needed for MAVLink message signing
+fn sha256(chunks: &[&[u8]]) -> [u8; 32] {
+    const K: [u32; 64] = [
+        0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4,
+        0xab1c5ed5, 0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe,
+        0x9bdc06a7, 0xc19bf174, 0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f,
+        0x4a7484aa, 0x5cb0a9dc, 0x76f988da, 0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7,
+        0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967, 0x27b70a85, 0x2e1b2138, 0x4d2c6dfc,
+        0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85, 0xa2bfe8a1, 0xa81a664b,
+        0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070, 0x19a4c116,
+        0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+        0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7,
+        0xc67178f2,
+    ];
+    let mut hash: [u32; 8] = [
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+        0x5be0cd19,
+    ];
+
+    let mut message: Vec<u8> = chunks.concat();
+    let bit_len = (message.len() as u64) * 8;
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&bit_len.to_be_bytes());
+
+    for block in message.chunks_exact(64) {
+        let mut w = [0u32; 64];
+        for (word, bytes) in w.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = hash;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let temp1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(temp1);
+            d = c;
+            c = b;
+            b = a;
+            a = temp1.wrapping_add(temp2);
+        }
+        for (value, add) in hash.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *value = value.wrapping_add(add);
+        }
+    }
+
+    let mut digest = [0u8; 32];
+    for (bytes, value) in digest.chunks_exact_mut(4).zip(hash) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    const TEST_KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    fn decode_all(decoder: &mut MavlinkDecoder, data: &[u8]) -> Vec<(u8, u32, Vec<u8>)> {
+        let mut frames = Vec::new();
+        decoder.decode(data, |frame| {
+            frames.push((frame.sequence, frame.message_id, frame.payload.to_vec()))
+        });
+        frames
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            sha256(&[b"a", b"bc"])[..8],
+            [0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea]
+        );
+        // Two block message
+        let digest = sha256(&[b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"]);
+        assert_eq!(digest[..4], [0x24, 0x8d, 0x6a, 0x61]);
+        assert_eq!(digest[28..], [0x19, 0xdb, 0x06, 0xc1]);
+    }
+
+    #[test]
+    fn test_common_messages_crc_extra() {
+        for (name, _) in COMMON_MESSAGES {
+            let def = MessageDef::common(name).unwrap();
+            assert_eq!(def.compute_crc_extra(), def.crc_extra, "{}", name);
+        }
+        assert_eq!(MessageDef::common("HEARTBEAT").unwrap().payload_len(), 9);
+        assert_eq!(MessageDef::common("ATTITUDE").unwrap().payload_len(), 28);
+        assert_eq!(MessageDef::common("HIGHRES_IMU").unwrap().payload_len(), 63);
+        assert!(MessageDef::common("NOT_A_MESSAGE").is_none());
+    }
+
+    #[test]
+    fn test_parse_custom_message() {
+        let def: MessageDef = "42000:77:x=float,flags=uint8_t[4],count=u16;quality=u8"
+            .parse()
+            .unwrap();
+        assert_eq!(def.id, 42000);
+        assert_eq!(def.crc_extra, 77);
+        assert_eq!(def.value_count(), 7);
+        assert_eq!(def.field_values("flags"), Some(1..5));
+        assert_eq!(def.field_values("quality"), Some(6..7));
+        assert_eq!(def.payload_len(), 11);
+        assert!(def.fields()[3].extension);
+
+        // Wire order: x, count, flags, then the extension
+        let mut payload = vec![];
+        def.pack_payload(&[1.0, 2.0, 3.0, 4.0, 5.0, 0x0102 as f64, 9.0], &mut payload);
+        assert_eq!(&payload[4..], &[0x02, 0x01, 2, 3, 4, 5, 9]);
+
+        assert!("42000:77:".parse::<MessageDef>().is_err());
+        assert!("42000:77:x=quaternion".parse::<MessageDef>().is_err());
+        assert!("42000:77:x=uint8_t[0]".parse::<MessageDef>().is_err());
+        assert!("42000:300:x=float".parse::<MessageDef>().is_err());
+        assert!("42000:1:x=double[32]".parse::<MessageDef>().is_err());
+    }
+
+    #[test]
+    fn test_heartbeat_frame() {
+        let def = MessageDef::common("HEARTBEAT").unwrap();
+        let mut encoder = MavlinkEncoder::new(1, 1, None);
+        let mut frame = vec![];
+        // type, autopilot, base_mode, custom_mode, system_status, mavlink_version
+        encoder.encode(&def, &[2.0, 12.0, 0.0, 0.0, 4.0, 3.0], 0, &mut frame);
+        assert_eq!(&frame[..HEADER_BYTES], &[0xFD, 9, 0, 0, 0, 1, 1, 0, 0, 0]);
+        assert_eq!(
+            &frame[HEADER_BYTES..HEADER_BYTES + 9],
+            &[0, 0, 0, 0, 2, 12, 0, 4, 3]
+        );
+        assert_eq!(frame.len(), HEADER_BYTES + 9 + CHECKSUM_BYTES);
+        assert_eq!(encoder.sequence(), 1);
+    }
+
+    #[test]
+    fn test_payload_truncation_roundtrip() {
+        let def = MessageDef::common("ATTITUDE").unwrap();
+        let mut encoder = MavlinkEncoder::new(1, 200, None);
+        let mut decoder = MavlinkDecoder::new(&[&def], None);
+        let values = [1234.0, 0.5, -0.25, 0.0, 0.0, 0.0, 0.0];
+
+        let mut stream = vec![];
+        encoder.encode(&def, &values, 0, &mut stream);
+        // The trailing zero rates are truncated
+        assert_eq!(stream[1], 12);
+        encoder.encode(&def, &[0.0; 7], 0, &mut stream);
+
+        let frames = decode_all(&mut decoder, &stream);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[1].2, vec![0]);
+
+        let mut decoded = [f64::NAN; 7];
+        def.unpack_payload(&frames[0].2, &mut decoded);
+        assert_eq!(decoded, values);
+        def.unpack_payload(&frames[1].2, &mut decoded);
+        assert_eq!(decoded, [0.0; 7]);
+    }
+
+    #[test]
+    fn test_decoder_resyncs_and_counts_errors() {
+        let def = MessageDef::common("SCALED_IMU").unwrap();
+        let other = MessageDef::common("HEARTBEAT").unwrap();
+        let mut encoder = MavlinkEncoder::new(1, 1, None);
+        let mut decoder = MavlinkDecoder::new(&[&def], None);
+
+        let mut stream = vec![0x55, 0xFD, 0x01];
+        encoder.encode(&def, &[1.0; 11], 0, &mut stream);
+        let corrupt_start = stream.len();
+        encoder.encode(&def, &[2.0; 11], 0, &mut stream);
+        stream[corrupt_start + HEADER_BYTES] ^= 0x01;
+        encoder.encode(&other, &[1.0; 6], 0, &mut stream);
+        encoder.encode(&def, &[3.0; 11], 0, &mut stream);
+
+        // Feed the stream a few bytes at a time
+        let mut frames = vec![];
+        for chunk in stream.chunks(7) {
+            frames.extend(decode_all(&mut decoder, chunk));
+        }
+        let sequences: Vec<u8> = frames.iter().map(|f| f.0).collect();
+        assert_eq!(sequences, vec![0, 3]);
+
+        let stats = decoder.stats();
+        assert_eq!(stats.frames, 2);
+        assert_eq!(stats.checksum_errors, 1);
+        assert_eq!(stats.unknown_messages, 1);
+        // The corrupt frame, and the unknown one since its sequence can't be trusted
+        assert_eq!(stats.lost_frames, 2);
+        assert!(stats.discarded_bytes >= 3);
+    }
+
+    #[test]
+    fn test_decoder_resyncs_after_unknown_message() {
+        let def = MessageDef::common("HEARTBEAT").unwrap();
+        let mut encoder = MavlinkEncoder::new(1, 1, None);
+        let mut decoder = MavlinkDecoder::new(&[&def], None);
+
+        // A stray STX whose header claims an unknown message overlapping the real frame
+        let mut stream = vec![0xFD, 2, 0, 0, 7, 1, 1, 0x99, 0, 0];
+        encoder.encode(&def, &[1.0; 6], 0, &mut stream);
+
+        let frames = decode_all(&mut decoder, &stream);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].1, def.id);
+
+        let stats = decoder.stats();
+        assert_eq!(stats.frames, 1);
+        assert_eq!(stats.unknown_messages, 1);
+        assert_eq!(stats.lost_frames, 0);
+    }
+
+    #[test]
+    fn test_lost_frames() {
+        let def = MessageDef::common("HEARTBEAT").unwrap();
+        let mut encoder = MavlinkEncoder::new(1, 1, None);
+        let mut decoder = MavlinkDecoder::new(&[&def], None);
+
+        // Only decode some of the frames. The last one wraps around to sequence number 1.
+        let received = [0, 5, 255, 257];
+        let mut frame = vec![];
+        for sequence in 0..=257u32 {
+            frame.clear();
+            encoder.encode(&def, &[1.0; 6], 0, &mut frame);
+            if received.contains(&sequence) {
+                decode_all(&mut decoder, &frame);
+            }
+        }
+        assert_eq!(decoder.stats().frames, 4);
+        assert_eq!(decoder.stats().lost_frames, 4 + 249 + 1);
+    }
+
+    #[test]
+    fn test_signing() {
+        let def = MessageDef::common("HEARTBEAT").unwrap();
+        let key = SigningKey::from_hex(TEST_KEY).unwrap();
+        let mut encoder = MavlinkEncoder::new(1, 1, Some((key, 7)));
+        let mut stream = vec![];
+        encoder.encode(&def, &[1.0; 6], 1000, &mut stream);
+        let first_len = stream.len();
+        encoder.encode(&def, &[1.0; 6], 1000, &mut stream);
+
+        assert_eq!(stream[2], INCOMPAT_FLAG_SIGNED);
+        assert_eq!(
+            first_len,
+            HEADER_BYTES + 9 + CHECKSUM_BYTES + SIGNATURE_BYTES
+        );
+        assert_eq!(stream[first_len - SIGNATURE_BYTES], 7);
+        // The second timestamp is bumped past the first
+        assert_eq!(stream[first_len * 2 - 12], 0xE9);
+
+        // Signed frames are accepted by a decoder without a key
+        let mut decoder = MavlinkDecoder::new(&[&def], None);
+        assert_eq!(decode_all(&mut decoder, &stream).len(), 2);
+
+        let mut decoder = MavlinkDecoder::new(&[&def], Some(key));
+        assert_eq!(decode_all(&mut decoder, &stream).len(), 2);
+        // Replayed frames are rejected
+        assert_eq!(decode_all(&mut decoder, &stream).len(), 0);
+        assert_eq!(decoder.stats().signature_errors, 2);
+
+        let wrong_key = SigningKey([0xAA; 32]);
+        let mut decoder = MavlinkDecoder::new(&[&def], Some(wrong_key));
+        assert_eq!(decode_all(&mut decoder, &stream).len(), 0);
+
+        let mut unsigned = vec![];
+        MavlinkEncoder::new(1, 1, None).encode(&def, &[1.0; 6], 0, &mut unsigned);
+        let mut decoder = MavlinkDecoder::new(&[&def], Some(key));
+        assert_eq!(decode_all(&mut decoder, &unsigned).len(), 0);
+        assert_eq!(decoder.stats().signature_errors, 1);
+    }
+
+    #[test]
+    fn test_signing_key_from_hex() {
+        assert_eq!(SigningKey::from_hex(TEST_KEY).unwrap().0[31], 0x1f);
+        assert!(SigningKey::from_hex("0011").is_none());
+        assert!(SigningKey::from_hex(&TEST_KEY.replace('0', "g")).is_none());
+    }
+}
@@ -0,0 +1,382 @@
+//! Scripted I2C transactions
+//!
+//! An [`I2cScript`] is a sequence of I2C transactions and delays that is run against a single
+//! device each tick. Each transaction is passed to [`embedded_hal::i2c::I2c::transaction`], so
+//! a read directly following a write in the same transaction uses a repeated start, and
+//! adjacent writes are sent as one write.
+//!
+//! Scripts are written as transactions separated by `;`, each made up of these operations:
+//!
+//! - `W <byte>...` writes bytes, such as an 8-bit register address followed by data
+//! - `W16 <word>...` writes big-endian 16-bit words, such as a 16-bit register address
+//! - `IN` writes the bytes input to the block
+//! - `R <count>` reads bytes
+//! - `D <ms>` waits before the next transaction, and must be on its own
+//!
+//! Numbers are decimal or `0x` prefixed hex. For example, a soft reset followed by a burst
+//! read of six bytes from register `0x12` is `W 0x7E 0xB6; D 50; W 0x12 R 6`.
+use alloc::vec::Vec;
+use core::str::FromStr;
+use core::time::Duration;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::{AddressMode, Error, ErrorKind, I2c, NoAcknowledgeSource, Operation};
+
+use crate::ParseEnumError;
+
+/// Address of an I2C device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum I2cAddress {
+    SevenBit(u8),
+    TenBit(u16),
+}
+
+impl I2cAddress {
+    /// Addresses above the 7-bit range are treated as 10-bit, unless `ten_bit` forces it.
+    /// Returns `None` for addresses above the 10-bit range.
+    pub fn new(address: u16, ten_bit: bool) -> Option<Self> {
+        match address {
+            0x400.. => None,
+            0x80.. => Some(Self::TenBit(address)),
+            _ if ten_bit => Some(Self::TenBit(address)),
+            _ => Some(Self::SevenBit(address as u8)),
+        }
+    }
+}
+
+/// Result code of a transaction, as output by the I2C blocks
+pub const I2C_OK: u8 = 0;
+/// The transaction was not attempted because the platform doesn't support the address mode
+pub const I2C_ERROR_UNSUPPORTED: u8 = 1;
+pub const I2C_ERROR_BUS: u8 = 2;
+pub const I2C_ERROR_ARBITRATION_LOSS: u8 = 3;
+pub const I2C_ERROR_NO_ACKNOWLEDGE_ADDRESS: u8 = 4;
+pub const I2C_ERROR_NO_ACKNOWLEDGE_DATA: u8 = 5;
+pub const I2C_ERROR_OVERRUN: u8 = 6;
+pub const I2C_ERROR_OTHER: u8 = 7;
+
+/// Converts an I2C error to a transaction result code
+pub fn error_code(error: &impl Error) -> u8 {
+    match error.kind() {
+        ErrorKind::Bus => I2C_ERROR_BUS,
+        ErrorKind::ArbitrationLoss => I2C_ERROR_ARBITRATION_LOSS,
+        ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address) => I2C_ERROR_NO_ACKNOWLEDGE_ADDRESS,
+        ErrorKind::NoAcknowledge(_) => I2C_ERROR_NO_ACKNOWLEDGE_DATA,
+        ErrorKind::Overrun => I2C_ERROR_OVERRUN,
+        _ => I2C_ERROR_OTHER,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum I2cOp {
+    Write(Vec<u8>),
+    WriteInput,
+    Read(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum I2cStep {
+    Transaction(Vec<I2cOp>),
+    Delay(Duration),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct I2cScript {
+    pub steps: Vec<I2cStep>,
+}
+
+fn parse_number(token: &str) -> Result<u32, ParseEnumError> {
+    match token
+        .strip_prefix("0x")
+        .or_else(|| token.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => token.parse(),
+    }
+    .map_err(|_| ParseEnumError)
+}
+
+impl FromStr for I2cScript {
+    type Err = ParseEnumError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut steps = Vec::new();
+        for transaction in s.split(';') {
+            let mut tokens = transaction.split_whitespace().peekable();
+            let mut ops: Vec<I2cOp> = Vec::new();
+            while let Some(op) = tokens.next() {
+                let op = op.to_ascii_uppercase();
+                // Collects the numbers following an operation
+                let mut numbers = || {
+                    let mut values = Vec::new();
+                    while let Some(value) = tokens.next_if(|t| parse_number(t).is_ok()) {
+                        values.push(parse_number(value).unwrap());
+                    }
+                    values
+                };
+                match op.as_str() {
+                    "W" => {
+                        let bytes = numbers()
+                            .into_iter()
+                            .map(|v| u8::try_from(v).map_err(|_| ParseEnumError))
+                            .collect::<Result<Vec<_>, _>>()?;
+                        ops.push(I2cOp::Write(bytes));
+                    }
+                    "W16" => {
+                        let mut bytes = Vec::new();
+                        for word in numbers() {
+                            let word = u16::try_from(word).map_err(|_| ParseEnumError)?;
+                            bytes.extend_from_slice(&word.to_be_bytes());
+                        }
+                        ops.push(I2cOp::Write(bytes));
+                    }
+                    "IN" => ops.push(I2cOp::WriteInput),
+                    "R" => match numbers().as_slice() {
+                        [count] if *count > 0 => ops.push(I2cOp::Read(*count as usize)),
+                        _ => return Err(ParseEnumError),
+                    },
+                    "D" => match numbers().as_slice() {
+                        [ms] if ops.is_empty() && tokens.peek().is_none() => {
+                            steps.push(I2cStep::Delay(Duration::from_millis(*ms as u64)));
+                        }
+                        _ => return Err(ParseEnumError),
+                    },
+                    _ => return Err(ParseEnumError),
+                }
+            }
+            if !ops.is_empty() {
+                steps.push(I2cStep::Transaction(ops));
+            }
+        }
+        Ok(Self { steps })
+    }
+}
+
+impl I2cScript {
+    /// The script for a single write of `command` followed by a repeated-start read of
+    /// `read_bytes`, or just the write if `read_bytes` is 0
+    pub fn write_read(command: &[u8], read_bytes: usize) -> Self {
+        let mut ops = alloc::vec![I2cOp::Write(command.to_vec())];
+        if read_bytes > 0 {
+            ops.push(I2cOp::Read(read_bytes));
+        }
+        Self {
+            steps: alloc::vec![I2cStep::Transaction(ops)],
+        }
+    }
+
+    /// The script for a write of `command` followed by the block input
+    pub fn write_input(command: &[u8]) -> Self {
+        Self {
+            steps: alloc::vec![I2cStep::Transaction(alloc::vec![
+                I2cOp::Write(command.to_vec()),
+                I2cOp::WriteInput,
+            ])],
+        }
+    }
+
+    fn transactions(&self) -> impl Iterator<Item = &[I2cOp]> {
+        self.steps.iter().filter_map(|step| match step {
+            I2cStep::Transaction(ops) => Some(ops.as_slice()),
+            I2cStep::Delay(_) => None,
+        })
+    }
+
+    /// Number of transactions, which is the number of result codes
+    pub fn transaction_count(&self) -> usize {
+        self.transactions().count()
+    }
+
+    /// Total number of bytes read by the script
+    pub fn read_len(&self) -> usize {
+        self.transactions()
+            .flatten()
+            .map(|op| match op {
+                I2cOp::Read(count) => *count,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Runs the script, writing `input` for each `IN` operation. The bytes read are
+    /// concatenated into `read_data` and one result code per transaction is written to
+    /// `results`. Transactions after a failed one still run, so the bytes read by a failed
+    /// transaction should not be trusted.
+    pub fn run<A, I, D>(
+        &self,
+        i2c: &mut I,
+        address: A,
+        delay: &mut D,
+        input: &[u8],
+        read_data: &mut Vec<u8>,
+        results: &mut Vec<u8>,
+    ) where
+        A: AddressMode + Copy,
+        I: I2c<A>,
+        D: DelayNs,
+    {
+        read_data.clear();
+        read_data.resize(self.read_len(), 0);
+        results.clear();
+
+        let mut unread = read_data.as_mut_slice();
+        let mut operations = Vec::new();
+        for step in &self.steps {
+            let ops = match step {
+                I2cStep::Transaction(ops) => ops,
+                I2cStep::Delay(duration) => {
+                    delay.delay_us(duration.as_micros() as u32);
+                    continue;
+                }
+            };
+
+            operations.clear();
+            for op in ops {
+                match op {
+                    I2cOp::Write(bytes) => operations.push(Operation::Write(bytes)),
+                    I2cOp::WriteInput => operations.push(Operation::Write(input)),
+                    I2cOp::Read(count) => {
+                        let (dest, rest) = core::mem::take(&mut unread).split_at_mut(*count);
+                        unread = rest;
+                        operations.push(Operation::Read(dest));
+                    }
+                }
+            }
+            let result = i2c.transaction(address, &mut operations);
+            results.push(result.map_or_else(|e| error_code(&e), |_| I2C_OK));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+    use embedded_hal::i2c::{ErrorType, SevenBitAddress};
+
+    #[test]
+    fn test_parse_script() {
+        let script: I2cScript = "W 0x7E 0xB6; D 50; w16 0x1234 r 6 ; IN".parse().unwrap();
+        assert_eq!(
+            script.steps,
+            vec![
+                I2cStep::Transaction(vec![I2cOp::Write(vec![0x7E, 0xB6])]),
+                I2cStep::Delay(Duration::from_millis(50)),
+                I2cStep::Transaction(vec![I2cOp::Write(vec![0x12, 0x34]), I2cOp::Read(6)]),
+                I2cStep::Transaction(vec![I2cOp::WriteInput]),
+            ]
+        );
+        assert_eq!(script.transaction_count(), 3);
+        assert_eq!(script.read_len(), 6);
+
+        assert!("W 0x100".parse::<I2cScript>().is_err());
+        assert!("R".parse::<I2cScript>().is_err());
+        assert!("W 1 D 5".parse::<I2cScript>().is_err());
+        assert!("X 1".parse::<I2cScript>().is_err());
+    }
+
+    #[test]
+    fn test_address() {
+        assert_eq!(
+            I2cAddress::new(0x68, false),
+            Some(I2cAddress::SevenBit(0x68))
+        );
+        assert_eq!(I2cAddress::new(0x68, true), Some(I2cAddress::TenBit(0x68)));
+        assert_eq!(
+            I2cAddress::new(0x2A0, false),
+            Some(I2cAddress::TenBit(0x2A0))
+        );
+        assert_eq!(
+            I2cAddress::new(0x3FF, true),
+            Some(I2cAddress::TenBit(0x3FF))
+        );
+        assert_eq!(I2cAddress::new(0x400, false), None);
+    }
+
+    /// Device with 16-bit registers that returns the low byte of the register address
+    /// incrementing for each byte read, and fails when the register address is 0xFFFF
+    #[derive(Default)]
+    struct FakeDevice {
+        transactions: Vec<Vec<(bool, Vec<u8>)>>,
+        delay_us: u32,
+    }
+
+    #[derive(Debug)]
+    struct FakeError;
+
+    impl Error for FakeError {
+        fn kind(&self) -> ErrorKind {
+            ErrorKind::NoAcknowledge(NoAcknowledgeSource::Data)
+        }
+    }
+
+    impl ErrorType for FakeDevice {
+        type Error = FakeError;
+    }
+
+    impl I2c<SevenBitAddress> for FakeDevice {
+        fn transaction(
+            &mut self,
+            _address: u8,
+            operations: &mut [Operation<'_>],
+        ) -> Result<(), FakeError> {
+            let mut register = 0u16;
+            let mut record = Vec::new();
+            for op in operations {
+                match op {
+                    Operation::Write(bytes) => {
+                        if let [hi, lo] = bytes {
+                            register = u16::from_be_bytes([*hi, *lo]);
+                        }
+                        record.push((false, bytes.to_vec()));
+                    }
+                    Operation::Read(dest) => {
+                        if register == 0xFFFF {
+                            return Err(FakeError);
+                        }
+                        for byte in dest.iter_mut() {
+                            *byte = register as u8;
+                            register += 1;
+                        }
+                        record.push((true, dest.to_vec()));
+                    }
+                }
+            }
+            self.transactions.push(record);
+            Ok(())
+        }
+    }
+
+    impl DelayNs for FakeDevice {
+        fn delay_ns(&mut self, ns: u32) {
+            self.delay_us += ns / 1000;
+        }
+    }
+
+    #[test]
+    fn test_run_script() {
+        let script: I2cScript = "W16 0x0010 R 2; D 5; W16 0xFFFF R 1; W16 0x0020 R 3; IN"
+            .parse()
+            .unwrap();
+        let mut device = FakeDevice::default();
+        let mut delay = FakeDevice::default();
+        let mut data = Vec::new();
+        let mut results = Vec::new();
+        script.run(
+            &mut device,
+            0x68,
+            &mut delay,
+            &[9, 8],
+            &mut data,
+            &mut results,
+        );
+
+        assert_eq!(data, [0x10, 0x11, 0, 0x20, 0x21, 0x22]);
+        assert_eq!(
+            results,
+            [I2C_OK, I2C_ERROR_NO_ACKNOWLEDGE_DATA, I2C_OK, I2C_OK]
+        );
+        assert_eq!(delay.delay_us, 5000);
+        assert_eq!(device.transactions[0][1], (true, vec![0x10, 0x11]));
+        assert_eq!(device.transactions[2], [(false, vec![9, 8])]);
+    }
+}
@@ -23,6 +23,9 @@ pub use stale_tracker::StaleTracker;
 
 pub mod byte_data;
 
+pub mod i2c_script;
+pub use i2c_script::{I2cAddress, I2cScript};
+
 pub mod timing;
 
 pub trait IsValid {